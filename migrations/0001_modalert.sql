@@ -0,0 +1,9 @@
+-- Mod alert settings, previously modalerts.json
+CREATE TABLE modalert_guilds (
+    guild_id INTEGER PRIMARY KEY,
+    enabled INTEGER NOT NULL DEFAULT 0,
+    channel_id INTEGER,
+    message_log INTEGER NOT NULL DEFAULT 0,
+    digest_per_minute INTEGER,
+    digest_window_secs INTEGER
+);
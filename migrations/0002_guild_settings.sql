@@ -0,0 +1,7 @@
+-- Per-guild settings, one row per overridden key
+CREATE TABLE guild_settings (
+    guild_id INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (guild_id, key)
+);
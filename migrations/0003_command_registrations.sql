@@ -0,0 +1,6 @@
+-- Checksum of the command set last pushed to each scope ("global" or "guild:<id>")
+CREATE TABLE command_registrations (
+    scope TEXT PRIMARY KEY,
+    checksum TEXT NOT NULL,
+    registered_at INTEGER NOT NULL
+);
@@ -0,0 +1,16 @@
+-- One row per command invocation, for `admin audit`
+CREATE TABLE command_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    at INTEGER NOT NULL,
+    guild_id INTEGER,
+    channel_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    user_name TEXT NOT NULL,
+    command TEXT NOT NULL,
+    invocation TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    error TEXT,
+    latency_ms INTEGER NOT NULL
+);
+CREATE INDEX command_audit_guild_at ON command_audit (guild_id, at);
+CREATE INDEX command_audit_at ON command_audit (at);
@@ -0,0 +1,8 @@
+-- Per-guild command restrictions: a command may only be used by one of its roles / in one of its channels
+CREATE TABLE command_permissions (
+    guild_id INTEGER NOT NULL,
+    command TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('role', 'channel')),
+    target_id INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, command, kind, target_id)
+);
@@ -0,0 +1,12 @@
+-- Owner-managed access lists: users whose commands are ignored, and the servers a private
+-- instance may stay in when `access.guild_allowlist` is on
+CREATE TABLE blocked_users (
+    user_id INTEGER PRIMARY KEY,
+    reason TEXT,
+    added_at INTEGER NOT NULL
+);
+
+CREATE TABLE allowed_guilds (
+    guild_id INTEGER PRIMARY KEY,
+    added_at INTEGER NOT NULL
+);
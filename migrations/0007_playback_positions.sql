@@ -0,0 +1,8 @@
+-- Where long tracks (podcasts, audiobooks) were left off, per guild and URL, for `music play` to offer resuming
+CREATE TABLE playback_positions (
+    guild_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    position_secs INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, url)
+);
@@ -0,0 +1,2 @@
+-- Which alert kinds a guild reports, comma-separated (`timeout,ban,...`); NULL reports all of them
+ALTER TABLE modalert_guilds ADD COLUMN events TEXT;
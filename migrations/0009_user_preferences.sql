@@ -0,0 +1,6 @@
+-- Per-user listening preferences from `/preferences`, across every server
+CREATE TABLE user_preferences (
+    user_id INTEGER PRIMARY KEY,
+    dm_on_start INTEGER NOT NULL DEFAULT 0,
+    search_source TEXT NOT NULL DEFAULT 'spotify'
+);
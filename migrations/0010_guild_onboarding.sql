@@ -0,0 +1,5 @@
+-- Servers that got the onboarding message, so it's posted once per server and never on a reconnect
+CREATE TABLE guild_onboarding (
+    guild_id INTEGER PRIMARY KEY,
+    welcomed_at INTEGER NOT NULL
+);
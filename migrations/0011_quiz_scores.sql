@@ -0,0 +1,7 @@
+-- Points from `music quiz`, per guild and member, for `music leaderboard`
+CREATE TABLE quiz_scores (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    points INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, user_id)
+);
@@ -0,0 +1,11 @@
+-- Last.fm / ListenBrainz accounts linked with `scrobble link`, one per service and user
+CREATE TABLE scrobble_accounts (
+    user_id INTEGER NOT NULL,
+    service TEXT NOT NULL,
+    username TEXT NOT NULL,
+    -- Last.fm session key or ListenBrainz user token
+    secret TEXT NOT NULL,
+    -- 'requested': tracks the user asked for; 'listening': everything played while they're in the channel
+    scope TEXT NOT NULL DEFAULT 'requested',
+    PRIMARY KEY (user_id, service)
+);
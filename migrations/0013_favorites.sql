@@ -0,0 +1,10 @@
+-- Tracks bookmarked with the control panel's Favorite button, per user, for `music favorites`
+CREATE TABLE favorites (
+    user_id INTEGER NOT NULL,
+    -- What `music play` is given to replay it
+    query TEXT NOT NULL,
+    -- "Artist – Title" as shown when it was saved
+    title TEXT NOT NULL,
+    added_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, query)
+);
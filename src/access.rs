@@ -0,0 +1,287 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{GuildId, UserId};
+use serenity::prelude::*;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::storage::{self, Db};
+use crate::{config, Ctx, Error};
+
+/// Blocked users and allowed guilds, mirrored from the database so every command check
+/// doesn't have to query it.
+#[derive(Debug, Default)]
+pub struct AccessLists {
+    blocked: HashSet<UserId>,
+    allowed: HashSet<GuildId>,
+}
+
+pub struct AccessStore;
+impl TypeMapKey for AccessStore {
+    type Value = Arc<RwLock<AccessLists>>;
+}
+
+/// Returned by the command check for blocked users; `errors::on_error` drops it without a reply.
+#[derive(Debug)]
+pub struct Blocked;
+
+impl std::fmt::Display for Blocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("user is blocked")
+    }
+}
+
+impl std::error::Error for Blocked {}
+
+pub async fn ensure_access_store(db: &Db) -> Result<Arc<RwLock<AccessLists>>, Error> {
+    let (blocked, allowed): (Vec<i64>, Vec<i64>) = db
+        .call(|conn| {
+            let blocked = conn
+                .prepare("SELECT user_id FROM blocked_users")?
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            let allowed = conn
+                .prepare("SELECT guild_id FROM allowed_guilds")?
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok((blocked, allowed))
+        })
+        .await?;
+    Ok(Arc::new(RwLock::new(AccessLists {
+        blocked: blocked.into_iter().map(|id| UserId::new(id as u64)).collect(),
+        allowed: allowed.into_iter().map(|id| GuildId::new(id as u64)).collect(),
+    })))
+}
+
+/// The bot's owners (the framework's `owners`), for checks outside commands such as the
+/// dashboard.
+pub struct OwnersStore;
+impl TypeMapKey for OwnersStore {
+    type Value = Arc<HashSet<UserId>>;
+}
+
+pub async fn is_owner(ctx: &Context, user: UserId) -> bool {
+    ctx.data.read().await.get::<OwnersStore>().is_some_and(|owners| owners.contains(&user))
+}
+
+async fn store(ctx: &Context) -> Option<Arc<RwLock<AccessLists>>> {
+    ctx.data.read().await.get::<AccessStore>().cloned()
+}
+
+pub async fn is_blocked(ctx: &Context, user: UserId) -> bool {
+    match store(ctx).await {
+        Some(store) => store.read().await.blocked.contains(&user),
+        None => false,
+    }
+}
+
+/// Whether the bot may stay in `gid`. Always true unless `access.guild_allowlist` is on and
+/// the allowlist has at least one server; an empty list restricts nothing, so turning the
+/// option on before adding servers doesn't make the bot leave everywhere.
+pub async fn guild_allowed(ctx: &Context, gid: GuildId) -> bool {
+    if !config::current(ctx).await.access.guild_allowlist {
+        return true;
+    }
+    match store(ctx).await {
+        Some(store) => {
+            let lists = store.read().await;
+            lists.allowed.is_empty() || lists.allowed.contains(&gid)
+        }
+        None => true,
+    }
+}
+
+/// `GuildCreate` hook: leave a server that isn't allowed. Returns whether the bot stays.
+pub async fn enforce_guild(ctx: &Context, gid: GuildId) -> bool {
+    if guild_allowed(ctx, gid).await {
+        return true;
+    }
+    tracing::info!("Leaving guild {} (not on the allowlist)", gid);
+    if let Err(e) = gid.leave(&ctx.http).await {
+        tracing::warn!("Failed to leave guild {}: {e:?}", gid);
+    }
+    false
+}
+
+/// Leave every server the bot is in that isn't allowed; run after the config is reloaded, since
+/// `access.guild_allowlist` may have just been turned on.
+pub async fn enforce_all(ctx: &Context) {
+    for gid in ctx.cache.guilds() {
+        enforce_guild(ctx, gid).await;
+    }
+}
+
+/// Framework `command_check`: blocked users get nothing, everyone else goes through the
+/// guild's command permissions.
+pub async fn check(ctx: Ctx<'_>) -> Result<bool, Error> {
+    if is_blocked(ctx.serenity_context(), ctx.author().id).await {
+        return Err(Blocked.into());
+    }
+    crate::features::check(ctx).await?;
+    crate::permissions::check(ctx).await
+}
+
+pub fn parse_guild(input: &str) -> Result<GuildId, Error> {
+    match input.trim().parse::<u64>() {
+        Ok(id) if id != 0 => Ok(GuildId::new(id)),
+        _ => Err(format!("`{}` isn't a server ID.", input).into()),
+    }
+}
+
+/// Ignore every command and button press from a user
+#[poise::command(prefix_command, slash_command, owners_only, rename = "block")]
+pub async fn admin_block(
+    ctx: Ctx<'_>,
+    #[description = "User to block"] user: serenity::User,
+    #[description = "Why (only shown to owners)"]
+    #[rest]
+    reason: Option<String>,
+) -> Result<(), Error> {
+    if ctx.framework().options().owners.contains(&user.id) {
+        ctx.say("Owners can't be blocked.").await?;
+        return Ok(());
+    }
+    let db = storage::db(ctx.serenity_context()).await?;
+    let user_id = user.id.get() as i64;
+    let at = serenity::Timestamp::now().unix_timestamp();
+    db.call(move |conn| {
+        conn.execute(
+            "INSERT INTO blocked_users (user_id, reason, added_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(user_id) DO UPDATE SET reason = excluded.reason",
+            rusqlite::params![user_id, reason, at],
+        )
+    })
+    .await?;
+    if let Some(store) = store(ctx.serenity_context()).await {
+        store.write().await.blocked.insert(user.id);
+    }
+    ctx.say(format!("Blocked {} (`{}`).", user.name, user.id)).await?;
+    Ok(())
+}
+
+/// Let a blocked user use the bot again
+#[poise::command(prefix_command, slash_command, owners_only, rename = "unblock")]
+pub async fn admin_unblock(ctx: Ctx<'_>, #[description = "User to unblock"] user: serenity::User) -> Result<(), Error> {
+    let db = storage::db(ctx.serenity_context()).await?;
+    let user_id = user.id.get() as i64;
+    let removed = db.call(move |conn| conn.execute("DELETE FROM blocked_users WHERE user_id = ?1", [user_id])).await?;
+    if let Some(store) = store(ctx.serenity_context()).await {
+        store.write().await.blocked.remove(&user.id);
+    }
+    if removed == 0 {
+        ctx.say(format!("{} isn't blocked.", user.name)).await?;
+    } else {
+        ctx.say(format!("Unblocked {}.", user.name)).await?;
+    }
+    Ok(())
+}
+
+/// List blocked users
+#[poise::command(prefix_command, slash_command, owners_only, rename = "blocklist")]
+pub async fn admin_blocklist(ctx: Ctx<'_>) -> Result<(), Error> {
+    let db = storage::db(ctx.serenity_context()).await?;
+    let rows: Vec<(i64, Option<String>, i64)> = db
+        .call(|conn| {
+            let mut stmt = conn.prepare("SELECT user_id, reason, added_at FROM blocked_users ORDER BY added_at DESC")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect()
+        })
+        .await?;
+    if rows.is_empty() {
+        ctx.say("Nobody is blocked.").await?;
+        return Ok(());
+    }
+    let mut lines: Vec<String> = rows
+        .iter()
+        .take(40)
+        .map(|(user_id, reason, at)| match reason {
+            Some(reason) => format!("<@{}> since <t:{}:d> — {}", user_id, at, reason),
+            None => format!("<@{}> since <t:{}:d>", user_id, at),
+        })
+        .collect();
+    if rows.len() > lines.len() {
+        lines.push(format!("…and {} more", rows.len() - lines.len()));
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Servers a private instance may stay in (needs `access.guild_allowlist` in the config)
+#[poise::command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    rename = "allowlist",
+    subcommands("allowlist_add", "allowlist_remove", "allowlist_list")
+)]
+pub async fn admin_allowlist(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.say("Subcommands: `add`, `remove`, `list`").await?;
+    Ok(())
+}
+
+/// Allow a server
+#[poise::command(prefix_command, slash_command, owners_only, rename = "add")]
+pub async fn allowlist_add(ctx: Ctx<'_>, #[description = "Server ID"] guild: String) -> Result<(), Error> {
+    let gid = parse_guild(&guild)?;
+    let db = storage::db(ctx.serenity_context()).await?;
+    let guild_id = gid.get() as i64;
+    let at = serenity::Timestamp::now().unix_timestamp();
+    db.call(move |conn| {
+        conn.execute("INSERT OR IGNORE INTO allowed_guilds (guild_id, added_at) VALUES (?1, ?2)", [guild_id, at])
+    })
+    .await?;
+    if let Some(store) = store(ctx.serenity_context()).await {
+        store.write().await.allowed.insert(gid);
+    }
+    ctx.say(format!("Allowed server `{}`.", gid)).await?;
+    // The first server on the list is what starts restricting the others
+    enforce_all(ctx.serenity_context()).await;
+    Ok(())
+}
+
+/// Remove a server; the bot leaves it right away while the allowlist is on
+#[poise::command(prefix_command, slash_command, owners_only, rename = "remove")]
+pub async fn allowlist_remove(ctx: Ctx<'_>, #[description = "Server ID"] guild: String) -> Result<(), Error> {
+    let gid = parse_guild(&guild)?;
+    let db = storage::db(ctx.serenity_context()).await?;
+    let guild_id = gid.get() as i64;
+    db.call(move |conn| conn.execute("DELETE FROM allowed_guilds WHERE guild_id = ?1", [guild_id])).await?;
+    if let Some(store) = store(ctx.serenity_context()).await {
+        store.write().await.allowed.remove(&gid);
+    }
+    // Say it before leaving, in case this is the server the command came from
+    ctx.say(format!("Removed server `{}` from the allowlist.", gid)).await?;
+    if ctx.serenity_context().cache.guild(gid).is_some() {
+        enforce_guild(ctx.serenity_context(), gid).await;
+    }
+    Ok(())
+}
+
+/// List allowed servers
+#[poise::command(prefix_command, slash_command, owners_only, rename = "list")]
+pub async fn allowlist_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    let enabled = config::current(ctx.serenity_context()).await.access.guild_allowlist;
+    let allowed: Vec<GuildId> = match store(ctx.serenity_context()).await {
+        Some(store) => store.read().await.allowed.iter().copied().collect(),
+        None => Vec::new(),
+    };
+    let mut text = if enabled && allowed.is_empty() {
+        "The allowlist is **on**, but restricts nothing until a server is added.".to_string()
+    } else if enabled {
+        "The allowlist is **on**: the bot leaves servers not listed here.".to_string()
+    } else {
+        "The allowlist is **off** (`access.guild_allowlist` in the config); the list below isn't enforced.".to_string()
+    };
+    if allowed.is_empty() {
+        text.push_str("\nNo servers allowed yet.");
+    }
+    for gid in allowed.iter().take(40) {
+        let name = ctx.serenity_context().cache.guild(*gid).map(|g| g.name.clone());
+        match name {
+            Some(name) => text.push_str(&format!("\n`{}` — {}", gid, name)),
+            None => text.push_str(&format!("\n`{}` — not joined", gid)),
+        }
+    }
+    ctx.say(text).await?;
+    Ok(())
+}
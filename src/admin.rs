@@ -0,0 +1,128 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use std::sync::Mutex;
+
+use crate::{config, player, registration};
+use crate::{Ctx, Error};
+
+/// Guilds `pauseall` paused, for `resumeall` to pick up again. Tracks someone had already
+/// paused themselves aren't in here and stay paused.
+static PAUSED_BY_OWNER: Mutex<Vec<GuildId>> = Mutex::new(Vec::new());
+
+/// Bot owner tools
+#[poise::command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    subcommands(
+        "admin_reload",
+        "admin_register",
+        "admin_build_helper",
+        "admin_pauseall",
+        "admin_resumeall",
+        "crate::party::admin_party",
+        "crate::features::admin_feature",
+        "crate::privacy::admin_purge_guild_data",
+        "crate::backup::admin_backup",
+        "crate::backup::admin_restore",
+        "crate::audit::admin_audit",
+        "crate::access::admin_block",
+        "crate::access::admin_unblock",
+        "crate::access::admin_blocklist",
+        "crate::access::admin_allowlist"
+    )
+)]
+pub async fn admin(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.say("Subcommands: `reload`, `register`, `build-helper`, `pauseall`, `resumeall`, `party`, `feature`, `purge-guild-data`, `backup`, `restore`, `audit`, `block`, `unblock`, `blocklist`, `allowlist`").await?;
+    Ok(())
+}
+
+/// Re-read config.jsonc without restarting
+#[poise::command(prefix_command, slash_command, owners_only, rename = "reload")]
+pub async fn admin_reload(ctx: Ctx<'_>) -> Result<(), Error> {
+    match config::reload(ctx.serenity_context()).await {
+        Ok(()) => ctx.say(format!("Reloaded `{}`.", config::config_path())).await?,
+        Err(e) => {
+            ctx.say(format!("`{}` is invalid, keeping the previous config: {}", config::config_path(), e))
+                .await?
+        }
+    };
+    Ok(())
+}
+
+/// Re-push slash commands to every scope, ignoring the unchanged-checksum skip
+#[poise::command(prefix_command, slash_command, owners_only, rename = "register")]
+pub async fn admin_register(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let report = registration::sync(ctx.serenity_context(), &ctx.framework().options().commands, true).await?;
+    ctx.say(format!("Commands re-synced: {}.", report)).await?;
+    Ok(())
+}
+
+/// Build the Spotify stream helper from `tools/librespot-wrapper` and install it to `.bin/`
+#[poise::command(prefix_command, slash_command, owners_only, rename = "build-helper")]
+pub async fn admin_build_helper(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    match crate::music::build_spotify_helper().await {
+        Ok(version) => ctx.say(format!("Built and installed the Spotify helper `{}`.", version)).await?,
+        Err(e) => ctx.say(format!("Couldn't build the Spotify helper: {}", e)).await?,
+    };
+    Ok(())
+}
+
+/// Pause playback in every server at once, e.g. before a restart
+#[poise::command(prefix_command, slash_command, owners_only, rename = "pauseall")]
+pub async fn admin_pauseall(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let mut paused = Vec::new();
+    let mut failed = 0;
+    for gid in player::playing_guilds(sctx).await {
+        if player::status(sctx, gid).await.is_none_or(|s| s.paused) {
+            continue;
+        }
+        match player::set_paused(sctx, gid, true).await {
+            Ok(true) => paused.push(gid),
+            Ok(false) => {}
+            Err(e) => {
+                tracing::warn!("pauseall: couldn't pause guild {}: {e:?}", gid);
+                failed += 1;
+            }
+        }
+    }
+    let count = paused.len();
+    PAUSED_BY_OWNER.lock().unwrap_or_else(|e| e.into_inner()).extend(paused);
+    let mut text = format!("Paused playback in {} server(s); `admin resumeall` picks it up again.", count);
+    if failed > 0 {
+        text.push_str(&format!(" {} couldn't be paused, see the log.", failed));
+    }
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// Resume everything `pauseall` paused
+#[poise::command(prefix_command, slash_command, owners_only, rename = "resumeall")]
+pub async fn admin_resumeall(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guilds = std::mem::take(&mut *PAUSED_BY_OWNER.lock().unwrap_or_else(|e| e.into_inner()));
+    if guilds.is_empty() {
+        ctx.say("Nothing was paused with `admin pauseall`.").await?;
+        return Ok(());
+    }
+    let mut resumed = 0;
+    for gid in guilds {
+        match player::set_paused(sctx, gid, false).await {
+            Ok(true) => resumed += 1,
+            // The track ended or the bot left since
+            Ok(false) => {}
+            Err(e) => tracing::warn!("resumeall: couldn't resume guild {}: {e:?}", gid),
+        }
+    }
+    ctx.say(format!("Resumed playback in {} server(s).", resumed)).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![admin()]
+}
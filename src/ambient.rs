@@ -0,0 +1,207 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId};
+use serenity::builder::CreateMessage;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::config::{self, AmbientPreset};
+use crate::{embeds, player, Ctx, Error};
+
+/// A stream that ends sooner than this after starting isn't restarted, so a dead URL doesn't
+/// respawn ffmpeg in a loop.
+const MIN_RUN: Duration = Duration::from_secs(30);
+
+/// The ambient preset a guild is playing. While set, the track ending restarts it instead of
+/// advancing the queue.
+#[derive(Debug, Clone)]
+pub struct Ambient {
+    preset: String,
+    channel: ChannelId,
+    /// `None` until the preset's track starts; a switch sets this so `resume` starts the new one.
+    started: Option<Instant>,
+}
+
+pub struct AmbientStore;
+impl TypeMapKey for AmbientStore {
+    type Value = Arc<Mutex<HashMap<GuildId, Ambient>>>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<HashMap<GuildId, Ambient>>>> {
+    ctx.data.read().await.get::<AmbientStore>().cloned()
+}
+
+/// Name of the preset playing in `guild_id`, if any.
+pub async fn active(ctx: &Context, guild_id: GuildId) -> Option<String> {
+    let store = store(ctx).await?;
+    store.lock().await.get(&guild_id).map(|a| a.preset.clone())
+}
+
+/// Forget the guild's ambient preset so the current track ending doesn't restart it. Called
+/// on skip and leave; returns whether one was playing.
+pub async fn stop(ctx: &Context, guild_id: GuildId) -> bool {
+    match store(ctx).await {
+        Some(store) => store.lock().await.remove(&guild_id).is_some(),
+        None => false,
+    }
+}
+
+/// `yt-dlp -g` for page URLs (YouTube etc.); direct media links come back unchanged, and
+/// anything yt-dlp can't handle is passed to ffmpeg as-is.
+async fn media_url(ctx: &Context, url: &str) -> String {
+    let cookie_args = config::current(ctx).await.music.cookie_args();
+    let out = tokio::process::Command::new("yt-dlp")
+        .kill_on_drop(true)
+        .args(&cookie_args)
+        .args(["-g", "-f", "bestaudio/best", url])
+        .output()
+        .await;
+    match out {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .next()
+            .map(str::to_string)
+            .unwrap_or_else(|| url.to_string()),
+        _ => url.to_string(),
+    }
+}
+
+/// Start `preset` on the guild's call. ffmpeg loops the input forever (`-stream_loop -1`) and
+/// reconnects dropped HTTP streams, so the track doesn't end on its own.
+async fn start(ctx: &Context, guild_id: GuildId, name: &str, preset: &AmbientPreset) -> Result<(), Error> {
+    let call = songbird::get(ctx)
+        .await
+        .and_then(|m| m.get(guild_id))
+        .ok_or("I'm not in a voice channel; use `music join` first.")?;
+
+    let mut ffmpeg = std::process::Command::new("ffmpeg");
+    ffmpeg.arg("-nostdin");
+    match (&preset.generator, &preset.url) {
+        (Some(generator), _) => {
+            ffmpeg.args(["-f", "lavfi", "-i", generator]);
+        }
+        (None, Some(url)) => {
+            let input = if url.starts_with("http://") || url.starts_with("https://") {
+                ffmpeg.args(["-reconnect", "1", "-reconnect_streamed", "1", "-reconnect_delay_max", "10"]);
+                media_url(ctx, url).await
+            } else {
+                url.clone()
+            };
+            ffmpeg.args(["-stream_loop", "-1", "-i", &input]);
+        }
+        (None, None) => return Err(format!("Preset `{}` has no `generator` or `url`.", name).into()),
+    }
+    ffmpeg
+        .args(["-vn", "-c:a", "pcm_s16le", "-f", "wav", "-ar", "48000", "-ac", "2", "pipe:1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let child = crate::music::spawn_child(ctx, guild_id, &mut ffmpeg).await?;
+
+    let input: songbird::input::Input = songbird::input::ChildContainer::from(child).into();
+    let handle = call.lock().await.play_input(input);
+    handle.make_playable_async().await?;
+    let _ = handle.set_volume(preset.volume.unwrap_or(0.20));
+    if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
+        let meta = crate::TrackMeta { title: Some(format!("Ambient: {}", name)), ..Default::default() };
+        ms.lock().await.insert(guild_id, meta);
+    }
+    let _ = crate::music::store_handle(ctx, guild_id, handle).await;
+    Ok(())
+}
+
+/// Called when the guild's track ends. Restarts the ambient preset if one is set; returns
+/// false (and lets the queue advance) if none is or it couldn't be kept going.
+pub async fn resume(ctx: &Context, guild_id: GuildId) -> bool {
+    let Some(store) = store(ctx).await else { return false };
+    let Some(ambient) = store.lock().await.get(&guild_id).cloned() else { return false };
+    let preset = config::current(ctx).await.music.ambient_preset(&ambient.preset);
+    let problem = match preset {
+        _ if ambient.started.is_some_and(|s| s.elapsed() < MIN_RUN) => "keeps ending right after it starts".to_string(),
+        None => "is no longer configured".to_string(),
+        Some(preset) => match start(ctx, guild_id, &ambient.preset, &preset).await {
+            Ok(()) => {
+                if let Some(current) = store.lock().await.get_mut(&guild_id) {
+                    current.started = Some(Instant::now());
+                }
+                return true;
+            }
+            Err(e) => format!("couldn't be restarted: {}", e),
+        },
+    };
+    store.lock().await.remove(&guild_id);
+    let theme = embeds::theme(ctx, Some(guild_id)).await;
+    let embed = theme.embed().title("Ambient").description(format!("Stopped `{}`: it {}.", ambient.preset, problem));
+    let _ = ambient.channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await;
+    false
+}
+
+async fn autocomplete_preset(ctx: Ctx<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    let mut names = config::current(ctx.serenity_context()).await.music.ambient_names();
+    names.push("off".to_string());
+    names.retain(|n| n.contains(&partial));
+    names
+}
+
+/// Loop an ambient sound (rain, noise, lo-fi…) until stopped
+#[poise::command(prefix_command, slash_command, guild_only, rename = "ambient")]
+pub async fn music_ambient(
+    ctx: Ctx<'_>,
+    #[description = "Preset, or `off`"]
+    #[autocomplete = "autocomplete_preset"]
+    preset: String,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let name = preset.trim().to_lowercase();
+
+    if name == "off" || name == "stop" {
+        // `skip` forgets the preset before ending its track
+        let text = if active(sctx, guild_id).await.is_some() {
+            player::skip(sctx, guild_id).await?;
+            "Ambient sound stopped."
+        } else {
+            "No ambient sound is playing."
+        };
+        ctx.say(text).await?;
+        return Ok(());
+    }
+
+    let music = config::current(sctx).await.music.clone();
+    if music.player_backend == config::PlayerBackend::Lavalink {
+        ctx.say("Ambient presets need the built-in player (`music.player_backend` \"songbird\").").await?;
+        return Ok(());
+    }
+    let Some(found) = music.ambient_preset(&name) else {
+        ctx.say(format!("Unknown preset `{}`. Presets: {}", name, music.ambient_names().join(", "))).await?;
+        return Ok(());
+    };
+    let switching = active(sctx, guild_id).await.is_some();
+    if !switching && player::status(sctx, guild_id).await.is_some() {
+        ctx.say("Music is playing; stop it first (ambient sound plays instead of the queue, not under it).").await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let Some(store) = store(sctx).await else { return Ok(()) };
+    let mut ambient = Ambient { preset: name.clone(), channel: ctx.channel_id(), started: None };
+    if switching {
+        // Ending the current preset's track makes `resume` start the new one
+        store.lock().await.insert(guild_id, ambient);
+        let tracks = sctx.data.read().await.get::<crate::TrackStore>().cloned();
+        if let Some(tracks) = tracks
+            && let Some(handle) = tracks.lock().await.get(&guild_id)
+        {
+            let _ = handle.stop();
+        }
+    } else {
+        start(sctx, guild_id, &name, &found).await?;
+        ambient.started = Some(Instant::now());
+        store.lock().await.insert(guild_id, ambient);
+    }
+    ctx.say(format!("Playing ambient `{}` on a loop. `music ambient off` or `music play` stops it.", name)).await?;
+    Ok(())
+}
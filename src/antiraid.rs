@@ -0,0 +1,579 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::builder::EditChannel;
+use serenity::model::channel::{ChannelType, Message, PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::Permissions;
+use serenity::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::modalert::AlertKind;
+use crate::moderation::{apply_timeout, format_duration, parse_duration, report};
+use crate::scheduler::JobFuture;
+use crate::{Ctx, Error};
+
+pub const ANTIRAID_PATH: &str = "antiraid.json";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum FloodAction {
+    /// Set channel slowmode (message floods only)
+    Slowmode { seconds: u16 },
+    /// Time out the offending members
+    Timeout { duration_secs: u64 },
+    /// Deny Send Messages to @everyone in every channel until `antiraid unlock`
+    Lock,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FloodRule {
+    pub count: usize,
+    pub window_secs: u64,
+    #[serde(flatten)]
+    pub action: FloodAction,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildAntiRaid {
+    /// Per-member message flood rule
+    #[serde(default)]
+    pub messages: Option<FloodRule>,
+    /// Guild-wide join flood rule
+    #[serde(default)]
+    pub joins: Option<FloodRule>,
+    /// Channels changed by a lockdown, with what @everyone had there before
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lockdown: Vec<LockedChannel>,
+}
+
+/// A channel's @everyone overwrite from before a lockdown, so `antiraid unlock` can put it back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedChannel {
+    pub channel: u64,
+    /// `(allow, deny)` bits, or `None` if @everyone had no overwrite there
+    pub previous: Option<(u64, u64)>,
+}
+
+/// Sliding windows used for detection (not persisted).
+#[derive(Default)]
+struct Tracker {
+    messages: HashMap<UserId, VecDeque<Instant>>,
+    joins: VecDeque<(Instant, UserId)>,
+}
+
+#[derive(Default)]
+pub struct AntiRaidState {
+    guilds: HashMap<u64, GuildAntiRaid>,
+    trackers: HashMap<GuildId, Tracker>,
+}
+
+pub struct AntiRaidStore;
+impl TypeMapKey for AntiRaidStore {
+    type Value = Arc<Mutex<AntiRaidState>>;
+}
+
+async fn load_disk() -> Result<HashMap<u64, GuildAntiRaid>, Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::load_json(&crate::storage::data_path(ANTIRAID_PATH)).await
+}
+
+async fn save_disk(state: &AntiRaidState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(ANTIRAID_PATH), &state.guilds).await
+}
+
+pub async fn ensure_antiraid_store() -> Result<Arc<Mutex<AntiRaidState>>, Box<dyn std::error::Error + Send + Sync>> {
+    let guilds = load_disk().await?;
+    Ok(Arc::new(Mutex::new(AntiRaidState { guilds, trackers: HashMap::new() })))
+}
+
+async fn antiraid_store(ctx: &Context) -> Result<Arc<Mutex<AntiRaidState>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<AntiRaidStore>()
+        .cloned()
+        .ok_or_else(|| "Anti-raid store not initialised".into())
+}
+
+/// Drop `gid`'s flood rules, detection windows and any lockdown it's in the middle of.
+pub async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<(), Error> {
+    let store = antiraid_store(ctx).await?;
+    let mut state = store.lock().await;
+    state.trackers.remove(&gid);
+    if state.guilds.remove(&gid.get()).is_some() {
+        save_disk(&state).await?;
+    }
+    Ok(())
+}
+
+/// Recurring scheduler job dropping detection windows that have gone quiet.
+pub const PRUNE_JOB: &str = "antiraid_prune";
+
+/// How often [`PRUNE_JOB`] runs.
+pub const PRUNE_EVERY: Duration = Duration::from_secs(10 * 60);
+
+pub fn run_prune_job(ctx: &Context, _payload: serde_json::Value) -> JobFuture<'_> {
+    Box::pin(async move {
+        let store = antiraid_store(ctx).await?;
+        let mut state = store.lock().await;
+        let now = Instant::now();
+        let AntiRaidState { guilds, trackers } = &mut *state;
+        trackers.retain(|gid, tracker| {
+            let Some(cfg) = guilds.get(&gid.get()) else { return false };
+            let msg_span = cfg.messages.map(|r| Duration::from_secs(r.window_secs)).unwrap_or_default();
+            drop_quiet_members(&mut tracker.messages, now, msg_span);
+            let join_span = cfg.joins.map(|r| Duration::from_secs(r.window_secs)).unwrap_or_default();
+            tracker.joins.retain(|(t, _)| now.duration_since(*t) <= join_span);
+            !tracker.messages.is_empty() || !tracker.joins.is_empty()
+        });
+        Ok(())
+    })
+}
+
+/// Forget members whose message window is empty or has nothing newer than `span`.
+fn drop_quiet_members(windows: &mut HashMap<UserId, VecDeque<Instant>>, now: Instant, span: Duration) {
+    windows.retain(|_, w| w.back().is_some_and(|t| now.duration_since(*t) <= span));
+}
+
+/// Push `now` into a sliding window and return how many entries fall inside it.
+fn record(window: &mut VecDeque<Instant>, now: Instant, span: Duration) -> usize {
+    window.push_back(now);
+    while window.front().is_some_and(|t| now.duration_since(*t) > span) {
+        window.pop_front();
+    }
+    window.len()
+}
+
+/// Track a guild message and act if its author is flooding.
+pub async fn check_message(ctx: &Context, msg: &Message) {
+    let Some(gid) = msg.guild_id else { return };
+    if msg.author.bot {
+        return;
+    }
+    let Ok(store) = antiraid_store(ctx).await else { return };
+
+    let rule = {
+        let mut state = store.lock().await;
+        let Some(rule) = state.guilds.get(&gid.get()).and_then(|g| g.messages) else { return };
+        let tracker = state.trackers.entry(gid).or_default();
+        let now = Instant::now();
+        let span = Duration::from_secs(rule.window_secs);
+        drop_quiet_members(&mut tracker.messages, now, span);
+        let window = tracker.messages.entry(msg.author.id).or_default();
+        if record(window, now, span) < rule.count {
+            return;
+        }
+        // Reset so one flood triggers one response
+        window.clear();
+        rule
+    };
+
+    let outcome = match rule.action {
+        FloodAction::Slowmode { seconds } => slowmode(ctx, gid, msg.channel_id, seconds).await,
+        FloodAction::Timeout { duration_secs } => {
+            timeout_all(ctx, gid, &[msg.author.id], Duration::from_secs(duration_secs)).await
+        }
+        FloodAction::Lock => lock(ctx, gid, true).await,
+    };
+    report(
+        ctx,
+        gid,
+        AlertKind::Raid,
+        format!(
+            "Message flood: {} sent {}+ messages within {}s in <#{}> ({}).",
+            msg.author.tag(),
+            rule.count,
+            rule.window_secs,
+            msg.channel_id,
+            outcome
+        ),
+    )
+    .await;
+}
+
+/// Track a member join and act if the guild is seeing a join flood.
+pub async fn check_join(ctx: &Context, member: &Member) {
+    let gid = member.guild_id;
+    let Ok(store) = antiraid_store(ctx).await else { return };
+
+    let (rule, joiners) = {
+        let mut state = store.lock().await;
+        let Some(rule) = state.guilds.get(&gid.get()).and_then(|g| g.joins) else { return };
+        let tracker = state.trackers.entry(gid).or_default();
+        let now = Instant::now();
+        tracker.joins.push_back((now, member.user.id));
+        let span = Duration::from_secs(rule.window_secs);
+        while tracker.joins.front().is_some_and(|(t, _)| now.duration_since(*t) > span) {
+            tracker.joins.pop_front();
+        }
+        if tracker.joins.len() < rule.count {
+            return;
+        }
+        let joiners: Vec<UserId> = tracker.joins.drain(..).map(|(_, u)| u).collect();
+        (rule, joiners)
+    };
+
+    let outcome = match rule.action {
+        FloodAction::Timeout { duration_secs } => {
+            timeout_all(ctx, gid, &joiners, Duration::from_secs(duration_secs)).await
+        }
+        FloodAction::Lock => lock(ctx, gid, true).await,
+        FloodAction::Slowmode { .. } => "no action (slowmode only applies to message floods)".to_string(),
+    };
+    report(
+        ctx,
+        gid,
+        AlertKind::Raid,
+        format!(
+            "Join flood: {} members joined within {}s ({}). Joiners: {}",
+            joiners.len(),
+            rule.window_secs,
+            outcome,
+            joiners.iter().map(|u| format!("<@{}>", u)).collect::<Vec<_>>().join(", ")
+        ),
+    )
+    .await;
+}
+
+async fn slowmode(ctx: &Context, gid: GuildId, channel: ChannelId, seconds: u16) -> String {
+    if crate::dryrun::active(ctx, Some(gid)).await {
+        crate::dryrun::skip(Some(gid), &format!("set slowmode in <#{}> to {}s", channel, seconds));
+        return format!("slowmode set to {}s [dry run]", seconds);
+    }
+    match channel.edit(&ctx.http, EditChannel::new().rate_limit_per_user(seconds)).await {
+        Ok(_) => format!("slowmode set to {}s", seconds),
+        Err(e) => format!("failed to set slowmode: {}", e),
+    }
+}
+
+async fn timeout_all(ctx: &Context, gid: GuildId, users: &[UserId], dur: Duration) -> String {
+    let mut ok = 0;
+    for uid in users {
+        match apply_timeout(ctx, gid, *uid, dur, "Anti-raid: flood detected").await {
+            Ok(()) => ok += 1,
+            Err(e) => tracing::warn!("Anti-raid timeout of {} in guild {} failed: {e:?}", uid, gid),
+        }
+    }
+    format!("timed out {}/{} for {}", ok, users.len(), format_duration(dur))
+}
+
+/// Lock the server by denying @everyone Send Messages in each channel, remembering the
+/// overwrite each one had; unlocking puts exactly those back.
+async fn lock(ctx: &Context, gid: GuildId, locked: bool) -> String {
+    if crate::dryrun::active(ctx, Some(gid)).await {
+        let verb = if locked { "lock" } else { "unlock" };
+        crate::dryrun::skip(Some(gid), &format!("{} the server", verb));
+        return format!("server {}ed [dry run]", verb);
+    }
+    let store = match antiraid_store(ctx).await {
+        Ok(store) => store,
+        Err(e) => return format!("failed to load anti-raid state: {}", e),
+    };
+    // Held throughout so a lock and an unlock can't interleave
+    let mut state = store.lock().await;
+    let cfg = state.guilds.entry(gid.get()).or_default();
+    let outcome = if locked { lock_channels(ctx, gid, cfg).await } else { unlock_channels(ctx, gid, cfg).await };
+    if let Err(e) = save_disk(&state).await {
+        tracing::error!(guild = %gid, error = %e, "couldn't save the anti-raid lockdown");
+    }
+    outcome
+}
+
+async fn lock_channels(ctx: &Context, gid: GuildId, cfg: &mut GuildAntiRaid) -> String {
+    if !cfg.lockdown.is_empty() {
+        return "server already locked (use `antiraid unlock` to undo)".to_string();
+    }
+    let channels = match gid.channels(&ctx.http).await {
+        Ok(channels) => channels,
+        Err(e) => return format!("failed to fetch channels: {}", e),
+    };
+    // The @everyone role shares the guild's id
+    let everyone = PermissionOverwriteType::Role(RoleId::new(gid.get()));
+    let (mut done, mut failed) = (0, 0);
+    for channel in channels.values() {
+        if !matches!(
+            channel.kind,
+            ChannelType::Text | ChannelType::News | ChannelType::Forum | ChannelType::Voice | ChannelType::Stage
+        ) {
+            continue;
+        }
+        let previous = channel.permission_overwrites.iter().find(|o| o.kind == everyone);
+        if previous.is_some_and(|o| o.deny.contains(Permissions::SEND_MESSAGES)) {
+            // Already read-only for @everyone: leave it alone so unlocking does too
+            continue;
+        }
+        let (allow, deny) = previous.map(|o| (o.allow, o.deny)).unwrap_or_default();
+        let overwrite = PermissionOverwrite {
+            allow: allow - Permissions::SEND_MESSAGES,
+            deny: deny | Permissions::SEND_MESSAGES,
+            kind: everyone,
+        };
+        match channel.id.create_permission(&ctx.http, overwrite).await {
+            Ok(()) => {
+                done += 1;
+                cfg.lockdown.push(LockedChannel {
+                    channel: channel.id.get(),
+                    previous: previous.map(|o| (o.allow.bits(), o.deny.bits())),
+                });
+            }
+            Err(e) => {
+                failed += 1;
+                tracing::warn!(guild = %gid, channel = %channel.id, error = %e, "anti-raid couldn't lock a channel");
+            }
+        }
+    }
+    let mut outcome = format!("server locked in {} channels (use `antiraid unlock` to undo)", done);
+    if failed > 0 {
+        outcome.push_str(&format!("; {} channels couldn't be changed", failed));
+    }
+    outcome
+}
+
+async fn unlock_channels(ctx: &Context, gid: GuildId, cfg: &mut GuildAntiRaid) -> String {
+    if cfg.lockdown.is_empty() {
+        return "server isn't locked".to_string();
+    }
+    let everyone = PermissionOverwriteType::Role(RoleId::new(gid.get()));
+    let mut remaining = Vec::new();
+    let mut done = 0;
+    for locked in std::mem::take(&mut cfg.lockdown) {
+        let channel = ChannelId::new(locked.channel);
+        let result = match locked.previous {
+            Some((allow, deny)) => {
+                let overwrite = PermissionOverwrite {
+                    allow: Permissions::from_bits_truncate(allow),
+                    deny: Permissions::from_bits_truncate(deny),
+                    kind: everyone,
+                };
+                channel.create_permission(&ctx.http, overwrite).await
+            }
+            None => channel.delete_permission(&ctx.http, everyone).await,
+        };
+        match result {
+            Ok(()) => done += 1,
+            // The channel is gone, so there's nothing left to restore
+            Err(serenity::Error::Http(h)) if h.status_code() == Some(serenity::StatusCode::NOT_FOUND) => {}
+            Err(e) => {
+                tracing::warn!(guild = %gid, %channel, error = %e, "anti-raid couldn't unlock a channel");
+                remaining.push(locked);
+            }
+        }
+    }
+    let failed = remaining.len();
+    cfg.lockdown = remaining;
+    if failed > 0 {
+        format!("server unlocked in {} channels; {} failed, run `antiraid unlock` again to retry", done, failed)
+    } else {
+        format!("server unlocked in {} channels", done)
+    }
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum ActionChoice {
+    #[name = "slowmode"]
+    Slowmode,
+    #[name = "timeout"]
+    Timeout,
+    #[name = "lock"]
+    Lock,
+}
+
+fn build_action(action: ActionChoice, duration: Option<&str>) -> Result<FloodAction, String> {
+    match action {
+        ActionChoice::Lock => Ok(FloodAction::Lock),
+        ActionChoice::Timeout => {
+            let dur = duration
+                .and_then(parse_duration)
+                .ok_or("The timeout action needs a duration, e.g. `10m`.")?;
+            Ok(FloodAction::Timeout { duration_secs: dur.as_secs() })
+        }
+        ActionChoice::Slowmode => {
+            let dur = duration
+                .and_then(parse_duration)
+                .ok_or("The slowmode action needs a delay, e.g. `30s`.")?;
+            // Discord allows up to 6 hours of slowmode
+            Ok(FloodAction::Slowmode { seconds: dur.as_secs().min(21600) as u16 })
+        }
+    }
+}
+
+fn describe_rule(rule: Option<FloodRule>) -> String {
+    match rule {
+        None => "off".to_string(),
+        Some(r) => {
+            let action = match r.action {
+                FloodAction::Slowmode { seconds } => format!("slowmode {}s", seconds),
+                FloodAction::Timeout { duration_secs } => {
+                    format!("timeout for {}", format_duration(Duration::from_secs(duration_secs)))
+                }
+                FloodAction::Lock => "lock server".to_string(),
+            };
+            format!("{} within {}s → {}", r.count, r.window_secs, action)
+        }
+    }
+}
+
+/// Message and join flood detection
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands("antiraid_messages", "antiraid_joins", "antiraid_off", "antiraid_status", "antiraid_unlock"),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn antiraid(ctx: Ctx<'_>) -> Result<(), Error> {
+    status(ctx).await
+}
+
+/// React when a member sends too many messages too quickly
+#[poise::command(prefix_command, slash_command, guild_only, rename = "messages")]
+pub async fn antiraid_messages(
+    ctx: Ctx<'_>,
+    #[description = "Messages per member that count as a flood"]
+    #[min = 2]
+    count: usize,
+    #[description = "Window in seconds"]
+    #[min = 1]
+    seconds: u64,
+    #[description = "What to do"] action: ActionChoice,
+    #[description = "Slowmode delay or timeout duration, e.g. 30s or 10m"] duration: Option<String>,
+) -> Result<(), Error> {
+    set_rule(ctx, false, count, seconds, action, duration).await
+}
+
+/// React when many members join in a short time
+#[poise::command(prefix_command, slash_command, guild_only, rename = "joins")]
+pub async fn antiraid_joins(
+    ctx: Ctx<'_>,
+    #[description = "Joins that count as a raid"]
+    #[min = 2]
+    count: usize,
+    #[description = "Window in seconds"]
+    #[min = 1]
+    seconds: u64,
+    #[description = "What to do (timeout the joiners or lock the server)"] action: ActionChoice,
+    #[description = "Timeout duration, e.g. 1h"] duration: Option<String>,
+) -> Result<(), Error> {
+    if matches!(action, ActionChoice::Slowmode) {
+        ctx.say("Slowmode only applies to message floods; use `timeout` or `lock` for joins.").await?;
+        return Ok(());
+    }
+    set_rule(ctx, true, count, seconds, action, duration).await
+}
+
+async fn set_rule(
+    ctx: Ctx<'_>,
+    joins: bool,
+    count: usize,
+    seconds: u64,
+    action: ActionChoice,
+    duration: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let action = match build_action(action, duration.as_deref()) {
+        Ok(a) => a,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+    let rule = FloodRule { count: count.max(2), window_secs: seconds.max(1), action };
+
+    let store = antiraid_store(ctx.serenity_context()).await?;
+    {
+        let mut state = store.lock().await;
+        let cfg = state.guilds.entry(guild_id.get()).or_default();
+        if joins {
+            cfg.joins = Some(rule);
+        } else {
+            cfg.messages = Some(rule);
+        }
+        save_disk(&state).await?;
+    }
+    let what = if joins { "Join flood" } else { "Message flood" };
+    ctx.say(format!("{} detection: {}.", what, describe_rule(Some(rule)))).await?;
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum DetectorChoice {
+    #[name = "messages"]
+    Messages,
+    #[name = "joins"]
+    Joins,
+    #[name = "all"]
+    All,
+}
+
+/// Turn off flood detection
+#[poise::command(prefix_command, slash_command, guild_only, rename = "off")]
+pub async fn antiraid_off(
+    ctx: Ctx<'_>,
+    #[description = "Which detector to turn off"] which: DetectorChoice,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = antiraid_store(ctx.serenity_context()).await?;
+    {
+        let mut state = store.lock().await;
+        let cfg = state.guilds.entry(guild_id.get()).or_default();
+        match which {
+            DetectorChoice::Messages => cfg.messages = None,
+            DetectorChoice::Joins => cfg.joins = None,
+            DetectorChoice::All => {
+                // A lockdown in progress still needs `antiraid unlock` to undo
+                cfg.messages = None;
+                cfg.joins = None;
+            }
+        }
+        state.trackers.remove(&guild_id);
+        save_disk(&state).await?;
+    }
+    ctx.say("Flood detection updated.").await?;
+    Ok(())
+}
+
+/// Show the current flood detection rules
+#[poise::command(prefix_command, slash_command, guild_only, rename = "status")]
+pub async fn antiraid_status(ctx: Ctx<'_>) -> Result<(), Error> {
+    status(ctx).await
+}
+
+async fn status(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = antiraid_store(ctx.serenity_context()).await?;
+    let cfg = {
+        let state = store.lock().await;
+        state.guilds.get(&guild_id.get()).cloned().unwrap_or_default()
+    };
+    ctx.say(format!(
+        "Message floods: {}\nJoin floods: {}",
+        describe_rule(cfg.messages),
+        describe_rule(cfg.joins)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Put back the channel permissions a lockdown changed
+#[poise::command(prefix_command, slash_command, guild_only, rename = "unlock")]
+pub async fn antiraid_unlock(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let outcome = lock(ctx.serenity_context(), guild_id, false).await;
+    report(
+        ctx.serenity_context(),
+        guild_id,
+        AlertKind::Raid,
+        format!("{} by {}.", outcome, ctx.author().tag()),
+    )
+    .await;
+    ctx.say(outcome).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![antiraid()]
+}
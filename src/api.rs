@@ -0,0 +1,247 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use poise::serenity_prelude as serenity;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use serenity::all::{ChannelId, GuildId};
+use tokio::sync::broadcast;
+
+use crate::config::ApiConfig;
+use crate::events::PlayerEvent;
+use crate::{player, queue, web};
+
+type ApiResult = Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>;
+
+/// Routes for the playback API, merged into the HTTP server when `http.api` is set.
+pub fn router(cfg: ApiConfig) -> Router {
+    Router::new()
+        .route("/guilds/{id}/nowplaying", get(now_playing))
+        .route("/guilds/{id}/queue", post(enqueue))
+        .route("/guilds/{id}/skip", post(skip))
+        .route("/guilds/{id}/events", get(events))
+        .with_state(cfg)
+}
+
+fn fail(code: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (code, Json(json!({ "error": message })))
+}
+
+/// Check the bearer token and guild, returning the bot context to act with.
+fn authorize(cfg: &ApiConfig, headers: &HeaderMap, id: u64) -> Result<(&'static serenity::Context, GuildId), (StatusCode, Json<Value>)> {
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    check(cfg, given, id)
+}
+
+fn check(cfg: &ApiConfig, given: &str, id: u64) -> Result<(&'static serenity::Context, GuildId), (StatusCode, Json<Value>)> {
+    if !same(given.as_bytes(), cfg.token.as_bytes()) {
+        return Err(fail(StatusCode::UNAUTHORIZED, "missing or wrong bearer token"));
+    }
+    let gid = GuildId::new(id.max(1));
+    if !cfg.guilds.is_empty() && !cfg.guilds.contains(&gid) {
+        return Err(fail(StatusCode::FORBIDDEN, "this token can't control that guild"));
+    }
+    let bot = web::bot().ok_or_else(|| fail(StatusCode::SERVICE_UNAVAILABLE, "the bot is still starting"))?;
+    if bot.cache.guild(gid).is_none() {
+        return Err(fail(StatusCode::NOT_FOUND, "the bot isn't in that guild"));
+    }
+    Ok((bot, gid))
+}
+
+/// The checks a music command goes through in `gid` that still apply without a user: the
+/// guild allowlist and the `features.music` switch.
+async fn music_allowed(bot: &serenity::Context, gid: GuildId) -> Result<(), (StatusCode, Json<Value>)> {
+    if !crate::access::guild_allowed(bot, gid).await {
+        return Err(fail(StatusCode::FORBIDDEN, "that guild isn't on the allowlist"));
+    }
+    if !crate::features::enabled(bot, gid, crate::features::Feature::Music).await {
+        return Err(fail(StatusCode::FORBIDDEN, "music is switched off in that guild"));
+    }
+    Ok(())
+}
+
+/// Constant-time comparison so the token can't be guessed byte by byte from response times.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// `GET /guilds/:id/nowplaying`: the current track, its position and what's queued after it.
+async fn now_playing(State(cfg): State<ApiConfig>, headers: HeaderMap, Path(id): Path<u64>) -> ApiResult {
+    let (bot, gid) = authorize(&cfg, &headers, id)?;
+    let queued: Vec<Value> = queue::list(bot, gid)
+        .await
+        .into_iter()
+        .map(|t| json!({ "query": t.query, "requested_by": t.requested_by.map(|u| u.to_string()) }))
+        .collect();
+    let Some(status) = player::status(bot, gid).await else {
+        return Ok((StatusCode::OK, Json(json!({ "playing": false, "queue": queued }))));
+    };
+    let meta = bot.data.read().await.get::<crate::TrackMetaStore>().cloned();
+    let (title, artist, duration) = match meta {
+        Some(meta) => match meta.lock().await.get(&gid) {
+            Some(m) => (m.title.clone(), m.artist.clone(), m.duration),
+            None => (None, None, None),
+        },
+        None => (None, None, None),
+    };
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "playing": true,
+            "paused": status.paused,
+            "title": title,
+            "artist": artist,
+            "duration_secs": duration.map(|d| d.as_secs()),
+            "position_secs": status.position.as_secs(),
+            "queue": queued,
+        })),
+    ))
+}
+
+#[derive(Deserialize)]
+struct EnqueueBody {
+    query: String,
+    /// Where the bot posts "now playing" and errors; defaults to the session's music thread,
+    /// else the voice channel's chat.
+    #[serde(default)]
+    channel_id: Option<ChannelId>,
+}
+
+/// `POST /guilds/:id/queue`: play `query` now if nothing is playing, otherwise queue it.
+/// Resolution happens in the background, so this answers 202 straight away.
+async fn enqueue(
+    State(cfg): State<ApiConfig>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Json(body): Json<EnqueueBody>,
+) -> ApiResult {
+    let (bot, gid) = authorize(&cfg, &headers, id)?;
+    music_allowed(bot, gid).await?;
+    let query = body.query.trim().to_string();
+    if query.is_empty() {
+        return Err(fail(StatusCode::BAD_REQUEST, "`query` is empty"));
+    }
+    let voice = match songbird::get(bot).await.and_then(|m| m.get(gid)) {
+        Some(call) => call.lock().await.current_channel(),
+        None => None,
+    };
+    let Some(voice) = voice else {
+        return Err(fail(StatusCode::CONFLICT, "the bot isn't in a voice channel there; use `music join` first"));
+    };
+    let channel = match body.channel_id {
+        Some(channel) => channel,
+        None => crate::music::session_channel(bot, gid, ChannelId::new(voice.0.get())).await,
+    };
+
+    if crate::dedupe::mode(bot, gid).await == crate::dedupe::Mode::Block
+        && let Some(duplicate) = crate::dedupe::find(bot, gid, &query).await
+    {
+        let reason = match duplicate {
+            crate::dedupe::Duplicate::Queued(_) => "already queued",
+            crate::dedupe::Duplicate::Played(_) => "played in the last hour",
+        };
+        return Err(fail(StatusCode::CONFLICT, &format!("duplicate: {}", reason)));
+    }
+    if player::status(bot, gid).await.is_some() {
+        let track = queue::QueuedTrack { query, requested_by: None, channel };
+        let position = queue::push(bot, gid, track).await;
+        return Ok((StatusCode::ACCEPTED, Json(json!({ "queued": true, "position": position }))));
+    }
+    tokio::spawn(async move {
+        if let Err(e) = crate::music::play_now(bot, channel, gid, &query, None).await {
+            tracing::warn!("API play `{}` failed: {e:?}", query);
+            crate::music::report_failure(bot, channel, gid, &e.to_string()).await;
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(json!({ "queued": false, "position": 0 }))))
+}
+
+/// `POST /guilds/:id/skip`: stop the current track; the next queued one starts.
+async fn skip(State(cfg): State<ApiConfig>, headers: HeaderMap, Path(id): Path<u64>) -> ApiResult {
+    let (bot, gid) = authorize(&cfg, &headers, id)?;
+    music_allowed(bot, gid).await?;
+    let skipped = player::skip(bot, gid)
+        .await
+        .map_err(|e| fail(StatusCode::INTERNAL_SERVER_ERROR, &format!("skip failed: {e}")))?;
+    Ok((StatusCode::OK, Json(json!({ "skipped": skipped }))))
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Browser sources can't set headers, so the token may come in the URL instead.
+    token: Option<String>,
+}
+
+/// `GET /guilds/:id/events`: WebSocket of `PlayerEvent`s for one guild, starting with the
+/// current track and queue so an overlay can draw itself straight away.
+async fn events(
+    State(cfg): State<ApiConfig>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Query(query): Query<EventsQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let auth = match query.token {
+        Some(token) => check(&cfg, &token, id),
+        None => authorize(&cfg, &headers, id),
+    };
+    let (bot, gid) = match auth {
+        Ok(ok) => ok,
+        Err(e) => return e.into_response(),
+    };
+    let Some(rx) = crate::events::subscribe(bot).await else {
+        return fail(StatusCode::SERVICE_UNAVAILABLE, "events aren't available").into_response();
+    };
+    ws.on_upgrade(move |socket| stream_events(socket, bot, gid, rx))
+}
+
+async fn stream_events(
+    mut socket: WebSocket,
+    bot: &'static serenity::Context,
+    gid: GuildId,
+    mut rx: broadcast::Receiver<(GuildId, PlayerEvent)>,
+) {
+    let mut initial = Vec::new();
+    if player::status(bot, gid).await.is_some() {
+        initial.push(crate::events::track_started(bot, gid).await);
+    }
+    let queued = queue::list(bot, gid).await.into_iter().map(|t| t.query).collect();
+    initial.push(PlayerEvent::QueueChanged { queue: queued });
+    for event in initial {
+        if send_event(&mut socket, &event).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Ok((g, event)) if g == gid => {
+                    if send_event(&mut socket, &event).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                // A slow client missed some events; position ticks will catch it up
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn send_event(socket: &mut WebSocket, event: &PlayerEvent) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(event).unwrap_or_default();
+    socket.send(Message::Text(text.into())).await
+}
@@ -0,0 +1,123 @@
+use poise::serenity_prelude as serenity;
+use serenity::builder::CreateEmbed;
+use std::time::Instant;
+
+use crate::{storage, Ctx, Error};
+
+/// Rows older than this are pruned as new ones are written.
+const RETENTION_DAYS: i64 = 90;
+const MAX_SHOWN: u32 = 25;
+
+/// `pre_command` hook: remember when the command started for the latency column.
+pub async fn before(ctx: Ctx<'_>) {
+    ctx.set_invocation_data(Instant::now()).await;
+}
+
+/// Record one invocation. `error` is `None` for success.
+pub async fn record(ctx: Ctx<'_>, error: Option<String>) {
+    let latency_ms = match ctx.invocation_data::<Instant>().await {
+        Some(started) => started.elapsed().as_millis() as i64,
+        None => 0,
+    };
+    let db = match storage::db(ctx.serenity_context()).await {
+        Ok(db) => db,
+        Err(e) => {
+            tracing::warn!("Audit log unavailable: {e:?}");
+            return;
+        }
+    };
+    let at = serenity::Timestamp::now().unix_timestamp();
+    let guild_id = ctx.guild_id().map(|g| g.get() as i64);
+    let channel_id = ctx.channel_id().get() as i64;
+    let user_id = ctx.author().id.get() as i64;
+    let user_name = ctx.author().name.clone();
+    let command = ctx.command().qualified_name.clone();
+    let invocation = ctx.invocation_string();
+    let outcome = if error.is_some() { "error" } else { "ok" };
+    let result = db
+        .call(move |conn| {
+            conn.execute(
+                "INSERT INTO command_audit
+                    (at, guild_id, channel_id, user_id, user_name, command, invocation, outcome, error, latency_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                rusqlite::params![
+                    at, guild_id, channel_id, user_id, user_name, command, invocation, outcome, error, latency_ms
+                ],
+            )?;
+            conn.execute("DELETE FROM command_audit WHERE at < ?1", [at - RETENTION_DAYS * 86_400])
+        })
+        .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to write audit log: {e:?}");
+    }
+}
+
+struct AuditRow {
+    at: i64,
+    user_id: i64,
+    invocation: String,
+    outcome: String,
+    error: Option<String>,
+    latency_ms: i64,
+}
+
+/// Recent command invocations (this server only when used in one)
+#[poise::command(prefix_command, slash_command, owners_only, rename = "audit")]
+pub async fn admin_audit(
+    ctx: Ctx<'_>,
+    #[description = "Only this user"] user: Option<serenity::User>,
+    #[description = "Only this command, e.g. `music leave`"] command: Option<String>,
+    #[description = "How many (default 10, max 25)"] limit: Option<u32>,
+) -> Result<(), Error> {
+    let db = storage::db(ctx.serenity_context()).await?;
+    let guild_id = ctx.guild_id().map(|g| g.get() as i64);
+    let user_id = user.as_ref().map(|u| u.id.get() as i64);
+    let command_filter = command.clone().map(|c| c.trim().to_lowercase());
+    let limit = limit.unwrap_or(10).clamp(1, MAX_SHOWN);
+    let rows: Vec<AuditRow> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT at, user_id, invocation, outcome, error, latency_ms FROM command_audit
+                 WHERE (?1 IS NULL OR guild_id = ?1)
+                   AND (?2 IS NULL OR user_id = ?2)
+                   AND (?3 IS NULL OR command = ?3)
+                 ORDER BY at DESC, id DESC LIMIT ?4",
+            )?;
+            let rows = stmt.query_map(rusqlite::params![guild_id, user_id, command_filter, limit], |row| {
+                Ok(AuditRow {
+                    at: row.get(0)?,
+                    user_id: row.get(1)?,
+                    invocation: row.get(2)?,
+                    outcome: row.get(3)?,
+                    error: row.get(4)?,
+                    latency_ms: row.get(5)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await?;
+
+    if rows.is_empty() {
+        ctx.say("No matching commands recorded.").await?;
+        return Ok(());
+    }
+    let lines = rows
+        .iter()
+        .map(|r| {
+            let outcome = match (&*r.outcome, &r.error) {
+                ("ok", _) => "ok".to_string(),
+                (_, Some(e)) => format!("**failed**: {}", e.chars().take(80).collect::<String>()),
+                (other, None) => other.to_string(),
+            };
+            let invocation: String = r.invocation.chars().take(100).collect();
+            format!("<t:{}:f> <@{}> `{}` → {} ({} ms)", r.at, r.user_id, invocation, outcome, r.latency_ms)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let embed = CreateEmbed::new()
+        .title(format!("Recent commands ({})", rows.len()))
+        .description(lines)
+        .color(crate::EMBED_COLOR);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
@@ -0,0 +1,417 @@
+use poise::serenity_prelude as serenity;
+use poise::ChoiceParameter;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::model::channel::Message;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::modalert::AlertKind;
+use crate::moderation::{apply_timeout, format_duration, parse_duration, report};
+use crate::{Ctx, Error};
+
+pub const AUTOMOD_PATH: &str = "automod.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    /// Whole-word, case-insensitive match
+    #[name = "word"]
+    Word,
+    #[name = "regex"]
+    Regex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub pattern: String,
+}
+
+/// What happens to a message that matches a filter. Every action deletes the message.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum AutomodAction {
+    #[default]
+    Delete,
+    /// Delete and record a warning (escalation rules apply)
+    Warn,
+    /// Delete and time the author out
+    Timeout { duration_secs: u64 },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildAutomod {
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub action: AutomodAction,
+    #[serde(default)]
+    pub exempt_roles: Vec<u64>,
+    #[serde(default)]
+    pub exempt_channels: Vec<u64>,
+}
+
+#[derive(Default)]
+pub struct AutomodState {
+    guilds: HashMap<u64, GuildAutomod>,
+    /// Compiled filters per guild with their index in `filters`, rebuilt whenever that guild's
+    /// filters change. Patterns that don't compile are left out, so the indexes can skip.
+    compiled: HashMap<u64, Vec<(usize, Regex)>>,
+}
+
+impl AutomodState {
+    fn recompile(&mut self, gid: u64) {
+        let compiled = self
+            .guilds
+            .get(&gid)
+            .map(|g| g.filters.iter().enumerate().filter_map(|(i, f)| compile(f).ok().map(|re| (i, re))).collect())
+            .unwrap_or_default();
+        self.compiled.insert(gid, compiled);
+    }
+}
+
+pub struct AutomodStore;
+impl TypeMapKey for AutomodStore {
+    type Value = Arc<Mutex<AutomodState>>;
+}
+
+fn compile(filter: &Filter) -> Result<Regex, regex::Error> {
+    match filter.kind {
+        FilterKind::Word => Regex::new(&format!(r"(?i)\b{}\b", regex::escape(&filter.pattern))),
+        FilterKind::Regex => Regex::new(&filter.pattern),
+    }
+}
+
+async fn load_disk() -> Result<AutomodState, Box<dyn std::error::Error + Send + Sync>> {
+    let guilds: HashMap<u64, GuildAutomod> = crate::storage::load_json(&crate::storage::data_path(AUTOMOD_PATH)).await?;
+    let mut state = AutomodState { guilds, compiled: HashMap::new() };
+    let ids: Vec<u64> = state.guilds.keys().copied().collect();
+    for gid in ids {
+        state.recompile(gid);
+    }
+    Ok(state)
+}
+
+async fn save_disk(state: &AutomodState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(AUTOMOD_PATH), &state.guilds).await
+}
+
+pub async fn ensure_automod_store() -> Result<Arc<Mutex<AutomodState>>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(Arc::new(Mutex::new(load_disk().await?)))
+}
+
+async fn automod_store(ctx: &Context) -> Result<Arc<Mutex<AutomodState>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<AutomodStore>()
+        .cloned()
+        .ok_or_else(|| "Automod store not initialised".into())
+}
+
+/// Drop `gid`'s filters, exemptions and action along with their compiled patterns.
+pub async fn purge_guild(ctx: &Context, gid: serenity::GuildId) -> Result<(), Error> {
+    let store = automod_store(ctx).await?;
+    let mut state = store.lock().await;
+    state.compiled.remove(&gid.get());
+    if state.guilds.remove(&gid.get()).is_some() {
+        save_disk(&state).await?;
+    }
+    Ok(())
+}
+
+/// Scan a new guild message against the guild's filters and act on a match.
+pub async fn check_message(ctx: &Context, msg: &Message) {
+    let Some(gid) = msg.guild_id else { return };
+    if msg.author.bot || msg.content.is_empty() {
+        return;
+    }
+    let Ok(store) = automod_store(ctx).await else { return };
+
+    let (action, matched) = {
+        let state = store.lock().await;
+        let Some(cfg) = state.guilds.get(&gid.get()) else { return };
+        if cfg.exempt_channels.contains(&msg.channel_id.get()) {
+            return;
+        }
+        let member_roles = msg.member.as_ref().map(|m| m.roles.as_slice()).unwrap_or(&[]);
+        if member_roles.iter().any(|r| cfg.exempt_roles.contains(&r.get())) {
+            return;
+        }
+        let Some(pos) = state
+            .compiled
+            .get(&gid.get())
+            .and_then(|c| c.iter().find(|(_, re)| re.is_match(&msg.content)).map(|(i, _)| *i))
+        else {
+            return;
+        };
+        (cfg.action, cfg.filters.get(pos).map(|f| f.pattern.clone()).unwrap_or_default())
+    };
+
+    if crate::dryrun::active(ctx, Some(gid)).await {
+        crate::dryrun::skip(Some(gid), &format!("delete message {} by {} in <#{}>", msg.id, msg.author.tag(), msg.channel_id));
+    } else if let Err(e) = msg.delete(&ctx.http).await {
+        tracing::warn!("Automod failed to delete message {} in guild {}: {e:?}", msg.id, gid);
+    }
+
+    let reason = format!("Automod: message matched filter `{}`", matched);
+    let outcome = match action {
+        AutomodAction::Delete => "message deleted".to_string(),
+        AutomodAction::Warn => {
+            let bot_id = ctx.cache.current_user().id;
+            match crate::warnings::record_warning(ctx, gid, msg.author.id, bot_id, &reason).await {
+                Ok(count) => {
+                    let mut outcome = format!("message deleted, warning #{} issued", count);
+                    if let Some(escalated) = crate::warnings::escalate(ctx, gid, &msg.author, count).await {
+                        outcome.push_str(&format!("; {}", escalated));
+                    }
+                    outcome
+                }
+                Err(e) => format!("message deleted, failed to record warning: {}", e),
+            }
+        }
+        AutomodAction::Timeout { duration_secs } => {
+            let dur = Duration::from_secs(duration_secs);
+            match apply_timeout(ctx, gid, msg.author.id, dur, &reason).await {
+                Ok(()) => format!("message deleted, timed out for {}", format_duration(dur)),
+                Err(e) => format!("message deleted, timeout failed: {}", e),
+            }
+        }
+    };
+
+    report(
+        ctx,
+        gid,
+        AlertKind::Automod,
+        format!(
+            "{} in <#{}> matched filter `{}` ({}).",
+            msg.author.tag(),
+            msg.channel_id,
+            matched,
+            outcome
+        ),
+    )
+    .await;
+}
+
+/// Manage automod filters, actions and exemptions
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands("automod_add", "automod_remove", "automod_list", "automod_action", "automod_exempt"),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn automod(ctx: Ctx<'_>) -> Result<(), Error> {
+    list(ctx).await
+}
+
+/// Add a banned word or regex
+#[poise::command(prefix_command, slash_command, guild_only, rename = "add")]
+pub async fn automod_add(
+    ctx: Ctx<'_>,
+    #[description = "Match a whole word or a regular expression"] kind: FilterKind,
+    #[description = "Word or pattern"]
+    #[rest]
+    pattern: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let pattern = pattern.trim().to_string();
+    if pattern.is_empty() {
+        ctx.say("Provide a word or pattern.").await?;
+        return Ok(());
+    }
+    let filter = Filter { kind, pattern: pattern.clone() };
+    if let Err(e) = compile(&filter) {
+        ctx.say(format!("Invalid regex: {}", e)).await?;
+        return Ok(());
+    }
+
+    let store = automod_store(ctx.serenity_context()).await?;
+    {
+        let mut state = store.lock().await;
+        let cfg = state.guilds.entry(guild_id.get()).or_default();
+        if cfg.filters.iter().any(|f| f.pattern == pattern) {
+            ctx.say("That filter already exists.").await?;
+            return Ok(());
+        }
+        cfg.filters.push(filter);
+        state.recompile(guild_id.get());
+        save_disk(&state).await?;
+    }
+    ctx.say(format!("Added {} filter `{}`.", kind.name(), pattern)).await?;
+    Ok(())
+}
+
+/// Remove a filter
+#[poise::command(prefix_command, slash_command, guild_only, rename = "remove")]
+pub async fn automod_remove(
+    ctx: Ctx<'_>,
+    #[description = "Word or pattern to remove"]
+    #[rest]
+    pattern: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let pattern = pattern.trim();
+    let store = automod_store(ctx.serenity_context()).await?;
+    let removed = {
+        let mut state = store.lock().await;
+        let cfg = state.guilds.entry(guild_id.get()).or_default();
+        let before = cfg.filters.len();
+        cfg.filters.retain(|f| f.pattern != pattern);
+        let removed = cfg.filters.len() != before;
+        state.recompile(guild_id.get());
+        save_disk(&state).await?;
+        removed
+    };
+    if removed {
+        ctx.say(format!("Removed filter `{}`.", pattern)).await?;
+    } else {
+        ctx.say(format!("No filter `{}` found.", pattern)).await?;
+    }
+    Ok(())
+}
+
+/// Show filters, action and exemptions
+#[poise::command(prefix_command, slash_command, guild_only, rename = "list")]
+pub async fn automod_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    list(ctx).await
+}
+
+async fn list(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = automod_store(ctx.serenity_context()).await?;
+    let cfg = {
+        let state = store.lock().await;
+        state.guilds.get(&guild_id.get()).cloned().unwrap_or_default()
+    };
+
+    let filters = if cfg.filters.is_empty() {
+        "<none>".to_string()
+    } else {
+        cfg.filters
+            .iter()
+            .map(|f| format!("- {} `{}`", f.kind.name(), f.pattern))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let exempt_roles = cfg.exempt_roles.iter().map(|r| format!("<@&{}>", r)).collect::<Vec<_>>();
+    let exempt_channels = cfg.exempt_channels.iter().map(|c| format!("<#{}>", c)).collect::<Vec<_>>();
+
+    let embed = serenity::CreateEmbed::new()
+        .title("Automod")
+        .field("Filters", filters, false)
+        .field("Action", describe_action(cfg.action), true)
+        .field(
+            "Exempt roles",
+            if exempt_roles.is_empty() { "<none>".to_string() } else { exempt_roles.join(", ") },
+            true,
+        )
+        .field(
+            "Exempt channels",
+            if exempt_channels.is_empty() { "<none>".to_string() } else { exempt_channels.join(", ") },
+            true,
+        )
+        .color(crate::EMBED_COLOR);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum ActionChoice {
+    #[name = "delete"]
+    Delete,
+    #[name = "warn"]
+    Warn,
+    #[name = "timeout"]
+    Timeout,
+}
+
+/// Set what happens to matching messages
+#[poise::command(prefix_command, slash_command, guild_only, rename = "action")]
+pub async fn automod_action(
+    ctx: Ctx<'_>,
+    #[description = "delete, warn (delete + warning) or timeout (delete + timeout)"] action: ActionChoice,
+    #[description = "Timeout duration, e.g. 10m (timeout only)"] duration: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let action = match action {
+        ActionChoice::Delete => AutomodAction::Delete,
+        ActionChoice::Warn => AutomodAction::Warn,
+        ActionChoice::Timeout => {
+            let Some(dur) = duration.as_deref().and_then(parse_duration) else {
+                ctx.say("The timeout action needs a duration, e.g. `10m`.").await?;
+                return Ok(());
+            };
+            AutomodAction::Timeout { duration_secs: dur.as_secs() }
+        }
+    };
+
+    let store = automod_store(ctx.serenity_context()).await?;
+    {
+        let mut state = store.lock().await;
+        state.guilds.entry(guild_id.get()).or_default().action = action;
+        save_disk(&state).await?;
+    }
+    ctx.say(format!("Automod action set to: {}.", describe_action(action))).await?;
+    Ok(())
+}
+
+/// Toggle an exempt role and/or channel
+#[poise::command(prefix_command, slash_command, guild_only, rename = "exempt")]
+pub async fn automod_exempt(
+    ctx: Ctx<'_>,
+    #[description = "Role to exempt (or un-exempt)"] role: Option<serenity::Role>,
+    #[description = "Channel to exempt (or un-exempt)"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    if role.is_none() && channel.is_none() {
+        ctx.say("Provide a role and/or a channel.").await?;
+        return Ok(());
+    }
+
+    let store = automod_store(ctx.serenity_context()).await?;
+    let mut lines = Vec::new();
+    {
+        let mut state = store.lock().await;
+        let cfg = state.guilds.entry(guild_id.get()).or_default();
+        if let Some(r) = role {
+            lines.push(toggle(&mut cfg.exempt_roles, r.id.get(), &format!("<@&{}>", r.id)));
+        }
+        if let Some(c) = channel {
+            lines.push(toggle(&mut cfg.exempt_channels, c.id.get(), &format!("<#{}>", c.id)));
+        }
+        save_disk(&state).await?;
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+fn toggle(list: &mut Vec<u64>, id: u64, label: &str) -> String {
+    if let Some(pos) = list.iter().position(|x| *x == id) {
+        list.remove(pos);
+        format!("{} is no longer exempt.", label)
+    } else {
+        list.push(id);
+        format!("{} is now exempt.", label)
+    }
+}
+
+fn describe_action(action: AutomodAction) -> String {
+    match action {
+        AutomodAction::Delete => "delete".to_string(),
+        AutomodAction::Warn => "delete + warn".to_string(),
+        AutomodAction::Timeout { duration_secs } => {
+            format!("delete + timeout for {}", format_duration(Duration::from_secs(duration_secs)))
+        }
+    }
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![automod()]
+}
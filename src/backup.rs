@@ -0,0 +1,336 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use poise::serenity_prelude as serenity;
+use serenity::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::{self, BackupConfig};
+use crate::storage::{self, Db};
+use crate::{antiraid, automod, dryrun, roles, scheduler, starboard, tags, warnings, welcome, Ctx, Error};
+
+/// How often the loop checks whether a backup is due.
+const CHECK_EVERY: Duration = Duration::from_secs(600);
+
+/// Names the backup to restore on the next start, inside the backup directory.
+const RESTORE_MARKER: &str = "RESTORE";
+
+/// Every JSON store, by file name in the data directory; together with `bot.db` that's all
+/// the bot persists.
+const STATE_FILES: [&str; 8] = [
+    warnings::WARNINGS_PATH,
+    automod::AUTOMOD_PATH,
+    antiraid::ANTIRAID_PATH,
+    tags::TAGS_PATH,
+    welcome::WELCOME_PATH,
+    roles::ROLE_PANELS_PATH,
+    starboard::STARBOARD_PATH,
+    scheduler::SCHEDULER_PATH,
+];
+
+/// Lists the files an archive holds, so a restore can tell a store that was empty when the
+/// backup was taken from one that's gone missing since.
+const MANIFEST: &str = "MANIFEST";
+
+/// The backup directory; a relative `backup.dir` is inside the data directory.
+fn backup_dir(cfg: &BackupConfig) -> PathBuf {
+    storage::data_path(cfg.dir())
+}
+
+fn archive_path(dir: &Path, stamp: i64) -> PathBuf {
+    dir.join(format!("{}.tar.gz", stamp))
+}
+
+/// Timestamps of the backups in `dir`, newest first. Archives still being written are hidden
+/// (`.tmp-` prefix) and not listed.
+pub async fn list(dir: &Path) -> Vec<i64> {
+    let mut stamps = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else { return stamps };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_type().await.is_ok_and(|t| t.is_file())
+            && let Some(stamp) =
+                entry.file_name().to_str().and_then(|n| n.strip_suffix(".tar.gz")).and_then(|n| n.parse().ok())
+        {
+            stamps.push(stamp);
+        }
+    }
+    stamps.sort_unstable_by(|a, b| b.cmp(a));
+    stamps
+}
+
+/// The backup a pending `admin restore` names, if there is one.
+async fn pending_restore(dir: &Path) -> Option<i64> {
+    tokio::fs::read_to_string(dir.join(RESTORE_MARKER)).await.ok()?.trim().parse().ok()
+}
+
+/// Snapshot the database and every JSON store into a new `<timestamp>.tar.gz` archive and
+/// return its timestamp. With `db` the snapshot goes through the open connection; without it
+/// (before startup opens the database) the file is opened directly. Old backups aren't pruned
+/// here; see `prune`.
+pub async fn create(cfg: &BackupConfig, db: Option<&Db>) -> Result<i64, Error> {
+    let dir = backup_dir(cfg);
+    let mut stamp = serenity::Timestamp::now().unix_timestamp();
+    while tokio::fs::try_exists(archive_path(&dir, stamp)).await? {
+        stamp += 1;
+    }
+    // Gathered in a hidden directory and archived under a hidden name that's renamed when
+    // complete, so a crash never leaves a partial backup that looks restorable
+    let staging = dir.join(format!(".tmp-{}", stamp));
+    tokio::fs::create_dir_all(&staging).await?;
+    let result = match gather(&staging, db).await {
+        Ok(names) => {
+            let partial = dir.join(format!(".tmp-{}.tar.gz", stamp));
+            let (from, to) = (staging.clone(), partial.clone());
+            match tokio::task::spawn_blocking(move || write_archive(&from, &names, &to)).await {
+                Ok(Ok(())) => tokio::fs::rename(&partial, archive_path(&dir, stamp)).await.map_err(Error::from),
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(e.into()),
+            }
+        }
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    result.map(|()| stamp)
+}
+
+/// Copy the database and the stores that exist into `staging`, with a manifest of them.
+async fn gather(staging: &Path, db: Option<&Db>) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    let target = staging.join(storage::DB_PATH).to_string_lossy().into_owned();
+    match db {
+        Some(db) => {
+            db.call(move |conn| conn.execute("VACUUM INTO ?1", [target])).await?;
+            names.push(storage::DB_PATH.to_string());
+        }
+        None if storage::data_path(storage::DB_PATH).exists() => {
+            tokio::task::spawn_blocking(move || -> Result<(), Error> {
+                let conn = rusqlite::Connection::open(storage::data_path(storage::DB_PATH))?;
+                conn.execute("VACUUM INTO ?1", [target])?;
+                Ok(())
+            })
+            .await??;
+            names.push(storage::DB_PATH.to_string());
+        }
+        None => {}
+    }
+    for name in STATE_FILES {
+        let path = storage::data_path(name);
+        if path.exists() {
+            tokio::fs::copy(path, staging.join(name)).await?;
+            names.push(name.to_string());
+        }
+    }
+    tokio::fs::write(staging.join(MANIFEST), names.join("\n")).await?;
+    names.push(MANIFEST.to_string());
+    Ok(names)
+}
+
+fn write_archive(staging: &Path, names: &[String], to: &Path) -> Result<(), Error> {
+    let file = std::fs::File::create(to)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    for name in names {
+        archive.append_path_with_name(staging.join(name), name)?;
+    }
+    archive.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Unpack `archive` into `to` and return the stores it holds, checking every one its manifest
+/// lists is there.
+fn unpack(archive: &Path, to: &Path) -> Result<Vec<String>, Error> {
+    let file = std::fs::File::open(archive)?;
+    tar::Archive::new(GzDecoder::new(file)).unpack(to)?;
+    let manifest = std::fs::read_to_string(to.join(MANIFEST))
+        .map_err(|e| format!("{} has no readable {}: {}", archive.display(), MANIFEST, e))?;
+    let names: Vec<String> = manifest.lines().filter(|l| !l.is_empty()).map(str::to_string).collect();
+    for name in &names {
+        if !to.join(name).is_file() {
+            return Err(format!("{} is missing {}", archive.display(), name).into());
+        }
+    }
+    Ok(names)
+}
+
+/// Delete all but the newest `keep` backups. The one a pending restore names is kept whatever
+/// its age. With `--dry-run` nothing is deleted; the dry-run lines for what would have been are
+/// returned.
+async fn prune(cfg: &BackupConfig) -> Vec<String> {
+    let dir = backup_dir(cfg);
+    let pending = pending_restore(&dir).await;
+    let mut skipped = Vec::new();
+    for stamp in list(&dir).await.into_iter().skip(cfg.keep()) {
+        if Some(stamp) == pending {
+            continue;
+        }
+        let path = archive_path(&dir, stamp);
+        if dryrun::forced() {
+            skipped.push(dryrun::skip(None, &format!("delete old backup {}", path.display())));
+            continue;
+        }
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            tracing::warn!("Failed to delete old backup {}: {e:?}", path.display());
+        }
+    }
+    skipped
+}
+
+/// Take a backup through the open database, then prune old ones. Returns the new backup's
+/// stamp and `prune`'s dry-run lines.
+async fn backup_and_prune(ctx: &Context, cfg: &BackupConfig) -> Result<(i64, Vec<String>), Error> {
+    let db = storage::db(ctx).await?;
+    let stamp = create(cfg, Some(&db)).await?;
+    let skipped = prune(cfg).await;
+    Ok((stamp, skipped))
+}
+
+/// Spawn the loop that takes a backup every `backup.interval_hours`. The newest backup on disk
+/// sets the schedule, so restarts don't reset it.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        loop {
+            let cfg = config::current(&ctx).await.backup.clone();
+            if let Some(interval) = cfg.interval() {
+                let now = serenity::Timestamp::now().unix_timestamp();
+                let due = list(&backup_dir(&cfg)).await.first().is_none_or(|last| now - last >= interval.as_secs() as i64);
+                if due {
+                    match backup_and_prune(&ctx, &cfg).await {
+                        Ok((stamp, _)) => tracing::info!("Backup {} written to {}", stamp, backup_dir(&cfg).display()),
+                        Err(e) => tracing::warn!("Scheduled backup failed: {e:?}"),
+                    }
+                }
+            }
+            tokio::time::sleep(CHECK_EVERY).await;
+        }
+    });
+}
+
+/// Finish an `admin restore`: put the chosen backup's files in place. Runs at startup before
+/// the database and stores are opened. The backup is unpacked and checked before anything is
+/// touched, and the state being replaced is backed up first (without pruning, so the backup
+/// being restored survives).
+pub async fn apply_pending_restore(cfg: &BackupConfig) -> Result<(), Error> {
+    let dir = backup_dir(cfg);
+    let marker = dir.join(RESTORE_MARKER);
+    let Ok(contents) = tokio::fs::read_to_string(&marker).await else { return Ok(()) };
+    let stamp: i64 = contents.trim().parse().map_err(|_| format!("{} doesn't name a backup", marker.display()))?;
+    if dryrun::forced() {
+        // The marker stays, so the restore happens on the next start without `--dry-run`
+        dryrun::skip(None, &format!("restore backup {} over the current database and state files", stamp));
+        return Ok(());
+    }
+    let archive = archive_path(&dir, stamp);
+    if !tokio::fs::try_exists(&archive).await? {
+        return Err(format!("backup {} named in {} doesn't exist", stamp, marker.display()).into());
+    }
+    let source = dir.join(format!(".restore-{}", stamp));
+    let _ = tokio::fs::remove_dir_all(&source).await;
+    tokio::fs::create_dir_all(&source).await?;
+    let unpacked = {
+        let (archive, source) = (archive.clone(), source.clone());
+        tokio::task::spawn_blocking(move || unpack(&archive, &source)).await?
+    };
+    let names = match unpacked {
+        Ok(names) => names,
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&source).await;
+            return Err(e);
+        }
+    };
+    let previous = create(cfg, None).await?;
+
+    // Stale write-ahead log pages would be replayed onto the restored database
+    for suffix in ["-wal", "-shm"] {
+        let _ = tokio::fs::remove_file(storage::data_path(format!("{}{}", storage::DB_PATH, suffix))).await;
+    }
+    for name in std::iter::once(storage::DB_PATH).chain(STATE_FILES) {
+        let path = storage::data_path(name);
+        if names.iter().any(|n| n == name) {
+            tokio::fs::copy(source.join(name), &path).await?;
+        } else if path.exists() {
+            // The store was empty when the backup was taken
+            tokio::fs::remove_file(&path).await?;
+        }
+    }
+    let _ = tokio::fs::remove_dir_all(&source).await;
+    tokio::fs::remove_file(&marker).await?;
+    tracing::info!("Restored backup {} (the state it replaced is backup {})", stamp, previous);
+    Ok(())
+}
+
+/// Back up the bot's stored state
+#[poise::command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    rename = "backup",
+    subcommands("backup_now", "backup_list")
+)]
+pub async fn admin_backup(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.say("Subcommands: `now`, `list`").await?;
+    Ok(())
+}
+
+/// Take a backup right away
+#[poise::command(prefix_command, slash_command, owners_only, rename = "now")]
+pub async fn backup_now(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let cfg = config::current(sctx).await.backup.clone();
+    let (stamp, skipped) = backup_and_prune(sctx, &cfg).await?;
+    let mut reply = format!("Backup `{}` written (<t:{}:f>).", stamp, stamp);
+    for line in skipped {
+        reply.push('\n');
+        reply.push_str(&line);
+    }
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+/// List the backups that can be restored
+#[poise::command(prefix_command, slash_command, owners_only, rename = "list")]
+pub async fn backup_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    let cfg = config::current(ctx.serenity_context()).await.backup.clone();
+    let dir = backup_dir(&cfg);
+    let stamps = list(&dir).await;
+    if stamps.is_empty() {
+        ctx.say(format!("No backups in `{}` yet.", dir.display())).await?;
+        return Ok(());
+    }
+    let mut lines: Vec<String> = stamps.iter().map(|s| format!("`{}` — <t:{}:f>", s, s)).collect();
+    if let Some(pending) = pending_restore(&dir).await {
+        lines.push(format!("\n`{}` will be restored on the next start.", pending));
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Restore a backup on the next start
+#[poise::command(prefix_command, slash_command, owners_only, rename = "restore")]
+pub async fn admin_restore(
+    ctx: Ctx<'_>,
+    #[description = "Backup timestamp from `admin backup list`"] timestamp: i64,
+) -> Result<(), Error> {
+    let cfg = config::current(ctx.serenity_context()).await.backup.clone();
+    let dir = backup_dir(&cfg);
+    if !list(&dir).await.contains(&timestamp) {
+        ctx.say(format!("There's no backup `{}`; see `admin backup list`.", timestamp)).await?;
+        return Ok(());
+    }
+    if dryrun::forced() {
+        let action = format!("restore backup {} over the current database and state files on the next start", timestamp);
+        ctx.say(dryrun::skip(None, &action)).await?;
+        return Ok(());
+    }
+    let marker = dir.join(RESTORE_MARKER);
+    tokio::fs::write(&marker, timestamp.to_string()).await?;
+    ctx.say(format!(
+        "Backup `{}` (<t:{}:f>) will be restored when the bot next starts; the current state is backed up \
+         first. Restart it to finish, or delete `{}` to call it off.",
+        timestamp,
+        timestamp,
+        marker.display()
+    ))
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{Channel, Guild, User};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::{Ctx, Error};
+
+/// Set once at startup, for the uptime.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+/// Invocations per command (`music play`) since startup.
+static USAGE: LazyLock<Mutex<HashMap<String, u64>>> = LazyLock::new(Default::default);
+/// Commands listed in `/botinfo`.
+const TOP_COMMANDS: usize = 8;
+
+pub fn mark_started() {
+    let _ = STARTED.set(Instant::now());
+}
+
+/// `pre_command` hook: count the invocation.
+pub fn count(ctx: Ctx<'_>) {
+    let mut usage = USAGE.lock().unwrap_or_else(|e| e.into_inner());
+    *usage.entry(ctx.command().qualified_name.clone()).or_default() += 1;
+}
+
+/// `3d 4h 12m`, leaving out leading zero units.
+fn uptime(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (days, hours, minutes) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+fn megabytes(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Version, uptime, reach and command usage of this instance
+#[poise::command(prefix_command, slash_command)]
+pub async fn botinfo(ctx: Ctx<'_>) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let theme = crate::embeds::theme(sctx, ctx.guild_id()).await;
+    let cache = &sctx.cache;
+
+    let guilds = cache.guilds();
+    let (mut channels, mut members) = (0, 0);
+    for gid in &guilds {
+        if let Some(guild) = cache.guild(*gid) {
+            channels += guild.channels.len();
+            members += guild.members.len();
+        }
+    }
+    let users = cache.user_count();
+    // Only the top-level structs, not the strings and maps they own: a floor, not a measurement
+    let cache_bytes = guilds.len() * size_of::<Guild>()
+        + channels * size_of::<Channel>()
+        + (users + members) * size_of::<User>();
+    let voice = match songbird::get(sctx).await {
+        Some(manager) => manager.iter().count(),
+        None => 0,
+    };
+    let uptime = STARTED.get().map_or_else(|| "unknown".to_string(), |s| uptime(s.elapsed()));
+
+    let (total, top) = {
+        let usage = USAGE.lock().unwrap_or_else(|e| e.into_inner());
+        let mut top: Vec<(String, u64)> = usage.iter().map(|(c, n)| (c.clone(), *n)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(TOP_COMMANDS);
+        (usage.values().sum::<u64>(), top)
+    };
+    let usage = if top.is_empty() {
+        "No commands yet.".to_string()
+    } else {
+        top.iter().map(|(c, n)| format!("`{}` — {}", c, n)).collect::<Vec<_>>().join("\n")
+    };
+
+    let embed = theme
+        .embed()
+        .title("Bot info")
+        .field("Version", format!("{} (`{}`)", env!("CARGO_PKG_VERSION"), env!("GIT_COMMIT")), true)
+        .field("Uptime", uptime, true)
+        .field("Servers", guilds.len().to_string(), true)
+        .field("Voice connections", voice.to_string(), true)
+        .field("Cached", format!("{} channels, {} members, {} users", channels, members, users), true)
+        .field("Cache memory (estimate)", format!("at least {}", megabytes(cache_bytes)), true)
+        .field(format!("Commands since start ({})", total), usage, false);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![botinfo()]
+}
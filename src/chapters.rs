@@ -0,0 +1,151 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use std::time::Duration;
+
+use crate::positions::clock;
+use crate::{player, Ctx, Error};
+
+/// One chapter of the current track, as yt-dlp reports them. It runs until the next one starts.
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+}
+
+/// Chapters from a yt-dlp info JSON (`-j`) or its `chapters` field alone. Empty if there are none.
+pub fn parse(value: &serde_json::Value) -> Vec<Chapter> {
+    let list = value.get("chapters").unwrap_or(value);
+    let Some(list) = list.as_array() else { return Vec::new() };
+    let mut chapters: Vec<Chapter> = list
+        .iter()
+        .filter_map(|c| {
+            let start = c.get("start_time")?.as_f64()?;
+            let title = c.get("title").and_then(|t| t.as_str()).unwrap_or("Untitled").to_string();
+            Some(Chapter { title, start: Duration::from_secs_f64(start.max(0.0)) })
+        })
+        .collect();
+    chapters.sort_by_key(|c| c.start);
+    chapters
+}
+
+/// Look up chapters for `target` (a URL or `ytsearch1:` query) in the background and add them
+/// to the guild's `TrackMeta`, for playback paths whose metadata doesn't include them. Dropped
+/// if another track has started by the time yt-dlp answers.
+pub fn fetch_later(ctx: &Context, guild_id: GuildId, target: String, title: Option<String>) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        let cookie_args = crate::config::current(&ctx).await.music.cookie_args();
+        let out = tokio::process::Command::new("yt-dlp")
+            .kill_on_drop(true)
+            .args(&cookie_args)
+            .args(["--no-playlist", "--skip-download", "--print", "%(chapters)j", &target])
+            .output()
+            .await;
+        let Ok(out) = out else { return };
+        let text = String::from_utf8_lossy(&out.stdout);
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(text.trim()) else { return };
+        let chapters = parse(&value);
+        if chapters.is_empty() {
+            return;
+        }
+        let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() else { return };
+        if let Some(meta) = ms.lock().await.get_mut(&guild_id)
+            && meta.title == title
+        {
+            meta.chapters = chapters;
+        }
+    });
+}
+
+/// Index of the chapter `position` falls in.
+pub fn index_at(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters.iter().rposition(|c| c.start <= position)
+}
+
+async fn current(ctx: &Context, guild_id: GuildId) -> Vec<Chapter> {
+    let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() else { return Vec::new() };
+    ms.lock().await.get(&guild_id).map(|m| m.chapters.clone()).unwrap_or_default()
+}
+
+/// Chapters of the current track, for the ones that have them
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "chapter",
+    subcommands("chapter_next", "chapter_prev", "chapter_list")
+)]
+pub async fn music_chapter(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.say("Subcommands: `next`, `prev`, `list`").await?;
+    Ok(())
+}
+
+/// Jump `step` chapters from the current one; 0 restarts it.
+async fn jump(ctx: Ctx<'_>, step: isize) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let Some(status) = player::status(sctx, guild_id).await else {
+        ctx.say("Nothing is playing.").await?;
+        return Ok(());
+    };
+    let chapters = current(sctx, guild_id).await;
+    if chapters.is_empty() {
+        ctx.say("This track has no chapters.").await?;
+        return Ok(());
+    }
+    let here = index_at(&chapters, status.position).unwrap_or(0) as isize;
+    let target = here + step;
+    if target < 0 || target as usize >= chapters.len() {
+        let edge = if step > 0 { "last" } else { "first" };
+        ctx.say(format!("Already in the {} chapter.", edge)).await?;
+        return Ok(());
+    }
+    let chapter = &chapters[target as usize];
+    player::seek(sctx, guild_id, chapter.start).await?;
+    ctx.say(format!("Chapter {}/{}: **{}** ({})", target + 1, chapters.len(), chapter.title, clock(chapter.start)))
+        .await?;
+    Ok(())
+}
+
+/// Skip to the next chapter
+#[poise::command(prefix_command, slash_command, guild_only, rename = "next")]
+pub async fn chapter_next(ctx: Ctx<'_>) -> Result<(), Error> {
+    jump(ctx, 1).await
+}
+
+/// Go back to the previous chapter
+#[poise::command(prefix_command, slash_command, guild_only, rename = "prev")]
+pub async fn chapter_prev(ctx: Ctx<'_>) -> Result<(), Error> {
+    jump(ctx, -1).await
+}
+
+/// List the current track's chapters
+#[poise::command(prefix_command, slash_command, guild_only, rename = "list")]
+pub async fn chapter_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let chapters = current(sctx, guild_id).await;
+    if chapters.is_empty() {
+        ctx.say("This track has no chapters.").await?;
+        return Ok(());
+    }
+    let here = match player::status(sctx, guild_id).await {
+        Some(status) => index_at(&chapters, status.position),
+        None => None,
+    };
+    let mut lines: Vec<String> = chapters
+        .iter()
+        .enumerate()
+        .take(40)
+        .map(|(i, c)| {
+            let marker = if Some(i) == here { "▶ " } else { "" };
+            format!("{}`{}` {}", marker, clock(c.start), c.title)
+        })
+        .collect();
+    if chapters.len() > lines.len() {
+        lines.push(format!("…and {} more", chapters.len() - lines.len()));
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use poise::serenity_prelude as serenity;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serenity::all::{ActionRowComponent, ComponentInteraction};
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage, EditInteractionResponse, EditMessage,
+};
+use serenity::futures::future::BoxFuture;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Handles one feature's components. Gets the `custom_id` with the `<feature>:` prefix removed;
+/// `payload` decodes it.
+pub type HandlerFn = for<'a> fn(&'a Context, &'a ComponentInteraction, &'a str) -> BoxFuture<'a, ()>;
+
+/// Component handlers by feature, the prefix of their `custom_id`s.
+#[derive(Default)]
+pub struct Registry {
+    handlers: HashMap<&'static str, HandlerFn>,
+}
+
+impl Registry {
+    pub fn register(&mut self, feature: &'static str, handler: HandlerFn) {
+        self.handlers.insert(feature, handler);
+    }
+}
+
+pub struct RegistryStore;
+impl TypeMapKey for RegistryStore {
+    type Value = Arc<Registry>;
+}
+
+/// `custom_id` for a component: `<feature>:[<expires>,<payload>]` with the payload as JSON.
+/// `ttl` makes the component go stale; pressing it after that just disables it.
+/// Discord caps ids at 100 characters, so keep payloads to a few short fields.
+pub fn custom_id<T: Serialize>(feature: &str, payload: &T, ttl: Option<Duration>) -> String {
+    let expires = ttl.map(|ttl| serenity::Timestamp::now().unix_timestamp() + ttl.as_secs() as i64);
+    let json = serde_json::to_string(&(expires, payload)).unwrap_or_default();
+    debug_assert!(feature.len() + json.len() < 100, "custom_id too long for {}", feature);
+    format!("{}:{}", feature, json)
+}
+
+/// Decode the payload a handler was given. `None` for ids from before the typed format.
+pub fn payload<T: DeserializeOwned>(rest: &str) -> Option<T> {
+    serde_json::from_str::<(Option<i64>, T)>(rest).ok().map(|(_, payload)| payload)
+}
+
+fn expired(rest: &str) -> bool {
+    match serde_json::from_str::<(Option<i64>, serde_json::Value)>(rest) {
+        Ok((Some(expires), _)) => serenity::Timestamp::now().unix_timestamp() > expires,
+        _ => false,
+    }
+}
+
+/// Route a component interaction to its feature's handler. Ids without a registered feature
+/// (e.g. buttons a collector is waiting on) are left alone, as are presses by blocked users.
+pub async fn dispatch(ctx: &Context, mc: &ComponentInteraction) {
+    let Some((feature, rest)) = mc.data.custom_id.split_once(':') else { return };
+    let Some(registry) = ctx.data.read().await.get::<RegistryStore>().cloned() else { return };
+    let Some(handler) = registry.handlers.get(feature) else { return };
+    if crate::access::is_blocked(ctx, mc.user.id).await {
+        return;
+    }
+    if expired(rest) {
+        expire(ctx, mc).await;
+        return;
+    }
+    handler(ctx, mc, rest).await;
+}
+
+/// Acknowledge a press with a private "thinking…" reply, for handlers that look something up
+/// before answering only the presser. Finish with `edit_deferred`.
+pub async fn defer_ephemeral(ctx: &Context, mc: &ComponentInteraction) -> serenity::Result<()> {
+    let defer = CreateInteractionResponseMessage::new().ephemeral(true);
+    mc.create_response(&ctx.http, CreateInteractionResponse::Defer(defer)).await
+}
+
+/// Replace the reply `defer_ephemeral` started with the real answer.
+pub async fn edit_deferred(ctx: &Context, mc: &ComponentInteraction, reply: EditInteractionResponse) {
+    if let Err(e) = mc.edit_response(&ctx.http, reply).await {
+        tracing::warn!("Failed to answer component {}: {e:?}", mc.data.custom_id);
+    }
+}
+
+/// Answer a stale press by disabling the message's buttons and telling the user why.
+async fn expire(ctx: &Context, mc: &ComponentInteraction) {
+    let rows = disabled_rows(&mc.message);
+    let update = CreateInteractionResponseMessage::new().components(rows);
+    if let Err(e) = mc.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(update)).await {
+        tracing::warn!("Failed to disable stale components: {e:?}");
+        return;
+    }
+    let notice = CreateInteractionResponseFollowup::new()
+        .content("These controls have expired. Run the command again for fresh ones.")
+        .ephemeral(true);
+    let _ = mc.create_followup(&ctx.http, notice).await;
+}
+
+/// The message's buttons, all disabled. Other components can't be rebuilt and are dropped.
+pub fn disabled_rows(message: &serenity::Message) -> Vec<CreateActionRow> {
+    message
+        .components
+        .iter()
+        .filter_map(|row| {
+            let buttons: Vec<CreateButton> = row
+                .components
+                .iter()
+                .filter_map(|c| match c {
+                    ActionRowComponent::Button(b) => Some(CreateButton::from(b.clone()).disabled(true)),
+                    _ => None,
+                })
+                .collect();
+            (!buttons.is_empty()).then_some(CreateActionRow::Buttons(buttons))
+        })
+        .collect()
+}
+
+/// Disable every button on a message the bot posted, e.g. once what it controls is gone.
+pub async fn disable(ctx: &Context, message: &mut serenity::Message) {
+    let rows = disabled_rows(message);
+    if let Err(e) = message.edit(&ctx.http, EditMessage::new().components(rows)).await {
+        tracing::warn!("Failed to disable components on {}: {e:?}", message.id);
+    }
+}
@@ -1,8 +1,38 @@
+use notify::{RecursiveMode, Watcher};
 use serde::Deserialize;
+use serenity::all::{ChannelId, GuildId, RoleId, UserId};
+use serenity::prelude::*;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use std::{collections::HashMap, io::ErrorKind};
+use tokio::sync::RwLock;
 
+/// Default config file name, in the data directory; `--config` overrides it.
 pub const CONFIG_PATH: &str = "config.jsonc";
 
+/// Env vars starting with this override config keys: `BOT__START__SERVICES__MC__URL`
+/// sets `start.services.mc.url`.
+const ENV_PREFIX: &str = "BOT__";
+
+/// Config file path and `--set` overrides from the command line, fixed at startup.
+struct Layers {
+    path: String,
+    cli: Vec<(String, String)>,
+}
+
+static LAYERS: OnceLock<Layers> = OnceLock::new();
+
+/// Record the command-line layer. Call once from `main` before the config is first loaded.
+pub fn init(path: String, overrides: Vec<(String, String)>) {
+    let _ = LAYERS.set(Layers { path, cli: overrides });
+}
+
+/// Path of the config file in use.
+pub fn config_path() -> &'static str {
+    LAYERS.get().map(|l| l.path.as_str()).unwrap_or(CONFIG_PATH)
+}
+
 const DEFAULT_CONFIG: &str = r#"// Global bot config (JSONC: supports comments)
 {
   // Start command configuration
@@ -24,20 +54,445 @@ const DEFAULT_CONFIG: &str = r#"// Global bot config (JSONC: supports comments)
 }
 "#;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct AppConfig {
     #[serde(default)]
     pub start: Option<StartConfig>,
+    #[serde(default)]
+    pub registration: RegistrationConfig,
+    #[serde(default)]
+    pub presence: PresenceConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub music: MusicConfig,
+    #[serde(default)]
+    pub access: AccessConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    #[serde(default)]
+    pub scrobble: ScrobbleConfig,
+}
+
+/// Where `scrobble link` connects accounts. Last.fm needs an API account
+/// (https://www.last.fm/api/account/create); ListenBrainz works without configuration.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ScrobbleConfig {
+    #[serde(default)]
+    pub lastfm_api_key: Option<String>,
+    #[serde(default)]
+    pub lastfm_api_secret: Option<String>,
+    /// For a self-hosted ListenBrainz. Defaults to `https://api.listenbrainz.org`.
+    #[serde(default)]
+    pub listenbrainz_url: Option<String>,
+}
+
+impl ScrobbleConfig {
+    /// The Last.fm API key and secret, when both are set.
+    pub fn lastfm(&self) -> Option<(&str, &str)> {
+        Some((self.lastfm_api_key.as_deref()?, self.lastfm_api_secret.as_deref()?))
+    }
+
+    pub fn listenbrainz_url(&self) -> &str {
+        self.listenbrainz_url.as_deref().unwrap_or("https://api.listenbrainz.org").trim_end_matches('/')
+    }
+}
+
+/// Periodic snapshots of the database and the JSON stores, for `admin restore`.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct BackupConfig {
+    /// Where backups are kept, one `<unix timestamp>.tar.gz` archive per backup. Defaults to
+    /// `backups`.
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Hours between automatic backups; 0 turns them off. Defaults to 24.
+    #[serde(default)]
+    pub interval_hours: Option<u64>,
+    /// Backups kept; older ones are deleted after each new one. Defaults to 7.
+    #[serde(default)]
+    pub keep: Option<usize>,
+}
+
+impl BackupConfig {
+    pub fn dir(&self) -> &str {
+        self.dir.as_deref().unwrap_or("backups")
+    }
+
+    /// Time between automatic backups; `None` when they're off.
+    pub fn interval(&self) -> Option<Duration> {
+        Some(self.interval_hours.unwrap_or(24)).filter(|h| *h > 0).map(|h| Duration::from_secs(h * 3600))
+    }
+
+    pub fn keep(&self) -> usize {
+        self.keep.unwrap_or(7).max(1)
+    }
+}
+
+/// Where command errors and panics are reported besides the log. Either, both or neither.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct TelemetryConfig {
+    /// Discord webhook URL that gets an embed per failure.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Sentry project DSN, `https://<key>@<host>/<project>`.
+    #[serde(default)]
+    pub sentry_dsn: Option<String>,
+    /// Reports sent per minute at most; the rest are dropped and counted. Defaults to 10.
+    #[serde(default)]
+    pub max_per_minute: Option<u32>,
+}
+
+impl TelemetryConfig {
+    pub fn max_per_minute(&self) -> u32 {
+        self.max_per_minute.unwrap_or(10)
+    }
+}
+
+/// Who may use the bot. The lists themselves live in the database and are managed with
+/// `/admin block` and `/admin allowlist`.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct AccessConfig {
+    /// Private instance: leave every server that isn't on the allowlist.
+    #[serde(default)]
+    pub guild_allowlist: bool,
+}
+
+/// Audio playback. The backend is chosen at startup; changing it needs a restart.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct MusicConfig {
+    #[serde(default)]
+    pub player_backend: PlayerBackend,
+    #[serde(default)]
+    pub lavalink: Option<LavalinkConfig>,
+    /// Netscape-format cookies file passed to yt-dlp (`--cookies`), for age-restricted and
+    /// members-only videos. Export it from a browser logged into a throwaway account.
+    #[serde(default)]
+    pub ytdlp_cookies: Option<String>,
+    /// Extra `music ambient` presets by name; a built-in preset of the same name is replaced.
+    #[serde(default)]
+    pub ambient: HashMap<String, AmbientPreset>,
+    /// Leave a voice channel after this many seconds with nothing playing or queued; 0 never
+    /// leaves. Defaults to 600.
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// Tracks resolved (yt-dlp/ffmpeg work) at once across all servers; the rest wait their
+    /// turn. Defaults to 4. Read at startup.
+    #[serde(default)]
+    pub max_concurrent_resolutions: Option<usize>,
+    /// How far (in percent) the music is lowered while a clip plays over it. Defaults to 70.
+    #[serde(default)]
+    pub duck_percent: Option<u8>,
+    /// Directory `music play file:<path>` can play audio files from; unset turns local files off.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "local"), allow(dead_code))]
+    pub local_dir: Option<String>,
+    /// Text-to-speech endpoint for spoken announcements: the text is added as a `text` query
+    /// parameter and the response played as audio. Unset turns spoken announcements off.
+    #[serde(default)]
+    pub tts_url: Option<String>,
+    /// Tries a queued request gets when starting it errors, before it's skipped with a notice.
+    /// Defaults to 3.
+    #[serde(default)]
+    pub queue_attempts: Option<u32>,
+}
+
+/// A looped background sound for `music ambient`: either an ffmpeg `lavfi` generator or a URL
+/// (a direct media link or anything yt-dlp can resolve, such as a YouTube live stream).
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct AmbientPreset {
+    #[serde(default)]
+    pub generator: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// 1.0 is unchanged; defaults to the music volume of 0.2.
+    #[serde(default)]
+    pub volume: Option<f32>,
+}
+
+/// Presets available without any config.
+const BUILTIN_AMBIENT: &[(&str, Option<&str>, Option<&str>)] = &[
+    ("rain", Some("anoisesrc=color=pink:amplitude=0.6,highpass=f=300,lowpass=f=2500"), None),
+    ("brown_noise", Some("anoisesrc=color=brown:amplitude=0.5"), None),
+    ("white_noise", Some("anoisesrc=color=white:amplitude=0.15"), None),
+    ("silence", Some("anullsrc=r=48000:cl=stereo"), None),
+    ("lofi", None, Some("https://www.youtube.com/watch?v=jfKfPfyJRdk")),
+];
+
+impl MusicConfig {
+    pub fn duck_percent(&self) -> u8 {
+        self.duck_percent.unwrap_or(70).min(100)
+    }
+
+    pub fn resolution_slots(&self) -> usize {
+        self.max_concurrent_resolutions.unwrap_or(4).max(1)
+    }
+
+    pub fn queue_attempts(&self) -> u32 {
+        self.queue_attempts.unwrap_or(3).max(1)
+    }
+
+    /// `idle_timeout_secs` as a duration; `None` when idle calls are kept.
+    pub fn idle_timeout(&self) -> Option<std::time::Duration> {
+        Some(self.idle_timeout_secs.unwrap_or(600)).filter(|s| *s > 0).map(std::time::Duration::from_secs)
+    }
+
+    /// Extra yt-dlp arguments for `ytdlp_cookies`; empty when it isn't set.
+    pub fn cookie_args(&self) -> Vec<String> {
+        match &self.ytdlp_cookies {
+            Some(path) => vec!["--cookies".to_string(), path.clone()],
+            None => Vec::new(),
+        }
+    }
+
+    /// The preset called `name`, from the config or else the built-ins.
+    pub fn ambient_preset(&self, name: &str) -> Option<AmbientPreset> {
+        if let Some(preset) = self.ambient.get(name) {
+            return Some(preset.clone());
+        }
+        BUILTIN_AMBIENT.iter().find(|(n, _, _)| *n == name).map(|(_, generator, url)| AmbientPreset {
+            generator: generator.map(str::to_string),
+            url: url.map(str::to_string),
+            volume: None,
+        })
+    }
+
+    /// Every preset name, sorted.
+    pub fn ambient_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_AMBIENT.iter().map(|(n, _, _)| n.to_string()).collect();
+        names.extend(self.ambient.keys().cloned());
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerBackend {
+    /// yt-dlp and ffmpeg inside the bot process.
+    #[default]
+    Songbird,
+    /// A Lavalink v4 node does the resolving and streaming; the bot only joins voice.
+    Lavalink,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LavalinkConfig {
+    /// `host:port` of the node, e.g. `localhost:2333`.
+    pub host: String,
+    pub password: String,
+    /// Use https/wss instead of http/ws.
+    #[serde(default)]
+    pub secure: bool,
+}
+
+/// Built-in HTTP server (health probes, dashboard). Off unless `port` is set; read once at startup.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HttpConfig {
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    #[serde(default)]
+    pub dashboard: Option<DashboardConfig>,
+    #[serde(default)]
+    pub api: Option<ApiConfig>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig { port: None, bind: default_bind(), dashboard: None, api: None }
+    }
+}
+
+/// Playback API under `/guilds/...`. Callers send `Authorization: Bearer <token>`; keep the token
+/// out of the file with `BOT__HTTP__API__TOKEN`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiConfig {
+    pub token: String,
+    /// Guilds the token may control; empty means every guild the bot is in.
+    #[serde(default)]
+    pub guilds: Vec<GuildId>,
+}
+
+/// Web dashboard with Discord login. The OAuth2 client secret comes from `DISCORD_CLIENT_SECRET`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DashboardConfig {
+    /// Address users reach the server at, e.g. `https://bot.example.com`; the OAuth2 redirect is
+    /// `<public_url>/dashboard/callback` and must be added in the Discord developer portal.
+    pub public_url: String,
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+/// Bot activity while no music is playing.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PresenceConfig {
+    /// Rotated in order; `{guilds}` is the server count. A leading "Playing ", "Listening to ",
+    /// "Watching " or "Competing in " sets the activity type, otherwise it's a custom status.
+    #[serde(default = "default_statuses")]
+    pub statuses: Vec<String>,
+    #[serde(default = "default_rotate_secs")]
+    pub rotate_secs: u64,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        PresenceConfig { statuses: default_statuses(), rotate_secs: default_rotate_secs() }
+    }
+}
+
+fn default_statuses() -> Vec<String> {
+    vec!["Listening to !is help".to_string(), "Watching {guilds} servers".to_string()]
+}
+
+fn default_rotate_secs() -> u64 {
+    60
+}
+
+/// Where slash commands are registered.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct RegistrationConfig {
+    #[serde(default)]
+    pub mode: RegistrationMode,
+    /// The only guild that gets commands in `dev` mode.
+    #[serde(default)]
+    pub dev_guild: Option<GuildId>,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    /// One global set; can take a while to show up everywhere but costs one API call.
+    Global,
+    /// Every guild gets its own copy, available instantly.
+    #[default]
+    Guild,
+    /// Only `dev_guild`, for testing changes without touching other servers.
+    Dev,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct StartConfig {
+    /// Shared services, visible in every guild unless it opts out.
+    #[serde(default)]
+    pub services: HashMap<String, ServiceConfig>,
+    /// Per-guild catalogs keyed by guild ID.
+    #[serde(default)]
+    pub guilds: HashMap<String, GuildServices>,
+    /// Local targets the `command`, `systemd` and `docker` service types may touch.
+    #[serde(default)]
+    pub allow: ExecAllowList,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GuildServices {
+    #[serde(default)]
     pub services: HashMap<String, ServiceConfig>,
+    /// Also offer the shared `start.services` here (on by default).
+    #[serde(default = "default_true")]
+    pub shared: bool,
 }
 
+fn default_true() -> bool {
+    true
+}
+
+impl StartConfig {
+    /// The services usable in `guild_id`: its own catalog over the shared one. Outside a guild
+    /// (DMs) only shared services are offered.
+    pub fn services_for(&self, guild_id: Option<GuildId>) -> HashMap<&str, &ServiceConfig> {
+        let own = guild_id.and_then(|g| self.guilds.get(&g.get().to_string()));
+        let mut services: HashMap<&str, &ServiceConfig> = HashMap::new();
+        if own.is_none_or(|g| g.shared) {
+            services.extend(self.services.iter().map(|(k, v)| (k.as_str(), v)));
+        }
+        if let Some(own) = own {
+            services.extend(own.services.iter().map(|(k, v)| (k.as_str(), v)));
+        }
+        services
+    }
+}
+
+/// Nothing local runs unless it is listed here.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ExecAllowList {
+    /// Programs (as written in a service's `command[0]`).
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// systemd unit names.
+    #[serde(default)]
+    pub units: Vec<String>,
+    /// Docker container names or IDs.
+    #[serde(default)]
+    pub containers: Vec<String>,
+    /// Docker Engine API socket; defaults to `/var/run/docker.sock`.
+    #[serde(default)]
+    pub docker_socket: Option<String>,
+}
+
+impl ExecAllowList {
+    /// Why `action` may not run, if it targets something not listed.
+    pub fn check(&self, action: &ActionConfig) -> Result<(), String> {
+        match action.kind.unwrap_or_default() {
+            ServiceKind::Http | ServiceKind::Wol => Ok(()),
+            ServiceKind::Command => {
+                let program = action.command.as_ref().and_then(|c| c.first()).ok_or("missing `command`")?;
+                if self.commands.contains(program) {
+                    Ok(())
+                } else {
+                    Err(format!("command `{}` is not in `start.allow.commands`", program))
+                }
+            }
+            ServiceKind::Systemd => {
+                let unit = action.unit.as_ref().ok_or("missing `unit`")?;
+                if self.units.contains(unit) {
+                    Ok(())
+                } else {
+                    Err(format!("unit `{}` is not in `start.allow.units`", unit))
+                }
+            }
+            ServiceKind::Docker => {
+                let container = action.container.as_ref().ok_or("missing `container`")?;
+                if self.containers.contains(container) {
+                    Ok(())
+                } else {
+                    Err(format!("container `{}` is not in `start.allow.containers`", container))
+                }
+            }
+        }
+    }
+}
+
+/// A service's request settings double as its `start` action and as defaults for `actions`.
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServiceConfig {
-    pub url: String,
+    /// Shown by `start list`.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, rename = "type")]
+    pub kind: Option<ServiceKind>,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Target MAC address for `type: "wol"`.
+    #[serde(default)]
+    pub mac: Option<String>,
+    /// Where to send the magic packet; defaults to `255.255.255.255:9`.
+    #[serde(default)]
+    pub broadcast: Option<String>,
+    /// Program and arguments for `type: "command"`.
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// Unit for `type: "systemd"`.
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Container for `type: "docker"`.
+    #[serde(default)]
+    pub container: Option<String>,
     #[serde(default)]
     pub method: Option<String>,
     #[serde(default)]
@@ -48,23 +503,858 @@ pub struct ServiceConfig {
     pub args_field: Option<String>,
     #[serde(default)]
     pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub response: Option<ResponseConfig>,
+    /// Who may use the service. Empty lists mean no restriction; when both roles and users are
+    /// set, matching either is enough. IDs are best written as strings.
+    #[serde(default)]
+    pub allowed_roles: Vec<RoleId>,
+    #[serde(default)]
+    pub allowed_users: Vec<UserId>,
+    #[serde(default)]
+    pub allowed_channels: Vec<ChannelId>,
+    /// Extra actions (`stop`, `restart`, `status`, ...); unset fields fall back to the service's own.
+    #[serde(default)]
+    pub actions: HashMap<String, ActionConfig>,
+    #[serde(default)]
+    pub poll: Option<PollConfig>,
+    /// Ask the invoker to confirm with buttons before running.
+    #[serde(default)]
+    pub confirm: Option<bool>,
+    /// Poll the `status` action after `start`/`restart` (and for `status` itself) until healthy.
+    #[serde(default)]
+    pub wait: Option<WaitConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ActionConfig {
+    #[serde(default, rename = "type")]
+    pub kind: Option<ServiceKind>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub mac: Option<String>,
+    #[serde(default)]
+    pub broadcast: Option<String>,
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub container: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+    #[serde(default)]
+    pub args_field: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub response: Option<ResponseConfig>,
+    /// When the reply counts as healthy; any 2xx if unset.
+    #[serde(default)]
+    pub healthy: Option<Condition>,
+    #[serde(default)]
+    pub poll: Option<PollConfig>,
+    #[serde(default)]
+    pub confirm: Option<bool>,
+}
+
+/// For backends that accept a job and finish it later: poll `url` until `success` or `failure`
+/// matches, editing the reply with `progress` in between.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PollConfig {
+    /// Rendered against the first reply, e.g. `http://host/jobs/{{.id}}`.
+    pub url: String,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default = "default_wait_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_poll_timeout")]
+    pub timeout_secs: u64,
+    pub success: Condition,
+    #[serde(default)]
+    pub failure: Option<Condition>,
+    /// Template shown while the job runs, e.g. `{{.progress}}% done`.
+    #[serde(default)]
+    pub progress: Option<String>,
+}
+
+/// What an action does when run.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceKind {
+    /// Send an HTTP request to `url` (the default).
+    #[default]
+    Http,
+    /// Send a Wake-on-LAN magic packet to `mac`.
+    Wol,
+    /// Run the allow-listed local `command`.
+    Command,
+    /// `systemctl <action> <unit>`; the action must be start, stop, restart or status.
+    Systemd,
+    /// Start/stop/restart/inspect `container` through the Docker Engine API.
+    Docker,
+}
+
+/// Matches when the JSON reply has `field` (a `{{.path}}`-style path) equal to `equals`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Condition {
+    pub field: String,
+    pub equals: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WaitConfig {
+    #[serde(default = "default_wait_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_wait_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_wait_interval() -> u64 {
+    5
+}
+
+fn default_wait_timeout() -> u64 {
+    120
+}
+
+fn default_poll_timeout() -> u64 {
+    600
+}
+
+impl ServiceConfig {
+    /// Resolve `name` into a complete request: `start` falls back to the service itself, other
+    /// actions must be listed in `actions`.
+    pub fn action(&self, name: &str) -> Option<ActionConfig> {
+        let own = self.actions.get(name);
+        if own.is_none() && name != "start" {
+            return None;
+        }
+        let own = own.cloned().unwrap_or_default();
+        Some(ActionConfig {
+            kind: own.kind.or(self.kind),
+            url: own.url.or_else(|| self.url.clone()),
+            mac: own.mac.or_else(|| self.mac.clone()),
+            broadcast: own.broadcast.or_else(|| self.broadcast.clone()),
+            command: own.command.or_else(|| self.command.clone()),
+            unit: own.unit.or_else(|| self.unit.clone()),
+            container: own.container.or_else(|| self.container.clone()),
+            method: own.method.or_else(|| self.method.clone()),
+            headers: own.headers.or_else(|| self.headers.clone()),
+            body: own.body.or_else(|| self.body.clone()),
+            args_field: own.args_field.or_else(|| self.args_field.clone()),
+            timeout_secs: own.timeout_secs.or(self.timeout_secs),
+            response: own.response.or_else(|| self.response.clone()),
+            healthy: own.healthy,
+            poll: own.poll.or_else(|| self.poll.clone()),
+            confirm: own.confirm.or(self.confirm),
+        })
+    }
+
+    /// Action names this service answers to, sorted.
+    pub fn action_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.actions.keys().cloned().collect();
+        let has_target = self.url.is_some()
+            || self.mac.is_some()
+            || self.command.is_some()
+            || self.unit.is_some()
+            || self.container.is_some();
+        if !self.actions.contains_key("start") && has_target {
+            names.push("start".to_string());
+        }
+        names.sort();
+        names
+    }
+}
+
+/// How to render a service's reply in chat.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ResponseConfig {
+    /// Embed title; defaults to the service name.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Description template: `{{.ip}}` pulls a field from the JSON reply, `{{.players.0.name}}`
+    /// walks into nested objects/arrays, `{{.}}` is the whole body and `{{status}}` the HTTP status.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Template used instead when the service answers with a non-2xx status.
+    #[serde(default)]
+    pub error_template: Option<String>,
 }
 
 pub async fn ensure_default_config() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    match tokio::fs::metadata(CONFIG_PATH).await {
+    match tokio::fs::metadata(config_path()).await {
         Ok(_) => Ok(()),
         Err(e) if e.kind() == ErrorKind::NotFound => {
-            tokio::fs::write(CONFIG_PATH, DEFAULT_CONFIG).await?;
+            tokio::fs::write(config_path(), DEFAULT_CONFIG).await?;
             Ok(())
         }
         Err(e) => Err(Box::new(e)),
     }
 }
 
+/// Build the config from its layers: defaults < file < `BOT__*` env vars < `--set` flags.
 pub async fn load_config() -> Result<AppConfig, Box<dyn std::error::Error + Send + Sync>> {
     let _ = ensure_default_config().await;
 
-    let contents = tokio::fs::read_to_string(CONFIG_PATH).await?;
-    let cfg: AppConfig = json5::from_str(&contents)?;
-    Ok(cfg)
+    let contents = match tokio::fs::read_to_string(config_path()).await {
+        Ok(contents) => contents,
+        // No file (e.g. a read-only container): env and flags alone are fine
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut merged = if contents.trim().is_empty() {
+        serde_json::Value::Object(Default::default())
+    } else {
+        json5::from_str::<serde_json::Value>(&contents).map_err(|e| ConfigError {
+            problems: vec![format!("syntax error: {}", e)],
+        })?
+    };
+
+    // `vars()` panics on a non-UTF-8 variable anywhere in the environment; those are skipped
+    for (key, value) in std::env::vars_os() {
+        let Some(key) = key.to_str() else { continue };
+        let Some(path) = key.strip_prefix(ENV_PREFIX) else { continue };
+        let Ok(value) = value.into_string() else {
+            tracing::warn!("Ignoring {}: its value isn't valid UTF-8", key);
+            continue;
+        };
+        let path: Vec<String> = path.split("__").map(|p| p.to_ascii_lowercase()).collect();
+        set_path(&mut merged, &path, &value);
+    }
+    if let Some(layers) = LAYERS.get() {
+        for (key, value) in &layers.cli {
+            let path: Vec<String> = key.split('.').map(str::to_string).collect();
+            set_path(&mut merged, &path, value);
+        }
+    }
+
+    Ok(validate(merged, &contents)?)
+}
+
+/// `start.guilds.<guild>.services` exactly as written in the config file, comments included,
+/// or `{}` if the guild has none.
+pub async fn guild_services_source(guild_id: GuildId) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = match tokio::fs::read_to_string(config_path()).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok("{}".to_string()),
+        Err(e) => return Err(e.into()),
+    };
+    let path = services_path(guild_id);
+    Ok(match jsonc::find(&contents, &path) {
+        Some(range) => jsonc::dedent(&contents, range),
+        None => "{}".to_string(),
+    })
+}
+
+fn services_path(guild_id: GuildId) -> [String; 4] {
+    ["start".to_string(), "guilds".to_string(), guild_id.to_string(), "services".to_string()]
+}
+
+/// Replace one guild's service catalog in the config file with `source` (JSONC). Only that
+/// value is rewritten, so comments elsewhere in the file and in `source` are kept. The new file
+/// is validated before it is written, so a bad catalog never reaches disk.
+pub async fn set_guild_services(guild_id: GuildId, source: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let services: serde_json::Value =
+        json5::from_str(source).map_err(|e| ConfigError { problems: vec![format!("syntax error: {}", e)] })?;
+    if !services.is_object() {
+        return Err("the catalog must be an object of services".into());
+    }
+    let contents = match tokio::fs::read_to_string(config_path()).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let path = services_path(guild_id);
+    let text = if contents.trim().is_empty() {
+        // No file yet, so nothing to keep: write it out plainly
+        let mut root = serde_json::Value::Object(Default::default());
+        set_value(&mut root, &path, services);
+        serde_json::to_string_pretty(&root)? + "\n"
+    } else {
+        jsonc::replace(&contents, &path, source.trim())
+            .ok_or_else(|| format!("couldn't find where the catalog goes in {}", config_path()))?
+    };
+    let root: serde_json::Value =
+        json5::from_str(&text).map_err(|e| ConfigError { problems: vec![format!("syntax error: {}", e)] })?;
+    validate(root, &text)?;
+    crate::storage::write_atomic(Path::new(config_path()), text.as_bytes()).await?;
+    Ok(())
+}
+
+/// Just enough of a JSONC/JSON5 scanner to find where a value sits in the config file, so the
+/// dashboard can rewrite one value in place instead of re-serializing (and losing comments).
+mod jsonc {
+    use std::ops::Range;
+
+    /// An object's keys and where each one's value sits.
+    type Members = Vec<(String, Range<usize>)>;
+
+    /// Index of the next byte that isn't whitespace or inside a comment.
+    fn skip(s: &[u8], mut i: usize) -> usize {
+        loop {
+            while i < s.len() && s[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if s[i..].starts_with(b"//") {
+                while i < s.len() && s[i] != b'\n' {
+                    i += 1;
+                }
+            } else if s[i..].starts_with(b"/*") {
+                i = s[i + 2..].windows(2).position(|w| w == b"*/").map_or(s.len(), |p| i + 2 + p + 2);
+            } else {
+                return i;
+            }
+        }
+    }
+
+    /// End of the string starting at `i` (on its quote).
+    fn string_end(s: &[u8], i: usize) -> Option<usize> {
+        let quote = s[i];
+        let mut j = i + 1;
+        while j < s.len() {
+            match s[j] {
+                b'\\' => j += 2,
+                c if c == quote => return Some(j + 1),
+                _ => j += 1,
+            }
+        }
+        None
+    }
+
+    /// End of the value starting at `i`.
+    fn value_end(s: &[u8], i: usize) -> Option<usize> {
+        match *s.get(i)? {
+            b'"' | b'\'' => string_end(s, i),
+            b'{' | b'[' => {
+                let mut depth = 0usize;
+                let mut j = i;
+                while j < s.len() {
+                    match s[j] {
+                        b'"' | b'\'' => {
+                            j = string_end(s, j)?;
+                            continue;
+                        }
+                        b'/' if matches!(s.get(j + 1), Some(b'/') | Some(b'*')) => {
+                            j = skip(s, j);
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                return Some(j + 1);
+                            }
+                        }
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                None
+            }
+            _ => {
+                let mut j = i;
+                while j < s.len() && !matches!(s[j], b',' | b'}' | b']' | b'/') && !s[j].is_ascii_whitespace() {
+                    j += 1;
+                }
+                (j > i).then_some(j)
+            }
+        }
+    }
+
+    /// Members of the object starting at `open`: each key with its value's range, and where
+    /// the closing brace is.
+    fn members(s: &[u8], open: usize) -> Option<(Members, usize)> {
+        let mut out = Vec::new();
+        let mut i = skip(s, open + 1);
+        loop {
+            match *s.get(i)? {
+                b'}' => return Some((out, i)),
+                b'"' | b'\'' => {
+                    let end = string_end(s, i)?;
+                    let key = String::from_utf8_lossy(&s[i + 1..end - 1]).into_owned();
+                    i = end;
+                    out.push((key, 0..0));
+                }
+                _ => {
+                    let start = i;
+                    while i < s.len() && (s[i].is_ascii_alphanumeric() || s[i] == b'_' || s[i] == b'$') {
+                        i += 1;
+                    }
+                    if i == start {
+                        return None;
+                    }
+                    out.push((String::from_utf8_lossy(&s[start..i]).into_owned(), 0..0));
+                }
+            }
+            i = skip(s, i);
+            if s.get(i) != Some(&b':') {
+                return None;
+            }
+            let start = skip(s, i + 1);
+            let end = value_end(s, start)?;
+            out.last_mut()?.1 = start..end;
+            i = skip(s, end);
+            if s.get(i) == Some(&b',') {
+                i = skip(s, i + 1);
+            }
+        }
+    }
+
+    /// Where the value at `path` sits in `text`.
+    pub fn find(text: &str, path: &[String]) -> Option<Range<usize>> {
+        let s = text.as_bytes();
+        let mut range = {
+            let start = skip(s, 0);
+            start..value_end(s, start)?
+        };
+        for key in path {
+            if s[range.start] != b'{' {
+                return None;
+            }
+            let (members, _) = members(s, range.start)?;
+            range = members.into_iter().find(|(k, _)| k == key)?.1;
+        }
+        Some(range)
+    }
+
+    /// Indentation of the line `at` is on.
+    fn indent_at(text: &str, at: usize) -> &str {
+        let line = &text[text[..at].rfind('\n').map_or(0, |p| p + 1)..];
+        &line[..line.len() - line.trim_start().len()]
+    }
+
+    /// The text at `range` with the indentation of its line taken off its later lines.
+    pub fn dedent(text: &str, range: Range<usize>) -> String {
+        let indent = indent_at(text, range.start);
+        let value = &text[range];
+        let mut lines = value.lines();
+        let first = lines.next().unwrap_or_default().to_string();
+        lines.fold(first, |acc, line| acc + "\n" + line.strip_prefix(indent).unwrap_or(line))
+    }
+
+    /// `value` indented for a line that starts with `indent`.
+    fn reindent(value: &str, indent: &str) -> String {
+        let mut lines = value.lines();
+        let first = lines.next().unwrap_or_default().to_string();
+        lines.fold(first, |acc, line| if line.is_empty() { acc + "\n" } else { acc + "\n" + indent + line })
+    }
+
+    /// `text` with the value at `path` replaced by `value`, creating the objects on the way
+    /// if they're missing. `None` if `text` isn't an object to put it in.
+    pub fn replace(text: &str, path: &[String], value: &str) -> Option<String> {
+        let s = text.as_bytes();
+        let start = skip(s, 0);
+        if s.get(start) != Some(&b'{') {
+            return None;
+        }
+        let mut open = start;
+        for (depth, key) in path.iter().enumerate() {
+            let (members, close) = members(s, open)?;
+            let last = members.last().map(|(_, range)| range.end);
+            match members.into_iter().find(|(k, _)| k == key) {
+                Some((_, range)) if depth + 1 == path.len() => {
+                    let value = reindent(value, indent_at(text, range.start));
+                    return Some(format!("{}{}{}", &text[..range.start], value, &text[range.end..]));
+                }
+                Some((_, range)) if s[range.start] == b'{' => open = range.start,
+                Some(_) => return None,
+                None => {
+                    // Build the missing objects and add them as the last member
+                    let indent = format!("{}  ", indent_at(text, open));
+                    let mut nested = reindent(value, &format!("{}{}", indent, "  ".repeat(path.len() - depth - 1)));
+                    for (level, k) in path[depth + 1..].iter().enumerate().rev() {
+                        let inner = format!("{}{}", indent, "  ".repeat(level + 1));
+                        let outer = format!("{}{}", indent, "  ".repeat(level));
+                        nested = format!("{{\n{}{}: {}\n{}}}", inner, serde_json::to_string(k).ok()?, nested, outer);
+                    }
+                    let entry = format!("{}: {}", serde_json::to_string(key).ok()?, nested);
+                    // After the last member, its comma (added if it has none) and any comment
+                    // that ends its line
+                    let (comma_at, comma, mut before) = match last {
+                        None => (open + 1, "", open + 1),
+                        Some(end) => match skip(s, end) {
+                            after if s.get(after) == Some(&b',') => (after + 1, "", after + 1),
+                            _ => (end, ",", end),
+                        },
+                    };
+                    if let Some(eol) = text[before..close].find('\n').map(|p| before + p)
+                        && skip(s, before) > eol
+                    {
+                        before = eol;
+                    }
+                    let rest = if text[before..close].trim().is_empty() {
+                        format!("\n{}{}", indent_at(text, close), &text[close..])
+                    } else {
+                        text[before..].to_string()
+                    };
+                    return Some(format!(
+                        "{}{}{}\n{}{}{}",
+                        &text[..comma_at],
+                        comma,
+                        &text[comma_at..before],
+                        indent,
+                        entry,
+                        rest
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn path(keys: &[&str]) -> Vec<String> {
+            keys.iter().map(|k| k.to_string()).collect()
+        }
+
+        #[test]
+        fn replaces_a_value_and_keeps_comments() {
+            let text = "{\n  // the bot\n  \"start\": {\n    \"services\": { \"a\": 1 }, // old\n  },\n}\n";
+            let out = replace(text, &path(&["start", "services"]), "{\n  \"b\": 2\n}").unwrap();
+            assert_eq!(out, "{\n  // the bot\n  \"start\": {\n    \"services\": {\n      \"b\": 2\n    }, // old\n  },\n}\n");
+            let range = find(&out, &path(&["start", "services"])).unwrap();
+            assert_eq!(dedent(&out, range), "{\n  \"b\": 2\n}");
+        }
+
+        #[test]
+        fn creates_missing_objects() {
+            let text = "{\n  \"token\": \"x\" // secret\n}\n";
+            let out = replace(text, &path(&["start", "guilds"]), "{}").unwrap();
+            assert_eq!(out, "{\n  \"token\": \"x\", // secret\n  \"start\": {\n    \"guilds\": {}\n  }\n}\n");
+            let value: serde_json::Value = json5::from_str(&out).unwrap();
+            assert_eq!(value["start"]["guilds"], serde_json::json!({}));
+        }
+    }
+}
+
+/// Everything wrong with a config, reported together so one restart fixes them all.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} has {} problem(s):", config_path(), self.problems.len())?;
+        for p in &self.problems {
+            writeln!(f, "  - {}", p)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Deserialize the merged layers into `AppConfig`, collecting unknown keys, type errors and
+/// missing fields (with the line in `source` where possible) plus a few semantic checks.
+fn validate(merged: serde_json::Value, source: &str) -> Result<AppConfig, ConfigError> {
+    let mut problems = Vec::new();
+    let mut unknown: Vec<String> = Vec::new();
+    let mut track = |path: serde_ignored::Path<'_>| unknown.push(path.to_string());
+    let de = serde_ignored::Deserializer::new(merged, &mut track);
+    let parsed: Result<AppConfig, _> = serde_path_to_error::deserialize(de);
+
+    for path in unknown {
+        problems.push(format!("unknown key `{}`{}", path, line_hint(source, &path)));
+    }
+    let cfg = match parsed {
+        Ok(cfg) => Some(cfg),
+        Err(e) => {
+            let path = e.path().to_string();
+            let at = if path == "." { "top level".to_string() } else { format!("`{}`", path) };
+            problems.push(format!("{}: {}{}", at, e.inner(), line_hint(source, &path)));
+            None
+        }
+    };
+
+    if let Some(reg) = cfg.as_ref().map(|c| &c.registration)
+        && reg.mode == RegistrationMode::Dev
+        && reg.dev_guild.is_none()
+    {
+        problems.push(format!("`registration.dev_guild` is required in dev mode{}", line_hint(source, "registration")));
+    }
+
+    if let Some(music) = cfg.as_ref().map(|c| &c.music)
+        && music.player_backend == PlayerBackend::Lavalink
+        && music.lavalink.is_none()
+    {
+        problems.push(format!(
+            "`music.lavalink` is required when `music.player_backend` is \"lavalink\"{}",
+            line_hint(source, "music.player_backend")
+        ));
+    }
+
+    if let Some(music) = cfg.as_ref().map(|c| &c.music) {
+        for (name, preset) in &music.ambient {
+            let path = format!("music.ambient.{}", name);
+            if preset.generator.is_some() == preset.url.is_some() {
+                problems.push(format!("`{}` needs exactly one of `generator` or `url`{}", path, line_hint(source, &path)));
+            }
+        }
+    }
+
+    if let Some(api) = cfg.as_ref().and_then(|c| c.http.api.as_ref())
+        && api.token.len() < 16
+    {
+        problems.push(format!("`http.api.token` must be at least 16 characters{}", line_hint(source, "http.api")));
+    }
+
+    if let Some(start) = cfg.as_ref().and_then(|c| c.start.as_ref()) {
+        for (name, svc) in &start.services {
+            check_service(&mut problems, source, &format!("start.services.{}", name), svc, &start.allow);
+        }
+        for (guild, catalog) in &start.guilds {
+            let path = format!("start.guilds.{}", guild);
+            if guild.parse::<u64>().is_err() {
+                problems.push(format!("`{}`: guild keys must be numeric guild IDs{}", path, line_hint(source, &path)));
+            }
+            for (name, svc) in &catalog.services {
+                check_service(&mut problems, source, &format!("{}.services.{}", path, name), svc, &start.allow);
+            }
+        }
+    }
+
+    match cfg {
+        Some(cfg) if problems.is_empty() => Ok(cfg),
+        _ => Err(ConfigError { problems }),
+    }
+}
+
+fn check_service(problems: &mut Vec<String>, source: &str, path: &str, svc: &ServiceConfig, allow: &ExecAllowList) {
+    if svc.action_names().is_empty() {
+        problems.push(format!(
+            "`{}` needs a target (`url`, `mac`, `command`, `unit` or `container`) or at least one entry in `actions`{}",
+            path,
+            line_hint(source, path)
+        ));
+    }
+    for action in svc.action_names() {
+        let Some(resolved) = svc.action(&action) else { continue };
+        let path = if svc.actions.contains_key(&action) {
+            format!("{}.actions.{}", path, action)
+        } else {
+            path.to_string()
+        };
+        check_action(problems, source, &path, &resolved);
+        if let Err(e) = allow.check(&resolved) {
+            problems.push(format!("`{}`: {}{}", path, e, line_hint(source, &path)));
+        }
+        if resolved.kind == Some(ServiceKind::Systemd) && !["start", "stop", "restart", "status"].contains(&action.as_str()) {
+            problems.push(format!(
+                "`{}`: systemd services only support start/stop/restart/status{}",
+                path,
+                line_hint(source, &path)
+            ));
+        }
+    }
+    if svc.wait.is_some() && !svc.actions.contains_key("status") {
+        problems.push(format!("`{}.wait` needs a `status` action to poll{}", path, line_hint(source, path)));
+    }
+}
+
+fn check_action(problems: &mut Vec<String>, source: &str, path: &str, action: &ActionConfig) {
+    // Local executors are checked against `start.allow` by the caller
+    if matches!(action.kind, Some(ServiceKind::Command | ServiceKind::Systemd | ServiceKind::Docker)) {
+        return;
+    }
+    if action.kind == Some(ServiceKind::Wol) {
+        match &action.mac {
+            None => problems.push(format!("`{}` is missing `mac`{}", path, line_hint(source, path))),
+            Some(mac) if parse_mac(mac).is_none() => {
+                problems.push(format!("`{}.mac` `{}` is not a MAC address{}", path, mac, line_hint(source, path)))
+            }
+            Some(_) => {}
+        }
+        if let Some(addr) = &action.broadcast
+            && addr.parse::<std::net::SocketAddr>().is_err()
+        {
+            problems.push(format!("`{}.broadcast` must look like `192.168.1.255:9`{}", path, line_hint(source, path)));
+        }
+        return;
+    }
+    match &action.url {
+        None => problems.push(format!("`{}` is missing `url`{}", path, line_hint(source, path))),
+        // `${VAR}` placeholders are only filled at request time
+        Some(url) if !(url.starts_with("http://") || url.starts_with("https://") || url.starts_with("${")) => {
+            problems.push(format!("`{}.url` must be an http(s) URL{}", path, line_hint(source, path)))
+        }
+        Some(_) => {}
+    }
+    if let Some(method) = &action.method
+        && reqwest::Method::from_bytes(method.to_ascii_uppercase().as_bytes()).is_err()
+    {
+        problems.push(format!("`{}.method` `{}` is not an HTTP method{}", path, method, line_hint(source, path)));
+    }
+    if action.timeout_secs == Some(0) {
+        problems.push(format!("`{}.timeout_secs` must be greater than 0{}", path, line_hint(source, path)));
+    }
+    if let Some(poll) = &action.poll {
+        if !(poll.url.starts_with("http://") || poll.url.starts_with("https://") || poll.url.starts_with("${")) {
+            problems.push(format!("`{}.poll.url` must be an http(s) URL{}", path, line_hint(source, path)));
+        }
+        if poll.interval_secs == 0 {
+            problems.push(format!("`{}.poll.interval_secs` must be greater than 0{}", path, line_hint(source, path)));
+        }
+    }
+}
+
+/// Parse `aa:bb:cc:dd:ee:ff` (or `-` separated) into bytes.
+pub fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let parts: Vec<&str> = mac.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    let mut bytes = [0u8; 6];
+    for (byte, part) in bytes.iter_mut().zip(parts) {
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Best-effort " (line N)" for a dotted key path by finding each key in turn in the source.
+fn line_hint(source: &str, path: &str) -> String {
+    let mut pos = 0;
+    let mut found = false;
+    for key in path.split('.').filter(|k| !k.is_empty() && k.parse::<usize>().is_err()) {
+        let hit = [format!("\"{}\"", key), format!("'{}'", key), format!("{}:", key)]
+            .iter()
+            .filter_map(|needle| source[pos..].find(needle.as_str()))
+            .min();
+        match hit {
+            Some(offset) => {
+                pos += offset;
+                found = true;
+            }
+            None => break,
+        }
+    }
+    if !found {
+        return String::new();
+    }
+    format!(" (line {})", source[..pos].lines().count().max(1))
+}
+
+/// Set `path` inside `root`, creating objects as needed. Values that parse as JSON
+/// (numbers, booleans, objects) are stored as such, anything else as a string.
+fn set_path(root: &mut serde_json::Value, path: &[String], raw: &str) {
+    let value = serde_json::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string()));
+    set_value(root, path, value);
+}
+
+fn set_value(root: &mut serde_json::Value, path: &[String], value: serde_json::Value) {
+    let Some((last, parents)) = path.split_last() else { return };
+    if path.iter().any(|p| p.is_empty()) {
+        return;
+    }
+    let mut node = root;
+    for key in parents {
+        if !node.is_object() {
+            *node = serde_json::Value::Object(Default::default());
+        }
+        node = node
+            .as_object_mut()
+            .expect("just made an object")
+            .entry(key.clone())
+            .or_insert_with(|| serde_json::Value::Object(Default::default()));
+    }
+    if !node.is_object() {
+        *node = serde_json::Value::Object(Default::default());
+    }
+    node.as_object_mut().expect("just made an object").insert(last.clone(), value);
+}
+
+/// Parse a `--set key.path=value` flag.
+pub fn parse_override(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{}`", s)),
+    }
+}
+
+/// The parsed config shared by every module. Reloading swaps the inner `Arc`, so readers holding
+/// the previous config keep a consistent snapshot.
+pub struct ConfigStore;
+impl TypeMapKey for ConfigStore {
+    type Value = Arc<RwLock<Arc<AppConfig>>>;
+}
+
+/// Current config snapshot (defaults if the store hasn't been set up).
+pub async fn current(ctx: &Context) -> Arc<AppConfig> {
+    let store = ctx.data.read().await.get::<ConfigStore>().cloned();
+    match store {
+        Some(store) => store.read().await.clone(),
+        None => Arc::new(AppConfig::default()),
+    }
+}
+
+/// Re-read the config layers and swap the result in, then apply the guild allowlist in case it
+/// changed. On a parse error the previous config stays active.
+pub async fn reload(ctx: &Context) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cfg = load_config().await?;
+    let store = ctx
+        .data
+        .read()
+        .await
+        .get::<ConfigStore>()
+        .cloned()
+        .ok_or("Config store not initialised")?;
+    *store.write().await = Arc::new(cfg);
+    crate::access::enforce_all(ctx).await;
+    Ok(())
+}
+
+/// Watch `config.jsonc` and reload it whenever it changes on disk.
+pub fn watch(ctx: Context) {
+    let path = Path::new(config_path());
+    let file_name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => Path::new(".").to_path_buf(),
+    };
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(16);
+    let mut watcher = match notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let touches_config = event
+                .paths
+                .iter()
+                .any(|p| p.file_name().is_some_and(|n| n == file_name));
+            if touches_config && (event.kind.is_modify() || event.kind.is_create()) {
+                let _ = tx.try_send(());
+            }
+        }
+    }) {
+        Ok(w) => w,
+        Err(e) => {
+            tracing::warn!("Config watcher unavailable, use `admin reload` instead: {e:?}");
+            return;
+        }
+    };
+    // Watch the directory rather than the file: editors often save by replacing the file
+    if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+        tracing::warn!("Failed to watch {}: {e:?}", config_path());
+        return;
+    }
+
+    tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+        while rx.recv().await.is_some() {
+            // Let a burst of write events settle before parsing
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            while rx.try_recv().is_ok() {}
+            match reload(&ctx).await {
+                Ok(()) => tracing::info!("Reloaded {}", config_path()),
+                Err(e) => tracing::warn!("Ignoring invalid {} change, keeping previous config: {e}", config_path()),
+            }
+        }
+    });
 }
@@ -0,0 +1,676 @@
+use axum::extract::{Form, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use poise::serenity_prelude as serenity;
+use serde::Deserialize;
+use serenity::all::{ChannelId, ChannelType, GuildId, Permissions, UserId};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::config::{self, DashboardConfig};
+use crate::modalert::{save_modalert_store, DigestConfig, ModAlertStore};
+use crate::{player, queue, web};
+
+const SESSION_COOKIE: &str = "dash_session";
+/// Holds the OAuth2 `state` between `/dashboard/login` and the callback, so a login started in
+/// someone else's browser can't be completed in this one.
+const LOGIN_COOKIE: &str = "dash_login";
+const SESSION_TTL: Duration = Duration::from_secs(12 * 3600);
+/// How long a login may take between leaving for Discord and coming back.
+const LOGIN_TTL: Duration = Duration::from_secs(600);
+const DISCORD_API: &str = "https://discord.com/api/v10";
+
+/// A logged-in dashboard user and the guilds Discord said they were in at login. Whether they
+/// can still manage one is checked again on every request (see `authorize`).
+#[derive(Clone)]
+struct Session {
+    user_id: UserId,
+    user_name: String,
+    guilds: Vec<UserGuild>,
+    /// Echoed in every form so other sites can't post on the user's behalf.
+    csrf: String,
+    expires: Instant,
+}
+
+#[derive(Clone, Deserialize)]
+struct UserGuild {
+    id: GuildId,
+    name: String,
+    #[serde(default)]
+    owner: bool,
+    #[serde(default)]
+    permissions: String,
+}
+
+impl UserGuild {
+    fn can_manage(&self) -> bool {
+        let perms = Permissions::from_bits_truncate(self.permissions.parse().unwrap_or(0));
+        self.owner || perms.contains(Permissions::MANAGE_GUILD) || perms.contains(Permissions::ADMINISTRATOR)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct DiscordUser {
+    id: UserId,
+    username: String,
+}
+
+#[derive(Clone)]
+struct DashState {
+    cfg: DashboardConfig,
+    http: reqwest::Client,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    /// OAuth2 `state` values handed out by `/dashboard/login`, waiting for the callback.
+    logins: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+/// Routes for the web dashboard, merged into the HTTP server when `http.dashboard` is set.
+pub fn router(cfg: DashboardConfig) -> Router {
+    let state = DashState {
+        cfg,
+        http: reqwest::Client::new(),
+        sessions: Arc::new(Mutex::new(HashMap::new())),
+        logins: Arc::new(Mutex::new(HashMap::new())),
+    };
+    Router::new()
+        .route("/dashboard", get(index))
+        .route("/dashboard/login", get(login))
+        .route("/dashboard/callback", get(callback))
+        .route("/dashboard/logout", post(logout))
+        .route("/dashboard/guilds/{id}", get(guild_page))
+        .route("/dashboard/guilds/{id}/music", post(music_action))
+        .route("/dashboard/guilds/{id}/queue", post(queue_action))
+        .route("/dashboard/guilds/{id}/modalert", post(save_modalert))
+        .route("/dashboard/guilds/{id}/services", post(save_services))
+        .with_state(state)
+}
+
+fn random_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn redirect_uri(cfg: &DashboardConfig) -> String {
+    format!("{}/dashboard/callback", cfg.public_url.trim_end_matches('/'))
+}
+
+fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    let cookies = headers.get(header::COOKIE)?.to_str().ok()?;
+    cookies
+        .split(';')
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(n, _)| *n == name)
+        .map(|(_, value)| value.to_string())
+}
+
+fn secure(cfg: &DashboardConfig) -> &'static str {
+    if cfg.public_url.starts_with("https://") { "; Secure" } else { "" }
+}
+
+async fn session(state: &DashState, headers: &HeaderMap) -> Option<(String, Session)> {
+    let token = cookie(headers, SESSION_COOKIE)?;
+    let mut sessions = state.sessions.lock().await;
+    match sessions.get(&token) {
+        Some(s) if s.expires > Instant::now() => Some((token, s.clone())),
+        Some(_) => {
+            sessions.remove(&token);
+            None
+        }
+        None => None,
+    }
+}
+
+/// The session plus the guild it may manage, or the response to send instead.
+async fn authorize(
+    state: &DashState,
+    headers: &HeaderMap,
+    id: u64,
+    csrf: Option<&str>,
+) -> Result<(Session, UserGuild, &'static serenity::Context), Response> {
+    let Some((_, session)) = session(state, headers).await else {
+        return Err(Redirect::to("/dashboard/login").into_response());
+    };
+    if csrf.is_some_and(|c| c != session.csrf) {
+        return Err(error_page(StatusCode::FORBIDDEN, "This form has expired, reload the page and try again."));
+    }
+    let Some(bot) = web::bot() else {
+        return Err(error_page(StatusCode::SERVICE_UNAVAILABLE, "The bot is still starting, try again shortly."));
+    };
+    let guild = session.guilds.iter().find(|g| g.id.get() == id).cloned();
+    let owner = match &guild {
+        Some(guild) => live_access(bot, guild.id, session.user_id).await,
+        None => None,
+    };
+    match (guild, owner) {
+        (Some(mut guild), Some(owner)) => {
+            guild.owner = owner;
+            Ok((session, guild, bot))
+        }
+        _ => Err(error_page(StatusCode::FORBIDDEN, "You can't manage that server, or the bot isn't in it.")),
+    }
+}
+
+/// Whether `user` can manage `guild_id` right now, from the bot's view of the server rather
+/// than the login snapshot: `Some(is_owner)` if they can, `None` if they can't (or left).
+async fn live_access(bot: &serenity::Context, guild_id: GuildId, user: UserId) -> Option<bool> {
+    let member = guild_id.member(bot, user).await.ok()?;
+    let guild = bot.cache.guild(guild_id)?;
+    let owner = guild.owner_id == user;
+    let perms = guild.member_permissions(&member);
+    (owner || perms.contains(Permissions::MANAGE_GUILD) || perms.administrator()).then_some(owner)
+}
+
+async fn login(State(state): State<DashState>) -> Response {
+    let Some(bot) = web::bot() else {
+        return error_page(StatusCode::SERVICE_UNAVAILABLE, "The bot is still starting, try again shortly.");
+    };
+    let nonce = random_token();
+    {
+        let mut logins = state.logins.lock().await;
+        logins.retain(|_, started| started.elapsed() < LOGIN_TTL);
+        logins.insert(nonce.clone(), Instant::now());
+    }
+    let client_id = bot.cache.current_user().id.to_string();
+    let url = reqwest::Url::parse_with_params(
+        "https://discord.com/oauth2/authorize",
+        &[
+            ("client_id", client_id.as_str()),
+            ("redirect_uri", redirect_uri(&state.cfg).as_str()),
+            ("response_type", "code"),
+            ("scope", "identify guilds"),
+            ("state", nonce.as_str()),
+        ],
+    );
+    let cookie = format!(
+        "{}={}; Path=/dashboard; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        LOGIN_COOKIE,
+        nonce,
+        LOGIN_TTL.as_secs(),
+        secure(&state.cfg)
+    );
+    match url {
+        Ok(url) => ([(header::SET_COOKIE, cookie)], Redirect::to(url.as_str())).into_response(),
+        Err(e) => error_page(StatusCode::INTERNAL_SERVER_ERROR, &format!("Bad `http.dashboard.public_url`: {e}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+}
+
+async fn callback(State(state): State<DashState>, headers: HeaderMap, Query(query): Query<CallbackQuery>) -> Response {
+    let (Some(code), Some(nonce)) = (query.code, query.state) else {
+        return error_page(StatusCode::BAD_REQUEST, "Login was cancelled.");
+    };
+    // The login has to finish in the browser that started it
+    if cookie(&headers, LOGIN_COOKIE).as_deref() != Some(nonce.as_str()) {
+        return error_page(StatusCode::BAD_REQUEST, "This login wasn't started in this browser, try again.");
+    }
+    let known = state
+        .logins
+        .lock()
+        .await
+        .remove(&nonce)
+        .is_some_and(|started| started.elapsed() < LOGIN_TTL);
+    if !known {
+        return error_page(StatusCode::BAD_REQUEST, "This login link has expired, try again.");
+    }
+    let session = match exchange(&state, &code).await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("Dashboard login failed: {e:?}");
+            return error_page(StatusCode::BAD_GATEWAY, "Discord login failed, try again.");
+        }
+    };
+    let token = random_token();
+    {
+        let mut sessions = state.sessions.lock().await;
+        sessions.retain(|_, s| s.expires > Instant::now());
+        sessions.insert(token.clone(), session);
+    }
+    let cookie = format!(
+        "{}={}; Path=/dashboard; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        SESSION_COOKIE,
+        token,
+        SESSION_TTL.as_secs(),
+        secure(&state.cfg)
+    );
+    let clear_login = format!("{}=; Path=/dashboard; HttpOnly; Max-Age=0", LOGIN_COOKIE);
+    ([(header::SET_COOKIE, cookie), (header::SET_COOKIE, clear_login)], Redirect::to("/dashboard")).into_response()
+}
+
+/// Trade the OAuth2 code for a token and look up who the user is and which guilds they're in.
+async fn exchange(state: &DashState, code: &str) -> Result<Session, crate::Error> {
+    let bot = web::bot().ok_or("bot not ready")?;
+    let secret = std::env::var("DISCORD_CLIENT_SECRET").map_err(|_| "DISCORD_CLIENT_SECRET is not set")?;
+    let client_id = bot.cache.current_user().id.to_string();
+    let redirect = redirect_uri(&state.cfg);
+    let token: TokenResponse = state
+        .http
+        .post(format!("{}/oauth2/token", DISCORD_API))
+        .form(&[
+            ("client_id", client_id.as_str()),
+            ("client_secret", secret.as_str()),
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect.as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let user: DiscordUser = state
+        .http
+        .get(format!("{}/users/@me", DISCORD_API))
+        .bearer_auth(&token.access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let guilds: Vec<UserGuild> = state
+        .http
+        .get(format!("{}/users/@me/guilds", DISCORD_API))
+        .bearer_auth(&token.access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    tracing::info!("Dashboard login: {} ({})", user.username, user.id);
+    Ok(Session { user_id: user.id, user_name: user.username, guilds, csrf: random_token(), expires: Instant::now() + SESSION_TTL })
+}
+
+#[derive(Deserialize)]
+struct LogoutForm {
+    csrf: String,
+}
+
+async fn logout(State(state): State<DashState>, headers: HeaderMap, Form(form): Form<LogoutForm>) -> Response {
+    if let Some((token, session)) = session(&state, &headers).await {
+        if form.csrf != session.csrf {
+            return error_page(StatusCode::FORBIDDEN, "This form has expired, reload the page and try again.");
+        }
+        state.sessions.lock().await.remove(&token);
+    }
+    let cookie = format!("{}=; Path=/dashboard; HttpOnly; Max-Age=0", SESSION_COOKIE);
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/dashboard/login")).into_response()
+}
+
+async fn index(State(state): State<DashState>, headers: HeaderMap) -> Response {
+    let Some((_, session)) = session(&state, &headers).await else {
+        return Redirect::to("/dashboard/login").into_response();
+    };
+    let bot = web::bot();
+    let rows: String = session
+        .guilds
+        .iter()
+        .filter(|g| g.can_manage() && bot.is_some_and(|b| b.cache.guild(g.id).is_some()))
+        .map(|g| format!("<li><a href=\"/dashboard/guilds/{}\">{}</a></li>", g.id, escape(&g.name)))
+        .collect();
+    let list = if rows.is_empty() {
+        "<p>None of your servers with Manage Server permission have the bot.</p>".to_string()
+    } else {
+        format!("<ul>{}</ul>", rows)
+    };
+    page("Servers", &session, &format!("<h1>Your servers</h1>{}", list))
+}
+
+async fn guild_page(State(state): State<DashState>, headers: HeaderMap, Path(id): Path<u64>) -> Response {
+    match authorize(&state, &headers, id, None).await {
+        Ok((session, guild, bot)) => render_guild(bot, &session, &guild, None, None).await,
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize)]
+struct MusicForm {
+    csrf: String,
+    action: String,
+}
+
+async fn music_action(
+    State(state): State<DashState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Form(form): Form<MusicForm>,
+) -> Response {
+    let (session, guild, bot) = match authorize(&state, &headers, id, Some(&form.csrf)).await {
+        Ok(ok) => ok,
+        Err(response) => return response,
+    };
+    let result = match form.action.as_str() {
+        "pause" => player::set_paused(bot, guild.id, true).await.map(|ok| ok.then_some("Paused.")),
+        "resume" => player::set_paused(bot, guild.id, false).await.map(|ok| ok.then_some("Resumed.")),
+        "skip" => player::skip(bot, guild.id).await.map(|ok| ok.then_some("Skipped.")),
+        "stop" => player::stop(bot, guild.id).await.map(|ok| ok.then_some("Stopped.")),
+        _ => Ok(Some("Unknown action.")),
+    };
+    let notice = match result {
+        Ok(Some(done)) => done.to_string(),
+        Ok(None) => "Nothing is playing.".to_string(),
+        Err(e) => format!("That didn't work: {e}"),
+    };
+    render_guild(bot, &session, &guild, Some(&notice), None).await
+}
+
+#[derive(Deserialize)]
+struct QueueForm {
+    csrf: String,
+    index: usize,
+    op: String,
+}
+
+async fn queue_action(
+    State(state): State<DashState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Form(form): Form<QueueForm>,
+) -> Response {
+    let (session, guild, bot) = match authorize(&state, &headers, id, Some(&form.csrf)).await {
+        Ok(ok) => ok,
+        Err(response) => return response,
+    };
+    let i = form.index;
+    let done = match form.op.as_str() {
+        "up" => i > 0 && queue::move_track(bot, guild.id, i, i - 1).await,
+        "down" => queue::move_track(bot, guild.id, i, i + 1).await,
+        "remove" => queue::remove(bot, guild.id, i).await.is_some(),
+        _ => false,
+    };
+    // The queue may have moved on since the page was rendered
+    let notice = if done { "Queue updated." } else { "That entry is no longer in the queue." };
+    render_guild(bot, &session, &guild, Some(notice), None).await
+}
+
+#[derive(Deserialize)]
+struct ModAlertForm {
+    csrf: String,
+    enabled: Option<String>,
+    message_log: Option<String>,
+    channel: String,
+    per_minute: u32,
+    window_minutes: u64,
+}
+
+async fn save_modalert(
+    State(state): State<DashState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Form(form): Form<ModAlertForm>,
+) -> Response {
+    let (session, guild, bot) = match authorize(&state, &headers, id, Some(&form.csrf)).await {
+        Ok(ok) => ok,
+        Err(response) => return response,
+    };
+    // Same rule as the `modalert` commands
+    if !guild.owner {
+        return render_guild(bot, &session, &guild, Some("Only the server owner can change mod alerts."), None).await;
+    }
+    let channel = form.channel.parse::<u64>().ok().filter(|c| *c != 0).map(ChannelId::new);
+    if let Some(store) = bot.data.read().await.get::<ModAlertStore>().cloned() {
+        let mut settings = store.lock().await;
+        if form.enabled.is_some() {
+            settings.enabled.insert(guild.id);
+        } else {
+            settings.enabled.remove(&guild.id);
+        }
+        if form.message_log.is_some() {
+            settings.message_log.insert(guild.id);
+        } else {
+            settings.message_log.remove(&guild.id);
+        }
+        match channel {
+            Some(c) => settings.channels.insert(guild.id, c),
+            None => settings.channels.remove(&guild.id),
+        };
+        settings.digest.insert(
+            guild.id,
+            DigestConfig { max_per_minute: form.per_minute.max(1), window_secs: form.window_minutes.max(1) * 60 },
+        );
+    }
+    let notice = match save_modalert_store(bot).await {
+        Ok(()) => "Mod alert settings saved.".to_string(),
+        Err(e) => {
+            tracing::warn!("Failed saving modalert store: {e:?}");
+            "Settings changed but could not be saved; they'll be lost on restart.".to_string()
+        }
+    };
+    render_guild(bot, &session, &guild, Some(&notice), None).await
+}
+
+#[derive(Deserialize)]
+struct ServicesForm {
+    csrf: String,
+    services: String,
+}
+
+async fn save_services(
+    State(state): State<DashState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Form(form): Form<ServicesForm>,
+) -> Response {
+    let (session, guild, bot) = match authorize(&state, &headers, id, Some(&form.csrf)).await {
+        Ok(ok) => ok,
+        Err(response) => return response,
+    };
+    // Catalogs can run commands and read the bot's environment through `${VAR}`
+    if !crate::access::is_owner(bot, session.user_id).await {
+        return render_guild(bot, &session, &guild, Some("Only the bot's owners can change start services."), None).await;
+    }
+    let notice = match config::set_guild_services(guild.id, &form.services).await {
+        Ok(()) => match config::reload(bot).await {
+            Ok(()) => "Services saved.".to_string(),
+            Err(e) => format!("Services saved, but the config didn't reload: {e}"),
+        },
+        Err(e) => {
+            let notice = format!("Not saved: {e}");
+            return render_guild(bot, &session, &guild, Some(&notice), Some(&form.services)).await;
+        }
+    };
+    render_guild(bot, &session, &guild, Some(&notice), None).await
+}
+
+/// The guild page: now playing, mod alert settings and the guild's start services.
+/// `services_draft` keeps the user's text in the editor after a failed save.
+async fn render_guild(
+    bot: &serenity::Context,
+    session: &Session,
+    guild: &UserGuild,
+    notice: Option<&str>,
+    services_draft: Option<&str>,
+) -> Response {
+    let gid = guild.id;
+    let mut channels: Vec<(u16, ChannelId, String)> = match bot.cache.guild(gid) {
+        Some(g) => g
+            .channels
+            .values()
+            .filter(|c| c.kind == ChannelType::Text)
+            .map(|c| (c.position, c.id, c.name.clone()))
+            .collect(),
+        None => Vec::new(),
+    };
+    channels.sort();
+
+    let mut body = format!("<p><a href=\"/dashboard\">&larr; Servers</a></p><h1>{}</h1>", escape(&guild.name));
+    if let Some(notice) = notice {
+        body += &format!("<p class=\"notice\">{}</p>", escape(notice));
+    }
+
+    // Music
+    let meta = bot.data.read().await.get::<crate::TrackMetaStore>().cloned();
+    let title = match meta {
+        Some(meta) => meta.lock().await.get(&gid).map(|m| match (&m.title, &m.artist) {
+            (Some(t), Some(a)) => format!("{} — {}", t, a),
+            (Some(t), None) => t.clone(),
+            (None, Some(a)) => a.clone(),
+            _ => "Unknown track".to_string(),
+        }),
+        None => None,
+    };
+    body += "<h2>Music</h2>";
+    match player::status(bot, gid).await {
+        Some(status) => {
+            let status = if status.paused {
+                format!("Paused at {}", clock(status.position))
+            } else {
+                format!("Playing, {} in", clock(status.position))
+            };
+            body += &format!(
+                "<p><b>{}</b><br>{}</p>\
+                 <form method=\"post\" action=\"/dashboard/guilds/{gid}/music\">{csrf}\
+                 <button name=\"action\" value=\"pause\">Pause</button> \
+                 <button name=\"action\" value=\"resume\">Resume</button> \
+                 <button name=\"action\" value=\"skip\">Skip</button> \
+                 <button name=\"action\" value=\"stop\">Stop</button></form>",
+                escape(title.as_deref().unwrap_or("Unknown track")),
+                escape(&status),
+                csrf = csrf_field(session),
+            );
+        }
+        None => body += "<p>Nothing is playing.</p>",
+    }
+    let queued = queue::list(bot, gid).await;
+    if !queued.is_empty() {
+        body += "<h3>Up next</h3><ol>";
+        for (i, track) in queued.iter().enumerate() {
+            body += &format!(
+                "<li>{} <form method=\"post\" action=\"/dashboard/guilds/{gid}/queue\" style=\"display:inline\">{csrf}\
+                 <input type=\"hidden\" name=\"index\" value=\"{i}\">\
+                 <button name=\"op\" value=\"up\"{}>&uarr;</button>\
+                 <button name=\"op\" value=\"down\"{}>&darr;</button>\
+                 <button name=\"op\" value=\"remove\">Remove</button></form></li>",
+                escape(&track.query),
+                if i == 0 { " disabled" } else { "" },
+                if i + 1 == queued.len() { " disabled" } else { "" },
+                csrf = csrf_field(session),
+            );
+        }
+        body += "</ol>";
+    }
+
+    // Mod alerts
+    body += "<h2>Mod alerts</h2>";
+    if guild.owner {
+        let (enabled, message_log, channel, digest) = match bot.data.read().await.get::<ModAlertStore>().cloned() {
+            Some(store) => {
+                let s = store.lock().await;
+                (
+                    s.enabled.contains(&gid),
+                    s.message_log.contains(&gid),
+                    s.channels.get(&gid).copied(),
+                    s.digest.get(&gid).copied().unwrap_or_default(),
+                )
+            }
+            None => (false, false, None, DigestConfig::default()),
+        };
+        let options: String = std::iter::once(format!(
+            "<option value=\"\"{}>DM the server owner</option>",
+            if channel.is_none() { " selected" } else { "" }
+        ))
+        .chain(channels.iter().map(|(_, id, name)| {
+            format!(
+                "<option value=\"{}\"{}>#{}</option>",
+                id,
+                if channel == Some(*id) { " selected" } else { "" },
+                escape(name)
+            )
+        }))
+        .collect();
+        body += &format!(
+            "<form method=\"post\" action=\"/dashboard/guilds/{gid}/modalert\">{csrf}\
+             <label><input type=\"checkbox\" name=\"enabled\"{}> Mod alerts enabled</label><br>\
+             <label><input type=\"checkbox\" name=\"message_log\"{}> Log deleted messages</label><br>\
+             <label>Alert channel <select name=\"channel\">{}</select></label><br>\
+             <label>Digest after <input type=\"number\" min=\"1\" name=\"per_minute\" value=\"{}\"> alerts/minute</label>, \
+             <label>collected every <input type=\"number\" min=\"1\" name=\"window_minutes\" value=\"{}\"> minutes</label><br>\
+             <button>Save</button></form>",
+            if enabled { " checked" } else { "" },
+            if message_log { " checked" } else { "" },
+            options,
+            digest.max_per_minute,
+            (digest.window_secs / 60).max(1),
+            csrf = csrf_field(session),
+        );
+    } else {
+        body += "<p>Only the server owner can change mod alerts.</p>";
+    }
+
+    // Start services: bot owners only, since a catalog can run commands and read the bot's
+    // environment through `${VAR}`
+    body += "<h2>Start services</h2>";
+    if !crate::access::is_owner(bot, session.user_id).await {
+        body += "<p>Only the bot's owners can change start services.</p>";
+        return page(&guild.name, session, &body);
+    }
+    let services = match services_draft {
+        Some(draft) => draft.to_string(),
+        None => match config::guild_services_source(gid).await {
+            Ok(source) => source,
+            Err(e) => {
+                body += &format!("<p class=\"notice\">Couldn't read {}: {}</p>", config::config_path(), escape(&e.to_string()));
+                "{}".to_string()
+            }
+        },
+    };
+    body += &format!(
+        "<p>This server's <code>start.guilds.{gid}.services</code> catalog, in the same format as the config file. \
+         Saving replaces just this part of the config file, comments included, and reloads it.</p>\
+         <form method=\"post\" action=\"/dashboard/guilds/{gid}/services\">{csrf}\
+         <textarea name=\"services\" rows=\"20\" cols=\"90\">{}</textarea><br>\
+         <button>Save</button></form>",
+        escape(&services),
+        csrf = csrf_field(session),
+    );
+
+    page(&guild.name, session, &body)
+}
+
+fn clock(d: Duration) -> String {
+    format!("{}:{:02}", d.as_secs() / 60, d.as_secs() % 60)
+}
+
+fn csrf_field(session: &Session) -> String {
+    format!("<input type=\"hidden\" name=\"csrf\" value=\"{}\">", session.csrf)
+}
+
+fn page(title: &str, session: &Session, body: &str) -> Response {
+    Html(format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>{} · Dashboard</title>\
+         <style>body{{font-family:sans-serif;max-width:60rem;margin:2rem auto;padding:0 1rem}}\
+         .notice{{background:#eef;padding:.5rem}}textarea{{font-family:monospace;width:100%}}</style></head>\
+         <body><form method=\"post\" action=\"/dashboard/logout\" style=\"float:right\">{}{} \
+         <button>Log out</button></form>{}</body></html>",
+        escape(title),
+        csrf_field(session),
+        escape(&session.user_name),
+        body
+    ))
+    .into_response()
+}
+
+fn error_page(code: StatusCode, message: &str) -> Response {
+    let html = format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>Dashboard</title></head>\
+         <body><p>{}</p><p><a href=\"/dashboard\">Back</a></p></body></html>",
+        escape(message)
+    );
+    (code, Html(html)).into_response()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
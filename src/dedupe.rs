@@ -0,0 +1,255 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use serenity::all::{ButtonStyle, ComponentInteractionCollector, GuildId};
+use serenity::builder::{CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::embeds::Theme;
+use crate::settings::{self, SettingKey};
+use crate::{queue, Ctx, Error};
+
+/// Tracks played longer ago than this aren't duplicates any more.
+const WINDOW: Duration = Duration::from_secs(60 * 60);
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Identities remembered per query before the cache is dropped and rebuilt.
+const MAX_CACHED: usize = 1000;
+
+/// The `queue.dedupe` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    /// Ask the requester before adding a duplicate.
+    Warn,
+    /// Only DJs can add duplicates.
+    Block,
+}
+
+pub async fn mode(ctx: &Context, guild_id: GuildId) -> Mode {
+    match settings::get(ctx, guild_id, SettingKey::QueueDedupe).await.as_deref() {
+        Some("warn") => Mode::Warn,
+        Some("block") => Mode::Block,
+        _ => Mode::Off,
+    }
+}
+
+struct Played {
+    keys: Vec<String>,
+    at: Instant,
+}
+
+#[derive(Default)]
+pub struct DedupeState {
+    /// Tracks started in the last `WINDOW`, oldest first.
+    recent: HashMap<GuildId, VecDeque<Played>>,
+    /// Identities of queries already looked up, so Spotify links aren't fetched again.
+    keys: HashMap<String, Vec<String>>,
+}
+
+pub struct DedupeStore;
+impl TypeMapKey for DedupeStore {
+    type Value = Arc<Mutex<DedupeState>>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<DedupeState>>> {
+    ctx.data.read().await.get::<DedupeStore>().cloned()
+}
+
+/// The video id of a YouTube (or YouTube Music) link.
+fn youtube_id(url: &str) -> Option<&str> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let (host, path) = rest.split_once('/')?;
+    let id = if host.ends_with("youtu.be") {
+        path
+    } else if host.ends_with("youtube.com") {
+        if let Some(id) = path.strip_prefix("shorts/").or_else(|| path.strip_prefix("live/")) {
+            id
+        } else {
+            let query = path.split_once('?')?.1;
+            query.split('&').find_map(|pair| pair.strip_prefix("v="))?
+        }
+    } else {
+        return None;
+    };
+    let id = id.split(['?', '&', '#', '/']).next()?;
+    (!id.is_empty()).then_some(id)
+}
+
+/// What `query` is the same track as, without network lookups: the video id for YouTube
+/// links, the track id for Spotify links, otherwise the URL or the search text.
+fn quick_keys(query: &str) -> Vec<String> {
+    let query = query.trim();
+    if let Some(id) = youtube_id(query) {
+        return vec![format!("yt:{}", id)];
+    }
+    if let Some(id) = crate::spotify::parse_track_id(query) {
+        return vec![format!("spotify:{}", id)];
+    }
+    if query.starts_with("http://") || query.starts_with("https://") {
+        return vec![format!("url:{}", query)];
+    }
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    vec![format!("search:{}", words.join(" "))]
+}
+
+/// `quick_keys` plus, for Spotify tracks, the ISRC, so the same recording on another album
+/// or single counts too. Looked up once per query.
+async fn keys(ctx: &Context, query: &str) -> Vec<String> {
+    let store = store(ctx).await;
+    if let Some(store) = &store
+        && let Some(keys) = store.lock().await.keys.get(query.trim())
+    {
+        return keys.clone();
+    }
+    let mut keys = quick_keys(query);
+    if let Some(isrc) = crate::spotify::isrc(query.trim()).await {
+        keys.push(format!("isrc:{}", isrc));
+    }
+    if let Some(store) = &store {
+        let mut state = store.lock().await;
+        if state.keys.len() >= MAX_CACHED {
+            state.keys.clear();
+        }
+        state.keys.insert(query.trim().to_string(), keys.clone());
+    }
+    keys
+}
+
+/// A track for `query` started in `guild_id`. Only recorded while the setting is on.
+pub async fn played(ctx: &Context, guild_id: GuildId, query: &str) {
+    if mode(ctx, guild_id).await == Mode::Off {
+        return;
+    }
+    let Some(store) = store(ctx).await else { return };
+    let ctx = ctx.clone();
+    let query = query.to_string();
+    // The ISRC lookup shouldn't hold up playback
+    tokio::spawn(async move {
+        let keys = keys(&ctx, &query).await;
+        let mut state = store.lock().await;
+        let recent = state.recent.entry(guild_id).or_default();
+        while recent.front().is_some_and(|p| p.at.elapsed() > WINDOW) {
+            recent.pop_front();
+        }
+        recent.push_back(Played { keys, at: Instant::now() });
+    });
+}
+
+/// Drop plays older than `WINDOW`, and guilds left with none. Run by the janitor.
+pub async fn prune(ctx: &Context) {
+    let Some(store) = store(ctx).await else { return };
+    let mut state = store.lock().await;
+    state.recent.retain(|_, recent| {
+        recent.retain(|p| p.at.elapsed() <= WINDOW);
+        !recent.is_empty()
+    });
+}
+
+/// Why a request counts as a duplicate.
+#[derive(Debug, Clone, Copy)]
+pub enum Duplicate {
+    /// 1-based position in the queue.
+    Queued(usize),
+    /// How long ago it started.
+    Played(Duration),
+}
+
+impl Duplicate {
+    fn describe(self) -> String {
+        match self {
+            Duplicate::Queued(position) => format!("is already queued (#{})", position),
+            Duplicate::Played(ago) if ago < Duration::from_secs(60) => "was just played".to_string(),
+            Duplicate::Played(ago) => format!("was played {} minutes ago", ago.as_secs() / 60),
+        }
+    }
+}
+
+/// Whether `query` is queued or was played in the last hour in `guild_id`. Queued requests
+/// are compared by their cached identities, or `quick_keys` for ones never looked up.
+pub async fn find(ctx: &Context, guild_id: GuildId, query: &str) -> Option<Duplicate> {
+    let wanted = keys(ctx, query).await;
+    let store = store(ctx).await?;
+    let queued = queue::list(ctx, guild_id).await;
+    let state = store.lock().await;
+    let same = |keys: &[String]| keys.iter().any(|k| wanted.contains(k));
+    for (i, track) in queued.iter().enumerate() {
+        let found = match state.keys.get(track.query.trim()) {
+            Some(keys) => same(keys),
+            None => same(&quick_keys(&track.query)),
+        };
+        if found {
+            return Some(Duplicate::Queued(i + 1));
+        }
+    }
+    state
+        .recent
+        .get(&guild_id)?
+        .iter()
+        .rev()
+        .find(|p| p.at.elapsed() <= WINDOW && same(&p.keys))
+        .map(|p| Duplicate::Played(p.at.elapsed()))
+}
+
+/// Check a `music play` request against the guild's `queue.dedupe` setting. Duplicates get an
+/// "Add anyway" button: in `warn` mode the requester or a DJ can press it, in `block` mode
+/// only a DJ. Returns whether to go ahead.
+pub async fn allow(cmd: Ctx<'_>, query: &str, theme: &Theme) -> Result<bool, Error> {
+    let ctx = cmd.serenity_context();
+    let Some(guild_id) = cmd.guild_id() else { return Ok(true) };
+    let mode = mode(ctx, guild_id).await;
+    if mode == Mode::Off {
+        return Ok(true);
+    }
+    let Some(duplicate) = find(ctx, guild_id, query).await else { return Ok(true) };
+
+    let mut text = format!("**{}** {}.", query.trim(), duplicate.describe());
+    text.push_str(match mode {
+        Mode::Block => " Duplicates are blocked here; a DJ can add it anyway.",
+        _ => " Add it anyway?",
+    });
+    let embed = theme.embed().title("Duplicate").description(text);
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("dedupe:add").label("Add anyway").style(ButtonStyle::Primary),
+        CreateButton::new("dedupe:cancel").label("Cancel").style(ButtonStyle::Secondary),
+    ])];
+    let reply = cmd.send(CreateReply::default().embed(embed).components(buttons)).await?;
+    let message = reply.message().await?;
+
+    loop {
+        let press = ComponentInteractionCollector::new(cmd)
+            .message_id(message.id)
+            .timeout(PROMPT_TIMEOUT)
+            .await;
+        let Some(press) = press else {
+            let embed = theme.embed().title("Duplicate").description(format!("Not added: {}", query.trim()));
+            reply.edit(cmd, CreateReply::default().embed(embed).components(vec![])).await?;
+            return Ok(false);
+        };
+        let dj = match &press.member {
+            Some(member) => settings::is_dj(ctx, guild_id, member).await,
+            None => false,
+        };
+        let requester = press.user.id == cmd.author().id;
+        let add = press.data.custom_id == "dedupe:add";
+        let allowed = dj || (requester && (mode == Mode::Warn || !add));
+        if !allowed {
+            let notice = if requester { "Only a DJ can add duplicates here." } else { "Only the requester or a DJ can choose." };
+            let notice = CreateInteractionResponseMessage::new().content(notice).ephemeral(true);
+            press.create_response(cmd.http(), CreateInteractionResponse::Message(notice)).await?;
+            continue;
+        }
+        let text = if add {
+            format!("Added anyway by <@{}>: {}", press.user.id, query.trim())
+        } else {
+            format!("Not added: {}", query.trim())
+        };
+        let update = CreateInteractionResponseMessage::new()
+            .embed(theme.embed().title("Duplicate").description(text))
+            .components(vec![]);
+        press.create_response(cmd.http(), CreateInteractionResponse::UpdateMessage(update)).await?;
+        return Ok(add);
+    }
+}
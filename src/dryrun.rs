@@ -0,0 +1,40 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::settings::{self, SettingKey};
+
+/// Set by `--dry-run`; applies to every guild regardless of its setting.
+static FORCED: AtomicBool = AtomicBool::new(false);
+
+pub fn force(on: bool) {
+    FORCED.store(on, Ordering::Relaxed);
+}
+
+/// Whether `--dry-run` is on. File deletions (backup pruning and restores) belong to no guild, so
+/// only this turns them off.
+pub fn forced() -> bool {
+    FORCED.load(Ordering::Relaxed)
+}
+
+/// Whether destructive actions (`start` requests, moderation, message deletions) are only
+/// simulated: everywhere with `--dry-run`, or in a guild with its `dry_run` setting on.
+pub async fn active(ctx: &Context, guild_id: Option<GuildId>) -> bool {
+    if forced() {
+        return true;
+    }
+    match guild_id {
+        Some(gid) => settings::get(ctx, gid, SettingKey::DryRun).await.as_deref() == Some("on"),
+        None => false,
+    }
+}
+
+/// Log that `action` was skipped and return the line to echo to whoever asked for it.
+pub fn skip(guild_id: Option<GuildId>, action: &str) -> String {
+    match guild_id {
+        Some(gid) => tracing::warn!("[dry run] guild {}: would {}", gid, action),
+        None => tracing::warn!("[dry run] would {}", action),
+    }
+    format!("Dry run: would {}. Nothing was changed.", action)
+}
@@ -0,0 +1,152 @@
+use poise::serenity_prelude as serenity;
+use reqwest::Client;
+use serenity::all::GuildId;
+use serenity::async_trait;
+use serenity::prelude::*;
+use songbird::events::{Event, EventContext, EventHandler, TrackEvent};
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::{config, player, Ctx, Error};
+
+/// How long the music takes to fade down or back up.
+const RAMP: Duration = Duration::from_millis(400);
+const RAMP_STEPS: u32 = 8;
+/// Clips are cut off after this long, so a soundboard can't be used to play whole songs.
+const MAX_CLIP: Duration = Duration::from_secs(30);
+
+/// A guild's music while it's ducked: the volume to restore and how many clips are still
+/// playing over it.
+pub struct Ducked {
+    restore: f32,
+    clips: usize,
+}
+
+pub struct DuckStore;
+impl TypeMapKey for DuckStore {
+    type Value = Arc<Mutex<HashMap<GuildId, Ducked>>>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<HashMap<GuildId, Ducked>>>> {
+    ctx.data.read().await.get::<DuckStore>().cloned()
+}
+
+/// Fade `handle` from `from` to `to` over `RAMP`.
+async fn ramp(handle: &TrackHandle, from: f32, to: f32) {
+    for step in 1..=RAMP_STEPS {
+        let volume = from + (to - from) * step as f32 / RAMP_STEPS as f32;
+        if handle.set_volume(volume).is_err() {
+            return;
+        }
+        tokio::time::sleep(RAMP / RAMP_STEPS).await;
+    }
+}
+
+/// Fires when a clip ends to bring the music back up once no other clip is playing.
+struct ClipEnded {
+    ctx: Context,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl EventHandler for ClipEnded {
+    async fn act(&self, _: &EventContext<'_>) -> Option<Event> {
+        let (ctx, guild_id) = (self.ctx.clone(), self.guild_id);
+        tokio::spawn(async move { clip_finished(&ctx, guild_id).await });
+        None
+    }
+}
+
+async fn clip_finished(ctx: &Context, guild_id: GuildId) {
+    let Some(store) = store(ctx).await else { return };
+    let restore = {
+        let mut ducked = store.lock().await;
+        let Some(entry) = ducked.get_mut(&guild_id) else { return };
+        entry.clips = entry.clips.saturating_sub(1);
+        if entry.clips > 0 {
+            return;
+        }
+        ducked.remove(&guild_id).map(|d| d.restore)
+    };
+    if let (Some(restore), Some(music)) = (restore, player::handle(ctx, guild_id).await) {
+        let now = music.get_info().await.map(|i| i.volume).unwrap_or(restore);
+        ramp(&music, now, restore).await;
+    }
+}
+
+/// Play `input` over the guild's music (TTS, soundboard clips…), lowering the music by
+/// `music.duck_percent` while it plays and restoring it afterwards. Overlapping clips keep the
+/// music down until the last one ends.
+pub async fn play_over(ctx: &Context, guild_id: GuildId, input: songbird::input::Input) -> Result<TrackHandle, Error> {
+    let call = songbird::get(ctx)
+        .await
+        .and_then(|m| m.get(guild_id))
+        .ok_or("I'm not in a voice channel; use `music join` first.")?;
+    let clip = call.lock().await.play_input(input);
+    clip.make_playable_async().await?;
+    let stopper = clip.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(MAX_CLIP).await;
+        let _ = stopper.stop();
+    });
+
+    // Counted before `ClipEnded` is attached, so a clip that ends right away can't be
+    // subtracted before it was added
+    let music = player::handle(ctx, guild_id).await;
+    let store = store(ctx).await;
+    let restore = match (&music, &store) {
+        (Some(music), Some(store)) => {
+            let current = music.get_info().await.map(|i| i.volume).unwrap_or(1.0);
+            let mut ducked = store.lock().await;
+            let entry = ducked.entry(guild_id).or_insert(Ducked { restore: current, clips: 0 });
+            entry.clips += 1;
+            // Only the first clip fades the music down
+            (entry.clips == 1).then_some(entry.restore)
+        }
+        _ => None,
+    };
+    for event in [TrackEvent::End, TrackEvent::Error] {
+        if let Err(e) = clip.add_event(Event::Track(event), ClipEnded { ctx: ctx.clone(), guild_id }) {
+            let _ = clip.stop();
+            clip_finished(ctx, guild_id).await;
+            return Err(e.into());
+        }
+    }
+    if let (Some(restore), Some(music)) = (restore, music) {
+        let percent = config::current(ctx).await.music.duck_percent();
+        ramp(&music, restore, restore * (1.0 - percent as f32 / 100.0)).await;
+    }
+    Ok(clip)
+}
+
+/// Play a short sound over the music, which ducks while it plays
+#[poise::command(prefix_command, slash_command, guild_only, rename = "clip")]
+pub async fn music_clip(
+    ctx: Ctx<'_>,
+    #[description = "Link to the sound (anything yt-dlp can play)"] url: String,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    if !crate::settings::author_is_dj(ctx).await {
+        ctx.say("Only DJs can play clips.").await?;
+        return Ok(());
+    }
+    let url = url.trim();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        ctx.say("Give a link to the sound.").await?;
+        return Ok(());
+    }
+    let music = config::current(sctx).await.music.clone();
+    if music.player_backend == config::PlayerBackend::Lavalink {
+        ctx.say("Clips need the built-in player (`music.player_backend` \"songbird\").").await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+    let input = songbird::input::YoutubeDl::new(Client::new(), url.to_string()).user_args(music.cookie_args());
+    play_over(sctx, guild_id, input.into()).await?;
+    ctx.say(format!("Playing clip (up to {} seconds).", MAX_CLIP.as_secs())).await?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
+use serenity::prelude::*;
+
+use crate::settings::{self, SettingKey};
+
+/// Discord's cap on an embed description, in characters.
+pub const DESCRIPTION_LIMIT: usize = 4096;
+
+/// `s` cut to at most `max_chars` characters, ending in `…` if anything was cut.
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+    let mut out: String = s.chars().take(max_chars.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// How a guild's embeds look, from its `embed_*` settings.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub color: u32,
+    pub footer: Option<String>,
+    pub thumbnails: bool,
+    pub timestamp: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme { color: crate::EMBED_COLOR, footer: None, thumbnails: true, timestamp: false }
+    }
+}
+
+impl Theme {
+    /// An empty embed with the theme's color, footer and timestamp. A footer or color set
+    /// afterwards replaces the theme's.
+    pub fn embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::new().color(self.color);
+        if let Some(footer) = &self.footer {
+            embed = embed.footer(CreateEmbedFooter::new(footer));
+        }
+        if self.timestamp {
+            embed = embed.timestamp(serenity::Timestamp::now());
+        }
+        embed
+    }
+
+    /// `embed` with `url` as its thumbnail, unless the guild turned thumbnails off.
+    pub fn thumbnail(&self, embed: CreateEmbed, url: impl Into<String>) -> CreateEmbed {
+        if self.thumbnails { embed.thumbnail(url) } else { embed }
+    }
+}
+
+/// The theme for `gid`; the defaults outside guilds.
+pub async fn theme(ctx: &Context, gid: Option<GuildId>) -> Theme {
+    let Some(gid) = gid else { return Theme::default() };
+    let on = |v: Option<String>| v.as_deref() == Some("on");
+    Theme {
+        color: settings::embed_color(ctx, Some(gid)).await,
+        footer: settings::get(ctx, gid, SettingKey::EmbedFooter).await,
+        thumbnails: on(settings::get(ctx, gid, SettingKey::EmbedThumbnails).await),
+        timestamp: on(settings::get(ctx, gid, SettingKey::EmbedTimestamp).await),
+    }
+}
+
+/// An empty embed in `gid`'s theme, for one-off messages.
+pub async fn builder(ctx: &Context, gid: Option<GuildId>) -> CreateEmbed {
+    theme(ctx, gid).await.embed()
+}
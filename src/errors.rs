@@ -0,0 +1,175 @@
+use poise::serenity_prelude as serenity;
+use poise::{CreateReply, FrameworkError};
+use rand::Rng;
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
+
+use crate::telemetry::{self, Report};
+use crate::{access, audit, i18n, Ctx, Data, Error};
+
+const ERROR_COLOR: u32 = 0xED4245;
+
+/// Framework `on_error` hook: audit the failure, tell the user what happened in an
+/// ephemeral embed, and log the full error chain under an id they can quote back to us.
+pub async fn on_error(error: FrameworkError<'_, Data, Error>) {
+    let id = error_id();
+    match error {
+        FrameworkError::Setup { error, ctx, .. } => {
+            let chain = chain(error.as_ref());
+            tracing::error!(error_id = %id, chain = %chain, "framework setup failed");
+            let report = Report { kind: "framework setup failed", error_id: Some(id), source: None, detail: chain };
+            telemetry::report(ctx, report).await;
+        }
+        FrameworkError::EventHandler { error, event, ctx, .. } => {
+            let chain = chain(error.as_ref());
+            tracing::error!(error_id = %id, event = event.snake_case_name(), chain = %chain, "event handler failed");
+            let source = Some(event.snake_case_name().to_string());
+            telemetry::report(ctx, Report { kind: "event handler failed", error_id: Some(id), source, detail: chain }).await;
+        }
+        FrameworkError::Command { error, ctx, .. } => {
+            let chain = chain(error.as_ref());
+            tracing::error!(
+                error_id = %id,
+                command = %ctx.command().qualified_name,
+                invocation = %ctx.invocation_string(),
+                chain = %chain,
+                "command failed"
+            );
+            audit::record(ctx, Some(format!("[{}] {}", id, error))).await;
+            let source = Some(ctx.command().qualified_name.clone());
+            let detail = format!("{}\n\n{}", ctx.invocation_string(), chain);
+            let report = Report { kind: "command failed", error_id: Some(id.clone()), source, detail };
+            telemetry::report(ctx.serenity_context(), report).await;
+            let locale = i18n::locale(ctx).await;
+            let text = i18n::t(locale, "errors.command_failed");
+            reply(ctx, locale, "errors.command_failed_title", &text, Some(&id)).await;
+        }
+        FrameworkError::CommandPanic { payload, ctx, .. } => {
+            tracing::error!(
+                error_id = %id,
+                command = %ctx.command().qualified_name,
+                payload = payload.as_deref().unwrap_or("<non-string panic>"),
+                "command panicked"
+            );
+            audit::record(ctx, Some(format!("[{}] panic", id))).await;
+            // Reported here rather than by the panic hook, so the report carries the error id
+            let detail = telemetry::claim_panic()
+                .unwrap_or_else(|| payload.clone().unwrap_or_else(|| "<non-string panic>".to_string()));
+            let source = Some(ctx.command().qualified_name.clone());
+            let report = Report { kind: "command panicked", error_id: Some(id.clone()), source, detail };
+            telemetry::report(ctx.serenity_context(), report).await;
+            let locale = i18n::locale(ctx).await;
+            let text = i18n::t(locale, "errors.command_panicked");
+            reply(ctx, locale, "errors.command_failed_title", &text, Some(&id)).await;
+        }
+        FrameworkError::ArgumentParse { error, input, ctx, .. } => {
+            audit::record(ctx, Some(error.to_string())).await;
+            let locale = i18n::locale(ctx).await;
+            let mut text = match input {
+                Some(input) => i18n::tf(locale, "errors.unparsable", &[("input", &input), ("error", &error)]),
+                None => i18n::tf(locale, "errors.invalid_args", &[("error", &error)]),
+            };
+            if let Some(help) = &ctx.command().help_text {
+                text.push_str(&format!("\n\n{}", help));
+            }
+            reply(ctx, locale, "errors.invalid_args_title", &text, None).await;
+        }
+        FrameworkError::CommandStructureMismatch { description, ctx, .. } => {
+            tracing::warn!(command = %ctx.command.qualified_name, description, "slash command structure mismatch");
+            let ctx = poise::Context::Application(ctx);
+            let locale = i18n::locale(ctx).await;
+            let text = i18n::t(locale, "errors.outdated");
+            reply(ctx, locale, "errors.outdated_title", &text, None).await;
+        }
+        FrameworkError::CooldownHit { remaining_cooldown, ctx, .. } => {
+            let locale = i18n::locale(ctx).await;
+            let secs = format!("{:.1}", remaining_cooldown.as_secs_f32());
+            let text = i18n::tf(locale, "errors.cooldown", &[("secs", &secs)]);
+            reply(ctx, locale, "errors.cooldown_title", &text, None).await;
+        }
+        FrameworkError::MissingBotPermissions { missing_permissions, ctx, .. } => {
+            audit::record(ctx, Some(format!("bot lacks {}", missing_permissions))).await;
+            let locale = i18n::locale(ctx).await;
+            let text = i18n::tf(locale, "errors.bot_permissions", &[("permissions", &missing_permissions)]);
+            reply(ctx, locale, "errors.bot_permissions_title", &text, None).await;
+        }
+        FrameworkError::MissingUserPermissions { missing_permissions, ctx, .. } => {
+            audit::record(ctx, Some("missing user permissions".to_string())).await;
+            let locale = i18n::locale(ctx).await;
+            let text = match missing_permissions {
+                Some(p) => i18n::tf(locale, "errors.user_permissions", &[("permissions", &p)]),
+                None => i18n::t(locale, "errors.user_permissions_unknown"),
+            };
+            reply(ctx, locale, "errors.user_permissions_title", &text, None).await;
+        }
+        FrameworkError::NotAnOwner { ctx, .. } => {
+            audit::record(ctx, Some("not an owner".to_string())).await;
+            simple(ctx, "errors.owners_only").await;
+        }
+        FrameworkError::GuildOnly { ctx, .. } => {
+            simple(ctx, "errors.guild_only").await;
+        }
+        FrameworkError::DmOnly { ctx, .. } => {
+            simple(ctx, "errors.dm_only").await;
+        }
+        FrameworkError::NsfwOnly { ctx, .. } => {
+            simple(ctx, "errors.nsfw_only").await;
+        }
+        FrameworkError::CommandCheckFailed { error: Some(e), .. } if e.is::<access::Blocked>() => {}
+        FrameworkError::CommandCheckFailed { error, ctx, .. } => {
+            audit::record(ctx, Some(error.as_ref().map_or_else(|| "check failed".to_string(), |e| e.to_string()))).await;
+            let locale = i18n::locale(ctx).await;
+            let text = match error {
+                Some(e) => e.to_string(),
+                None => i18n::t(locale, "errors.check_failed"),
+            };
+            reply(ctx, locale, "errors.check_failed_title", &text, None).await;
+        }
+        FrameworkError::SubcommandRequired { ctx } => {
+            let subcommands: Vec<String> =
+                ctx.command().subcommands.iter().map(|s| format!("`{}`", s.name)).collect();
+            let locale = i18n::locale(ctx).await;
+            let text = i18n::tf(locale, "errors.subcommand", &[("subcommands", &subcommands.join(", "))]);
+            reply(ctx, locale, "errors.subcommand_title", &text, None).await;
+        }
+        // Unknown prefix commands are usually ordinary chat; stay quiet
+        FrameworkError::UnknownCommand { .. } => {}
+        other => {
+            tracing::warn!(error_id = %id, "{}", other);
+            if let Err(e) = poise::builtins::on_error(other).await {
+                tracing::error!("Error while handling error: {e:?}");
+            }
+        }
+    }
+}
+
+/// Short random id tying a user-facing error to its log line.
+fn error_id() -> String {
+    format!("{:08x}", rand::thread_rng().r#gen::<u32>())
+}
+
+/// `error` followed by each of its sources, outermost first.
+fn chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut parts = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(e) = source {
+        parts.push(e.to_string());
+        source = e.source();
+    }
+    parts.join(": caused by: ")
+}
+
+/// Reply with the `<key>_title` / `<key>` pair, for errors without details to fill in.
+async fn simple(ctx: Ctx<'_>, key: &str) {
+    let locale = i18n::locale(ctx).await;
+    reply(ctx, locale, &format!("{}_title", key), &i18n::t(locale, key), None).await;
+}
+
+async fn reply(ctx: Ctx<'_>, locale: &str, title_key: &str, text: &str, id: Option<&str>) {
+    let mut embed = CreateEmbed::new().title(i18n::t(locale, title_key)).description(text).color(ERROR_COLOR);
+    if let Some(id) = id {
+        embed = embed.footer(CreateEmbedFooter::new(i18n::tf(locale, "errors.error_id", &[("id", &id)])));
+    }
+    if let Err(e) = ctx.send(CreateReply::default().embed(embed).ephemeral(true)).await {
+        tracing::warn!("Couldn't report an error to the user: {e:?}");
+    }
+}
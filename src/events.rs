@@ -0,0 +1,97 @@
+use poise::serenity_prelude as serenity;
+use serde::Serialize;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::{player, queue};
+
+/// How often `position` events go out while something plays and someone is listening.
+const TICK: Duration = Duration::from_secs(2);
+
+/// Player changes pushed to WebSocket subscribers (see `api::events`).
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlayerEvent {
+    TrackStarted {
+        title: Option<String>,
+        artist: Option<String>,
+        thumbnail: Option<String>,
+        duration_secs: Option<u64>,
+    },
+    Paused { position_secs: u64 },
+    Resumed { position_secs: u64 },
+    Position { position_secs: u64, duration_secs: Option<u64> },
+    /// The current track finished or was stopped.
+    TrackEnded,
+    QueueChanged { queue: Vec<String> },
+}
+
+pub struct EventBus;
+impl TypeMapKey for EventBus {
+    type Value = broadcast::Sender<(GuildId, PlayerEvent)>;
+}
+
+pub fn bus() -> broadcast::Sender<(GuildId, PlayerEvent)> {
+    broadcast::channel(256).0
+}
+
+/// Publish `event` for `guild_id`. Free when nobody is subscribed.
+pub async fn emit(ctx: &Context, guild_id: GuildId, event: PlayerEvent) {
+    if let Some(tx) = ctx.data.read().await.get::<EventBus>() {
+        let _ = tx.send((guild_id, event));
+    }
+}
+
+pub async fn subscribe(ctx: &Context) -> Option<broadcast::Receiver<(GuildId, PlayerEvent)>> {
+    ctx.data.read().await.get::<EventBus>().map(|tx| tx.subscribe())
+}
+
+/// `track_started` for whatever metadata the player recorded for `guild_id`.
+pub async fn track_started(ctx: &Context, guild_id: GuildId) -> PlayerEvent {
+    let meta = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned();
+    let meta = match meta {
+        Some(meta) => meta.lock().await.get(&guild_id).cloned(),
+        None => None,
+    };
+    PlayerEvent::TrackStarted {
+        title: meta.as_ref().and_then(|m| m.title.clone()),
+        artist: meta.as_ref().and_then(|m| m.artist.clone()),
+        thumbnail: meta.as_ref().and_then(|m| m.thumbnail.clone()),
+        duration_secs: meta.and_then(|m| m.duration).map(|d| d.as_secs()),
+    }
+}
+
+pub async fn queue_changed(ctx: &Context, guild_id: GuildId) {
+    let queue = queue::list(ctx, guild_id).await.into_iter().map(|t| t.query).collect();
+    emit(ctx, guild_id, PlayerEvent::QueueChanged { queue }).await;
+}
+
+/// Send `position` ticks for every playing guild while anyone is subscribed.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(TICK).await;
+            let (tx, meta) = {
+                let data = ctx.data.read().await;
+                (data.get::<EventBus>().cloned(), data.get::<crate::TrackMetaStore>().cloned())
+            };
+            let Some(tx) = tx else { continue };
+            if tx.receiver_count() == 0 {
+                continue;
+            }
+            for gid in player::playing_guilds(&ctx).await {
+                let Some(status) = player::status(&ctx, gid).await else { continue };
+                if status.paused {
+                    continue;
+                }
+                let duration_secs = match &meta {
+                    Some(meta) => meta.lock().await.get(&gid).and_then(|m| m.duration).map(|d| d.as_secs()),
+                    None => None,
+                };
+                let _ = tx.send((gid, PlayerEvent::Position { position_secs: status.position.as_secs(), duration_secs }));
+            }
+        }
+    });
+}
@@ -0,0 +1,201 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use serde_json::json;
+use serenity::all::{GuildId, UserId};
+use serenity::prelude::*;
+
+use crate::{embeds, player, storage, Ctx, Error};
+
+/// Favorites a user can keep; the oldest has to be removed to add more.
+const MAX_FAVORITES: i64 = 200;
+const PAGE_SIZE: usize = 10;
+
+/// A bookmarked track.
+#[derive(Debug, Clone)]
+pub struct Favorite {
+    pub query: String,
+    pub title: String,
+}
+
+/// What `add` did.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Added {
+    Saved(String),
+    AlreadySaved(String),
+    Full,
+    NothingPlaying,
+}
+
+/// Bookmark the track `guild_id` is playing for `user`.
+pub async fn add(ctx: &Context, user: UserId, guild_id: GuildId) -> Result<Added, Error> {
+    if player::status(ctx, guild_id).await.is_none() {
+        return Ok(Added::NothingPlaying);
+    }
+    let Some((query, title)) = crate::music::replayable(ctx, guild_id).await else { return Ok(Added::NothingPlaying) };
+    let db = storage::db(ctx).await?;
+    let uid = user.get() as i64;
+    let now = serenity::Timestamp::now().unix_timestamp();
+    let saved = title.clone();
+    let outcome = db
+        .call(move |conn| {
+            let count: i64 = conn.query_row("SELECT COUNT(*) FROM favorites WHERE user_id = ?1", [uid], |r| r.get(0))?;
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM favorites WHERE user_id = ?1 AND query = ?2)",
+                rusqlite::params![uid, query],
+                |r| r.get(0),
+            )?;
+            if exists {
+                return Ok(Added::AlreadySaved(saved));
+            }
+            if count >= MAX_FAVORITES {
+                return Ok(Added::Full);
+            }
+            conn.execute(
+                "INSERT INTO favorites (user_id, query, title, added_at) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![uid, query, saved, now],
+            )?;
+            Ok(Added::Saved(saved))
+        })
+        .await?;
+    Ok(outcome)
+}
+
+/// `user`'s favorites, newest first; `music favorites play` and `remove` number them this way.
+pub async fn list(ctx: &Context, user: UserId) -> Result<Vec<Favorite>, Error> {
+    let db = storage::db(ctx).await?;
+    let uid = user.get() as i64;
+    let favorites = db
+        .call(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT query, title FROM favorites WHERE user_id = ?1 ORDER BY added_at DESC, rowid DESC")?;
+            let rows = stmt.query_map([uid], |row| Ok(Favorite { query: row.get(0)?, title: row.get(1)? }))?;
+            rows.collect()
+        })
+        .await?;
+    Ok(favorites)
+}
+
+/// Favorites for `data export`.
+pub async fn export_user(ctx: &Context, user: UserId) -> Result<serde_json::Value, Error> {
+    let favorites: Vec<serde_json::Value> =
+        list(ctx, user).await?.into_iter().map(|f| json!({ "query": f.query, "title": f.title })).collect();
+    Ok(json!(favorites))
+}
+
+/// The favorite numbered `number` in `list`, or a message saying why there isn't one.
+async fn nth(ctx: Ctx<'_>, number: usize) -> Result<Result<Favorite, String>, Error> {
+    let favorites = list(ctx.serenity_context(), ctx.author().id).await?;
+    if favorites.is_empty() {
+        return Ok(Err("You have no favorites yet; press ❤️ Favorite on the control panel while a track plays.".to_string()));
+    }
+    match number.checked_sub(1).and_then(|i| favorites.get(i)) {
+        Some(favorite) => Ok(Ok(favorite.clone())),
+        None => Ok(Err(format!("Pick a number from 1 to {}; see `music favorites list`.", favorites.len()))),
+    }
+}
+
+async fn say_private(ctx: Ctx<'_>, text: impl Into<String>) -> Result<(), Error> {
+    ctx.send(CreateReply::default().content(text).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Favorites matching what's typed so far, labelled with their number and title; the value
+/// sent is the number.
+async fn autocomplete_favorite(ctx: Ctx<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let Ok(favorites) = list(ctx.serenity_context(), ctx.author().id).await else { return Vec::new() };
+    let partial = partial.trim().to_lowercase();
+    favorites
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (i + 1, f))
+        .filter(|(n, f)| partial.is_empty() || n.to_string().starts_with(&partial) || f.title.to_lowercase().contains(&partial))
+        .take(25)
+        .map(|(n, f)| {
+            let label: String = format!("{}. {}", n, f.title).chars().take(100).collect();
+            serenity::AutocompleteChoice::new(label, n)
+        })
+        .collect()
+}
+
+/// Tracks you bookmarked with the control panel's Favorite button
+#[poise::command(
+    prefix_command,
+    slash_command,
+    rename = "favorites",
+    subcommands("favorites_list", "favorites_play", "favorites_remove")
+)]
+pub async fn music_favorites(ctx: Ctx<'_>) -> Result<(), Error> {
+    show(ctx, 1).await
+}
+
+async fn show(ctx: Ctx<'_>, page: usize) -> Result<(), Error> {
+    let favorites = list(ctx.serenity_context(), ctx.author().id).await?;
+    if favorites.is_empty() {
+        return say_private(ctx, "You have no favorites yet; press ❤️ Favorite on the control panel while a track plays.").await;
+    }
+    let pages = favorites.len().div_ceil(PAGE_SIZE);
+    let page = page.clamp(1, pages);
+    let description = favorites
+        .iter()
+        .enumerate()
+        .skip((page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(i, f)| format!("`{}.` {}", i + 1, f.title))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let embed = embeds::theme(ctx.serenity_context(), ctx.guild_id())
+        .await
+        .embed()
+        .title("Your favorites")
+        .description(description)
+        .footer(serenity::CreateEmbedFooter::new(format!(
+            "Page {}/{} · music favorites play <number>",
+            page, pages
+        )));
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// List your favorite tracks
+#[poise::command(prefix_command, slash_command, rename = "list")]
+pub async fn favorites_list(ctx: Ctx<'_>, #[description = "Page (default 1)"] page: Option<usize>) -> Result<(), Error> {
+    show(ctx, page.unwrap_or(1)).await
+}
+
+/// Play one of your favorites
+#[poise::command(prefix_command, slash_command, guild_only, rename = "play")]
+pub async fn favorites_play(
+    ctx: Ctx<'_>,
+    #[description = "Number from `music favorites list`"]
+    #[autocomplete = "autocomplete_favorite"]
+    number: usize,
+) -> Result<(), Error> {
+    let favorite = match nth(ctx, number).await? {
+        Ok(favorite) => favorite,
+        Err(why) => return say_private(ctx, why).await,
+    };
+    crate::defer_music(ctx).await?;
+    crate::music::handle_music(ctx, None, &format!("play {}", favorite.query)).await?;
+    crate::finish_deferred(ctx).await;
+    Ok(())
+}
+
+/// Remove one of your favorites
+#[poise::command(prefix_command, slash_command, rename = "remove")]
+pub async fn favorites_remove(
+    ctx: Ctx<'_>,
+    #[description = "Number from `music favorites list`"]
+    #[autocomplete = "autocomplete_favorite"]
+    number: usize,
+) -> Result<(), Error> {
+    let favorite = match nth(ctx, number).await? {
+        Ok(favorite) => favorite,
+        Err(why) => return say_private(ctx, why).await,
+    };
+    let db = storage::db(ctx.serenity_context()).await?;
+    let uid = ctx.author().id.get() as i64;
+    let query = favorite.query.clone();
+    db.call(move |conn| conn.execute("DELETE FROM favorites WHERE user_id = ?1 AND query = ?2", rusqlite::params![uid, query]))
+        .await?;
+    say_private(ctx, format!("Removed {} from your favorites.", favorite.title)).await
+}
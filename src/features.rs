@@ -0,0 +1,133 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+
+use crate::settings::{self, SettingKey};
+use crate::{i18n, registration, Ctx, Data, Error};
+
+type Command = poise::Command<Data, Error>;
+
+/// A subsystem a guild can switch off as a whole with its `features.*` setting. Each one is a
+/// help category, so the commands `main` puts in that category belong to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Feature {
+    #[name = "music"]
+    Music,
+    #[name = "moderation"]
+    Moderation,
+    #[name = "start"]
+    Start,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Feature::Music, Feature::Moderation, Feature::Start];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Music => "music",
+            Feature::Moderation => "moderation",
+            Feature::Start => "start",
+        }
+    }
+
+    pub fn setting(self) -> SettingKey {
+        match self {
+            Feature::Music => SettingKey::FeaturesMusic,
+            Feature::Moderation => SettingKey::FeaturesModeration,
+            Feature::Start => SettingKey::FeaturesStart,
+        }
+    }
+
+    fn category(self) -> &'static str {
+        match self {
+            Feature::Music => "Music",
+            Feature::Moderation => "Moderation",
+            Feature::Start => "Services",
+        }
+    }
+
+    /// The feature a top-level command belongs to; `None` for commands that are always there.
+    pub fn of(command: &Command) -> Option<Feature> {
+        let category = command.category.as_deref()?;
+        Feature::ALL.into_iter().find(|f| f.category() == category)
+    }
+}
+
+pub async fn enabled(ctx: &Context, gid: GuildId, feature: Feature) -> bool {
+    settings::get(ctx, gid, feature.setting()).await.as_deref() != Some("off")
+}
+
+/// Whether `command` (top-level) is available in `gid`.
+pub async fn allows(ctx: &Context, gid: GuildId, command: &Command) -> bool {
+    match Feature::of(command) {
+        Some(feature) => enabled(ctx, gid, feature).await,
+        None => true,
+    }
+}
+
+/// The top-level commands to register in `gid`, without those of disabled features.
+pub async fn guild_commands<'a>(ctx: &Context, gid: GuildId, commands: &'a [Command]) -> Vec<&'a Command> {
+    let mut kept = Vec::with_capacity(commands.len());
+    for command in commands {
+        if allows(ctx, gid, command).await {
+            kept.push(command);
+        }
+    }
+    kept
+}
+
+/// Part of the framework `command_check`: reject commands of a feature the guild switched off.
+/// Per-guild registration hides them, but text commands and stale slash commands still arrive,
+/// and with global registration they stay listed everywhere, which the reply explains.
+pub async fn check(ctx: Ctx<'_>) -> Result<(), Error> {
+    let Some(gid) = ctx.guild_id() else { return Ok(()) };
+    let root = ctx.parent_commands().first().copied().unwrap_or(ctx.command());
+    let Some(feature) = Feature::of(root) else { return Ok(()) };
+    let sctx = ctx.serenity_context();
+    if enabled(sctx, gid, feature).await {
+        return Ok(());
+    }
+    let locale = i18n::locale(ctx).await;
+    let mut text = i18n::tf(locale, "features.disabled", &[("feature", &feature.name())]);
+    if !registration::per_guild(sctx, gid).await {
+        text.push(' ');
+        text.push_str(&i18n::t(locale, "features.still_listed"));
+    }
+    Err(text.into())
+}
+
+/// Store a feature switch and re-register the guild's commands to match. Used by
+/// `settings set` and `admin feature`.
+pub async fn set(ctx: &Context, commands: &[Command], gid: GuildId, feature: Feature, on: bool) -> Result<(), Error> {
+    let value = if on { "on" } else { "off" };
+    settings::set(ctx, gid, feature.setting(), value.to_string()).await?;
+    resync(ctx, commands, gid).await;
+    Ok(())
+}
+
+/// Re-register `gid`'s commands after a `features.*` change.
+pub async fn resync(ctx: &Context, commands: &[Command], gid: GuildId) {
+    if let Err(e) = registration::sync_guild(ctx, commands, gid).await {
+        tracing::warn!("Failed to re-register commands in guild {} after a feature change: {e:?}", gid);
+    }
+}
+
+/// Switch a feature on or off in any server
+#[poise::command(prefix_command, slash_command, owners_only, rename = "feature")]
+pub async fn admin_feature(
+    ctx: Ctx<'_>,
+    #[description = "Server ID"] guild: String,
+    #[description = "Feature"] feature: Feature,
+    #[description = "Whether it's available there"] enabled: bool,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let gid = crate::access::parse_guild(&guild)?;
+    set(ctx.serenity_context(), &ctx.framework().options().commands, gid, feature, enabled).await?;
+    let state = if enabled { "on" } else { "off" };
+    let mut reply = format!("`features.{}` is now `{}` in server {}.", feature.name(), state, gid);
+    if !enabled && !registration::per_guild(ctx.serenity_context(), gid).await {
+        reply.push_str(" Its commands stay listed there (`registration.mode` is `global`), but they're refused when used.");
+    }
+    ctx.say(reply).await?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId, UserId, VoiceState};
+use serenity::prelude::*;
+use std::collections::HashMap;
+
+use crate::settings::{self, SettingKey};
+use crate::{Ctx, Error};
+
+/// The guild's AFK voice channel, if it has one.
+pub fn afk_channel(ctx: &Context, guild_id: GuildId) -> Option<ChannelId> {
+    ctx.cache.guild(guild_id)?.afk_metadata.as_ref().map(|afk| afk.afk_channel_id)
+}
+
+async fn enabled(ctx: &Context, guild_id: GuildId) -> bool {
+    settings::get(ctx, guild_id, SettingKey::MusicFollow).await.as_deref() == Some("on")
+}
+
+/// Members other than bots in `channel`, from the cache.
+fn humans_in(ctx: &Context, guild_id: GuildId, channel: ChannelId) -> usize {
+    let Some(guild) = ctx.cache.guild(guild_id) else { return 0 };
+    guild
+        .voice_states
+        .values()
+        .filter(|v| v.channel_id == Some(channel) && v.member.as_ref().is_none_or(|m| !m.user.bot))
+        .count()
+}
+
+/// The non-AFK voice channel holding the most people with tracks in the queue.
+async fn requesters_channel(ctx: &Context, guild_id: GuildId, afk: Option<ChannelId>) -> Option<ChannelId> {
+    let requesters: Vec<UserId> = crate::queue::list(ctx, guild_id).await.into_iter().filter_map(|t| t.requested_by).collect();
+    let guild = ctx.cache.guild(guild_id)?;
+    let mut counts: HashMap<ChannelId, usize> = HashMap::new();
+    for user in requesters {
+        if let Some(channel) = guild.voice_states.get(&user).and_then(|v| v.channel_id)
+            && Some(channel) != afk
+        {
+            *counts.entry(channel).or_default() += 1;
+        }
+    }
+    counts.into_iter().max_by_key(|(_, n)| *n).map(|(channel, _)| channel)
+}
+
+async fn move_to(ctx: &Context, guild_id: GuildId, channel: ChannelId) {
+    tracing::warn!("music.follow: moving to {} in guild {}", channel, guild_id);
+    if let Err(e) = crate::music::connect(ctx, guild_id, channel).await {
+        tracing::warn!("music.follow: failed to move to {} in guild {}: {e:?}", channel, guild_id);
+    }
+}
+
+/// With `music.follow` on, keep the bot with its listeners: out of the AFK channel when it's
+/// moved there, and after them when its own channel empties. Called on every voice state update.
+pub async fn on_voice_state(ctx: &Context, old: Option<&VoiceState>, new: &VoiceState) {
+    let Some(guild_id) = new.guild_id else { return };
+    // Only sessions the bot is actually in
+    if songbird::get(ctx).await.and_then(|m| m.get(guild_id)).is_none() || !enabled(ctx, guild_id).await {
+        return;
+    }
+    let me = ctx.cache.current_user().id;
+    let afk = afk_channel(ctx, guild_id);
+    let old_channel = old.and_then(|v| v.channel_id);
+
+    if new.user_id == me {
+        if new.channel_id.is_none() || new.channel_id != afk {
+            return;
+        }
+        // Moved to AFK: back to where the listeners still are, else to the requesters
+        let target = match old_channel {
+            Some(c) if Some(c) != afk && humans_in(ctx, guild_id, c) > 0 => Some(c),
+            _ => requesters_channel(ctx, guild_id, afk).await,
+        };
+        match target {
+            Some(channel) => move_to(ctx, guild_id, channel).await,
+            None => tracing::warn!("music.follow: moved to the AFK channel in guild {} with no listeners to follow", guild_id),
+        }
+        return;
+    }
+
+    if new.member.as_ref().is_some_and(|m| m.user.bot) {
+        return;
+    }
+    let Some(target) = new.channel_id.filter(|c| Some(*c) != afk) else { return };
+    let current = ctx.cache.guild(guild_id).and_then(|g| g.voice_states.get(&me).and_then(|v| v.channel_id));
+    if current == Some(target) {
+        return;
+    }
+    // Follow a listener who left, or a requester, once nobody's left with the bot
+    let was_listening = old_channel.is_some() && old_channel == current;
+    let is_requester = crate::queue::list(ctx, guild_id).await.iter().any(|t| t.requested_by == Some(new.user_id));
+    let abandoned = current.is_none_or(|c| Some(c) == afk || humans_in(ctx, guild_id, c) == 0);
+    if (was_listening || is_requester) && abandoned {
+        move_to(ctx, guild_id, target).await;
+    }
+}
+
+/// Follow the listeners to other voice channels, never into the AFK channel (DJs)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "follow")]
+pub async fn music_follow(
+    ctx: Ctx<'_>,
+    #[description = "on or off; leave empty to show the current mode"] mode: Option<String>,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let Some(mode) = mode else {
+        let current = if enabled(sctx, guild_id).await { "on" } else { "off" };
+        ctx.say(format!("Follow mode is `{}`. Options: `on`, `off`.", current)).await?;
+        return Ok(());
+    };
+    if !settings::author_is_dj(ctx).await {
+        ctx.say("Only DJs can change follow mode.").await?;
+        return Ok(());
+    }
+    let locale = crate::i18n::locale(ctx).await;
+    let value = match SettingKey::MusicFollow.parse(None, &mode, locale) {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+    settings::set(sctx, guild_id, SettingKey::MusicFollow, value.clone()).await?;
+    let reply = if value == "on" {
+        "Follow mode is `on`: the bot moves to wherever its listeners go and leaves the AFK channel."
+    } else {
+        "Follow mode is `off`."
+    };
+    ctx.say(reply).await?;
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use serenity::all::{ButtonStyle, ComponentInteractionCollector, Permissions};
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage,
+};
+use std::time::Duration;
+
+use crate::{embeds, i18n, permissions, Ctx, Data, Error};
+
+type Command = poise::Command<Data, Error>;
+
+/// Help overview sections, in order. Commands are put in one by `categorize` in `main`.
+pub const CATEGORIES: [&str; 4] = ["Music", "Moderation", "Services", "Utility"];
+
+/// Commands listed per page of a category.
+const PAGE_SIZE: usize = 10;
+
+/// The navigation buttons stop working after this long without a press.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Usage examples shown on a command's detail page, by qualified name.
+const EXAMPLES: &[(&str, &[&str])] = &[
+    ("music join", &["/music join", "/music join channel:#Music"]),
+    ("music play", &["/music play query:never gonna give you up", "/music play query:https://youtu.be/dQw4w9WgXcQ"]),
+    ("start", &["/start service:list", "/start service:mc", "/start service:mc action:status"]),
+    ("timeout", &["/timeout user:@someone duration:10m reason:spam"]),
+    ("tempban", &["/tempban user:@someone duration:2d"]),
+    ("purge", &["/purge count:20"]),
+    ("settings set", &["/settings set key:embed_color value:#FF8800", "/settings set key:locale value:de"]),
+    ("help", &["/help", "/help command:music play"]),
+];
+
+/// Put every command in `category`, which the help overview groups by.
+pub fn categorize(category: &str, mut commands: Vec<Command>) -> Vec<Command> {
+    for command in &mut commands {
+        command.category = Some(category.to_string());
+    }
+    commands
+}
+
+/// Show help for all commands or a specific one
+#[poise::command(prefix_command, slash_command)]
+pub async fn help(
+    ctx: Ctx<'_>,
+    #[description = "Specific command to show help for"]
+    #[rest]
+    command: Option<String>,
+) -> Result<(), Error> {
+    let locale = i18n::locale(ctx).await;
+    let theme = embeds::theme(ctx.serenity_context(), ctx.guild_id()).await;
+    match command.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        Some(query) => detail(ctx, locale, &theme, query).await,
+        None => overview(ctx, locale, &theme).await,
+    }
+}
+
+/// Whether the invoking user could run `command` here, so help doesn't advertise the rest.
+async fn can_run(ctx: Ctx<'_>, command: &Command, permissions: Option<Permissions>) -> bool {
+    if command.hide_in_help {
+        return false;
+    }
+    if command.owners_only && !ctx.framework().options().owners.contains(&ctx.author().id) {
+        return false;
+    }
+    let in_guild = ctx.guild_id().is_some();
+    if (command.guild_only && !in_guild) || (command.dm_only && in_guild) {
+        return false;
+    }
+    if let Some(gid) = ctx.guild_id()
+        && !crate::features::allows(ctx.serenity_context(), gid, command).await
+    {
+        return false;
+    }
+    let needed = command.required_permissions | command.default_member_permissions;
+    needed.is_empty() || permissions.is_some_and(|p| p.contains(needed))
+}
+
+fn description(command: &Command, locale: &str) -> String {
+    command
+        .description_localizations
+        .get(locale)
+        .or(command.description.as_ref())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// `/name` lines for the visible commands of each category, in `CATEGORIES` order.
+async fn sections(ctx: Ctx<'_>, locale: &str) -> Vec<(&'static str, Vec<String>)> {
+    let permissions = permissions::author_permissions(ctx).await;
+    let mut sections: Vec<(&'static str, Vec<String>)> = CATEGORIES.iter().map(|c| (*c, Vec::new())).collect();
+    for command in &ctx.framework().options().commands {
+        if !can_run(ctx, command, permissions).await {
+            continue;
+        }
+        let category = command.category.as_deref().unwrap_or("Utility");
+        let Some((_, lines)) = sections.iter_mut().find(|(c, _)| *c == category) else { continue };
+        let mut line = format!("`/{}` — {}", command.name, description(command, locale));
+        if !command.subcommands.is_empty() {
+            let subs: Vec<&str> = command.subcommands.iter().map(|s| s.name.as_str()).collect();
+            line.push_str(&format!(" ({})", subs.join(", ")));
+        }
+        lines.push(line);
+    }
+    sections.retain(|(_, lines)| !lines.is_empty());
+    sections
+}
+
+fn category_name(locale: &str, category: &str) -> String {
+    i18n::t(locale, &format!("help.categories.{}", category.to_ascii_lowercase()))
+}
+
+/// Page 0 is the overview; each category follows in `PAGE_SIZE` chunks.
+fn pages(locale: &str, theme: &embeds::Theme, sections: &[(&str, Vec<String>)]) -> Vec<CreateEmbed> {
+    let summary: Vec<String> = sections
+        .iter()
+        .map(|(category, lines)| {
+            i18n::tf(locale, "help.category_line", &[("category", &category_name(locale, category)), ("count", &lines.len())])
+        })
+        .collect();
+    let mut pages = vec![theme
+        .embed()
+        .title(i18n::t(locale, "help.title"))
+        .description(format!("{}\n\n{}", i18n::t(locale, "help.intro"), summary.join("\n")))];
+    for (category, lines) in sections {
+        let chunks: Vec<&[String]> = lines.chunks(PAGE_SIZE).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut title = format!("{} — {}", i18n::t(locale, "help.title"), category_name(locale, category));
+            if chunks.len() > 1 {
+                title.push_str(&format!(" ({}/{})", i + 1, chunks.len()));
+            }
+            pages.push(theme.embed().title(title).description(chunk.join("\n")));
+        }
+    }
+    let total = pages.len();
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, page)| {
+            page.footer(CreateEmbedFooter::new(i18n::tf(locale, "help.page", &[("page", &(i + 1)), ("total", &total)])))
+        })
+        .collect()
+}
+
+fn nav_buttons(locale: &str, page: usize, total: usize, enabled: bool) -> Vec<CreateActionRow> {
+    let button = |id: &str, key: &str, disabled: bool| {
+        CreateButton::new(id).label(i18n::t(locale, key)).style(ButtonStyle::Secondary).disabled(!enabled || disabled)
+    };
+    vec![CreateActionRow::Buttons(vec![
+        button("help:prev", "help.prev", page == 0),
+        button("help:home", "help.home", page == 0),
+        button("help:next", "help.next", page + 1 >= total),
+    ])]
+}
+
+/// Paginated overview. Only the invoking user can page; the buttons go dead after
+/// `IDLE_TIMEOUT` without a press.
+async fn overview(ctx: Ctx<'_>, locale: &str, theme: &embeds::Theme) -> Result<(), Error> {
+    let sections = sections(ctx, locale).await;
+    let pages = pages(locale, theme, &sections);
+    let total = pages.len();
+    let mut page = 0;
+    let mut reply = CreateReply::default().embed(pages[page].clone()).ephemeral(true);
+    if total > 1 {
+        reply = reply.components(nav_buttons(locale, page, total, true));
+    }
+    let handle = ctx.send(reply).await?;
+    if total == 1 {
+        return Ok(());
+    }
+    let message = handle.message().await?;
+
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .message_id(message.id)
+        .timeout(IDLE_TIMEOUT)
+        .await
+    {
+        if press.user.id != ctx.author().id {
+            let notice = CreateInteractionResponseMessage::new().content(i18n::t(locale, "help.not_yours")).ephemeral(true);
+            press.create_response(ctx.http(), CreateInteractionResponse::Message(notice)).await?;
+            continue;
+        }
+        page = match press.data.custom_id.as_str() {
+            "help:prev" => page.saturating_sub(1),
+            "help:next" => (page + 1).min(total - 1),
+            _ => 0,
+        };
+        let update = CreateInteractionResponseMessage::new()
+            .embed(pages[page].clone())
+            .components(nav_buttons(locale, page, total, true));
+        press.create_response(ctx.http(), CreateInteractionResponse::UpdateMessage(update)).await?;
+    }
+
+    let expired = CreateReply::default().embed(pages[page].clone()).components(nav_buttons(locale, page, total, false));
+    handle.edit(ctx, expired).await?;
+    Ok(())
+}
+
+/// Find a command by its (space-separated) qualified name, matching names and aliases.
+fn find<'a>(commands: &'a [Command], query: &str) -> Option<&'a Command> {
+    let query = query.trim_start_matches('/');
+    let mut words = query.split_whitespace();
+    let first = words.next()?;
+    let matches = |c: &&Command, word: &str| {
+        c.name.eq_ignore_ascii_case(word) || c.aliases.iter().any(|a| a.eq_ignore_ascii_case(word))
+    };
+    let mut command = commands.iter().find(|c| matches(c, first))?;
+    for word in words {
+        command = command.subcommands.iter().find(|c| matches(c, word))?;
+    }
+    Some(command)
+}
+
+/// One command's page: description, usage, options, subcommands, examples and the
+/// permissions it needs.
+async fn detail(ctx: Ctx<'_>, locale: &str, theme: &embeds::Theme, query: &str) -> Result<(), Error> {
+    let commands = &ctx.framework().options().commands;
+    let permissions = permissions::author_permissions(ctx).await;
+    let command = match find(commands, query) {
+        Some(c) if can_run(ctx, c, permissions).await => c,
+        _ => {
+            let text = i18n::tf(locale, "help.unknown", &[("command", &query)]);
+            ctx.send(CreateReply::default().content(text).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+
+    let mut text = description(command, locale);
+    if let Some(help_text) = &command.help_text {
+        text.push_str(&format!("\n\n{}", help_text));
+    }
+    let mut embed = theme.embed().title(format!("/{}", command.qualified_name)).description(text);
+
+    let mut usage = format!("/{}", command.qualified_name);
+    for param in &command.parameters {
+        usage.push_str(&if param.required { format!(" <{}>", param.name) } else { format!(" [{}]", param.name) });
+    }
+    embed = embed.field(i18n::t(locale, "help.usage"), format!("`{}`", usage), false);
+
+    if !command.parameters.is_empty() {
+        let options: Vec<String> = command
+            .parameters
+            .iter()
+            .map(|p| {
+                let text = p.description_localizations.get(locale).or(p.description.as_ref()).cloned().unwrap_or_default();
+                let required = if p.required { "" } else { " *(optional)*" };
+                format!("`{}`{} — {}", p.name, required, text)
+            })
+            .collect();
+        embed = embed.field(i18n::t(locale, "help.options"), options.join("\n"), false);
+    }
+
+    if !command.subcommands.is_empty() {
+        let mut subs = Vec::new();
+        for sub in &command.subcommands {
+            if can_run(ctx, sub, permissions).await {
+                subs.push(format!("`/{}` — {}", sub.qualified_name, description(sub, locale)));
+            }
+        }
+        if !subs.is_empty() {
+            embed = embed.field(i18n::t(locale, "help.subcommands"), subs.join("\n"), false);
+        }
+    }
+
+    if let Some((_, examples)) = EXAMPLES.iter().find(|(name, _)| *name == command.qualified_name) {
+        let examples: Vec<String> = examples.iter().map(|e| format!("`{}`", e)).collect();
+        embed = embed.field(i18n::t(locale, "help.examples"), examples.join("\n"), false);
+    }
+
+    let needed = command.required_permissions | command.default_member_permissions;
+    if !needed.is_empty() {
+        embed = embed.field(i18n::t(locale, "help.permissions"), needed.to_string(), true);
+    }
+    if let Some(category) = &command.category {
+        embed = embed.field(i18n::t(locale, "help.category"), category_name(locale, category), true);
+    }
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![help()]
+}
@@ -0,0 +1,159 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use crate::settings::{self, SettingKey};
+use crate::{Ctx, Data, Error};
+
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// Bundled translations as (Discord locale code, TOML source). The first is the fallback.
+const SOURCES: [(&str, &str); 2] = [
+    ("en-US", include_str!("../locales/en-US.toml")),
+    ("de", include_str!("../locales/de.toml")),
+];
+
+/// Flattened `section.key` → text, per locale.
+static CATALOG: LazyLock<HashMap<&'static str, HashMap<String, String>>> = LazyLock::new(|| {
+    SOURCES
+        .iter()
+        .map(|(locale, source)| {
+            let table: toml::Table =
+                toml::from_str(source).unwrap_or_else(|e| panic!("locales/{}.toml is invalid: {e}", locale));
+            let mut strings = HashMap::new();
+            flatten("", &table, &mut strings);
+            (*locale, strings)
+        })
+        .collect()
+});
+
+fn flatten(prefix: &str, table: &toml::Table, out: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::String(s) => {
+                out.insert(path, s.clone());
+            }
+            toml::Value::Table(t) => flatten(&path, t, out),
+            _ => {}
+        }
+    }
+}
+
+pub fn supported() -> impl Iterator<Item = &'static str> {
+    SOURCES.iter().map(|(locale, _)| *locale)
+}
+
+/// The bundled locale for a Discord locale code: an exact match, else the same language
+/// (`de-AT` → `de`, `en-GB` → `en-US`).
+pub fn resolve(code: &str) -> Option<&'static str> {
+    let language = code.split('-').next().unwrap_or(code);
+    supported()
+        .find(|l| l.eq_ignore_ascii_case(code))
+        .or_else(|| supported().find(|l| l.split('-').next().is_some_and(|ll| ll.eq_ignore_ascii_case(language))))
+}
+
+/// `key` in `locale`, falling back to English and then to the key itself.
+pub fn t(locale: &str, key: &str) -> String {
+    CATALOG
+        .get(locale)
+        .and_then(|s| s.get(key))
+        .or_else(|| CATALOG.get(DEFAULT_LOCALE).and_then(|s| s.get(key)))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// `t` with `{name}` placeholders filled from `args`.
+pub fn tf(locale: &str, key: &str, args: &[(&str, &(dyn std::fmt::Display + Sync))]) -> String {
+    let mut text = t(locale, key);
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// Reply language for a guild: the `locale` setting, or the server's Discord language when
+/// it's `auto`.
+pub async fn guild_locale(ctx: &Context, gid: GuildId) -> &'static str {
+    let chosen = settings::get(ctx, gid, SettingKey::Locale).await;
+    if let Some(locale) = chosen.as_deref().filter(|v| *v != "auto").and_then(resolve) {
+        return locale;
+    }
+    let preferred = ctx.cache.guild(gid).map(|g| g.preferred_locale.clone());
+    preferred.as_deref().and_then(resolve).unwrap_or(DEFAULT_LOCALE)
+}
+
+/// Reply language for a command: the guild's, or the user's client language in DMs.
+pub async fn locale(ctx: Ctx<'_>) -> &'static str {
+    match ctx.guild_id() {
+        Some(gid) => guild_locale(ctx.serenity_context(), gid).await,
+        None => ctx.locale().and_then(resolve).unwrap_or(DEFAULT_LOCALE),
+    }
+}
+
+/// Fill in slash command name/description localizations from the `commands.*` keys of every
+/// non-default locale, before the commands are registered.
+pub fn localize_commands(commands: &mut [poise::Command<Data, Error>]) {
+    for command in commands {
+        localize(command, "commands");
+    }
+}
+
+fn localize(command: &mut poise::Command<Data, Error>, parent: &str) {
+    let path = format!("{}.{}", parent, command.name);
+    for (locale, strings) in CATALOG.iter().filter(|(l, _)| **l != DEFAULT_LOCALE) {
+        let locale = locale.to_string();
+        if let Some(name) = strings.get(&format!("{}.name", path)) {
+            command.name_localizations.insert(locale.clone(), name.clone());
+        }
+        if let Some(description) = strings.get(&format!("{}.description", path)) {
+            command.description_localizations.insert(locale.clone(), description.clone());
+        }
+        for param in &mut command.parameters {
+            let param_path = format!("{}.params.{}", path, param.name);
+            if let Some(name) = strings.get(&format!("{}.name", param_path)) {
+                param.name_localizations.insert(locale.clone(), name.clone());
+            }
+            if let Some(description) = strings.get(&format!("{}.description", param_path)) {
+                param.description_localizations.insert(locale.clone(), description.clone());
+            }
+        }
+    }
+    for sub in &mut command.subcommands {
+        localize(sub, &path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Reply keys of a locale file; `commands.*` only exists in translations, since English
+    /// command names come from the code.
+    fn keys(source: &str) -> BTreeSet<String> {
+        let table: toml::Table = toml::from_str(source).unwrap();
+        let mut strings = HashMap::new();
+        flatten("", &table, &mut strings);
+        strings.into_keys().filter(|k| !k.starts_with("commands.")).collect()
+    }
+
+    #[test]
+    fn every_locale_has_the_english_keys() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("locales");
+        let english = keys(SOURCES[0].1);
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let locale = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let source = std::fs::read_to_string(&path).unwrap();
+            assert!(toml::from_str::<toml::Table>(&source).is_ok(), "locales/{locale}.toml doesn't parse");
+            assert!(supported().any(|l| l == locale), "locales/{locale}.toml isn't in SOURCES");
+            let found = keys(&source);
+            let missing: Vec<_> = english.difference(&found).collect();
+            let extra: Vec<_> = found.difference(&english).collect();
+            assert!(missing.is_empty() && extra.is_empty(), "locales/{locale}.toml is missing {missing:?} and has unknown {extra:?}");
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Only the Spotify source looks recordings up by ISRC on the built-in player
+#![cfg_attr(not(feature = "spotify"), allow(dead_code))]
+
+use serde::Deserialize;
+use std::time::Duration;
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// A candidate whose length is further than this from the Spotify track's is a different version.
+const DURATION_TOLERANCE: Duration = Duration::from_secs(3);
+/// YouTube Music results checked for a matching length.
+const CANDIDATES: usize = 5;
+
+#[derive(Deserialize)]
+struct IsrcLookup {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Deserialize)]
+struct Recording {
+    #[serde(default)]
+    relations: Vec<Relation>,
+}
+
+#[derive(Deserialize)]
+struct Relation {
+    url: Option<RelationUrl>,
+}
+
+#[derive(Deserialize)]
+struct RelationUrl {
+    resource: String,
+}
+
+/// A YouTube URL for exactly the recording `isrc` identifies, so a Spotify track doesn't
+/// end up as a live version, cover or nightcore edit the way a "title artist" search can.
+/// MusicBrainz links recordings to official uploads; failing that, YouTube Music is searched
+/// for the ISRC (its catalog uploads carry it) and a result of the right length is taken.
+pub async fn youtube_match(isrc: &str, duration: Option<Duration>, extra_args: &[String]) -> Option<String> {
+    if let Some(url) = official_upload(isrc).await {
+        return Some(url);
+    }
+    youtube_music(isrc, duration, extra_args).await
+}
+
+/// The YouTube upload MusicBrainz links to the recording, if it knows one. Needs no yt-dlp,
+/// so the Lavalink backend uses this alone.
+pub async fn official_upload(isrc: &str) -> Option<String> {
+    // MusicBrainz rejects requests without an identifying User-Agent
+    let agent = format!("{}/{} (Discord music bot)", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let client = reqwest::Client::builder().timeout(LOOKUP_TIMEOUT).user_agent(agent).build().ok()?;
+    let lookup: IsrcLookup = client
+        .get(format!("https://musicbrainz.org/ws/2/isrc/{}", isrc))
+        .query(&[("inc", "url-rels"), ("fmt", "json")])
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    lookup
+        .recordings
+        .iter()
+        .flat_map(|r| &r.relations)
+        .filter_map(|rel| rel.url.as_ref())
+        .map(|u| u.resource.clone())
+        .find(|u| u.contains("youtube.com/watch") || u.contains("youtu.be/"))
+}
+
+async fn youtube_music(isrc: &str, duration: Option<Duration>, extra_args: &[String]) -> Option<String> {
+    let search = format!("https://music.youtube.com/search?q={}#songs", isrc);
+    let out = tokio::process::Command::new("yt-dlp")
+        .kill_on_drop(true)
+        .args(extra_args)
+        .args(["--flat-playlist", "--playlist-items", &format!("1:{}", CANDIDATES), "-j", &search])
+        .output()
+        .await
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|entry| {
+            let length = entry.get("duration").and_then(|d| d.as_f64()).map(Duration::from_secs_f64);
+            match (duration, length) {
+                (Some(want), Some(got)) => want.abs_diff(got) <= DURATION_TOLERANCE,
+                // Without lengths to compare, trust the ISRC search's top hit
+                _ => true,
+            }
+        })
+        .and_then(|entry| {
+            let id = entry.get("id")?.as_str()?;
+            Some(format!("https://www.youtube.com/watch?v={}", id))
+        })
+}
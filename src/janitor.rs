@@ -0,0 +1,85 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::player;
+
+const SWEEP_EVERY: Duration = Duration::from_secs(60);
+
+/// Guilds with an entry in the per-guild store `K`.
+async fn guilds<K, V>(ctx: &Context) -> Vec<GuildId>
+where
+    K: TypeMapKey<Value = Arc<Mutex<HashMap<GuildId, V>>>>,
+    V: Send + Sync + 'static,
+{
+    let Some(store) = ctx.data.read().await.get::<K>().cloned() else { return Vec::new() };
+    store.lock().await.keys().copied().collect()
+}
+
+/// Every guild some music store still holds state for.
+async fn tracked(ctx: &Context) -> HashSet<GuildId> {
+    let mut all = HashSet::new();
+    all.extend(guilds::<crate::TrackStore, _>(ctx).await);
+    all.extend(guilds::<crate::TrackMetaStore, _>(ctx).await);
+    all.extend(guilds::<crate::SessionThreadStore, _>(ctx).await);
+    all.extend(guilds::<crate::music::ChildStore, _>(ctx).await);
+    all.extend(guilds::<crate::queue::QueueStore, _>(ctx).await);
+    all.extend(guilds::<crate::ambient::AmbientStore, _>(ctx).await);
+    all.extend(guilds::<crate::live::LiveStore, _>(ctx).await);
+    all.extend(guilds::<crate::positions::PositionStore, _>(ctx).await);
+    all
+}
+
+/// Whether the guild's call has nothing to do: no track, nothing resolving, nothing queued.
+async fn idle(ctx: &Context, guild_id: GuildId) -> bool {
+    player::status(ctx, guild_id).await.is_none()
+        && !crate::music::resolving(ctx, guild_id).await
+        && crate::queue::list(ctx, guild_id).await.is_empty()
+}
+
+/// One pass: forget state for guilds without a call, and leave calls that have been idle for
+/// `music.idle_timeout_secs`. `idle_since` carries when each call was first seen idle.
+async fn sweep(ctx: &Context, idle_since: &mut HashMap<GuildId, Instant>) {
+    let Some(manager) = songbird::get(ctx).await else { return };
+    let calls: HashSet<GuildId> = manager.iter().map(|(gid, _)| GuildId::new(gid.0.get())).collect();
+
+    // Kicked, disconnected, or left without `music leave`
+    for guild_id in tracked(ctx).await.difference(&calls) {
+        crate::music::end_session(ctx, *guild_id).await;
+    }
+    crate::dedupe::prune(ctx).await;
+    crate::music::prune_children(ctx).await;
+
+    idle_since.retain(|gid, _| calls.contains(gid));
+    let Some(timeout) = crate::config::current(ctx).await.music.idle_timeout() else { return };
+    for guild_id in calls {
+        if !idle(ctx, guild_id).await {
+            idle_since.remove(&guild_id);
+            continue;
+        }
+        let since = *idle_since.entry(guild_id).or_insert_with(Instant::now);
+        if since.elapsed() < timeout {
+            continue;
+        }
+        idle_since.remove(&guild_id);
+        crate::music::end_session(ctx, guild_id).await;
+        if let Err(e) = manager.remove(guild_id).await {
+            tracing::warn!("Failed to leave idle call in guild {}: {e:?}", guild_id);
+        }
+    }
+}
+
+/// Sweep every `SWEEP_EVERY` for the life of the bot.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        let mut idle_since = HashMap::new();
+        loop {
+            tokio::time::sleep(SWEEP_EVERY).await;
+            sweep(&ctx, &mut idle_since).await;
+        }
+    });
+}
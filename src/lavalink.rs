@@ -0,0 +1,304 @@
+use poise::serenity_prelude as serenity;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use serenity::all::GuildId;
+use serenity::futures::StreamExt;
+use serenity::prelude::*;
+use songbird::ConnectionInfo;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::config::LavalinkConfig;
+use crate::Error;
+
+/// Lavalink's unity volume; songbird's 0.20 default maps to 20.
+pub const DEFAULT_VOLUME: u16 = 20;
+
+/// A resolved track as Lavalink describes it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LavaTrack {
+    pub encoded: String,
+    pub info: TrackInfo,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackInfo {
+    pub title: String,
+    pub author: String,
+    /// Milliseconds.
+    pub length: u64,
+    #[serde(default)]
+    pub is_stream: bool,
+    #[serde(default)]
+    pub artwork_url: Option<String>,
+    #[serde(default)]
+    pub uri: Option<String>,
+}
+
+/// What the node last told us about one guild's player.
+#[derive(Debug, Clone)]
+pub struct LavaPlayer {
+    pub track: Option<LavaTrack>,
+    pub paused: bool,
+    pub volume: u16,
+    position: Duration,
+    /// When `position` was reported, to extrapolate between the node's 5 s updates.
+    updated: Instant,
+    /// Round trip between the node and Discord's voice server; `None` until it's connected.
+    pub voice_ping: Option<Duration>,
+}
+
+impl Default for LavaPlayer {
+    fn default() -> Self {
+        LavaPlayer {
+            track: None,
+            paused: false,
+            volume: DEFAULT_VOLUME,
+            position: Duration::ZERO,
+            updated: Instant::now(),
+            voice_ping: None,
+        }
+    }
+}
+
+impl LavaPlayer {
+    pub fn position(&self) -> Duration {
+        if self.paused || self.track.is_none() {
+            self.position
+        } else {
+            self.position + self.updated.elapsed()
+        }
+    }
+}
+
+/// One Lavalink node: REST calls plus the state its WebSocket reports.
+pub struct Node {
+    cfg: LavalinkConfig,
+    http: reqwest::Client,
+    session: RwLock<Option<String>>,
+    players: Mutex<HashMap<GuildId, LavaPlayer>>,
+}
+
+pub struct LavalinkStore;
+impl TypeMapKey for LavalinkStore {
+    type Value = Arc<Node>;
+}
+
+/// The node when `music.player_backend` is `lavalink`, else `None` (songbird plays).
+pub async fn node(ctx: &Context) -> Option<Arc<Node>> {
+    ctx.data.read().await.get::<LavalinkStore>().cloned()
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "loadType", content = "data", rename_all = "camelCase")]
+enum LoadResult {
+    Track(LavaTrack),
+    Playlist { tracks: Vec<LavaTrack> },
+    Search(Vec<LavaTrack>),
+    Empty,
+    Error { message: Option<String> },
+}
+
+impl Node {
+    pub fn new(cfg: LavalinkConfig) -> Self {
+        Node { cfg, http: reqwest::Client::new(), session: RwLock::new(None), players: Mutex::new(HashMap::new()) }
+    }
+
+    fn rest(&self, path: &str) -> String {
+        let scheme = if self.cfg.secure { "https" } else { "http" };
+        format!("{}://{}/v4{}", scheme, self.cfg.host, path)
+    }
+
+    async fn player_url(&self, guild_id: GuildId) -> Result<String, Error> {
+        let session = self.session.read().await.clone().ok_or("Lavalink node is not connected")?;
+        Ok(self.rest(&format!("/sessions/{}/players/{}", session, guild_id)))
+    }
+
+    async fn update(&self, guild_id: GuildId, body: Value) -> Result<(), Error> {
+        let url = self.player_url(guild_id).await?;
+        let resp = self.http.patch(url).header("Authorization", &self.cfg.password).json(&body).send().await?;
+        if !resp.status().is_success() {
+            return Err(format!("Lavalink returned {}: {}", resp.status(), resp.text().await.unwrap_or_default()).into());
+        }
+        Ok(())
+    }
+
+    /// Resolve `identifier` (a URL, or `ytsearch:...`) to its first track.
+    pub async fn load(&self, identifier: &str) -> Result<Option<LavaTrack>, Error> {
+        let result: LoadResult = self
+            .http
+            .get(self.rest("/loadtracks"))
+            .query(&[("identifier", identifier)])
+            .header("Authorization", &self.cfg.password)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(match result {
+            LoadResult::Track(track) => Some(track),
+            LoadResult::Playlist { tracks } | LoadResult::Search(tracks) => tracks.into_iter().next(),
+            LoadResult::Empty => None,
+            LoadResult::Error { message } => {
+                return Err(format!("Lavalink couldn't load it: {}", message.unwrap_or_default()).into());
+            }
+        })
+    }
+
+    /// Hand the voice connection songbird negotiated over to the node.
+    pub async fn connect_voice(&self, guild_id: GuildId, info: &ConnectionInfo) -> Result<(), Error> {
+        let voice = json!({ "token": info.token, "endpoint": info.endpoint, "sessionId": info.session_id });
+        self.update(guild_id, json!({ "voice": voice })).await
+    }
+
+    pub async fn play(&self, guild_id: GuildId, track: LavaTrack) -> Result<(), Error> {
+        let volume = self.players.lock().await.get(&guild_id).map(|p| p.volume).unwrap_or(DEFAULT_VOLUME);
+        self.update(guild_id, json!({ "track": { "encoded": track.encoded }, "paused": false, "volume": volume }))
+            .await?;
+        let mut players = self.players.lock().await;
+        let player = players.entry(guild_id).or_default();
+        player.track = Some(track);
+        player.paused = false;
+        player.position = Duration::ZERO;
+        player.updated = Instant::now();
+        Ok(())
+    }
+
+    pub async fn set_paused(&self, guild_id: GuildId, paused: bool) -> Result<(), Error> {
+        self.update(guild_id, json!({ "paused": paused })).await?;
+        let mut players = self.players.lock().await;
+        let player = players.entry(guild_id).or_default();
+        player.position = player.position();
+        player.updated = Instant::now();
+        player.paused = paused;
+        Ok(())
+    }
+
+    /// Stop the current track; the node answers with a `TrackEndEvent`.
+    pub async fn stop(&self, guild_id: GuildId) -> Result<(), Error> {
+        self.update(guild_id, json!({ "track": { "encoded": null } })).await
+    }
+
+    pub async fn seek(&self, guild_id: GuildId, position: Duration) -> Result<(), Error> {
+        self.update(guild_id, json!({ "position": position.as_millis() as u64 })).await?;
+        let mut players = self.players.lock().await;
+        let player = players.entry(guild_id).or_default();
+        player.position = position;
+        player.updated = Instant::now();
+        Ok(())
+    }
+
+    /// `volume` on songbird's scale (1.0 = unchanged).
+    pub async fn set_volume(&self, guild_id: GuildId, volume: f32) -> Result<(), Error> {
+        let volume = (volume * 100.0).round().clamp(0.0, 1000.0) as u16;
+        self.update(guild_id, json!({ "volume": volume })).await?;
+        self.players.lock().await.entry(guild_id).or_default().volume = volume;
+        Ok(())
+    }
+
+    /// Drop the guild's player, e.g. when the bot leaves voice.
+    pub async fn destroy(&self, guild_id: GuildId) -> Result<(), Error> {
+        self.players.lock().await.remove(&guild_id);
+        let url = self.player_url(guild_id).await?;
+        self.http.delete(url).header("Authorization", &self.cfg.password).send().await?;
+        Ok(())
+    }
+
+    pub async fn player(&self, guild_id: GuildId) -> Option<LavaPlayer> {
+        self.players.lock().await.get(&guild_id).cloned()
+    }
+
+    pub async fn playing_guilds(&self) -> Vec<GuildId> {
+        self.players.lock().await.iter().filter(|(_, p)| p.track.is_some()).map(|(g, _)| *g).collect()
+    }
+}
+
+/// Keep a WebSocket to the node open, reconnecting after drops. Track ends advance the queue.
+pub fn start(ctx: Context, node: Arc<Node>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = run(&ctx, &node).await {
+                tracing::warn!("Lavalink connection lost: {e:?}");
+            }
+            *node.session.write().await = None;
+            // The node drops its players with the session
+            node.players.lock().await.clear();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+}
+
+async fn run(ctx: &Context, node: &Arc<Node>) -> Result<(), Error> {
+    let scheme = if node.cfg.secure { "wss" } else { "ws" };
+    let mut request = format!("{}://{}/v4/websocket", scheme, node.cfg.host).into_client_request()?;
+    let headers = request.headers_mut();
+    headers.insert("Authorization", node.cfg.password.parse()?);
+    headers.insert("User-Id", ctx.cache.current_user().id.to_string().parse()?);
+    headers.insert("Client-Name", concat!("discord/", env!("CARGO_PKG_VERSION")).parse()?);
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+
+    while let Some(message) = socket.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let Ok(payload) = serde_json::from_str::<Value>(&text) else { continue };
+        handle(ctx, node, &payload).await;
+    }
+    Ok(())
+}
+
+async fn handle(ctx: &Context, node: &Arc<Node>, payload: &Value) {
+    let guild_id = payload["guildId"].as_str().and_then(|g| g.parse::<u64>().ok()).map(GuildId::new);
+    match payload["op"].as_str() {
+        Some("ready") => {
+            let session = payload["sessionId"].as_str().map(str::to_string);
+            tracing::info!("Lavalink node {} ready", node.cfg.host);
+            *node.session.write().await = session;
+        }
+        Some("playerUpdate") => {
+            let Some(gid) = guild_id else { return };
+            let position = Duration::from_millis(payload["state"]["position"].as_u64().unwrap_or(0));
+            let mut players = node.players.lock().await;
+            if let Some(player) = players.get_mut(&gid) {
+                player.position = position;
+                player.updated = Instant::now();
+                // -1 while the node has no voice connection
+                player.voice_ping = payload["state"]["ping"].as_u64().map(Duration::from_millis);
+            }
+        }
+        Some("event") => {
+            let Some(gid) = guild_id else { return };
+            match payload["type"].as_str() {
+                Some("TrackEndEvent") => {
+                    // `replaced` means a newer track already took over
+                    if payload["reason"].as_str() == Some("replaced") {
+                        return;
+                    }
+                    if let Some(player) = node.players.lock().await.get_mut(&gid) {
+                        player.track = None;
+                    }
+                    crate::presence::stopped(ctx, gid).await;
+                    // Starting the next track talks to the node again; don't block the socket on it
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move { crate::queue::track_finished(&ctx, gid).await });
+                }
+                Some("TrackExceptionEvent") => {
+                    tracing::warn!("Lavalink track error in {}: {}", gid, payload["exception"]["message"]);
+                }
+                Some("TrackStuckEvent") => tracing::warn!("Lavalink track stuck in {}", gid),
+                Some("WebSocketClosedEvent") => {
+                    tracing::warn!("Lavalink voice connection closed in {}: {}", gid, payload["reason"]);
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
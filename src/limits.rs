@@ -0,0 +1,78 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use std::time::Duration;
+
+use crate::positions::clock;
+use crate::settings::{self, SettingKey};
+
+/// What the length and livestream limits look at, from yt-dlp, Lavalink or the Spotify API.
+#[derive(Debug, Clone, Default)]
+pub struct Facts {
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+    pub is_live: bool,
+}
+
+/// A guild's `max_track_length` and `allow_livestreams` settings.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    max_length: Option<Duration>,
+    allow_livestreams: bool,
+}
+
+pub async fn get(ctx: &Context, guild_id: GuildId) -> Limits {
+    let max_length = settings::get(ctx, guild_id, SettingKey::MaxTrackLength)
+        .await
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let allow_livestreams = settings::get(ctx, guild_id, SettingKey::AllowLivestreams).await.as_deref() != Some("off");
+    Limits { max_length, allow_livestreams }
+}
+
+impl Limits {
+    /// Whether anything is limited, i.e. whether looking a track up first is worth it.
+    pub fn active(&self) -> bool {
+        self.max_length.is_some() || !self.allow_livestreams
+    }
+
+    /// Why a track with these facts can't be played here, if it can't.
+    pub fn violation(&self, facts: &Facts) -> Option<String> {
+        let name = facts.title.as_deref().map(|t| format!("**{}**", t)).unwrap_or_else(|| "That track".to_string());
+        if facts.is_live {
+            return (!self.allow_livestreams)
+                .then(|| format!("{} is a livestream, and livestreams are turned off on this server. A DJ can still play it.", name));
+        }
+        match (self.max_length, facts.duration) {
+            (Some(max), Some(length)) if length > max => Some(format!(
+                "{} is {} long; this server allows tracks up to {}. A DJ can still play it.",
+                name,
+                clock(length),
+                clock(max)
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Look `target` (a URL or `ytsearch1:` query) up with yt-dlp without downloading it. `None`
+/// if yt-dlp fails, in which case the limits aren't applied.
+pub async fn probe(target: &str, extra_args: &[String]) -> Option<Facts> {
+    let out = tokio::process::Command::new("yt-dlp")
+        .kill_on_drop(true)
+        .args(extra_args)
+        .args(["--no-playlist", "--skip-download", "--print", "%(is_live)j\t%(duration)j\t%(title)s", target])
+        .output()
+        .await
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let mut fields = stdout.lines().next()?.splitn(3, '\t');
+    let is_live = fields.next()? == "true";
+    let duration = fields.next()?.parse::<f64>().ok().filter(|d| *d > 0.0).map(Duration::from_secs_f64);
+    let title = fields.next().map(|t| t.trim().to_string()).filter(|t| !t.is_empty() && t != "NA");
+    Some(Facts { title, duration, is_live })
+}
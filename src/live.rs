@@ -0,0 +1,180 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId};
+use serenity::builder::CreateMessage;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::{embeds, Error};
+
+/// A stream that ends sooner than this after (re)starting counts as a failed attempt.
+const MIN_RUN: Duration = Duration::from_secs(30);
+/// Failed attempts in a row before the stream is given up on.
+const MAX_FAILURES: u32 = 3;
+/// ffmpeg gives up on a read after this long without data, so a stalled HLS feed ends the
+/// track (and gets reconnected) instead of hanging silently.
+const STALL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The livestream a guild is playing. While set, the track ending reconnects it instead of
+/// advancing the queue.
+#[derive(Debug, Clone)]
+pub struct Live {
+    /// The page URL; the HLS URL behind it expires, so it's looked up again on reconnect.
+    url: String,
+    title: Option<String>,
+    channel: ChannelId,
+    /// When the stream first started, for the elapsed time shown in the control panel.
+    since: Instant,
+    started: Instant,
+    failures: u32,
+}
+
+pub struct LiveStore;
+impl TypeMapKey for LiveStore {
+    type Value = Arc<Mutex<HashMap<GuildId, Live>>>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<HashMap<GuildId, Live>>>> {
+    ctx.data.read().await.get::<LiveStore>().cloned()
+}
+
+/// How long the guild's livestream has been playing, across reconnects.
+pub async fn elapsed(ctx: &Context, guild_id: GuildId) -> Option<Duration> {
+    let store = store(ctx).await?;
+    store.lock().await.get(&guild_id).map(|l| l.since.elapsed())
+}
+
+/// Forget the guild's livestream so its track ending doesn't reconnect it. Called on skip
+/// and leave.
+pub async fn stop(ctx: &Context, guild_id: GuildId) -> bool {
+    match store(ctx).await {
+        Some(store) => store.lock().await.remove(&guild_id).is_some(),
+        None => false,
+    }
+}
+
+/// The HLS URL yt-dlp currently gives for `url`.
+async fn manifest_url(ctx: &Context, url: &str) -> Result<String, Error> {
+    let cookie_args = crate::config::current(ctx).await.music.cookie_args();
+    let out = tokio::process::Command::new("yt-dlp")
+        .kill_on_drop(true)
+        .args(&cookie_args)
+        .args(["--no-playlist", "-g", "-f", "bestaudio/best", url])
+        .output()
+        .await?;
+    if !out.status.success() {
+        return Err(format!("yt-dlp couldn't find the stream: {}", String::from_utf8_lossy(&out.stderr).trim()).into());
+    }
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .next()
+        .map(str::to_string)
+        .ok_or_else(|| "yt-dlp returned no stream URL".into())
+}
+
+/// Play `url` on `call` through ffmpeg, which reads the HLS feed, reconnects dropped
+/// requests, and exits once the feed stalls for `STALL_TIMEOUT`.
+async fn play_on(
+    ctx: &Context,
+    call: &mut songbird::Call,
+    guild_id: GuildId,
+    url: &str,
+    title: Option<String>,
+) -> Result<(), Error> {
+    let manifest = manifest_url(ctx, url).await?;
+    let quality = crate::quality::get(ctx, guild_id).await;
+    let mut ffmpeg = std::process::Command::new("ffmpeg");
+    ffmpeg
+        .args(["-nostdin", "-hide_banner", "-loglevel", "error"])
+        .args(["-reconnect", "1", "-reconnect_streamed", "1", "-reconnect_delay_max", "5"])
+        .args(["-rw_timeout", &STALL_TIMEOUT.as_micros().to_string()])
+        .args(["-i", &manifest])
+        .arg("-vn")
+        .args(quality.ffmpeg_output())
+        .arg("pipe:1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let child = crate::music::spawn_child(ctx, guild_id, &mut ffmpeg).await?;
+
+    let input: songbird::input::Input = songbird::input::ChildContainer::from(child).into();
+    let handle = call.play_input(input);
+    handle.make_playable_async().await?;
+    let _ = handle.set_volume(0.20);
+    if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
+        let mut metas = ms.lock().await;
+        let meta = metas.entry(guild_id).or_default();
+        meta.title = title.or(meta.title.take());
+        meta.duration = None;
+        meta.chapters.clear();
+        meta.is_live = true;
+    }
+    let _ = crate::music::store_handle(ctx, guild_id, handle).await;
+    Ok(())
+}
+
+/// Start the livestream at `url` on the guild's call, which the caller holds.
+pub async fn start(
+    ctx: &Context,
+    call: &mut songbird::Call,
+    guild_id: GuildId,
+    channel: ChannelId,
+    url: &str,
+    title: Option<String>,
+) -> Result<(), Error> {
+    play_on(ctx, call, guild_id, url, title.clone()).await?;
+    if let Some(store) = store(ctx).await {
+        let now = Instant::now();
+        let live = Live { url: url.to_string(), title, channel, since: now, started: now, failures: 0 };
+        store.lock().await.insert(guild_id, live);
+    }
+    Ok(())
+}
+
+/// Called when the guild's track ends. Reconnects its livestream if one is set; returns false
+/// (and lets the queue advance) if none is or it keeps failing.
+pub async fn resume(ctx: &Context, guild_id: GuildId) -> bool {
+    let Some(store) = store(ctx).await else { return false };
+    let Some(mut live) = store.lock().await.get(&guild_id).cloned() else { return false };
+    let call = songbird::get(ctx).await.and_then(|m| m.get(guild_id));
+    let Some(call) = call else {
+        store.lock().await.remove(&guild_id);
+        return false;
+    };
+
+    // A stream that ended right after (re)connecting counts as a failed attempt
+    if live.started.elapsed() < MIN_RUN {
+        live.failures += 1;
+    } else {
+        live.failures = 0;
+    }
+    while live.failures < MAX_FAILURES {
+        tracing::warn!("Livestream {} in guild {} ended; reconnecting", live.url, guild_id);
+        match play_on(ctx, &mut *call.lock().await, guild_id, &live.url, live.title.clone()).await {
+            Ok(()) => {
+                live.started = Instant::now();
+                if let Some(current) = store.lock().await.get_mut(&guild_id) {
+                    *current = live;
+                }
+                return true;
+            }
+            Err(e) => {
+                tracing::warn!("Livestream reconnect failed: {e:?}");
+                live.failures += 1;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+        // Skipped or left while reconnecting
+        if !store.lock().await.contains_key(&guild_id) {
+            return false;
+        }
+    }
+
+    store.lock().await.remove(&guild_id);
+    let theme = embeds::theme(ctx, Some(guild_id)).await;
+    let embed = theme.embed().title("Music").description(format!("Lost the livestream {} and couldn't reconnect.", live.url));
+    let _ = live.channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await;
+    false
+}
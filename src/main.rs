@@ -1,24 +1,86 @@
 use poise::serenity_prelude as serenity;
-use serenity::builder::{
-    CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage,
-};
-use serenity::model::id::{GuildId, UserId};
+use serenity::builder::CreateMessage;
+use serenity::model::id::GuildId;
 use serenity::prelude::*;
 use songbird::SerenityInit;
+use clap::Parser;
 use dotenvy::dotenv;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 mod music;
+mod queue;
+mod sources;
+mod chapters;
+mod podcasts;
+mod isrc;
+mod spotify;
+mod spotify_helper;
+mod dedupe;
+mod limits;
+mod live;
+mod janitor;
+mod quality;
+mod follow;
+mod duck;
+mod upnext;
+mod positions;
+mod ambient;
+mod quiz;
+mod party;
+mod scrobble;
+mod favorites;
+mod player;
+mod lavalink;
+mod events;
 mod start;
 mod config;
 mod modalert;
+mod dryrun;
+mod automod;
+mod antiraid;
+mod scheduler;
+mod reminders;
+mod tags;
+mod welcome;
+mod roles;
+mod starboard;
+mod storage;
+mod backup;
+mod verify;
+mod settings;
+mod setup;
+mod onboarding;
+mod botinfo;
+mod ping;
+mod admin;
+mod access;
+mod features;
+mod registration;
+mod presence;
+mod preferences;
+mod privacy;
+mod audit;
+mod errors;
+mod telemetry;
+mod components;
+mod i18n;
+mod embeds;
+mod help;
+mod permissions;
+mod web;
+mod api;
+mod dashboard;
+mod moderation;
+mod warnings;
 
 use crate::config::ensure_default_config;
 use crate::modalert::{
-    ensure_modalert_store, is_modalert_enabled, save_modalert_store, ModAlertStore,
+    ensure_modalert_store, is_modalert_enabled, log_deleted_messages, save_modalert_store,
+    send_alert, AlertKind, AlertLimiterStore, DigestConfig, ModAlertStore, MESSAGE_CACHE_SIZE,
 };
 use crate::music::{ensure_media_tools, handle_music};
 use crate::start::handle_start;
@@ -44,35 +106,76 @@ pub struct TrackMeta {
     pub artist: Option<String>,
     pub duration: Option<std::time::Duration>,
     pub thumbnail: Option<String>,
+    /// From yt-dlp, for videos that have them; empty otherwise.
+    pub chapters: Vec<chapters::Chapter>,
+    /// A livestream: no duration, and the control panel shows elapsed time instead.
+    pub is_live: bool,
+    /// The page the track resolved to, when the player was told; plays the same track again
+    /// where a search might not.
+    pub url: Option<String>,
 }
 struct TrackMetaStore;
 impl TypeMapKey for TrackMetaStore {
     type Value = Arc<Mutex<HashMap<GuildId, TrackMeta>>>;
 }
 
+/// The thread each voice session posts to when the guild's `music_thread` setting is on.
+struct SessionThreadStore;
+impl TypeMapKey for SessionThreadStore {
+    type Value = Arc<Mutex<HashMap<GuildId, serenity::ChannelId>>>;
+}
+
 // ---------- Commands ----------
-#[poise::command(prefix_command, slash_command)]
-async fn ping(ctx: Ctx<'_>) -> Result<(), Error> {
-    ctx.say("Pong!").await?;
-    Ok(())
+/// The channel a thread (or forum post) hangs off, if `channel_id` is one the cache knows.
+fn thread_parent(ctx: &serenity::Context, guild_id: GuildId, channel_id: serenity::ChannelId) -> Option<serenity::ChannelId> {
+    let guild = ctx.cache.guild(guild_id)?;
+    guild.threads.iter().find(|t| t.id == channel_id).and_then(|t| t.parent_id)
 }
 
-#[poise::command(prefix_command, slash_command)]
-async fn help(
-    ctx: Ctx<'_>,
-    #[description = "Specific command to show help for"] command: Option<String>,
-) -> Result<(), Error> {
-    poise::builtins::help(
-        ctx,
-        command.as_deref(),
-        poise::builtins::HelpConfiguration::default(),
-    )
-    .await?;
-    Ok(())
+async fn is_guild_owner(ctx: Ctx<'_>, guild_id: GuildId) -> bool {
+    let sctx = ctx.serenity_context();
+    if let Some(g) = sctx.cache.guild(guild_id) {
+        g.owner_id == ctx.author().id
+    } else if let Ok(pg) = guild_id.to_partial_guild(&sctx.http).await {
+        pg.owner_id == ctx.author().id
+    } else {
+        false
+    }
 }
 
-#[poise::command(prefix_command, slash_command)]
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("modalert_config", "modalert_toggle", "modalert_channel", "modalert_messagelog", "modalert_digest")
+)]
 async fn modalert(ctx: Ctx<'_>) -> Result<(), Error> {
+    configure_modalert(ctx).await
+}
+
+/// Choose alert events, the alert channel and digest thresholds
+#[poise::command(prefix_command, slash_command, rename = "config")]
+async fn modalert_config(ctx: Ctx<'_>) -> Result<(), Error> {
+    configure_modalert(ctx).await
+}
+
+async fn configure_modalert(ctx: Ctx<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("This command can only be used in a server.").await?;
+        return Ok(());
+    };
+    if !is_guild_owner(ctx, guild_id).await {
+        ctx.say("Only the server owner can change mod alert settings.").await?;
+        return Ok(());
+    }
+    modalert::config_panel(ctx, guild_id).await
+}
+
+#[poise::command(prefix_command, slash_command, rename = "toggle")]
+async fn modalert_toggle(ctx: Ctx<'_>) -> Result<(), Error> {
+    toggle_modalert(ctx).await
+}
+
+async fn toggle_modalert(ctx: Ctx<'_>) -> Result<(), Error> {
     ctx.defer().await?;
     let sctx = ctx.serenity_context();
     let guild_id = match ctx.guild_id() {
@@ -84,17 +187,7 @@ async fn modalert(ctx: Ctx<'_>) -> Result<(), Error> {
     };
 
     // Only server owner can toggle
-    let is_owner = {
-        if let Some(g) = sctx.cache.guild(guild_id) {
-            g.owner_id == ctx.author().id
-        } else if let Ok(pg) = guild_id.to_partial_guild(&sctx.http).await {
-            pg.owner_id == ctx.author().id
-        } else {
-            false
-        }
-    };
-
-    if !is_owner {
+    if !is_guild_owner(ctx, guild_id).await {
         ctx.say("Only the server owner can toggle mod alerts.").await?;
         return Ok(());
     }
@@ -102,12 +195,12 @@ async fn modalert(ctx: Ctx<'_>) -> Result<(), Error> {
     let toggled_on = {
         let data = sctx.data.read().await;
         if let Some(store) = data.get::<ModAlertStore>() {
-            let mut set = store.lock().await;
-            if set.contains(&guild_id) {
-                set.remove(&guild_id);
+            let mut settings = store.lock().await;
+            if settings.enabled.contains(&guild_id) {
+                settings.enabled.remove(&guild_id);
                 false
             } else {
-                set.insert(guild_id);
+                settings.enabled.insert(guild_id);
                 true
             }
         } else {
@@ -116,7 +209,7 @@ async fn modalert(ctx: Ctx<'_>) -> Result<(), Error> {
     };
 
     if let Err(e) = save_modalert_store(sctx).await {
-        eprintln!("Failed saving modalert store: {e:?}");
+        tracing::warn!("Failed saving modalert store: {e:?}");
     }
 
     if toggled_on {
@@ -127,10 +220,152 @@ async fn modalert(ctx: Ctx<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Post alerts to a channel instead of DMing the server owner
+#[poise::command(prefix_command, slash_command, rename = "channel")]
+async fn modalert_channel(
+    ctx: Ctx<'_>,
+    #[description = "Alert channel (omit to go back to owner DMs)"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    if !is_guild_owner(ctx, guild_id).await {
+        ctx.say("Only the server owner can change the mod alert channel.").await?;
+        return Ok(());
+    }
+
+    {
+        let data = sctx.data.read().await;
+        if let Some(store) = data.get::<ModAlertStore>() {
+            let mut settings = store.lock().await;
+            match &channel {
+                Some(c) => settings.channels.insert(guild_id, c.id),
+                None => settings.channels.remove(&guild_id),
+            };
+        }
+    }
+
+    if let Err(e) = save_modalert_store(sctx).await {
+        tracing::warn!("Failed saving modalert store: {e:?}");
+    }
+
+    match channel {
+        Some(c) => ctx.say(format!("Mod alerts will be posted to <#{}>.", c.id)).await?,
+        None => ctx.say("Mod alerts will be sent to the server owner via DM.").await?,
+    };
+    Ok(())
+}
+
+/// Toggle logging of deleted messages to the alert channel
+#[poise::command(prefix_command, slash_command, rename = "messagelog")]
+async fn modalert_messagelog(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    if !is_guild_owner(ctx, guild_id).await {
+        ctx.say("Only the server owner can toggle the message log.").await?;
+        return Ok(());
+    }
+
+    let (toggled_on, has_channel) = {
+        let data = sctx.data.read().await;
+        if let Some(store) = data.get::<ModAlertStore>() {
+            let mut settings = store.lock().await;
+            let on = if settings.message_log.remove(&guild_id) {
+                false
+            } else {
+                settings.message_log.insert(guild_id);
+                true
+            };
+            (on, settings.channels.contains_key(&guild_id))
+        } else {
+            (false, false)
+        }
+    };
+
+    if let Err(e) = save_modalert_store(sctx).await {
+        tracing::warn!("Failed saving modalert store: {e:?}");
+    }
+
+    if !toggled_on {
+        ctx.say("Message log disabled for this server.").await?;
+    } else if has_channel {
+        ctx.say("Message log enabled: deleted messages will be posted to the alert channel.").await?;
+    } else {
+        ctx.say("Message log enabled, but no alert channel is set. Use `modalert channel` to pick one.").await?;
+    }
+    Ok(())
+}
+
+/// Collapse alert floods into a single digest message
+#[poise::command(prefix_command, slash_command, rename = "digest")]
+async fn modalert_digest(
+    ctx: Ctx<'_>,
+    #[description = "Alerts per minute before switching to digests"]
+    #[min = 1]
+    per_minute: u32,
+    #[description = "Minutes collected into one digest (default 5)"]
+    #[min = 1]
+    window_minutes: Option<u64>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    if !is_guild_owner(ctx, guild_id).await {
+        ctx.say("Only the server owner can change mod alert thresholds.").await?;
+        return Ok(());
+    }
+
+    let cfg = DigestConfig {
+        max_per_minute: per_minute.max(1),
+        window_secs: window_minutes.unwrap_or(5).max(1) * 60,
+    };
+    {
+        let data = sctx.data.read().await;
+        if let Some(store) = data.get::<ModAlertStore>() {
+            let mut settings = store.lock().await;
+            settings.digest.insert(guild_id, cfg);
+        }
+    }
+
+    if let Err(e) = save_modalert_store(sctx).await {
+        tracing::warn!("Failed saving modalert store: {e:?}");
+    }
+
+    ctx.say(format!(
+        "More than {} alerts per minute will be collected into a digest every {} minutes.",
+        cfg.max_per_minute,
+        cfg.window_secs / 60
+    ))
+    .await?;
+    Ok(())
+}
+
 #[poise::command(
     prefix_command,
     slash_command,
-    subcommands("music_join", "music_play", "music_leave", "music_control"),
+    subcommands("music_join", "music_play", "music_leave", "music_control", "crate::queue::music_queue", "crate::queue::music_playnext", "crate::queue::music_clear", "crate::ambient::music_ambient", "crate::chapters::music_chapter", "crate::quality::music_quality", "crate::follow::music_follow", "crate::duck::music_clip", "crate::quiz::music_quiz", "crate::quiz::music_leaderboard", "crate::favorites::music_favorites"),
     rename = "music",
     track_edits
 )]
@@ -138,14 +373,35 @@ async fn music(_ctx: Ctx<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Defer a music command, privately when its replies will be (see `settings::ephemeral`), since
+/// the first follow-up inherits the deferral's visibility.
+async fn defer_music(ctx: Ctx<'_>) -> Result<(), Error> {
+    if settings::ephemeral(ctx).await {
+        ctx.defer_ephemeral().await?;
+    } else {
+        ctx.defer().await?;
+    }
+    Ok(())
+}
+
+/// Drop the "thinking…" placeholder if the command only posted to the channel (the control
+/// panel, "Now playing"), so it doesn't hang around until Discord times it out.
+async fn finish_deferred(ctx: Ctx<'_>) {
+    let poise::Context::Application(app) = ctx else { return };
+    if let Ok(response) = app.interaction.get_response(ctx.http()).await
+        && response.flags.is_some_and(|f| f.contains(serenity::model::channel::MessageFlags::LOADING))
+    {
+        let _ = app.interaction.delete_response(ctx.http()).await;
+    }
+}
+
 #[poise::command(prefix_command, slash_command, rename = "join")]
 async fn music_join(
     ctx: Ctx<'_>,
     #[description = "Voice channel id or mention (optional)"] channel: Option<String>,
 ) -> Result<(), Error> {
-    ctx.defer().await?;
+    defer_music(ctx).await?;
     let sctx = ctx.serenity_context();
-    let channel_id = ctx.channel_id();
     let author_id = ctx.author().id;
     let guild_id = ctx.guild_id();
 
@@ -168,17 +424,9 @@ async fn music_join(
         })
     };
 
-    handle_music(
-        sctx,
-        channel_id,
-        user_vc,
-        author_id,
-        guild_id,
-        "join",
-        EMBED_COLOR,
-    )
-    .await
-    .map_err(|e| e.into())
+    handle_music(ctx, user_vc, "join").await?;
+    finish_deferred(ctx).await;
+    Ok(())
 }
 
 #[poise::command(prefix_command, slash_command, rename = "play")]
@@ -186,53 +434,46 @@ async fn music_play(
     ctx: Ctx<'_>,
     #[description = "Song name or URL"] query: String,
 ) -> Result<(), Error> {
-    ctx.defer().await?;
-    let sctx = ctx.serenity_context();
-    let channel_id = ctx.channel_id();
-    let author_id = ctx.author().id;
-    let guild_id = ctx.guild_id();
-    let args = format!("play {}", query);
-    handle_music(sctx, channel_id, None, author_id, guild_id, &args, EMBED_COLOR).await?;
+    defer_music(ctx).await?;
+    handle_music(ctx, None, &format!("play {}", query)).await?;
+    finish_deferred(ctx).await;
     Ok(())
 }
 
 #[poise::command(prefix_command, slash_command, rename = "leave")]
 async fn music_leave(ctx: Ctx<'_>) -> Result<(), Error> {
-    ctx.defer().await?;
-    let sctx = ctx.serenity_context();
-    let channel_id = ctx.channel_id();
-    let author_id = ctx.author().id;
-    let guild_id = ctx.guild_id();
-    handle_music(sctx, channel_id, None, author_id, guild_id, "leave", EMBED_COLOR).await?;
+    defer_music(ctx).await?;
+    handle_music(ctx, None, "leave").await?;
+    finish_deferred(ctx).await;
     Ok(())
 }
 
 #[poise::command(prefix_command, slash_command, rename = "control")]
 async fn music_control(ctx: Ctx<'_>) -> Result<(), Error> {
-    ctx.defer().await?;
-    let sctx = ctx.serenity_context();
-    let channel_id = ctx.channel_id();
-    let author_id = ctx.author().id;
-    let guild_id = ctx.guild_id();
-    handle_music(sctx, channel_id, None, author_id, guild_id, "control", EMBED_COLOR).await?;
+    defer_music(ctx).await?;
+    handle_music(ctx, None, "control").await?;
+    finish_deferred(ctx).await;
     Ok(())
 }
 
 #[poise::command(prefix_command, slash_command, rename = "start")]
 async fn start_service(
     ctx: Ctx<'_>,
-    #[description = "Service key (or 'list')"] service: String,
+    #[description = "Service key (or 'list')"]
+    #[autocomplete = "start::autocomplete_service"]
+    service: String,
+    #[description = "start, stop, restart or status (default start)"]
+    #[autocomplete = "start::autocomplete_action"]
+    action: Option<String>,
     #[description = "Extra args (optional)"] args: Option<String>,
 ) -> Result<(), Error> {
     ctx.defer().await?;
-    let sctx = ctx.serenity_context();
-    let channel_id = ctx.channel_id();
-    let joined = if let Some(a) = args {
-        format!("{} {}", service, a)
-    } else {
-        service
-    };
-    handle_start(sctx, channel_id, joined.trim()).await.map_err(|e| e.into())
+    let mut joined = service;
+    for part in [action, args].into_iter().flatten() {
+        joined.push(' ');
+        joined.push_str(&part);
+    }
+    handle_start(ctx, joined.trim()).await
 }
 
 // ---------- Event forwarding ----------
@@ -244,19 +485,25 @@ async fn poise_event_handler(
 ) -> Result<(), Error> {
     match event {
         serenity::FullEvent::Ready { data_about_bot, .. } => {
-            println!("Connected as {}", data_about_bot.user.name);
+            tracing::info!("Connected as {}", data_about_bot.user.name);
+            if let Some(health) = web::health(ctx).await {
+                health.set_gateway(true);
+            }
+        }
+        serenity::FullEvent::ShardStageUpdate { event } => {
+            if let Some(health) = web::health(ctx).await {
+                health.set_gateway(event.new == serenity::all::ConnectionStage::Connected);
+            }
         }
-        serenity::FullEvent::GuildCreate { guild, .. } => {
+        serenity::FullEvent::GuildCreate { guild, is_new } => {
             let gid = guild.id;
-            if let Err(e) = poise::builtins::register_in_guild(
-                ctx,
-                &framework_ctx.options().commands,
-                gid,
-            )
-            .await
-            {
-                eprintln!("Failed to register commands in guild {}: {e:?}", gid);
+            if !access::enforce_guild(ctx, gid).await {
+                return Ok(());
+            }
+            if let Err(e) = registration::sync_guild(ctx, &framework_ctx.options().commands, gid).await {
+                tracing::warn!("Failed to register commands in guild {}: {e:?}", gid);
             }
+            onboarding::on_guild_create(ctx, guild, *is_new).await;
         }
         serenity::FullEvent::GuildMemberUpdate { old_if_available, new, event } => {
             let gid = event.guild_id;
@@ -278,296 +525,402 @@ async fn poise_event_handler(
                 _ => false,
             };
             if !is_timeout_newly_applied { return Ok(()); }
+            // Timeouts issued through the `timeout` command were already reported with a reason
+            if moderation::take_handled_timeout(ctx, gid, event.user.id).await {
+                return Ok(());
+            }
 
             let user_tag = new
                 .as_ref()
                 .map(|m| m.user.tag())
                 .unwrap_or_else(|| event.user.tag());
 
-            let owner_id = if let Some(g) = ctx.cache.guild(gid) { g.owner_id } else {
-                match gid.to_partial_guild(&ctx.http).await {
-                    Ok(pg) => pg.owner_id,
-                    Err(_) => return Ok(()),
-                }
-            };
             let content = format!(
                 "Moderation alert: {} was timed out in server {}.",
                 user_tag,
                 gid
             );
-            if let Ok(dm) = owner_id.create_dm_channel(&ctx.http).await {
-                let _ = dm.say(&ctx.http, content).await;
+            if let Err(e) = send_alert(ctx, gid, AlertKind::Timeout, user_tag, CreateMessage::new().content(content)).await {
+                tracing::warn!("Failed to send mod alert for guild {}: {e:?}", gid);
             }
         }
-        serenity::FullEvent::InteractionCreate { interaction } => {
-            if let serenity::all::Interaction::Component(mc) = interaction.clone() {
-                // custom_id format: music:<action>:<user_id>:<guild_id>
-                let custom_id = mc.data.custom_id.clone();
-                let mut parts = custom_id.split(':');
-                let prefix = parts.next().unwrap_or("");
-                if prefix != "music" { return Ok(()); }
-                let action = parts.next().unwrap_or("");
-                let owner_id = parts
-                    .next()
-                    .and_then(|s: &str| s.parse::<u64>().ok())
-                    .map(|v| UserId::new(v));
-                let guild_id = parts
-                    .next()
-                    .and_then(|s: &str| s.parse::<u64>().ok())
-                    .map(|v| GuildId::new(v));
-
-                if let Some(owner) = owner_id {
-                    if mc.user.id != owner {
-                        let _ = mc
-                            .create_response(
-                                &ctx.http,
-                                CreateInteractionResponse::Message(
-                                    CreateInteractionResponseMessage::new()
-                                        .content("You are not the owner of this control panel.")
-                                        .ephemeral(true),
-                                ),
-                            )
-                            .await;
-                        return Ok(());
-                    }
-                }
-
-                // Fetch handle from TypeMap
-                let data_read = ctx.data.read().await;
-                if let Some(store) = data_read.get::<TrackStore>() {
-                    let mut map = store.lock().await;
-                    if let Some(gid) = guild_id {
-                        if let Some(handle) = map.get(&gid) {
-                            let _ = match action {
-                                "pause" => handle
-                                    .pause()
-                                    .map(|_| "Paused".to_string())
-                                    .unwrap_or_else(|e| format!("Pause failed: {e:?}")),
-                                "resume" => handle
-                                    .play()
-                                    .map(|_| "Resumed".to_string())
-                                    .unwrap_or_else(|e| format!("Resume failed: {e:?}")),
-                                "stop" => {
-                                    let r = handle.stop();
-                                    map.remove(&gid);
-                                    r.map(|_| "Stopped".to_string())
-                                        .unwrap_or_else(|e| format!("Stop failed: {e:?}"))
-                                }
-                                "vol_up" => match handle.get_info().await {
-                                    Ok(info) => {
-                                        let mut v = info.volume;
-                                        v = (v + 0.1).min(5.0);
-                                        match handle.set_volume(v) {
-                                            Ok(()) => format!("Volume: {:.2}", v),
-                                            Err(e) => format!("Set volume failed: {e:?}"),
-                                        }
-                                    }
-                                    Err(e) => format!("Failed to get info: {e:?}"),
-                                },
-                                "vol_down" => match handle.get_info().await {
-                                    Ok(info) => {
-                                        let mut v = info.volume;
-                                        v = (v - 0.1).max(0.0);
-                                        match handle.set_volume(v) {
-                                            Ok(()) => format!("Volume: {:.2}", v),
-                                            Err(e) => format!("Set volume failed: {e:?}"),
-                                        }
-                                    }
-                                    Err(e) => format!("Failed to get info: {e:?}"),
-                                },
-                                _ => "Unknown action".to_string(),
-                            };
-
-                            // Acknowledge the interaction
-                            let _ = mc
-                                .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
-                                .await;
-
-                            // Update the control panel embed to reflect current state
-                            let (new_desc, title_and_thumb) = if let Some(handle2) = map.get(&gid)
-                            {
-                                match handle2.get_info().await {
-                                    Ok(info2) => {
-                                        let meta_opt = {
-                                            let data_read = ctx.data.read().await;
-                                            data_read.get::<TrackMetaStore>().cloned()
-                                        };
-
-                                        let remaining = if let Some(meta_store) = meta_opt.clone() {
-                                            let meta_map = meta_store.lock().await;
-                                            if let Some(meta) = meta_map.get(&gid) {
-                                                if let Some(total) = meta.duration {
-                                                    if total > info2.position {
-                                                        let rem = total - info2.position;
-                                                        let secs = rem.as_secs();
-                                                        let mins = secs / 60;
-                                                        let secs = secs % 60;
-                                                        format!("{mins}:{:02}", secs)
-                                                    } else {
-                                                        "0:00".into()
-                                                    }
-                                                } else {
-                                                    "Unknown".into()
-                                                }
-                                            } else {
-                                                "Unknown".into()
-                                            }
-                                        } else {
-                                            "Unknown".into()
-                                        };
-
-                                        let mut title_str = "Music Controls".to_string();
-                                        let mut thumbnail: Option<String> = None;
-                                        if let Some(meta_store) = meta_opt {
-                                            let meta_map = meta_store.lock().await;
-                                            if let Some(meta) = meta_map.get(&gid) {
-                                                match (&meta.title, &meta.artist) {
-                                                    (Some(t), Some(a)) => {
-                                                        title_str = format!("{} — {}", t, a)
-                                                    }
-                                                    (Some(t), None) => title_str = t.clone(),
-                                                    (None, Some(a)) => title_str = a.clone(),
-                                                    _ => {}
-                                                }
-                                                thumbnail = meta.thumbnail.clone();
-                                            }
-                                        }
-
-                                        (
-                                            format!(
-                                                "Status: {:?}\nVolume: {:.2}\nRemaining: {}",
-                                                info2.playing, info2.volume, remaining
-                                            ),
-                                            (title_str, thumbnail),
-                                        )
-                                    }
-                                    Err(_) => (
-                                        "Status: Unknown".into(),
-                                        ("Music Controls".into(), None),
-                                    ),
-                                }
-                            } else {
-                                (
-                                    "No active track".into(),
-                                    ("Music Controls".into(), None),
-                                )
-                            };
-
-                            let mut ce = CreateEmbed::new()
-                                .title(title_and_thumb.0)
-                                .description(new_desc)
-                                .color(EMBED_COLOR);
-                            if let Some(th) = title_and_thumb.1 {
-                                ce = ce.thumbnail(th);
-                            }
-                            let edit_msg = serenity::builder::EditMessage::new().embed(ce);
-                            let _ = mc.message.clone().edit(&ctx.http, edit_msg).await;
-                        } else {
-                            let _ = mc
-                                .create_response(
-                                    &ctx.http,
-                                    CreateInteractionResponse::Message(
-                                        CreateInteractionResponseMessage::new()
-                                            .content("No active track to control.")
-                                            .ephemeral(true),
-                                    ),
-                                )
-                                .await;
-                        }
-                    }
-                }
+        serenity::FullEvent::Message { new_message } => {
+            automod::check_message(ctx, new_message).await;
+            antiraid::check_message(ctx, new_message).await;
+        }
+        serenity::FullEvent::GuildMemberAddition { new_member } => {
+            antiraid::check_join(ctx, new_member).await;
+            welcome::on_member_join(ctx, new_member).await;
+        }
+        serenity::FullEvent::GuildMemberRemoval { guild_id, user, .. } => {
+            welcome::on_member_leave(ctx, *guild_id, user).await;
+        }
+        serenity::FullEvent::MessageDelete { channel_id, deleted_message_id, guild_id: Some(gid) } => {
+            let theme = embeds::theme(ctx, Some(*gid)).await;
+            log_deleted_messages(ctx, *gid, *channel_id, &[*deleted_message_id], &theme).await;
+            roles::forget_panel(ctx, *deleted_message_id).await;
+            starboard::on_messages_deleted(ctx, &[*deleted_message_id]).await;
+        }
+        serenity::FullEvent::MessageDeleteBulk {
+            channel_id,
+            multiple_deleted_messages_ids,
+            guild_id: Some(gid),
+        } => {
+            let theme = embeds::theme(ctx, Some(*gid)).await;
+            log_deleted_messages(ctx, *gid, *channel_id, multiple_deleted_messages_ids, &theme).await;
+            starboard::on_messages_deleted(ctx, multiple_deleted_messages_ids).await;
+        }
+        serenity::FullEvent::VoiceStateUpdate { old, new } => {
+            follow::on_voice_state(ctx, old.as_ref(), new).await;
+        }
+        serenity::FullEvent::ReactionAdd { add_reaction } => {
+            starboard::on_reaction(ctx, add_reaction).await;
+        }
+        serenity::FullEvent::ReactionRemove { removed_reaction } => {
+            starboard::on_reaction(ctx, removed_reaction).await;
+        }
+        serenity::FullEvent::ReactionRemoveEmoji { removed_reactions } => {
+            starboard::on_reaction(ctx, removed_reactions).await;
+        }
+        serenity::FullEvent::ReactionRemoveAll { channel_id, removed_from_message_id } => {
+            if let Ok(serenity::Channel::Guild(channel)) = channel_id.to_channel(ctx).await {
+                starboard::on_reactions_cleared(ctx, channel.guild_id, *channel_id, *removed_from_message_id).await;
             }
         }
+        serenity::FullEvent::InteractionCreate { interaction: serenity::all::Interaction::Component(mc) } => {
+            components::dispatch(ctx, mc).await;
+        }
         _ => {}
     }
     Ok(())
 }
 
 // ---------- Main & framework ----------
+/// Config layers are merged as defaults < config file < `BOT__*` env vars < these flags.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Directory all state is kept in (database, stores, backups, downloaded tools, the default
+    /// config); defaults to `BOT_DATA_DIR`, else the working directory
+    #[arg(long, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+    /// Path to the JSONC config file [default: config.jsonc in the data directory]
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
+    /// Override a config key, e.g. `--set start.services.mc.url=http://host:8080/start`
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = config::parse_override)]
+    overrides: Vec<(String, String)>,
+    /// Log `start` requests, moderation actions, message deletions and file deletions (backup pruning and restores) instead of carrying them out
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Wait for Ctrl-C or, on Unix, SIGTERM. False if neither can be listened for.
+async fn shutdown_signal() -> bool {
+    #[cfg(unix)]
+    {
+        let Ok(mut term) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) else {
+            return tokio::signal::ctrl_c().await.is_ok();
+        };
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.is_ok(),
+            _ = term.recv() => true,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.is_ok()
+    }
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn,discord=info")),
+        )
+        .init();
+    let cli = Cli::parse();
+    if let Some(dir) = cli.data_dir.or_else(|| env::var_os("BOT_DATA_DIR").map(PathBuf::from))
+        && let Err(e) = storage::set_data_dir(dir.clone())
+    {
+        tracing::error!("Can't use {} as the data directory: {e}", dir.display());
+        std::process::exit(1);
+    }
+    let config_path = cli.config.unwrap_or_else(|| storage::data_path(config::CONFIG_PATH).to_string_lossy().into_owned());
+    config::init(config_path, cli.overrides);
+    dryrun::force(cli.dry_run);
+    botinfo::mark_started();
+    telemetry::install_panic_hook();
     let token = env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN not set");
 
     // Ensure config.jsonc exists (creates default if missing)
     if let Err(e) = ensure_default_config().await {
-        eprintln!("Failed to ensure config: {e:?}");
+        tracing::error!("Failed to ensure config: {e:?}");
     }
 
-    ensure_media_tools()
-        .await
-        .expect("Failed to prepare media tools (yt-dlp)");
+    // Refuse to start on a bad config rather than running with surprising defaults
+    let app_config = match config::load_config().await {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            tracing::error!("{e}");
+            tracing::error!("Fix the config (or remove it to regenerate the default) and restart.");
+            std::process::exit(1);
+        }
+    };
+
+    // An `admin restore` from the last run is applied before anything opens the stores
+    if let Err(e) = backup::apply_pending_restore(&app_config.backup).await {
+        tracing::error!("Failed to restore the backup: {e:?}");
+    }
+
+    // Probes are served from before the gateway connects, so startup reads as "not ready yet"
+    let health = Arc::new(web::Health::default());
+    web::start(health.clone(), &app_config.http);
+
+    // The backend is fixed for the process; switching needs a restart
+    let lavalink_node = match (app_config.music.player_backend, &app_config.music.lavalink) {
+        (config::PlayerBackend::Lavalink, Some(cfg)) => Some(Arc::new(lavalink::Node::new(cfg.clone()))),
+        _ => None,
+    };
+
+    // Lavalink resolves and streams on the node, so yt-dlp is only needed for songbird
+    if lavalink_node.is_none() {
+        ensure_media_tools()
+            .await
+            .expect("Failed to prepare media tools (yt-dlp)");
+    }
 
     // Attempt to prepare an optional Spotify helper binary (librespot wrapper)
     if let Err(e) = crate::music::ensure_spotify_helper().await {
-        eprintln!("Failed to prepare Spotify helper: {e:?}");
+        tracing::error!("Failed to prepare Spotify helper: {e:?}");
     }
 
     let intents = serenity::GatewayIntents::GUILD_MESSAGES
+        | serenity::GatewayIntents::GUILD_MESSAGE_REACTIONS
         | serenity::GatewayIntents::DIRECT_MESSAGES
         | serenity::GatewayIntents::MESSAGE_CONTENT
         | serenity::GatewayIntents::GUILDS
         | serenity::GatewayIntents::GUILD_MEMBERS
         | serenity::GatewayIntents::GUILD_VOICE_STATES;
 
+    // Categories group the commands in `help`
+    let mut commands = help::categorize(
+        "Music",
+        vec![music(), music_join(), music_play(), music_leave(), music_control()],
+    );
+    commands.extend(help::categorize("Music", scrobble::commands()));
+    commands.extend(help::categorize("Moderation", vec![modalert()]));
+    commands.extend(help::categorize("Moderation", moderation::commands()));
+    commands.extend(help::categorize("Moderation", warnings::commands()));
+    commands.extend(help::categorize("Moderation", automod::commands()));
+    commands.extend(help::categorize("Moderation", antiraid::commands()));
+    commands.extend(help::categorize("Services", vec![start_service()]));
+    commands.extend(help::categorize("Utility", ping::commands()));
+    commands.extend(help::categorize("Utility", botinfo::commands()));
+    commands.extend(help::categorize("Utility", help::commands()));
+    commands.extend(help::categorize("Utility", reminders::commands()));
+    commands.extend(help::categorize("Utility", tags::commands()));
+    commands.extend(help::categorize("Utility", welcome::commands()));
+    commands.extend(help::categorize("Utility", roles::commands()));
+    commands.extend(help::categorize("Utility", starboard::commands()));
+    commands.extend(help::categorize("Utility", settings::commands()));
+    commands.extend(help::categorize("Utility", setup::commands()));
+    commands.extend(help::categorize("Utility", preferences::commands()));
+    commands.extend(help::categorize("Utility", privacy::commands()));
+    commands.extend(help::categorize("Utility", permissions::commands()));
+    commands.extend(help::categorize("Utility", admin::commands()));
+    i18n::localize_commands(&mut commands);
+
     let framework = poise::Framework::builder()
-        .setup(|ctx, _ready, framework| {
+        .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
                 // Initialize shared stores
                 {
                     let mut data = ctx.data.write().await;
                     data.insert::<TrackStore>(Arc::new(Mutex::new(HashMap::new())));
                     data.insert::<TrackMetaStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<SessionThreadStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<music::ResolverStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<music::CurrentQueryStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<music::ChildStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<music::ResolutionSlotStore>(Arc::new(music::ResolutionSlots::new(app_config.music.resolution_slots())));
+                    let mut registry = components::Registry::default();
+                    registry.register("music", music::handle_component);
+                    registry.register("roles", roles::handle_component);
+                    data.insert::<components::RegistryStore>(Arc::new(registry));
+                    data.insert::<queue::QueueStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<queue::HistoryStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<ambient::AmbientStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<quiz::QuizStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<party::PartyStore>(Default::default());
+                    data.insert::<scrobble::RequesterStore>(Default::default());
+                    data.insert::<live::LiveStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<duck::DuckStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<positions::PositionStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<dedupe::DedupeStore>(Default::default());
+                    data.insert::<events::EventBus>(events::bus());
+                    if let Some(node) = lavalink_node.clone() {
+                        data.insert::<lavalink::LavalinkStore>(node);
+                    }
+                    data.insert::<presence::PresenceState>(Default::default());
+                    data.insert::<web::HealthStore>(health.clone());
+                    data.insert::<access::OwnersStore>(Arc::new(framework.options().owners.clone()));
+                    data.insert::<AlertLimiterStore>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<moderation::HandledTimeouts>(Arc::new(Mutex::new(HashSet::new())));
+                    data.insert::<config::ConfigStore>(Arc::new(tokio::sync::RwLock::new(Arc::new(app_config))));
+                    let db = storage::Db::open(&storage::data_path(storage::DB_PATH)).await?;
+                    data.insert::<storage::DbStore>(db.clone());
+                    data.insert::<settings::GuildSettingsCache>(Arc::new(Mutex::new(HashMap::new())));
+                    data.insert::<permissions::PermissionCache>(Arc::new(Mutex::new(HashMap::new())));
+                    match access::ensure_access_store(&db).await {
+                        Ok(store) => {
+                            data.insert::<access::AccessStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load access lists: {e:?}"),
+                    }
                     // Load ModAlert settings into shared store
-                    if let Ok(store) = ensure_modalert_store().await {
-                        data.insert::<ModAlertStore>(store);
+                    match ensure_modalert_store(&db).await {
+                        Ok(store) => {
+                            data.insert::<ModAlertStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load mod alert settings: {e:?}"),
                     }
-                }
-
-                // Register in all existing guilds for immediate availability
-                for gid in ctx.cache.guilds() {
-                    if let Err(e) = poise::builtins::register_in_guild(ctx, &framework.options().commands, gid).await {
-                        eprintln!("Failed to register commands in guild {}: {e:?}", gid);
+                    match warnings::ensure_warning_store().await {
+                        Ok(store) => {
+                            data.insert::<warnings::WarningStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load warnings: {e:?}"),
+                    }
+                    match automod::ensure_automod_store().await {
+                        Ok(store) => {
+                            data.insert::<automod::AutomodStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load automod config: {e:?}"),
+                    }
+                    match antiraid::ensure_antiraid_store().await {
+                        Ok(store) => {
+                            data.insert::<antiraid::AntiRaidStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load anti-raid config: {e:?}"),
+                    }
+                    match tags::ensure_tag_store().await {
+                        Ok(store) => {
+                            data.insert::<tags::TagStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load tags: {e:?}"),
+                    }
+                    match welcome::ensure_welcome_store().await {
+                        Ok(store) => {
+                            data.insert::<welcome::WelcomeStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load welcome config: {e:?}"),
                     }
+                    match roles::ensure_role_panel_store().await {
+                        Ok(store) => {
+                            data.insert::<roles::RolePanelStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load role panels: {e:?}"),
+                    }
+                    match starboard::ensure_starboard_store().await {
+                        Ok(store) => {
+                            data.insert::<starboard::StarboardStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load starboard: {e:?}"),
+                    }
+                    match scheduler::ensure_scheduler_store().await {
+                        Ok(store) => {
+                            data.insert::<scheduler::SchedulerStore>(store);
+                        }
+                        Err(e) => tracing::error!("Failed to load scheduled jobs: {e:?}"),
+                    }
+                }
+                let mut jobs = scheduler::Registry::default();
+                jobs.register(moderation::UNBAN_JOB, moderation::run_unban_job);
+                jobs.register(antiraid::PRUNE_JOB, antiraid::run_prune_job);
+                jobs.register(reminders::REMINDER_JOB, reminders::run_reminder_job);
+                if let Err(e) = scheduler::ensure_recurring(ctx, antiraid::PRUNE_JOB, antiraid::PRUNE_EVERY, ()).await {
+                    tracing::error!("Failed to schedule anti-raid pruning: {e:?}");
+                }
+                scheduler::start(ctx.clone(), jobs);
+                config::watch(ctx.clone());
+                presence::start(ctx.clone());
+                janitor::start(ctx.clone());
+                backup::start(ctx.clone());
+                positions::start(ctx.clone());
+                events::start(ctx.clone());
+                party::start(ctx.clone());
+                scrobble::start(ctx.clone());
+                upnext::start(ctx.clone());
+                if let Some(node) = lavalink_node.clone() {
+                    lavalink::start(ctx.clone(), node);
                 }
 
-                // Optional: clear any previously set global commands to prevent duplicates
-                // If you want to keep global commands, comment this out.
-                let _ = serenity::all::Command::set_global_commands(&ctx.http, vec![]).await;
+                // Push commands where `registration.mode` wants them; unchanged scopes are skipped
+                match registration::sync(ctx, &framework.options().commands, false).await {
+                    Ok(report) => tracing::info!("Command registration: {report}"),
+                    Err(e) => tracing::error!("Failed to register commands: {e:?}"),
+                }
+                health.set_ready(songbird::get(ctx).await.is_some());
+                web::attach(ctx);
                 Ok(Data)
             })
         })
         .options(poise::FrameworkOptions {
-            commands: vec![
-                ping(),
-                help(),
-                modalert(),
-                music(),
-                music_join(),
-                music_play(),
-                music_leave(),
-                music_control(),
-                start_service(),
-            ],
+            commands,
             prefix_options: poise::PrefixFrameworkOptions {
                 prefix: Some(PREFIX.into()),
+                // Guild-specific prefix from the settings store; `!is` above always works too
+                dynamic_prefix: Some(|pctx| {
+                    Box::pin(async move {
+                        let Some(gid) = pctx.guild_id else { return Ok(None) };
+                        Ok(Some(settings::prefix(pctx.serenity_context, gid).await))
+                    })
+                }),
                 ..Default::default()
             },
             event_handler: |ctx, event, framework, data| {
                 Box::pin(poise_event_handler(ctx, event, framework, data))
             },
+            command_check: Some(|ctx| Box::pin(access::check(ctx))),
+            pre_command: |ctx| {
+                Box::pin(async move {
+                    botinfo::count(ctx);
+                    audit::before(ctx).await;
+                })
+            },
+            post_command: |ctx| Box::pin(audit::record(ctx, None)),
+            on_error: |error| Box::pin(errors::on_error(error)),
             ..Default::default()
         })
         .build();
 
+    // Keep a small per-channel message cache so the modalert message log can show deleted content
+    let mut cache_settings = serenity::cache::Settings::default();
+    cache_settings.max_messages = MESSAGE_CACHE_SIZE;
+
     let mut client = serenity::ClientBuilder::new(token, intents)
+        .cache_settings(cache_settings)
         .register_songbird()
         .framework(framework)
         .await
         .expect("Err creating client");
 
+    // On Ctrl-C or SIGTERM (docker stop, systemd), kill playback processes before the gateway
+    // goes away
+    let shard_manager = client.shard_manager.clone();
+    let data = client.data.clone();
+    tokio::spawn(async move {
+        if shutdown_signal().await {
+            music::kill_all_children(&data).await;
+            shard_manager.shutdown_all().await;
+        }
+    });
+
     if let Err(why) = client.start().await {
-        eprintln!("Client error: {why:?}");
+        tracing::error!("Client error: {why:?}");
     }
 }
 
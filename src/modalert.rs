@@ -1,69 +1,748 @@
+use poise::serenity_prelude as serenity;
 use serde::{Deserialize, Serialize};
+use serenity::all::{ButtonStyle, ChannelType, ComponentInteractionCollector, ComponentInteractionDataKind};
+use serenity::builder::{
+    CreateActionRow, CreateAttachment, CreateButton, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+};
 use serenity::prelude::*;
-use serenity::model::id::GuildId;
-use std::collections::HashSet;
-use std::path::Path;
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+use crate::storage::{self, Db};
+use crate::Error;
+
+/// Pre-database settings file, imported on first start.
 const MODALERT_PATH: &str = "modalerts.json";
 
+/// Number of messages kept per channel in the serenity cache so deleted messages can be logged.
+pub const MESSAGE_CACHE_SIZE: usize = 200;
+
+/// Per-guild mod alert settings.
+#[derive(Debug, Default)]
+pub struct ModAlertSettings {
+    pub enabled: HashSet<GuildId>,
+    /// Channel alerts are posted to; guilds without one get alerts via owner DM.
+    pub channels: HashMap<GuildId, ChannelId>,
+    /// Guilds that log deleted and bulk-deleted messages to their alert channel.
+    pub message_log: HashSet<GuildId>,
+    /// Per-guild digest thresholds; guilds without an entry use `DigestConfig::default()`.
+    pub digest: HashMap<GuildId, DigestConfig>,
+    /// Kinds of alert a guild reports; guilds without an entry report all of them.
+    pub events: HashMap<GuildId, HashSet<AlertKind>>,
+}
+
+/// When more than `max_per_minute` alerts fire within a minute, further alerts are
+/// collected for `window_secs` and delivered as a single digest message.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DigestConfig {
+    pub max_per_minute: u32,
+    pub window_secs: u64,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self { max_per_minute: 5, window_secs: 300 }
+    }
+}
+
 pub struct ModAlertStore;
 impl TypeMapKey for ModAlertStore {
-    type Value = Arc<Mutex<HashSet<GuildId>>>;
+    type Value = Arc<Mutex<ModAlertSettings>>;
 }
 
-#[derive(Serialize, Deserialize, Default)]
+/// Legacy flat-file format, imported into the database on first start.
+#[derive(Deserialize, Default)]
 struct ModAlertDisk {
     enabled_guilds: Vec<u64>,
+    #[serde(default)]
+    alert_channels: HashMap<u64, u64>,
+    #[serde(default)]
+    message_log_guilds: Vec<u64>,
+    #[serde(default)]
+    digest: HashMap<u64, DigestConfig>,
 }
 
-async fn load_disk() -> Result<HashSet<GuildId>, Box<dyn std::error::Error + Send + Sync>> {
-    if !Path::new(MODALERT_PATH).exists() {
-        // Create empty file
-        let data = ModAlertDisk::default();
-        let s = serde_json::to_string_pretty(&data)?;
-        tokio::fs::write(MODALERT_PATH, s).await?;
-        return Ok(HashSet::new());
+impl From<ModAlertDisk> for ModAlertSettings {
+    fn from(data: ModAlertDisk) -> Self {
+        ModAlertSettings {
+            enabled: data.enabled_guilds.into_iter().map(GuildId::new).collect(),
+            channels: data
+                .alert_channels
+                .into_iter()
+                .map(|(g, c)| (GuildId::new(g), ChannelId::new(c)))
+                .collect(),
+            message_log: data.message_log_guilds.into_iter().map(GuildId::new).collect(),
+            digest: data.digest.into_iter().map(|(g, d)| (GuildId::new(g), d)).collect(),
+            events: HashMap::new(),
+        }
     }
+}
 
-    let s = tokio::fs::read_to_string(MODALERT_PATH).await?;
-    let data: ModAlertDisk = serde_json::from_str(&s)?;
-    let set: HashSet<GuildId> = data.enabled_guilds.into_iter().map(GuildId::new).collect();
-    Ok(set)
+async fn load_db(db: &Db) -> Result<ModAlertSettings, Error> {
+    db.call(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT guild_id, enabled, channel_id, message_log, digest_per_minute, digest_window_secs, events
+             FROM modalert_guilds",
+        )?;
+        let mut settings = ModAlertSettings::default();
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let gid = GuildId::new(row.get::<_, i64>(0)? as u64);
+            if row.get(1)? {
+                settings.enabled.insert(gid);
+            }
+            if let Some(c) = row.get::<_, Option<i64>>(2)? {
+                settings.channels.insert(gid, ChannelId::new(c as u64));
+            }
+            if row.get(3)? {
+                settings.message_log.insert(gid);
+            }
+            if let (Some(max_per_minute), Some(window_secs)) = (row.get(4)?, row.get::<_, Option<i64>>(5)?) {
+                settings
+                    .digest
+                    .insert(gid, DigestConfig { max_per_minute, window_secs: window_secs as u64 });
+            }
+            if let Some(events) = row.get::<_, Option<String>>(6)? {
+                settings.events.insert(gid, events.split(',').filter_map(AlertKind::from_key).collect());
+            }
+        }
+        Ok(settings)
+    })
+    .await
 }
 
-async fn save_disk(set: &HashSet<GuildId>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let data = ModAlertDisk {
-        enabled_guilds: set.iter().map(|g| g.get()).collect(),
-    };
-    let s = serde_json::to_string_pretty(&data)?;
-    tokio::fs::write(MODALERT_PATH, s).await?;
+async fn save_db(db: &Db, settings: &ModAlertSettings) -> Result<(), Error> {
+    let guilds: HashSet<GuildId> = settings
+        .enabled
+        .iter()
+        .chain(settings.channels.keys())
+        .chain(settings.message_log.iter())
+        .chain(settings.digest.keys())
+        .chain(settings.events.keys())
+        .copied()
+        .collect();
+    let rows: Vec<_> = guilds
+        .into_iter()
+        .map(|gid| {
+            let digest = settings.digest.get(&gid);
+            (
+                gid.get() as i64,
+                settings.enabled.contains(&gid),
+                settings.channels.get(&gid).map(|c| c.get() as i64),
+                settings.message_log.contains(&gid),
+                digest.map(|d| d.max_per_minute),
+                digest.map(|d| d.window_secs as i64),
+                settings.events.get(&gid).map(|events| {
+                    AlertKind::ALL.iter().filter(|k| events.contains(k)).map(|k| k.key()).collect::<Vec<_>>().join(",")
+                }),
+            )
+        })
+        .collect();
+
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM modalert_guilds", [])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO modalert_guilds
+                 (guild_id, enabled, channel_id, message_log, digest_per_minute, digest_window_secs, events)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for row in rows {
+                stmt.execute(rusqlite::params![row.0, row.1, row.2, row.3, row.4, row.5, row.6])?;
+            }
+        }
+        tx.commit()
+    })
+    .await
+}
+
+/// Load mod alert settings from the database, importing `modalerts.json` the first time.
+pub async fn ensure_modalert_store(db: &Db) -> Result<Arc<Mutex<ModAlertSettings>>, Error> {
+    let mut settings = load_db(db).await?;
+    let legacy = storage::data_path(MODALERT_PATH);
+    if legacy.exists() {
+        let data: ModAlertDisk = storage::load_json(&legacy).await?;
+        settings = data.into();
+        save_db(db, &settings).await?;
+        // Keep the old file around (renamed) in case the import needs checking
+        tokio::fs::rename(&legacy, storage::data_path(format!("{}.imported", MODALERT_PATH))).await?;
+    }
+    Ok(Arc::new(Mutex::new(settings)))
+}
+
+pub async fn save_modalert_store(ctx: &Context) -> Result<(), Error> {
+    let db = storage::db(ctx).await?;
+    let store = ctx.data.read().await.get::<ModAlertStore>().cloned();
+    if let Some(store) = store {
+        let settings = store.lock().await;
+        save_db(&db, &settings).await?;
+    }
     Ok(())
 }
 
-pub async fn ensure_modalert_store(
-    
-) -> Result<Arc<Mutex<HashSet<GuildId>>>, Box<dyn std::error::Error + Send + Sync>> {
-    let set = load_disk().await?;
-    Ok(Arc::new(Mutex::new(set)))
+/// Forget `gid`'s mod alert settings, for `admin purge-guild-data`.
+pub async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<(), Error> {
+    let store = ctx.data.read().await.get::<ModAlertStore>().cloned();
+    if let Some(store) = store {
+        let mut s = store.lock().await;
+        s.enabled.remove(&gid);
+        s.channels.remove(&gid);
+        s.message_log.remove(&gid);
+        s.digest.remove(&gid);
+        s.events.remove(&gid);
+    }
+    save_modalert_store(ctx).await
 }
 
-pub async fn save_modalert_store(ctx: &Context) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn is_modalert_enabled(ctx: &Context, gid: GuildId) -> bool {
     let data = ctx.data.read().await;
     if let Some(store) = data.get::<ModAlertStore>() {
-        let set = store.lock().await;
-        save_disk(&set).await?
+        let settings = store.lock().await;
+        settings.enabled.contains(&gid)
+    } else {
+        false
     }
-    Ok(())
 }
 
-pub async fn is_modalert_enabled(ctx: &Context, gid: GuildId) -> bool {
+async fn alert_channel(ctx: &Context, gid: GuildId) -> Option<ChannelId> {
+    let data = ctx.data.read().await;
+    let store = data.get::<ModAlertStore>()?;
+    let settings = store.lock().await;
+    settings.channels.get(&gid).copied()
+}
+
+async fn is_message_log_enabled(ctx: &Context, gid: GuildId) -> bool {
     let data = ctx.data.read().await;
     if let Some(store) = data.get::<ModAlertStore>() {
-        let set = store.lock().await;
-        set.contains(&gid)
+        let settings = store.lock().await;
+        settings.enabled.contains(&gid) && settings.message_log.contains(&gid)
     } else {
         false
     }
 }
+
+/// What an alert is about; used to group alerts in digests and to pick which ones a guild gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    Timeout,
+    Warn,
+    Kick,
+    Ban,
+    Unban,
+    Purge,
+    Automod,
+    Raid,
+}
+
+impl AlertKind {
+    pub const ALL: [AlertKind; 8] = [
+        AlertKind::Timeout,
+        AlertKind::Warn,
+        AlertKind::Kick,
+        AlertKind::Ban,
+        AlertKind::Unban,
+        AlertKind::Purge,
+        AlertKind::Automod,
+        AlertKind::Raid,
+    ];
+
+    /// Stored name, in `modalert_guilds.events` and component values.
+    pub fn key(self) -> &'static str {
+        match self {
+            AlertKind::Timeout => "timeout",
+            AlertKind::Warn => "warn",
+            AlertKind::Kick => "kick",
+            AlertKind::Ban => "ban",
+            AlertKind::Unban => "unban",
+            AlertKind::Purge => "purge",
+            AlertKind::Automod => "automod",
+            AlertKind::Raid => "raid",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        AlertKind::ALL.into_iter().find(|k| k.key() == key.trim())
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AlertKind::Timeout => "Timeouts",
+            AlertKind::Warn => "Warnings",
+            AlertKind::Kick => "Kicks",
+            AlertKind::Ban => "Bans",
+            AlertKind::Unban => "Unbans",
+            AlertKind::Purge => "Purges",
+            AlertKind::Automod => "Automod",
+            AlertKind::Raid => "Raid detection",
+        }
+    }
+
+    /// Digest summary fragment, e.g. "14 members timed out".
+    fn digest_label(self, n: usize) -> String {
+        let (one, many) = match self {
+            AlertKind::Timeout => ("member timed out", "members timed out"),
+            AlertKind::Warn => ("member warned", "members warned"),
+            AlertKind::Kick => ("member kicked", "members kicked"),
+            AlertKind::Ban => ("member banned", "members banned"),
+            AlertKind::Unban => ("user unbanned", "users unbanned"),
+            AlertKind::Purge => ("channel purged", "channels purged"),
+            AlertKind::Automod => ("message caught by automod", "messages caught by automod"),
+            AlertKind::Raid => ("flood detection event", "flood detection events"),
+        };
+        format!("{} {}", n, if n == 1 { one } else { many })
+    }
+}
+
+/// Runtime rate-limit state per guild (not persisted).
+#[derive(Default)]
+pub struct AlertLimiter {
+    recent: VecDeque<Instant>,
+    /// Alerts waiting for the next digest
+    pending: Vec<(AlertKind, String)>,
+    digest_scheduled: bool,
+}
+
+pub struct AlertLimiterStore;
+impl TypeMapKey for AlertLimiterStore {
+    type Value = Arc<Mutex<HashMap<GuildId, AlertLimiter>>>;
+}
+
+async fn digest_config(ctx: &Context, gid: GuildId) -> DigestConfig {
+    let data = ctx.data.read().await;
+    if let Some(store) = data.get::<ModAlertStore>() {
+        let settings = store.lock().await;
+        settings.digest.get(&gid).copied().unwrap_or_default()
+    } else {
+        DigestConfig::default()
+    }
+}
+
+/// Whether the guild reports alerts of this kind.
+async fn reports(ctx: &Context, gid: GuildId, kind: AlertKind) -> bool {
+    let Some(store) = ctx.data.read().await.get::<ModAlertStore>().cloned() else { return true };
+    let settings = store.lock().await;
+    settings.events.get(&gid).is_none_or(|events| events.contains(&kind))
+}
+
+/// Send an alert through the rate limiter. `detail` is the one-line description listed in digests.
+/// Kinds the guild has switched off are dropped.
+pub async fn send_alert(
+    ctx: &Context,
+    gid: GuildId,
+    kind: AlertKind,
+    detail: String,
+    message: CreateMessage,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !reports(ctx, gid, kind).await {
+        return Ok(());
+    }
+    let limiter_store = ctx.data.read().await.get::<AlertLimiterStore>().cloned();
+    let Some(limiter_store) = limiter_store else {
+        return deliver_alert(ctx, gid, message).await;
+    };
+    let cfg = digest_config(ctx, gid).await;
+
+    let schedule_digest = {
+        let mut limiters = limiter_store.lock().await;
+        let limiter = limiters.entry(gid).or_default();
+        let now = Instant::now();
+        while limiter
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > Duration::from_secs(60))
+        {
+            limiter.recent.pop_front();
+        }
+
+        if !limiter.digest_scheduled && (limiter.recent.len() as u32) < cfg.max_per_minute {
+            limiter.recent.push_back(now);
+            None
+        } else {
+            limiter.pending.push((kind, detail));
+            if limiter.digest_scheduled {
+                return Ok(());
+            }
+            limiter.digest_scheduled = true;
+            Some(cfg.window_secs)
+        }
+    };
+
+    match schedule_digest {
+        None => deliver_alert(ctx, gid, message).await,
+        Some(window_secs) => {
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(window_secs)).await;
+                if let Err(e) = flush_digest(&ctx, gid, &limiter_store, window_secs).await {
+                    tracing::warn!("Failed to send mod alert digest for guild {}: {e:?}", gid);
+                }
+            });
+            Ok(())
+        }
+    }
+}
+
+async fn flush_digest(
+    ctx: &Context,
+    gid: GuildId,
+    limiter_store: &Arc<Mutex<HashMap<GuildId, AlertLimiter>>>,
+    window_secs: u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let pending = {
+        let mut limiters = limiter_store.lock().await;
+        let limiter = limiters.entry(gid).or_default();
+        limiter.digest_scheduled = false;
+        limiter.recent.clear();
+        std::mem::take(&mut limiter.pending)
+    };
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    // Count per kind, keeping first-seen order so the summary reads naturally
+    let mut counts: Vec<(AlertKind, usize)> = Vec::new();
+    for (kind, _) in &pending {
+        match counts.iter_mut().find(|(k, _)| k == kind) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((*kind, 1)),
+        }
+    }
+    let minutes = (window_secs / 60).max(1);
+    let summary = counts
+        .iter()
+        .map(|(kind, n)| kind.digest_label(*n))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let content = format!(
+        "Moderation alert digest: {} in the last {} minute{}, see attached list.",
+        summary,
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    );
+
+    let list = pending
+        .iter()
+        .map(|(kind, detail)| format!("[{:?}] {}", kind, detail))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let message = CreateMessage::new()
+        .content(content)
+        .add_file(CreateAttachment::bytes(list.into_bytes(), "alerts.txt"));
+    deliver_alert(ctx, gid, message).await
+}
+
+/// Deliver an alert to the guild's alert channel, or DM the server owner when none is configured.
+async fn deliver_alert(
+    ctx: &Context,
+    gid: GuildId,
+    message: CreateMessage,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(channel) = alert_channel(ctx, gid).await {
+        channel.send_message(&ctx.http, message).await?;
+        return Ok(());
+    }
+
+    let owner_id = if let Some(g) = ctx.cache.guild(gid) {
+        g.owner_id
+    } else {
+        gid.to_partial_guild(&ctx.http).await?.owner_id
+    };
+    let dm = owner_id.create_dm_channel(&ctx.http).await?;
+    dm.send_message(&ctx.http, message).await?;
+    Ok(())
+}
+
+/// Post deleted messages (as far as the cache remembers them) to the guild's alert channel.
+pub async fn log_deleted_messages(
+    ctx: &Context,
+    gid: GuildId,
+    channel_id: ChannelId,
+    message_ids: &[MessageId],
+    theme: &crate::embeds::Theme,
+) {
+    if message_ids.is_empty() || !is_message_log_enabled(ctx, gid).await {
+        return;
+    }
+    // Message logs are only ever posted to a channel; DMing the owner every deletion would be noise
+    let log_channel = match alert_channel(ctx, gid).await {
+        Some(c) => c,
+        None => return,
+    };
+    if log_channel == channel_id {
+        return;
+    }
+
+    let lines: Vec<String> = message_ids
+        .iter()
+        .map(|id| describe_deleted_message(ctx, channel_id, *id))
+        .collect();
+
+    let (title, body) = if let [line] = lines.as_slice() {
+        ("Message deleted".to_string(), line.clone())
+    } else {
+        (format!("{} messages bulk-deleted", lines.len()), lines.join("\n"))
+    };
+    let embed = theme
+        .embed()
+        .title(title)
+        .description(crate::embeds::truncate(
+            &format!("Channel: <#{}>\n{}", channel_id.get(), body),
+            crate::embeds::DESCRIPTION_LIMIT,
+        ));
+
+    if let Err(e) = log_channel
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await
+    {
+        tracing::warn!("Failed to post message log for guild {}: {e:?}", gid);
+    }
+}
+
+fn describe_deleted_message(ctx: &Context, channel_id: ChannelId, id: MessageId) -> String {
+    let Some(msg) = ctx.cache.message(channel_id, id) else {
+        return format!("`{}`: <not cached>", id);
+    };
+
+    let mut line = format!("**{}** (<@{}>): {}", msg.author.tag(), msg.author.id, msg.content);
+    if !msg.attachments.is_empty() {
+        let files = msg
+            .attachments
+            .iter()
+            .map(|a| format!("[{}]({})", a.filename, a.url))
+            .collect::<Vec<_>>()
+            .join(", ");
+        line.push_str(&format!("\nAttachments: {}", files));
+    }
+    line
+}
+
+/// How long `modalert config` waits for each change before closing.
+const CONFIG_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, poise::Modal)]
+#[name = "Digest thresholds"]
+struct DigestModal {
+    #[name = "Alerts per minute before digesting"]
+    #[placeholder = "5"]
+    #[max_length = 4]
+    per_minute: String,
+    #[name = "Minutes collected into one digest"]
+    #[placeholder = "5"]
+    #[max_length = 4]
+    window_minutes: String,
+}
+
+/// One guild's settings, as shown in the config panel.
+struct GuildAlerts {
+    enabled: bool,
+    channel: Option<ChannelId>,
+    message_log: bool,
+    digest: DigestConfig,
+    events: HashSet<AlertKind>,
+}
+
+async fn guild_alerts(ctx: &Context, gid: GuildId) -> GuildAlerts {
+    let store = ctx.data.read().await.get::<ModAlertStore>().cloned();
+    let Some(store) = store else {
+        return GuildAlerts {
+            enabled: false,
+            channel: None,
+            message_log: false,
+            digest: DigestConfig::default(),
+            events: AlertKind::ALL.into_iter().collect(),
+        };
+    };
+    let settings = store.lock().await;
+    GuildAlerts {
+        enabled: settings.enabled.contains(&gid),
+        channel: settings.channels.get(&gid).copied(),
+        message_log: settings.message_log.contains(&gid),
+        digest: settings.digest.get(&gid).copied().unwrap_or_default(),
+        events: settings.events.get(&gid).cloned().unwrap_or_else(|| AlertKind::ALL.into_iter().collect()),
+    }
+}
+
+/// Change the settings and save them.
+async fn update(ctx: &Context, change: impl FnOnce(&mut ModAlertSettings)) -> Result<(), Error> {
+    let Some(store) = ctx.data.read().await.get::<ModAlertStore>().cloned() else {
+        return Err("Mod alert store not initialised".into());
+    };
+    change(&mut *store.lock().await);
+    save_modalert_store(ctx).await
+}
+
+fn config_view(alerts: &GuildAlerts, theme: &crate::embeds::Theme) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let events = AlertKind::ALL
+        .iter()
+        .filter(|k| alerts.events.contains(k))
+        .map(|k| k.label())
+        .collect::<Vec<_>>();
+    let embed = theme
+        .embed()
+        .title("Mod alerts")
+        .field("Status", if alerts.enabled { "On" } else { "Off" }, true)
+        .field(
+            "Sent to",
+            alerts.channel.map(|c| format!("<#{}>", c)).unwrap_or_else(|| "Server owner's DMs".to_string()),
+            true,
+        )
+        .field("Deleted-message log", if alerts.message_log { "On" } else { "Off" }, true)
+        .field("Events", if events.is_empty() { "None".to_string() } else { events.join(", ") }, false)
+        .field(
+            "Digest",
+            format!(
+                "More than {} alerts a minute are collected into one message every {} minutes",
+                alerts.digest.max_per_minute,
+                alerts.digest.window_secs / 60
+            ),
+            false,
+        );
+
+    let mut options: Vec<CreateSelectMenuOption> = AlertKind::ALL
+        .iter()
+        .map(|k| CreateSelectMenuOption::new(k.label(), k.key()).default_selection(alerts.events.contains(k)))
+        .collect();
+    options.push(
+        CreateSelectMenuOption::new("Deleted messages", "message_log")
+            .description("Log deleted messages (needs an alert channel)")
+            .default_selection(alerts.message_log),
+    );
+    let max = options.len() as u8;
+    let rows = vec![
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new("modalert:events", CreateSelectMenuKind::String { options })
+                .placeholder("No events reported")
+                .min_values(0)
+                .max_values(max),
+        ),
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                "modalert:channel",
+                CreateSelectMenuKind::Channel {
+                    channel_types: Some(vec![ChannelType::Text]),
+                    default_channels: Some(alerts.channel.into_iter().collect()),
+                },
+            )
+            .placeholder("Server owner's DMs")
+            .min_values(0)
+            .max_values(1),
+        ),
+        CreateActionRow::Buttons(vec![
+            if alerts.enabled {
+                CreateButton::new("modalert:toggle").label("Turn off").style(ButtonStyle::Danger)
+            } else {
+                CreateButton::new("modalert:toggle").label("Turn on").style(ButtonStyle::Success)
+            },
+            CreateButton::new("modalert:digest").label("Digest thresholds...").style(ButtonStyle::Secondary),
+            CreateButton::new("modalert:done").label("Done").style(ButtonStyle::Primary),
+        ]),
+    ];
+    (embed, rows)
+}
+
+/// Interactive settings for the guild: event types and the alert channel from select menus,
+/// digest thresholds from a modal. Every change is saved as it's made.
+pub async fn config_panel(ctx: crate::Ctx<'_>, gid: GuildId) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let theme = crate::embeds::theme(sctx, Some(gid)).await;
+    let (embed, rows) = config_view(&guild_alerts(sctx, gid).await, &theme);
+    let reply = ctx.send(poise::CreateReply::default().embed(embed).components(rows).ephemeral(true)).await?;
+    let message = reply.message().await?;
+
+    loop {
+        let press = ComponentInteractionCollector::new(sctx)
+            .message_id(message.id)
+            .timeout(CONFIG_TIMEOUT)
+            .await;
+        let Some(press) = press else {
+            let (embed, _) = config_view(&guild_alerts(sctx, gid).await, &theme);
+            reply.edit(ctx, poise::CreateReply::default().embed(embed).components(vec![])).await?;
+            return Ok(());
+        };
+        if press.user.id != ctx.author().id {
+            let notice = CreateInteractionResponseMessage::new()
+                .content("Only the person who opened these settings can change them.")
+                .ephemeral(true);
+            press.create_response(&sctx.http, CreateInteractionResponse::Message(notice)).await?;
+            continue;
+        }
+
+        match (press.data.custom_id.as_str(), &press.data.kind) {
+            ("modalert:events", ComponentInteractionDataKind::StringSelect { values }) => {
+                let events: HashSet<AlertKind> = values.iter().filter_map(|v| AlertKind::from_key(v)).collect();
+                let message_log = values.iter().any(|v| v == "message_log");
+                update(sctx, |s| {
+                    s.events.insert(gid, events);
+                    if message_log {
+                        s.message_log.insert(gid);
+                    } else {
+                        s.message_log.remove(&gid);
+                    }
+                })
+                .await?;
+            }
+            ("modalert:channel", ComponentInteractionDataKind::ChannelSelect { values }) => {
+                let channel = values.first().copied();
+                update(sctx, |s| {
+                    match channel {
+                        Some(c) => s.channels.insert(gid, c),
+                        None => s.channels.remove(&gid),
+                    };
+                })
+                .await?;
+            }
+            ("modalert:toggle", _) => {
+                update(sctx, |s| {
+                    if !s.enabled.remove(&gid) {
+                        s.enabled.insert(gid);
+                    }
+                })
+                .await?;
+            }
+            ("modalert:digest", _) => {
+                let current = guild_alerts(sctx, gid).await.digest;
+                let defaults = DigestModal {
+                    per_minute: current.max_per_minute.to_string(),
+                    window_minutes: (current.window_secs / 60).to_string(),
+                };
+                // The modal answers the press, so the panel is edited through the reply instead
+                let submitted =
+                    poise::execute_modal_on_component_interaction(ctx, press.clone(), Some(defaults), Some(CONFIG_TIMEOUT)).await?;
+                if let Some(modal) = submitted {
+                    match (modal.per_minute.trim().parse::<u32>(), modal.window_minutes.trim().parse::<u64>()) {
+                        (Ok(per_minute), Ok(minutes)) if per_minute >= 1 && minutes >= 1 => {
+                            let cfg = DigestConfig { max_per_minute: per_minute, window_secs: minutes * 60 };
+                            update(sctx, |s| {
+                                s.digest.insert(gid, cfg);
+                            })
+                            .await?;
+                        }
+                        _ => {
+                            let notice = "Both thresholds need to be whole numbers of at least 1.";
+                            ctx.send(poise::CreateReply::default().content(notice).ephemeral(true)).await?;
+                        }
+                    }
+                }
+                let (embed, rows) = config_view(&guild_alerts(sctx, gid).await, &theme);
+                reply.edit(ctx, poise::CreateReply::default().embed(embed).components(rows)).await?;
+                continue;
+            }
+            ("modalert:done", _) => {
+                let (embed, _) = config_view(&guild_alerts(sctx, gid).await, &theme);
+                let update = CreateInteractionResponseMessage::new().embed(embed).components(vec![]);
+                press.create_response(&sctx.http, CreateInteractionResponse::UpdateMessage(update)).await?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let (embed, rows) = config_view(&guild_alerts(sctx, gid).await, &theme);
+        let update = CreateInteractionResponseMessage::new().embed(embed).components(rows);
+        press.create_response(&sctx.http, CreateInteractionResponse::UpdateMessage(update)).await?;
+    }
+}
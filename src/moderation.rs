@@ -0,0 +1,580 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::builder::{CreateMessage, EditMember, GetMessages};
+use serenity::model::id::{GuildId, MessageId, UserId};
+use serenity::prelude::*;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::dryrun;
+use crate::modalert::{is_modalert_enabled, send_alert, AlertKind};
+use crate::scheduler::{self, JobFuture};
+use crate::{Ctx, Error};
+
+/// Discord caps member timeouts at 28 days.
+const MAX_TIMEOUT: Duration = Duration::from_secs(28 * 24 * 60 * 60);
+
+/// Timeouts applied by the bot itself. The `GuildMemberUpdate` alert skips these because the
+/// command already reported them with the moderator and reason attached.
+pub struct HandledTimeouts;
+impl TypeMapKey for HandledTimeouts {
+    type Value = Arc<Mutex<HashSet<(GuildId, UserId)>>>;
+}
+
+/// Returns true (and forgets the entry) if the bot applied this timeout itself.
+pub async fn take_handled_timeout(ctx: &Context, gid: GuildId, uid: UserId) -> bool {
+    let store = ctx.data.read().await.get::<HandledTimeouts>().cloned();
+    match store {
+        Some(store) => store.lock().await.remove(&(gid, uid)),
+        None => false,
+    }
+}
+
+/// Time out a member, marking the timeout as handled so the member-update alert doesn't repeat it.
+/// In dry-run mode the timeout is only logged.
+pub async fn apply_timeout(
+    ctx: &Context,
+    gid: GuildId,
+    uid: UserId,
+    dur: Duration,
+    reason: &str,
+) -> Result<(), Error> {
+    if dryrun::active(ctx, Some(gid)).await {
+        dryrun::skip(Some(gid), &format!("time out <@{}> for {} ({})", uid, format_duration(dur), reason));
+        return Ok(());
+    }
+    let until = serenity::Timestamp::from_unix_timestamp(
+        serenity::Timestamp::now().unix_timestamp() + dur.min(MAX_TIMEOUT).as_secs() as i64,
+    )?;
+
+    let handled = ctx.data.read().await.get::<HandledTimeouts>().cloned();
+    if let Some(store) = &handled {
+        store.lock().await.insert((gid, uid));
+    }
+    let edit = EditMember::new()
+        .disable_communication_until_datetime(until)
+        .audit_log_reason(reason);
+    if let Err(e) = gid.edit_member(ctx, uid, edit).await {
+        if let Some(store) = &handled {
+            store.lock().await.remove(&(gid, uid));
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Parse durations like `90s`, `10m`, `1h30m` or `2d`. A bare number is taken as minutes.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim().to_ascii_lowercase();
+    if s.is_empty() {
+        return None;
+    }
+    if let Ok(mins) = s.parse::<u64>() {
+        return Some(Duration::from_secs(mins * 60));
+    }
+
+    let mut total = 0u64;
+    let mut num = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let n: u64 = num.parse().ok()?;
+        num.clear();
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        total = total.checked_add(n.checked_mul(unit)?)?;
+    }
+    if !num.is_empty() || total == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(total))
+}
+
+/// Render a duration as e.g. `1d 2h 5m`.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (days, hours, mins, secs) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+    let mut parts = Vec::new();
+    if days > 0 {
+        parts.push(format!("{days}d"));
+    }
+    if hours > 0 {
+        parts.push(format!("{hours}h"));
+    }
+    if mins > 0 {
+        parts.push(format!("{mins}m"));
+    }
+    if secs > 0 || parts.is_empty() {
+        parts.push(format!("{secs}s"));
+    }
+    parts.join(" ")
+}
+
+/// Report a moderation action through the modalert pipeline (if enabled for the guild). Actions
+/// simulated in dry-run mode are marked as such.
+pub async fn report(ctx: &Context, gid: GuildId, kind: AlertKind, detail: String) {
+    if !is_modalert_enabled(ctx, gid).await {
+        return;
+    }
+    let content = if dryrun::active(ctx, Some(gid)).await {
+        format!("Moderation alert (dry run, nothing was changed): {}", detail)
+    } else {
+        format!("Moderation alert: {}", detail)
+    };
+    if let Err(e) = send_alert(ctx, gid, kind, detail, CreateMessage::new().content(content)).await {
+        tracing::warn!("Failed to send mod alert for guild {}: {e:?}", gid);
+    }
+}
+
+fn reason_or_default(reason: &Option<String>) -> &str {
+    reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).unwrap_or("No reason given")
+}
+
+/// Warn a member (they receive a DM with the reason)
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MODERATE_MEMBERS",
+    default_member_permissions = "MODERATE_MEMBERS"
+)]
+pub async fn warn(
+    ctx: Ctx<'_>,
+    #[description = "Member to warn"] user: serenity::User,
+    #[description = "Reason"]
+    #[rest]
+    reason: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let reason = reason_or_default(&reason);
+
+    let guild_name = guild_id.name(&sctx.cache).unwrap_or_else(|| guild_id.to_string());
+    let dm_sent = match user.create_dm_channel(&sctx.http).await {
+        Ok(dm) => dm
+            .say(&sctx.http, format!("You have been warned in {}: {}", guild_name, reason))
+            .await
+            .is_ok(),
+        Err(_) => false,
+    };
+
+    let count = crate::warnings::record_warning(sctx, guild_id, user.id, ctx.author().id, reason).await?;
+
+    report(
+        sctx,
+        guild_id,
+        AlertKind::Warn,
+        format!(
+            "{} was warned by {} (warning #{}). Reason: {}",
+            user.tag(),
+            ctx.author().tag(),
+            count,
+            reason
+        ),
+    )
+    .await;
+
+    let note = if dm_sent { "" } else { " (could not DM them)" };
+    let mut reply = format!("Warned {}{} (warning #{}). Reason: {}", user.tag(), note, count, reason);
+    if let Some(escalated) = crate::warnings::escalate(sctx, guild_id, &user, count).await {
+        reply.push('\n');
+        reply.push_str(&escalated);
+    }
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+/// Time out a member for a duration such as 10m, 1h or 2d
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MODERATE_MEMBERS",
+    required_bot_permissions = "MODERATE_MEMBERS",
+    default_member_permissions = "MODERATE_MEMBERS"
+)]
+pub async fn timeout(
+    ctx: Ctx<'_>,
+    #[description = "Member to time out"] user: serenity::User,
+    #[description = "Duration, e.g. 10m, 1h, 2d (max 28d)"] duration: String,
+    #[description = "Reason"]
+    #[rest]
+    reason: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let reason = reason_or_default(&reason);
+
+    let Some(dur) = parse_duration(&duration) else {
+        ctx.say(format!("Couldn't parse duration '{}'. Use e.g. 10m, 1h30m or 2d.", duration)).await?;
+        return Ok(());
+    };
+    if dur > MAX_TIMEOUT {
+        ctx.say("Timeouts can be at most 28 days.").await?;
+        return Ok(());
+    }
+    if dryrun::active(sctx, Some(guild_id)).await {
+        let action = format!("time out {} for {} (reason: {})", user.tag(), format_duration(dur), reason);
+        ctx.say(dryrun::skip(Some(guild_id), &action)).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = apply_timeout(sctx, guild_id, user.id, dur, reason).await {
+        ctx.say(format!("Failed to time out {}: {}", user.tag(), e)).await?;
+        return Ok(());
+    }
+
+    report(
+        sctx,
+        guild_id,
+        AlertKind::Timeout,
+        format!(
+            "{} was timed out for {} by {}. Reason: {}",
+            user.tag(),
+            format_duration(dur),
+            ctx.author().tag(),
+            reason
+        ),
+    )
+    .await;
+
+    ctx.say(format!("Timed out {} for {}. Reason: {}", user.tag(), format_duration(dur), reason)).await?;
+    Ok(())
+}
+
+/// Kick a member from the server
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "KICK_MEMBERS",
+    required_bot_permissions = "KICK_MEMBERS",
+    default_member_permissions = "KICK_MEMBERS"
+)]
+pub async fn kick(
+    ctx: Ctx<'_>,
+    #[description = "Member to kick"] user: serenity::User,
+    #[description = "Reason"]
+    #[rest]
+    reason: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let reason = reason_or_default(&reason);
+
+    if dryrun::active(sctx, Some(guild_id)).await {
+        ctx.say(dryrun::skip(Some(guild_id), &format!("kick {} (reason: {})", user.tag(), reason))).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = guild_id.kick_with_reason(&sctx.http, user.id, reason).await {
+        ctx.say(format!("Failed to kick {}: {}", user.tag(), e)).await?;
+        return Ok(());
+    }
+
+    report(
+        sctx,
+        guild_id,
+        AlertKind::Kick,
+        format!("{} was kicked by {}. Reason: {}", user.tag(), ctx.author().tag(), reason),
+    )
+    .await;
+
+    ctx.say(format!("Kicked {}. Reason: {}", user.tag(), reason)).await?;
+    Ok(())
+}
+
+/// Ban a user, optionally deleting their recent messages
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "BAN_MEMBERS",
+    required_bot_permissions = "BAN_MEMBERS",
+    default_member_permissions = "BAN_MEMBERS"
+)]
+pub async fn ban(
+    ctx: Ctx<'_>,
+    #[description = "User to ban"] user: serenity::User,
+    #[description = "Days of messages to delete (0-7)"]
+    #[max = 7]
+    delete_days: Option<u8>,
+    #[description = "Reason"]
+    #[rest]
+    reason: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let reason = reason_or_default(&reason);
+    let delete_days = delete_days.unwrap_or(0).min(7);
+
+    if dryrun::active(sctx, Some(guild_id)).await {
+        let action = format!("ban {}, deleting {} days of messages (reason: {})", user.tag(), delete_days, reason);
+        ctx.say(dryrun::skip(Some(guild_id), &action)).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = guild_id.ban_with_reason(&sctx.http, user.id, delete_days, reason).await {
+        ctx.say(format!("Failed to ban {}: {}", user.tag(), e)).await?;
+        return Ok(());
+    }
+
+    report(
+        sctx,
+        guild_id,
+        AlertKind::Ban,
+        format!("{} was banned by {}. Reason: {}", user.tag(), ctx.author().tag(), reason),
+    )
+    .await;
+
+    ctx.say(format!("Banned {}. Reason: {}", user.tag(), reason)).await?;
+    Ok(())
+}
+
+/// Scheduler job lifting a temporary ban.
+pub const UNBAN_JOB: &str = "unban";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct UnbanJob {
+    guild_id: u64,
+    user_id: u64,
+}
+
+async fn cancel_unban(ctx: &Context, job: &UnbanJob) -> Result<usize, Error> {
+    scheduler::cancel_where(ctx, UNBAN_JOB, |payload| {
+        serde_json::from_value::<UnbanJob>(payload.clone()).is_ok_and(|p| p == *job)
+    })
+    .await
+}
+
+/// Drop the pending tempban lifts for `gid`, for `admin purge-guild-data`.
+pub async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<usize, Error> {
+    scheduler::cancel_where(ctx, UNBAN_JOB, |payload| {
+        serde_json::from_value::<UnbanJob>(payload.clone()).is_ok_and(|p| p.guild_id == gid.get())
+    })
+    .await
+}
+
+pub fn run_unban_job(ctx: &Context, payload: serde_json::Value) -> JobFuture<'_> {
+    Box::pin(async move {
+        let job: UnbanJob = serde_json::from_value(payload)?;
+        let gid = GuildId::new(job.guild_id);
+        let uid = UserId::new(job.user_id);
+        if let Err(e) = gid.unban(&ctx.http, uid).await {
+            // Already unbanned by hand; nothing left to do
+            if matches!(&e, serenity::Error::Http(h) if h.status_code() == Some(serenity::StatusCode::NOT_FOUND)) {
+                return Ok(());
+            }
+            return Err(e.into());
+        }
+        let who = match uid.to_user(ctx).await {
+            Ok(u) => u.tag(),
+            Err(_) => format!("<@{}>", uid),
+        };
+        report(
+            ctx,
+            gid,
+            AlertKind::Unban,
+            format!("{} was automatically unbanned (temporary ban expired).", who),
+        )
+        .await;
+        Ok(())
+    })
+}
+
+/// Ban a member and lift the ban automatically after a duration
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "BAN_MEMBERS",
+    required_bot_permissions = "BAN_MEMBERS",
+    default_member_permissions = "BAN_MEMBERS"
+)]
+pub async fn tempban(
+    ctx: Ctx<'_>,
+    #[description = "User to ban"] user: serenity::User,
+    #[description = "How long, e.g. 1h, 2d, 1w"] duration: String,
+    #[description = "Reason"]
+    #[rest]
+    reason: Option<String>,
+) -> Result<(), Error> {
+    let Some(dur) = parse_duration(&duration) else {
+        ctx.say("Invalid duration. Use something like `30m`, `12h` or `7d`.").await?;
+        return Ok(());
+    };
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let reason = reason_or_default(&reason);
+
+    if dryrun::active(sctx, Some(guild_id)).await {
+        let action = format!("ban {} for {} (reason: {})", user.tag(), format_duration(dur), reason);
+        ctx.say(dryrun::skip(Some(guild_id), &action)).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = guild_id.ban_with_reason(&sctx.http, user.id, 0, reason).await {
+        ctx.say(format!("Failed to ban {}: {}", user.tag(), e)).await?;
+        return Ok(());
+    }
+
+    // A new tempban replaces any pending unban for the same member
+    let job = UnbanJob { guild_id: guild_id.get(), user_id: user.id.get() };
+    cancel_unban(sctx, &job).await?;
+    let unban_at = serenity::Timestamp::now().unix_timestamp() + dur.as_secs() as i64;
+    scheduler::schedule(sctx, UNBAN_JOB, unban_at, &job).await?;
+
+    report(
+        sctx,
+        guild_id,
+        AlertKind::Ban,
+        format!(
+            "{} was banned for {} by {}. Reason: {}",
+            user.tag(),
+            format_duration(dur),
+            ctx.author().tag(),
+            reason
+        ),
+    )
+    .await;
+
+    ctx.say(format!(
+        "Banned {} until <t:{}:f>. Reason: {}",
+        user.tag(),
+        unban_at,
+        reason
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Lift a ban
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "BAN_MEMBERS",
+    required_bot_permissions = "BAN_MEMBERS",
+    default_member_permissions = "BAN_MEMBERS"
+)]
+pub async fn unban(
+    ctx: Ctx<'_>,
+    #[description = "User to unban (mention or id)"] user: serenity::User,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+
+    if dryrun::active(sctx, Some(guild_id)).await {
+        ctx.say(dryrun::skip(Some(guild_id), &format!("unban {}", user.tag()))).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = guild_id.unban(&sctx.http, user.id).await {
+        ctx.say(format!("Failed to unban {}: {}", user.tag(), e)).await?;
+        return Ok(());
+    }
+    cancel_unban(sctx, &UnbanJob { guild_id: guild_id.get(), user_id: user.id.get() }).await?;
+
+    report(
+        sctx,
+        guild_id,
+        AlertKind::Unban,
+        format!("{} was unbanned by {}.", user.tag(), ctx.author().tag()),
+    )
+    .await;
+
+    ctx.say(format!("Unbanned {}.", user.tag())).await?;
+    Ok(())
+}
+
+/// Delete the most recent messages in this channel
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES",
+    required_bot_permissions = "MANAGE_MESSAGES",
+    default_member_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn purge(
+    ctx: Ctx<'_>,
+    #[description = "Number of messages to delete (1-100)"]
+    #[min = 1]
+    #[max = 100]
+    count: u8,
+) -> Result<(), Error> {
+    // Ephemeral so the deferred reply isn't swept up by the purge itself
+    ctx.defer_ephemeral().await?;
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let channel_id = ctx.channel_id();
+    let count = count.clamp(1, 100);
+
+    // Snowflakes are time-ordered, so the invocation id works as a "before now" cursor for
+    // both prefix (message id) and slash (interaction id) invocations
+    let messages = channel_id
+        .messages(sctx, GetMessages::new().before(MessageId::new(ctx.id())).limit(count))
+        .await?;
+
+    // Bulk delete only accepts messages younger than 14 days
+    let cutoff = serenity::Timestamp::now().unix_timestamp() - 14 * 24 * 60 * 60;
+    let ids: Vec<MessageId> = messages
+        .iter()
+        .filter(|m| m.timestamp.unix_timestamp() > cutoff)
+        .map(|m| m.id)
+        .collect();
+    let skipped = messages.len() - ids.len();
+
+    if dryrun::active(sctx, Some(guild_id)).await {
+        let action = format!("delete {} messages in <#{}> ({} skipped as older than 14 days)", ids.len(), channel_id, skipped);
+        ctx.say(dryrun::skip(Some(guild_id), &action)).await?;
+        return Ok(());
+    }
+
+    let result = match ids.as_slice() {
+        [] => Ok(()),
+        [single] => channel_id.delete_message(&sctx.http, *single).await,
+        _ => channel_id.delete_messages(&sctx.http, &ids).await,
+    };
+    if let Err(e) = result {
+        ctx.say(format!("Failed to purge messages: {}", e)).await?;
+        return Ok(());
+    }
+
+    if !ids.is_empty() {
+        report(
+            sctx,
+            guild_id,
+            AlertKind::Purge,
+            format!("{} messages were purged in <#{}> by {}.", ids.len(), channel_id, ctx.author().tag()),
+        )
+        .await;
+    }
+
+    let mut reply = format!("Deleted {} messages.", ids.len());
+    if skipped > 0 {
+        reply.push_str(&format!(" Skipped {} older than 14 days.", skipped));
+    }
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![warn(), timeout(), kick(), ban(), tempban(), unban(), purge()]
+}
@@ -1,7 +1,5 @@
-use base64::engine::general_purpose::STANDARD as B64_ENGINE;
-use base64::Engine;
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serenity::{
     builder::{CreateEmbed, CreateMessage},
     model::prelude::*,
@@ -10,80 +8,57 @@ use serenity::{
 use std::env;
 use tokio::fs;
 use std::path::PathBuf;
+use crate::embeds::Theme;
 use serenity::async_trait;
 
 type MusicResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-async fn store_handle(ctx: &Context, guild_id: GuildId, handle: songbird::tracks::TrackHandle) -> Result<(), ()> {
+pub async fn store_handle(ctx: &Context, guild_id: GuildId, handle: songbird::tracks::TrackHandle) -> Result<(), ()> {
     let maybe_store = ctx.data.read().await.get::<crate::TrackStore>().cloned();
     if let Some(store) = maybe_store {
-        let mut map = store.lock().await;
-        map.insert(guild_id, handle);
+        crate::queue::track_started(ctx, guild_id, &handle);
+        crate::presence::track_started(ctx, guild_id, Some(&handle)).await;
+        crate::queue::record_played(ctx, guild_id).await;
+        store.lock().await.insert(guild_id, handle);
+        let event = crate::events::track_started(ctx, guild_id).await;
+        crate::events::emit(ctx, guild_id, event).await;
         Ok(())
     } else {
         Err(())
     }
 }
 
-#[derive(Deserialize)]
-struct SpotifyToken {
-    access_token: String,
-}
-
-#[derive(Deserialize)]
-struct SpotifySearch {
-    tracks: SpotifyTracks,
-}
-
-#[derive(Deserialize)]
-struct SpotifyTracks {
-    items: Vec<SpotifyTrack>,
-}
-
-#[derive(Deserialize)]
-struct SpotifyTrack {
-    name: String,
-    artists: Vec<SpotifyArtist>,
-}
-
-#[derive(Deserialize)]
-struct SpotifyArtist {
-    name: String,
-}
-
-pub async fn handle_music(
-    ctx: &Context,
-    channel: ChannelId,
-    user_voice: Option<ChannelId>,
-    user_id: UserId,
-    guild_id: Option<GuildId>,
-    args: &str,
-    embed_color: u32,
-) -> serenity::Result<()> {
+/// Run a `music` subcommand. Replies go through `cmd` (the interaction for slash commands);
+/// "Now playing" and the control panel are posted to its channel.
+pub async fn handle_music(cmd: crate::Ctx<'_>, user_voice: Option<ChannelId>, args: &str) -> serenity::Result<()> {
+    let ctx = cmd.serenity_context();
+    let guild_id = cmd.guild_id();
+    let theme = crate::embeds::theme(ctx, guild_id).await;
     let mut parts = args.split_whitespace();
     let sub = parts.next().unwrap_or("");
     let remainder = parts.collect::<Vec<_>>().join(" ");
 
     let result: MusicResult<()> = match sub {
-        "join" => join(ctx, channel, user_voice, user_id, guild_id, &remainder, embed_color).await,
-        "leave" => leave(ctx, channel, user_id, guild_id, embed_color).await,
-        "play" => play(ctx, channel, user_id, guild_id, &remainder, embed_color).await,
+        "join" => join(cmd, user_voice, &remainder, &theme).await,
+        "leave" => leave(cmd, &theme).await,
+        "play" => play(cmd, &remainder, &theme).await,
         "control" => {
             if let Some(gid) = guild_id {
-                if let Err(e) = send_control_panel(ctx, channel, user_id, gid, embed_color).await {
-                    eprintln!("Failed to send control panel: {e:?}");
+                let channel = session_channel(ctx, gid, cmd.channel_id()).await;
+                if let Err(e) = send_control_panel(ctx, channel, cmd.author().id, gid, &theme).await {
+                    tracing::warn!("Failed to send control panel: {e:?}");
                 }
                 Ok(())
             } else {
-                send_info(ctx, channel, embed_color, "Music", "Controls only available in a guild").await
+                notify(cmd, &theme, "Music", "Controls only available in a guild").await
             }
         }
-        _ => send_info(ctx, channel, embed_color, "Music", "Subcommands: join, play <song>, leave, control").await,
+        _ => notify(cmd, &theme, "Music", "Subcommands: join, play <song>, leave, control").await,
     };
 
     if let Err(err) = result {
-        eprintln!("Music command error: {err:?}");
-        let _ = send_info(ctx, channel, embed_color, "Music Error", &format!("{err}"),).await;
+        tracing::warn!("Music command error: {err:?}");
+        let _ = notify(cmd, &theme, "Music Error", &format!("{err}")).await;
     }
 
     Ok(())
@@ -92,84 +67,195 @@ pub async fn handle_music(
 pub async fn ensure_media_tools() -> MusicResult<()> {
     const BIN_DIR: &str = ".bin";
     const YTDLP_BIN: &str = "yt-dlp";
-    const YTDLP_URL: &str = "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp";
-
-    let ytdlp_path = PathBuf::from(BIN_DIR).join(YTDLP_BIN);
-
-    if fs::metadata(&ytdlp_path).await.is_err() {
-        fs::create_dir_all(BIN_DIR).await?;
-        let bytes = Client::new()
-            .get(YTDLP_URL)
-            .send()
-            .await?
-            .error_for_status()?;
-        let content = bytes.bytes().await?;
-        fs::write(&ytdlp_path, &content).await?;
-
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&ytdlp_path).await?.permissions();
-            perms.set_mode(0o755);
-            fs::set_permissions(&ytdlp_path, perms).await?;
+
+    let bin_dir = crate::storage::data_path(BIN_DIR);
+    let ytdlp_path = bin_dir.join(YTDLP_BIN);
+
+    // Missing, or not the file a verified download left behind
+    if !crate::verify::is_verified(&ytdlp_path).await {
+        match download_ytdlp(&ytdlp_path).await {
+            Ok(()) => tracing::info!("Downloaded and verified yt-dlp"),
+            Err(e) if fs::metadata(&ytdlp_path).await.is_ok() => {
+                tracing::warn!("Couldn't download a verified yt-dlp, keeping the existing {}: {e}", ytdlp_path.display());
+            }
+            Err(e) => return Err(e),
         }
     }
 
     // Verify ffmpeg is available on PATH — log a warning if not
     match tokio::process::Command::new("ffmpeg").arg("-version").output().await {
         Ok(o) if o.status.success() => {
-            println!("ffmpeg found");
+            tracing::info!("ffmpeg found");
         }
         Ok(o) => {
-            eprintln!("ffmpeg exists but failed to run: {}", String::from_utf8_lossy(&o.stderr));
+            tracing::warn!("ffmpeg exists but failed to run: {}", String::from_utf8_lossy(&o.stderr));
         }
         Err(_) => {
-            eprintln!("Warning: ffmpeg not found on PATH. Playback may fail.");
+            tracing::warn!("Warning: ffmpeg not found on PATH. Playback may fail.");
         }
     }
 
-    prepend_path(BIN_DIR)?;
+    prepend_path(&bin_dir)?;
     Ok(())
 }
 
-/// Ensure an optional Spotify stream helper binary is present in `.bin/librespot-wrapper`.
-/// The downloader will attempt to fetch the URL from `SPOTIFY_WRAPPER_URL` if set.
-pub async fn ensure_spotify_helper() -> MusicResult<()> {
-    const BIN_DIR: &str = ".bin";
-    const WRAPPER_BIN: &str = "librespot-wrapper";
+const HELPER_BIN_DIR: &str = ".bin";
+const HELPER_BIN: &str = "librespot-wrapper";
+/// The bundled helper's source, built by `build_spotify_helper`.
+const HELPER_SOURCE: &str = "tools/librespot-wrapper";
+
+/// The bundled helper's version plus a fingerprint of its source, e.g. `0.1.0+1a2b3c4d5e6f7a8b`,
+/// so an edited or updated checkout gets rebuilt.
+async fn helper_source_version() -> MusicResult<String> {
+    let manifest = fs::read_to_string(PathBuf::from(HELPER_SOURCE).join("Cargo.toml")).await?;
+    let main = fs::read(PathBuf::from(HELPER_SOURCE).join("src").join("main.rs")).await?;
+    let version = manifest
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("version = "))
+        .map(|v| v.trim_matches('"').to_string())
+        .unwrap_or_else(|| "0.0.0".to_string());
+    let mut source = manifest.into_bytes();
+    source.extend(main);
+    Ok(format!("{}+{:016x}", version, crate::registration::fnv1a(&source)))
+}
 
-    let wrapper_path = PathBuf::from(BIN_DIR).join(WRAPPER_BIN);
+/// Where the installed helper's version stamp lives.
+fn helper_stamp_path() -> PathBuf {
+    crate::storage::data_path(HELPER_BIN_DIR).join(format!("{}.version", HELPER_BIN))
+}
 
-    // If the wrapper already exists, nothing to do
-    if fs::metadata(&wrapper_path).await.is_ok() {
+/// Build the bundled helper with `cargo build --release` and install it as
+/// `.bin/librespot-wrapper`, writing its version to `.bin/librespot-wrapper.version`. Returns
+/// the version.
+pub async fn build_spotify_helper() -> MusicResult<String> {
+    let version = helper_source_version().await?;
+    tracing::info!("Building Spotify helper {} from {}", version, HELPER_SOURCE);
+    let out = tokio::process::Command::new("cargo")
+        .kill_on_drop(true)
+        .args(["build", "--release", "--manifest-path"])
+        .arg(PathBuf::from(HELPER_SOURCE).join("Cargo.toml"))
+        .output()
+        .await?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(15)..].join("\n");
+        return Err(format!("cargo build failed:\n{}", tail).into());
+    }
+
+    let built = PathBuf::from(HELPER_SOURCE)
+        .join("target")
+        .join("release")
+        .join(format!("{}{}", HELPER_BIN, env::consts::EXE_SUFFIX));
+    let installed = crate::storage::data_path(HELPER_BIN_DIR).join(HELPER_BIN);
+    fs::create_dir_all(crate::storage::data_path(HELPER_BIN_DIR)).await?;
+    // Copy next to it and rename, so a stream starting mid-copy never runs half a binary
+    let partial = installed.with_extension("partial");
+    fs::copy(&built, &partial).await?;
+    fs::rename(&partial, &installed).await?;
+    fs::write(helper_stamp_path(), &version).await?;
+    tracing::info!("Installed Spotify helper {} to {}", version, installed.display());
+    Ok(version)
+}
+
+/// Download the latest yt-dlp release to `path`, checking it against the release's
+/// `SHA2-256SUMS` (and that file's GPG signature when `YTDLP_GPG_KEYRING` names a keyring with
+/// the yt-dlp signing key) before it replaces anything.
+async fn download_ytdlp(path: &std::path::Path) -> MusicResult<()> {
+    const RELEASE: &str = "https://github.com/yt-dlp/yt-dlp/releases/latest/download";
+
+    let client = Client::new();
+    let fetch = |file: &str| {
+        let request = client.get(format!("{}/{}", RELEASE, file));
+        async move { request.send().await?.error_for_status()?.bytes().await }
+    };
+    let binary = fetch("yt-dlp").await?;
+    let sums = fetch("SHA2-256SUMS").await?;
+    if let Ok(keyring) = env::var("YTDLP_GPG_KEYRING") {
+        // With a keyring set, an unsigned update is refused rather than installed on its checksum alone
+        let signature = fetch("SHA2-256SUMS.sig")
+            .await
+            .map_err(|e| format!("couldn't download SHA2-256SUMS.sig to check yt-dlp's signature, not updating: {}", e))?;
+        crate::verify::check_gpg(&sums, &signature, &keyring, "yt-dlp's SHA2-256SUMS").await?;
+    }
+    let expected = crate::verify::find_checksum(&String::from_utf8_lossy(&sums), "yt-dlp")
+        .ok_or("yt-dlp's SHA2-256SUMS has no entry for yt-dlp")?;
+    crate::verify::check_sha256(&binary, &expected, "yt-dlp")?;
+    crate::verify::install(path, &binary).await
+}
+
+/// Download the helper at `url` to `path`. It needs a SHA-256 from `SPOTIFY_WRAPPER_SHA256` or
+/// a `<url>.sha256` file next to it, and a valid `<url>.minisig` when
+/// `SPOTIFY_WRAPPER_MINISIGN_KEY` is set; anything unverified is refused.
+async fn download_spotify_helper(url: &str, path: &std::path::Path) -> MusicResult<()> {
+    let client = Client::new();
+    let binary = client.get(url).send().await?.error_for_status()?.bytes().await?;
+    let expected = match env::var("SPOTIFY_WRAPPER_SHA256") {
+        Ok(hash) => hash,
+        Err(_) => {
+            let name = url.rsplit('/').next().unwrap_or(HELPER_BIN);
+            let res = client.get(format!("{}.sha256", url)).send().await?;
+            let sums = if res.status().is_success() { res.text().await? } else { String::new() };
+            crate::verify::find_checksum(&sums, name).ok_or(
+                "No checksum for the Spotify helper (set SPOTIFY_WRAPPER_SHA256 or publish <url>.sha256); refusing to install it",
+            )?
+        }
+    };
+    crate::verify::check_sha256(&binary, &expected, "The Spotify helper")?;
+    if let Ok(key) = env::var("SPOTIFY_WRAPPER_MINISIGN_KEY") {
+        let signature = async { client.get(format!("{}.minisig", url)).send().await?.error_for_status()?.text().await }
+            .await
+            .map_err(|e| format!("couldn't download {}.minisig to check the Spotify helper's signature, not updating: {}", url, e))?;
+        crate::verify::check_minisign(&binary, &signature, &key, "The Spotify helper")?;
+    }
+    crate::verify::install(path, &binary).await
+}
+
+/// Ensure an optional Spotify stream helper binary is present in `.bin/librespot-wrapper`.
+/// With `SPOTIFY_WRAPPER_BUILD=1` it's built from `tools/librespot-wrapper` whenever the
+/// installed one is missing or its version stamp doesn't match the source; otherwise the
+/// downloader will attempt to fetch the URL from `SPOTIFY_WRAPPER_URL` if set.
+pub async fn ensure_spotify_helper() -> MusicResult<()> {
+    let wrapper_path = crate::storage::data_path(HELPER_BIN_DIR).join(HELPER_BIN);
+
+    let build = env::var("SPOTIFY_WRAPPER_BUILD").map(|s| matches!(s.as_str(), "1" | "true" | "TRUE" | "True")).unwrap_or(false);
+    if build {
+        let wanted = helper_source_version().await?;
+        let installed = fs::read_to_string(helper_stamp_path()).await.ok();
+        let current = installed.as_deref().map(str::trim) == Some(wanted.as_str()) && fs::metadata(&wrapper_path).await.is_ok();
+        if !current {
+            build_spotify_helper().await?;
+        }
+        prepend_path(&crate::storage::data_path(HELPER_BIN_DIR))?;
         return Ok(());
     }
 
     // Check for SPOTIFY_WRAPPER_URL env var to download a prebuilt helper
     if let Ok(url) = std::env::var("SPOTIFY_WRAPPER_URL") {
-        fs::create_dir_all(BIN_DIR).await?;
-        eprintln!("Downloading Spotify helper from {}", url);
-        let bytes = Client::new().get(&url).send().await?.error_for_status()?;
-        let content = bytes.bytes().await?;
-        fs::write(&wrapper_path, &content).await?;
-
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&wrapper_path).await?.permissions();
-            perms.set_mode(0o755);
-            fs::set_permissions(&wrapper_path, perms).await?;
+        // Already downloaded and unchanged since
+        if crate::verify::is_verified(&wrapper_path).await {
+            prepend_path(&crate::storage::data_path(HELPER_BIN_DIR))?;
+            return Ok(());
         }
-
-        prepend_path(BIN_DIR)?;
-        println!("Downloaded Spotify helper to {}", wrapper_path.display());
+        tracing::warn!("Downloading Spotify helper from {}", url);
+        match download_spotify_helper(&url, &wrapper_path).await {
+            Ok(()) => tracing::info!("Downloaded and verified Spotify helper to {}", wrapper_path.display()),
+            Err(e) if fs::metadata(&wrapper_path).await.is_ok() => {
+                tracing::warn!("Couldn't download a verified Spotify helper, keeping the existing one: {e}");
+            }
+            Err(e) => return Err(e),
+        }
+        prepend_path(&crate::storage::data_path(HELPER_BIN_DIR))?;
+        Ok(())
+    } else if fs::metadata(&wrapper_path).await.is_ok() {
+        // Put there by hand or built locally
         Ok(())
     } else {
         // No auto-download URL provided — leave an example wrapper behind so users can configure one
-        let example_path = PathBuf::from(BIN_DIR).join(format!("{}.example", WRAPPER_BIN));
+        let example_path = crate::storage::data_path(HELPER_BIN_DIR).join(format!("{}.example", HELPER_BIN));
         if fs::metadata(&example_path).await.is_err() {
             let example_script = include_str!("../.bin/librespot-wrapper.example");
-            fs::create_dir_all(BIN_DIR).await?;
+            fs::create_dir_all(crate::storage::data_path(HELPER_BIN_DIR)).await?;
             fs::write(&example_path, example_script).await?;
             #[cfg(unix)]
             {
@@ -178,14 +264,16 @@ pub async fn ensure_spotify_helper() -> MusicResult<()> {
                 perms.set_mode(0o644);
                 fs::set_permissions(&example_path, perms).await?;
             }
-            eprintln!("Wrote example Spotify helper to {}. To enable auto-download, set SPOTIFY_WRAPPER_URL to a prebuilt binary URL.", example_path.display());
+            tracing::warn!("Wrote example Spotify helper to {}. To enable auto-download, set SPOTIFY_WRAPPER_URL to a prebuilt binary URL.", example_path.display());
         }
         Ok(())
     }
 }
 
-async fn join(ctx: &Context, channel: ChannelId, user_voice: Option<ChannelId>, user_id: UserId, guild_id: Option<GuildId>, args: &str, color: u32) -> MusicResult<()> {
-    let guild_id = guild_id.ok_or("This command only works in a guild")?;
+async fn join(cmd: crate::Ctx<'_>, user_voice: Option<ChannelId>, args: &str, theme: &Theme) -> MusicResult<()> {
+    let ctx = cmd.serenity_context();
+    let user_id = cmd.author().id;
+    let guild_id = cmd.guild_id().ok_or("This command only works in a guild")?;
 
     // Allow optional channel id argument: "music join <channel>". Priority: explicit arg -> provided user_voice
     let mut channel_id = args
@@ -195,12 +283,12 @@ async fn join(ctx: &Context, channel: ChannelId, user_voice: Option<ChannelId>,
         .map(ChannelId::from);
 
     if let Some(guild) = ctx.cache.guild(guild_id) {
-      eprintln!("Voice states:");
+      tracing::warn!("Voice states:");
       for (uid, vs) in &guild.voice_states {
-        eprintln!("user={} channel={:?}", uid.get(), vs.channel_id);
+        tracing::warn!("user={} channel={:?}", uid.get(), vs.channel_id);
       }
     } else {
-      eprintln!("Guild not in cache");
+      tracing::warn!("Guild not in cache");
     }
 
 
@@ -208,27 +296,34 @@ async fn join(ctx: &Context, channel: ChannelId, user_voice: Option<ChannelId>,
     if channel_id.is_none() {
         if let Some(v) = voice_channel_for_user_id(ctx, guild_id, user_id) {
             channel_id = Some(v);
-            eprintln!("Detected user voice channel from cache: {:?}", v);
+            tracing::warn!("Detected user voice channel from cache: {:?}", v);
         } else {
             // fallback to the precomputed user_voice (from message handler)
             channel_id = user_voice;
         }
+        // Someone idling in the AFK channel didn't mean for music to start there
+        if channel_id.is_some() && channel_id == crate::follow::afk_channel(ctx, guild_id) {
+            notify(cmd, theme, "Music", "You're in the AFK channel; join another voice channel or name one: music join <channel>").await?;
+            return Err("Won't join the AFK channel".into());
+        }
     }
 
-    // Inform the user which voice channel we will join (ephemeral-like): auto-delete after a few seconds
-    if let Some(cid) = channel_id {
+    // Tell slash users which voice channel we picked while the connection is set up; only
+    // when it stays private, or it's just more clutter before "Joined"
+    if let Some(cid) = channel_id
+        && crate::settings::ephemeral(cmd).await
+    {
         let notice = format!("Joining <#{}> (requested by <@{}>)", cid.get(), user_id);
-        let _ = send_temp_info(ctx.clone(), channel, &notice).await;
+        let _ = notify(cmd, theme, "Music", &notice).await;
     }
 
     let channel_id = match channel_id {
         Some(cid) => cid,
         None => {
             // Provide a simple diagnostic without needing cache access
-            let _ = send_info(
-                ctx,
-                channel,
-                color,
+            let _ = notify(
+                cmd,
+                theme,
                 "Music",
                 "Couldn't determine your voice channel. Join a voice channel or provide channel id: is; music join <channel>",
             )
@@ -238,251 +333,748 @@ async fn join(ctx: &Context, channel: ChannelId, user_voice: Option<ChannelId>,
         }
     };
 
+    connect(ctx, guild_id, channel_id).await?;
+
+    let joined = match open_session_thread(cmd, guild_id).await {
+        Some(thread) => format!("Joined <#{}>; music requests and updates go to <#{}>", channel_id.get(), thread.get()),
+        None => format!("Joined <#{}>", channel_id.get()),
+    };
+    notify(cmd, theme, "Music", &joined).await?;
+
+    Ok(())
+}
+
+/// Join (or move to) `channel_id`, through the Lavalink node when there is one.
+pub async fn connect(ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> MusicResult<()> {
     let manager = songbird::get(ctx)
         .await
         .ok_or("Songbird Voice client placed in at initialisation.")?
         .clone();
 
-    let _handler = manager.join(guild_id, channel_id).await?;
+    if let Some(node) = crate::lavalink::node(ctx).await {
+        // Only the gateway side: the node opens the voice connection itself
+        let (info, _call) = manager.join_gateway(guild_id, channel_id).await?;
+        node.connect_voice(guild_id, &info).await?;
+    } else {
+        let _handler = manager.join(guild_id, channel_id).await?;
+        crate::quality::apply(ctx, guild_id).await;
+    }
+    Ok(())
+}
 
-    send_info(
-        ctx,
-        channel,
-        color,
-        "Music",
-        &format!("Joined <#{}>", channel_id.get()),
-    )
-    .await?;
+/// The guild's `music.command_channel`, if music is bound to one.
+pub async fn command_channel(ctx: &Context, guild_id: GuildId) -> Option<ChannelId> {
+    let value = crate::settings::get(ctx, guild_id, crate::settings::SettingKey::MusicCommandChannel).await?;
+    value.parse().ok().map(ChannelId::new)
+}
 
-    Ok(())
+/// Where this guild's music posts go: its session thread if there is one, else the bound
+/// `music.command_channel`, else `fallback`.
+pub async fn session_channel(ctx: &Context, guild_id: GuildId, fallback: ChannelId) -> ChannelId {
+    let threads = ctx.data.read().await.get::<crate::SessionThreadStore>().cloned();
+    if let Some(threads) = threads
+        && let Some(thread) = threads.lock().await.get(&guild_id).copied()
+    {
+        return thread;
+    }
+    command_channel(ctx, guild_id).await.unwrap_or(fallback)
 }
 
-async fn leave(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Option<GuildId>, color: u32) -> MusicResult<()> {
-    let guild_id = guild_id.ok_or("This command only works in a guild")?;
+/// With `music_thread` on, give the session a `music-requests` thread off the invoking channel.
+/// Invoked from a thread already, that thread is used as is. Returns the thread.
+async fn open_session_thread(cmd: crate::Ctx<'_>, guild_id: GuildId) -> Option<ChannelId> {
+    use serenity::builder::CreateThread;
+
+    let ctx = cmd.serenity_context();
+    if crate::settings::get(ctx, guild_id, crate::settings::SettingKey::MusicThread).await.as_deref() != Some("on") {
+        return None;
+    }
+    let threads = ctx.data.read().await.get::<crate::SessionThreadStore>().cloned()?;
+    let mut threads = threads.lock().await;
+    // Moving to another voice channel keeps the session's thread
+    if let Some(thread) = threads.get(&guild_id) {
+        return Some(*thread);
+    }
+    let invoked_in = cmd.channel_id();
+    let thread = if crate::thread_parent(ctx, guild_id, invoked_in).is_some() {
+        invoked_in
+    } else {
+        let builder = CreateThread::new("music-requests")
+            .kind(ChannelType::PublicThread)
+            .auto_archive_duration(AutoArchiveDuration::OneDay);
+        match invoked_in.create_thread(&ctx.http, builder).await {
+            Ok(thread) => thread.id,
+            Err(e) => {
+                tracing::warn!("Failed to create music thread in {}: {e:?}", invoked_in);
+                return None;
+            }
+        }
+    };
+    threads.insert(guild_id, thread);
+    Some(thread)
+}
+
+/// End the session's thread, archiving it if the bot created it for the session.
+async fn close_session_thread(ctx: &Context, guild_id: GuildId) {
+    use serenity::builder::EditThread;
+
+    let Some(threads) = ctx.data.read().await.get::<crate::SessionThreadStore>().cloned() else { return };
+    let Some(thread) = threads.lock().await.remove(&guild_id) else { return };
+    let created = ctx
+        .cache
+        .guild(guild_id)
+        .and_then(|g| g.threads.iter().find(|t| t.id == thread).map(|t| t.owner_id == Some(ctx.cache.current_user().id)))
+        .unwrap_or(false);
+    if created && let Err(e) = thread.edit_thread(&ctx.http, EditThread::new().archived(true)).await {
+        tracing::warn!("Failed to archive music thread {}: {e:?}", thread);
+    }
+}
+
+async fn leave(cmd: crate::Ctx<'_>, theme: &Theme) -> MusicResult<()> {
+    let ctx = cmd.serenity_context();
+    let guild_id = cmd.guild_id().ok_or("This command only works in a guild")?;
     let manager = songbird::get(ctx)
         .await
         .ok_or("Songbird Voice client placed in at initialisation.")?
         .clone();
 
     if manager.get(guild_id).is_none() {
-        send_info(ctx, channel, color, "Music", "Not connected to a voice channel").await?;
+        notify(cmd, theme, "Music", "Not connected to a voice channel").await?;
         return Ok(());
     }
 
+    end_session(ctx, guild_id).await;
     manager.remove(guild_id).await?;
 
-    send_info(ctx, channel, color, "Music", "Left the voice channel").await?;
+    notify(cmd, theme, "Music", "Left the voice channel").await?;
     Ok(())
 }
 
-async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Option<GuildId>, query: &str, color: u32) -> MusicResult<()> {
-    let guild_id = guild_id.ok_or("This command only works in a guild")?;
+/// Stop everything the guild's voice session started and forget its state. The call itself is
+/// left to the caller; the janitor also uses this for guilds whose call is already gone.
+pub async fn end_session(ctx: &Context, guild_id: GuildId) {
+    crate::queue::clear(ctx, guild_id).await;
+    crate::ambient::stop(ctx, guild_id).await;
+    crate::quiz::stop(ctx, guild_id).await;
+    crate::live::stop(ctx, guild_id).await;
+    crate::positions::stopped(ctx, guild_id).await;
+    cancel_resolution(ctx, guild_id).await;
+    kill_children(ctx, guild_id).await;
+    if let Some(node) = crate::lavalink::node(ctx).await
+        && let Err(e) = node.destroy(guild_id).await
+    {
+        tracing::warn!("Failed to destroy Lavalink player: {e:?}");
+    }
+    let stores = {
+        let data = ctx.data.read().await;
+        (data.get::<crate::TrackStore>().cloned(), data.get::<crate::TrackMetaStore>().cloned())
+    };
+    if let Some(tracks) = stores.0 {
+        tracks.lock().await.remove(&guild_id);
+    }
+    if let Some(metas) = stores.1 {
+        metas.lock().await.remove(&guild_id);
+    }
+    crate::presence::stopped(ctx, guild_id).await;
+    close_session_thread(ctx, guild_id).await;
+}
+
+async fn play(cmd: crate::Ctx<'_>, query: &str, theme: &Theme) -> MusicResult<()> {
+    let ctx = cmd.serenity_context();
+    let guild_id = cmd.guild_id().ok_or("This command only works in a guild")?;
+    let channel = session_channel(ctx, guild_id, cmd.channel_id()).await;
     if query.trim().is_empty() {
-        send_info(ctx, channel, color, "Music", "Provide a song name: music play <song>").await?;
+        notify(cmd, theme, "Music", "Provide a song name: music play <song>").await?;
+        return Ok(());
+    }
+    if crate::quiz::active(ctx, guild_id).await {
+        notify(cmd, theme, "Music", "A music quiz is running; `music quiz stop` ends it.").await?;
+        return Ok(());
+    }
+    if crate::party::following(ctx, guild_id).await.is_some() {
+        notify(cmd, theme, "Music", "This server is in a listening party and plays the host's tracks; `music play` works again once the party ends.").await?;
+        return Ok(());
+    }
+    if !crate::dedupe::allow(cmd, query, theme).await? {
+        return Ok(());
+    }
+    // Only requests that wait in the queue count towards the per-member limit
+    let queueing = crate::ambient::active(ctx, guild_id).await.is_some() || crate::player::status(ctx, guild_id).await.is_some();
+    if queueing
+        && let Some(limit) = crate::queue::limit_reached(ctx, guild_id, cmd.author().id).await
+        && !crate::settings::author_is_dj(cmd).await
+    {
+        let text = format!("You already have {} request(s) waiting, the most this server allows. Try again once one has played.", limit);
+        notify(cmd, theme, "Music", &text).await?;
         return Ok(());
     }
 
-    let manager = songbird::get(ctx)
-        .await
-        .ok_or("Songbird Voice client placed in at initialisation.")?
-        .clone();
+    // Ambient sound gives way: queue the request and end it, so the queue starts it next
+    if crate::ambient::active(ctx, guild_id).await.is_some() {
+        let track = crate::queue::QueuedTrack { query: query.trim().to_string(), requested_by: Some(cmd.author().id), channel };
+        crate::queue::push(ctx, guild_id, track).await;
+        crate::player::skip(ctx, guild_id).await?;
+        notify(cmd, theme, "Music", &format!("Ambient sound stopped; up next: {}", query.trim())).await?;
+        return Ok(());
+    }
 
-    let handler_lock = if let Some(lock) = manager.get(guild_id) {
-        lock
-    } else {
-        send_info(ctx, channel, color, "Music", "Bot is not in a voice channel (use music join)").await?;
+    // Something is already playing: wait for it instead of mixing over it
+    if crate::player::status(ctx, guild_id).await.is_some() {
+        let track = crate::queue::QueuedTrack { query: query.trim().to_string(), requested_by: Some(cmd.author().id), channel };
+        let position = crate::queue::push(ctx, guild_id, track).await;
+        notify(cmd, theme, "Music", &format!("Queued #{}: {}", position, query.trim())).await?;
         return Ok(());
+    }
+
+    // Long URLs played before can pick up where they were left
+    let resume_at = match crate::positions::saved(ctx, guild_id, query.trim()).await {
+        Some(at) => crate::positions::offer(cmd, query.trim(), at, theme).await?,
+        None => None,
+    };
+
+    // Resolution can take a while across the fallback chain; keep a "Resolving…" embed
+    // updated with each stage start_track reports
+    let ephemeral = crate::settings::ephemeral(cmd).await;
+    let reply = cmd
+        .send(poise::CreateReply::default().embed(progress_embed(query.trim(), Stage::Searching, None, theme)).ephemeral(ephemeral))
+        .await?;
+    let (progress, mut stages) = tokio::sync::watch::channel(Stage::Searching);
+    let updates = async {
+        let mut last = Stage::Searching;
+        while stages.changed().await.is_ok() {
+            last = *stages.borrow_and_update();
+            let embed = progress_embed(query.trim(), last, None, theme);
+            let _ = reply.edit(cmd, poise::CreateReply::default().embed(embed)).await;
+        }
+        last
+    };
+    let exempt = crate::settings::author_is_dj(cmd).await;
+    let (result, last) = tokio::join!(resolve(ctx, channel, guild_id, query, theme, Some(progress), exempt, Some(cmd.author().id)), updates);
+    let halt = match &result {
+        Ok(false) => Some(Halt::Cancelled),
+        _ if last != Stage::Playing => Some(Halt::Failed),
+        _ => None,
     };
+    if halt.is_some() {
+        let embed = progress_embed(query.trim(), last, halt, theme);
+        let _ = reply.edit(cmd, poise::CreateReply::default().embed(embed)).await;
+    } else if let Some(at) = resume_at {
+        match crate::player::seek(ctx, guild_id, at).await {
+            Ok(_) => crate::positions::playing(ctx, guild_id, query, at).await,
+            Err(e) => {
+                tracing::warn!("Failed to resume {} at {:?}: {e:?}", query.trim(), at);
+                send_info(ctx, channel, theme, "Music", "This stream can't seek, so it's playing from the beginning.").await?;
+            }
+        }
+    }
+    result.map(|_| ())
+}
+
+/// Resolve and start `query` immediately, bypassing the queue. Used when the queue advances.
+/// The server's track limits apply unless `requested_by` is a DJ. Errors when the track can't
+/// be played, leaving the caller to say so (the queue retries first); refusals such as a track
+/// over the server's limits are answered in `channel` and return `Ok`.
+pub async fn play_now(
+    ctx: &Context,
+    channel: ChannelId,
+    guild_id: GuildId,
+    query: &str,
+    requested_by: Option<serenity::all::UserId>,
+) -> MusicResult<()> {
+    let theme = crate::embeds::theme(ctx, Some(guild_id)).await;
+    let exempt = match requested_by {
+        Some(user) => crate::settings::user_is_dj(ctx, guild_id, user).await,
+        None => false,
+    };
+    let started = resolve(ctx, channel, guild_id, query, &theme, None, exempt, requested_by).await?;
+    if started && let Some(user) = requested_by {
+        let label = match ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
+            Some(ms) => ms.lock().await.get(&guild_id).and_then(|m| m.title.clone()),
+            None => None,
+        };
+        crate::preferences::track_started(ctx, guild_id, user, label.as_deref().unwrap_or(query.trim())).await;
+    }
+    Ok(())
+}
+
+/// Tell `channel` a track `play_now` couldn't start, for callers with no command to answer.
+pub async fn report_failure(ctx: &Context, channel: ChannelId, guild_id: GuildId, error: &str) {
+    let theme = crate::embeds::theme(ctx, Some(guild_id)).await;
+    let _ = send_info(ctx, channel, &theme, "Music", error).await;
+}
 
-    // Support direct URLs: YouTube links will be played directly; Spotify track links will be resolved via the Spotify Web API and then searched on YouTube
-    let raw_query = query.trim().to_string();
-    let mut search_query = raw_query.clone();
+/// The query each guild's current (or resolving) track was started from.
+pub struct CurrentQueryStore;
+impl TypeMapKey for CurrentQueryStore {
+    type Value = std::sync::Arc<Mutex<std::collections::HashMap<GuildId, String>>>;
+}
 
-    // If it's a Spotify link, try to resolve it to a title+artist using the Spotify API
-    if raw_query.starts_with("http") && raw_query.contains("spotify") {
-        if let Some(id) = parse_spotify_track_id(&raw_query) {
-            if let Ok(token) = fetch_spotify_token_from_env().await {
-                if let Ok(Some((title, artist, duration_opt, thumbnail_opt))) = fetch_spotify_track_by_id(&token.access_token, &id).await {
-                    // Use the Spotify metadata to search YouTube and store metadata in TrackMetaStore
-                    search_query = format!("{} {}", title, artist);
+async fn track_meta(ctx: &Context, guild_id: GuildId) -> Option<crate::TrackMeta> {
+    let ms = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned()?;
+    ms.lock().await.get(&guild_id).cloned()
+}
 
-                    if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
-                        let mut mm = ms.lock().await;
-                        mm.insert(guild_id, crate::TrackMeta { title: Some(title.clone()), artist: Some(artist.clone()), duration: duration_opt, thumbnail: thumbnail_opt.clone() });
-                    }
+fn label(meta: crate::TrackMeta) -> Option<String> {
+    match (meta.title, meta.artist) {
+        (Some(title), Some(artist)) => Some(format!("{} – {}", artist, title)),
+        (title, _) => title,
+    }
+}
 
+/// "Artist – Title" of what `guild_id` plays, as far as the player knows.
+pub async fn track_label(ctx: &Context, guild_id: GuildId) -> Option<String> {
+    label(track_meta(ctx, guild_id).await?)
+}
 
-                }
+/// What `guild_id` plays, as a query that plays the same track again and its label, both from
+/// one read of the player's metadata. The query is the page the track resolved to when that's
+/// known, else what it was started from.
+pub async fn replayable(ctx: &Context, guild_id: GuildId) -> Option<(String, String)> {
+    let query = current_query(ctx, guild_id).await?;
+    let meta = track_meta(ctx, guild_id).await;
+    let url = meta.as_ref().and_then(|m| m.url.clone());
+    let title = meta.and_then(label).unwrap_or_else(|| query.clone());
+    Some((url.unwrap_or(query), title))
+}
+
+/// What `guild_id` was last asked to play, e.g. for favorites and listening parties.
+pub async fn current_query(ctx: &Context, guild_id: GuildId) -> Option<String> {
+    let store = ctx.data.read().await.get::<CurrentQueryStore>().cloned()?;
+    store.lock().await.get(&guild_id).cloned()
+}
+
+/// The guild's in-flight resolution, so a newer command can abort it.
+pub struct ResolverStore;
+impl TypeMapKey for ResolverStore {
+    type Value = std::sync::Arc<Mutex<std::collections::HashMap<GuildId, tokio::task::AbortHandle>>>;
+}
+
+/// Run `start_track` as the guild's resolver task, cancelling the one already in flight.
+/// `Ok(false)` if a later command cancelled this one in turn. `exempt` skips the server's
+/// length and livestream limits (see `limits`); searches follow `requested_by`'s preferences.
+#[allow(clippy::too_many_arguments)]
+async fn resolve(
+    ctx: &Context,
+    channel: ChannelId,
+    guild_id: GuildId,
+    query: &str,
+    theme: &Theme,
+    progress: Option<Progress>,
+    exempt: bool,
+    requested_by: Option<UserId>,
+) -> MusicResult<bool> {
+    cancel_resolution(ctx, guild_id).await;
+    crate::positions::playing(ctx, guild_id, query, std::time::Duration::ZERO).await;
+    crate::dedupe::played(ctx, guild_id, query).await;
+    if let Some(store) = ctx.data.read().await.get::<CurrentQueryStore>().cloned() {
+        store.lock().await.insert(guild_id, query.trim().to_string());
+    }
+    crate::party::track_requested(ctx, guild_id, query).await;
+    crate::scrobble::requested(ctx, guild_id, requested_by).await;
+    let task_ctx = ctx.clone();
+    let query = query.to_string();
+    let theme = theme.clone();
+    let task = tokio::spawn(async move {
+        let _slot = resolution_slot(&task_ctx, progress.as_ref()).await;
+        let search = crate::preferences::search_source(&task_ctx, requested_by).await;
+        start_track(&task_ctx, channel, guild_id, &query, &theme, progress.as_ref(), exempt, search).await
+    });
+    let store = ctx.data.read().await.get::<ResolverStore>().cloned();
+    if let Some(store) = &store {
+        store.lock().await.insert(guild_id, task.abort_handle());
+    }
+    let id = task.id();
+    let result = task.await;
+    if let Some(store) = &store {
+        let mut running = store.lock().await;
+        if running.get(&guild_id).is_some_and(|h| h.id() == id) {
+            running.remove(&guild_id);
+        }
+    }
+    match result {
+        Ok(result) => result.map(|()| true),
+        Err(e) if e.is_cancelled() => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Caps concurrent resolutions across all guilds (`music.max_concurrent_resolutions`), so a
+/// burst of `play`s can't start more yt-dlp/ffmpeg pipelines than the machine can hold.
+pub struct ResolutionSlots {
+    permits: std::sync::Arc<tokio::sync::Semaphore>,
+    /// Tickets of the resolutions waiting for a permit, first in line first.
+    line: std::sync::Mutex<std::collections::VecDeque<u64>>,
+    next_ticket: std::sync::atomic::AtomicU64,
+}
+
+impl ResolutionSlots {
+    pub fn new(slots: usize) -> Self {
+        ResolutionSlots {
+            permits: std::sync::Arc::new(tokio::sync::Semaphore::new(slots)),
+            line: Default::default(),
+            next_ticket: Default::default(),
+        }
+    }
+
+    fn place(&self, ticket: u64) -> usize {
+        let line = self.line.lock().unwrap_or_else(|e| e.into_inner());
+        line.iter().position(|t| *t == ticket).map_or(1, |i| i + 1)
+    }
+
+    fn leave(&self, ticket: u64) {
+        self.line.lock().unwrap_or_else(|e| e.into_inner()).retain(|t| *t != ticket);
+    }
+}
+
+pub struct ResolutionSlotStore;
+impl TypeMapKey for ResolutionSlotStore {
+    type Value = std::sync::Arc<ResolutionSlots>;
+}
+
+/// Takes the ticket out of line even if the wait is aborted.
+struct Ticket<'a> {
+    slots: &'a ResolutionSlots,
+    ticket: u64,
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        self.slots.leave(self.ticket);
+    }
+}
+
+/// Wait for a resolution slot, reporting the place in line while all are taken. The permit
+/// is held until dropped.
+async fn resolution_slot(ctx: &Context, progress: Option<&Progress>) -> Option<tokio::sync::OwnedSemaphorePermit> {
+    let slots = ctx.data.read().await.get::<ResolutionSlotStore>().cloned()?;
+    if let Ok(permit) = slots.permits.clone().try_acquire_owned() {
+        return Some(permit);
+    }
+    let ticket = slots.next_ticket.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    slots.line.lock().unwrap_or_else(|e| e.into_inner()).push_back(ticket);
+    let _ticket = Ticket { slots: &slots, ticket };
+    // One acquire for the whole wait: the semaphore is first come, first served
+    let acquire = slots.permits.clone().acquire_owned();
+    tokio::pin!(acquire);
+    loop {
+        report(progress, Stage::Waiting(slots.place(ticket)));
+        // Wake now and then to keep the place in line current
+        match tokio::time::timeout(std::time::Duration::from_secs(2), &mut acquire).await {
+            Ok(permit) => {
+                report(progress, Stage::Searching);
+                return permit.ok();
             }
+            Err(_) => continue,
         }
-    } else {
-        // Not a Spotify link — perform the existing 'spotify-first' lookup for plain queries
-        search_query = match spotify_first_then_query(query).await {
-            Ok(Some(s)) => s,
-            Ok(None) => query.to_string(),
-            Err(e) => {
-                eprintln!("Spotify lookup failed, falling back to direct search: {e:?}");
-                query.to_string()
+    }
+}
+
+/// Whether a track is being resolved for the guild right now.
+pub async fn resolving(ctx: &Context, guild_id: GuildId) -> bool {
+    let Some(store) = ctx.data.read().await.get::<ResolverStore>().cloned() else { return false };
+    store.lock().await.contains_key(&guild_id)
+}
+
+/// Abort the guild's in-flight resolution, if any, killing its yt-dlp/ffmpeg processes and
+/// dropping a track it had handed to songbird but not yet started. Returns whether one was running.
+pub async fn cancel_resolution(ctx: &Context, guild_id: GuildId) -> bool {
+    let Some(store) = ctx.data.read().await.get::<ResolverStore>().cloned() else { return false };
+    let Some(task) = store.lock().await.remove(&guild_id) else { return false };
+    task.abort();
+    kill_children(ctx, guild_id).await;
+    // Resolution only runs while nothing plays, so anything in the driver is its leftover
+    if let Some(manager) = songbird::get(ctx).await
+        && let Some(call) = manager.get(guild_id)
+    {
+        call.lock().await.stop();
+    }
+    true
+}
+
+/// Processes spawned for each guild's playback (stream helpers, ffmpeg). Songbird only kills
+/// the direct child when it drops an input, so `sh -c` pipelines would outlive it.
+pub struct ChildStore;
+impl TypeMapKey for ChildStore {
+    type Value = std::sync::Arc<Mutex<std::collections::HashMap<GuildId, Vec<u32>>>>;
+}
+
+/// Spawn a playback process in its own process group and remember it for `kill_children`.
+pub async fn spawn_child(ctx: &Context, guild_id: GuildId, command: &mut std::process::Command) -> std::io::Result<std::process::Child> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    let child = command.spawn()?;
+    if let Some(store) = ctx.data.read().await.get::<ChildStore>().cloned() {
+        store.lock().await.entry(guild_id).or_default().push(child.id());
+    }
+    Ok(child)
+}
+
+/// Forget processes whose whole group has exited, so their ids (which the system may hand out
+/// again) aren't killed later.
+pub async fn prune_children(ctx: &Context) {
+    let Some(store) = ctx.data.read().await.get::<ChildStore>().cloned() else { return };
+    let tracked: Vec<(GuildId, Vec<u32>)> = store.lock().await.iter().map(|(gid, pids)| (*gid, pids.clone())).collect();
+    for (guild_id, pids) in tracked {
+        let mut exited = Vec::new();
+        for pid in pids {
+            if !group_running(pid).await {
+                exited.push(pid);
             }
-        };
+        }
+        let mut children = store.lock().await;
+        if let Some(pids) = children.get_mut(&guild_id) {
+            pids.retain(|pid| !exited.contains(pid));
+            if pids.is_empty() {
+                children.remove(&guild_id);
+            }
+        }
     }
+}
 
-    // Use Songbird's YoutubeDl lazy input to resolve and play the query
-    let req_client = Client::builder().build()?;
-    let http_client = req_client.clone();
+/// Whether anything in the process group `pid` leads is still running.
+async fn group_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        let check = tokio::process::Command::new("kill")
+            .arg("-0")
+            .arg("--")
+            .arg(format!("-{}", pid))
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await;
+        check.is_ok_and(|status| status.success())
+    }
+    // No cheap check elsewhere; keep it until the guild's children are killed
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
 
-    // If the user provided a YouTube URL directly, play that URL; otherwise use a search
-    let mut ytdl = if raw_query.starts_with("http") && (raw_query.contains("youtube.com") || raw_query.contains("youtu.be")) {
-        songbird::input::YoutubeDl::new(req_client, raw_query.clone())
-            .user_args(vec!["-f".into(), "bestaudio[ext=webm]/bestaudio/best".into()])
-    } else {
-        songbird::input::YoutubeDl::new_search(req_client, search_query.clone())
-            .user_args(vec!["-f".into(), "bestaudio[ext=webm]/bestaudio/best".into()])
+/// Kill every process spawned for the guild's playback, with anything they started.
+pub async fn kill_children(ctx: &Context, guild_id: GuildId) {
+    let Some(store) = ctx.data.read().await.get::<ChildStore>().cloned() else { return };
+    let pids = store.lock().await.remove(&guild_id).unwrap_or_default();
+    for pid in pids {
+        kill_group(pid).await;
+    }
+}
+
+/// `kill_children` for every guild, on shutdown.
+pub async fn kill_all_children(data: &RwLock<TypeMap>) {
+    let Some(store) = data.read().await.get::<ChildStore>().cloned() else { return };
+    let pids: Vec<u32> = store.lock().await.drain().flat_map(|(_, pids)| pids).collect();
+    for pid in pids {
+        kill_group(pid).await;
+    }
+}
+
+async fn kill_group(pid: u32) {
+    #[cfg(unix)]
+    let mut kill = {
+        let mut kill = tokio::process::Command::new("kill");
+        kill.arg("-KILL").arg("--").arg(format!("-{}", pid));
+        kill
     };
-    let input: songbird::input::Input = ytdl.clone().into();
+    #[cfg(not(unix))]
+    let mut kill = {
+        let mut kill = tokio::process::Command::new("taskkill");
+        kill.args(["/F", "/T", "/PID", &pid.to_string()]);
+        kill
+    };
+    // Already-exited groups just make kill fail
+    let _ = kill.stdout(std::process::Stdio::null()).stderr(std::process::Stdio::null()).status().await;
+}
 
-    let mut handler = handler_lock.lock().await;
+/// Where `start_track` is in resolving a query. Fallbacks can revisit earlier stages.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    /// In line for a resolution slot; the number is the place in line.
+    Waiting(usize),
+    Searching,
+    Downloading,
+    Transcoding,
+    Playing,
+}
 
-    // If a Spotify link is provided, try streaming directly via a configured command or a bundled `.bin` helper; otherwise fall back to YouTube search
-    if raw_query.starts_with("http") && raw_query.contains("spotify") {
-        // Allow opting out of direct Spotify streaming and force the YouTube fallback
-        let prefer_youtube = std::env::var("SPOTIFY_PREFER_YOUTUBE").map(|s| matches!(s.as_str(), "1" | "true" | "TRUE" | "True")).unwrap_or(false);
-        if prefer_youtube {
-            let _ = send_info(ctx, channel, color, "Music", "Spotify direct streaming disabled by `SPOTIFY_PREFER_YOUTUBE`; falling back to YouTube search").await;
-        } else if let Some(cmd) = get_spotify_stream_cmd(&raw_query) {
-            // Spawn via shell so users can compose pipelines; expect the command to write raw PCM/WAV to stdout
-            match std::process::Command::new("sh").arg("-c").arg(&cmd).stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn() {
-                Ok(child_proc) => {
-                    // First attempt: try to play the raw child output directly
-                    let container = songbird::input::ChildContainer::from(child_proc);
-                    let child_input: songbird::input::Input = container.into();
-                    let new_handle = handler.play_input(child_input);
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Searching, Stage::Downloading, Stage::Transcoding, Stage::Playing];
 
-                    match new_handle.make_playable_async().await {
-                        Ok(()) => {
-                            let _ = new_handle.play();
-                            let _ = new_handle.set_volume(0.20);
-                            let gid = guild_id;
-                            let _ = store_handle(ctx, gid, new_handle.clone()).await;
+    fn label(self) -> &'static str {
+        match self {
+            Stage::Waiting(_) => "Waiting",
+            Stage::Searching => "Searching",
+            Stage::Downloading => "Downloading",
+            Stage::Transcoding => "Transcoding",
+            Stage::Playing => "Playing",
+        }
+    }
+}
 
-                            let _ = send_info(
-                                ctx,
-                                channel,
-                                color,
-                                "Music",
-                                &format!("Now streaming from Spotify: {}", raw_query),
-                            )
-                            .await?;
+/// Why resolution stopped short of playing.
+#[derive(Clone, Copy)]
+enum Halt {
+    Failed,
+    Cancelled,
+}
 
-                            return Ok(());
-                        }
-                        Err(e) => {
-                            eprintln!("Initial spotify stream parse failed: {e:?}; attempting ffmpeg transcode fallback");
-
-                            // Try several common input hints to ffmpeg to handle helpers that emit raw PCM, WAV, MP3, or Opus
-                            let input_formats = [
-                                "",                    // let ffmpeg probe
-                                "-f wav",             // WAV container
-                                "-f s16le -ar 44100 -ac 2", // raw signed 16-bit PCM 44.1kHz stereo
-                                "-f s16le -ar 48000 -ac 2", // raw signed 16-bit PCM 48kHz stereo
-                                "-f mp3",
-                                "-f opus",
-                            ];
-
-                            // Collect stderr logs for diagnostics
-                            let mut stderr_logs: Vec<String> = Vec::new();
-
-                            for fmt in &input_formats {
-                                let ff_cmd = if fmt.is_empty() {
-                                    format!("{cmd} | ffmpeg -hide_banner -loglevel error -i - -vn -c:a pcm_s16le -ar 48000 -ac 2 -f wav -", cmd = cmd)
-                                } else {
-                                    format!("{cmd} | ffmpeg -hide_banner -loglevel error {fmt} -i - -vn -c:a pcm_s16le -ar 48000 -ac 2 -f wav -", cmd = cmd, fmt = fmt)
-                                };
-
-                                match std::process::Command::new("sh").arg("-c").arg(&ff_cmd).stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn() {
-                                    Ok(mut child_proc2) => {
-                                        // Prepare a stderr file to capture ffmpeg diagnostics
-                                        let cwd = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
-                                        let uniq = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
-                                        let stderr_log = cwd.join(format!("spotify-{}-ffstderr-{}.log", std::process::id(), uniq));
-
-                                        if let Some(mut stderr) = child_proc2.stderr.take() {
-                                            let stderr_log_clone = stderr_log.clone();
-                                            std::thread::spawn(move || {
-                                                use std::io::Read;
-                                                let mut buf = String::new();
-                                                let _ = stderr.read_to_string(&mut buf);
-                                                let _ = std::fs::write(&stderr_log_clone, &buf);
-                                            });
-                                        }
+/// Progress events from `start_track`; the receiver keeps the "Resolving…" embed current.
+type Progress = tokio::sync::watch::Sender<Stage>;
+
+fn report(progress: Option<&Progress>, stage: Stage) {
+    if let Some(progress) = progress {
+        // Only a change wakes the embed updater; the wait loop reports its place every 2s
+        progress.send_if_modified(|current| {
+            let changed = *current != stage;
+            *current = stage;
+            changed
+        });
+    }
+}
 
-                                        let container2 = songbird::input::ChildContainer::from(child_proc2);
-                                        let child_input2: songbird::input::Input = container2.into();
-                                        let new_handle2 = handler.play_input(child_input2);
-
-                                        match new_handle2.make_playable_async().await {
-                                            Ok(()) => {
-                                                let _ = new_handle2.play();
-                                                let _ = new_handle2.set_volume(0.20);
-                                                let gid = guild_id;
-                                                let _ = store_handle(ctx, gid, new_handle2.clone()).await;
-
-                                                let _ = send_info(
-                                                    ctx,
-                                                    channel,
-                                                    color,
-                                                    "Music",
-                                                    &format!("Now streaming from Spotify (transcoded, fmt='{}'): {}", fmt, raw_query),
-                                                )
-                                                .await?;
-
-                                                return Ok(());
-                                            }
-                                            Err(e2) => {
-                                                eprintln!("Transcoded spotify stream (fmt='{}') failed to play: {e2:?}", fmt);
+/// The "Resolving…" embed at `current`, or where it stopped when `halt` is set.
+fn progress_embed(query: &str, current: Stage, halt: Option<Halt>, theme: &Theme) -> CreateEmbed {
+    let lines: Vec<String> = Stage::ALL
+        .iter()
+        .map(|&stage| {
+            let mark = if stage < current || (stage == Stage::Playing && current == Stage::Playing) {
+                "✅"
+            } else if stage == current {
+                match halt {
+                    Some(Halt::Failed) => "❌",
+                    Some(Halt::Cancelled) => "⏹️",
+                    None => "⏳",
+                }
+            } else {
+                "▫️"
+            };
+            format!("{} {}", mark, stage.label())
+        })
+        .collect();
+    let title = match (current, halt) {
+        (Stage::Playing, _) => "Resolved",
+        (_, Some(Halt::Failed)) => "Couldn't resolve",
+        (_, Some(Halt::Cancelled)) => "Cancelled",
+        (Stage::Waiting(_), None) => "Waiting…",
+        _ => "Resolving…",
+    };
+    let mut description = format!("`{}`\n\n", query);
+    if let Stage::Waiting(place) = current {
+        description.push_str(&format!("#{} in the resolution queue; other servers' tracks are loading.\n", place));
+    }
+    description.push_str(&lines.join("\n"));
+    theme.embed().title(title).description(description)
+}
 
-                                                // Read stderr log (if present) for diagnostics and append
-                                                if let Ok(s) = tokio::fs::read_to_string(&stderr_log).await {
-                                                    if !s.is_empty() {
-                                                        stderr_logs.push(format!("fmt='{}' stderr:\n{}", fmt, s));
-                                                        let _ = tokio::fs::remove_file(&stderr_log).await;
-                                                    }
-                                                }
+/// Play `query` on the guild's call. Errors when it can't be played; refusals (not in a voice
+/// channel, over the server's limits) are answered in `channel` and return `Ok`.
+#[allow(clippy::too_many_arguments)]
+async fn start_track(
+    ctx: &Context,
+    channel: ChannelId,
+    guild_id: GuildId,
+    query: &str,
+    theme: &Theme,
+    progress: Option<&Progress>,
+    exempt: bool,
+    search: crate::preferences::SearchSource,
+) -> MusicResult<()> {
+    let manager = songbird::get(ctx)
+        .await
+        .ok_or("Songbird Voice client placed in at initialisation.")?
+        .clone();
 
-                                                // try next format
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    Err(e2) => {
-                                        eprintln!("Failed to spawn ffmpeg transcode pipeline (fmt='{}'): {e2:?}", fmt);
-                                        stderr_logs.push(format!("fmt='{}' spawn failed: {e2:?}", fmt));
-                                        continue;
-                                    }
-                                }
-                            }
+    let handler_lock = if let Some(lock) = manager.get(guild_id) {
+        lock
+    } else {
+        send_info(ctx, channel, theme, "Music", "Bot is not in a voice channel (use music join)").await?;
+        return Ok(());
+    };
 
-                            // If we reach here, all attempts failed. Optionally send verbose diagnostics
-                            if std::env::var("MUSIC_VERBOSE").is_ok() {
-                                let msg = if stderr_logs.is_empty() { "No ffmpeg stderr captured".to_string() } else { stderr_logs.join("\n-----\n") };
-                                let _ = send_info(ctx, channel, color, "Music - Spotify ffmpeg diagnostics", &msg).await;
-                            }
+    if let Some(node) = crate::lavalink::node(ctx).await {
+        return play_lavalink(ctx, &node, channel, guild_id, query, theme, progress, exempt, search).await;
+    }
 
-                            let _ = send_info(ctx, channel, color, "Music", "Spotify stream failed (all transcode attempts failed), falling back to YouTube search").await;
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to spawn spotify stream command: {e:?}");
-                    let _ = send_info(ctx, channel, color, "Music", "Failed to start Spotify stream command, falling back to YouTube search").await;
-                }
-            }
-        } else {
-            let _ = send_info(ctx, channel, color, "Music", "No Spotify stream command configured (set SPOTIFY_STREAM_CMD or place `librespot-wrapper` in .bin). Falling back to YouTube search").await;
+    // The source that claims the request (see `sources`) says what to play and what's known
+    let resolved = match crate::sources::resolve(ctx, query, search).await {
+        Ok(track) => track,
+        Err(e) => return Err(format!("Couldn't play {}: {}", query.trim(), e).into()),
+    };
+    let mut search_query = resolved.label.clone();
+    // Length the source reported, so the limits don't need a yt-dlp lookup
+    let known_duration = resolved.meta.as_ref().and_then(|m| m.duration);
+    if let Some(meta) = resolved.meta.clone()
+        && let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned()
+    {
+        ms.lock().await.insert(guild_id, meta);
+    }
+    // The stream helper's command, if the source set one up, and what plays otherwise
+    let (command, playable) = match resolved.playable {
+        crate::sources::Playable::Command { command, fallback } => (Some(command), *fallback),
+        other => (None, other),
+    };
+    // An exact page for yt-dlp to play instead of searching
+    let direct_url: Option<String> = match playable {
+        crate::sources::Playable::Page(url) => Some(url),
+        crate::sources::Playable::Search(text) => {
+            search_query = text;
+            None
+        }
+        crate::sources::Playable::Stream(_) | crate::sources::Playable::File(_) => {
+            let is_live = resolved.meta.as_ref().is_some_and(|m| m.is_live);
+            return play_direct(ctx, &handler_lock, channel, guild_id, playable, &search_query, is_live, theme, progress, exempt).await;
+        }
+        crate::sources::Playable::Command { .. } => None,
+    };
+
+    // Use Songbird's YoutubeDl lazy input to resolve and play the query
+    let cookie_args = crate::config::current(ctx).await.music.cookie_args();
+    let quality = crate::quality::get(ctx, guild_id).await;
+    let ytdl_args = [vec!["-f".to_string(), quality.ytdl_format().to_string()], cookie_args.clone()].concat();
+    let req_client = Client::builder().build()?;
+    let http_client = req_client.clone();
+
+    // What yt-dlp plays: the exact source if there is one, else the search
+    let target = match &direct_url {
+        Some(url) => url.clone(),
+        None => format!("ytsearch1:{}", search_query),
+    };
+    // Spotify's lengths are known up front; anything else is only looked up if needed
+    let mut facts = known_duration.map(|d| crate::limits::Facts { title: Some(search_query.clone()), duration: Some(d), is_live: false });
+
+    // The server's length and livestream limits; DJs are exempt
+    let limits = crate::limits::get(ctx, guild_id).await;
+    if !exempt && limits.active() {
+        if facts.is_none() {
+            facts = crate::limits::probe(&target, &cookie_args).await;
         }
+        if let Some(problem) = facts.as_ref().and_then(|f| limits.violation(f)) {
+            send_info(ctx, channel, theme, "Music", &problem).await?;
+            return Ok(());
+        }
+    }
+
+    // Play the page the source found if there is one; otherwise use a search
+    let mut ytdl = if let Some(url) = &direct_url {
+        songbird::input::YoutubeDl::new(req_client, url.clone()).user_args(ytdl_args)
+    } else {
+        songbird::input::YoutubeDl::new_search(req_client, search_query.clone()).user_args(ytdl_args)
+    };
+    let input: songbird::input::Input = ytdl.clone().into();
+
+    let mut handler = handler_lock.lock().await;
+
+    // The stream helper goes first; if it can't play, the fallback does
+    if let Some(command) = &command
+        && play_command(ctx, &mut handler, channel, guild_id, command, query, theme, progress).await?
+    {
+        return Ok(());
     }
 
     // `play` accepts a Track; Input implements conversion so `.into()` works
+    report(progress, Stage::Downloading);
     let handle = handler.play(input.into());
 
     // Attempt to make the lazy track playable (yt-dlp in background)
@@ -500,22 +1092,27 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                     let artist = meta.artist;
                     let thumbnail = meta.thumbnail;
                     let duration = meta.duration;
+                    let url = meta.source_url.clone();
 
                     if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
                         let mut mm = ms.lock().await;
-                        mm.insert(guild_id, crate::TrackMeta { title, artist, duration, thumbnail });
+                        mm.insert(guild_id, crate::TrackMeta { title: title.clone(), artist, duration, thumbnail, chapters: Vec::new(), is_live: facts.as_ref().is_some_and(|f| f.is_live), url });
                     }
+                    // Songbird's metadata has no chapters; ask yt-dlp for them separately
+                    let target = meta.source_url.unwrap_or_else(|| format!("ytsearch1:{}", search_query));
+                    crate::chapters::fetch_later(ctx, guild_id, target, title);
                 }
             }
 
             // Store the handle for control panels
             let gid = guild_id;
             let _ = store_handle(ctx, gid, handle.clone()).await;
+            report(progress, Stage::Playing);
 
             send_info(
                 ctx,
                 channel,
-                color,
+                theme,
                 "Music",
                 &format!("Now playing: {search_query}"),
             )
@@ -523,7 +1120,29 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
             return Ok(());
         }
         Err(e) => {
-            eprintln!("Failed to make track playable: {e:?}");
+            tracing::warn!("Failed to make track playable: {e:?}");
+
+            // Livestreams are HLS feeds: the format probes can't play them and downloading one
+            // never finishes, so they go straight to a reconnecting ffmpeg instead
+            if facts.is_none() {
+                facts = crate::limits::probe(&target, &cookie_args).await;
+            }
+            if let Some(facts) = facts.filter(|f| f.is_live) {
+                report(progress, Stage::Transcoding);
+                let title = facts.title.clone();
+                return match crate::live::start(ctx, &mut handler, guild_id, channel, &target, facts.title).await {
+                    Ok(()) => {
+                        report(progress, Stage::Playing);
+                        let name = title.unwrap_or_else(|| search_query.clone());
+                        send_info(ctx, channel, theme, "Music", &format!("Now playing (LIVE): {}", name)).await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        tracing::warn!("Livestream failed to start: {e:?}");
+                        Err(format!("Couldn't play the livestream {}: {}", search_query, e).into())
+                    }
+                };
+            }
 
             // Attempt to gather metadata from ytdl for diagnostics
             let diagnostic = match ytdl.search(Some(1)).await {
@@ -547,216 +1166,203 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                 "bestaudio/best",
             ];
 
-            for fmt in &formats {
-                let search_arg = format!("ytsearch1:{}", search_query);
-                let output = Command::new("yt-dlp")
-                    .arg("-f")
-                    .arg(fmt)
-                    .arg("-j")
-                    .arg(&search_arg)
-                    .output()
-                    .await;
-
-                match output {
-                    Ok(o) if o.status.success() => {
-                        let stdout = String::from_utf8_lossy(&o.stdout);
-                        if let Some(json_line) = stdout.lines().next() {
-                            if let Ok(val) = serde_json::from_str::<serde_json::Value>(json_line) {
-                                if let Some(url) = val.get("url").and_then(|v| v.as_str()) {
-                                    // Build header map if provided
-                                    let mut headers = reqwest::header::HeaderMap::new();
-                                    if let Some(hm) = val.get("http_headers").and_then(|v| v.as_object()) {
-                                        for (k, v) in hm.iter() {
-                                            if let Some(s) = v.as_str() {
-                                                if let (Ok(hn), Ok(hv)) = (
-                                                    reqwest::header::HeaderName::from_bytes(k.as_bytes()),
-                                                    reqwest::header::HeaderValue::from_str(s),
-                                                ) {
-                                                    headers.insert(hn, hv);
-                                                }
-                                            }
-                                        }
-                                    }
+            // Probe every format at once and try each as its probe returns. The first that plays
+            // wins; returning drops the set, which aborts the remaining probes and their yt-dlp.
+            report(progress, Stage::Downloading);
+            let mut probes = tokio::task::JoinSet::new();
+            for fmt in formats {
+                probes.spawn(probe_format(search_query.clone(), fmt, cookie_args.clone()));
+            }
+
+            while let Some(probe) = probes.join_next().await {
+                let Ok(Some((fmt, val))) = probe else { continue };
+                if let Some(url) = val.get("url").and_then(|v| v.as_str()) {
+                    // Build header map if provided
+                    let mut headers = reqwest::header::HeaderMap::new();
+                    if let Some(hm) = val.get("http_headers").and_then(|v| v.as_object()) {
+                        for (k, v) in hm.iter() {
+                            if let Some(s) = v.as_str() {
+                                if let (Ok(hn), Ok(hv)) = (
+                                    reqwest::header::HeaderName::from_bytes(k.as_bytes()),
+                                    reqwest::header::HeaderValue::from_str(s),
+                                ) {
+                                    headers.insert(hn, hv);
+                                }
+                            }
+                        }
+                    }
+
+                    // If JSON contains metadata, store title/artist/thumbnail/duration in TrackMetaStore
+                    let title = val.get("title").and_then(|v| v.as_str()).map(|s| s.to_string());
+                    let artist = val.get("artist").and_then(|v| v.as_str()).map(|s| s.to_string())
+                        .or_else(|| val.get("uploader").and_then(|v| v.as_str()).map(|s| s.to_string()));
+                    let thumbnail = val.get("thumbnail").and_then(|v| v.as_str()).map(|s| s.to_string());
+                    let page = val.get("webpage_url").and_then(|v| v.as_str()).map(|s| s.to_string());
+
+                    let mut duration_opt: Option<std::time::Duration> = None;
+                    if let Some(dv) = val.get("duration") {
+                        if let Some(f) = dv.as_f64() {
+                            duration_opt = Some(std::time::Duration::from_secs_f64(f));
+                        } else if let Some(u) = dv.as_u64() {
+                            duration_opt = Some(std::time::Duration::from_secs(u));
+                        }
+                    }
+
+                    if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
+                        let mut mm = ms.lock().await;
+                        let chapters = crate::chapters::parse(&val);
+                        mm.insert(guild_id, crate::TrackMeta { title, artist, duration: duration_opt, thumbnail, chapters, is_live: false, url: page });
+                    }
+
+                    let mut http_input = songbird::input::HttpRequest::new_with_headers(http_client.clone(), url.to_string(), headers.clone());
+                    if let Some(fs) = val.get("filesize").and_then(|v| v.as_u64()) {
+                        http_input.content_length = Some(fs);
+                    }
 
-                                    // If JSON contains metadata, store title/artist/thumbnail/duration in TrackMetaStore
-                                    let title = val.get("title").and_then(|v| v.as_str()).map(|s| s.to_string());
-                                    let artist = val.get("artist").and_then(|v| v.as_str()).map(|s| s.to_string())
-                                        .or_else(|| val.get("uploader").and_then(|v| v.as_str()).map(|s| s.to_string()));
-                                    let thumbnail = val.get("thumbnail").and_then(|v| v.as_str()).map(|s| s.to_string());
-
-                                    let mut duration_opt: Option<std::time::Duration> = None;
-                                    if let Some(dv) = val.get("duration") {
-                                        if let Some(f) = dv.as_f64() {
-                                            duration_opt = Some(std::time::Duration::from_secs_f64(f));
-                                        } else if let Some(u) = dv.as_u64() {
-                                            duration_opt = Some(std::time::Duration::from_secs(u));
-                                        }
-                                    }
+                    let new_handle = handler.play_input(http_input.into());
 
-                                    if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
-                                        let mut mm = ms.lock().await;
-                                        mm.insert(guild_id, crate::TrackMeta { title, artist, duration: duration_opt, thumbnail });
-                                    }
+                    match new_handle.make_playable_async().await {
+                        Ok(()) => {
+                            let _ = new_handle.play();
+                            // Set default volume
+                            let _ = new_handle.set_volume(0.20);
+                            let gid = guild_id;
+                            let _ = store_handle(ctx, gid, new_handle.clone()).await;
+                            report(progress, Stage::Playing);
+                            send_info(
+                                ctx,
+                                channel,
+                                theme,
+                                "Music",
+                                &format!("Now playing (format {}): {search_query}", fmt),
+                            )
+                            .await?;
+                            return Ok(());
+                        }
+                        Err(e2) => {
+                            tracing::warn!("Format fallback {} failed: {e2:?}", fmt);
+
+                            // Try an ffmpeg child-stream fallback: spawn ffmpeg to read the URL and pipe PCM to stdout
+                            // Build header string for ffmpeg if provided
+                            let mut header_str = String::new();
+                            for (hn, hv) in headers.iter() {
+                                header_str.push_str(&format!("{}: {}\r\n", hn.as_str(), hv.to_str().unwrap_or_default()));
+                            }
 
-                                    let mut http_input = songbird::input::HttpRequest::new_with_headers(http_client.clone(), url.to_string(), headers.clone());
-                                    if let Some(fs) = val.get("filesize").and_then(|v| v.as_u64()) {
-                                        http_input.content_length = Some(fs);
+                            report(progress, Stage::Transcoding);
+                            // Use std::process::Command so we get a std::process::Child suitable for ChildContainer
+                            let mut ff_cmd = std::process::Command::new("ffmpeg");
+                            if !header_str.is_empty() {
+                                ff_cmd.arg("-headers").arg(header_str);
+                            }
+// Opus in Ogg at the server's quality, which songbird decodes itself
+                                ff_cmd
+                                .arg("-i")
+                                .arg(url.to_string())
+                                .arg("-vn")
+                                .args(quality.ffmpeg_output())
+                                .arg("pipe:1")
+                                .stdout(std::process::Stdio::piped())
+                                    .stderr(std::process::Stdio::piped());
+                            let child_proc_res = spawn_child(ctx, guild_id, &mut ff_cmd).await;
+
+                            match child_proc_res {
+                                Ok(mut child_proc) => {
+                                    // Prepare a stderr file to capture ffmpeg diagnostics we can send to Discord if requested
+                                    let uniq_child = std::time::SystemTime::now()
+                                        .duration_since(std::time::UNIX_EPOCH)
+                                        .map(|d| d.as_nanos())
+                                        .unwrap_or(0);
+                                    let stderr_log = crate::storage::data_path(format!("yt-{}-{}-ffstderr.log", std::process::id(), uniq_child));
+
+                                    // Capture ffmpeg stderr into a file for later inspection
+                                    if let Some(mut stderr) = child_proc.stderr.take() {
+                                        let stderr_log_clone = stderr_log.clone();
+                                        std::thread::spawn(move || {
+                                            use std::io::Read;
+                                            let mut buf = String::new();
+                                            let _ = stderr.read_to_string(&mut buf);
+                                            let _ = std::fs::write(&stderr_log_clone, &buf);
+                                            if !buf.is_empty() {
+                                                tracing::warn!("ffmpeg child stderr written to {}", stderr_log_clone.display());
+                                            }
+                                        });
                                     }
 
-                                    let new_handle = handler.play_input(http_input.into());
+                                    // Wrap the std child in Songbird's ChildContainer adapter
+                                    let container = songbird::input::ChildContainer::from(child_proc);
+                                    let child_input: songbird::input::Input = container.into();
+                                    let child_handle = handler.play_input(child_input);
 
-                                    match new_handle.make_playable_async().await {
+                                    match child_handle.make_playable_async().await {
                                         Ok(()) => {
-                                            let _ = new_handle.play();
+                                            // If we had a stderr file, remove it on success
+                                            let _ = tokio::fs::remove_file(&stderr_log).await;
+
+                                            let _ = child_handle.play();
                                             // Set default volume
-                                            let _ = new_handle.set_volume(0.20);
-                                            let gid = guild_id;
-                                            let _ = store_handle(ctx, gid, new_handle.clone()).await;
+                                            let _ = child_handle.set_volume(0.20);
+                                            report(progress, Stage::Playing);
                                             send_info(
                                                 ctx,
                                                 channel,
-                                                color,
+                                                theme,
                                                 "Music",
-                                                &format!("Now playing (format {}): {search_query}", fmt),
+                                                &format!("Now playing (ffmpeg stream): {search_query}"),
                                             )
                                             .await?;
                                             return Ok(());
                                         }
-                                        Err(e2) => {
-                                            eprintln!("Format fallback {} failed: {e2:?}", fmt);
-
-                                            // Try an ffmpeg child-stream fallback: spawn ffmpeg to read the URL and pipe PCM to stdout
-                                            // Build header string for ffmpeg if provided
-                                            let mut header_str = String::new();
-                                            for (hn, hv) in headers.iter() {
-                                                header_str.push_str(&format!("{}: {}\r\n", hn.as_str(), hv.to_str().unwrap_or_default()));
-                                            }
-
-                                            // Use std::process::Command so we get a std::process::Child suitable for ChildContainer
-                                            let mut ff_cmd = std::process::Command::new("ffmpeg");
-                                            if !header_str.is_empty() {
-                                                ff_cmd.arg("-headers").arg(header_str);
-                                            }
-// Use WAV (pcm_s16le) container so symphonia can probe the stream reliably
-                                                let child_proc_res = ff_cmd
-                                                .arg("-i")
-                                                .arg(url.to_string())
-                                                .arg("-vn")
-                                                .arg("-c:a").arg("pcm_s16le")
-                                                .arg("-f").arg("wav")
-                                                .arg("-ar").arg("48000")
-                                                .arg("-ac").arg("2")
-                                                .arg("pipe:1")
-                                                .stdout(std::process::Stdio::piped())
-                                                    .stderr(std::process::Stdio::piped())
-                                                .spawn();
-
-                                            match child_proc_res {
-                                                Ok(mut child_proc) => {
-                                                    // Prepare a stderr file to capture ffmpeg diagnostics we can send to Discord if requested
-                                                    let cwd = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
-                                                    let uniq_child = std::time::SystemTime::now()
-                                                        .duration_since(std::time::UNIX_EPOCH)
-                                                        .map(|d| d.as_nanos())
-                                                        .unwrap_or(0);
-                                                    let stderr_log = cwd.join(format!("yt-{}-{}-ffstderr.log", std::process::id(), uniq_child));
-
-                                                    // Capture ffmpeg stderr into a file for later inspection
-                                                    if let Some(mut stderr) = child_proc.stderr.take() {
-                                                        let stderr_log_clone = stderr_log.clone();
-                                                        std::thread::spawn(move || {
-                                                            use std::io::Read;
-                                                            let mut buf = String::new();
-                                                            let _ = stderr.read_to_string(&mut buf);
-                                                            let _ = std::fs::write(&stderr_log_clone, &buf);
-                                                            if !buf.is_empty() {
-                                                                eprintln!("ffmpeg child stderr written to {}", stderr_log_clone.display());
-                                                            }
-                                                        });
-                                                    }
-
-                                                    // Wrap the std child in Songbird's ChildContainer adapter
-                                                    let container = songbird::input::ChildContainer::from(child_proc);
-                                                    let child_input: songbird::input::Input = container.into();
-                                                    let child_handle = handler.play_input(child_input);
-
-                                                    match child_handle.make_playable_async().await {
-                                                        Ok(()) => {
-                                                            // If we had a stderr file, remove it on success
-                                                            let _ = tokio::fs::remove_file(&stderr_log).await;
-
-                                                            let _ = child_handle.play();
-                                                            // Set default volume
-                                                            let _ = child_handle.set_volume(0.20);
-                                                            send_info(
-                                                                ctx,
-                                                                channel,
-                                                                color,
-                                                                "Music",
-                                                                &format!("Now playing (ffmpeg stream): {search_query}"),
-                                                            )
-                                                            .await?;
-                                                            return Ok(());
-                                                        }
-                                                        Err(e3) => {
-                                                            eprintln!("ffmpeg child playback failed: {e3:?}");
-                                                            // If verbose, send stderr file content to the channel for debugging
-                                                            if std::env::var("MUSIC_VERBOSE").is_ok() {
-                                                                if let Ok(s) = tokio::fs::read_to_string(&stderr_log).await {
-                                                                    if !s.is_empty() {
-                                                                        let _ = send_info(
-                                                                            ctx,
-                                                                            channel,
-                                                                            color,
-                                                                            "Music - ffmpeg stderr",
-                                                                            &s,
-                                                                        )
-                                                                        .await;
-                                                                    }
-                                                                }
-                                                            }
-                                                            // Clean up stderr file
-                                                            let _ = tokio::fs::remove_file(&stderr_log).await;
-
-                                                            continue;
-                                                        }
+                                        Err(e3) => {
+                                            tracing::warn!("ffmpeg child playback failed: {e3:?}");
+                                            // If verbose, send stderr file content to the channel for debugging
+                                            if std::env::var("MUSIC_VERBOSE").is_ok() {
+                                                if let Ok(s) = tokio::fs::read_to_string(&stderr_log).await {
+                                                    if !s.is_empty() {
+                                                        let _ = send_info(
+                                                            ctx,
+                                                            channel,
+                                                            theme,
+                                                            "Music - ffmpeg stderr",
+                                                            &s,
+                                                        )
+                                                        .await;
                                                     }
                                                 }
-                                                Err(err_spawn) => {
-                                                    eprintln!("Failed to spawn ffmpeg for child stream: {err_spawn:?}");
-                                                    continue;
-                                                }
                                             }
+                                            // Clean up stderr file
+                                            let _ = tokio::fs::remove_file(&stderr_log).await;
+
+                                            continue;
                                         }
                                     }
                                 }
+                                Err(err_spawn) => {
+                                    tracing::warn!("Failed to spawn ffmpeg for child stream: {err_spawn:?}");
+                                    continue;
+                                }
                             }
                         }
                     }
-                    Ok(o) => {
-                        eprintln!("yt-dlp -g for format {} failed: {}", fmt, String::from_utf8_lossy(&o.stderr));
-                        continue;
-                    }
-                    Err(err2) => {
-                        eprintln!("Failed to run yt-dlp for format {}: {err2:?}", fmt);
-                        continue;
-                    }
                 }
             }
 
-            // Final fallback: download a file into the bot's current working dir and play it, then remove after finish
+            // Final fallback: download a file into the data directory and play it, then remove after finish
             // Use an output template so yt-dlp chooses the extension (avoid mismatches)
-            let cwd = std::env::current_dir()?;
+            let data_dir = crate::storage::data_path(".");
             let uniq = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)?
                 .as_nanos();
             let out_template_prefix = format!("yt-{}-{}", std::process::id(), uniq);
-            let out_template = cwd.join(format!("{}.%(ext)s", out_template_prefix));
+            let out_template = data_dir.join(format!("{}.%(ext)s", out_template_prefix));
 
+            report(progress, Stage::Downloading);
             let download_arg = format!("ytsearch1:{}", search_query);
             let out = Command::new("yt-dlp")
+                .kill_on_drop(true)
+                .args(&cookie_args)
                 .arg("-f")
-                .arg("bestaudio")
+                .arg(quality.ytdl_format())
                 .arg("-o")
                 .arg(out_template.to_string_lossy().to_string())
                 .arg(&download_arg)
@@ -764,21 +1370,19 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                 .await?;
 
             if !out.status.success() {
-                eprintln!("yt-dlp download failed: {}", String::from_utf8_lossy(&out.stderr));
-                send_info(
-                    ctx,
-                    channel,
-                    color,
-                    "Music",
-                    &format!("Failed to play {search_query}: {e:?}. Diagnostic: {diagnostic}. Also failed to download fallback."),
-                )
-                .await?;
-                return Ok(());
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                tracing::warn!("yt-dlp download failed: {}", stderr);
+                // Known refusals get a specific answer instead of the raw yt-dlp output
+                let text = match Unplayable::classify(&format!("{e:?}\n{stderr}")) {
+                    Some(reason) => reason.message(&search_query),
+                    None => format!("Failed to play {search_query}: {e:?}. Diagnostic: {diagnostic}. Also failed to download fallback."),
+                };
+                return Err(text.into());
             }
 
-            // Attempt to discover the actual downloaded file written by yt-dlp in the cwd
+            // Attempt to discover the actual downloaded file written by yt-dlp in the data directory
             let mut found: Option<PathBuf> = None;
-            let mut rd = tokio::fs::read_dir(&cwd).await?;
+            let mut rd = tokio::fs::read_dir(&data_dir).await?;
             while let Some(entry) = rd.next_entry().await? {
                 let name = entry.file_name();
                 if let Some(s) = name.to_str() {
@@ -790,23 +1394,21 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
             }
 
             if found.is_none() {
-                eprintln!("yt-dlp reported success but couldn't find file with prefix {} in {}", out_template_prefix, cwd.display());
-                eprintln!("yt-dlp stdout: {}", String::from_utf8_lossy(&out.stdout));
-                eprintln!("yt-dlp stderr: {}", String::from_utf8_lossy(&out.stderr));
-
-                send_info(
-                    ctx,
-                    channel,
-                    color,
-                    "Music",
-                    &format!("Downloaded fallback reported success but the expected file wasn't found in {}. yt-dlp output: stdout: {} stderr: {}", cwd.display(), String::from_utf8_lossy(&out.stdout), String::from_utf8_lossy(&out.stderr)),
+                tracing::warn!("yt-dlp reported success but couldn't find file with prefix {} in {}", out_template_prefix, data_dir.display());
+                tracing::warn!("yt-dlp stdout: {}", String::from_utf8_lossy(&out.stdout));
+                tracing::warn!("yt-dlp stderr: {}", String::from_utf8_lossy(&out.stderr));
+
+                return Err(format!(
+                    "Downloaded fallback reported success but the expected file wasn't found in {}. yt-dlp output: stdout: {} stderr: {}",
+                    data_dir.display(),
+                    String::from_utf8_lossy(&out.stdout),
+                    String::from_utf8_lossy(&out.stderr)
                 )
-                .await?;
-                return Ok(());
+                .into());
             }
 
             let tmp_path = found.unwrap();
-            eprintln!("Using downloaded file: {}", tmp_path.display());
+            tracing::warn!("Using downloaded file: {}", tmp_path.display());
 
             // Play the downloaded file (or the discovered one)
             let file_input = songbird::input::File::new(tmp_path.clone());
@@ -834,11 +1436,12 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
 
                     let gid = guild_id;
                     let _ = store_handle(ctx, gid, new_handle.clone()).await;
+                    report(progress, Stage::Playing);
 
                     send_info(
                         ctx,
                         channel,
-                        color,
+                        theme,
                         "Music",
                         &format!("Now playing (downloaded): {search_query}"),
                     )
@@ -846,15 +1449,15 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                     return Ok(());
                 }
                 Err(e2) => {
-                    eprintln!("Download fallback failed: {e2:?}. Trying ffmpeg transcode...");
+                    tracing::warn!("Download fallback failed: {e2:?}. Trying ffmpeg transcode...");
 
                     // Verify the downloaded file still exists before attempting ffmpeg transcode
                     if tokio::fs::metadata(&tmp_path).await.is_err() {
-                        eprintln!("Transcode: expected downloaded file no longer exists: {}", tmp_path.display());
+                        tracing::warn!("Transcode: expected downloaded file no longer exists: {}", tmp_path.display());
                         send_info(
                             ctx,
                             channel,
-                            color,
+                            theme,
                             "Music",
                             &format!("Failed to transcode: expected downloaded file missing: {}. Aborting fallback.", tmp_path.display()),
                         )
@@ -863,20 +1466,17 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                     }
 
                     // Attempt to transcode the downloaded file to a more-compatible audio file using ffmpeg
-                    // Transcode to an Ogg/Opus file (more broadly probeable)
-                    // Transcode to a WAV file (pcm_s16le) so symphonia can probe it reliably
-                    let trans_path = std::env::current_dir()?.join(format!("yt-{}-{}.wav", std::process::id(), uniq));
+                    // Transcode to an Ogg/Opus file at the server's quality, which songbird decodes itself
+                    let trans_path = crate::storage::data_path(format!("yt-{}-{}.ogg", std::process::id(), uniq));
 
+                    report(progress, Stage::Transcoding);
                     let ffout = Command::new("ffmpeg")
+                        .kill_on_drop(true)
                         .arg("-y")
                         .arg("-i")
                         .arg(tmp_path.to_string_lossy().to_string())
-                        .arg("-ac")
-                        .arg("2")
-                        .arg("-ar")
-                        .arg("48000")
-                        .arg("-c:a")
-                        .arg("pcm_s16le")
+                        .arg("-vn")
+                        .args(quality.ffmpeg_output())
                         .arg(trans_path.to_string_lossy().to_string())
                         .output()
                         .await;
@@ -910,11 +1510,12 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
 
                                     let gid = guild_id;
                                     let _ = store_handle(ctx, gid, new_handle2.clone()).await;
+                                    report(progress, Stage::Playing);
 
                                     send_info(
                                         ctx,
                                         channel,
-                                        color,
+                                        theme,
                                         "Music",
                                         &format!("Now playing (transcoded): {search_query}"),
                                     )
@@ -922,14 +1523,14 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                                     return Ok(());
                                 }
                                 Err(e3) => {
-                                    eprintln!("Transcoded playback failed: {e3:?}");
+                                    tracing::warn!("Transcoded playback failed: {e3:?}");
                                     // Include ffmpeg stderr in diagnostics if verbose mode is enabled
                                     let ff_stderr = String::from_utf8_lossy(&o.stderr).to_string();
                                     if std::env::var("MUSIC_VERBOSE").is_ok() && !ff_stderr.is_empty() {
                                         let _ = send_info(
                                             ctx,
                                             channel,
-                                            color,
+                                            theme,
                                             "Music - Transcode stderr",
                                             &format!("ffmpeg stderr: {}", ff_stderr),
                                         )
@@ -939,7 +1540,7 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                                     send_info(
                                         ctx,
                                         channel,
-                                        color,
+                                        theme,
                                         "Music",
                                         &format!("Failed to play {search_query}: {e:?}. Transcode playback failed: {e3:?}. Diagnostic: {diagnostic}"),
                                     )
@@ -949,13 +1550,13 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                             }
                         }
                         Ok(o) => {
-                            eprintln!("ffmpeg failed: {}", String::from_utf8_lossy(&o.stderr));
+                            tracing::warn!("ffmpeg failed: {}", String::from_utf8_lossy(&o.stderr));
                             let ff_stderr = String::from_utf8_lossy(&o.stderr).to_string();
                             if std::env::var("MUSIC_VERBOSE").is_ok() && !ff_stderr.is_empty() {
                                 let _ = send_info(
                                     ctx,
                                     channel,
-                                    color,
+                                    theme,
                                     "Music - Transcode stderr",
                                     &format!("ffmpeg stderr: {}", ff_stderr),
                                 )
@@ -965,7 +1566,7 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                             send_info(
                                 ctx,
                                 channel,
-                                color,
+                                theme,
                                 "Music",
                                 &format!("Failed to play {search_query}: {e:?}. Download fallback succeeded but ffmpeg transcode failed."),
                             )
@@ -973,11 +1574,11 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
                             return Ok(());
                         }
                         Err(err3) => {
-                            eprintln!("Failed to run ffmpeg: {err3:?}");
+                            tracing::warn!("Failed to run ffmpeg: {err3:?}");
                             send_info(
                                 ctx,
                                 channel,
-                                color,
+                                theme,
                                 "Music",
                                 &format!("Failed to play {search_query}: {e:?}. Download fallback succeeded but ffmpeg couldn't be run."),
                             )
@@ -991,144 +1592,513 @@ async fn play(ctx: &Context, channel: ChannelId, _user_id: UserId, guild_id: Opt
     }
 }
 
+/// Play the stream helper's output (see `sources::Playable::Command`), transcoding it through
+/// ffmpeg when songbird can't read it as is. `Ok(false)` when nothing worked and the fallback
+/// should play instead.
+#[allow(clippy::too_many_arguments)]
+async fn play_command(
+    ctx: &Context,
+    handler: &mut songbird::Call,
+    channel: ChannelId,
+    guild_id: GuildId,
+    cmd: &str,
+    query: &str,
+    theme: &Theme,
+    progress: Option<&Progress>,
+) -> MusicResult<bool> {
+    report(progress, Stage::Downloading);
+    // Spawn via shell so users can compose pipelines; expect the command to write raw PCM/WAV to stdout
+    match spawn_child(ctx, guild_id, std::process::Command::new("sh").arg("-c").arg(cmd).stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped())).await {
+        Ok(mut child_proc) => {
+            let helper_stderr = child_proc.stderr.take();
+            // First attempt: try to play the raw child output directly
+            let container = songbird::input::ChildContainer::from(child_proc);
+            let child_input: songbird::input::Input = container.into();
+            let new_handle = handler.play_input(child_input);
+
+            match new_handle.make_playable_async().await {
+                Ok(()) => {
+                    let _ = new_handle.play();
+                    let _ = new_handle.set_volume(0.20);
+                    let gid = guild_id;
+                    let _ = store_handle(ctx, gid, new_handle.clone()).await;
+                    if let Some(stderr) = helper_stderr {
+                        crate::spotify_helper::watch(ctx, gid, stderr, new_handle.clone());
+                    }
+                    report(progress, Stage::Playing);
+
+                    let _ = send_info(
+                        ctx,
+                        channel,
+                        theme,
+                        "Music",
+                        &format!("Now streaming from Spotify: {}", query.trim()),
+                    )
+                    .await?;
+
+                    return Ok(true);
+                }
+                Err(e) => {
+                    tracing::warn!("Initial spotify stream parse failed: {e:?}; attempting ffmpeg transcode fallback");
+
+                    // Try several common input hints to ffmpeg to handle helpers that emit raw PCM, WAV, MP3, or Opus
+                    let input_formats = [
+                        "",                    // let ffmpeg probe
+                        "-f wav",             // WAV container
+                        "-f s16le -ar 44100 -ac 2", // raw signed 16-bit PCM 44.1kHz stereo
+                        "-f s16le -ar 48000 -ac 2", // raw signed 16-bit PCM 48kHz stereo
+                        "-f mp3",
+                        "-f opus",
+                    ];
+
+                    // Collect stderr logs for diagnostics
+                    let mut stderr_logs: Vec<String> = Vec::new();
+
+                    for fmt in &input_formats {
+                        report(progress, Stage::Transcoding);
+                        let ff_cmd = if fmt.is_empty() {
+                            format!("{cmd} | ffmpeg -hide_banner -loglevel error -i - -vn -c:a pcm_s16le -ar 48000 -ac 2 -f wav -", cmd = cmd)
+                        } else {
+                            format!("{cmd} | ffmpeg -hide_banner -loglevel error {fmt} -i - -vn -c:a pcm_s16le -ar 48000 -ac 2 -f wav -", cmd = cmd, fmt = fmt)
+                        };
+
+                        match spawn_child(ctx, guild_id, std::process::Command::new("sh").arg("-c").arg(&ff_cmd).stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped())).await {
+                            Ok(mut child_proc2) => {
+                                // Prepare a stderr file to capture ffmpeg diagnostics
+                                let uniq = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+                                let stderr_log = crate::storage::data_path(format!("spotify-{}-ffstderr-{}.log", std::process::id(), uniq));
+
+                                if let Some(mut stderr) = child_proc2.stderr.take() {
+                                    let stderr_log_clone = stderr_log.clone();
+                                    std::thread::spawn(move || {
+                                        use std::io::Read;
+                                        let mut buf = String::new();
+                                        let _ = stderr.read_to_string(&mut buf);
+                                        let _ = std::fs::write(&stderr_log_clone, &buf);
+                                    });
+                                }
+
+                                let container2 = songbird::input::ChildContainer::from(child_proc2);
+                                let child_input2: songbird::input::Input = container2.into();
+                                let new_handle2 = handler.play_input(child_input2);
+
+                                match new_handle2.make_playable_async().await {
+                                    Ok(()) => {
+                                        let _ = new_handle2.play();
+                                        let _ = new_handle2.set_volume(0.20);
+                                        let gid = guild_id;
+                                        let _ = store_handle(ctx, gid, new_handle2.clone()).await;
+                                        report(progress, Stage::Playing);
+
+                                        let _ = send_info(
+                                            ctx,
+                                            channel,
+                                            theme,
+                                            "Music",
+                                            &format!("Now streaming from Spotify (transcoded, fmt='{}'): {}", fmt, query.trim()),
+                                        )
+                                        .await?;
+
+                                        return Ok(true);
+                                    }
+                                    Err(e2) => {
+                                        tracing::warn!("Transcoded spotify stream (fmt='{}') failed to play: {e2:?}", fmt);
+
+                                        // Read stderr log (if present) for diagnostics and append
+                                        if let Ok(s) = tokio::fs::read_to_string(&stderr_log).await {
+                                            if !s.is_empty() {
+                                                stderr_logs.push(format!("fmt='{}' stderr:\n{}", fmt, s));
+                                                let _ = tokio::fs::remove_file(&stderr_log).await;
+                                            }
+                                        }
+
+                                        // try next format
+                                        continue;
+                                    }
+                                }
+                            }
+                            Err(e2) => {
+                                tracing::warn!("Failed to spawn ffmpeg transcode pipeline (fmt='{}'): {e2:?}", fmt);
+                                stderr_logs.push(format!("fmt='{}' spawn failed: {e2:?}", fmt));
+                                continue;
+                            }
+                        }
+                    }
+
+                    // If we reach here, all attempts failed. Optionally send verbose diagnostics
+                    if std::env::var("MUSIC_VERBOSE").is_ok() {
+                        let msg = if stderr_logs.is_empty() { "No ffmpeg stderr captured".to_string() } else { stderr_logs.join("\n-----\n") };
+                        let _ = send_info(ctx, channel, theme, "Music - Spotify ffmpeg diagnostics", &msg).await;
+                    }
+
+                    let _ = send_info(ctx, channel, theme, "Music", "Spotify stream failed (all transcode attempts failed), falling back to YouTube search").await;
+                }
+            }
+        }
+        Err(e) => {
+            tracing::warn!("Failed to spawn spotify stream command: {e:?}");
+            let _ = send_info(ctx, channel, theme, "Music", "Failed to start Spotify stream command, falling back to YouTube search").await;
+        }
+    }
+    Ok(false)
+}
+
+/// Play a direct audio URL or local file, which need no yt-dlp. Live streams go through
+/// `live`, which reconnects them when they drop.
+#[allow(clippy::too_many_arguments)]
+async fn play_direct(
+    ctx: &Context,
+    call: &std::sync::Arc<Mutex<songbird::Call>>,
+    channel: ChannelId,
+    guild_id: GuildId,
+    playable: crate::sources::Playable,
+    label: &str,
+    is_live: bool,
+    theme: &Theme,
+    progress: Option<&Progress>,
+    exempt: bool,
+) -> MusicResult<()> {
+    let limits = crate::limits::get(ctx, guild_id).await;
+    let facts = crate::limits::Facts { title: Some(label.to_string()), duration: None, is_live };
+    if !exempt && let Some(problem) = limits.violation(&facts) {
+        send_info(ctx, channel, theme, "Music", &problem).await?;
+        return Ok(());
+    }
+
+    let mut handler = call.lock().await;
+    report(progress, Stage::Downloading);
+    let input: songbird::input::Input = match playable {
+        crate::sources::Playable::Stream(url) if is_live => {
+            report(progress, Stage::Transcoding);
+            crate::live::start(ctx, &mut handler, guild_id, channel, &url, Some(label.to_string())).await?;
+            report(progress, Stage::Playing);
+            send_info(ctx, channel, theme, "Music", &format!("Now playing (LIVE): {}", label)).await?;
+            return Ok(());
+        }
+        crate::sources::Playable::Stream(url) => songbird::input::HttpRequest::new(Client::new(), url).into(),
+        crate::sources::Playable::File(path) => songbird::input::File::new(path).into(),
+        _ => return Err("not a direct stream or file".into()),
+    };
+    let handle = handler.play_input(input);
+    handle.make_playable_async().await?;
+    let _ = handle.set_volume(0.20);
+    let _ = store_handle(ctx, guild_id, handle).await;
+    report(progress, Stage::Playing);
+    send_info(ctx, channel, theme, "Music", &format!("Now playing: {}", label)).await?;
+    Ok(())
+}
+
+/// Why yt-dlp refused a video, recognised from its error output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unplayable {
+    AgeRestricted,
+    SignInRequired,
+    GeoBlocked,
+    Private,
+    MembersOnly,
+    Copyright,
+}
+
+/// Lowercase fragments of yt-dlp (and YouTube) error messages, checked in order.
+const UNPLAYABLE_SIGNATURES: &[(&str, Unplayable)] = &[
+    ("confirm your age", Unplayable::AgeRestricted),
+    ("age-restricted", Unplayable::AgeRestricted),
+    ("inappropriate for some users", Unplayable::AgeRestricted),
+    ("not a bot", Unplayable::SignInRequired),
+    ("members-only", Unplayable::MembersOnly),
+    ("join this channel to get access", Unplayable::MembersOnly),
+    ("available to this channel's members", Unplayable::MembersOnly),
+    ("private video", Unplayable::Private),
+    ("video is private", Unplayable::Private),
+    ("copyright", Unplayable::Copyright),
+    ("not available in your country", Unplayable::GeoBlocked),
+    ("geo restriction", Unplayable::GeoBlocked),
+    ("geo-restricted", Unplayable::GeoBlocked),
+    ("blocked it in your country", Unplayable::GeoBlocked),
+];
+
+impl Unplayable {
+    fn classify(output: &str) -> Option<Self> {
+        let output = output.to_lowercase();
+        UNPLAYABLE_SIGNATURES.iter().find(|(sig, _)| output.contains(sig)).map(|(_, reason)| *reason)
+    }
+
+    fn message(self, query: &str) -> String {
+        const COOKIES: &str = "The bot owner can set `music.ytdlp_cookies` in config.jsonc to a cookies file from a \
+             signed-in account (see \"yt-dlp cookies\" in the README).";
+        match self {
+            Unplayable::AgeRestricted => format!("Can't play {query}: it's age-restricted and YouTube wants a signed-in account. {COOKIES}"),
+            Unplayable::SignInRequired => format!("Can't play {query}: YouTube is asking the bot to sign in. {COOKIES}"),
+            Unplayable::MembersOnly => format!("Can't play {query}: it's for channel members only. {COOKIES} The account has to be a member."),
+            Unplayable::GeoBlocked => format!("Can't play {query}: it isn't available in the bot's country. Try another upload of it."),
+            Unplayable::Private => format!("Can't play {query}: the video is private."),
+            Unplayable::Copyright => format!("Can't play {query}: it was taken down over a copyright claim. Try another upload of it."),
+        }
+    }
+}
+
+/// One format of the `yt-dlp -j` fallback: the format and the info JSON for the first search
+/// hit, or `None` if yt-dlp couldn't produce it.
+async fn probe_format(search_query: String, fmt: &'static str, extra_args: Vec<String>) -> Option<(&'static str, serde_json::Value)> {
+    let output = tokio::process::Command::new("yt-dlp")
+        .args(&extra_args)
+        .arg("-f")
+        .arg(fmt)
+        .arg("-j")
+        .arg(format!("ytsearch1:{}", search_query))
+        .kill_on_drop(true)
+        .output()
+        .await;
+
+    match output {
+        Ok(o) if o.status.success() => {
+            let stdout = String::from_utf8_lossy(&o.stdout);
+            let json_line = stdout.lines().next()?;
+            serde_json::from_str(json_line).ok().map(|val| (fmt, val))
+        }
+        Ok(o) => {
+            tracing::warn!("yt-dlp -j for format {} failed: {}", fmt, String::from_utf8_lossy(&o.stderr));
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Failed to run yt-dlp for format {}: {e:?}", fmt);
+            None
+        }
+    }
+}
+
 async fn send_info(
     ctx: &Context,
     channel: ChannelId,
-    color: u32,
+    theme: &Theme,
     title: &str,
     desc: &str,
 ) -> MusicResult<()> {
-    let embed = CreateEmbed::new()
-        .title(title)
-        .description(desc)
-        .color(color);
+    let embed = theme.embed().title(title).description(desc);
 
     let message = CreateMessage::new().embed(embed);
     channel.send_message(&ctx.http, message).await?;
     Ok(())
 }
 
-async fn send_temp_info(ctx: Context, channel: ChannelId, content: &str) -> MusicResult<()> {
-    // Send a short non-embedded message and delete it after a short delay to mimic ephemeral behavior
-    let msg = channel
-        .send_message(&ctx.http, CreateMessage::new().content(content))
-        .await?;
-
-    let http = ctx.http.clone();
-    let id = msg.id;
-    let channel_clone = channel;
-    tokio::spawn(async move {
-        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-        let _ = channel_clone.delete_message(&http, id).await;
-    });
-
+/// Informational reply to a music command. Slash invocations answer through the interaction,
+/// ephemerally when the guild's `ephemeral_replies` setting is on.
+async fn notify(cmd: crate::Ctx<'_>, theme: &Theme, title: &str, desc: &str) -> MusicResult<()> {
+    let embed = theme.embed().title(title).description(desc);
+    let ephemeral = crate::settings::ephemeral(cmd).await;
+    cmd.send(poise::CreateReply::default().embed(embed).ephemeral(ephemeral)).await?;
     Ok(())
 }
 
-async fn send_control_panel(
+/// Lavalink backend: resolve `query` on the node (Spotify links through their metadata, as the
+/// songbird path does) and play the first match.
+#[allow(clippy::too_many_arguments)]
+async fn play_lavalink(
     ctx: &Context,
+    node: &crate::lavalink::Node,
     channel: ChannelId,
-    owner: UserId,
     guild_id: GuildId,
-    color: u32,
+    query: &str,
+    theme: &Theme,
+    progress: Option<&Progress>,
+    exempt: bool,
+    search: crate::preferences::SearchSource,
 ) -> MusicResult<()> {
-    use serenity::builder::{CreateActionRow, CreateButton};
-    use serenity::all::ButtonStyle;
-
-    // Attempt to fetch current track info
-    let mut _desc = String::new();
-    let maybe_store = ctx.data.read().await.get::<crate::TrackStore>().cloned();
-
-    if let Some(store) = maybe_store {
-        let map = store.lock().await;
-        if let Some(handle) = map.get(&guild_id) {
-            match handle.get_info().await {
-                Ok(info) => {
-                    // Try to fetch stored total duration for this guild, if present
-                    let dur_opt = {
-                        let data_read = ctx.data.read().await;
-                        data_read.get::<crate::TrackMetaStore>().cloned()
-                    };
-
-                    let remaining = if let Some(meta_store) = dur_opt {
-                        let meta_map = meta_store.lock().await;
-                        if let Some(meta) = meta_map.get(&guild_id) {
-                            if let Some(total) = meta.duration {
-                                if total > info.position {
-                                    let rem = total - info.position;
-                                    let secs = rem.as_secs();
-                                    let mins = secs / 60;
-                                    let secs = secs % 60;
-                                    format!("{mins}:{:02}", secs)
-                                } else {
-                                    "0:00".into()
-                                }
-                            } else {
-                                "Unknown".into()
-                            }
-                        } else {
-                            "Unknown".into()
-                        }
-                    } else {
-                        "Unknown".into()
-                    };
-                   _desc = format!("Status: {:?}\nVolume: {:.2}\nRemaining: {}", info.playing, info.volume, remaining);
-                }
-                Err(_) => {
-                    _desc = "Status: Unknown".into();
+    let raw = query.trim();
+    let identifier = if raw.starts_with("http") && raw.contains("spotify") {
+        let api = crate::spotify::SpotifyApi::from_env();
+        let resolved = match (crate::spotify::parse_link(raw), api.token_from_env().await) {
+            (Some(crate::spotify::SpotifyLink::Track(id)), Ok(token)) => match api.track(&token.access_token, &id).await.ok().flatten() {
+                Some(track) => match track.isrc.as_deref() {
+                    Some(isrc) => crate::isrc::official_upload(isrc).await,
+                    None => None,
                 }
+                .or_else(|| Some(format!("ytsearch:{} {}", track.title, track.artist))),
+                None => None,
+            },
+            // Podcasts play from the show's RSS feed when it can be found
+            (Some(link), Ok(token)) => match api.episode(&token.access_token, &link).await.ok().flatten() {
+                Some(episode) => match crate::podcasts::episode_audio_url(&episode.show, &episode.title).await {
+                    Some(url) => Some(url),
+                    None => Some(format!("ytsearch:{} {}", episode.show, episode.title)),
+                },
+                None => None,
+            },
+            _ => None,
+        };
+        match resolved {
+            Some(identifier) => identifier,
+            None => {
+                return Err("Couldn't resolve that Spotify link".into());
             }
-        } else {
-            _desc = "No active track".into();
         }
+    } else if raw.starts_with("http") {
+        raw.to_string()
+    } else if search == crate::preferences::SearchSource::Spotify {
+        let search = crate::spotify::first_then_query(raw).await.ok().flatten().unwrap_or_else(|| raw.to_string());
+        format!("ytsearch:{}", search)
     } else {
-        _desc = "No active track store".into();
+        format!("ytsearch:{}", raw)
+    };
+
+    let Some(track) = node.load(&identifier).await? else {
+        return Err(format!("No results for {raw}").into());
+    };
+    let info = track.info.clone();
+    if !exempt {
+        let facts = crate::limits::Facts {
+            title: Some(info.title.clone()),
+            duration: (!info.is_stream).then(|| std::time::Duration::from_millis(info.length)),
+            is_live: info.is_stream,
+        };
+        if let Some(problem) = crate::limits::get(ctx, guild_id).await.violation(&facts) {
+            send_info(ctx, channel, theme, "Music", &problem).await?;
+            return Ok(());
+        }
+    }
+    if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
+        let duration = (!info.is_stream).then(|| std::time::Duration::from_millis(info.length));
+        ms.lock().await.insert(
+            guild_id,
+            crate::TrackMeta {
+                title: Some(info.title.clone()),
+                artist: Some(info.author.clone()),
+                duration,
+                thumbnail: info.artwork_url.clone(),
+                chapters: Vec::new(),
+                is_live: info.is_stream,
+                url: info.uri.clone(),
+            },
+        );
+    }
+    node.play(guild_id, track).await?;
+    report(progress, Stage::Playing);
+    crate::presence::track_started(ctx, guild_id, None).await;
+    crate::queue::record_played(ctx, guild_id).await;
+    let event = crate::events::track_started(ctx, guild_id).await;
+    crate::events::emit(ctx, guild_id, event).await;
+
+    send_info(ctx, channel, theme, "Music", &format!("Now playing: {} — {}", info.title, info.author)).await?;
+    Ok(())
+}
+
+/// The control panel embed for `guild_id`; the flag is false once nothing is playing.
+pub async fn panel_embed(ctx: &Context, guild_id: GuildId, theme: &Theme) -> (CreateEmbed, bool) {
+    let Some(status) = crate::player::status(ctx, guild_id).await else {
+        return (theme.embed().title("Music Controls").description("No active track"), false);
+    };
+    let meta = match ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
+        Some(ms) => ms.lock().await.get(&guild_id).cloned(),
+        None => None,
+    };
+
+    let remaining = match meta.as_ref().and_then(|m| m.duration) {
+        // Livestreams have no end; show how long it's been on instead
+        _ if meta.as_ref().is_some_and(|m| m.is_live) => {
+            let elapsed = crate::live::elapsed(ctx, guild_id).await.unwrap_or(status.position);
+            format!("LIVE · {} elapsed", crate::positions::clock(elapsed))
+        }
+        Some(total) if total > status.position => {
+            let secs = (total - status.position).as_secs();
+            format!("{}:{:02}", secs / 60, secs % 60)
+        }
+        Some(_) => "0:00".into(),
+        None => "Unknown".into(),
+    };
+    let state = if status.paused { "Paused" } else { "Playing" };
+    let mut desc = format!("Status: {}\nVolume: {:.2}\nRemaining: {}", state, status.volume, remaining);
+    if let Some(chapters) = meta.as_ref().map(|m| &m.chapters)
+        && let Some(i) = crate::chapters::index_at(chapters, status.position)
+    {
+        desc.push_str(&format!("\nChapter {}/{}: {}", i + 1, chapters.len(), chapters[i].title));
     }
 
-    // Try to get track title/artist/thumbnail from TrackMetaStore to make the embed more prominent
+    // Title/artist/thumbnail make the embed more prominent
     let mut title_str = "Music Controls".to_string();
     let mut thumbnail_opt: Option<String> = None;
-    if let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() {
-        let mm = ms.lock().await;
-        if let Some(meta) = mm.get(&guild_id) {
-            match (&meta.title, &meta.artist) {
-                (Some(t), Some(a)) => title_str = format!("{} — {}", t, a),
-                (Some(t), None) => title_str = t.clone(),
-                (None, Some(a)) => title_str = a.clone(),
-                _ => {}
-            }
-            thumbnail_opt = meta.thumbnail.clone();
+    if let Some(meta) = meta {
+        match (&meta.title, &meta.artist) {
+            (Some(t), Some(a)) => title_str = format!("{} — {}", t, a),
+            (Some(t), None) => title_str = t.clone(),
+            (None, Some(a)) => title_str = a.clone(),
+            _ => {}
         }
+        thumbnail_opt = meta.thumbnail;
     }
 
-    let mut embed = CreateEmbed::new().title(title_str).description(_desc).color(color);
+    let mut embed = theme.embed().title(title_str).description(desc);
     if let Some(th) = thumbnail_opt {
-        embed = embed.thumbnail(th);
+        embed = theme.thumbnail(embed, th);
     }
+    (embed, true)
+}
+
+/// Control panel buttons stop working after this long; the panel is disabled sooner if the
+/// track ends.
+const PANEL_TTL: std::time::Duration = std::time::Duration::from_secs(6 * 3600);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PanelAction {
+    Pause,
+    Resume,
+    Stop,
+    VolUp,
+    VolDown,
+    /// Anyone can press these three; they answer privately.
+    Favorite,
+    Queue,
+    History,
+}
+
+/// `custom_id` payload of a control panel button.
+#[derive(Debug, Serialize, Deserialize)]
+struct PanelButton {
+    #[serde(rename = "a")]
+    action: PanelAction,
+    /// Only whoever opened the panel can use it.
+    #[serde(rename = "o")]
+    owner: UserId,
+    #[serde(rename = "g")]
+    guild: GuildId,
+}
+
+async fn send_control_panel(
+    ctx: &Context,
+    channel: ChannelId,
+    owner: UserId,
+    guild_id: GuildId,
+    theme: &Theme,
+) -> MusicResult<()> {
+    use serenity::builder::{CreateActionRow, CreateButton};
+    use serenity::all::ButtonStyle;
 
-    // Build buttons with owner and guild embedded in custom id
-    let owner_id = owner.to_string();
-    let guild_id_s = guild_id.to_string();
+    let (embed, _) = panel_embed(ctx, guild_id, theme).await;
 
-    let pause_id = format!("music:pause:{}:{}", owner_id, guild_id_s);
-    let resume_id = format!("music:resume:{}:{}", owner_id, guild_id_s);
-    let stop_id = format!("music:stop:{}:{}", owner_id, guild_id_s);
-    let vol_down_id = format!("music:vol_down:{}:{}", owner_id, guild_id_s);
-    let vol_up_id = format!("music:vol_up:{}:{}", owner_id, guild_id_s);
+    let button = |action: PanelAction, style: ButtonStyle, label: &str| {
+        let id = crate::components::custom_id("music", &PanelButton { action, owner, guild: guild_id }, Some(PANEL_TTL));
+        CreateButton::new(id).style(style).label(label)
+    };
 
     let row1 = CreateActionRow::Buttons(vec![
-        CreateButton::new(pause_id).style(ButtonStyle::Primary).label("Pause"),
-        CreateButton::new(resume_id).style(ButtonStyle::Success).label("Resume"),
-        CreateButton::new(stop_id).style(ButtonStyle::Danger).label("Stop"),
+        button(PanelAction::Pause, ButtonStyle::Primary, "Pause"),
+        button(PanelAction::Resume, ButtonStyle::Success, "Resume"),
+        button(PanelAction::Stop, ButtonStyle::Danger, "Stop"),
     ]);
 
     let row2 = CreateActionRow::Buttons(vec![
-        CreateButton::new(vol_down_id).style(ButtonStyle::Secondary).label("Vol -"),
-        CreateButton::new(vol_up_id).style(ButtonStyle::Secondary).label("Vol +"),
+        button(PanelAction::VolDown, ButtonStyle::Secondary, "Vol -"),
+        button(PanelAction::VolUp, ButtonStyle::Secondary, "Vol +"),
+    ]);
+
+    let row3 = CreateActionRow::Buttons(vec![
+        button(PanelAction::Favorite, ButtonStyle::Secondary, "❤️ Favorite"),
+        button(PanelAction::Queue, ButtonStyle::Secondary, "Queue"),
+        button(PanelAction::History, ButtonStyle::Secondary, "History"),
     ]);
 
     let mut message = CreateMessage::new().embed(embed);
-    message = message.components(vec![row1, row2]);
+    message = message.components(vec![row1, row2, row3]);
 
     // Send the control panel message and capture it so we can update it live
     let sent = channel.send_message(&ctx.http, message).await?;
@@ -1136,103 +2106,118 @@ async fn send_control_panel(
     // Spawn a background task to periodically update the remaining time and state
     let ctx_clone = ctx.clone();
     let mut message_clone = sent.clone();
-    let guild_copy = guild_id;
-    let col = color;
+    let theme = theme.clone();
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-
-            // Fetch handle from TypeMap
-            let maybe_store = ctx_clone.data.read().await.get::<crate::TrackStore>().cloned();
-            if maybe_store.is_none() {
-                let ce = CreateEmbed::new().title("Music Controls").description("No active track store").color(col);
-                let edit_msg = serenity::builder::EditMessage::new().embed(ce);
-                let _ = message_clone.edit(&ctx_clone.http, edit_msg).await;
+            let (embed, active) = panel_embed(&ctx_clone, guild_id, &theme).await;
+            let edit_msg = serenity::builder::EditMessage::new().embed(embed);
+            let _ = message_clone.edit(&ctx_clone.http, edit_msg).await;
+            // Stop updating when the track stops; the buttons have nothing left to control
+            if !active {
+                crate::components::disable(&ctx_clone, &mut message_clone).await;
                 break;
             }
+        }
+    });
 
-            let store = maybe_store.unwrap();
-            let map = store.lock().await;
-            if let Some(handle) = map.get(&guild_copy) {
-                match handle.get_info().await {
-                    Ok(info) => {
-                        // Try to fetch stored total duration for this guild, if present
-                        let duration_str = {
-                            let data_read = ctx_clone.data.read().await;
-                            data_read.get::<crate::TrackMetaStore>().cloned()
-                        };
-
-                        let remaining = if let Some(meta_store) = duration_str {
-                            let meta_map = meta_store.lock().await;
-                            if let Some(meta) = meta_map.get(&guild_copy) {
-                                if let Some(total) = meta.duration {
-                                    if total > info.position {
-                                        let rem = total - info.position;
-                                        let secs = rem.as_secs();
-                                        let mins = secs / 60;
-                                        let secs = secs % 60;
-                                        format!("{mins}:{:02}", secs)
-                                    } else {
-                                        "0:00".into()
-                                    }
-                                } else {
-                                    "Unknown".into()
-                                }
-                            } else {
-                                "Unknown".into()
-                            }
-                        } else {
-                            "Unknown".into()
-                        };
-
-                        let new_desc = format!("Status: {:?}\nVolume: {:.2}\nRemaining: {}", info.playing, info.volume, remaining);
-
-                        // Look up meta for title/artist/thumbnail
-                        let mut title_str = "Music Controls".to_string();
-                        let mut thumbnail: Option<String> = None;
-                        if let Some(ms2) = ctx_clone.data.read().await.get::<crate::TrackMetaStore>().cloned() {
-                            let mm2 = ms2.lock().await;
-                            if let Some(meta) = mm2.get(&guild_copy) {
-                                match (&meta.title, &meta.artist) {
-                                    (Some(t), Some(a)) => title_str = format!("{} — {}", t, a),
-                                    (Some(t), None) => title_str = t.clone(),
-                                    (None, Some(a)) => title_str = a.clone(),
-                                    _ => {}
-                                }
-                                thumbnail = meta.thumbnail.clone();
-                            }
-                        }
-
-                        let mut ce = CreateEmbed::new().title(title_str).description(new_desc).color(col);
-                        if let Some(turl) = thumbnail {
-                            ce = ce.thumbnail(turl);
-                        }
+    Ok(())
+}
 
-                        let edit_msg = serenity::builder::EditMessage::new().embed(ce);
-                        let _ = message_clone.edit(&ctx_clone.http, edit_msg).await;
+/// `music` component handler: the control panel buttons. Only the panel's owner controls
+/// playback; anyone can use Favorite, Queue and History.
+pub fn handle_component<'a>(
+    ctx: &'a Context,
+    mc: &'a ComponentInteraction,
+    payload: &'a str,
+) -> serenity::futures::future::BoxFuture<'a, ()> {
+    use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage};
+
+    Box::pin(async move {
+        let respond = |message: String| {
+            CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(message).ephemeral(true))
+        };
+        let Some(button) = crate::components::payload::<PanelButton>(payload) else {
+            let _ = mc.create_response(&ctx.http, respond("This control panel is outdated; run `music control` again.".to_string())).await;
+            return;
+        };
+        if matches!(button.action, PanelAction::Favorite | PanelAction::Queue | PanelAction::History) {
+            // Answered privately so the channel only ever has the one panel
+            if let Err(e) = crate::components::defer_ephemeral(ctx, mc).await {
+                tracing::warn!("Music control {:?} failed to defer: {e:?}", button.action);
+                return;
+            }
+            let reply = panel_view(ctx, mc.user.id, button.guild, button.action).await;
+            crate::components::edit_deferred(ctx, mc, reply).await;
+            return;
+        }
+        if mc.user.id != button.owner {
+            let _ = mc.create_response(&ctx.http, respond("You are not the owner of this control panel.".to_string())).await;
+            return;
+        }
 
-                        // Stop updating when track stops
-                        if matches!(info.playing, songbird::tracks::PlayMode::Stop) {
-                            break;
-                        }
-                    }
-                    Err(_) => {
-                        let ce = CreateEmbed::new().title("Music Controls").description("Status: Unknown").color(col);
-                        let edit_msg = serenity::builder::EditMessage::new().embed(ce);
-                        let _ = message_clone.edit(&ctx_clone.http, edit_msg).await;
-                        break;
-                    }
-                }
-            } else {
-                let ce = CreateEmbed::new().title("Music Controls").description("No active track").color(col);
-                let edit_msg = serenity::builder::EditMessage::new().embed(ce);
-                let _ = message_clone.edit(&ctx_clone.http, edit_msg).await;
-                break;
+        let gid = button.guild;
+        let acted = match button.action {
+            PanelAction::Pause => crate::player::set_paused(ctx, gid, true).await,
+            PanelAction::Resume => crate::player::set_paused(ctx, gid, false).await,
+            PanelAction::Stop => crate::player::stop(ctx, gid).await,
+            PanelAction::VolUp => crate::player::change_volume(ctx, gid, 0.1).await.map(|v| v.is_some()),
+            PanelAction::VolDown => crate::player::change_volume(ctx, gid, -0.1).await.map(|v| v.is_some()),
+            PanelAction::Favorite | PanelAction::Queue | PanelAction::History => return,
+        };
+        match acted {
+            Ok(true) => {
+                // Show the new state straight away rather than on the next 5 s refresh
+                let theme = crate::embeds::theme(ctx, Some(gid)).await;
+                let (embed, _) = panel_embed(ctx, gid, &theme).await;
+                let update = CreateInteractionResponseMessage::new().embed(embed);
+                let _ = mc.create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(update)).await;
+            }
+            Ok(false) => {
+                let _ = mc.create_response(&ctx.http, respond("No active track to control.".to_string())).await;
+            }
+            Err(e) => {
+                tracing::warn!("Music control {:?} failed: {e:?}", button.action);
+                let _ = mc.create_response(&ctx.http, respond(format!("That didn't work: {e}"))).await;
             }
         }
-    });
+    })
+}
 
-    Ok(())
+/// The private answer to a Favorite, Queue or History press by `user`.
+async fn panel_view(
+    ctx: &Context,
+    user: UserId,
+    guild_id: GuildId,
+    action: PanelAction,
+) -> serenity::builder::EditInteractionResponse {
+    use crate::favorites::Added;
+    use serenity::builder::EditInteractionResponse;
+
+    let theme = crate::embeds::theme(ctx, Some(guild_id)).await;
+    match action {
+        PanelAction::Queue => {
+            let tracks = crate::queue::list(ctx, guild_id).await;
+            EditInteractionResponse::new().embed(crate::queue::page_embed(&theme, &tracks, 0))
+        }
+        PanelAction::History => {
+            let played = crate::queue::history(ctx, guild_id).await;
+            EditInteractionResponse::new().embed(crate::queue::history_embed(&theme, &played))
+        }
+        _ => {
+            let text = match crate::favorites::add(ctx, user, guild_id).await {
+                Ok(Added::Saved(title)) => format!("Added {} to your favorites; `music favorites` lists them.", title),
+                Ok(Added::AlreadySaved(title)) => format!("{} is already in your favorites.", title),
+                Ok(Added::Full) => "Your favorites are full; remove some with `music favorites remove`.".to_string(),
+                Ok(Added::NothingPlaying) => "No active track to favorite.".to_string(),
+                Err(e) => {
+                    tracing::warn!("Saving a favorite failed: {e:?}");
+                    format!("That didn't work: {e}")
+                }
+            };
+            EditInteractionResponse::new().content(text)
+        }
+    }
 }
 
 fn voice_channel_for_user_id(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
@@ -1248,8 +2233,8 @@ fn voice_channel_for_user(ctx: &Context, msg: &Message) -> Option<ChannelId> {
     voice_channel_for_user_id(ctx, guild_id, msg.author.id)
 }
 
-fn prepend_path(bin: &str) -> MusicResult<()> {
-    let bin_path = PathBuf::from(bin);
+fn prepend_path(bin: &std::path::Path) -> MusicResult<()> {
+    let bin_path = bin.to_path_buf();
     let mut paths: Vec<PathBuf> = env::var_os("PATH")
         .map(|p| env::split_paths(&p).collect())
         .unwrap_or_default();
@@ -1264,65 +2249,9 @@ fn prepend_path(bin: &str) -> MusicResult<()> {
     Ok(())
 }
 
-async fn spotify_first_then_query(user_query: &str) -> MusicResult<Option<String>> {
-    let client_id = match env::var("SPOTIFY_CLIENT_ID") {
-        Ok(v) if !v.is_empty() => v,
-        _ => return Ok(None),
-    };
-    let client_secret = match env::var("SPOTIFY_CLIENT_SECRET") {
-        Ok(v) if !v.is_empty() => v,
-        _ => return Ok(None),
-    };
-
-    let token = fetch_spotify_token(&client_id, &client_secret).await?;
-    let track = search_spotify_track(&token.access_token, user_query).await?;
-
-    Ok(track.map(|(name, artist)| format!("{} {}", name, artist)))
-}
-
-// Convenience wrapper to fetch a token using env vars (returns SpotifyToken or Err)
-async fn fetch_spotify_token_from_env() -> MusicResult<SpotifyToken> {
-    let client_id = env::var("SPOTIFY_CLIENT_ID").map_err(|_| "SPOTIFY_CLIENT_ID not set")?;
-    let client_secret = env::var("SPOTIFY_CLIENT_SECRET").map_err(|_| "SPOTIFY_CLIENT_SECRET not set")?;
-    fetch_spotify_token(&client_id, &client_secret).await
-}
-
-// Fetch a Spotify track by its id using the Web API, returning (title, artist, duration_opt, thumbnail_opt)
-async fn fetch_spotify_track_by_id(token: &str, id: &str) -> MusicResult<Option<(String, String, Option<std::time::Duration>, Option<String>)>> {
-    let url = format!("https://api.spotify.com/v1/tracks/{}", id);
-    let client = Client::builder().build()?;
-    let res = client.get(&url).bearer_auth(token).send().await?.error_for_status()?;
-    let v: serde_json::Value = res.json().await?;
-
-    let name = v.get("name").and_then(|s| s.as_str()).map(|s| s.to_string());
-    let artist = v.get("artists").and_then(|a| a.as_array()).and_then(|arr| arr.get(0)).and_then(|a0| a0.get("name")).and_then(|n| n.as_str()).map(|s| s.to_string());
-    let duration = v.get("duration_ms").and_then(|d| d.as_u64()).map(|ms| std::time::Duration::from_millis(ms));
-    let thumbnail = v.get("album").and_then(|al| al.get("images")).and_then(|imgs| imgs.as_array()).and_then(|arr| arr.get(0)).and_then(|i0| i0.get("url")).and_then(|u| u.as_str()).map(|s| s.to_string());
-
-    if let (Some(n), Some(a)) = (name, artist) {
-        Ok(Some((n, a, duration, thumbnail)))
-    } else {
-        Ok(None)
-    }
-}
-
-// Parse track id from a spotify URL or URI, returning the 'id' part
-fn parse_spotify_track_id(s: &str) -> Option<String> {
-    // spotify:track:ID
-    if let Some(pos) = s.find("spotify:track:") {
-        return s[pos + "spotify:track:".len()..].split(&['?', '&'][..]).next().map(|x| x.to_string());
-    }
-
-    // https://open.spotify.com/track/ID
-    if let Some(idx) = s.find("/track/") {
-        return s[idx + "/track/".len()..].split(&['?', '&', '/'][..]).next().map(|x| x.to_string());
-    }
-
-    None
-}
-
 // Construct a spotify stream command by checking env and falling back to `.bin/librespot-wrapper` if present.
-fn get_spotify_stream_cmd(uri: &str) -> Option<String> {
+#[cfg(feature = "spotify")]
+pub fn spotify_stream_cmd(uri: &str) -> Option<String> {
     // Prefer explicit env var
     if let Ok(t) = std::env::var("SPOTIFY_STREAM_CMD") {
         // Allow user to include quotes in their template; but if they didn't, we'll still quote for safety
@@ -1330,9 +2259,9 @@ fn get_spotify_stream_cmd(uri: &str) -> Option<String> {
         return Some(quoted);
     }
 
-    // Fallback: look for `.bin/librespot-wrapper` in current directory
-    if let Ok(cwd) = std::env::current_dir() {
-        let candidate = cwd.join(".bin").join("librespot-wrapper");
+    // Fallback: look for `.bin/librespot-wrapper` in the data directory
+    {
+        let candidate = crate::storage::data_path(HELPER_BIN_DIR).join(HELPER_BIN);
         if candidate.is_file() {
             // Check executable bit on unix-like systems
             #[cfg(unix)]
@@ -1347,13 +2276,17 @@ fn get_spotify_stream_cmd(uri: &str) -> Option<String> {
                 }
             }
 
-            // If the input was an open.spotify.com link, prefer the spotify:track:ID form
-            if let Some(id) = parse_spotify_track_id(uri) {
-                let s_uri = format!("spotify:track:{}", id);
-                return Some(format!("{} --uri {} --stdout", candidate.to_string_lossy(), shell_quote(&s_uri)));
+            // If the input was an open.spotify.com link, prefer the spotify:track:ID / spotify:episode:ID form
+            let s_uri = match crate::spotify::parse_link(uri) {
+                Some(crate::spotify::SpotifyLink::Track(id)) => Some(format!("spotify:track:{}", id)),
+                Some(crate::spotify::SpotifyLink::Episode(id)) => Some(format!("spotify:episode:{}", id)),
+                _ => None,
+            };
+            if let Some(s_uri) = s_uri {
+                return Some(format!("{} --uri {} --stdout --json", candidate.to_string_lossy(), shell_quote(&s_uri)));
             }
 
-            return Some(format!("{} --uri {} --stdout", candidate.to_string_lossy(), shell_quote(uri)));
+            return Some(format!("{} --uri {} --stdout --json", candidate.to_string_lossy(), shell_quote(uri)));
         }
     }
 
@@ -1361,6 +2294,7 @@ fn get_spotify_stream_cmd(uri: &str) -> Option<String> {
 }
 
 // Simple shell-quoting helper for safe substitution
+#[cfg(feature = "spotify")]
 fn shell_quote(s: &str) -> String {
     if s.contains('"') {
         // fallback to single quotes, escaping if necessary
@@ -1371,42 +2305,3 @@ fn shell_quote(s: &str) -> String {
     }
 }
 
-async fn fetch_spotify_token(client_id: &str, client_secret: &str) -> MusicResult<SpotifyToken> {
-    let auth = format!("{}:{}", client_id, client_secret);
-    let auth_b64 = B64_ENGINE.encode(auth);
-
-    let client = Client::builder().build()?;
-    let res = client
-        .post("https://accounts.spotify.com/api/token")
-        .header("Authorization", format!("Basic {}", auth_b64))
-        .form(&[("grant_type", "client_credentials")])
-        .send()
-        .await?
-        .error_for_status()?;
-
-    let token: SpotifyToken = res.json().await?;
-    Ok(token)
-}
-
-async fn search_spotify_track(token: &str, query: &str) -> MusicResult<Option<(String, String)>> {
-    let client = Client::builder().build()?;
-
-    let res = client
-        .get("https://api.spotify.com/v1/search")
-        .query(&[("q", query), ("type", "track"), ("limit", "1")])
-        .bearer_auth(token)
-        .send()
-        .await?
-        .error_for_status()?;
-
-    let data: SpotifySearch = res.json().await?;
-    let track = data.tracks.items.into_iter().next();
-    Ok(track.map(|t| {
-        let artist = t
-            .artists
-            .get(0)
-            .map(|a| a.name.clone())
-            .unwrap_or_else(|| "Unknown".to_string());
-        (t.name, artist)
-    }))
-}
@@ -0,0 +1,48 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::Guild;
+use serenity::builder::CreateMessage;
+use serenity::prelude::*;
+
+use crate::{embeds, storage};
+
+/// Record that `guild` was welcomed; false if it already had been.
+async fn first_welcome(ctx: &Context, guild: &Guild) -> bool {
+    let Ok(db) = storage::db(ctx).await else { return false };
+    let gid = guild.id.get() as i64;
+    let at = serenity::Timestamp::now().unix_timestamp();
+    db.call(move |conn| {
+        conn.execute("INSERT OR IGNORE INTO guild_onboarding (guild_id, welcomed_at) VALUES (?1, ?2)", rusqlite::params![gid, at])
+    })
+    .await
+    .is_ok_and(|inserted| inserted == 1)
+}
+
+/// `GuildCreate` hook: introduce the bot in the system channel of a server it just joined.
+/// `is_new` is false for servers the bot was already in when it connected, and the database
+/// keeps a rejoin after a restart (or a second invite) from posting again.
+pub async fn on_guild_create(ctx: &Context, guild: &Guild, is_new: Option<bool>) {
+    if is_new != Some(true) {
+        return;
+    }
+    let Some(channel) = guild.system_channel_id else { return };
+    if !first_welcome(ctx, guild).await {
+        return;
+    }
+    let theme = embeds::theme(ctx, Some(guild.id)).await;
+    let prefix = crate::settings::get(ctx, guild.id, crate::settings::SettingKey::Prefix).await.unwrap_or_else(|| crate::PREFIX.to_string());
+    let embed = theme
+        .embed()
+        .title(format!("Thanks for adding me to {}!", guild.name))
+        .description(format!(
+            "A server manager can run `/setup` to pick the announcement channel, DJ role, default volume and mod alerts in a few clicks. \
+             Everything can be changed later with `/settings`.\n\nCommands work as slash commands or with the `{}` prefix.",
+            prefix
+        ))
+        .field("Music", "`/music join`, `/music play <song>`, `/music queue`, `/music control`", false)
+        .field("Moderation", "`/timeout`, `/kick`, `/ban`, `/warn`, `/purge`, `/automod`", false)
+        .field("Utility", "`/remind`, `/tag`, `/welcome`, `/roles`, `/preferences`", false)
+        .field("Help", "`/help` lists every command; `/help <command>` explains one.", false);
+    if let Err(e) = channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await {
+        tracing::warn!("Couldn't post the onboarding message in guild {}: {e:?}", guild.id);
+    }
+}
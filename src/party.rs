@@ -0,0 +1,315 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId};
+use serenity::builder::CreateMessage;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::events::{self, PlayerEvent};
+use crate::{embeds, player, Ctx, Error};
+
+/// How often guests' positions are compared with the host's.
+const SYNC_EVERY: Duration = Duration::from_secs(5);
+/// A guest further than this from the host's position is seeked back in line.
+const MAX_DRIFT: Duration = Duration::from_secs(3);
+
+/// A guild playing along with another guild's player.
+#[derive(Debug, Clone)]
+struct Guest {
+    host: GuildId,
+    /// Where "now playing" and party notices are posted in the guest guild.
+    channel: ChannelId,
+    /// The host query the guest last started, so drift is only corrected on the same track.
+    playing: Option<String>,
+    /// Cleared when a seek fails (piped streams), so it isn't retried every tick.
+    seekable: bool,
+}
+
+/// Linked guilds. Kept in memory: parties end with a restart.
+#[derive(Default)]
+pub struct Parties {
+    guests: HashMap<GuildId, Guest>,
+}
+
+pub struct PartyStore;
+impl TypeMapKey for PartyStore {
+    type Value = Arc<Mutex<Parties>>;
+}
+
+/// What the coordinator is told besides player events.
+#[derive(Debug)]
+enum Op {
+    /// A host started resolving `query`.
+    Started(GuildId, String),
+    /// Bring a newly linked guest up to what its host is playing.
+    CatchUp(GuildId),
+}
+
+struct OpSender;
+impl TypeMapKey for OpSender {
+    type Value = mpsc::UnboundedSender<Op>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<Parties>>> {
+    ctx.data.read().await.get::<PartyStore>().cloned()
+}
+
+async fn send(ctx: &Context, op: Op) {
+    if let Some(tx) = ctx.data.read().await.get::<OpSender>() {
+        let _ = tx.send(op);
+    }
+}
+
+/// The host `guild_id` is following, if it's a guest. Its own queue and `music play` wait
+/// while it is.
+pub async fn following(ctx: &Context, guild_id: GuildId) -> Option<GuildId> {
+    let store = store(ctx).await?;
+    store.lock().await.guests.get(&guild_id).map(|g| g.host)
+}
+
+/// Called whenever `guild_id` starts resolving `query`; hosts pass it on to their guests.
+pub async fn track_requested(ctx: &Context, guild_id: GuildId, query: &str) {
+    let Some(store) = store(ctx).await else { return };
+    if store.lock().await.guests.values().any(|g| g.host == guild_id) {
+        send(ctx, Op::Started(guild_id, query.trim().to_string())).await;
+    }
+}
+
+/// Start the host's current query on `guest`, replacing whatever it plays.
+async fn play_on(ctx: &Context, guest_id: GuildId, query: String) {
+    let Some(store) = store(ctx).await else { return };
+    let channel = {
+        let mut parties = store.lock().await;
+        let Some(guest) = parties.guests.get_mut(&guest_id) else { return };
+        guest.playing = Some(query.clone());
+        guest.seekable = true;
+        guest.channel
+    };
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        let _ = player::skip(&ctx, guest_id).await;
+        if let Err(e) = crate::music::play_now(&ctx, channel, guest_id, &query, None).await {
+            tracing::warn!("Listening party: `{}` failed in guild {}: {e:?}", query, guest_id);
+            crate::music::report_failure(&ctx, channel, guest_id, &e.to_string()).await;
+        }
+    });
+}
+
+/// The guilds following `host`.
+async fn guests_of(ctx: &Context, host: GuildId) -> Vec<GuildId> {
+    let Some(store) = store(ctx).await else { return Vec::new() };
+    store.lock().await.guests.iter().filter(|(_, g)| g.host == host).map(|(id, _)| *id).collect()
+}
+
+/// Repeat a host's pause or resume on its guests. Skips and stops reach them through
+/// `Op::Started` and `sync_positions`, which don't race the track ending.
+async fn mirror(ctx: &Context, host: GuildId, event: PlayerEvent) {
+    let paused = match event {
+        PlayerEvent::Paused { .. } => true,
+        PlayerEvent::Resumed { .. } => false,
+        _ => return,
+    };
+    for guest in guests_of(ctx, host).await {
+        let _ = player::set_paused(ctx, guest, paused).await;
+    }
+}
+
+/// Stop guests whose host stopped, seek those that drifted from their host's position, and
+/// match the host's pause state.
+async fn sync_positions(ctx: &Context) {
+    let Some(store) = store(ctx).await else { return };
+    let guests: Vec<(GuildId, Guest)> = {
+        let parties = store.lock().await;
+        parties.guests.iter().filter(|(_, g)| g.playing.is_some()).map(|(id, g)| (*id, g.clone())).collect()
+    };
+    for (guest_id, guest) in guests {
+        let same_track = guest.playing == crate::music::current_query(ctx, guest.host).await;
+        let Some(status) = player::status(ctx, guest_id).await else { continue };
+        let Some(host) = player::status(ctx, guest.host).await else {
+            if !crate::music::resolving(ctx, guest.host).await {
+                let _ = player::skip(ctx, guest_id).await;
+                if let Some(g) = store.lock().await.guests.get_mut(&guest_id) {
+                    g.playing = None;
+                }
+            }
+            continue;
+        };
+        if !same_track || !guest.seekable {
+            continue;
+        }
+        if host.paused != status.paused {
+            let _ = player::set_paused(ctx, guest_id, host.paused).await;
+        }
+        if host.position.abs_diff(status.position) <= MAX_DRIFT {
+            continue;
+        }
+        if let Err(e) = player::seek(ctx, guest_id, host.position).await {
+            tracing::warn!("Listening party: can't keep guild {} in sync, its stream doesn't seek: {e:?}", guest_id);
+            if let Some(g) = store.lock().await.guests.get_mut(&guest_id) {
+                g.seekable = false;
+            }
+        }
+    }
+}
+
+/// Spawn the coordinator: it plays each track a host starts on the host's guests, repeats
+/// pauses, stops and skips, and keeps the guests' positions within `MAX_DRIFT` of the host's.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        let (tx, mut ops) = mpsc::unbounded_channel();
+        ctx.data.write().await.insert::<OpSender>(tx);
+        let Some(mut bus) = events::subscribe(&ctx).await else { return };
+        let mut tick = tokio::time::interval(SYNC_EVERY);
+        loop {
+            tokio::select! {
+                Some(op) = ops.recv() => match op {
+                    Op::Started(host, query) => {
+                        for guest in guests_of(&ctx, host).await {
+                            play_on(&ctx, guest, query.clone()).await;
+                        }
+                    }
+                    Op::CatchUp(guest) => {
+                        let Some(host) = following(&ctx, guest).await else { continue };
+                        if let Some(query) = crate::music::current_query(&ctx, host).await
+                            && player::status(&ctx, host).await.is_some()
+                        {
+                            play_on(&ctx, guest, query).await;
+                        }
+                    }
+                },
+                event = bus.recv() => match event {
+                    Ok((gid, event)) => mirror(&ctx, gid, event).await,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+                },
+                _ = tick.tick() => sync_positions(&ctx).await,
+            }
+        }
+    });
+}
+
+async fn announce(ctx: &Context, guild_id: GuildId, channel: ChannelId, text: String) {
+    let embed = embeds::theme(ctx, Some(guild_id)).await.embed().title("Listening party").description(text);
+    let _ = channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await;
+}
+
+fn guild_name(ctx: &Context, gid: GuildId) -> String {
+    ctx.cache.guild(gid).map(|g| g.name.clone()).unwrap_or_else(|| gid.to_string())
+}
+
+/// Link servers' players for a listening party
+#[poise::command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    rename = "party",
+    subcommands("party_link", "party_unlink", "party_list")
+)]
+pub async fn admin_party(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.say("Subcommands: `link`, `unlink`, `list`").await?;
+    Ok(())
+}
+
+/// Make a server play along with another server's player
+#[poise::command(prefix_command, slash_command, owners_only, rename = "link")]
+pub async fn party_link(
+    ctx: Ctx<'_>,
+    #[description = "Server ID whose player leads"] host: String,
+    #[description = "Server ID that plays along"] guest: String,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let (host, guest) = (crate::access::parse_guild(&host)?, crate::access::parse_guild(&guest)?);
+    if host == guest {
+        ctx.say("A server can't follow itself.").await?;
+        return Ok(());
+    }
+    if sctx.cache.guild(host).is_none() || sctx.cache.guild(guest).is_none() {
+        ctx.say("The bot has to be in both servers.").await?;
+        return Ok(());
+    }
+    let channel = match crate::music::command_channel(sctx, guest).await {
+        Some(channel) => Some(channel),
+        None => sctx.cache.guild(guest).and_then(|g| g.system_channel_id),
+    };
+    let Some(channel) = channel else {
+        ctx.say("The guest server has no `music.command_channel` or system channel to post to.").await?;
+        return Ok(());
+    };
+    let Some(store) = store(sctx).await else { return Ok(()) };
+    {
+        let mut parties = store.lock().await;
+        let problem = if parties.guests.contains_key(&host) {
+            Some("The host is itself following another server; link to that one instead.")
+        } else if parties.guests.contains_key(&guest) {
+            Some("The guest is already in a party; unlink it first.")
+        } else if parties.guests.values().any(|g| g.host == guest) {
+            Some("The guest is hosting a party of its own; unlink its guests first.")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            drop(parties);
+            ctx.say(problem).await?;
+            return Ok(());
+        }
+        parties.guests.insert(guest, Guest { host, channel, playing: None, seekable: true });
+    }
+    send(sctx, Op::CatchUp(guest)).await;
+    let (host_name, guest_name) = (guild_name(sctx, host), guild_name(sctx, guest));
+    announce(sctx, guest, channel, format!("This server now plays along with **{}**: its tracks play here in sync. This server's own queue is kept for when the party ends, and `music play` is off until then.", host_name)).await;
+    ctx.say(format!("**{}** now plays along with **{}**. The bot has to be in a voice channel in both.", guest_name, host_name)).await?;
+    Ok(())
+}
+
+/// End a server's listening party (a guest leaves; a host ends it for everyone)
+#[poise::command(prefix_command, slash_command, owners_only, rename = "unlink")]
+pub async fn party_unlink(ctx: Ctx<'_>, #[description = "Server ID"] guild: String) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let gid = crate::access::parse_guild(&guild)?;
+    let Some(store) = store(sctx).await else { return Ok(()) };
+    let removed: Vec<(GuildId, Guest)> = {
+        let mut parties = store.lock().await;
+        let ids: Vec<GuildId> = parties
+            .guests
+            .iter()
+            .filter(|(id, g)| **id == gid || g.host == gid)
+            .map(|(id, _)| *id)
+            .collect();
+        let removed: Vec<_> = ids.into_iter().filter_map(|id| parties.guests.remove(&id).map(|g| (id, g))).collect();
+        removed
+    };
+    if removed.is_empty() {
+        ctx.say("That server isn't in a listening party.").await?;
+        return Ok(());
+    }
+    for (guest, g) in &removed {
+        announce(sctx, *guest, g.channel, "The listening party is over; this server's own queue is back.".to_string()).await;
+        // Nothing left over from the party to finish first: pick the queue up now
+        if player::status(sctx, *guest).await.is_none() {
+            let (sctx, guest) = (sctx.clone(), *guest);
+            tokio::spawn(async move { crate::queue::start_next(&sctx, guest).await });
+        }
+    }
+    ctx.say(format!("Unlinked {} server(s).", removed.len())).await?;
+    Ok(())
+}
+
+/// Show the linked servers
+#[poise::command(prefix_command, slash_command, owners_only, rename = "list")]
+pub async fn party_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let Some(store) = store(sctx).await else { return Ok(()) };
+    let mut lines: Vec<String> = store
+        .lock()
+        .await
+        .guests
+        .iter()
+        .map(|(guest, g)| format!("**{}** ({}) → follows **{}** ({})", guild_name(sctx, *guest), guest, guild_name(sctx, g.host), g.host))
+        .collect();
+    lines.sort();
+    let text = if lines.is_empty() { "No listening parties.".to_string() } else { lines.join("\n") };
+    ctx.say(text).await?;
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId, Permissions, RoleId};
+use serenity::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::{embeds, i18n, storage, Ctx, Data, Error};
+
+/// Commands that can't be restricted, so admins can't lock themselves out.
+const EXEMPT: [&str; 2] = ["permissions", "help"];
+
+/// Who may use one command in a guild. An empty set doesn't restrict that side.
+#[derive(Debug, Default, Clone)]
+pub struct Rule {
+    pub roles: HashSet<RoleId>,
+    pub channels: HashSet<ChannelId>,
+}
+
+type GuildRules = Arc<HashMap<String, Rule>>;
+
+/// Rules read from `command_permissions`, per guild, keyed by dotted command path
+/// (`music.play`). Dropped on change and reloaded on the next check.
+pub struct PermissionCache;
+impl TypeMapKey for PermissionCache {
+    type Value = Arc<Mutex<HashMap<GuildId, GuildRules>>>;
+}
+
+/// `music play` → `music.play`, the form rules are stored and typed in.
+fn path(qualified_name: &str) -> String {
+    qualified_name.split_whitespace().collect::<Vec<_>>().join(".")
+}
+
+async fn load(ctx: &Context, gid: GuildId) -> Result<GuildRules, Error> {
+    let cache = ctx.data.read().await.get::<PermissionCache>().cloned();
+    if let Some(cache) = &cache
+        && let Some(rules) = cache.lock().await.get(&gid).cloned()
+    {
+        return Ok(rules);
+    }
+
+    let db = storage::db(ctx).await?;
+    let rows: Vec<(String, String, i64)> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare("SELECT command, kind, target_id FROM command_permissions WHERE guild_id = ?1")?;
+            let rows = stmt.query_map([gid.get() as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect()
+        })
+        .await?;
+    let mut rules: HashMap<String, Rule> = HashMap::new();
+    for (command, kind, target) in rows {
+        let rule = rules.entry(command).or_default();
+        match kind.as_str() {
+            "role" => rule.roles.insert(RoleId::new(target as u64)),
+            _ => rule.channels.insert(ChannelId::new(target as u64)),
+        };
+    }
+    let rules = Arc::new(rules);
+    if let Some(cache) = cache {
+        cache.lock().await.insert(gid, rules.clone());
+    }
+    Ok(rules)
+}
+
+pub async fn invalidate(ctx: &Context, gid: GuildId) {
+    if let Some(cache) = ctx.data.read().await.get::<PermissionCache>().cloned() {
+        cache.lock().await.remove(&gid);
+    }
+}
+
+/// The rule for `command_path`: its own, else the nearest parent's (`music` covers `music.play`).
+fn rule_for<'a>(rules: &'a HashMap<String, Rule>, command_path: &str) -> Option<(&'a str, &'a Rule)> {
+    let mut candidate = command_path;
+    loop {
+        if let Some((key, rule)) = rules.get_key_value(candidate) {
+            return Some((key.as_str(), rule));
+        }
+        candidate = &candidate[..candidate.rfind('.')?];
+    }
+}
+
+/// The author's server-wide permissions; `None` in DMs.
+pub async fn author_permissions(ctx: Ctx<'_>) -> Option<Permissions> {
+    let member = ctx.author_member().await?;
+    if let Some(permissions) = member.permissions {
+        return Some(permissions);
+    }
+    let guild = ctx.guild()?;
+    Some(guild.member_permissions(&member))
+}
+
+/// Framework `command_check`: enforce the guild's rule for the invoked command. Members with
+/// Manage Server bypass rules. Rules only ever narrow: a command's own Discord permission
+/// requirement (e.g. Ban Members for `ban`) still applies on top.
+pub async fn check(ctx: Ctx<'_>) -> Result<bool, Error> {
+    let Some(gid) = ctx.guild_id() else { return Ok(true) };
+    let command_path = path(&ctx.command().qualified_name);
+    if EXEMPT.iter().any(|e| command_path.split('.').next() == Some(e)) {
+        return Ok(true);
+    }
+    if command_path.split('.').next() == Some("music") {
+        music_channel(ctx, gid).await?;
+    }
+    let rules = load(ctx.serenity_context(), gid).await?;
+    let Some((_, rule)) = rule_for(&rules, &command_path) else { return Ok(true) };
+    if author_permissions(ctx).await.is_some_and(|p| p.manage_guild()) {
+        return Ok(true);
+    }
+
+    let locale = i18n::locale(ctx).await;
+    if !rule.roles.is_empty() {
+        let has_role = match ctx.author_member().await {
+            Some(member) => member.roles.iter().any(|r| rule.roles.contains(r)),
+            None => false,
+        };
+        if !has_role {
+            let roles: Vec<String> = rule.roles.iter().map(|r| format!("<@&{}>", r)).collect();
+            return Err(i18n::tf(locale, "permissions.denied_role", &[("roles", &roles.join(", "))]).into());
+        }
+    }
+    if !rule.channels.is_empty() {
+        let channel = ctx.channel_id();
+        let parent = crate::thread_parent(ctx.serenity_context(), gid, channel);
+        if !rule.channels.contains(&channel) && !parent.is_some_and(|p| rule.channels.contains(&p)) {
+            let channels: Vec<String> = rule.channels.iter().map(|c| format!("<#{}>", c)).collect();
+            return Err(i18n::tf(locale, "permissions.denied_channel", &[("channels", &channels.join(", "))]).into());
+        }
+    }
+    Ok(true)
+}
+
+/// With `music.command_channel` set, music commands only run there (or in its threads); anywhere
+/// else the error sends the member over, ephemerally for slash commands.
+async fn music_channel(ctx: Ctx<'_>, gid: GuildId) -> Result<(), Error> {
+    let Some(bound) = crate::music::command_channel(ctx.serenity_context(), gid).await else { return Ok(()) };
+    let channel = ctx.channel_id();
+    if channel == bound || crate::thread_parent(ctx.serenity_context(), gid, channel) == Some(bound) {
+        return Ok(());
+    }
+    let locale = i18n::locale(ctx).await;
+    Err(i18n::tf(locale, "permissions.music_channel", &[("channel", &format!("<#{}>", bound))]).into())
+}
+
+/// Every command path, parents before their subcommands.
+fn all_paths(commands: &[poise::Command<Data, Error>], out: &mut Vec<String>) {
+    for command in commands {
+        out.push(path(&command.qualified_name));
+        all_paths(&command.subcommands, out);
+    }
+}
+
+async fn autocomplete_command(ctx: Ctx<'_>, partial: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    all_paths(&ctx.framework().options().commands, &mut paths);
+    let partial = partial.to_ascii_lowercase();
+    paths.retain(|p| p.contains(&partial) && !EXEMPT.contains(&p.split('.').next().unwrap_or_default()));
+    paths.sort();
+    paths.dedup();
+    paths.truncate(25);
+    paths
+}
+
+/// The Discord permissions `command_path` needs whatever the rules say: its own and its parents'
+/// `required_permissions` and `default_member_permissions`. These are the per-command defaults
+/// (Ban Members for `ban`, Manage Server for `settings`, none for everyday commands), the same
+/// checks the commands had before rules existed; a rule can only narrow them.
+fn default_permissions(commands: &[poise::Command<Data, Error>], command_path: &str) -> Permissions {
+    let mut needed = Permissions::empty();
+    let mut level = commands;
+    for name in command_path.split('.') {
+        let Some(command) = level.iter().find(|c| c.name == name) else { break };
+        needed |= command.required_permissions | command.default_member_permissions;
+        level = &command.subcommands;
+    }
+    needed
+}
+
+/// Normalise and validate a typed command path against the registered commands.
+fn resolve_command(ctx: Ctx<'_>, input: &str) -> Option<String> {
+    let wanted = path(&input.trim().trim_start_matches('/').replace('.', " ")).to_ascii_lowercase();
+    let mut paths = Vec::new();
+    all_paths(&ctx.framework().options().commands, &mut paths);
+    paths.into_iter().find(|p| *p == wanted)
+}
+
+/// Restrict commands to roles or channels
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands("permissions_allow", "permissions_revoke", "permissions_list"),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn permissions(ctx: Ctx<'_>) -> Result<(), Error> {
+    list(ctx, None).await
+}
+
+/// Let a role use a command, or allow it in a channel
+#[poise::command(prefix_command, slash_command, guild_only, rename = "allow")]
+pub async fn permissions_allow(
+    ctx: Ctx<'_>,
+    #[description = "Command, e.g. music.play"]
+    #[autocomplete = "autocomplete_command"]
+    command: String,
+    #[description = "Role that may use it"] role: Option<serenity::Role>,
+    #[description = "Channel it may be used in"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let locale = i18n::locale(ctx).await;
+    let Some(command) = resolve_command(ctx, &command).filter(|c| !EXEMPT.contains(&c.split('.').next().unwrap_or_default()))
+    else {
+        ctx.say(i18n::tf(locale, "permissions.unknown_command", &[("command", &command)])).await?;
+        return Ok(());
+    };
+    let mut targets: Vec<(&str, i64)> = Vec::new();
+    if let Some(role) = &role {
+        targets.push(("role", role.id.get() as i64));
+    }
+    if let Some(channel) = &channel {
+        targets.push(("channel", channel.id.get() as i64));
+    }
+    if targets.is_empty() {
+        ctx.say(i18n::t(locale, "permissions.need_target")).await?;
+        return Ok(());
+    }
+
+    let db = storage::db(ctx.serenity_context()).await?;
+    let rows: Vec<(String, i64)> = targets.iter().map(|(k, id)| (k.to_string(), *id)).collect();
+    let key = command.clone();
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        for (kind, id) in &rows {
+            tx.execute(
+                "INSERT OR IGNORE INTO command_permissions (guild_id, command, kind, target_id) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![guild_id.get() as i64, key, kind, id],
+            )?;
+        }
+        tx.commit()
+    })
+    .await?;
+    invalidate(ctx.serenity_context(), guild_id).await;
+
+    let rules = load(ctx.serenity_context(), guild_id).await?;
+    let summary = rules.get(&command).map(|r| describe(locale, r)).unwrap_or_default();
+    ctx.say(i18n::tf(locale, "permissions.allowed", &[("command", &command), ("rule", &summary)])).await?;
+    Ok(())
+}
+
+/// Take a role or channel off a command's rule, or drop the rule entirely
+#[poise::command(prefix_command, slash_command, guild_only, rename = "revoke")]
+pub async fn permissions_revoke(
+    ctx: Ctx<'_>,
+    #[description = "Command, e.g. music.play"]
+    #[autocomplete = "autocomplete_command"]
+    command: String,
+    #[description = "Role to remove (omit both to remove the whole rule)"] role: Option<serenity::Role>,
+    #[description = "Channel to remove"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let locale = i18n::locale(ctx).await;
+    let Some(command) = resolve_command(ctx, &command) else {
+        ctx.say(i18n::tf(locale, "permissions.unknown_command", &[("command", &command)])).await?;
+        return Ok(());
+    };
+    let role = role.map(|r| r.id.get() as i64);
+    let channel = channel.map(|c| c.id.get() as i64);
+
+    let db = storage::db(ctx.serenity_context()).await?;
+    let key = command.clone();
+    let removed = db
+        .call(move |conn| {
+            let gid = guild_id.get() as i64;
+            if role.is_none() && channel.is_none() {
+                return conn.execute(
+                    "DELETE FROM command_permissions WHERE guild_id = ?1 AND command = ?2",
+                    rusqlite::params![gid, key],
+                );
+            }
+            let mut removed = 0;
+            for (kind, id) in [("role", role), ("channel", channel)] {
+                if let Some(id) = id {
+                    removed += conn.execute(
+                        "DELETE FROM command_permissions WHERE guild_id = ?1 AND command = ?2 AND kind = ?3 AND target_id = ?4",
+                        rusqlite::params![gid, key, kind, id],
+                    )?;
+                }
+            }
+            Ok(removed)
+        })
+        .await?;
+    invalidate(ctx.serenity_context(), guild_id).await;
+
+    let key = if removed == 0 { "permissions.nothing_removed" } else { "permissions.revoked" };
+    ctx.say(i18n::tf(locale, key, &[("command", &command)])).await?;
+    Ok(())
+}
+
+/// Show the command restrictions in this server
+#[poise::command(prefix_command, slash_command, guild_only, rename = "list")]
+pub async fn permissions_list(
+    ctx: Ctx<'_>,
+    #[description = "Only this command (and what it inherits)"]
+    #[autocomplete = "autocomplete_command"]
+    command: Option<String>,
+) -> Result<(), Error> {
+    list(ctx, command).await
+}
+
+fn describe(locale: &str, rule: &Rule) -> String {
+    let anyone = i18n::t(locale, "permissions.anyone");
+    let anywhere = i18n::t(locale, "permissions.anywhere");
+    let roles: Vec<String> = rule.roles.iter().map(|r| format!("<@&{}>", r)).collect();
+    let channels: Vec<String> = rule.channels.iter().map(|c| format!("<#{}>", c)).collect();
+    i18n::tf(
+        locale,
+        "permissions.rule",
+        &[
+            ("roles", &if roles.is_empty() { anyone } else { roles.join(", ") }),
+            ("channels", &if channels.is_empty() { anywhere } else { channels.join(", ") }),
+        ],
+    )
+}
+
+async fn list(ctx: Ctx<'_>, command: Option<String>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let locale = i18n::locale(ctx).await;
+    let rules = load(ctx.serenity_context(), guild_id).await?;
+    let mut embed = embeds::builder(ctx.serenity_context(), Some(guild_id)).await.title(i18n::t(locale, "permissions.title"));
+
+    let mut lines: Vec<String> = match command.as_deref().map(|c| resolve_command(ctx, c)) {
+        Some(None) => {
+            let text = i18n::tf(locale, "permissions.unknown_command", &[("command", &command.unwrap_or_default())]);
+            ctx.say(text).await?;
+            return Ok(());
+        }
+        Some(Some(path)) => {
+            let needed = default_permissions(&ctx.framework().options().commands, &path);
+            let default = if needed.is_empty() {
+                i18n::tf(locale, "permissions.default_anyone", &[("command", &path)])
+            } else {
+                i18n::tf(locale, "permissions.default", &[("command", &path), ("permissions", &needed.get_permission_names().join(", "))])
+            };
+            let rule = rule_for(&rules, &path).map(|(key, rule)| {
+                let inherited = if key == path { String::new() } else { format!(" ← `{}`", key) };
+                format!("`{}`{}: {}", path, inherited, describe(locale, rule))
+            });
+            std::iter::once(default).chain(rule).collect()
+        }
+        None => rules.iter().map(|(path, rule)| format!("`{}`: {}", path, describe(locale, rule))).collect(),
+    };
+    lines.sort();
+    // Keep within the embed description limit
+    lines.truncate(40);
+    let body = if lines.is_empty() { i18n::t(locale, "permissions.none") } else { lines.join("\n") };
+    embed = embed.description(format!("{}\n\n*{}*", body, i18n::t(locale, "permissions.footnote")));
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<Data, Error>> {
+    vec![permissions()]
+}
@@ -0,0 +1,78 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::{i18n, Ctx, Error};
+
+/// Give up on reaching the voice server after this long.
+const VOICE_TIMEOUT: Duration = Duration::from_secs(3);
+
+fn ms(d: Duration) -> String {
+    format!("{} ms", d.as_millis())
+}
+
+/// How the voice latency was measured, which decides its label.
+enum VoiceLatency {
+    /// Lavalink's own voice ping.
+    Heartbeat(Duration),
+    /// Time to open a TCP connection to the voice endpoint.
+    Connect(Duration),
+}
+
+/// Latency to the guild's voice server, if the bot is in a call there. Lavalink reports its
+/// voice ping; with the built-in player songbird doesn't expose its heartbeat or UDP round
+/// trip, so this times a TCP connect to the voice endpoint instead and is labelled as such.
+async fn voice_latency(ctx: &Context, guild_id: GuildId) -> Option<VoiceLatency> {
+    if let Some(node) = crate::lavalink::node(ctx).await {
+        return node.player(guild_id).await?.voice_ping.map(VoiceLatency::Heartbeat);
+    }
+    let call = songbird::get(ctx).await?.get(guild_id)?;
+    let endpoint = call.lock().await.current_connection()?.endpoint.clone();
+    let address = if endpoint.contains(':') { endpoint } else { format!("{}:443", endpoint) };
+    let started = Instant::now();
+    match tokio::time::timeout(VOICE_TIMEOUT, tokio::net::TcpStream::connect(&address)).await {
+        Ok(Ok(_)) => Some(VoiceLatency::Connect(started.elapsed())),
+        _ => None,
+    }
+}
+
+/// Check the bot's latency to Discord
+#[poise::command(prefix_command, slash_command)]
+pub async fn ping(ctx: Ctx<'_>) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let locale = i18n::locale(ctx).await;
+    // The first reply is a REST call (or the interaction callback), so time it
+    let started = Instant::now();
+    let reply = ctx.say(i18n::t(locale, "ping.pong")).await?;
+    let rest = started.elapsed();
+
+    let gateway = match ctx.ping().await {
+        Duration::ZERO => i18n::t(locale, "ping.unknown"),
+        d => ms(d),
+    };
+    let (voice_label, voice) = match ctx.guild_id() {
+        Some(gid) if songbird::get(sctx).await.and_then(|m| m.get(gid)).is_some() => match voice_latency(sctx, gid).await {
+            Some(VoiceLatency::Heartbeat(d)) => ("ping.voice", ms(d)),
+            Some(VoiceLatency::Connect(d)) => ("ping.voice_connect", ms(d)),
+            None => ("ping.voice", i18n::t(locale, "ping.unknown")),
+        },
+        _ => ("ping.voice", i18n::t(locale, "ping.not_in_voice")),
+    };
+    let shard = format!("{} / {}", sctx.shard_id.0, sctx.cache.shard_count());
+
+    let theme = crate::embeds::theme(sctx, ctx.guild_id()).await;
+    let embed = theme
+        .embed()
+        .title(i18n::t(locale, "ping.pong"))
+        .field(i18n::t(locale, "ping.gateway"), gateway, true)
+        .field(i18n::t(locale, "ping.rest"), ms(rest), true)
+        .field(i18n::t(locale, voice_label), voice, true)
+        .field(i18n::t(locale, "ping.shard"), shard, true);
+    reply.edit(ctx, poise::CreateReply::default().content("").embed(embed)).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![ping()]
+}
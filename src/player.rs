@@ -0,0 +1,124 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use songbird::tracks::{PlayMode, TrackHandle};
+use std::time::Duration;
+
+use crate::events::{self, PlayerEvent};
+use crate::{lavalink, queue, Error};
+
+/// The current track's state, from whichever backend is playing. Absent when nothing is.
+#[derive(Debug, Clone, Copy)]
+pub struct Status {
+    pub paused: bool,
+    pub position: Duration,
+    /// 1.0 is unchanged.
+    pub volume: f32,
+}
+
+/// The built-in player's handle for the guild's current track.
+pub async fn handle(ctx: &Context, guild_id: GuildId) -> Option<TrackHandle> {
+    let tracks = ctx.data.read().await.get::<crate::TrackStore>().cloned()?;
+    tracks.lock().await.get(&guild_id).cloned()
+}
+
+pub async fn status(ctx: &Context, guild_id: GuildId) -> Option<Status> {
+    if let Some(node) = lavalink::node(ctx).await {
+        let player = node.player(guild_id).await?;
+        player.track.as_ref()?;
+        return Some(Status { paused: player.paused, position: player.position(), volume: f32::from(player.volume) / 100.0 });
+    }
+    let info = handle(ctx, guild_id).await?.get_info().await.ok()?;
+    if info.playing.is_done() {
+        return None;
+    }
+    Some(Status { paused: info.playing == PlayMode::Pause, position: info.position, volume: info.volume })
+}
+
+/// Pause or resume. Returns false if nothing is playing.
+pub async fn set_paused(ctx: &Context, guild_id: GuildId, paused: bool) -> Result<bool, Error> {
+    let Some(before) = status(ctx, guild_id).await else { return Ok(false) };
+    if let Some(node) = lavalink::node(ctx).await {
+        node.set_paused(guild_id, paused).await?;
+    } else if let Some(handle) = handle(ctx, guild_id).await {
+        if paused { handle.pause()? } else { handle.play()? }
+    }
+    let position_secs = before.position.as_secs();
+    let event = if paused { PlayerEvent::Paused { position_secs } } else { PlayerEvent::Resumed { position_secs } };
+    events::emit(ctx, guild_id, event).await;
+    Ok(true)
+}
+
+/// End the current track, or abort the one still resolving; the next queued one starts.
+/// Returns false if there was neither.
+pub async fn skip(ctx: &Context, guild_id: GuildId) -> Result<bool, Error> {
+    crate::ambient::stop(ctx, guild_id).await;
+    crate::live::stop(ctx, guild_id).await;
+    if crate::music::cancel_resolution(ctx, guild_id).await {
+        return Ok(true);
+    }
+    if status(ctx, guild_id).await.is_none() {
+        return Ok(false);
+    }
+    crate::positions::remember(ctx, guild_id).await;
+    if let Some(node) = lavalink::node(ctx).await {
+        node.stop(guild_id).await?;
+    } else if let Some(handle) = handle(ctx, guild_id).await {
+        handle.stop()?;
+        crate::music::kill_children(ctx, guild_id).await;
+    }
+    Ok(true)
+}
+
+/// Clear the queue and end the current track. Returns false if nothing was playing.
+pub async fn stop(ctx: &Context, guild_id: GuildId) -> Result<bool, Error> {
+    queue::clear(ctx, guild_id).await;
+    let stopped = skip(ctx, guild_id).await?;
+    if let Some(tracks) = ctx.data.read().await.get::<crate::TrackStore>().cloned() {
+        tracks.lock().await.remove(&guild_id);
+    }
+    Ok(stopped)
+}
+
+/// Jump to `position` in the current track. Returns false if nothing is playing. Streams that
+/// can't seek (piped ffmpeg output) fail with an error.
+pub async fn seek(ctx: &Context, guild_id: GuildId, position: Duration) -> Result<bool, Error> {
+    if status(ctx, guild_id).await.is_none() {
+        return Ok(false);
+    }
+    if let Some(node) = lavalink::node(ctx).await {
+        node.seek(guild_id, position).await?;
+    } else if let Some(handle) = handle(ctx, guild_id).await {
+        handle.seek_async(position).await?;
+    }
+    Ok(true)
+}
+
+/// Change the volume by `delta` (clamped to 0.0..=5.0). Returns the new volume, or `None`
+/// if nothing is playing.
+pub async fn change_volume(ctx: &Context, guild_id: GuildId, delta: f32) -> Result<Option<f32>, Error> {
+    let Some(current) = status(ctx, guild_id).await else { return Ok(None) };
+    let volume = (current.volume + delta).clamp(0.0, 5.0);
+    if let Some(node) = lavalink::node(ctx).await {
+        node.set_volume(guild_id, volume).await?;
+    } else if let Some(handle) = handle(ctx, guild_id).await {
+        handle.set_volume(volume)?;
+    }
+    Ok(Some(volume))
+}
+
+/// Guilds with a track loaded, playing or paused.
+pub async fn playing_guilds(ctx: &Context) -> Vec<GuildId> {
+    if let Some(node) = lavalink::node(ctx).await {
+        return node.playing_guilds().await;
+    }
+    let Some(tracks) = ctx.data.read().await.get::<crate::TrackStore>().cloned() else { return Vec::new() };
+    let guilds: Vec<GuildId> = tracks.lock().await.keys().copied().collect();
+    let mut playing = Vec::new();
+    for gid in guilds {
+        if status(ctx, gid).await.is_some() {
+            playing.push(gid);
+        }
+    }
+    playing
+}
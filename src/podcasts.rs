@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Feeds bigger than this are ignored.
+const MAX_FEED_BYTES: usize = 8 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct ItunesSearch {
+    results: Vec<ItunesShow>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ItunesShow {
+    collection_name: Option<String>,
+    feed_url: Option<String>,
+}
+
+/// Audio URL of the episode titled `episode` of the show `show`, from its public RSS feed.
+/// Spotify doesn't publish feed URLs, so the show is looked up in the iTunes podcast directory
+/// by name. `None` if the show or the episode can't be found.
+pub async fn episode_audio_url(show: &str, episode: &str) -> Option<String> {
+    let client = reqwest::Client::builder().timeout(LOOKUP_TIMEOUT).build().ok()?;
+    let search: ItunesSearch = client
+        .get("https://itunes.apple.com/search")
+        .query(&[("media", "podcast"), ("entity", "podcast"), ("limit", "5"), ("term", show)])
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    let wanted = normalize(show);
+    let feed_url = search
+        .results
+        .iter()
+        .find(|r| r.collection_name.as_deref().map(normalize) == Some(wanted.clone()))
+        .or(search.results.first())?
+        .feed_url
+        .clone()?;
+
+    let response = client.get(&feed_url).send().await.ok()?;
+    if response.content_length().is_some_and(|len| len as usize > MAX_FEED_BYTES) {
+        return None;
+    }
+    let feed = response.bytes().await.ok()?;
+    if feed.len() > MAX_FEED_BYTES {
+        return None;
+    }
+    find_enclosure(&String::from_utf8_lossy(&feed), episode)
+}
+
+/// Lowercase with only letters and digits, so punctuation and spacing differences match.
+fn normalize(s: &str) -> String {
+    s.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// The `<enclosure url>` of the feed item whose `<title>` matches `episode`. RSS is regular
+/// enough that scanning for the few tags needed beats pulling in an XML parser.
+fn find_enclosure(feed: &str, episode: &str) -> Option<String> {
+    let wanted = normalize(episode);
+    for item in feed.split("<item").skip(1) {
+        let item = item.split("</item>").next().unwrap_or(item);
+        let Some(title) = between(item, "<title>", "</title>") else { continue };
+        let title = title.trim().trim_start_matches("<![CDATA[").trim_end_matches("]]>");
+        if normalize(&unescape(title)) != wanted {
+            continue;
+        }
+        let enclosure = &item[item.find("<enclosure")?..];
+        let url = between(enclosure, "url=\"", "\"").or_else(|| between(enclosure, "url='", "'"))?;
+        return Some(unescape(url));
+    }
+    None
+}
+
+fn between<'a>(s: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let from = s.find(start)? + start.len();
+    let len = s[from..].find(end)?;
+    Some(&s[from..from + len])
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&#39;", "'")
+}
@@ -0,0 +1,212 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use serenity::all::{ButtonStyle, ComponentInteractionCollector, GuildId};
+use serenity::builder::{CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::embeds::Theme;
+use crate::{player, storage, Ctx, Error};
+
+/// Tracks at least this long get their position remembered.
+const LONG_CONTENT: Duration = Duration::from_secs(20 * 60);
+/// Positions closer than this to either end aren't worth resuming from.
+const MARGIN: Duration = Duration::from_secs(60);
+/// How often positions of playing tracks are written, so a restart loses at most this much.
+const SAVE_EVERY: Duration = Duration::from_secs(30);
+/// Saved positions untouched for this long are pruned.
+const RETENTION_DAYS: i64 = 90;
+const OFFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The URL a guild is playing and how far it got, as of the last save.
+#[derive(Debug, Clone)]
+pub struct Current {
+    url: String,
+    position: Duration,
+}
+
+pub struct PositionStore;
+impl TypeMapKey for PositionStore {
+    type Value = Arc<Mutex<HashMap<GuildId, Current>>>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<HashMap<GuildId, Current>>>> {
+    ctx.data.read().await.get::<PositionStore>().cloned()
+}
+
+fn is_url(query: &str) -> bool {
+    query.starts_with("http://") || query.starts_with("https://")
+}
+
+/// `1:23:45`, or `23:45` under an hour.
+pub fn clock(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+async fn duration(ctx: &Context, guild_id: GuildId) -> Option<Duration> {
+    let ms = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned()?;
+    ms.lock().await.get(&guild_id).and_then(|m| m.duration)
+}
+
+/// The saved position for `url` in `guild_id`, if there's one worth resuming from.
+pub async fn saved(ctx: &Context, guild_id: GuildId, url: &str) -> Option<Duration> {
+    if !is_url(url) {
+        return None;
+    }
+    let db = storage::db(ctx).await.ok()?;
+    let (gid, url) = (guild_id.get() as i64, url.to_string());
+    let secs: Option<i64> = db
+        .call(move |conn| {
+            conn.query_row(
+                "SELECT position_secs FROM playback_positions WHERE guild_id = ?1 AND url = ?2",
+                rusqlite::params![gid, url],
+                |row| row.get(0),
+            )
+            .map(Some)
+            .or_else(|e| if e == rusqlite::Error::QueryReturnedNoRows { Ok(None) } else { Err(e) })
+        })
+        .await
+        .ok()?;
+    secs.map(|s| Duration::from_secs(s as u64)).filter(|p| *p >= MARGIN)
+}
+
+async fn write(ctx: &Context, guild_id: GuildId, url: String, position: Option<Duration>) {
+    let db = match storage::db(ctx).await {
+        Ok(db) => db,
+        Err(e) => {
+            tracing::warn!("Playback positions unavailable: {e:?}");
+            return;
+        }
+    };
+    let gid = guild_id.get() as i64;
+    let now = serenity::Timestamp::now().unix_timestamp();
+    let result = db
+        .call(move |conn| {
+            match position {
+                Some(position) => conn.execute(
+                    "INSERT INTO playback_positions (guild_id, url, position_secs, updated_at) VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(guild_id, url) DO UPDATE SET position_secs = excluded.position_secs, updated_at = excluded.updated_at",
+                    rusqlite::params![gid, url, position.as_secs() as i64, now],
+                )?,
+                None => conn.execute(
+                    "DELETE FROM playback_positions WHERE guild_id = ?1 AND url = ?2",
+                    rusqlite::params![gid, url],
+                )?,
+            };
+            conn.execute("DELETE FROM playback_positions WHERE updated_at < ?1", [now - RETENTION_DAYS * 86_400])
+        })
+        .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to save playback position: {e:?}");
+    }
+}
+
+/// A track for `query` is starting in `guild_id`; remember it if it's a URL.
+pub async fn playing(ctx: &Context, guild_id: GuildId, query: &str, from: Duration) {
+    let Some(store) = store(ctx).await else { return };
+    let mut current = store.lock().await;
+    if is_url(query.trim()) {
+        current.insert(guild_id, Current { url: query.trim().to_string(), position: from });
+    } else {
+        current.remove(&guild_id);
+    }
+}
+
+/// Save where the guild's current URL is, if it's long content. Called periodically and
+/// before a skip, so the exact spot is kept.
+pub async fn remember(ctx: &Context, guild_id: GuildId) {
+    let Some(store) = store(ctx).await else { return };
+    let Some(status) = player::status(ctx, guild_id).await else { return };
+    let Some(url) = store.lock().await.get_mut(&guild_id).map(|c| {
+        c.position = status.position;
+        c.url.clone()
+    }) else {
+        return;
+    };
+    if duration(ctx, guild_id).await.is_some_and(|d| d >= LONG_CONTENT) && status.position >= MARGIN {
+        write(ctx, guild_id, url, Some(status.position)).await;
+    }
+}
+
+/// The guild's track ended. If it got (nearly) to the end the saved position is dropped,
+/// otherwise the last one seen is kept.
+pub async fn finished(ctx: &Context, guild_id: GuildId) {
+    let Some(store) = store(ctx).await else { return };
+    let Some(current) = store.lock().await.remove(&guild_id) else { return };
+    let Some(total) = duration(ctx, guild_id).await.filter(|d| *d >= LONG_CONTENT) else { return };
+    // The last save can be up to `SAVE_EVERY` behind the real end
+    let done = current.position + SAVE_EVERY + MARGIN >= total;
+    let position = (!done && current.position >= MARGIN).then_some(current.position);
+    if done || position.is_some() {
+        write(ctx, guild_id, current.url, position).await;
+    }
+}
+
+/// `remember`, then forget the guild's URL, e.g. when the bot leaves voice.
+pub async fn stopped(ctx: &Context, guild_id: GuildId) {
+    remember(ctx, guild_id).await;
+    if let Some(store) = store(ctx).await {
+        store.lock().await.remove(&guild_id);
+    }
+}
+
+/// Save every playing URL's position every `SAVE_EVERY`.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(SAVE_EVERY).await;
+            let Some(store) = store(&ctx).await else { continue };
+            let guilds: Vec<GuildId> = store.lock().await.keys().copied().collect();
+            for guild_id in guilds {
+                remember(&ctx, guild_id).await;
+            }
+        }
+    });
+}
+
+/// Ask whether to resume `url` from `at`. Only the invoking user can answer; no answer
+/// within `OFFER_TIMEOUT` starts over.
+pub async fn offer(cmd: Ctx<'_>, url: &str, at: Duration, theme: &Theme) -> Result<Option<Duration>, Error> {
+    let ephemeral = crate::settings::ephemeral(cmd).await;
+    let embed = theme.embed().title("Resume?").description(format!("You left off at **{}** in {}.", clock(at), url));
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("resume:yes").label(format!("Resume from {}", clock(at))).style(ButtonStyle::Primary),
+        CreateButton::new("resume:no").label("Start over").style(ButtonStyle::Secondary),
+    ])];
+    let reply = cmd.send(CreateReply::default().embed(embed).components(buttons).ephemeral(ephemeral)).await?;
+    let message = reply.message().await?;
+
+    loop {
+        let press = ComponentInteractionCollector::new(cmd)
+            .message_id(message.id)
+            .timeout(OFFER_TIMEOUT)
+            .await;
+        let Some(press) = press else {
+            let embed = theme.embed().title("Resume?").description("No answer, starting from the beginning.");
+            reply.edit(cmd, CreateReply::default().embed(embed).components(vec![])).await?;
+            return Ok(None);
+        };
+        if press.user.id != cmd.author().id {
+            let notice = CreateInteractionResponseMessage::new()
+                .content("Only the person who ran the command can choose.")
+                .ephemeral(true);
+            press.create_response(cmd.http(), CreateInteractionResponse::Message(notice)).await?;
+            continue;
+        }
+        let resume = press.data.custom_id == "resume:yes";
+        let text = if resume { format!("Resuming from {}.", clock(at)) } else { "Starting from the beginning.".to_string() };
+        let update = CreateInteractionResponseMessage::new()
+            .embed(theme.embed().title("Resume?").description(text))
+            .components(vec![]);
+        press.create_response(cmd.http(), CreateInteractionResponse::UpdateMessage(update)).await?;
+        return Ok(resume.then_some(at));
+    }
+}
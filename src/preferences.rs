@@ -0,0 +1,139 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{GuildId, UserId};
+use serenity::builder::CreateMessage;
+use serenity::prelude::*;
+
+use crate::{storage, Ctx, Error};
+
+/// Where a member's plain-text `music play` searches look first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum SearchSource {
+    /// Search YouTube for the text as typed.
+    #[name = "youtube"]
+    YouTube,
+    /// Look the text up on Spotify and search YouTube for the best match's title and artist,
+    /// when the bot has Spotify API credentials.
+    #[name = "spotify"]
+    Spotify,
+}
+
+impl SearchSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchSource::YouTube => "youtube",
+            SearchSource::Spotify => "spotify",
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "youtube" => SearchSource::YouTube,
+            _ => SearchSource::Spotify,
+        }
+    }
+}
+
+/// One user's `/preferences`; the defaults for anyone who never set them.
+#[derive(Debug, Clone, Copy)]
+pub struct Preferences {
+    pub dm_on_start: bool,
+    pub search: SearchSource,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences { dm_on_start: false, search: SearchSource::Spotify }
+    }
+}
+
+pub async fn get(ctx: &Context, user: UserId) -> Preferences {
+    let Ok(db) = storage::db(ctx).await else { return Preferences::default() };
+    let uid = user.get() as i64;
+    let row: Option<(bool, String)> = db
+        .call(move |conn| {
+            conn.query_row("SELECT dm_on_start, search_source FROM user_preferences WHERE user_id = ?1", [uid], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map(Some)
+            .or_else(|e| if e == rusqlite::Error::QueryReturnedNoRows { Ok(None) } else { Err(e) })
+        })
+        .await
+        .unwrap_or_default();
+    match row {
+        Some((dm_on_start, search)) => Preferences { dm_on_start, search: SearchSource::from_name(&search) },
+        None => Preferences::default(),
+    }
+}
+
+async fn save(ctx: &Context, user: UserId, prefs: Preferences) -> Result<(), Error> {
+    let db = storage::db(ctx).await?;
+    let uid = user.get() as i64;
+    db.call(move |conn| {
+        conn.execute(
+            "INSERT INTO user_preferences (user_id, dm_on_start, search_source) VALUES (?1, ?2, ?3)
+             ON CONFLICT(user_id) DO UPDATE SET dm_on_start = excluded.dm_on_start, search_source = excluded.search_source",
+            rusqlite::params![uid, prefs.dm_on_start, prefs.search.name()],
+        )
+    })
+    .await?;
+    Ok(())
+}
+
+/// The search source for a request: the requester's preference, or the default for requests
+/// nobody in particular made (e.g. through the API).
+pub async fn search_source(ctx: &Context, requested_by: Option<UserId>) -> SearchSource {
+    match requested_by {
+        Some(user) => get(ctx, user).await.search,
+        None => SearchSource::Spotify,
+    }
+}
+
+/// DM `user` that their request `label` started playing in `guild_id`, if they asked for that.
+pub async fn track_started(ctx: &Context, guild_id: GuildId, user: UserId, label: &str) {
+    if !get(ctx, user).await.dm_on_start {
+        return;
+    }
+    let guild = ctx.cache.guild(guild_id).map(|g| g.name.clone()).unwrap_or_else(|| "the server".to_string());
+    let text = format!("Your request is playing in **{}**: {}", guild, label);
+    let sent = match user.create_dm_channel(&ctx.http).await {
+        Ok(dm) => dm.send_message(&ctx.http, CreateMessage::new().content(text)).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        tracing::warn!("Couldn't DM {} that their track started: {e:?}", user);
+    }
+}
+
+/// Show or change your listening preferences
+#[poise::command(prefix_command, slash_command, rename = "preferences")]
+pub async fn preferences(
+    ctx: Ctx<'_>,
+    #[description = "DM me when a track I requested starts playing"] dm_on_start: Option<bool>,
+    #[description = "Where my plain-text searches look first"] search: Option<SearchSource>,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let user = ctx.author().id;
+    let mut prefs = get(sctx, user).await;
+    let changed = dm_on_start.is_some() || search.is_some();
+    if let Some(on) = dm_on_start {
+        prefs.dm_on_start = on;
+    }
+    if let Some(source) = search {
+        prefs.search = source;
+    }
+    if changed {
+        save(sctx, user, prefs).await?;
+    }
+    let text = format!(
+        "{}\nDM when your request starts: `{}`\nSearch source: `{}`",
+        if changed { "Preferences saved." } else { "Your preferences:" },
+        if prefs.dm_on_start { "on" } else { "off" },
+        prefs.search.name(),
+    );
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true)).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![preferences()]
+}
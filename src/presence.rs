@@ -0,0 +1,158 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ActivityData, GuildId};
+use serenity::async_trait;
+use serenity::prelude::*;
+use songbird::events::{Event, EventContext, EventHandler, TrackEvent};
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::config;
+
+/// Tracks currently playing per guild, and where the idle rotation is.
+#[derive(Default)]
+pub struct Presence {
+    /// Songbird tracks keep their handle to tell a stale end event from the current track.
+    playing: HashMap<GuildId, (Option<TrackHandle>, String)>,
+    rotation: usize,
+}
+
+pub struct PresenceState;
+impl TypeMapKey for PresenceState {
+    type Value = Arc<Mutex<Presence>>;
+}
+
+/// Fires on track end/error to drop the guild from the now-playing set.
+struct TrackEnded {
+    ctx: Context,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl EventHandler for TrackEnded {
+    async fn act(&self, ectx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ectx {
+            for (_, handle) in tracks.iter() {
+                track_ended(&self.ctx, self.guild_id, handle).await;
+            }
+        }
+        None
+    }
+}
+
+async fn state(ctx: &Context) -> Option<Arc<Mutex<Presence>>> {
+    ctx.data.read().await.get::<PresenceState>().cloned()
+}
+
+/// Show the guild's new track as the now-playing activity (if its guild is the busiest) until it
+/// ends. Songbird tracks pass their `handle` to be watched; Lavalink reports ends via `stopped`.
+pub async fn track_started(ctx: &Context, guild_id: GuildId, handle: Option<&TrackHandle>) {
+    let Some(state) = state(ctx).await else { return };
+    let title = {
+        let meta = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned();
+        match meta {
+            Some(meta) => meta.lock().await.get(&guild_id).and_then(|m| match (&m.title, &m.artist) {
+                (Some(t), Some(a)) => Some(format!("{} - {}", a, t)),
+                (Some(t), None) => Some(t.clone()),
+                _ => None,
+            }),
+            None => None,
+        }
+    }
+    .unwrap_or_else(|| "music".to_string());
+
+    if let Some(handle) = handle {
+        for event in [TrackEvent::End, TrackEvent::Error] {
+            let _ = handle.add_event(Event::Track(event), TrackEnded { ctx: ctx.clone(), guild_id });
+        }
+    }
+    state.lock().await.playing.insert(guild_id, (handle.cloned(), title));
+    refresh(ctx).await;
+}
+
+async fn track_ended(ctx: &Context, guild_id: GuildId, handle: &TrackHandle) {
+    let Some(state) = state(ctx).await else { return };
+    {
+        let mut presence = state.lock().await;
+        // A newer track may already have replaced this one
+        if presence.playing.get(&guild_id).is_some_and(|(h, _)| h.as_ref().is_some_and(|h| h.uuid() == handle.uuid())) {
+            presence.playing.remove(&guild_id);
+        }
+    }
+    refresh(ctx).await;
+}
+
+/// Whatever was playing in `guild_id` is over (the bot left voice, or Lavalink ended the track).
+pub async fn stopped(ctx: &Context, guild_id: GuildId) {
+    let Some(state) = state(ctx).await else { return };
+    state.lock().await.playing.remove(&guild_id);
+    refresh(ctx).await;
+}
+
+/// Listeners sharing the bot's voice channel in `guild_id`, from the cache.
+fn listeners(ctx: &Context, guild_id: GuildId) -> usize {
+    let me = ctx.cache.current_user().id;
+    let Some(guild) = ctx.cache.guild(guild_id) else { return 0 };
+    let Some(channel) = guild.voice_states.get(&me).and_then(|v| v.channel_id) else { return 0 };
+    guild
+        .voice_states
+        .values()
+        .filter(|v| v.channel_id == Some(channel) && v.user_id != me)
+        .count()
+}
+
+/// Set the activity: the song in the guild with the most listeners, else the current idle status.
+pub async fn refresh(ctx: &Context) {
+    let Some(state) = state(ctx).await else { return };
+    let presence = state.lock().await;
+    let busiest = presence
+        .playing
+        .iter()
+        .max_by_key(|(gid, _)| listeners(ctx, **gid))
+        .map(|(_, (_, title))| title.clone());
+    if let Some(title) = busiest {
+        ctx.set_activity(Some(ActivityData::listening(title)));
+        return;
+    }
+    let cfg = config::current(ctx).await;
+    let statuses = &cfg.presence.statuses;
+    if statuses.is_empty() {
+        ctx.set_activity(None);
+        return;
+    }
+    let text = statuses[presence.rotation % statuses.len()].replace("{guilds}", &ctx.cache.guild_count().to_string());
+    ctx.set_activity(Some(parse_status(&text)));
+}
+
+/// "Playing x", "Listening to x", "Watching x" and "Competing in x" pick the activity type;
+/// anything else becomes a custom status.
+fn parse_status(text: &str) -> ActivityData {
+    if let Some(rest) = text.strip_prefix("Playing ") {
+        ActivityData::playing(rest)
+    } else if let Some(rest) = text.strip_prefix("Listening to ") {
+        ActivityData::listening(rest)
+    } else if let Some(rest) = text.strip_prefix("Watching ") {
+        ActivityData::watching(rest)
+    } else if let Some(rest) = text.strip_prefix("Competing in ") {
+        ActivityData::competing(rest)
+    } else {
+        ActivityData::custom(text)
+    }
+}
+
+/// Rotate the idle statuses every `presence.rotate_secs`.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        loop {
+            refresh(&ctx).await;
+            let every = config::current(&ctx).await.presence.rotate_secs.max(15);
+            tokio::time::sleep(Duration::from_secs(every)).await;
+            if let Some(state) = state(&ctx).await {
+                let mut presence = state.lock().await;
+                presence.rotation = presence.rotation.wrapping_add(1);
+            }
+        }
+    });
+}
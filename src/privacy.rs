@@ -0,0 +1,200 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use serde_json::json;
+use serenity::all::{ButtonStyle, ComponentInteractionCollector, GuildId, UserId};
+use serenity::builder::{
+    CreateActionRow, CreateAttachment, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateMessage,
+};
+use serenity::prelude::*;
+use std::time::Duration;
+
+use crate::{
+    antiraid, automod, favorites, modalert, moderation, permissions, preferences, reminders, roles, scrobble, settings, starboard,
+    storage, tags, warnings, welcome, Ctx, Error,
+};
+
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Everything the bot stores about `user`, as one JSON document.
+async fn export(ctx: &Context, user: UserId) -> Result<serde_json::Value, Error> {
+    let prefs = preferences::get(ctx, user).await;
+    let db = storage::db(ctx).await?;
+    let uid = user.get() as i64;
+    let history: Vec<serde_json::Value> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT at, guild_id, command, invocation, outcome FROM command_audit WHERE user_id = ?1 ORDER BY at",
+            )?;
+            let rows = stmt.query_map([uid], |row| {
+                Ok(json!({
+                    "at": row.get::<_, i64>(0)?,
+                    "guild_id": row.get::<_, Option<i64>>(1)?.map(|g| g.to_string()),
+                    "command": row.get::<_, String>(2)?,
+                    "invocation": row.get::<_, String>(3)?,
+                    "outcome": row.get::<_, String>(4)?,
+                }))
+            })?;
+            rows.collect()
+        })
+        .await?;
+    let quiz: Vec<serde_json::Value> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare("SELECT guild_id, points FROM quiz_scores WHERE user_id = ?1")?;
+            let rows = stmt.query_map([uid], |row| {
+                Ok(json!({ "guild_id": row.get::<_, i64>(0)?.to_string(), "points": row.get::<_, i64>(1)? }))
+            })?;
+            rows.collect()
+        })
+        .await?;
+    Ok(json!({
+        "user_id": user.to_string(),
+        "exported_at": serenity::Timestamp::now().unix_timestamp(),
+        "preferences": { "dm_on_start": prefs.dm_on_start, "search_source": prefs.search.name() },
+        "warnings": warnings::export_user(ctx, user).await?,
+        "tags": tags::export_user(ctx, user).await?,
+        "reminders": reminders::export_user(ctx, user).await?,
+        "quiz_points": quiz,
+        "scrobble_accounts": scrobble::export_user(ctx, user).await?,
+        "favorites": favorites::export_user(ctx, user).await?,
+        "command_history": history,
+    }))
+}
+
+/// What the bot stores about you
+#[poise::command(prefix_command, slash_command, subcommands("data_export", "data_delete"))]
+pub async fn data(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.send(
+        CreateReply::default()
+            .content("`data export` DMs you everything the bot stores about you; `data delete` erases it.")
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// DM yourself a JSON file of everything the bot stores about you
+#[poise::command(prefix_command, slash_command, rename = "export")]
+pub async fn data_export(ctx: Ctx<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let user = ctx.author().id;
+    let document = serde_json::to_vec_pretty(&export(ctx.serenity_context(), user).await?)?;
+    let message = CreateMessage::new()
+        .content("Everything the bot stores about you. Use `/data delete` to erase it.")
+        .add_file(CreateAttachment::bytes(document, format!("data-{}.json", user)));
+    let sent = match user.create_dm_channel(ctx).await {
+        Ok(dm) => dm.send_message(ctx, message).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let reply = match sent {
+        Ok(()) => "Sent you a DM with your data.",
+        Err(e) => {
+            tracing::warn!("Couldn't DM {} their data export: {e:?}", user);
+            "I couldn't DM you. Allow direct messages from server members and try again."
+        }
+    };
+    ctx.send(CreateReply::default().content(reply).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Erase your preferences, history, reminders, quiz points, favorites and scrobble accounts
+#[poise::command(prefix_command, slash_command, rename = "delete")]
+pub async fn data_delete(ctx: Ctx<'_>) -> Result<(), Error> {
+    let user = ctx.author().id;
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("data:delete").label("Delete").style(ButtonStyle::Danger),
+        CreateButton::new("data:cancel").label("Cancel").style(ButtonStyle::Secondary),
+    ])];
+    let prompt = "This erases your `/preferences`, your command history, your pending reminders, your music \
+                  quiz points, your favorites and your linked scrobble accounts. Warnings you received and tags you created belong to their servers and are kept.";
+    let reply = ctx.send(CreateReply::default().content(prompt).components(buttons).ephemeral(true)).await?;
+    let message = reply.message().await?;
+    let press = ComponentInteractionCollector::new(ctx)
+        .message_id(message.id)
+        .author_id(user)
+        .timeout(CONFIRM_TIMEOUT)
+        .await;
+    let Some(press) = press.filter(|p| p.data.custom_id == "data:delete") else {
+        reply.edit(ctx, CreateReply::default().content("Nothing was deleted.").components(vec![])).await?;
+        return Ok(());
+    };
+
+    let sctx = ctx.serenity_context();
+    let reminders = reminders::delete_user(sctx, user).await?;
+    let db = storage::db(sctx).await?;
+    let uid = user.get() as i64;
+    let history = db
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM user_preferences WHERE user_id = ?1", [uid])?;
+            tx.execute("DELETE FROM quiz_scores WHERE user_id = ?1", [uid])?;
+            tx.execute("DELETE FROM scrobble_accounts WHERE user_id = ?1", [uid])?;
+            tx.execute("DELETE FROM favorites WHERE user_id = ?1", [uid])?;
+            let history = tx.execute("DELETE FROM command_audit WHERE user_id = ?1", [uid])?;
+            tx.commit()?;
+            Ok(history)
+        })
+        .await?;
+    let text = format!(
+        "Deleted your preferences, quiz points, favorites, scrobble accounts, {} command history entries and {} reminders.",
+        history, reminders
+    );
+    press
+        .create_response(
+            &sctx.http,
+            CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().content(text).components(vec![])),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Wipe everything stored for a server the bot was removed from
+#[poise::command(prefix_command, slash_command, owners_only, rename = "purge-guild-data")]
+pub async fn admin_purge_guild_data(ctx: Ctx<'_>, #[description = "Server ID"] guild: String) -> Result<(), Error> {
+    let gid = crate::access::parse_guild(&guild)?;
+    let sctx = ctx.serenity_context();
+    if sctx.cache.guild(gid).is_some() {
+        ctx.say("The bot is still in that server; remove it first so nothing is stored again right away.").await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+    purge_guild(sctx, gid).await?;
+    ctx.say(format!("Deleted everything stored for server {}.", gid)).await?;
+    Ok(())
+}
+
+async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<(), Error> {
+    settings::reset(ctx, gid, None).await?;
+    let db = storage::db(ctx).await?;
+    let id = gid.get() as i64;
+    db.call(move |conn| {
+        let tx = conn.transaction()?;
+        for table in [
+            "command_permissions",
+            "command_audit",
+            "playback_positions",
+            "guild_onboarding",
+            "quiz_scores",
+        ] {
+            tx.execute(&format!("DELETE FROM {} WHERE guild_id = ?1", table), [id])?;
+        }
+        tx.execute("DELETE FROM command_registrations WHERE scope = ?1", [format!("guild:{}", id)])?;
+        tx.commit()
+    })
+    .await?;
+    permissions::invalidate(ctx, gid).await;
+    modalert::purge_guild(ctx, gid).await?;
+    moderation::purge_guild(ctx, gid).await?;
+    warnings::purge_guild(ctx, gid).await?;
+    automod::purge_guild(ctx, gid).await?;
+    antiraid::purge_guild(ctx, gid).await?;
+    roles::purge_guild(ctx, gid).await?;
+    starboard::purge_guild(ctx, gid).await?;
+    tags::purge_guild(ctx, gid).await?;
+    welcome::purge_guild(ctx, gid).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![data()]
+}
@@ -0,0 +1,111 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId};
+use serenity::prelude::*;
+use songbird::driver::Bitrate;
+
+use crate::settings::{self, SettingKey};
+use crate::{Ctx, Error};
+
+/// Encoder bitrate for `medium`, songbird's default.
+const MEDIUM_BITRATE: i32 = 128_000;
+const LOW_BITRATE: i32 = 64_000;
+/// Discord's ceiling, for channels on boosted servers.
+const MAX_BITRATE: i32 = 384_000;
+
+/// The guild's `audio_quality` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Quality {
+    #[name = "low"]
+    Low,
+    #[name = "medium"]
+    Medium,
+    #[name = "high"]
+    High,
+}
+
+impl Quality {
+    pub fn name(self) -> &'static str {
+        match self {
+            Quality::Low => "low",
+            Quality::Medium => "medium",
+            Quality::High => "high",
+        }
+    }
+
+    /// yt-dlp `-f` selector for the source stream.
+    pub fn ytdl_format(self) -> &'static str {
+        match self {
+            // Smaller streams download and decode faster on a busy machine
+            Quality::Low => "bestaudio[abr<=96]/worstaudio/bestaudio/best",
+            Quality::Medium => "bestaudio[ext=webm]/bestaudio/best",
+            Quality::High => "bestaudio/best",
+        }
+    }
+
+    /// ffmpeg output options for the player's transcodes: Opus in Ogg at this quality's bitrate,
+    /// which songbird decodes itself. `high` gets Discord's ceiling, since the call's encoder
+    /// brings it down to the channel's bitrate afterwards.
+    pub fn ffmpeg_output(self) -> Vec<String> {
+        let bitrate = match self {
+            Quality::High => MAX_BITRATE,
+            other => other.bitrate(None),
+        };
+        ["-c:a", "libopus", "-b:a", &format!("{}k", bitrate / 1000), "-ar", "48000", "-ac", "2", "-f", "ogg"]
+            .map(str::to_string)
+            .to_vec()
+    }
+
+    /// Opus bitrate sent to Discord. `high` matches the voice channel's own bitrate, which
+    /// boosted servers can raise to 384 kbps.
+    fn bitrate(self, channel_bitrate: Option<u32>) -> i32 {
+        match self {
+            Quality::Low => LOW_BITRATE,
+            Quality::Medium => MEDIUM_BITRATE,
+            Quality::High => channel_bitrate.map_or(MEDIUM_BITRATE, |b| (b as i32).clamp(MEDIUM_BITRATE, MAX_BITRATE)),
+        }
+    }
+}
+
+pub async fn get(ctx: &Context, guild_id: GuildId) -> Quality {
+    match settings::get(ctx, guild_id, SettingKey::AudioQuality).await.as_deref() {
+        Some("low") => Quality::Low,
+        Some("high") => Quality::High,
+        _ => Quality::Medium,
+    }
+}
+
+/// Set the guild's call encoder to its quality. Called on join and when the setting changes;
+/// a Lavalink node encodes on its own, so this only affects the built-in player.
+pub async fn apply(ctx: &Context, guild_id: GuildId) {
+    let Some(call) = songbird::get(ctx).await.and_then(|m| m.get(guild_id)) else { return };
+    let quality = get(ctx, guild_id).await;
+    let mut call = call.lock().await;
+    let channel_bitrate = call.current_channel().and_then(|c| {
+        let channel = ChannelId::new(c.0.get());
+        ctx.cache.guild(guild_id).and_then(|g| g.channels.get(&channel).and_then(|ch| ch.bitrate))
+    });
+    call.set_bitrate(Bitrate::BitsPerSecond(quality.bitrate(channel_bitrate)));
+}
+
+/// Show or set the audio quality (DJs)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "quality")]
+pub async fn music_quality(
+    ctx: Ctx<'_>,
+    #[description = "low saves bandwidth and CPU; high uses the channel's full bitrate"] level: Option<Quality>,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let Some(level) = level else {
+        let current = get(sctx, guild_id).await;
+        ctx.say(format!("Audio quality is `{}`. Options: `low`, `medium`, `high`.", current.name())).await?;
+        return Ok(());
+    };
+    if !settings::author_is_dj(ctx).await {
+        ctx.say("Only DJs can change the audio quality.").await?;
+        return Ok(());
+    }
+    settings::set(sctx, guild_id, SettingKey::AudioQuality, level.name().to_string()).await?;
+    apply(sctx, guild_id).await;
+    ctx.say(format!("Audio quality set to `{}`; the source format changes from the next track.", level.name())).await?;
+    Ok(())
+}
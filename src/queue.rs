@@ -0,0 +1,809 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ButtonStyle, ChannelId, ComponentInteractionCollector, GuildId, UserId};
+use serenity::async_trait;
+use serenity::builder::{
+    CreateActionRow, CreateAttachment, CreateButton, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseMessage,
+};
+use serenity::prelude::*;
+use songbird::events::{Event, EventContext, EventHandler, TrackEvent};
+use songbird::tracks::TrackHandle;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::embeds::{truncate, Theme, DESCRIPTION_LIMIT};
+use crate::events::{self, PlayerEvent};
+use crate::settings::{self, SettingKey};
+use crate::{player, Ctx, Error};
+
+/// A request waiting for the current track to finish. The query is resolved only when its turn
+/// comes, the same way `music play` resolves it.
+#[derive(Debug, Clone)]
+pub struct QueuedTrack {
+    pub query: String,
+    /// `None` for requests from the HTTP API.
+    pub requested_by: Option<UserId>,
+    /// Where "now playing" and errors for this track are posted.
+    pub channel: ChannelId,
+}
+
+pub struct QueueStore;
+impl TypeMapKey for QueueStore {
+    type Value = Arc<Mutex<HashMap<GuildId, VecDeque<QueuedTrack>>>>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<HashMap<GuildId, VecDeque<QueuedTrack>>>>> {
+    ctx.data.read().await.get::<QueueStore>().cloned()
+}
+
+/// Tracks remembered per guild for the control panel's History button.
+const HISTORY_SIZE: usize = 10;
+
+/// What each guild played recently, newest last. Kept in memory only.
+pub struct HistoryStore;
+impl TypeMapKey for HistoryStore {
+    type Value = Arc<Mutex<HashMap<GuildId, VecDeque<String>>>>;
+}
+
+/// Called whenever a track starts, once its metadata is known.
+pub async fn record_played(ctx: &Context, guild_id: GuildId) {
+    let label = match crate::music::track_label(ctx, guild_id).await {
+        Some(label) => label,
+        None => match crate::music::current_query(ctx, guild_id).await {
+            Some(query) => query,
+            None => return,
+        },
+    };
+    let Some(store) = ctx.data.read().await.get::<HistoryStore>().cloned() else { return };
+    let mut history = store.lock().await;
+    let played = history.entry(guild_id).or_default();
+    played.push_back(label);
+    if played.len() > HISTORY_SIZE {
+        played.pop_front();
+    }
+}
+
+/// What `guild_id` played recently, newest last.
+pub async fn history(ctx: &Context, guild_id: GuildId) -> Vec<String> {
+    let Some(store) = ctx.data.read().await.get::<HistoryStore>().cloned() else { return Vec::new() };
+    store.lock().await.get(&guild_id).map(|h| h.iter().cloned().collect()).unwrap_or_default()
+}
+
+/// Add a request to the queue; returns its 1-based position. That's the end, unless
+/// `queue.fair` is on and requesters take turns (see `fair_slot`).
+pub async fn push(ctx: &Context, guild_id: GuildId, track: QueuedTrack) -> usize {
+    let Some(store) = store(ctx).await else { return 0 };
+    let fair = settings::get(ctx, guild_id, SettingKey::QueueFair).await.as_deref() == Some("on");
+    let position = {
+        let mut queues = store.lock().await;
+        let queue = queues.entry(guild_id).or_default();
+        let at = if fair { fair_slot(queue, track.requested_by) } else { queue.len() };
+        queue.insert(at, track);
+        at + 1
+    };
+    events::queue_changed(ctx, guild_id).await;
+    position
+}
+
+/// Put a request at the front of the queue, to play as soon as the current track ends.
+pub async fn push_next(ctx: &Context, guild_id: GuildId, track: QueuedTrack) {
+    let Some(store) = store(ctx).await else { return };
+    store.lock().await.entry(guild_id).or_default().push_front(track);
+    events::queue_changed(ctx, guild_id).await;
+}
+
+/// Where round-robin puts a new request from `requester`: a member's n-th waiting request
+/// goes after everyone else's n-th, so `A A A` plus two from B becomes `A B A B A`.
+fn fair_slot(queue: &VecDeque<QueuedTrack>, requester: Option<UserId>) -> usize {
+    let round = queue.iter().filter(|t| t.requested_by == requester).count();
+    let mut seen: HashMap<Option<UserId>, usize> = HashMap::new();
+    let mut slot = 0;
+    for (i, track) in queue.iter().enumerate() {
+        let earlier = seen.entry(track.requested_by).or_default();
+        if *earlier <= round {
+            slot = i + 1;
+        }
+        *earlier += 1;
+    }
+    slot
+}
+
+/// The guild's `queue.max_per_user`; `None` when unlimited.
+pub async fn max_per_user(ctx: &Context, guild_id: GuildId) -> Option<usize> {
+    let limit = settings::get(ctx, guild_id, SettingKey::QueueMaxPerUser).await?;
+    limit.parse().ok().filter(|n| *n > 0)
+}
+
+/// How many requests `user` has waiting.
+pub async fn waiting(ctx: &Context, guild_id: GuildId, user: UserId) -> usize {
+    let Some(store) = store(ctx).await else { return 0 };
+    store.lock().await.get(&guild_id).map_or(0, |q| q.iter().filter(|t| t.requested_by == Some(user)).count())
+}
+
+/// The limit, if `user` already has `queue.max_per_user` requests waiting.
+pub async fn limit_reached(ctx: &Context, guild_id: GuildId, user: UserId) -> Option<usize> {
+    let limit = max_per_user(ctx, guild_id).await?;
+    (waiting(ctx, guild_id, user).await >= limit).then_some(limit)
+}
+
+/// Snapshot of the waiting requests, next first.
+pub async fn list(ctx: &Context, guild_id: GuildId) -> Vec<QueuedTrack> {
+    let Some(store) = store(ctx).await else { return Vec::new() };
+    store.lock().await.get(&guild_id).map(|q| q.iter().cloned().collect()).unwrap_or_default()
+}
+
+/// Move the request at `from` to `to` (0-based, clamped). Returns false if `from` is out of range.
+pub async fn move_track(ctx: &Context, guild_id: GuildId, from: usize, to: usize) -> bool {
+    let Some(store) = store(ctx).await else { return false };
+    {
+        let mut queues = store.lock().await;
+        let Some(queue) = queues.get_mut(&guild_id) else { return false };
+        let Some(track) = queue.remove(from) else { return false };
+        let to = to.min(queue.len());
+        queue.insert(to, track);
+    }
+    events::queue_changed(ctx, guild_id).await;
+    true
+}
+
+/// Drop the request at `index` (0-based).
+pub async fn remove(ctx: &Context, guild_id: GuildId, index: usize) -> Option<QueuedTrack> {
+    let store = store(ctx).await?;
+    let removed = store.lock().await.get_mut(&guild_id)?.remove(index)?;
+    events::queue_changed(ctx, guild_id).await;
+    Some(removed)
+}
+
+/// Forget everything waiting in `guild_id`, e.g. when playback is stopped or the bot leaves.
+pub async fn clear(ctx: &Context, guild_id: GuildId) {
+    if let Some(store) = store(ctx).await
+        && store.lock().await.remove(&guild_id).is_some()
+    {
+        events::queue_changed(ctx, guild_id).await;
+    }
+}
+
+/// Fires on track end/error to start the next queued request.
+struct TrackFinished {
+    ctx: Context,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl EventHandler for TrackFinished {
+    async fn act(&self, ectx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ectx {
+            for (_, handle) in tracks.iter() {
+                advance(&self.ctx, self.guild_id, handle).await;
+            }
+        }
+        None
+    }
+}
+
+/// Called for every track the player starts so the queue moves on when it finishes.
+pub fn track_started(ctx: &Context, guild_id: GuildId, handle: &TrackHandle) {
+    for event in [TrackEvent::End, TrackEvent::Error] {
+        let _ = handle.add_event(Event::Track(event), TrackFinished { ctx: ctx.clone(), guild_id });
+    }
+}
+
+/// `ended` finished. Ignored if a newer track has already replaced it in the store.
+async fn advance(ctx: &Context, guild_id: GuildId, ended: &TrackHandle) {
+    let tracks = ctx.data.read().await.get::<crate::TrackStore>().cloned();
+    if let Some(tracks) = tracks
+        && tracks.lock().await.get(&guild_id).is_some_and(|h| h.uuid() != ended.uuid())
+    {
+        return;
+    }
+    track_finished(ctx, guild_id).await;
+}
+
+/// The current track is over: play queued requests until one starts.
+pub async fn track_finished(ctx: &Context, guild_id: GuildId) {
+    events::emit(ctx, guild_id, PlayerEvent::TrackEnded).await;
+    crate::positions::finished(ctx, guild_id).await;
+    // The finished track's stream processes (and any left by failed attempts) are done with
+    crate::music::kill_children(ctx, guild_id).await;
+    if crate::ambient::resume(ctx, guild_id).await || crate::live::resume(ctx, guild_id).await {
+        return;
+    }
+    start_next(ctx, guild_id).await;
+}
+
+/// Wait between tries of a queued request that failed to start.
+const RETRY_AFTER: Duration = Duration::from_secs(2);
+
+/// Play queued requests until one starts. Also used to kick off an imported queue when nothing
+/// is playing. A request that fails to start is tried `music.queue_attempts` times, then skipped
+/// with a notice so one broken link doesn't hold up the rest.
+pub async fn start_next(ctx: &Context, guild_id: GuildId) {
+    // A listening party guest plays its host's tracks; its own queue waits for the party to end
+    if crate::party::following(ctx, guild_id).await.is_some() {
+        return;
+    }
+    let attempts = crate::config::current(ctx).await.music.queue_attempts();
+    while let Some(next) = pop(ctx, guild_id).await {
+        let tried = try_start(
+            attempts,
+            RETRY_AFTER,
+            || crate::music::play_now(ctx, next.channel, guild_id, &next.query, next.requested_by),
+            || busy(ctx, guild_id),
+        )
+        .await;
+        match tried {
+            Tried::Taken => return,
+            Tried::Refused => {}
+            Tried::Failed(e) => {
+                tracing::warn!("Queued track `{}` failed {} time(s), skipping it: {e:?}", next.query, attempts);
+                skipped(ctx, guild_id, &next, &e.to_string()).await;
+            }
+            // Someone played something meanwhile: the request keeps its turn
+            Tried::Interrupted => {
+                push_next(ctx, guild_id, next).await;
+                return;
+            }
+        }
+    }
+}
+
+/// How `try_start` went.
+#[derive(Debug)]
+enum Tried {
+    /// Something is playing or resolving now, this request or a newer `play`.
+    Taken,
+    /// Not started, and the channel was told why (over a limit, ...); trying again won't help.
+    Refused,
+    /// Every attempt failed; the last error.
+    Failed(Error),
+    /// Something else started while waiting to try again.
+    Interrupted,
+}
+
+/// Call `play` up to `attempts` times, `wait` apart, until it starts something or refuses.
+/// `busy` says whether the guild is playing or resolving.
+async fn try_start<P, PF, B, BF>(attempts: u32, wait: Duration, mut play: P, mut busy: B) -> Tried
+where
+    P: FnMut() -> PF,
+    PF: std::future::Future<Output = Result<(), Error>>,
+    B: FnMut() -> BF,
+    BF: std::future::Future<Output = bool>,
+{
+    let mut attempt = 1;
+    loop {
+        let result = play().await;
+        if busy().await {
+            return Tried::Taken;
+        }
+        let e = match result {
+            Ok(()) => return Tried::Refused,
+            Err(e) => e,
+        };
+        if attempt >= attempts {
+            return Tried::Failed(e);
+        }
+        tracing::warn!("Queued track failed to start (attempt {}/{}), retrying: {e:?}", attempt, attempts);
+        attempt += 1;
+        tokio::time::sleep(wait).await;
+        if busy().await {
+            return Tried::Interrupted;
+        }
+    }
+}
+
+async fn busy(ctx: &Context, guild_id: GuildId) -> bool {
+    player::status(ctx, guild_id).await.is_some() || crate::music::resolving(ctx, guild_id).await
+}
+
+/// Tell the request's channel it was given up on. Only the first line of `reason`, since
+/// ffmpeg and yt-dlp errors can run long.
+async fn skipped(ctx: &Context, guild_id: GuildId, track: &QueuedTrack, reason: &str) {
+    let reason = crate::embeds::truncate(reason.lines().next().unwrap_or_default(), 200);
+    let embed = crate::embeds::theme(ctx, Some(guild_id))
+        .await
+        .embed()
+        .title("Music")
+        .description(format!("Skipped {}: {}", track.query, reason));
+    let _ = track.channel.send_message(&ctx.http, serenity::builder::CreateMessage::new().embed(embed)).await;
+}
+
+async fn pop(ctx: &Context, guild_id: GuildId) -> Option<QueuedTrack> {
+    let store = store(ctx).await?;
+    let next = store.lock().await.get_mut(&guild_id)?.pop_front()?;
+    events::queue_changed(ctx, guild_id).await;
+    Some(next)
+}
+
+// ---------- Queue view and ordering ----------
+
+/// Requests shown per page of `music queue`.
+const PAGE_SIZE: usize = 10;
+/// How long the page buttons keep working.
+const VIEW_TIMEOUT: Duration = Duration::from_secs(120);
+/// Longest query or title shown per entry, so a page of long ones stays inside the description limit.
+const ENTRY_CHARS: usize = 300;
+
+fn page_count(tracks: &[QueuedTrack]) -> usize {
+    tracks.len().div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Page `page` (0-based, clamped) of the waiting requests, numbered as `move` and `remove` take them.
+pub fn page_embed(theme: &Theme, tracks: &[QueuedTrack], page: usize) -> CreateEmbed {
+    let pages = page_count(tracks);
+    let page = page.min(pages - 1);
+    let lines: Vec<String> = tracks
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(i, t)| {
+            let query = truncate(&t.query, ENTRY_CHARS);
+            match t.requested_by {
+                Some(user) => format!("`{}.` {} — <@{}>", i + 1, query, user),
+                None => format!("`{}.` {}", i + 1, query),
+            }
+        })
+        .collect();
+    let description = if lines.is_empty() { "Nothing is waiting.".to_string() } else { lines.join("\n") };
+    theme
+        .embed()
+        .title(format!("Queue — {} waiting", tracks.len()))
+        .description(truncate(&description, DESCRIPTION_LIMIT))
+        .footer(serenity::builder::CreateEmbedFooter::new(format!("Page {}/{}", page + 1, pages)))
+}
+
+/// The recently played tracks, newest first; the first is the one playing if anything is.
+pub fn history_embed(theme: &Theme, played: &[String]) -> CreateEmbed {
+    let lines: Vec<String> = played
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, t)| format!("`{}.` {}", i + 1, truncate(t, ENTRY_CHARS)))
+        .collect();
+    let description = if lines.is_empty() { "Nothing has played yet.".to_string() } else { lines.join("\n") };
+    theme.embed().title("Recently played").description(truncate(&description, DESCRIPTION_LIMIT))
+}
+
+fn page_buttons(page: usize, pages: usize) -> Vec<CreateActionRow> {
+    if pages <= 1 {
+        return vec![];
+    }
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("queue:prev").label("Previous").style(ButtonStyle::Secondary).disabled(page == 0),
+        CreateButton::new("queue:next").label("Next").style(ButtonStyle::Secondary).disabled(page + 1 >= pages),
+    ])]
+}
+
+/// Show the waiting requests a page at a time, read fresh from the queue on every press.
+async fn show(ctx: Ctx<'_>, guild_id: GuildId) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let theme = crate::embeds::theme(sctx, Some(guild_id)).await;
+    let ephemeral = settings::ephemeral(ctx).await;
+    let tracks = list(sctx, guild_id).await;
+    let mut page = 0;
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(page_embed(&theme, &tracks, page))
+                .components(page_buttons(page, page_count(&tracks)))
+                .ephemeral(ephemeral),
+        )
+        .await?;
+    if page_count(&tracks) <= 1 {
+        return Ok(());
+    }
+    let message = reply.message().await?;
+
+    while let Some(press) = ComponentInteractionCollector::new(ctx).message_id(message.id).timeout(VIEW_TIMEOUT).await {
+        if press.user.id != ctx.author().id {
+            let notice = CreateInteractionResponseMessage::new()
+                .content("Run `music queue` to page through the queue yourself.")
+                .ephemeral(true);
+            press.create_response(ctx.http(), CreateInteractionResponse::Message(notice)).await?;
+            continue;
+        }
+        let tracks = list(sctx, guild_id).await;
+        let pages = page_count(&tracks);
+        page = match press.data.custom_id.as_str() {
+            "queue:prev" => page.saturating_sub(1),
+            _ => page + 1,
+        }
+        .min(pages - 1);
+        let update = CreateInteractionResponseMessage::new()
+            .embed(page_embed(&theme, &tracks, page))
+            .components(page_buttons(page, pages));
+        press.create_response(ctx.http(), CreateInteractionResponse::UpdateMessage(update)).await?;
+    }
+    reply.edit(ctx, CreateReply::default().components(vec![])).await?;
+    Ok(())
+}
+
+/// Move a waiting request to another place in the queue (DJs)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "move")]
+pub async fn queue_move(
+    ctx: Ctx<'_>,
+    #[description = "Its number in `music queue`"]
+    #[min = 1]
+    from: usize,
+    #[description = "The number it should have"]
+    #[min = 1]
+    to: usize,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    if !settings::author_is_dj(ctx).await {
+        ctx.say("Only DJs can reorder the queue.").await?;
+        return Ok(());
+    }
+    let tracks = list(sctx, guild_id).await;
+    let Some(track) = from.checked_sub(1).and_then(|i| tracks.get(i)) else {
+        ctx.say(format!("There's no #{} in the queue; it has {} request(s).", from, tracks.len())).await?;
+        return Ok(());
+    };
+    let to = to.clamp(1, tracks.len());
+    if !move_track(sctx, guild_id, from - 1, to - 1).await {
+        ctx.say("The queue changed in the meantime; check `music queue` and try again.").await?;
+        return Ok(());
+    }
+    ctx.say(format!("Moved {} to #{}.", track.query, to)).await?;
+    Ok(())
+}
+
+/// Take a request out of the queue (DJs)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "remove")]
+pub async fn queue_remove(
+    ctx: Ctx<'_>,
+    #[description = "Its number in `music queue`"]
+    #[min = 1]
+    index: usize,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    if !settings::author_is_dj(ctx).await {
+        ctx.say("Only DJs can remove requests from the queue.").await?;
+        return Ok(());
+    }
+    match remove(sctx, guild_id, index.saturating_sub(1)).await {
+        Some(track) => ctx.say(format!("Removed #{}: {}", index, track.query)).await?,
+        None => ctx.say(format!("There's no #{} in the queue.", index)).await?,
+    };
+    Ok(())
+}
+
+/// Empty the queue and keep the current track playing (DJs)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "clear")]
+pub async fn music_clear(ctx: Ctx<'_>) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    if !settings::author_is_dj(ctx).await {
+        ctx.say("Only DJs can clear the queue.").await?;
+        return Ok(());
+    }
+    let waiting = list(sctx, guild_id).await.len();
+    if waiting == 0 {
+        ctx.say("The queue is already empty.").await?;
+        return Ok(());
+    }
+    clear(sctx, guild_id).await;
+    ctx.say(format!("Cleared {} waiting request(s); the current track keeps playing.", waiting)).await?;
+    Ok(())
+}
+
+/// Play a track right after the current one, ahead of the queue (DJs)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "playnext")]
+pub async fn music_playnext(
+    ctx: Ctx<'_>,
+    #[description = "Song name or URL"]
+    #[rest]
+    query: String,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    if !settings::author_is_dj(ctx).await {
+        ctx.say("Only DJs can put requests ahead of the queue; use `music play` to queue it.").await?;
+        return Ok(());
+    }
+    // Nothing to jump ahead of: play it the usual way
+    let busy = crate::ambient::active(sctx, guild_id).await.is_some() || player::status(sctx, guild_id).await.is_some();
+    if !busy {
+        crate::defer_music(ctx).await?;
+        crate::music::handle_music(ctx, None, &format!("play {}", query)).await?;
+        crate::finish_deferred(ctx).await;
+        return Ok(());
+    }
+    let theme = crate::embeds::theme(sctx, Some(guild_id)).await;
+    if !crate::dedupe::allow(ctx, &query, &theme).await? {
+        return Ok(());
+    }
+    let channel = crate::music::session_channel(sctx, guild_id, ctx.channel_id()).await;
+    push_next(sctx, guild_id, QueuedTrack { query: query.trim().to_string(), requested_by: Some(ctx.author().id), channel }).await;
+    // Ambient sound gives way; its track ending starts the queue
+    if crate::ambient::active(sctx, guild_id).await.is_some() {
+        player::skip(sctx, guild_id).await?;
+    }
+    ctx.say(format!("Up next: {}", query.trim())).await?;
+    Ok(())
+}
+
+// ---------- Import / export ----------
+
+/// Files bigger than this aren't fetched.
+const MAX_IMPORT_BYTES: u32 = 256 * 1024;
+/// Requests taken from one file; the rest are dropped.
+const MAX_IMPORT_TRACKS: usize = 200;
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum QueueFormat {
+    #[name = "json"]
+    Json,
+    #[name = "m3u"]
+    M3u,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedTrack {
+    query: String,
+}
+
+/// The JSON export: `{"tracks": [{"query": "..."}]}`.
+#[derive(Serialize, Deserialize)]
+struct ExportedQueue {
+    tracks: Vec<ExportedTrack>,
+}
+
+fn to_json(tracks: &[QueuedTrack]) -> String {
+    let queue = ExportedQueue { tracks: tracks.iter().map(|t| ExportedTrack { query: t.query.clone() }).collect() };
+    serde_json::to_string_pretty(&queue).unwrap_or_default()
+}
+
+/// Extended M3U. Search queries aren't URLs, but `import` reads any non-comment line back as one.
+fn to_m3u(tracks: &[QueuedTrack]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for track in tracks {
+        out.push_str(&format!("#EXTINF:-1,{}\n{}\n", track.query, track.query));
+    }
+    out
+}
+
+/// Queries from an export: our JSON, a plain JSON array of strings, or one entry per line
+/// (M3U, or any text list) with `#` lines skipped.
+fn parse_import(text: &str) -> Vec<String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let queries: Vec<String> = if let Ok(queue) = serde_json::from_str::<ExportedQueue>(text) {
+        queue.tracks.into_iter().map(|t| t.query).collect()
+    } else if let Ok(list) = serde_json::from_str::<Vec<String>>(text) {
+        list
+    } else {
+        text.lines().filter(|l| !l.trim_start().starts_with('#')).map(str::to_string).collect()
+    };
+    queries.into_iter().map(|q| q.trim().to_string()).filter(|q| !q.is_empty()).collect()
+}
+
+/// Where `queue import` will fetch a link from: paste sites, GitHub's raw file hosts and
+/// Discord's attachment CDN.
+const IMPORT_HOSTS: [&str; 6] = [
+    "pastebin.com",
+    "paste.rs",
+    "gist.githubusercontent.com",
+    "raw.githubusercontent.com",
+    "cdn.discordapp.com",
+    "media.discordapp.net",
+];
+
+/// The URL to fetch an import link from, or `None` if it's not an https link to a supported
+/// host. Pastebin page links point at HTML, so they're swapped for the raw paste.
+fn import_url(url: &str) -> Option<reqwest::Url> {
+    let url = reqwest::Url::parse(url.trim()).ok()?;
+    if url.scheme() != "https" || !url.host_str().is_some_and(|h| IMPORT_HOSTS.contains(&h)) {
+        return None;
+    }
+    match url.as_str().strip_prefix("https://pastebin.com/") {
+        Some(id) if !id.starts_with("raw/") => reqwest::Url::parse(&format!("https://pastebin.com/raw/{}", id)).ok(),
+        _ => Some(url),
+    }
+}
+
+async fn fetch(url: reqwest::Url) -> Result<String, Error> {
+    // Redirects aren't followed, so a supported host can't hand the request on to another
+    let client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build()?;
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    if !response.status().is_success() {
+        return Err("That link doesn't lead straight to a file.".into());
+    }
+    if response.content_length().is_some_and(|len| len > MAX_IMPORT_BYTES as u64) {
+        return Err("That file is too big to import.".into());
+    }
+    // Read in chunks so a server that lies about (or leaves out) the length can't send more
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > MAX_IMPORT_BYTES as usize {
+            return Err("That file is too big to import.".into());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Show the waiting requests; subcommands reorder them or save and load them as a file
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "queue",
+    subcommands("queue_show", "queue_move", "queue_remove", "queue_export", "queue_import")
+)]
+pub async fn music_queue(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    show(ctx, guild_id).await
+}
+
+/// Page through the waiting requests
+#[poise::command(prefix_command, slash_command, guild_only, rename = "show")]
+pub async fn queue_show(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    show(ctx, guild_id).await
+}
+
+/// Download the waiting queue as a JSON or M3U file
+#[poise::command(prefix_command, slash_command, guild_only, rename = "export")]
+pub async fn queue_export(
+    ctx: Ctx<'_>,
+    #[description = "File format (default json)"] format: Option<QueueFormat>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let tracks = list(ctx.serenity_context(), guild_id).await;
+    if tracks.is_empty() {
+        ctx.say("The queue is empty; nothing to export.").await?;
+        return Ok(());
+    }
+    let (body, name) = match format.unwrap_or(QueueFormat::Json) {
+        QueueFormat::Json => (to_json(&tracks), "queue.json"),
+        QueueFormat::M3u => (to_m3u(&tracks), "queue.m3u"),
+    };
+    let reply = poise::CreateReply::default()
+        .content(format!("{} queued request(s).", tracks.len()))
+        .attachment(CreateAttachment::bytes(body.into_bytes(), name));
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// Queue every request in an exported file or a pastebin link
+#[poise::command(prefix_command, slash_command, guild_only, rename = "import")]
+pub async fn queue_import(
+    ctx: Ctx<'_>,
+    #[description = "A queue.json / queue.m3u export"] file: Option<serenity::Attachment>,
+    #[description = "Or a link to one (pastebin links work)"] url: Option<String>,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let voice = match songbird::get(sctx).await.and_then(|m| m.get(guild_id)) {
+        Some(call) => call.lock().await.current_channel(),
+        None => None,
+    };
+    let Some(voice) = voice else {
+        ctx.say("I'm not in a voice channel; use `music join` first.").await?;
+        return Ok(());
+    };
+
+    let text = match (file, url) {
+        (Some(file), _) if file.size > MAX_IMPORT_BYTES => {
+            ctx.say("That file is too big to import.").await?;
+            return Ok(());
+        }
+        (Some(file), _) => {
+            ctx.defer().await?;
+            String::from_utf8_lossy(&file.download().await?).into_owned()
+        }
+        (None, Some(url)) => match import_url(&url) {
+            Some(url) => {
+                ctx.defer().await?;
+                fetch(url).await?
+            }
+            None => {
+                ctx.say(format!("Give an https:// link to a file on one of: {}.", IMPORT_HOSTS.join(", "))).await?;
+                return Ok(());
+            }
+        },
+        (None, None) => {
+            ctx.say("Attach an exported queue file or give a link to one.").await?;
+            return Ok(());
+        }
+    };
+    let mut queries = parse_import(&text);
+    if queries.is_empty() {
+        ctx.say("Found no tracks in that file.").await?;
+        return Ok(());
+    }
+    let dropped = queries.len().saturating_sub(MAX_IMPORT_TRACKS);
+    queries.truncate(MAX_IMPORT_TRACKS);
+
+    let channel = crate::music::session_channel(sctx, guild_id, ChannelId::new(voice.0.get())).await;
+    let blocking = crate::dedupe::mode(sctx, guild_id).await == crate::dedupe::Mode::Block;
+    let room = match max_per_user(sctx, guild_id).await {
+        Some(limit) if !settings::author_is_dj(ctx).await => limit.saturating_sub(waiting(sctx, guild_id, ctx.author().id).await),
+        _ => usize::MAX,
+    };
+    let (mut count, mut duplicates, mut over_limit) = (0, 0, 0);
+    for query in queries {
+        if blocking && crate::dedupe::find(sctx, guild_id, &query).await.is_some() {
+            duplicates += 1;
+            continue;
+        }
+        if count >= room {
+            over_limit += 1;
+            continue;
+        }
+        push(sctx, guild_id, QueuedTrack { query, requested_by: Some(ctx.author().id), channel }).await;
+        count += 1;
+    }
+    let mut text = format!("Queued {} request(s).", count);
+    if dropped > 0 {
+        text.push_str(&format!(" {} more were skipped (at most {} per import).", dropped, MAX_IMPORT_TRACKS));
+    }
+    if duplicates > 0 {
+        text.push_str(&format!(" {} duplicate(s) were left out (`queue.dedupe` is `block`).", duplicates));
+    }
+    if over_limit > 0 {
+        text.push_str(&format!(" {} more didn't fit under this server's per-member queue limit.", over_limit));
+    }
+    ctx.say(text).await?;
+
+    // Ambient sound gives way; its track ending starts the queue
+    if crate::ambient::active(sctx, guild_id).await.is_some() {
+        player::skip(sctx, guild_id).await?;
+    } else if player::status(sctx, guild_id).await.is_none() && !crate::music::resolving(sctx, guild_id).await {
+        let sctx = sctx.clone();
+        tokio::spawn(async move { start_next(&sctx, guild_id).await });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[tokio::test]
+    async fn failing_requests_are_retried_then_given_up() {
+        let calls = Cell::new(0);
+        let tried = try_start(
+            3,
+            Duration::ZERO,
+            || {
+                calls.set(calls.get() + 1);
+                async { Err::<(), Error>("no results".into()) }
+            },
+            || async { false },
+        )
+        .await;
+        assert!(matches!(tried, Tried::Failed(e) if e.to_string() == "no results"));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_newer_play_interrupts_the_retries() {
+        let calls = Cell::new(0);
+        let checks = Cell::new(0);
+        let tried = try_start(
+            3,
+            Duration::ZERO,
+            || {
+                calls.set(calls.get() + 1);
+                async { Err::<(), Error>("timed out".into()) }
+            },
+            || {
+                // Idle right after the failed attempt, playing by the time of the retry
+                checks.set(checks.get() + 1);
+                let playing = checks.get() > 1;
+                async move { playing }
+            },
+        )
+        .await;
+        assert!(matches!(tried, Tried::Interrupted));
+        assert_eq!(calls.get(), 1);
+    }
+}
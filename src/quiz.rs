@@ -0,0 +1,469 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serenity::all::{ChannelId, GuildId, MessageCollector, ReactionType, UserId};
+use serenity::builder::CreateMessage;
+use serenity::futures::StreamExt;
+use serenity::prelude::*;
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+
+use crate::embeds::Theme;
+use crate::{config, embeds, player, sources, storage, Ctx, Error};
+
+const DEFAULT_ROUNDS: u32 = 5;
+/// How long each clip plays, which is also how long guesses are taken.
+const CLIP: Duration = Duration::from_secs(30);
+const BETWEEN_ROUNDS: Duration = Duration::from_secs(5);
+/// Playlist entries (or search results for a genre) the rounds are drawn from.
+const CANDIDATES: usize = 50;
+const LEADERBOARD_SIZE: usize = 10;
+
+/// Guilds with a quiz running, and how to stop each one.
+pub struct QuizStore;
+impl TypeMapKey for QuizStore {
+    type Value = Arc<Mutex<HashMap<GuildId, Arc<Notify>>>>;
+}
+
+async fn store(ctx: &Context) -> Option<Arc<Mutex<HashMap<GuildId, Arc<Notify>>>>> {
+    ctx.data.read().await.get::<QuizStore>().cloned()
+}
+
+/// Whether `guild_id` has a quiz running; `music play` waits until it's over.
+pub async fn active(ctx: &Context, guild_id: GuildId) -> bool {
+    match store(ctx).await {
+        Some(store) => store.lock().await.contains_key(&guild_id),
+        None => false,
+    }
+}
+
+/// End the guild's quiz after the current round's reveal. Called by `music quiz stop` and on
+/// leave; returns whether one was running.
+pub async fn stop(ctx: &Context, guild_id: GuildId) -> bool {
+    let Some(store) = store(ctx).await else { return false };
+    let Some(stop) = store.lock().await.remove(&guild_id) else { return false };
+    stop.notify_one();
+    true
+}
+
+/// One round's song and the answers that score.
+#[derive(Debug, Clone)]
+struct Song {
+    url: String,
+    title: String,
+    artist: Option<String>,
+    duration: Option<f64>,
+}
+
+/// Split a video title into song title and artist. Most music uploads are "Artist - Title";
+/// otherwise the uploader is taken as the artist, without YouTube's " - Topic"/"VEVO" suffixes.
+/// Scrobbles name tracks the same way.
+pub fn answer(title: &str, uploader: Option<&str>) -> (String, Option<String>) {
+    let title = strip_extras(title);
+    if let Some((artist, song)) = title.split_once(" - ") {
+        return (song.trim().to_string(), Some(artist.trim().to_string()));
+    }
+    let artist = uploader
+        .map(|u| u.trim_end_matches(" - Topic").trim_end_matches("VEVO").trim().to_string())
+        .filter(|a| !a.is_empty());
+    (title, artist)
+}
+
+/// `title` without bracketed parts ("(Official Video)", "[Lyrics]") and featured artists.
+fn strip_extras(title: &str) -> String {
+    let mut depth = 0usize;
+    let mut kept = String::with_capacity(title.len());
+    for c in title.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => kept.push(c),
+            _ => {}
+        }
+    }
+    let lower = kept.to_lowercase();
+    let cut = [" feat.", " feat ", " ft.", " ft ", " | "].iter().filter_map(|m| lower.find(m)).min();
+    if let Some(at) = cut {
+        kept.truncate(at);
+    }
+    kept.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lowercase letters and digits only, so punctuation and a leading "the" don't matter.
+fn normalize(text: &str) -> String {
+    let text: String = text.to_lowercase().chars().map(|c| if c.is_alphanumeric() { c } else { ' ' }).collect();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    text.strip_prefix("the ").map(str::to_string).unwrap_or(text)
+}
+
+/// Whether `guess` names `answer`, allowing about one typo in five letters.
+fn matches(guess: &str, answer: &str) -> bool {
+    let (guess, answer) = (normalize(guess), normalize(answer));
+    if answer.is_empty() {
+        return false;
+    }
+    let length = answer.chars().count();
+    guess == answer || (length >= 5 && distance(&guess, &answer) <= length / 5)
+}
+
+/// Levenshtein distance.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != *cb)).min(row[j] + 1).min(above + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Songs from a playlist link (anything yt-dlp lists) or a YouTube search for a genre.
+async fn songs(ctx: &Context, source: &str) -> Result<Vec<Song>, Error> {
+    let target = if sources::is_url(source) {
+        source.to_string()
+    } else {
+        format!("ytsearch{}:{} song", CANDIDATES, source)
+    };
+    let cookie_args = config::current(ctx).await.music.cookie_args();
+    let out = tokio::process::Command::new("yt-dlp")
+        .kill_on_drop(true)
+        .args(&cookie_args)
+        .args(["--flat-playlist", "--playlist-items", &format!("1:{}", CANDIDATES), "-j", &target])
+        .output()
+        .await?;
+    if !out.status.success() {
+        return Err(format!("yt-dlp couldn't list that: {}", String::from_utf8_lossy(&out.stderr).trim()).into());
+    }
+    let mut seen = std::collections::HashSet::new();
+    let songs = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter_map(|entry| {
+            let url = match entry.get("url").and_then(|u| u.as_str()) {
+                Some(url) if sources::is_url(url) => url.to_string(),
+                _ => format!("https://www.youtube.com/watch?v={}", entry.get("id")?.as_str()?),
+            };
+            let uploader = entry.get("uploader").or_else(|| entry.get("channel")).and_then(|u| u.as_str());
+            let (title, artist) = answer(entry.get("title")?.as_str()?, uploader);
+            let duration = entry.get("duration").and_then(|d| d.as_f64());
+            Some(Song { url, title, artist, duration })
+        })
+        // Too short to take a clip from, or the same song twice
+        .filter(|s| s.duration.is_none_or(|d| d >= CLIP.as_secs_f64() * 2.0))
+        .filter(|s| !normalize(&s.title).is_empty() && seen.insert(normalize(&s.title)))
+        .collect();
+    Ok(songs)
+}
+
+/// Where the clip starts: somewhere in the middle of the song, past the intro.
+fn clip_start(duration: Option<f64>) -> u64 {
+    match duration {
+        Some(d) if d >= CLIP.as_secs_f64() * 3.0 => rand::thread_rng().gen_range((d * 0.2) as u64..(d * 0.6) as u64),
+        Some(_) => 0,
+        None => 30,
+    }
+}
+
+/// Play `CLIP` of `song` on the guild's call, outside the queue.
+async fn play_clip(ctx: &Context, guild_id: GuildId, song: &Song) -> Result<TrackHandle, Error> {
+    let call = songbird::get(ctx)
+        .await
+        .and_then(|m| m.get(guild_id))
+        .ok_or("I'm not in a voice channel any more.")?;
+    let cookie_args = config::current(ctx).await.music.cookie_args();
+    let out = tokio::process::Command::new("yt-dlp")
+        .kill_on_drop(true)
+        .args(&cookie_args)
+        .args(["--no-playlist", "-g", "-f", "bestaudio/best", &song.url])
+        .output()
+        .await?;
+    let media = String::from_utf8_lossy(&out.stdout).lines().next().map(str::to_string);
+    let Some(media) = media.filter(|_| out.status.success()) else {
+        return Err(format!("yt-dlp couldn't play {}", song.url).into());
+    };
+
+    let mut ffmpeg = std::process::Command::new("ffmpeg");
+    ffmpeg
+        .args(["-nostdin", "-ss", &clip_start(song.duration).to_string(), "-t", &CLIP.as_secs().to_string()])
+        .args(["-i", &media])
+        .args(["-vn", "-c:a", "pcm_s16le", "-f", "wav", "-ar", "48000", "-ac", "2", "pipe:1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let child = crate::music::spawn_child(ctx, guild_id, &mut ffmpeg).await?;
+    let input: songbird::input::Input = songbird::input::ChildContainer::from(child).into();
+    let handle = call.lock().await.play_input(input);
+    handle.make_playable_async().await?;
+    Ok(handle)
+}
+
+async fn award(ctx: &Context, guild_id: GuildId, user: UserId, points: u32) -> Result<(), Error> {
+    let db = storage::db(ctx).await?;
+    let (gid, uid) = (guild_id.get() as i64, user.get() as i64);
+    db.call(move |conn| {
+        conn.execute(
+            "INSERT INTO quiz_scores (guild_id, user_id, points) VALUES (?1, ?2, ?3)
+             ON CONFLICT (guild_id, user_id) DO UPDATE SET points = quiz_scores.points + excluded.points",
+            rusqlite::params![gid, uid, points],
+        )
+    })
+    .await?;
+    Ok(())
+}
+
+/// Who got a round's answers, and whether the quiz was stopped during it.
+#[derive(Default)]
+struct RoundResult {
+    title_by: Option<UserId>,
+    artist_by: Option<UserId>,
+    stopped: bool,
+}
+
+/// Take guesses in `channel` while the clip plays. The first member to name the title and the
+/// first to name the artist score a point each; one message can name both ("title - artist").
+async fn take_guesses(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel: ChannelId,
+    song: &Song,
+    stop: &Notify,
+    totals: &mut HashMap<UserId, u32>,
+) -> RoundResult {
+    let mut result = RoundResult::default();
+    let mut guesses = MessageCollector::new(ctx)
+        .channel_id(channel)
+        .timeout(CLIP)
+        .filter(|m| !m.author.bot)
+        .stream();
+    loop {
+        let message = tokio::select! {
+            message = guesses.next() => message,
+            _ = stop.notified() => {
+                result.stopped = true;
+                return result;
+            }
+        };
+        let Some(message) = message else { return result };
+        let mut parts = vec![message.content.as_str()];
+        for separator in [" - ", " by "] {
+            if let Some((a, b)) = message.content.split_once(separator) {
+                parts.extend([a, b]);
+            }
+        }
+        let mut points = 0;
+        if result.title_by.is_none() && parts.iter().any(|p| matches(p, &song.title)) {
+            result.title_by = Some(message.author.id);
+            points += 1;
+        }
+        if result.artist_by.is_none()
+            && let Some(artist) = &song.artist
+            && parts.iter().any(|p| matches(p, artist))
+        {
+            result.artist_by = Some(message.author.id);
+            points += 1;
+        }
+        if points > 0 {
+            *totals.entry(message.author.id).or_default() += points;
+            if let Err(e) = award(ctx, guild_id, message.author.id, points).await {
+                tracing::warn!("Failed to record quiz points: {e:?}");
+            }
+            let _ = message.react(&ctx.http, ReactionType::Unicode("✅".to_string())).await;
+        }
+        if result.title_by.is_some() && (song.artist.is_none() || result.artist_by.is_some()) {
+            return result;
+        }
+    }
+}
+
+fn reveal(theme: &Theme, round: usize, song: &Song, result: &RoundResult) -> serenity::CreateEmbed {
+    let named = |who: Option<UserId>| match who {
+        Some(user) => format!("<@{}>", user),
+        None => "nobody".to_string(),
+    };
+    let mut lines = vec![format!("It was **{}**{}", song.title, song.artist.as_ref().map(|a| format!(" by **{}**", a)).unwrap_or_default())];
+    lines.push(format!("Title: {}", named(result.title_by)));
+    if song.artist.is_some() {
+        lines.push(format!("Artist: {}", named(result.artist_by)));
+    }
+    lines.push(song.url.clone());
+    theme.embed().title(format!("Round {} answer", round)).description(lines.join("\n"))
+}
+
+fn standings(totals: &HashMap<UserId, u32>) -> String {
+    let mut totals: Vec<_> = totals.iter().collect();
+    totals.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    if totals.is_empty() {
+        return "Nobody scored this time.".to_string();
+    }
+    totals
+        .iter()
+        .enumerate()
+        .map(|(i, (user, points))| format!("`{}.` <@{}> — {} point(s)", i + 1, user, points))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Play the rounds, then post this game's standings. Runs in the background after `music quiz`.
+async fn run(ctx: Context, guild_id: GuildId, channel: ChannelId, songs: Vec<Song>, stop: Arc<Notify>) {
+    let theme = embeds::theme(&ctx, Some(guild_id)).await;
+    let mut totals: HashMap<UserId, u32> = HashMap::new();
+    let rounds = songs.len();
+    for (i, song) in songs.iter().enumerate() {
+        let round = i + 1;
+        let clip = match play_clip(&ctx, guild_id, song).await {
+            Ok(clip) => clip,
+            Err(e) => {
+                tracing::warn!("Quiz clip {} failed: {e:?}", song.url);
+                let embed = theme.embed().title(format!("Round {}/{}", round, rounds)).description("That song wouldn't play; skipping it.");
+                let _ = channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await;
+                continue;
+            }
+        };
+        let text = format!("Guess the song! Type the title and the artist here; you have {} seconds.", CLIP.as_secs());
+        let embed = theme.embed().title(format!("Music quiz — round {}/{}", round, rounds)).description(text);
+        let _ = channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await;
+
+        let result = take_guesses(&ctx, guild_id, channel, song, &stop, &mut totals).await;
+        let _ = clip.stop();
+        let _ = channel.send_message(&ctx.http, CreateMessage::new().embed(reveal(&theme, round, song, &result))).await;
+        if result.stopped || round == rounds {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(BETWEEN_ROUNDS) => {}
+            _ = stop.notified() => break,
+        }
+    }
+    // A stop already removed this game; don't remove a newer one
+    if let Some(store) = store(&ctx).await {
+        let mut games = store.lock().await;
+        if games.get(&guild_id).is_some_and(|s| Arc::ptr_eq(s, &stop)) {
+            games.remove(&guild_id);
+        }
+    }
+    let embed = theme.embed().title("Music quiz — final standings").description(standings(&totals));
+    let _ = channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await;
+}
+
+/// Guess-the-song: short clips from a playlist or genre, with points for title and artist
+#[poise::command(prefix_command, slash_command, guild_only, rename = "quiz")]
+pub async fn music_quiz(
+    ctx: Ctx<'_>,
+    #[description = "Playlist link or genre, or `stop`"] source: String,
+    #[description = "Number of songs (default 5)"]
+    #[min = 1]
+    #[max = 20]
+    rounds: Option<u32>,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let source = source.trim();
+
+    if source.eq_ignore_ascii_case("stop") {
+        let text = if stop(sctx, guild_id).await { "The quiz ends after this round." } else { "No quiz is running." };
+        ctx.say(text).await?;
+        return Ok(());
+    }
+    if config::current(sctx).await.music.player_backend == config::PlayerBackend::Lavalink {
+        ctx.say("The music quiz needs the built-in player (`music.player_backend` \"songbird\").").await?;
+        return Ok(());
+    }
+    if active(sctx, guild_id).await {
+        ctx.say("A quiz is already running here; `music quiz stop` ends it.").await?;
+        return Ok(());
+    }
+    if crate::ambient::active(sctx, guild_id).await.is_some() || player::status(sctx, guild_id).await.is_some() {
+        ctx.say("Music is playing; stop it first so the clips can be heard.").await?;
+        return Ok(());
+    }
+    if songbird::get(sctx).await.and_then(|m| m.get(guild_id)).is_none() {
+        ctx.say("I'm not in a voice channel; use `music join` first.").await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let mut picked = songs(sctx, source).await?;
+    picked.shuffle(&mut rand::thread_rng());
+    picked.truncate(rounds.unwrap_or(DEFAULT_ROUNDS) as usize);
+    if picked.is_empty() {
+        ctx.say("I couldn't find any songs there.").await?;
+        return Ok(());
+    }
+    let Some(store) = store(sctx).await else { return Ok(()) };
+    let stop = Arc::new(Notify::new());
+    {
+        let mut games = store.lock().await;
+        if games.contains_key(&guild_id) {
+            ctx.say("A quiz is already running here; `music quiz stop` ends it.").await?;
+            return Ok(());
+        }
+        games.insert(guild_id, stop.clone());
+    }
+    ctx.say(format!("Starting a {}-round music quiz. Listen closely!", picked.len())).await?;
+    tokio::spawn(run(sctx.clone(), guild_id, ctx.channel_id(), picked, stop));
+    Ok(())
+}
+
+/// The server's music quiz leaderboard
+#[poise::command(prefix_command, slash_command, guild_only, rename = "leaderboard")]
+pub async fn music_leaderboard(ctx: Ctx<'_>) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command only works in a guild")?;
+    let db = storage::db(sctx).await?;
+    let gid = guild_id.get() as i64;
+    let top: Vec<(i64, i64)> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT user_id, points FROM quiz_scores WHERE guild_id = ?1 ORDER BY points DESC, user_id LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(rusqlite::params![gid, LEADERBOARD_SIZE as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await?;
+    let description = if top.is_empty() {
+        "Nobody has scored yet; start a game with `music quiz <playlist|genre>`.".to_string()
+    } else {
+        top.iter()
+            .enumerate()
+            .map(|(i, (user, points))| format!("`{}.` <@{}> — {} point(s)", i + 1, user, points))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let embed = embeds::theme(sctx, Some(guild_id)).await.embed().title("Music quiz leaderboard").description(description);
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_come_from_the_video_title() {
+        assert_eq!(
+            answer("Daft Punk - Get Lucky (Official Video) ft. Pharrell Williams", Some("DaftPunkVEVO")),
+            ("Get Lucky".to_string(), Some("Daft Punk".to_string()))
+        );
+        assert_eq!(
+            answer("Bohemian Rhapsody [Remastered 2011]", Some("Queen - Topic")),
+            ("Bohemian Rhapsody".to_string(), Some("Queen".to_string()))
+        );
+    }
+
+    #[test]
+    fn guesses_allow_small_typos() {
+        assert!(matches("bohemian rapsody", "Bohemian Rhapsody"));
+        assert!(matches("beatles", "The Beatles"));
+        assert!(!matches("queen", "Bohemian Rhapsody"));
+        assert!(!matches("abc", "Abba"));
+    }
+}
@@ -0,0 +1,163 @@
+use poise::serenity_prelude as serenity;
+use rusqlite::OptionalExtension;
+use serenity::all::{Command, CreateCommand, GuildId};
+use serenity::prelude::*;
+
+use crate::config::{self, RegistrationMode};
+use crate::{features, storage, Error};
+
+/// What a sync did, for logs and `/admin register`.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub updated: Vec<String>,
+    pub unchanged: usize,
+    pub failed: Vec<String>,
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} updated, {} unchanged", self.updated.len(), self.unchanged)?;
+        if !self.failed.is_empty() {
+            write!(f, ", {} failed ({})", self.failed.len(), self.failed.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Bring every scope in line with the configured mode: the commands go to the global scope,
+/// every guild, or only the dev guild, and every other scope is emptied so nothing stale is left.
+/// Scopes whose command set hasn't changed since the last push are skipped unless `force`.
+pub async fn sync(
+    ctx: &Context,
+    commands: &[poise::Command<crate::Data, Error>],
+    force: bool,
+) -> Result<SyncReport, Error> {
+    let cfg = config::current(ctx).await;
+    let mode = cfg.registration.mode;
+    let dev_guild = cfg.registration.dev_guild;
+    if mode == RegistrationMode::Dev && dev_guild.is_none() {
+        return Err("`registration.mode` is \"dev\" but `registration.dev_guild` is not set".into());
+    }
+    let mut report = SyncReport::default();
+    let global = if mode == RegistrationMode::Global {
+        poise::builtins::create_application_commands(commands)
+    } else {
+        Vec::new()
+    };
+    record(&mut report, "global", sync_scope(ctx, None, global, force).await);
+    for gid in ctx.cache.guilds() {
+        let wanted = wants_commands(mode, dev_guild, gid);
+        let set = if wanted { guild_set(ctx, commands, gid).await } else { Vec::new() };
+        record(&mut report, &format!("guild {}", gid), sync_scope(ctx, Some(gid), set, force).await);
+    }
+    Ok(report)
+}
+
+/// Register in a guild the bot just joined (or whose features changed), when the mode gives
+/// guilds their own commands.
+pub async fn sync_guild(ctx: &Context, commands: &[poise::Command<crate::Data, Error>], gid: GuildId) -> Result<(), Error> {
+    let cfg = config::current(ctx).await;
+    if !wants_commands(cfg.registration.mode, cfg.registration.dev_guild, gid) {
+        return Ok(());
+    }
+    let set = guild_set(ctx, commands, gid).await;
+    sync_scope(ctx, Some(gid), set, false).await?;
+    Ok(())
+}
+
+/// Whether `gid` gets its own command set, so switching a feature off there hides its commands.
+/// With global registration every server sees the same list.
+pub async fn per_guild(ctx: &Context, gid: GuildId) -> bool {
+    let cfg = config::current(ctx).await;
+    wants_commands(cfg.registration.mode, cfg.registration.dev_guild, gid)
+}
+
+/// The commands for one guild's scope: everything but the features it switched off.
+async fn guild_set(ctx: &Context, commands: &[poise::Command<crate::Data, Error>], gid: GuildId) -> Vec<CreateCommand> {
+    let mut set = Vec::new();
+    for command in features::guild_commands(ctx, gid, commands).await {
+        set.extend(poise::builtins::create_application_commands(std::slice::from_ref(command)));
+    }
+    set
+}
+
+fn wants_commands(mode: RegistrationMode, dev_guild: Option<GuildId>, gid: GuildId) -> bool {
+    match mode {
+        RegistrationMode::Global => false,
+        RegistrationMode::Guild => true,
+        RegistrationMode::Dev => dev_guild == Some(gid),
+    }
+}
+
+fn record(report: &mut SyncReport, scope: &str, result: Result<bool, Error>) {
+    match result {
+        Ok(true) => report.updated.push(scope.to_string()),
+        Ok(false) => report.unchanged += 1,
+        Err(e) => {
+            tracing::warn!("Failed to register commands ({}): {e:?}", scope);
+            report.failed.push(scope.to_string());
+        }
+    }
+}
+
+/// Push `set` to one scope if its checksum differs from the last push. Returns whether it pushed.
+async fn sync_scope(ctx: &Context, gid: Option<GuildId>, set: Vec<CreateCommand>, force: bool) -> Result<bool, Error> {
+    let scope = match gid {
+        Some(gid) => format!("guild:{}", gid),
+        None => "global".to_string(),
+    };
+    let checksum = format!("{:016x}", fnv1a(serde_json::to_string(&set)?.as_bytes()));
+    let db = storage::db(ctx).await?;
+
+    let key = scope.clone();
+    let previous: Option<String> = db
+        .call(move |conn| {
+            conn.query_row("SELECT checksum FROM command_registrations WHERE scope = ?1", [key], |row| row.get(0))
+                .optional()
+        })
+        .await?;
+    let unchanged = match &previous {
+        Some(prev) => *prev == checksum,
+        // Never pushed by this database: a guild meant to stay empty only needs a cleanup if it
+        // still has commands from an older mode, which one read is cheaper than a write to learn
+        None => match gid {
+            Some(gid) if set.is_empty() && !force => gid.get_commands(&ctx.http).await?.is_empty(),
+            _ => false,
+        },
+    };
+    if unchanged && !force {
+        if previous.is_none() {
+            store_checksum(&db, scope, checksum).await?;
+        }
+        return Ok(false);
+    }
+
+    match gid {
+        Some(gid) => {
+            gid.set_commands(&ctx.http, set).await?;
+        }
+        None => {
+            Command::set_global_commands(&ctx.http, set).await?;
+        }
+    }
+    store_checksum(&db, scope, checksum).await?;
+    Ok(true)
+}
+
+async fn store_checksum(db: &storage::Db, scope: String, checksum: String) -> Result<(), Error> {
+    let now = serenity::Timestamp::now().unix_timestamp();
+    db.call(move |conn| {
+        conn.execute(
+            "INSERT INTO command_registrations (scope, checksum, registered_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (scope) DO UPDATE SET checksum = excluded.checksum, registered_at = excluded.registered_at",
+            rusqlite::params![scope, checksum, now],
+        )
+    })
+    .await?;
+    Ok(())
+}
+
+/// FNV-1a: stable across builds, unlike `DefaultHasher`, so stored checksums stay comparable.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3))
+}
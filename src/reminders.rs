@@ -0,0 +1,237 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::builder::{CreateAllowedMentions, CreateEmbed, CreateMessage};
+use serenity::model::id::{ChannelId, UserId};
+use serenity::prelude::*;
+
+use crate::moderation::{format_duration, parse_duration};
+use crate::scheduler::{self, JobFuture};
+use crate::{Ctx, Error};
+
+/// Scheduler job delivering a reminder.
+pub const REMINDER_JOB: &str = "reminder";
+
+/// Pending reminders a single user may have at once.
+const MAX_PER_USER: usize = 25;
+
+/// Reminders delivered later than this past their due time mention the delay.
+const LATE_AFTER_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReminderJob {
+    user_id: u64,
+    /// Channel to mention the user in; `None` means DM
+    channel_id: Option<u64>,
+    text: String,
+    /// Unix timestamps (seconds)
+    created_at: i64,
+    due_at: i64,
+}
+
+pub fn run_reminder_job(ctx: &Context, payload: serde_json::Value) -> JobFuture<'_> {
+    Box::pin(async move {
+        let job: ReminderJob = serde_json::from_value(payload)?;
+        let uid = UserId::new(job.user_id);
+        let now = serenity::Timestamp::now().unix_timestamp();
+
+        let mut description = job.text.clone();
+        if now - job.due_at > LATE_AFTER_SECS {
+            description.push_str(&format!("\n\n*Delivered late, this was due <t:{}:R>.*", job.due_at));
+        }
+        let embed = CreateEmbed::new()
+            .title("Reminder")
+            .description(description)
+            .footer(serenity::CreateEmbedFooter::new("Set"))
+            .timestamp(serenity::Timestamp::from_unix_timestamp(job.created_at)?)
+            .color(crate::EMBED_COLOR);
+
+        if let Some(channel) = job.channel_id {
+            let msg = CreateMessage::new()
+                .content(format!("<@{}>", uid))
+                .embed(embed.clone())
+                .allowed_mentions(CreateAllowedMentions::new().users(vec![uid]));
+            match ChannelId::new(channel).send_message(&ctx.http, msg).await {
+                Ok(_) => return Ok(()),
+                // Channel gone or no access: fall back to a DM
+                Err(e) => tracing::warn!("Reminder for {} could not be posted in {}: {e:?}", uid, channel),
+            }
+        }
+
+        let dm = uid.create_dm_channel(&ctx.http).await?;
+        dm.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+        Ok(())
+    })
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Delivery {
+    #[name = "here"]
+    Here,
+    #[name = "dm"]
+    Dm,
+}
+
+/// Reminders
+#[poise::command(prefix_command, slash_command, subcommands("remind_me", "remind_list", "remind_cancel"))]
+pub async fn remind(ctx: Ctx<'_>) -> Result<(), Error> {
+    list(ctx).await
+}
+
+/// Remind yourself about something later
+#[poise::command(prefix_command, slash_command, rename = "me")]
+pub async fn remind_me(
+    ctx: Ctx<'_>,
+    #[description = "When, e.g. 10m, 2h, 1d"] duration: String,
+    #[description = "Where to deliver it (defaults to this channel, or DM outside servers)"] delivery: Option<Delivery>,
+    #[description = "What to remind you about"]
+    #[rest]
+    text: String,
+) -> Result<(), Error> {
+    // Allow the natural prefix form `remind me in 10m ...`
+    let (duration, text) = if duration.eq_ignore_ascii_case("in") {
+        let mut parts = text.trim().splitn(2, char::is_whitespace);
+        let d = parts.next().unwrap_or_default().to_string();
+        (d, parts.next().unwrap_or_default().trim().to_string())
+    } else {
+        (duration, text.trim().to_string())
+    };
+
+    let Some(dur) = parse_duration(&duration) else {
+        ctx.say("Invalid duration. Use something like `10m`, `2h30m` or `3d`.").await?;
+        return Ok(());
+    };
+    if text.is_empty() {
+        ctx.say("What should I remind you about?").await?;
+        return Ok(());
+    }
+
+    let user_id = ctx.author().id.get();
+    let sctx = ctx.serenity_context();
+    let existing = scheduler::pending(sctx, REMINDER_JOB)
+        .await?
+        .into_iter()
+        .filter(|j| owned_by(&j.payload, user_id))
+        .count();
+    if existing >= MAX_PER_USER {
+        ctx.say(format!("You already have {} pending reminders. Cancel some first.", MAX_PER_USER)).await?;
+        return Ok(());
+    }
+
+    let dm = match delivery {
+        Some(Delivery::Dm) => true,
+        Some(Delivery::Here) => false,
+        None => ctx.guild_id().is_none(),
+    };
+    let now = serenity::Timestamp::now().unix_timestamp();
+    let due_at = now + dur.as_secs() as i64;
+    let job = ReminderJob {
+        user_id,
+        channel_id: (!dm).then(|| ctx.channel_id().get()),
+        text,
+        created_at: now,
+        due_at,
+    };
+    let id = scheduler::schedule(sctx, REMINDER_JOB, due_at, &job).await?;
+
+    let whereto = if dm { "by DM" } else { "here" };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "Reminder #{} set for <t:{}:f> (in {}), I'll remind you {}.",
+                id,
+                due_at,
+                format_duration(dur),
+                whereto
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// List your pending reminders
+#[poise::command(prefix_command, slash_command, rename = "list")]
+pub async fn remind_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    list(ctx).await
+}
+
+async fn list(ctx: Ctx<'_>) -> Result<(), Error> {
+    let user_id = ctx.author().id.get();
+    let mut mine: Vec<(u64, ReminderJob)> = scheduler::pending(ctx.serenity_context(), REMINDER_JOB)
+        .await?
+        .into_iter()
+        .filter_map(|j| serde_json::from_value::<ReminderJob>(j.payload).ok().map(|r| (j.id, r)))
+        .filter(|(_, r)| r.user_id == user_id)
+        .collect();
+    if mine.is_empty() {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("You have no pending reminders. Set one with `remind me <duration> <text>`.")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+    mine.sort_by_key(|(_, r)| r.due_at);
+
+    let lines = mine
+        .iter()
+        .map(|(id, r)| {
+            let dest = if r.channel_id.is_some() { "" } else { " (DM)" };
+            format!("**#{}** <t:{}:R>{}: {}", id, r.due_at, dest, crate::embeds::truncate(&r.text, 80))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let embed = CreateEmbed::new()
+        .title(format!("Your reminders ({})", mine.len()))
+        .description(lines)
+        .color(crate::EMBED_COLOR);
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Cancel one of your reminders
+#[poise::command(prefix_command, slash_command, rename = "cancel")]
+pub async fn remind_cancel(
+    ctx: Ctx<'_>,
+    #[description = "Reminder number from `remind list`"] id: u64,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let user_id = ctx.author().id.get();
+    let owned = scheduler::pending(sctx, REMINDER_JOB)
+        .await?
+        .iter()
+        .any(|j| j.id == id && owned_by(&j.payload, user_id));
+
+    let reply = if owned && scheduler::cancel(sctx, id).await? {
+        format!("Cancelled reminder #{}.", id)
+    } else {
+        format!("You have no pending reminder #{}.", id)
+    };
+    ctx.send(poise::CreateReply::default().content(reply).ephemeral(true)).await?;
+    Ok(())
+}
+
+fn owned_by(payload: &serde_json::Value, user_id: u64) -> bool {
+    payload.get("user_id").and_then(|v| v.as_u64()) == Some(user_id)
+}
+
+/// `user`'s pending reminders, for `data export`.
+pub async fn export_user(ctx: &Context, user: UserId) -> Result<serde_json::Value, Error> {
+    let mine: Vec<serde_json::Value> = scheduler::pending(ctx, REMINDER_JOB)
+        .await?
+        .into_iter()
+        .filter(|j| owned_by(&j.payload, user.get()))
+        .map(|j| j.payload)
+        .collect();
+    Ok(mine.into())
+}
+
+/// Cancel all of `user`'s reminders, for `data delete`. Returns how many there were.
+pub async fn delete_user(ctx: &Context, user: UserId) -> Result<usize, Error> {
+    scheduler::cancel_where(ctx, REMINDER_JOB, |payload| owned_by(payload, user.get())).await
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![remind()]
+}
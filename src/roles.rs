@@ -0,0 +1,480 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ButtonStyle, ComponentInteraction, ComponentInteractionDataKind};
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditMessage,
+};
+use serenity::model::guild::Role;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::{Ctx, Error};
+
+pub const ROLE_PANELS_PATH: &str = "role_panels.json";
+
+/// Discord allows 25 buttons (5 rows of 5) or 25 select options per message.
+const MAX_PANEL_ROLES: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelStyle {
+    #[name = "buttons"]
+    Buttons,
+    #[name = "select"]
+    Select,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RolePanel {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub style: PanelStyle,
+    pub title: String,
+    pub roles: Vec<u64>,
+}
+
+/// message id -> panel
+pub type RolePanelData = HashMap<u64, RolePanel>;
+
+pub struct RolePanelStore;
+impl TypeMapKey for RolePanelStore {
+    type Value = Arc<Mutex<RolePanelData>>;
+}
+
+async fn load_disk() -> Result<RolePanelData, Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::load_json(&crate::storage::data_path(ROLE_PANELS_PATH)).await
+}
+
+async fn save_disk(data: &RolePanelData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(ROLE_PANELS_PATH), data).await
+}
+
+pub async fn ensure_role_panel_store() -> Result<Arc<Mutex<RolePanelData>>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = load_disk().await?;
+    Ok(Arc::new(Mutex::new(data)))
+}
+
+async fn role_panel_store(ctx: &Context) -> Result<Arc<Mutex<RolePanelData>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<RolePanelStore>()
+        .cloned()
+        .ok_or_else(|| "Role panel store not initialised".into())
+}
+
+/// Drop the role panels posted in `gid`.
+pub async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<(), Error> {
+    let store = role_panel_store(ctx).await?;
+    let mut data = store.lock().await;
+    let before = data.len();
+    data.retain(|_, panel| panel.guild_id != gid.get());
+    if data.len() != before {
+        save_disk(&data).await?;
+    }
+    Ok(())
+}
+
+async fn guild_roles(ctx: &Context, gid: GuildId) -> Result<HashMap<RoleId, Role>, Error> {
+    if let Some(roles) = ctx.cache.guild(gid).map(|g| g.roles.clone()) {
+        return Ok(roles);
+    }
+    Ok(gid.roles(&ctx.http).await?)
+}
+
+fn highest_position(roles: &HashMap<RoleId, Role>, member_roles: &[RoleId]) -> u16 {
+    member_roles.iter().filter_map(|r| roles.get(r)).map(|r| r.position).max().unwrap_or(0)
+}
+
+/// Check the bot (and optionally the member configuring the panel) sit above `role`.
+async fn check_assignable(
+    ctx: &Context,
+    gid: GuildId,
+    roles: &HashMap<RoleId, Role>,
+    role: RoleId,
+    invoker: Option<UserId>,
+) -> Result<(), String> {
+    let Some(target) = roles.get(&role) else {
+        return Err(format!("<@&{}> no longer exists.", role));
+    };
+    if role.get() == gid.get() || target.managed {
+        return Err(format!("<@&{}> is managed by Discord or an integration and can't be self-assigned.", role));
+    }
+    if target.permissions.administrator() {
+        return Err(format!("<@&{}> grants Administrator and can't be self-assigned.", role));
+    }
+
+    let bot_id = ctx.cache.current_user().id;
+    let bot = gid.member(ctx, bot_id).await.map_err(|e| format!("Couldn't look up my roles: {}", e))?;
+    if highest_position(roles, &bot.roles) <= target.position {
+        return Err(format!("My highest role must be above <@&{}> to assign it.", role));
+    }
+
+    if let Some(uid) = invoker {
+        let owner = ctx.cache.guild(gid).map(|g| g.owner_id);
+        if owner != Some(uid) {
+            let member = gid.member(ctx, uid).await.map_err(|e| format!("Couldn't look up your roles: {}", e))?;
+            if highest_position(roles, &member.roles) <= target.position {
+                return Err(format!("Your highest role must be above <@&{}> to hand it out.", role));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn panel_embed(panel: &RolePanel) -> CreateEmbed {
+    let lines = panel.roles.iter().map(|r| format!("<@&{}>", r)).collect::<Vec<_>>().join("\n");
+    let hint = match panel.style {
+        PanelStyle::Buttons => "Click a button to add or remove the role.",
+        PanelStyle::Select => "Pick your roles from the menu; unselected ones are removed.",
+    };
+    CreateEmbed::new()
+        .title(&panel.title)
+        .description(format!("{}\n\n{}", lines, hint))
+        .color(crate::EMBED_COLOR)
+}
+
+fn panel_components(panel: &RolePanel, roles: &HashMap<RoleId, Role>) -> Vec<CreateActionRow> {
+    let name = |r: &u64| {
+        roles
+            .get(&RoleId::new(*r))
+            .map(|role| role.name.clone())
+            .unwrap_or_else(|| format!("Role {}", r))
+    };
+    match panel.style {
+        PanelStyle::Buttons => panel
+            .roles
+            .chunks(5)
+            .map(|row| {
+                CreateActionRow::Buttons(
+                    row.iter()
+                        .map(|r| {
+                            CreateButton::new(crate::components::custom_id("roles", &RoleComponent::Toggle(*r), None))
+                                .label(name(r))
+                                .style(ButtonStyle::Secondary)
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+        PanelStyle::Select => {
+            let options = panel
+                .roles
+                .iter()
+                .map(|r| CreateSelectMenuOption::new(name(r), r.to_string()))
+                .collect();
+            let id = crate::components::custom_id("roles", &RoleComponent::Select, None);
+            let menu = CreateSelectMenu::new(id, CreateSelectMenuKind::String { options })
+                .placeholder("Choose your roles")
+                .min_values(0)
+                .max_values(panel.roles.len() as u8);
+            vec![CreateActionRow::SelectMenu(menu)]
+        }
+    }
+}
+
+/// `custom_id` payload of a role panel component.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RoleComponent {
+    Toggle(u64),
+    Select,
+}
+
+impl RoleComponent {
+    /// Panels posted before typed ids used `toggle:<role>` and `select`; they stay in channels.
+    fn parse(payload: &str) -> Option<Self> {
+        if let Some(component) = crate::components::payload(payload) {
+            return Some(component);
+        }
+        match payload {
+            "select" => Some(RoleComponent::Select),
+            _ => payload.strip_prefix("toggle:")?.parse().ok().map(RoleComponent::Toggle),
+        }
+    }
+}
+
+/// `roles` component handler: a click on a role panel.
+pub fn handle_component<'a>(
+    ctx: &'a Context,
+    mc: &'a ComponentInteraction,
+    payload: &'a str,
+) -> serenity::futures::future::BoxFuture<'a, ()> {
+    Box::pin(async move {
+        let reply = apply_selection(ctx, mc, payload).await.unwrap_or_else(|msg| msg);
+        let _ = mc
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new().content(reply).ephemeral(true),
+                ),
+            )
+            .await;
+    })
+}
+
+async fn apply_selection(ctx: &Context, mc: &ComponentInteraction, payload: &str) -> Result<String, String> {
+    let gid = mc.guild_id.ok_or("Role panels only work in servers.")?;
+    let member = mc.member.as_ref().ok_or("Couldn't read your roles.")?;
+    let panel = {
+        let store = role_panel_store(ctx).await.map_err(|e| e.to_string())?;
+        let data = store.lock().await;
+        data.get(&mc.message.id.get()).cloned().ok_or("This role panel is no longer active.")?
+    };
+
+    // Work out which panel roles the member should end up with
+    let wanted: Vec<u64> = match (&mc.data.kind, RoleComponent::parse(payload)) {
+        (ComponentInteractionDataKind::Button, Some(RoleComponent::Toggle(role))) => {
+            let has = member.roles.contains(&RoleId::new(role));
+            panel
+                .roles
+                .iter()
+                .copied()
+                .filter(|r| if *r == role { !has } else { member.roles.contains(&RoleId::new(*r)) })
+                .collect()
+        }
+        (ComponentInteractionDataKind::StringSelect { values }, Some(RoleComponent::Select)) => {
+            values.iter().filter_map(|v| v.parse::<u64>().ok()).collect()
+        }
+        _ => return Err("Unsupported component.".to_string()),
+    };
+
+    let roles = guild_roles(ctx, gid).await.map_err(|e| e.to_string())?;
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut problems = Vec::new();
+    for r in &panel.roles {
+        let role = RoleId::new(*r);
+        let has = member.roles.contains(&role);
+        let want = wanted.contains(r);
+        if has == want {
+            continue;
+        }
+        if let Err(msg) = check_assignable(ctx, gid, &roles, role, None).await {
+            problems.push(msg);
+            continue;
+        }
+        let result = if want {
+            ctx.http.add_member_role(gid, member.user.id, role, Some("Role panel")).await
+        } else {
+            ctx.http.remove_member_role(gid, member.user.id, role, Some("Role panel")).await
+        };
+        match result {
+            Ok(()) if want => added.push(format!("<@&{}>", r)),
+            Ok(()) => removed.push(format!("<@&{}>", r)),
+            Err(e) => problems.push(format!("Failed to update <@&{}>: {}", r, e)),
+        }
+    }
+
+    let mut lines = Vec::new();
+    if !added.is_empty() {
+        lines.push(format!("Added {}", added.join(", ")));
+    }
+    if !removed.is_empty() {
+        lines.push(format!("Removed {}", removed.join(", ")));
+    }
+    lines.extend(problems);
+    if lines.is_empty() {
+        lines.push("No changes.".to_string());
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Forget a panel whose message was deleted.
+pub async fn forget_panel(ctx: &Context, message_id: MessageId) {
+    let Ok(store) = role_panel_store(ctx).await else { return };
+    let mut data = store.lock().await;
+    if data.remove(&message_id.get()).is_some()
+        && let Err(e) = save_disk(&data).await
+    {
+        tracing::warn!("Failed to save role panels: {e:?}");
+    }
+}
+
+/// Self-assignable roles
+#[poise::command(prefix_command, slash_command, guild_only, subcommands("roles_panel"))]
+pub async fn roles(_ctx: Ctx<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Manage role panels
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "panel",
+    subcommands("roles_panel_create", "roles_panel_add", "roles_panel_remove"),
+    required_permissions = "MANAGE_ROLES",
+    required_bot_permissions = "MANAGE_ROLES",
+    default_member_permissions = "MANAGE_ROLES"
+)]
+pub async fn roles_panel(_ctx: Ctx<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Post a panel members can use to pick roles
+#[poise::command(prefix_command, slash_command, guild_only, rename = "create")]
+#[allow(clippy::too_many_arguments)]
+pub async fn roles_panel_create(
+    ctx: Ctx<'_>,
+    #[description = "Buttons or a select menu"] style: PanelStyle,
+    #[description = "Panel title"] title: String,
+    #[description = "Role"] role1: serenity::Role,
+    #[description = "Role"] role2: Option<serenity::Role>,
+    #[description = "Role"] role3: Option<serenity::Role>,
+    #[description = "Role"] role4: Option<serenity::Role>,
+    #[description = "Role"] role5: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    // Fetching roles and members, posting the panel and saving it can outlast the interaction's
+    // three seconds; the confirmation is private, so the deferral is too
+    ctx.defer_ephemeral().await?;
+    let sctx = ctx.serenity_context();
+    let mut picked: Vec<u64> = Vec::new();
+    for r in [Some(role1), role2, role3, role4, role5].into_iter().flatten() {
+        if !picked.contains(&r.id.get()) {
+            picked.push(r.id.get());
+        }
+    }
+
+    let roles = guild_roles(sctx, guild_id).await?;
+    for r in &picked {
+        if let Err(msg) = check_assignable(sctx, guild_id, &roles, RoleId::new(*r), Some(ctx.author().id)).await {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    }
+
+    let panel = RolePanel {
+        guild_id: guild_id.get(),
+        channel_id: ctx.channel_id().get(),
+        style,
+        title,
+        roles: picked,
+    };
+    let msg = ctx
+        .channel_id()
+        .send_message(
+            ctx.http(),
+            CreateMessage::new().embed(panel_embed(&panel)).components(panel_components(&panel, &roles)),
+        )
+        .await?;
+
+    let store = role_panel_store(sctx).await?;
+    {
+        let mut data = store.lock().await;
+        data.insert(msg.id.get(), panel);
+        save_disk(&data).await?;
+    }
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "Role panel created (message id `{}`). Use `roles panel add {} <role>` to add more roles.",
+                msg.id, msg.id
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Add a role to an existing panel
+#[poise::command(prefix_command, slash_command, guild_only, rename = "add")]
+pub async fn roles_panel_add(
+    ctx: Ctx<'_>,
+    #[description = "Panel message id"] message_id: String,
+    #[description = "Role to add"] role: serenity::Role,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    ctx.defer().await?;
+    let roles = guild_roles(ctx.serenity_context(), guild_id).await?;
+    if let Err(msg) = check_assignable(ctx.serenity_context(), guild_id, &roles, role.id, Some(ctx.author().id)).await {
+        ctx.say(msg).await?;
+        return Ok(());
+    }
+    edit_panel(ctx, &message_id, |panel| {
+        if panel.roles.contains(&role.id.get()) {
+            return Err(format!("<@&{}> is already on that panel.", role.id));
+        }
+        if panel.roles.len() >= MAX_PANEL_ROLES {
+            return Err(format!("A panel can hold at most {} roles.", MAX_PANEL_ROLES));
+        }
+        panel.roles.push(role.id.get());
+        Ok(format!("Added <@&{}> to the panel.", role.id))
+    })
+    .await
+}
+
+/// Remove a role from an existing panel
+#[poise::command(prefix_command, slash_command, guild_only, rename = "remove")]
+pub async fn roles_panel_remove(
+    ctx: Ctx<'_>,
+    #[description = "Panel message id"] message_id: String,
+    #[description = "Role to remove"] role: serenity::Role,
+) -> Result<(), Error> {
+    edit_panel(ctx, &message_id, |panel| {
+        if !panel.roles.contains(&role.id.get()) {
+            return Err(format!("<@&{}> isn't on that panel.", role.id));
+        }
+        if panel.roles.len() == 1 {
+            return Err("A panel needs at least one role; delete the message to remove the panel.".to_string());
+        }
+        panel.roles.retain(|r| *r != role.id.get());
+        Ok(format!("Removed <@&{}> from the panel.", role.id))
+    })
+    .await
+}
+
+async fn edit_panel(
+    ctx: Ctx<'_>,
+    message_id: &str,
+    f: impl FnOnce(&mut RolePanel) -> Result<String, String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    ctx.defer().await?;
+    let Ok(mid) = message_id.trim().parse::<u64>() else {
+        ctx.say("That isn't a message id.").await?;
+        return Ok(());
+    };
+    let sctx = ctx.serenity_context();
+    let store = role_panel_store(sctx).await?;
+    let (panel, reply) = {
+        let mut data = store.lock().await;
+        let Some(panel) = data.get_mut(&mid).filter(|p| p.guild_id == guild_id.get()) else {
+            drop(data);
+            ctx.say("No role panel with that message id in this server.").await?;
+            return Ok(());
+        };
+        match f(panel) {
+            Ok(reply) => {
+                let panel = panel.clone();
+                save_disk(&data).await?;
+                (panel, reply)
+            }
+            Err(msg) => {
+                drop(data);
+                ctx.say(msg).await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let roles = guild_roles(sctx, guild_id).await?;
+    ChannelId::new(panel.channel_id)
+        .edit_message(
+            ctx.http(),
+            MessageId::new(mid),
+            EditMessage::new().embed(panel_embed(&panel)).components(panel_components(&panel, &roles)),
+        )
+        .await?;
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![roles()]
+}
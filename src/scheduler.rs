@@ -0,0 +1,269 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::Error;
+
+pub const SCHEDULER_PATH: &str = "scheduled_jobs.json";
+
+/// How often the scheduler looks for due jobs.
+const TICK: Duration = Duration::from_secs(5);
+
+/// Failed runs are retried with exponential backoff starting here.
+const RETRY_BASE_SECS: i64 = 30;
+
+/// A job that has failed this many times in a row is dropped (recurring jobs skip to their next run).
+const MAX_ATTEMPTS: u32 = 5;
+
+pub type JobFuture<'a> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+/// Runs a job given its stored payload. Handlers must be idempotent: a job is only removed after
+/// its handler returns, so a crash mid-run means it runs again on the next start.
+pub type JobHandler = for<'a> fn(&'a Context, Value) -> JobFuture<'a>;
+
+/// Maps job names (as stored on disk) to their handlers.
+#[derive(Default)]
+pub struct Registry {
+    handlers: HashMap<&'static str, JobHandler>,
+}
+
+impl Registry {
+    pub fn register(&mut self, name: &'static str, handler: JobHandler) {
+        self.handlers.insert(name, handler);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredJob")]
+pub struct Job {
+    pub id: u64,
+    /// Handler name, see [`Registry::register`]
+    pub job: String,
+    /// Unix timestamp (seconds)
+    pub run_at: i64,
+    #[serde(default)]
+    pub payload: Value,
+    /// Re-run every this many seconds after a successful run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_secs: Option<u64>,
+    /// Consecutive failed runs
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// A job as read from disk. Jobs written before the handler registry kept their fields next to
+/// `job` (`{"job": "unban", "guild_id": .., "user_id": ..}`) instead of under `payload`.
+#[derive(Deserialize)]
+struct StoredJob {
+    id: u64,
+    job: String,
+    run_at: i64,
+    #[serde(default)]
+    payload: Value,
+    #[serde(default)]
+    repeat_secs: Option<u64>,
+    #[serde(default)]
+    attempts: u32,
+    #[serde(flatten)]
+    rest: serde_json::Map<String, Value>,
+}
+
+impl From<StoredJob> for Job {
+    fn from(stored: StoredJob) -> Self {
+        let payload = match stored.payload {
+            Value::Null if !stored.rest.is_empty() => Value::Object(stored.rest),
+            payload => payload,
+        };
+        Job {
+            id: stored.id,
+            job: stored.job,
+            run_at: stored.run_at,
+            payload,
+            repeat_secs: stored.repeat_secs,
+            attempts: stored.attempts,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SchedulerData {
+    #[serde(default)]
+    next_id: u64,
+    #[serde(default)]
+    jobs: Vec<Job>,
+}
+
+pub struct SchedulerStore;
+impl TypeMapKey for SchedulerStore {
+    type Value = Arc<Mutex<SchedulerData>>;
+}
+
+async fn load_disk() -> Result<SchedulerData, Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::load_json(&crate::storage::data_path(SCHEDULER_PATH)).await
+}
+
+async fn save_disk(data: &SchedulerData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(SCHEDULER_PATH), data).await
+}
+
+pub async fn ensure_scheduler_store() -> Result<Arc<Mutex<SchedulerData>>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = load_disk().await?;
+    Ok(Arc::new(Mutex::new(data)))
+}
+
+async fn scheduler_store(ctx: &Context) -> Result<Arc<Mutex<SchedulerData>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<SchedulerStore>()
+        .cloned()
+        .ok_or_else(|| "Scheduler store not initialised".into())
+}
+
+async fn insert(
+    ctx: &Context,
+    job: &str,
+    run_at: i64,
+    payload: Value,
+    repeat_secs: Option<u64>,
+) -> Result<u64, Error> {
+    let store = scheduler_store(ctx).await?;
+    let mut data = store.lock().await;
+    data.next_id += 1;
+    let id = data.next_id;
+    data.jobs.push(Job { id, job: job.to_string(), run_at, payload, repeat_secs, attempts: 0 });
+    save_disk(&data).await?;
+    Ok(id)
+}
+
+/// Persist a one-off job to run at `run_at` (unix seconds) and return its id.
+pub async fn schedule(ctx: &Context, job: &str, run_at: i64, payload: impl Serialize) -> Result<u64, Error> {
+    insert(ctx, job, run_at, serde_json::to_value(payload)?, None).await
+}
+
+/// Make sure a recurring `job` exists, running every `every` from now on. Meant for periodic
+/// housekeeping registered at startup: if one is already persisted it's left as is.
+pub async fn ensure_recurring(ctx: &Context, job: &str, every: Duration, payload: impl Serialize) -> Result<(), Error> {
+    let store = scheduler_store(ctx).await?;
+    if store.lock().await.jobs.iter().any(|j| j.job == job && j.repeat_secs.is_some()) {
+        return Ok(());
+    }
+    let every = every.as_secs().max(1);
+    let first_run = serenity::Timestamp::now().unix_timestamp() + every as i64;
+    insert(ctx, job, first_run, serde_json::to_value(payload)?, Some(every)).await?;
+    Ok(())
+}
+
+/// Pending jobs with the given handler name.
+pub async fn pending(ctx: &Context, job: &str) -> Result<Vec<Job>, Error> {
+    let store = scheduler_store(ctx).await?;
+    let data = store.lock().await;
+    Ok(data.jobs.iter().filter(|j| j.job == job).cloned().collect())
+}
+
+/// Drop every pending `job` whose payload matches `pred`, returning how many were removed.
+pub async fn cancel_where(ctx: &Context, job: &str, pred: impl Fn(&Value) -> bool) -> Result<usize, Error> {
+    let store = scheduler_store(ctx).await?;
+    let mut data = store.lock().await;
+    let before = data.jobs.len();
+    data.jobs.retain(|j| j.job != job || !pred(&j.payload));
+    let removed = before - data.jobs.len();
+    if removed > 0 {
+        save_disk(&data).await?;
+    }
+    Ok(removed)
+}
+
+/// Drop a pending job by id. Returns false if there was no such job.
+pub async fn cancel(ctx: &Context, id: u64) -> Result<bool, Error> {
+    let store = scheduler_store(ctx).await?;
+    let mut data = store.lock().await;
+    let before = data.jobs.len();
+    data.jobs.retain(|j| j.id != id);
+    let removed = data.jobs.len() != before;
+    if removed {
+        save_disk(&data).await?;
+    }
+    Ok(removed)
+}
+
+/// Spawn the background loop that runs due jobs. Jobs that came due while the bot was offline
+/// run on the first tick after startup.
+pub fn start(ctx: Context, registry: Registry) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            let Ok(store) = scheduler_store(&ctx).await else { continue };
+            let now = serenity::Timestamp::now().unix_timestamp();
+            let due: Vec<Job> = {
+                let data = store.lock().await;
+                data.jobs.iter().filter(|j| j.run_at <= now).cloned().collect()
+            };
+            for job in due {
+                let result = match registry.handlers.get(job.job.as_str()) {
+                    Some(handler) => handler(&ctx, job.payload.clone()).await,
+                    None => Err(format!("no handler registered for job `{}`", job.job).into()),
+                };
+                if let Err(e) = &result {
+                    tracing::warn!("Scheduled job {} ({}) failed: {e:?}", job.id, job.job);
+                }
+                finish(&store, &job, result.is_ok()).await;
+            }
+        }
+    });
+}
+
+/// Remove, reschedule or back off a job after it ran.
+async fn finish(store: &Mutex<SchedulerData>, job: &Job, ok: bool) {
+    let now = serenity::Timestamp::now().unix_timestamp();
+    let mut data = store.lock().await;
+    let Some(pos) = data.jobs.iter().position(|j| j.id == job.id) else { return };
+
+    if !ok && job.attempts + 1 < MAX_ATTEMPTS {
+        let entry = &mut data.jobs[pos];
+        entry.attempts += 1;
+        entry.run_at = now + RETRY_BASE_SECS * (1 << (entry.attempts - 1));
+    } else if let Some(every) = job.repeat_secs {
+        // Skip runs missed while offline instead of firing them all at once
+        let every = every as i64;
+        let entry = &mut data.jobs[pos];
+        entry.attempts = 0;
+        if entry.run_at <= now {
+            entry.run_at += ((now - entry.run_at) / every + 1) * every;
+        }
+    } else {
+        if !ok {
+            tracing::warn!("Dropping scheduled job {} ({}) after {} attempts", job.id, job.job, MAX_ATTEMPTS);
+        }
+        data.jobs.remove(pos);
+    }
+
+    if let Err(e) = save_disk(&data).await {
+        tracing::warn!("Failed to save scheduled jobs: {e:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_jobs_from_before_the_registry() {
+        let data: SchedulerData = serde_json::from_str(
+            r#"{ "next_id": 1, "jobs": [{ "id": 1, "run_at": 1700000000, "job": "unban", "guild_id": 10, "user_id": 20 }] }"#,
+        )
+        .unwrap();
+        let job = &data.jobs[0];
+        assert_eq!(job.job, "unban");
+        assert_eq!(job.payload, serde_json::json!({ "guild_id": 10, "user_id": 20 }));
+        assert_eq!((job.repeat_secs, job.attempts), (None, 0));
+    }
+}
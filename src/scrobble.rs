@@ -0,0 +1,520 @@
+use md5::{Digest, Md5};
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use reqwest::Client;
+use serde_json::json;
+use serenity::all::{ButtonStyle, ComponentInteractionCollector, GuildId, UserId};
+use serenity::builder::{CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::config::{self, ScrobbleConfig};
+use crate::events::{self, PlayerEvent};
+use crate::{storage, Ctx, Error};
+
+const LASTFM_API: &str = "https://ws.audioscrobbler.com/2.0/";
+/// How long `scrobble link lastfm` waits for the user to allow access on Last.fm.
+const LINK_TIMEOUT: Duration = Duration::from_secs(300);
+/// Last.fm and ListenBrainz only take tracks longer than this...
+const MIN_LENGTH: Duration = Duration::from_secs(30);
+/// ...played for half their length or this long, whichever comes first.
+const MIN_PLAYED: Duration = Duration::from_secs(240);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Service {
+    #[name = "lastfm"]
+    LastFm,
+    #[name = "listenbrainz"]
+    ListenBrainz,
+}
+
+impl Service {
+    fn key(self) -> &'static str {
+        match self {
+            Service::LastFm => "lastfm",
+            Service::ListenBrainz => "listenbrainz",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "lastfm" => Some(Service::LastFm),
+            "listenbrainz" => Some(Service::ListenBrainz),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Service::LastFm => "Last.fm",
+            Service::ListenBrainz => "ListenBrainz",
+        }
+    }
+}
+
+/// Which tracks are scrobbled for a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Scope {
+    /// Tracks they requested.
+    #[name = "requested"]
+    Requested,
+    /// Everything played while they're in the bot's voice channel.
+    #[name = "listening"]
+    Listening,
+}
+
+impl Scope {
+    fn key(self) -> &'static str {
+        match self {
+            Scope::Requested => "requested",
+            Scope::Listening => "listening",
+        }
+    }
+}
+
+/// A linked account, from `scrobble_accounts`.
+#[derive(Debug, Clone)]
+struct Account {
+    service: Service,
+    username: String,
+    /// Last.fm session key or ListenBrainz user token.
+    secret: String,
+    scope: Scope,
+}
+
+/// Who asked for the track each guild is resolving, for the next `track_started` event.
+pub struct RequesterStore;
+impl TypeMapKey for RequesterStore {
+    type Value = Arc<Mutex<HashMap<GuildId, Option<UserId>>>>;
+}
+
+/// Called whenever `guild_id` starts resolving a track, so its scrobbles go to the requester.
+pub async fn requested(ctx: &Context, guild_id: GuildId, requested_by: Option<UserId>) {
+    if let Some(store) = ctx.data.read().await.get::<RequesterStore>().cloned() {
+        store.lock().await.insert(guild_id, requested_by);
+    }
+}
+
+/// The track a guild is playing, as it will be scrobbled.
+#[derive(Debug, Clone)]
+struct Track {
+    title: String,
+    artist: String,
+    length: Duration,
+    started_at: i64,
+    /// The furthest position seen, from the player's `position` ticks.
+    played: Duration,
+    requester: Option<UserId>,
+}
+
+/// Whether a track of `length` played for `played` counts as listened to.
+fn counts(length: Duration, played: Duration) -> bool {
+    length > MIN_LENGTH && played >= (length / 2).min(MIN_PLAYED)
+}
+
+async fn accounts(ctx: &Context, user: UserId) -> Vec<Account> {
+    let Ok(db) = storage::db(ctx).await else { return Vec::new() };
+    let uid = user.get() as i64;
+    let rows: Vec<(String, String, String, String)> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare("SELECT service, username, secret, scope FROM scrobble_accounts WHERE user_id = ?1")?;
+            let rows = stmt.query_map([uid], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+            rows.collect()
+        })
+        .await
+        .unwrap_or_default();
+    rows.into_iter()
+        .filter_map(|(service, username, secret, scope)| {
+            let scope = if scope == "listening" { Scope::Listening } else { Scope::Requested };
+            Some(Account { service: Service::from_key(&service)?, username, secret, scope })
+        })
+        .collect()
+}
+
+/// Members in the bot's voice channel in `guild_id`, from the cache.
+fn listeners(ctx: &Context, guild_id: GuildId) -> Vec<UserId> {
+    let me = ctx.cache.current_user().id;
+    let Some(guild) = ctx.cache.guild(guild_id) else { return Vec::new() };
+    let Some(channel) = guild.voice_states.get(&me).and_then(|v| v.channel_id) else { return Vec::new() };
+    guild
+        .voice_states
+        .values()
+        .filter(|v| v.channel_id == Some(channel) && v.user_id != me)
+        .map(|v| v.user_id)
+        .collect()
+}
+
+/// The accounts `track` goes to: its requester's, and those of listeners who scrobble
+/// everything.
+async fn recipients(ctx: &Context, guild_id: GuildId, track: &Track) -> Vec<Account> {
+    let listening = listeners(ctx, guild_id);
+    let mut users = listening.clone();
+    if let Some(requester) = track.requester
+        && !users.contains(&requester)
+    {
+        users.push(requester);
+    }
+    let mut found = Vec::new();
+    for user in users {
+        for account in accounts(ctx, user).await {
+            let wanted = match account.scope {
+                Scope::Requested => track.requester == Some(user),
+                Scope::Listening => listening.contains(&user),
+            };
+            if wanted {
+                found.push(account);
+            }
+        }
+    }
+    found
+}
+
+/// Last.fm's `api_sig`: every parameter as name + value, sorted by name, then the secret, MD5ed.
+fn lastfm_signature(params: &[(&str, String)], secret: &str) -> String {
+    let mut sorted = params.to_vec();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+    let mut text: String = sorted.iter().map(|(k, v)| format!("{}{}", k, v)).collect();
+    text.push_str(secret);
+    Md5::digest(text.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Call a signed Last.fm method; writes are POSTed.
+async fn lastfm_call(cfg: &ScrobbleConfig, mut params: Vec<(&str, String)>, write: bool) -> Result<serde_json::Value, Error> {
+    let (key, secret) = cfg.lastfm().ok_or("Last.fm isn't configured on this bot (`scrobble.lastfm_api_key`).")?;
+    params.push(("api_key", key.to_string()));
+    let signature = lastfm_signature(&params, secret);
+    params.push(("api_sig", signature));
+    params.push(("format", "json".to_string()));
+    let client = Client::new();
+    let request = if write { client.post(LASTFM_API).form(&params) } else { client.get(LASTFM_API).query(&params) };
+    let body: serde_json::Value = request.send().await?.json().await?;
+    if let Some(code) = body.get("error") {
+        let message = body.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error");
+        return Err(format!("Last.fm error {}: {}", code, message).into());
+    }
+    Ok(body)
+}
+
+/// Send `track` to `account`: a scrobble with `listened_at`, else "now playing".
+async fn submit(cfg: &ScrobbleConfig, account: &Account, track: &Track, listened_at: Option<i64>) -> Result<(), Error> {
+    match account.service {
+        Service::LastFm => {
+            let mut params = vec![
+                ("artist", track.artist.clone()),
+                ("track", track.title.clone()),
+                ("duration", track.length.as_secs().to_string()),
+                ("sk", account.secret.clone()),
+            ];
+            let method = match listened_at {
+                Some(at) => {
+                    params.push(("timestamp", at.to_string()));
+                    "track.scrobble"
+                }
+                None => "track.updateNowPlaying",
+            };
+            params.push(("method", method.to_string()));
+            lastfm_call(cfg, params, true).await?;
+        }
+        Service::ListenBrainz => {
+            let metadata = json!({ "artist_name": track.artist, "track_name": track.title });
+            let body = match listened_at {
+                Some(at) => json!({ "listen_type": "single", "payload": [{ "listened_at": at, "track_metadata": metadata }] }),
+                None => json!({ "listen_type": "playing_now", "payload": [{ "track_metadata": metadata }] }),
+            };
+            Client::new()
+                .post(format!("{}/1/submit-listens", cfg.listenbrainz_url()))
+                .header("Authorization", format!("Token {}", account.secret))
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+    }
+    Ok(())
+}
+
+/// Send `track` to every account it's for, in the background.
+fn send_all(ctx: &Context, guild_id: GuildId, track: Track, listened_at: Option<i64>) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        let accounts = recipients(&ctx, guild_id, &track).await;
+        if accounts.is_empty() {
+            return;
+        }
+        let cfg = config::current(&ctx).await.scrobble.clone();
+        for account in accounts {
+            if let Err(e) = submit(&cfg, &account, &track, listened_at).await {
+                tracing::warn!("Failed to scrobble to {} for {}: {e:?}", account.service.label(), account.username);
+            }
+        }
+    });
+}
+
+/// The track a `track_started` event describes, if it can be scrobbled: it needs an artist and
+/// a known length (so no livestreams or ambient sounds).
+async fn track_for(
+    ctx: &Context,
+    guild_id: GuildId,
+    title: Option<String>,
+    artist: Option<String>,
+    duration_secs: Option<u64>,
+) -> Option<Track> {
+    let (title, artist) = crate::quiz::answer(&title?, artist.as_deref());
+    let requester = match ctx.data.read().await.get::<RequesterStore>().cloned() {
+        Some(store) => store.lock().await.get(&guild_id).copied().flatten(),
+        None => None,
+    };
+    Some(Track {
+        title,
+        artist: artist?,
+        length: Duration::from_secs(duration_secs?),
+        started_at: serenity::Timestamp::now().unix_timestamp(),
+        played: Duration::ZERO,
+        requester,
+    })
+}
+
+/// Spawn the scrobbler: it follows every guild's player events, posts "now playing" when a
+/// track starts and scrobbles it when it ends, if it played long enough.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        let Some(mut bus) = events::subscribe(&ctx).await else { return };
+        let mut playing: HashMap<GuildId, Track> = HashMap::new();
+        loop {
+            let (guild_id, event) = match bus.recv().await {
+                Ok(received) => received,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+            };
+            match event {
+                PlayerEvent::TrackStarted { title, artist, duration_secs, .. } => {
+                    if let Some(done) = playing.remove(&guild_id)
+                        && counts(done.length, done.played)
+                    {
+                        let at = done.started_at;
+                        send_all(&ctx, guild_id, done, Some(at));
+                    }
+                    if let Some(track) = track_for(&ctx, guild_id, title, artist, duration_secs).await {
+                        send_all(&ctx, guild_id, track.clone(), None);
+                        playing.insert(guild_id, track);
+                    }
+                }
+                PlayerEvent::Position { position_secs, .. } => {
+                    if let Some(track) = playing.get_mut(&guild_id) {
+                        track.played = track.played.max(Duration::from_secs(position_secs));
+                    }
+                }
+                PlayerEvent::TrackEnded => {
+                    if let Some(done) = playing.remove(&guild_id)
+                        && counts(done.length, done.played)
+                    {
+                        let at = done.started_at;
+                        send_all(&ctx, guild_id, done, Some(at));
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+async fn save(ctx: &Context, user: UserId, service: Service, username: String, secret: String) -> Result<(), Error> {
+    let db = storage::db(ctx).await?;
+    let uid = user.get() as i64;
+    db.call(move |conn| {
+        conn.execute(
+            "INSERT INTO scrobble_accounts (user_id, service, username, secret) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (user_id, service) DO UPDATE SET username = excluded.username, secret = excluded.secret",
+            rusqlite::params![uid, service.key(), username, secret],
+        )
+    })
+    .await?;
+    Ok(())
+}
+
+/// Linked accounts for `data export`, without their keys.
+pub async fn export_user(ctx: &Context, user: UserId) -> Result<serde_json::Value, Error> {
+    let accounts: Vec<serde_json::Value> = accounts(ctx, user)
+        .await
+        .iter()
+        .map(|a| json!({ "service": a.service.key(), "username": a.username, "scope": a.scope.key() }))
+        .collect();
+    Ok(json!(accounts))
+}
+
+async fn say_private(ctx: Ctx<'_>, text: impl Into<String>) -> Result<(), Error> {
+    ctx.send(CreateReply::default().content(text).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Scrobble what you listen to to Last.fm or ListenBrainz
+#[poise::command(
+    prefix_command,
+    slash_command,
+    subcommands("scrobble_link", "scrobble_unlink", "scrobble_mode", "scrobble_status")
+)]
+pub async fn scrobble(ctx: Ctx<'_>) -> Result<(), Error> {
+    scrobble_status_reply(ctx).await
+}
+
+async fn scrobble_status_reply(ctx: Ctx<'_>) -> Result<(), Error> {
+    let accounts = accounts(ctx.serenity_context(), ctx.author().id).await;
+    if accounts.is_empty() {
+        return say_private(ctx, "No accounts linked. `scrobble link lastfm` or `scrobble link listenbrainz <token>` starts scrobbling.").await;
+    }
+    let lines: Vec<String> = accounts
+        .iter()
+        .map(|a| {
+            let scope = match a.scope {
+                Scope::Requested => "tracks you request",
+                Scope::Listening => "everything you listen to with the bot",
+            };
+            format!("{} as **{}**: {}", a.service.label(), a.username, scope)
+        })
+        .collect();
+    say_private(ctx, lines.join("\n")).await
+}
+
+/// Show your linked accounts
+#[poise::command(prefix_command, slash_command, rename = "status")]
+pub async fn scrobble_status(ctx: Ctx<'_>) -> Result<(), Error> {
+    scrobble_status_reply(ctx).await
+}
+
+/// Link a Last.fm or ListenBrainz account
+#[poise::command(prefix_command, slash_command, rename = "link")]
+pub async fn scrobble_link(
+    ctx: Ctx<'_>,
+    #[description = "Where to scrobble"] service: Service,
+    #[description = "ListenBrainz user token (from listenbrainz.org/settings)"] token: Option<String>,
+) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let cfg = config::current(sctx).await.scrobble.clone();
+    let user = ctx.author().id;
+    match service {
+        Service::ListenBrainz => {
+            let Some(token) = token.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()) else {
+                return say_private(ctx, "Give your ListenBrainz user token, from https://listenbrainz.org/settings/.").await;
+            };
+            ctx.defer_ephemeral().await?;
+            let check: serde_json::Value = Client::new()
+                .get(format!("{}/1/validate-token", cfg.listenbrainz_url()))
+                .header("Authorization", format!("Token {}", token))
+                .send()
+                .await?
+                .json()
+                .await?;
+            let username = check.get("user_name").and_then(|n| n.as_str()).filter(|_| check.get("valid") == Some(&json!(true)));
+            let Some(username) = username else {
+                return say_private(ctx, "ListenBrainz doesn't accept that token.").await;
+            };
+            save(sctx, user, service, username.to_string(), token).await?;
+            say_private(ctx, format!("Linked ListenBrainz as **{}**. `scrobble mode` picks what gets scrobbled.", username)).await
+        }
+        Service::LastFm => {
+            let Some((key, _)) = cfg.lastfm() else {
+                return say_private(ctx, "Last.fm isn't set up on this bot; ask its owner to add `scrobble.lastfm_api_key` and `lastfm_api_secret`.").await;
+            };
+            let body = lastfm_call(&cfg, vec![("method", "auth.getToken".to_string())], false).await?;
+            let auth_token = body.get("token").and_then(|t| t.as_str()).ok_or("Last.fm returned no token")?.to_string();
+            let url = format!("https://www.last.fm/api/auth/?api_key={}&token={}", key, auth_token);
+            let buttons = vec![CreateActionRow::Buttons(vec![
+                CreateButton::new_link(url).label("Allow on Last.fm"),
+                CreateButton::new("scrobble:done").label("Done").style(ButtonStyle::Primary),
+            ])];
+            let prompt = "Allow the bot to scrobble on Last.fm, then press **Done**.";
+            let reply = ctx.send(CreateReply::default().content(prompt).components(buttons).ephemeral(true)).await?;
+            let message = reply.message().await?;
+            let press = ComponentInteractionCollector::new(ctx)
+                .message_id(message.id)
+                .author_id(user)
+                .timeout(LINK_TIMEOUT)
+                .await;
+            let Some(press) = press else {
+                reply.edit(ctx, CreateReply::default().content("Linking timed out.").components(vec![])).await?;
+                return Ok(());
+            };
+            // Whatever happens, the press gets an answer
+            let text = match lastfm_call(&cfg, vec![("method", "auth.getSession".to_string()), ("token", auth_token)], false).await {
+                Ok(session) => {
+                    let name = session.pointer("/session/name").and_then(|n| n.as_str()).unwrap_or_default().to_string();
+                    let saved = match session.pointer("/session/key").and_then(|k| k.as_str()) {
+                        Some(session_key) => save(sctx, user, service, name.clone(), session_key.to_string()).await,
+                        None => Err("Last.fm returned no session".into()),
+                    };
+                    match saved {
+                        Ok(()) => format!("Linked Last.fm as **{}**. `scrobble mode` picks what gets scrobbled.", name),
+                        Err(e) => format!("Couldn't link Last.fm: {}", e),
+                    }
+                }
+                Err(e) => format!("Last.fm didn't confirm access ({}). Allow it on the page first, then run this again.", e),
+            };
+            press
+                .create_response(
+                    &sctx.http,
+                    CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().content(text).components(vec![])),
+                )
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+/// Stop scrobbling to a service and forget its key
+#[poise::command(prefix_command, slash_command, rename = "unlink")]
+pub async fn scrobble_unlink(ctx: Ctx<'_>, #[description = "Service"] service: Service) -> Result<(), Error> {
+    let db = storage::db(ctx.serenity_context()).await?;
+    let uid = ctx.author().id.get() as i64;
+    let removed = db
+        .call(move |conn| conn.execute("DELETE FROM scrobble_accounts WHERE user_id = ?1 AND service = ?2", rusqlite::params![uid, service.key()]))
+        .await?;
+    let text = if removed > 0 { format!("Unlinked {}.", service.label()) } else { format!("No {} account was linked.", service.label()) };
+    say_private(ctx, text).await
+}
+
+/// Choose whether only your requests are scrobbled or everything you listen to
+#[poise::command(prefix_command, slash_command, rename = "mode")]
+pub async fn scrobble_mode(
+    ctx: Ctx<'_>,
+    #[description = "`requested`: tracks you ask for; `listening`: everything while you're in the channel"] scope: Scope,
+) -> Result<(), Error> {
+    let db = storage::db(ctx.serenity_context()).await?;
+    let uid = ctx.author().id.get() as i64;
+    let changed = db
+        .call(move |conn| conn.execute("UPDATE scrobble_accounts SET scope = ?2 WHERE user_id = ?1", rusqlite::params![uid, scope.key()]))
+        .await?;
+    if changed == 0 {
+        return say_private(ctx, "Link an account first with `scrobble link`.").await;
+    }
+    let text = match scope {
+        Scope::Requested => "Only tracks you request are scrobbled now.",
+        Scope::Listening => "Everything played while you're in the bot's voice channel is scrobbled now.",
+    };
+    say_private(ctx, text).await
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![scrobble()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lastfm_signature_sorts_params_and_appends_the_secret() {
+        let params = [("token", "t".to_string()), ("method", "auth.getSession".to_string()), ("api_key", "k".to_string())];
+        assert_eq!(lastfm_signature(&params, "secret"), "47656600a9b4352066d3092b68e9036b");
+    }
+
+    #[test]
+    fn tracks_count_after_half_or_four_minutes() {
+        assert!(!counts(Duration::from_secs(25), Duration::from_secs(25)));
+        assert!(counts(Duration::from_secs(180), Duration::from_secs(90)));
+        assert!(!counts(Duration::from_secs(180), Duration::from_secs(80)));
+        assert!(counts(Duration::from_secs(1200), Duration::from_secs(240)));
+    }
+}
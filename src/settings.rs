@@ -0,0 +1,627 @@
+use poise::serenity_prelude as serenity;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::modalert::ModAlertStore;
+use crate::{embeds, i18n, storage};
+use crate::{Ctx, Error};
+
+/// Every per-guild setting. Values are stored as text in `guild_settings`; guilds without a row
+/// use the key's default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum SettingKey {
+    #[name = "prefix"]
+    Prefix,
+    #[name = "embed_color"]
+    EmbedColor,
+    #[name = "dj_role"]
+    DjRole,
+    #[name = "announce_channel"]
+    AnnounceChannel,
+    #[name = "default_volume"]
+    DefaultVolume,
+    #[name = "locale"]
+    Locale,
+    #[name = "ephemeral_replies"]
+    EphemeralReplies,
+    #[name = "music_thread"]
+    MusicThread,
+    #[name = "embed_footer"]
+    EmbedFooter,
+    #[name = "embed_thumbnails"]
+    EmbedThumbnails,
+    #[name = "embed_timestamp"]
+    EmbedTimestamp,
+    #[name = "queue.dedupe"]
+    QueueDedupe,
+    #[name = "queue.max_per_user"]
+    QueueMaxPerUser,
+    #[name = "queue.fair"]
+    QueueFair,
+    #[name = "max_track_length"]
+    MaxTrackLength,
+    #[name = "allow_livestreams"]
+    AllowLivestreams,
+    #[name = "audio_quality"]
+    AudioQuality,
+    #[name = "dry_run"]
+    DryRun,
+    #[name = "music.follow"]
+    MusicFollow,
+    #[name = "music.command_channel"]
+    MusicCommandChannel,
+    #[name = "music.announce_next"]
+    MusicAnnounceNext,
+    #[name = "features.music"]
+    FeaturesMusic,
+    #[name = "features.moderation"]
+    FeaturesModeration,
+    #[name = "features.start"]
+    FeaturesStart,
+}
+
+impl SettingKey {
+    pub const ALL: [SettingKey; 24] = [
+        SettingKey::Prefix,
+        SettingKey::EmbedColor,
+        SettingKey::DjRole,
+        SettingKey::AnnounceChannel,
+        SettingKey::DefaultVolume,
+        SettingKey::Locale,
+        SettingKey::EphemeralReplies,
+        SettingKey::MusicThread,
+        SettingKey::EmbedFooter,
+        SettingKey::EmbedThumbnails,
+        SettingKey::EmbedTimestamp,
+        SettingKey::QueueDedupe,
+        SettingKey::QueueMaxPerUser,
+        SettingKey::QueueFair,
+        SettingKey::MaxTrackLength,
+        SettingKey::AllowLivestreams,
+        SettingKey::AudioQuality,
+        SettingKey::DryRun,
+        SettingKey::MusicFollow,
+        SettingKey::MusicCommandChannel,
+        SettingKey::MusicAnnounceNext,
+        SettingKey::FeaturesMusic,
+        SettingKey::FeaturesModeration,
+        SettingKey::FeaturesStart,
+    ];
+
+    /// Column value in `guild_settings.key`.
+    pub fn key(self) -> &'static str {
+        match self {
+            SettingKey::Prefix => "prefix",
+            SettingKey::EmbedColor => "embed_color",
+            SettingKey::DjRole => "dj_role",
+            SettingKey::AnnounceChannel => "announce_channel",
+            SettingKey::DefaultVolume => "default_volume",
+            SettingKey::Locale => "locale",
+            SettingKey::EphemeralReplies => "ephemeral_replies",
+            SettingKey::MusicThread => "music_thread",
+            SettingKey::EmbedFooter => "embed_footer",
+            SettingKey::EmbedThumbnails => "embed_thumbnails",
+            SettingKey::EmbedTimestamp => "embed_timestamp",
+            SettingKey::QueueDedupe => "queue.dedupe",
+            SettingKey::QueueMaxPerUser => "queue.max_per_user",
+            SettingKey::QueueFair => "queue.fair",
+            SettingKey::MaxTrackLength => "max_track_length",
+            SettingKey::AllowLivestreams => "allow_livestreams",
+            SettingKey::AudioQuality => "audio_quality",
+            SettingKey::DryRun => "dry_run",
+            SettingKey::MusicFollow => "music.follow",
+            SettingKey::MusicCommandChannel => "music.command_channel",
+            SettingKey::MusicAnnounceNext => "music.announce_next",
+            SettingKey::FeaturesMusic => "features.music",
+            SettingKey::FeaturesModeration => "features.moderation",
+            SettingKey::FeaturesStart => "features.start",
+        }
+    }
+
+    /// One of the `features.*` switches, which change what's registered in the guild.
+    pub fn is_feature(self) -> bool {
+        matches!(self, SettingKey::FeaturesMusic | SettingKey::FeaturesModeration | SettingKey::FeaturesStart)
+    }
+
+    fn description(self, locale: &str) -> String {
+        i18n::t(locale, &format!("settings.keys.{}", self.key()))
+    }
+
+    fn default_value(self) -> Option<String> {
+        match self {
+            SettingKey::Prefix => Some(crate::PREFIX.to_string()),
+            SettingKey::EmbedColor => Some(format!("#{:06X}", crate::EMBED_COLOR)),
+            SettingKey::DjRole
+            | SettingKey::AnnounceChannel
+            | SettingKey::EmbedFooter
+            | SettingKey::MusicCommandChannel => None,
+            SettingKey::DefaultVolume => Some("100".to_string()),
+            SettingKey::Locale => Some("auto".to_string()),
+            SettingKey::EphemeralReplies => Some("on".to_string()),
+            SettingKey::MusicThread => Some("off".to_string()),
+            SettingKey::EmbedThumbnails => Some("on".to_string()),
+            SettingKey::EmbedTimestamp => Some("off".to_string()),
+            SettingKey::QueueDedupe => Some("off".to_string()),
+            SettingKey::QueueMaxPerUser => Some("0".to_string()),
+            SettingKey::QueueFair => Some("off".to_string()),
+            SettingKey::MaxTrackLength => Some("0".to_string()),
+            SettingKey::AllowLivestreams => Some("on".to_string()),
+            SettingKey::AudioQuality => Some("medium".to_string()),
+            SettingKey::DryRun => Some("off".to_string()),
+            SettingKey::MusicFollow => Some("off".to_string()),
+            SettingKey::MusicAnnounceNext => Some("off".to_string()),
+            SettingKey::FeaturesMusic | SettingKey::FeaturesModeration | SettingKey::FeaturesStart => {
+                Some("on".to_string())
+            }
+        }
+    }
+
+    /// Validate user input and normalise it to the stored form. Errors are in `locale`.
+    pub fn parse(self, guild: Option<&serenity::Guild>, input: &str, locale: &str) -> Result<String, String> {
+        let input = input.trim();
+        match self {
+            SettingKey::Prefix => {
+                if input.is_empty() || input.chars().count() > 5 || input.contains(char::is_whitespace) {
+                    return Err(i18n::t(locale, "settings.bad_prefix"));
+                }
+                Ok(input.to_string())
+            }
+            SettingKey::EmbedColor => {
+                let hex = input.trim_start_matches('#').trim_start_matches("0x");
+                match u32::from_str_radix(hex, 16) {
+                    Ok(c) if hex.len() == 6 => Ok(format!("#{:06X}", c)),
+                    _ => Err(i18n::t(locale, "settings.bad_color")),
+                }
+            }
+            SettingKey::DjRole => {
+                let id = parse_id(input, "<@&").ok_or_else(|| i18n::t(locale, "settings.bad_role"))?;
+                if guild.is_some_and(|g| !g.roles.contains_key(&RoleId::new(id))) {
+                    return Err(i18n::t(locale, "settings.unknown_role"));
+                }
+                Ok(id.to_string())
+            }
+            SettingKey::AnnounceChannel | SettingKey::MusicCommandChannel => {
+                let id = parse_id(input, "<#").ok_or_else(|| i18n::t(locale, "settings.bad_channel"))?;
+                if guild.is_some_and(|g| !g.channels.contains_key(&ChannelId::new(id))) {
+                    return Err(i18n::t(locale, "settings.unknown_channel"));
+                }
+                Ok(id.to_string())
+            }
+            SettingKey::DefaultVolume => match input.trim_end_matches('%').parse::<u32>() {
+                Ok(v) if v <= 200 => Ok(v.to_string()),
+                _ => Err(i18n::t(locale, "settings.bad_volume")),
+            },
+            SettingKey::Locale => {
+                if input.eq_ignore_ascii_case("auto") {
+                    return Ok("auto".to_string());
+                }
+                i18n::resolve(input).map(str::to_string).ok_or_else(|| {
+                    let locales: Vec<String> = i18n::supported().map(|l| format!("`{}`", l)).collect();
+                    i18n::tf(locale, "settings.bad_locale", &[("locales", &locales.join(", "))])
+                })
+            }
+            // Discord caps embed footers at 2048 characters
+            SettingKey::EmbedFooter => {
+                if input.is_empty() || input.chars().count() > 2048 {
+                    return Err(i18n::t(locale, "settings.bad_footer"));
+                }
+                Ok(input.to_string())
+            }
+            SettingKey::QueueMaxPerUser => match input.parse::<u32>() {
+                Ok(n) if n <= 500 => Ok(n.to_string()),
+                _ => Err(i18n::t(locale, "settings.bad_max_per_user")),
+            },
+            // Stored in seconds; given as minutes or `h:mm:ss` / `m:ss`
+            SettingKey::MaxTrackLength => {
+                if matches!(input.to_ascii_lowercase().as_str(), "off" | "none") {
+                    return Ok("0".to_string());
+                }
+                let parts: Vec<Option<u64>> = input.split(':').map(|p| p.parse().ok()).collect();
+                let secs = match parts.as_slice() {
+                    [Some(minutes)] => minutes.checked_mul(60),
+                    [Some(m), Some(s)] if *s < 60 => m.checked_mul(60).and_then(|m| m.checked_add(*s)),
+                    [Some(h), Some(m), Some(s)] if *m < 60 && *s < 60 => {
+                        h.checked_mul(3600).and_then(|h| h.checked_add(m * 60 + s))
+                    }
+                    _ => None,
+                };
+                secs.filter(|s| *s <= 24 * 3600)
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| i18n::t(locale, "settings.bad_track_length"))
+            }
+            SettingKey::EphemeralReplies
+            | SettingKey::MusicThread
+            | SettingKey::EmbedThumbnails
+            | SettingKey::EmbedTimestamp
+            | SettingKey::QueueFair
+            | SettingKey::AllowLivestreams
+            | SettingKey::DryRun
+            | SettingKey::MusicFollow
+            | SettingKey::FeaturesMusic
+            | SettingKey::FeaturesModeration
+            | SettingKey::FeaturesStart => match input.to_ascii_lowercase().as_str() {
+                "on" | "true" | "yes" => Ok("on".to_string()),
+                "off" | "false" | "no" => Ok("off".to_string()),
+                _ => Err(i18n::t(locale, "settings.bad_switch")),
+            },
+            SettingKey::AudioQuality => match input.to_ascii_lowercase().as_str() {
+                level @ ("low" | "medium" | "high") => Ok(level.to_string()),
+                _ => Err(i18n::t(locale, "settings.bad_quality")),
+            },
+            SettingKey::MusicAnnounceNext => match input.to_ascii_lowercase().as_str() {
+                mode @ ("off" | "text" | "voice" | "both") => Ok(mode.to_string()),
+                _ => Err(i18n::t(locale, "settings.bad_announce")),
+            },
+            SettingKey::QueueDedupe => match input.to_ascii_lowercase().as_str() {
+                mode @ ("off" | "warn" | "block") => Ok(mode.to_string()),
+                _ => Err(i18n::t(locale, "settings.bad_dedupe")),
+            },
+        }
+    }
+
+    fn display(self, value: &str) -> String {
+        match self {
+            SettingKey::DjRole => format!("<@&{}>", value),
+            SettingKey::AnnounceChannel | SettingKey::MusicCommandChannel => format!("<#{}>", value),
+            SettingKey::DefaultVolume => format!("{}%", value),
+            SettingKey::Prefix => format!("`{}`", value),
+            SettingKey::EmbedColor | SettingKey::EmbedFooter => value.to_string(),
+            SettingKey::Locale => format!("`{}`", value),
+            SettingKey::QueueMaxPerUser | SettingKey::MaxTrackLength if value == "0" => "unlimited".to_string(),
+            SettingKey::MaxTrackLength => match value.parse() {
+                Ok(secs) => crate::positions::clock(std::time::Duration::from_secs(secs)),
+                Err(_) => value.to_string(),
+            },
+            SettingKey::EphemeralReplies
+            | SettingKey::MusicThread
+            | SettingKey::EmbedThumbnails
+            | SettingKey::EmbedTimestamp
+            | SettingKey::QueueDedupe
+            | SettingKey::QueueMaxPerUser
+            | SettingKey::QueueFair
+            | SettingKey::AllowLivestreams
+            | SettingKey::AudioQuality
+            | SettingKey::DryRun
+            | SettingKey::MusicFollow
+            | SettingKey::MusicAnnounceNext
+            | SettingKey::FeaturesMusic
+            | SettingKey::FeaturesModeration
+            | SettingKey::FeaturesStart => value.to_string(),
+        }
+    }
+}
+
+fn parse_id(input: &str, mention_prefix: &str) -> Option<u64> {
+    input
+        .strip_prefix(mention_prefix)
+        .and_then(|s| s.strip_suffix('>'))
+        .unwrap_or(input)
+        .parse()
+        .ok()
+        .filter(|id| *id != 0)
+}
+
+type GuildValues = Arc<HashMap<String, String>>;
+
+/// Settings read from the database, per guild. Entries are dropped whenever a guild's settings
+/// change and reloaded on the next read.
+pub struct GuildSettingsCache;
+impl TypeMapKey for GuildSettingsCache {
+    type Value = Arc<Mutex<HashMap<GuildId, GuildValues>>>;
+}
+
+async fn load(ctx: &Context, gid: GuildId) -> Result<GuildValues, Error> {
+    let cache = ctx.data.read().await.get::<GuildSettingsCache>().cloned();
+    if let Some(values) = match &cache {
+        Some(cache) => cache.lock().await.get(&gid).cloned(),
+        None => None,
+    } {
+        return Ok(values);
+    }
+
+    let db = storage::db(ctx).await?;
+    let values: HashMap<String, String> = db
+        .call(move |conn| {
+            let mut stmt = conn.prepare("SELECT key, value FROM guild_settings WHERE guild_id = ?1")?;
+            let rows = stmt.query_map([gid.get() as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await?;
+    let values = Arc::new(values);
+    if let Some(cache) = cache {
+        cache.lock().await.insert(gid, values.clone());
+    }
+    Ok(values)
+}
+
+async fn invalidate(ctx: &Context, gid: GuildId) {
+    let cache = ctx.data.read().await.get::<GuildSettingsCache>().cloned();
+    if let Some(cache) = cache {
+        cache.lock().await.remove(&gid);
+    }
+}
+
+/// Stored value for `key`, falling back to its default.
+pub async fn get(ctx: &Context, gid: GuildId, key: SettingKey) -> Option<String> {
+    match load(ctx, gid).await {
+        Ok(values) => values.get(key.key()).cloned().or_else(|| key.default_value()),
+        Err(e) => {
+            tracing::warn!("Failed to load settings for guild {}: {e:?}", gid);
+            key.default_value()
+        }
+    }
+}
+
+pub async fn set(ctx: &Context, gid: GuildId, key: SettingKey, value: String) -> Result<(), Error> {
+    let db = storage::db(ctx).await?;
+    db.call(move |conn| {
+        conn.execute(
+            "INSERT INTO guild_settings (guild_id, key, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT (guild_id, key) DO UPDATE SET value = excluded.value",
+            rusqlite::params![gid.get() as i64, key.key(), value],
+        )
+    })
+    .await?;
+    invalidate(ctx, gid).await;
+    Ok(())
+}
+
+pub async fn reset(ctx: &Context, gid: GuildId, key: Option<SettingKey>) -> Result<(), Error> {
+    let db = storage::db(ctx).await?;
+    db.call(move |conn| match key {
+        Some(key) => conn.execute(
+            "DELETE FROM guild_settings WHERE guild_id = ?1 AND key = ?2",
+            rusqlite::params![gid.get() as i64, key.key()],
+        ),
+        None => conn.execute("DELETE FROM guild_settings WHERE guild_id = ?1", [gid.get() as i64]),
+    })
+    .await?;
+    invalidate(ctx, gid).await;
+    Ok(())
+}
+
+/// Embed accent color for a guild (the bot default outside guilds).
+pub async fn embed_color(ctx: &Context, gid: Option<GuildId>) -> u32 {
+    let Some(gid) = gid else { return crate::EMBED_COLOR };
+    get(ctx, gid, SettingKey::EmbedColor)
+        .await
+        .and_then(|v| u32::from_str_radix(v.trim_start_matches('#'), 16).ok())
+        .unwrap_or(crate::EMBED_COLOR)
+}
+
+/// Whether informational replies to this slash invocation should only be shown to its
+/// author. Text commands can't be ephemeral, so they always answer publicly.
+pub async fn ephemeral(ctx: Ctx<'_>) -> bool {
+    if !matches!(ctx, poise::Context::Application(_)) {
+        return false;
+    }
+    let Some(gid) = ctx.guild_id() else { return true };
+    get(ctx.serenity_context(), gid, SettingKey::EphemeralReplies).await.as_deref() != Some("off")
+}
+
+/// Whether `member` may override music restrictions: holders of the `dj_role`, and anyone
+/// with Manage Server.
+pub async fn is_dj(ctx: &Context, gid: GuildId, member: &serenity::Member) -> bool {
+    let permissions = member.permissions.or_else(|| ctx.cache.guild(gid).map(|g| g.member_permissions(member)));
+    if permissions.is_some_and(|p| p.manage_guild()) {
+        return true;
+    }
+    let dj_role = get(ctx, gid, SettingKey::DjRole).await.and_then(|v| v.parse().ok()).map(RoleId::new);
+    dj_role.is_some_and(|role| member.roles.contains(&role))
+}
+
+/// `is_dj` for a member known only by id, e.g. whoever queued a request.
+pub async fn user_is_dj(ctx: &Context, gid: GuildId, user: serenity::UserId) -> bool {
+    match gid.member(ctx, user).await {
+        Ok(member) => is_dj(ctx, gid, &member).await,
+        Err(_) => false,
+    }
+}
+
+/// `is_dj` for whoever ran the command.
+pub async fn author_is_dj(ctx: Ctx<'_>) -> bool {
+    let Some(gid) = ctx.guild_id() else { return false };
+    if crate::permissions::author_permissions(ctx).await.is_some_and(|p| p.manage_guild()) {
+        return true;
+    }
+    match ctx.author_member().await {
+        Some(member) => is_dj(ctx.serenity_context(), gid, &member).await,
+        None => false,
+    }
+}
+
+/// Text-command prefix for a guild. `!is` keeps working everywhere as a fallback.
+pub async fn prefix(ctx: &Context, gid: GuildId) -> String {
+    get(ctx, gid, SettingKey::Prefix).await.unwrap_or_else(|| crate::PREFIX.to_string())
+}
+
+/// Server configuration
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands("settings_view", "settings_set", "settings_reset"),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn settings(ctx: Ctx<'_>) -> Result<(), Error> {
+    view(ctx).await
+}
+
+/// Show this server's settings
+#[poise::command(prefix_command, slash_command, guild_only, rename = "view")]
+pub async fn settings_view(ctx: Ctx<'_>) -> Result<(), Error> {
+    view(ctx).await
+}
+
+async fn view(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let sctx = ctx.serenity_context();
+    let values = load(sctx, guild_id).await?;
+    let locale = i18n::locale(ctx).await;
+
+    let mut embed = embeds::builder(sctx, Some(guild_id)).await.title(i18n::t(locale, "settings.title"));
+    for key in SettingKey::ALL {
+        let shown = match values.get(key.key()) {
+            Some(v) => key.display(v),
+            None => match key.default_value() {
+                Some(d) => i18n::tf(locale, "settings.default", &[("value", &key.display(&d))]),
+                None => i18n::t(locale, "settings.not_set"),
+            },
+        };
+        embed = embed.field(key.key(), format!("{}\n*{}*", shown, key.description(locale)), true);
+    }
+
+    // Mod alert options have their own command but are shown here for a complete picture
+    let modalert = ctx.serenity_context().data.read().await.get::<ModAlertStore>().cloned();
+    if let Some(store) = modalert {
+        let s = store.lock().await;
+        let enabled = s.enabled.contains(&guild_id);
+        let channel = s
+            .channels
+            .get(&guild_id)
+            .map(|c| format!("<#{}>", c))
+            .unwrap_or_else(|| i18n::t(locale, "settings.owner_dm"));
+        let log = s.message_log.contains(&guild_id);
+        let on_off = |on: bool| i18n::t(locale, if on { "settings.on" } else { "settings.off" });
+        let summary = i18n::tf(
+            locale,
+            "settings.modalert",
+            &[("state", &on_off(enabled)), ("channel", &channel), ("log", &on_off(log))],
+        );
+        embed = embed.field("modalert", summary, false);
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Change a setting
+#[poise::command(prefix_command, slash_command, guild_only, rename = "set")]
+pub async fn settings_set(
+    ctx: Ctx<'_>,
+    #[description = "Setting"] key: SettingKey,
+    #[description = "New value"]
+    #[rest]
+    value: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let locale = i18n::locale(ctx).await;
+    let parsed = {
+        let guild = ctx.guild();
+        key.parse(guild.as_deref(), &value, locale)
+    };
+    let value = match parsed {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+    let shown = key.display(&value);
+    let switched_off = key.is_feature() && value == "off";
+    set(ctx.serenity_context(), guild_id, key, value).await?;
+    if key == SettingKey::AudioQuality {
+        crate::quality::apply(ctx.serenity_context(), guild_id).await;
+    }
+    let mut still_listed = false;
+    if key.is_feature() {
+        crate::features::resync(ctx.serenity_context(), &ctx.framework().options().commands, guild_id).await;
+        still_listed = switched_off && !crate::registration::per_guild(ctx.serenity_context(), guild_id).await;
+    }
+    // A new `locale` answers in the new language straight away
+    let locale = i18n::locale(ctx).await;
+    let mut reply = i18n::tf(locale, "settings.set", &[("key", &key.key()), ("value", &shown)]);
+    if still_listed {
+        reply.push(' ');
+        reply.push_str(&i18n::t(locale, "features.still_listed"));
+    }
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+/// Reset a setting (or all of them) to the default
+#[poise::command(prefix_command, slash_command, guild_only, rename = "reset")]
+pub async fn settings_reset(
+    ctx: Ctx<'_>,
+    #[description = "Setting to reset (omit to reset everything)"] key: Option<SettingKey>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    reset(ctx.serenity_context(), guild_id, key).await?;
+    if key.is_none_or(SettingKey::is_feature) {
+        crate::features::resync(ctx.serenity_context(), &ctx.framework().options().commands, guild_id).await;
+    }
+    let locale = i18n::locale(ctx).await;
+    match key {
+        Some(key) => ctx.say(i18n::tf(locale, "settings.reset_one", &[("key", &key.key())])).await?,
+        None => ctx.say(i18n::t(locale, "settings.reset_all")).await?,
+    };
+    Ok(())
+}
+
+/// Show or change the text command prefix
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "prefix",
+    subcommands("prefix_set", "prefix_reset")
+)]
+pub async fn prefix_cmd(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let current = prefix(ctx.serenity_context(), guild_id).await;
+    let locale = i18n::locale(ctx).await;
+    ctx.say(i18n::tf(locale, "prefix.current", &[("prefix", &current), ("fallback", &crate::PREFIX)])).await?;
+    Ok(())
+}
+
+/// Set the text command prefix for this server
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "set",
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn prefix_set(
+    ctx: Ctx<'_>,
+    #[description = "New prefix (1-5 characters, no spaces)"] prefix: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let locale = i18n::locale(ctx).await;
+    let value = match SettingKey::Prefix.parse(None, &prefix, locale) {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+    set(ctx.serenity_context(), guild_id, SettingKey::Prefix, value.clone()).await?;
+    ctx.say(i18n::tf(locale, "prefix.set", &[("prefix", &value)])).await?;
+    Ok(())
+}
+
+/// Go back to the default prefix
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "reset",
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn prefix_reset(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    reset(ctx.serenity_context(), guild_id, Some(SettingKey::Prefix)).await?;
+    let locale = i18n::locale(ctx).await;
+    ctx.say(i18n::tf(locale, "prefix.reset", &[("prefix", &crate::PREFIX)])).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![settings(), prefix_cmd()]
+}
@@ -0,0 +1,358 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+use serenity::all::{
+    ButtonStyle, ChannelId, ChannelType, ComponentInteraction, ComponentInteractionCollector,
+    ComponentInteractionDataKind, GuildId, RoleId,
+};
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+};
+use std::time::Duration;
+
+use crate::embeds::Theme;
+use crate::modalert::{save_modalert_store, ModAlertStore};
+use crate::settings::{self, SettingKey};
+use crate::{Ctx, Error};
+
+/// How long the wizard waits for each click before giving up.
+const STEP_TIMEOUT: Duration = Duration::from_secs(300);
+
+const VOLUME_PRESETS: [u32; 4] = [50, 75, 100, 150];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Announce,
+    Dj,
+    ModAlert,
+    Volume,
+    Review,
+}
+
+impl Step {
+    /// The steps in order. Mod alerts are left out for anyone but the server owner, who is the
+    /// only one allowed to change them (see `modalert`).
+    fn all(owner: bool) -> Vec<Step> {
+        let mut steps = vec![Step::Announce, Step::Dj];
+        if owner {
+            steps.push(Step::ModAlert);
+        }
+        steps.extend([Step::Volume, Step::Review]);
+        steps
+    }
+}
+
+/// Everything the wizard sets, starting from the guild's current values.
+#[derive(Debug, Clone)]
+struct Choices {
+    announce_channel: Option<ChannelId>,
+    dj_role: Option<RoleId>,
+    alerts: bool,
+    message_log: bool,
+    alert_channel: Option<ChannelId>,
+    volume: u32,
+}
+
+impl Choices {
+    async fn load(ctx: &serenity::Context, gid: GuildId) -> Self {
+        let id = |v: Option<String>| v.and_then(|v| v.parse::<u64>().ok()).filter(|id| *id != 0);
+        let mut choices = Choices {
+            announce_channel: id(settings::get(ctx, gid, SettingKey::AnnounceChannel).await).map(ChannelId::new),
+            dj_role: id(settings::get(ctx, gid, SettingKey::DjRole).await).map(RoleId::new),
+            alerts: false,
+            message_log: false,
+            alert_channel: None,
+            volume: settings::get(ctx, gid, SettingKey::DefaultVolume).await.and_then(|v| v.parse().ok()).unwrap_or(100),
+        };
+        if let Some(store) = ctx.data.read().await.get::<ModAlertStore>().cloned() {
+            let modalert = store.lock().await;
+            choices.alerts = modalert.enabled.contains(&gid);
+            choices.message_log = modalert.message_log.contains(&gid);
+            choices.alert_channel = modalert.channels.get(&gid).copied();
+        }
+        choices
+    }
+
+    async fn save(&self, ctx: &serenity::Context, gid: GuildId, owner: bool) -> Result<(), Error> {
+        match self.announce_channel {
+            Some(c) => settings::set(ctx, gid, SettingKey::AnnounceChannel, c.to_string()).await?,
+            None => settings::reset(ctx, gid, Some(SettingKey::AnnounceChannel)).await?,
+        }
+        match self.dj_role {
+            Some(r) => settings::set(ctx, gid, SettingKey::DjRole, r.to_string()).await?,
+            None => settings::reset(ctx, gid, Some(SettingKey::DjRole)).await?,
+        }
+        settings::set(ctx, gid, SettingKey::DefaultVolume, self.volume.to_string()).await?;
+
+        if owner && let Some(store) = ctx.data.read().await.get::<ModAlertStore>().cloned() {
+            {
+                let mut modalert = store.lock().await;
+                if self.alerts {
+                    modalert.enabled.insert(gid);
+                } else {
+                    modalert.enabled.remove(&gid);
+                }
+                if self.message_log {
+                    modalert.message_log.insert(gid);
+                } else {
+                    modalert.message_log.remove(&gid);
+                }
+                match self.alert_channel {
+                    Some(c) => modalert.channels.insert(gid, c),
+                    None => modalert.channels.remove(&gid),
+                };
+            }
+            save_modalert_store(ctx).await?;
+        }
+        Ok(())
+    }
+
+    fn summary(&self, owner: bool) -> String {
+        let channel = |c: Option<ChannelId>, none: &str| c.map(|c| format!("<#{}>", c)).unwrap_or_else(|| none.to_string());
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        let mut lines = vec![
+            format!("**Announce channel:** {}", channel(self.announce_channel, "none (the channel the command was used in)")),
+            format!("**DJ role:** {}", self.dj_role.map(|r| format!("<@&{}>", r)).unwrap_or_else(|| "none (Manage Server only)".to_string())),
+        ];
+        if owner {
+            lines.push(format!(
+                "**Mod alerts:** {}, deleted-message log {}, sent to {}",
+                on_off(self.alerts),
+                on_off(self.message_log),
+                channel(self.alert_channel, "the server owner's DMs")
+            ));
+        }
+        lines.push(format!("**Default volume:** {}%", self.volume));
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, poise::Modal)]
+#[name = "Default volume"]
+struct VolumeModal {
+    #[name = "Volume in percent (0-200)"]
+    #[placeholder = "100"]
+    #[min_length = 1]
+    #[max_length = 4]
+    volume: String,
+}
+
+fn nav_row(index: usize, last: bool) -> CreateActionRow {
+    let mut buttons = Vec::new();
+    if index > 0 {
+        buttons.push(CreateButton::new("setup:back").label("Back").style(ButtonStyle::Secondary));
+    }
+    buttons.push(if last {
+        CreateButton::new("setup:save").label("Save").style(ButtonStyle::Success)
+    } else {
+        CreateButton::new("setup:next").label("Next").style(ButtonStyle::Primary)
+    });
+    buttons.push(CreateButton::new("setup:cancel").label("Cancel").style(ButtonStyle::Danger));
+    CreateActionRow::Buttons(buttons)
+}
+
+fn render(step: Step, index: usize, total: usize, choices: &Choices, owner: bool, theme: &Theme) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let (title, description, mut rows) = match step {
+        Step::Announce => (
+            "Announce channel",
+            "Where music announcements (now playing, queue updates) are posted. Leave it empty to answer in whichever channel the command was used.",
+            vec![CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(
+                    "setup:announce",
+                    CreateSelectMenuKind::Channel {
+                        channel_types: Some(vec![ChannelType::Text, ChannelType::News]),
+                        default_channels: Some(choices.announce_channel.into_iter().collect()),
+                    },
+                )
+                .placeholder("No announce channel")
+                .min_values(0)
+                .max_values(1),
+            )],
+        ),
+        Step::Dj => (
+            "DJ role",
+            "Members with this role can skip, stop and override music limits. Anyone with Manage Server always can.",
+            vec![CreateActionRow::SelectMenu(
+                CreateSelectMenu::new("setup:dj", CreateSelectMenuKind::Role { default_roles: Some(choices.dj_role.into_iter().collect()) })
+                    .placeholder("No DJ role")
+                    .min_values(0)
+                    .max_values(1),
+            )],
+        ),
+        Step::ModAlert => {
+            let events = vec![
+                CreateSelectMenuOption::new("Moderation alerts", "alerts")
+                    .description("Kicks, bans, timeouts, automod and raid detection")
+                    .default_selection(choices.alerts),
+                CreateSelectMenuOption::new("Deleted-message log", "message_log")
+                    .description("Post deleted messages to the alert channel")
+                    .default_selection(choices.message_log),
+            ];
+            (
+                "Mod alerts",
+                "Which events to report, and where. Without an alert channel, alerts are sent to you by DM.",
+                vec![
+                    CreateActionRow::SelectMenu(
+                        CreateSelectMenu::new("setup:events", CreateSelectMenuKind::String { options: events })
+                            .placeholder("Nothing reported")
+                            .min_values(0)
+                            .max_values(2),
+                    ),
+                    CreateActionRow::SelectMenu(
+                        CreateSelectMenu::new(
+                            "setup:alert_channel",
+                            CreateSelectMenuKind::Channel {
+                                channel_types: Some(vec![ChannelType::Text]),
+                                default_channels: Some(choices.alert_channel.into_iter().collect()),
+                            },
+                        )
+                        .placeholder("Owner DMs")
+                        .min_values(0)
+                        .max_values(1),
+                    ),
+                ],
+            )
+        }
+        Step::Volume => {
+            let mut buttons: Vec<CreateButton> = VOLUME_PRESETS
+                .iter()
+                .map(|v| {
+                    let style = if *v == choices.volume { ButtonStyle::Success } else { ButtonStyle::Secondary };
+                    CreateButton::new(format!("setup:volume:{}", v)).label(format!("{}%", v)).style(style)
+                })
+                .collect();
+            buttons.push(CreateButton::new("setup:volume_custom").label("Custom...").style(ButtonStyle::Secondary));
+            ("Default volume", "The volume music starts at when the bot joins a call.", vec![CreateActionRow::Buttons(buttons)])
+        }
+        Step::Review => ("Review", "Save these settings? Nothing has been changed yet.", vec![]),
+    };
+    rows.push(nav_row(index, step == Step::Review));
+
+    let embed = theme
+        .embed()
+        .title(format!("Setup ({}/{}): {}", index + 1, total, title))
+        .description(format!("{}\n\n{}", description, choices.summary(owner)));
+    (embed, rows)
+}
+
+/// Apply a select-menu change to `choices`. Returns false for anything that isn't one.
+fn apply_select(press: &ComponentInteraction, choices: &mut Choices) -> bool {
+    match (press.data.custom_id.as_str(), &press.data.kind) {
+        ("setup:announce", ComponentInteractionDataKind::ChannelSelect { values }) => {
+            choices.announce_channel = values.first().copied();
+        }
+        ("setup:dj", ComponentInteractionDataKind::RoleSelect { values }) => {
+            choices.dj_role = values.first().copied();
+        }
+        ("setup:events", ComponentInteractionDataKind::StringSelect { values }) => {
+            choices.alerts = values.iter().any(|v| v == "alerts");
+            choices.message_log = values.iter().any(|v| v == "message_log");
+        }
+        ("setup:alert_channel", ComponentInteractionDataKind::ChannelSelect { values }) => {
+            choices.alert_channel = values.first().copied();
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Step through the basic server configuration
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn setup(ctx: Ctx<'_>) -> Result<(), Error> {
+    let sctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let theme = crate::embeds::theme(sctx, Some(guild_id)).await;
+    let locale = crate::i18n::locale(ctx).await;
+    let owner = crate::is_guild_owner(ctx, guild_id).await;
+    let steps = Step::all(owner);
+    let mut choices = Choices::load(sctx, guild_id).await;
+    let mut index = 0;
+
+    let (embed, rows) = render(steps[index], index, steps.len(), &choices, owner, &theme);
+    let reply = ctx.send(CreateReply::default().embed(embed).components(rows).ephemeral(true)).await?;
+    let message = reply.message().await?;
+
+    loop {
+        let press = ComponentInteractionCollector::new(sctx)
+            .message_id(message.id)
+            .timeout(STEP_TIMEOUT)
+            .await;
+        let Some(press) = press else {
+            let embed = theme.embed().title("Setup").description("Setup timed out; nothing was changed.");
+            reply.edit(ctx, CreateReply::default().embed(embed).components(vec![])).await?;
+            return Ok(());
+        };
+        if press.user.id != ctx.author().id {
+            let notice = CreateInteractionResponseMessage::new()
+                .content("Only the person who ran `setup` can use these controls.")
+                .ephemeral(true);
+            press.create_response(&sctx.http, CreateInteractionResponse::Message(notice)).await?;
+            continue;
+        }
+
+        let id = press.data.custom_id.as_str();
+        if id == "setup:volume_custom" {
+            // The modal's own response acknowledges the press, so the prompt is edited afterwards
+            let submitted =
+                poise::execute_modal_on_component_interaction::<VolumeModal>(ctx, press.clone(), None, Some(STEP_TIMEOUT)).await?;
+            if let Some(modal) = submitted {
+                match SettingKey::DefaultVolume.parse(None, &modal.volume, locale) {
+                    Ok(v) => choices.volume = v.parse().unwrap_or(choices.volume),
+                    Err(msg) => {
+                        ctx.send(CreateReply::default().content(msg).ephemeral(true)).await?;
+                    }
+                }
+            }
+            let (embed, rows) = render(steps[index], index, steps.len(), &choices, owner, &theme);
+            reply.edit(ctx, CreateReply::default().embed(embed).components(rows)).await?;
+            continue;
+        }
+
+        if id == "setup:cancel" {
+            let embed = theme.embed().title("Setup").description("Setup cancelled; nothing was changed.");
+            let update = CreateInteractionResponseMessage::new().embed(embed).components(vec![]);
+            press.create_response(&sctx.http, CreateInteractionResponse::UpdateMessage(update)).await?;
+            return Ok(());
+        }
+
+        if id == "setup:save" {
+            let (text, done) = match choices.save(sctx, guild_id, owner).await {
+                Ok(()) => (format!("Saved. Change any of these later with `settings set`.\n\n{}", choices.summary(owner)), true),
+                Err(e) => (format!("Saving failed: {}", e), false),
+            };
+            let embed = theme.embed().title("Setup").description(text);
+            let rows = if done { vec![] } else { render(steps[index], index, steps.len(), &choices, owner, &theme).1 };
+            let update = CreateInteractionResponseMessage::new().embed(embed).components(rows);
+            press.create_response(&sctx.http, CreateInteractionResponse::UpdateMessage(update)).await?;
+            if done {
+                return Ok(());
+            }
+            continue;
+        }
+
+        match id {
+            "setup:next" => index = (index + 1).min(steps.len() - 1),
+            "setup:back" => index = index.saturating_sub(1),
+            _ => {
+                if let Some(v) = id.strip_prefix("setup:volume:").and_then(|v| v.parse().ok()) {
+                    choices.volume = v;
+                } else {
+                    apply_select(&press, &mut choices);
+                }
+            }
+        }
+        let (embed, rows) = render(steps[index], index, steps.len(), &choices, owner, &theme);
+        let update = CreateInteractionResponseMessage::new().embed(embed).components(rows);
+        press.create_response(&sctx.http, CreateInteractionResponse::UpdateMessage(update)).await?;
+    }
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![setup()]
+}
@@ -0,0 +1,39 @@
+use poise::serenity_prelude as serenity;
+use serenity::async_trait;
+use serenity::prelude::*;
+
+use super::{Playable, Source, Track};
+use crate::{Error, TrackMeta};
+
+/// `file:<path>` requests for audio files under the configured `music.local_dir`. Off unless
+/// that's set; paths that lead outside it are refused.
+pub struct Local;
+
+#[async_trait]
+impl Source for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn can_handle(&self, query: &str) -> bool {
+        query.starts_with("file:")
+    }
+
+    async fn resolve(&self, ctx: &Context, query: &str) -> Result<Vec<Track>, Error> {
+        let Some(dir) = crate::config::current(ctx).await.music.local_dir.clone() else {
+            return Err("local files aren't enabled (`music.local_dir` isn't set)".into());
+        };
+        let relative = query.trim_start_matches("file:").trim_start_matches("//").trim_start_matches('/');
+        let root = tokio::fs::canonicalize(&dir).await.map_err(|e| format!("`music.local_dir` ({}): {}", dir, e))?;
+        let path = tokio::fs::canonicalize(root.join(relative)).await.map_err(|_| "no such file")?;
+        // Also catches `..` and symlinks out of the directory
+        if !path.starts_with(&root) || !path.is_file() {
+            return Err("no such file".into());
+        }
+
+        let title = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+        let mut track = Track::new(Playable::File(path), title.clone().unwrap_or_else(|| relative.to_string()));
+        track.meta = Some(TrackMeta { title, ..Default::default() });
+        Ok(vec![track])
+    }
+}
@@ -0,0 +1,112 @@
+//! Where `music play` requests are resolved. Each source recognises the queries it can play and
+//! turns them into something the player can start; they're tried in `registry` order and the
+//! first that claims a query resolves it. Each lives behind a Cargo feature of the same name,
+//! so a deployment can compile out the ones it doesn't use, and adding one doesn't touch the
+//! player.
+
+// A build with no sources at all compiles, but has nothing to put in the registry
+#![cfg_attr(not(any(feature = "youtube", feature = "spotify", feature = "soundcloud", feature = "radio", feature = "local")), allow(dead_code, unused_mut))]
+
+use poise::serenity_prelude as serenity;
+use serenity::async_trait;
+use serenity::prelude::*;
+use std::path::PathBuf;
+
+use crate::preferences::SearchSource;
+use crate::{Error, TrackMeta};
+
+#[cfg(feature = "local")]
+pub mod local;
+#[cfg(feature = "radio")]
+pub mod radio;
+#[cfg(feature = "soundcloud")]
+pub mod soundcloud;
+#[cfg(feature = "spotify")]
+pub mod spotify;
+#[cfg(feature = "youtube")]
+pub mod youtube;
+
+/// How the player gets a resolved track's audio. The player handles every variant, whichever
+/// sources are compiled in to build them.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub enum Playable {
+    /// A page yt-dlp can play: YouTube, SoundCloud, and most other sites.
+    Page(String),
+    /// A YouTube search through yt-dlp; the first result plays.
+    Search(String),
+    /// A shell command that writes the audio to stdout (the Spotify stream helper), with what to
+    /// play instead if it fails.
+    Command { command: String, fallback: Box<Playable> },
+    /// A direct audio URL ffmpeg reads, such as an internet radio stream.
+    Stream(String),
+    /// A file on the bot's machine.
+    File(PathBuf),
+}
+
+/// A track a source resolved a query to.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub playable: Playable,
+    /// How the request is named in messages, and the search text for yt-dlp fallbacks.
+    pub label: String,
+    /// Whatever the source already knows about the track; stored for the control panel before
+    /// it starts.
+    pub meta: Option<TrackMeta>,
+}
+
+impl Track {
+    pub fn new(playable: Playable, label: impl Into<String>) -> Self {
+        Track { playable, label: label.into(), meta: None }
+    }
+}
+
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Short name for logs, e.g. `youtube`.
+    fn name(&self) -> &'static str;
+
+    /// Whether this source should resolve `query` (a URL or search text). Only looks at the
+    /// text; no requests.
+    fn can_handle(&self, query: &str) -> bool;
+
+    /// The tracks `query` stands for, with what's known about them. Most sources return one.
+    async fn resolve(&self, ctx: &Context, query: &str) -> Result<Vec<Track>, Error>;
+}
+
+/// The compiled-in sources, most specific first. YouTube goes last: it takes any search that
+/// isn't a link.
+fn registry() -> Vec<Box<dyn Source>> {
+    let mut sources: Vec<Box<dyn Source>> = Vec::new();
+    #[cfg(feature = "spotify")]
+    sources.push(Box::new(spotify::Spotify));
+    #[cfg(feature = "soundcloud")]
+    sources.push(Box::new(soundcloud::SoundCloud));
+    #[cfg(feature = "local")]
+    sources.push(Box::new(local::Local));
+    #[cfg(feature = "radio")]
+    sources.push(Box::new(radio::Radio));
+    #[cfg(feature = "youtube")]
+    sources.push(Box::new(youtube::YouTube));
+    sources
+}
+
+pub fn is_url(query: &str) -> bool {
+    query.starts_with("http://") || query.starts_with("https://")
+}
+
+/// Resolve `query` with the first source that handles it, to the track to play. `search` is the
+/// requester's preference for plain-text searches (see `preferences`).
+#[cfg_attr(not(feature = "youtube"), allow(unused_variables))]
+pub async fn resolve(ctx: &Context, query: &str, search: SearchSource) -> Result<Track, Error> {
+    let query = query.trim();
+    #[cfg(feature = "youtube")]
+    if search == SearchSource::YouTube && !is_url(query) {
+        return Ok(youtube::plain_search(query));
+    }
+    let Some(source) = registry().into_iter().find(|s| s.can_handle(query)) else {
+        return Err("None of this bot's music sources can play that.".into());
+    };
+    let tracks = source.resolve(ctx, query).await.map_err(|e| format!("{} couldn't resolve that: {}", source.name(), e))?;
+    tracks.into_iter().next().ok_or_else(|| format!("{} found nothing for that", source.name()).into())
+}
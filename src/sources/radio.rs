@@ -0,0 +1,117 @@
+use poise::serenity_prelude as serenity;
+use reqwest::{Client, Url};
+use serenity::async_trait;
+use serenity::prelude::*;
+use std::net::IpAddr;
+
+use super::{Playable, Source, Track};
+use crate::{Error, TrackMeta};
+
+/// File extensions of direct audio links and of the playlist files radio stations publish.
+const AUDIO: &[&str] = &[".mp3", ".aac", ".ogg", ".opus", ".flac", ".m4a", ".wav"];
+const PLAYLISTS: &[&str] = &[".pls", ".m3u"];
+
+/// Direct audio links: internet radio streams (and their `.pls`/`.m3u` playlists) and audio
+/// files on a web server, read straight by ffmpeg.
+pub struct Radio;
+
+fn extension_of(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rfind('.').map(|i| name[i..].to_ascii_lowercase()).unwrap_or_default()
+}
+
+/// Whether `ip` is on the public internet, not this machine or a private network.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let shared = v4.octets()[0] == 100 && (v4.octets()[1] & 0xc0) == 64;
+            !(v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_unspecified() || v4.is_broadcast() || v4.is_multicast() || shared)
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback() || v6.is_unspecified() || v6.is_multicast() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Check that `url` is an http(s) link to a public host before the bot (or ffmpeg) fetches it;
+/// members pick these links, and they shouldn't reach services on the bot's own network.
+async fn check_public(url: &str) -> Result<(), Error> {
+    let parsed = Url::parse(url).map_err(|_| "that isn't a valid link")?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("only http and https streams can be played".into());
+    }
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let host = parsed.host_str().ok_or("that link has no host")?;
+    let public = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let addrs: Vec<_> = tokio::net::lookup_host((host, port)).await.map_err(|_| "that host couldn't be found")?.collect();
+            !addrs.is_empty() && addrs.iter().all(|a| is_public_ip(a.ip()))
+        }
+    };
+    if !public {
+        return Err("that link points at a private or local address".into());
+    }
+    Ok(())
+}
+
+/// The first stream URL in a `.pls` (`File1=...`) or `.m3u` playlist.
+fn first_entry(playlist: &str) -> Option<String> {
+    playlist.lines().map(str::trim).find_map(|line| {
+        let url = line.split_once('=').filter(|(k, _)| k.starts_with("File")).map_or(line, |(_, v)| v);
+        super::is_url(url).then(|| url.to_string())
+    })
+}
+
+#[async_trait]
+impl Source for Radio {
+    fn name(&self) -> &'static str {
+        "radio"
+    }
+
+    fn can_handle(&self, query: &str) -> bool {
+        let ext = extension_of(query);
+        let has_host = Url::parse(query).is_ok_and(|url| url.host_str().is_some());
+        super::is_url(query) && has_host && (AUDIO.contains(&ext.as_str()) || PLAYLISTS.contains(&ext.as_str()))
+    }
+
+    async fn resolve(&self, _ctx: &Context, query: &str) -> Result<Vec<Track>, Error> {
+        check_public(query).await?;
+        let client = Client::new();
+        let mut url = query.to_string();
+        if PLAYLISTS.contains(&extension_of(query).as_str()) {
+            let playlist = client.get(query).send().await?.error_for_status()?.text().await?;
+            url = first_entry(&playlist).ok_or("the playlist has no stream in it")?;
+            check_public(&url).await?;
+        }
+
+        // Only the headers are read: a station names itself in `icy-name`, and a stream with no
+        // length is live and gets reconnected when it drops
+        let res = client.get(&url).header("Icy-MetaData", "0").send().await?.error_for_status()?;
+        let name = res.headers().get("icy-name").and_then(|v| v.to_str().ok()).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        let is_live = res.content_length().is_none();
+        drop(res);
+
+        let mut track = Track::new(Playable::Stream(url), name.clone().unwrap_or_else(|| query.to_string()));
+        track.meta = Some(TrackMeta { title: name, is_live, ..Default::default() });
+        Ok(vec![track])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refuses_local_and_private_hosts() {
+        for url in ["http://127.0.0.1/radio.mp3", "http://[::1]/radio.mp3", "http://192.168.1.5:8000/live.pls", "http://169.254.169.254/a.mp3", "ftp://1.1.1.1/a.mp3"] {
+            assert!(check_public(url).await.is_err(), "{url}");
+        }
+        assert!(check_public("http://1.1.1.1/radio.mp3").await.is_ok());
+    }
+}
@@ -0,0 +1,24 @@
+use poise::serenity_prelude as serenity;
+use serenity::async_trait;
+use serenity::prelude::*;
+
+use super::{Playable, Source, Track};
+use crate::Error;
+
+/// SoundCloud track links, played through yt-dlp.
+pub struct SoundCloud;
+
+#[async_trait]
+impl Source for SoundCloud {
+    fn name(&self) -> &'static str {
+        "soundcloud"
+    }
+
+    fn can_handle(&self, query: &str) -> bool {
+        super::is_url(query) && (query.contains("soundcloud.com/") || query.contains("snd.sc/"))
+    }
+
+    async fn resolve(&self, _ctx: &Context, query: &str) -> Result<Vec<Track>, Error> {
+        Ok(vec![Track::new(Playable::Page(query.to_string()), query)])
+    }
+}
@@ -0,0 +1,88 @@
+use poise::serenity_prelude as serenity;
+use serenity::async_trait;
+use serenity::prelude::*;
+
+use super::{Playable, Source, Track};
+use crate::spotify::{SpotifyApi, SpotifyLink};
+use crate::{Error, TrackMeta};
+
+/// Spotify track and podcast links. The stream helper plays them when one is set up; otherwise
+/// the same recording is found on YouTube by its ISRC, an episode's audio comes from the show's
+/// RSS feed, and failing both the title is searched.
+pub struct Spotify;
+
+#[async_trait]
+impl Source for Spotify {
+    fn name(&self) -> &'static str {
+        "spotify"
+    }
+
+    fn can_handle(&self, query: &str) -> bool {
+        super::is_url(query) && query.contains("spotify")
+    }
+
+    async fn resolve(&self, ctx: &Context, query: &str) -> Result<Vec<Track>, Error> {
+        // Without the Web API the link's text is all there is to search for
+        let mut track = Track::new(Playable::Search(query.to_string()), query);
+        let mut uri = query.to_string();
+        let api = SpotifyApi::from_env();
+        let Ok(token) = api.token_from_env().await else {
+            return Ok(vec![with_helper(track, uri)]);
+        };
+
+        match crate::spotify::parse_link(query) {
+            Some(SpotifyLink::Track(id)) => {
+                if let Ok(Some(info)) = api.track(&token.access_token, &id).await {
+                    track.label = format!("{} {}", info.title, info.artist);
+                    track.playable = Playable::Search(track.label.clone());
+                    if let Some(isrc) = &info.isrc {
+                        let cookie_args = crate::config::current(ctx).await.music.cookie_args();
+                        if let Some(url) = crate::isrc::youtube_match(isrc, info.duration, &cookie_args).await {
+                            track.playable = Playable::Page(url);
+                        }
+                    }
+                    track.meta = Some(TrackMeta {
+                        title: Some(info.title),
+                        artist: Some(info.artist),
+                        duration: info.duration,
+                        thumbnail: info.thumbnail,
+                        ..Default::default()
+                    });
+                }
+            }
+            // Podcast episode (or a show's newest one)
+            Some(link) => {
+                if let Ok(Some(episode)) = api.episode(&token.access_token, &link).await {
+                    track.label = format!("{} {}", episode.show, episode.title);
+                    uri = format!("spotify:episode:{}", episode.id);
+                    track.playable = match crate::podcasts::episode_audio_url(&episode.show, &episode.title).await {
+                        Some(url) => Playable::Page(url),
+                        None => Playable::Search(track.label.clone()),
+                    };
+                    track.meta = Some(TrackMeta {
+                        title: Some(episode.title),
+                        artist: Some(episode.show),
+                        duration: episode.duration,
+                        thumbnail: episode.thumbnail,
+                        ..Default::default()
+                    });
+                }
+            }
+            None => {}
+        }
+        Ok(vec![with_helper(track, uri)])
+    }
+}
+
+/// Hand `track` to the stream helper as `uri` first, keeping what it resolved to as the fallback.
+/// Without a helper, or with `SPOTIFY_PREFER_YOUTUBE` set, the fallback is all there is.
+fn with_helper(mut track: Track, uri: String) -> Track {
+    let prefer_youtube = std::env::var("SPOTIFY_PREFER_YOUTUBE").is_ok_and(|s| matches!(s.as_str(), "1" | "true" | "TRUE" | "True"));
+    if prefer_youtube {
+        return track;
+    }
+    if let Some(command) = crate::music::spotify_stream_cmd(&uri) {
+        track.playable = Playable::Command { command, fallback: Box::new(track.playable) };
+    }
+    track
+}
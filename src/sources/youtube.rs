@@ -0,0 +1,71 @@
+use poise::serenity_prelude as serenity;
+use serenity::async_trait;
+use serenity::prelude::*;
+
+use super::{Playable, Source, Track};
+use crate::Error;
+
+/// Hosts of YouTube links, including short links and YouTube Music.
+const HOSTS: &[&str] = &["youtube.com", "www.youtube.com", "m.youtube.com", "music.youtube.com", "youtu.be"];
+
+/// YouTube links and searches: anything that isn't a link is searched on YouTube.
+pub struct YouTube;
+
+#[async_trait]
+impl Source for YouTube {
+    fn name(&self) -> &'static str {
+        "youtube"
+    }
+
+    fn can_handle(&self, query: &str) -> bool {
+        if !super::is_url(query) {
+            return true;
+        }
+        reqwest::Url::parse(query).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase)).is_some_and(|host| HOSTS.contains(&host.as_str()))
+    }
+
+    async fn resolve(&self, _ctx: &Context, query: &str) -> Result<Vec<Track>, Error> {
+        if super::is_url(query) {
+            return Ok(vec![Track::new(Playable::Page(query.to_string()), query)]);
+        }
+        Ok(vec![Track::new(Playable::Search(search_text(query).await), query)])
+    }
+}
+
+/// A YouTube search for `query` as typed, for members who'd rather skip the Spotify lookup.
+pub fn plain_search(query: &str) -> Track {
+    Track::new(Playable::Search(query.to_string()), query)
+}
+
+/// The text to search YouTube for: the best Spotify match's "title artist" when the Web API is
+/// configured, which finds the song rather than whatever video mentions the words.
+#[cfg(feature = "spotify")]
+async fn search_text(query: &str) -> String {
+    match crate::spotify::first_then_query(query).await {
+        Ok(Some(s)) => s,
+        Ok(None) => query.to_string(),
+        Err(e) => {
+            tracing::warn!("Spotify lookup failed, falling back to direct search: {e:?}");
+            query.to_string()
+        }
+    }
+}
+
+#[cfg(not(feature = "spotify"))]
+async fn search_text(query: &str) -> String {
+    query.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_searches_and_youtube_links_only() {
+        assert!(YouTube.can_handle("never gonna give you up"));
+        assert!(YouTube.can_handle("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert!(YouTube.can_handle("https://youtu.be/dQw4w9WgXcQ"));
+        assert!(!YouTube.can_handle("https://example.com/watch?v=dQw4w9WgXcQ"));
+        assert!(!YouTube.can_handle("https://youtube.com.example.com/watch"));
+    }
+}
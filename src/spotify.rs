@@ -0,0 +1,382 @@
+use base64::engine::general_purpose::STANDARD as B64_ENGINE;
+use base64::Engine;
+use reqwest::Client;
+use serde::Deserialize;
+use std::env;
+use std::time::Duration;
+
+use crate::Error;
+
+const ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+const API_URL: &str = "https://api.spotify.com/v1";
+
+#[derive(Deserialize)]
+pub struct SpotifyToken {
+    pub access_token: String,
+}
+
+#[derive(Deserialize)]
+struct SpotifySearch {
+    tracks: SpotifyTracks,
+}
+
+#[derive(Deserialize)]
+struct SpotifyTracks {
+    items: Vec<SpotifyTrack>,
+}
+
+#[derive(Deserialize)]
+struct SpotifyTrack {
+    name: String,
+    artists: Vec<SpotifyArtist>,
+}
+
+#[derive(Deserialize)]
+struct SpotifyArtist {
+    name: String,
+}
+
+/// A track from the Spotify Web API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotifyTrackInfo {
+    pub title: String,
+    pub artist: String,
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+    /// Identifies the exact recording, for matching it on YouTube (see `isrc`).
+    pub isrc: Option<String>,
+}
+
+/// A podcast episode from the Spotify Web API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotifyEpisode {
+    pub id: String,
+    pub title: String,
+    pub show: String,
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+}
+
+impl SpotifyEpisode {
+    fn from_json(v: &serde_json::Value, show: &str) -> Option<Self> {
+        Some(SpotifyEpisode {
+            id: v.get("id")?.as_str()?.to_string(),
+            title: v.get("name")?.as_str()?.to_string(),
+            show: show.to_string(),
+            duration: v.get("duration_ms").and_then(|d| d.as_u64()).map(Duration::from_millis),
+            thumbnail: v.get("images").and_then(|i| i.get(0)).and_then(|i| i.get("url")).and_then(|u| u.as_str()).map(|s| s.to_string()),
+        })
+    }
+}
+
+/// What a Spotify link points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpotifyLink {
+    Track(String),
+    Episode(String),
+    /// A podcast; its newest episode is played.
+    Show(String),
+}
+
+// Parse track id from a spotify URL or URI, returning the 'id' part
+pub fn parse_track_id(s: &str) -> Option<String> {
+    parse_id(s, "track")
+}
+
+// Parse the id of a `kind` (track, episode, show) link: `spotify:kind:ID` or `https://open.spotify.com/kind/ID`
+fn parse_id(s: &str, kind: &str) -> Option<String> {
+    let uri_prefix = format!("spotify:{}:", kind);
+    if let Some(pos) = s.find(&uri_prefix) {
+        return s[pos + uri_prefix.len()..].split(&['?', '&'][..]).next().map(|x| x.to_string());
+    }
+
+    let path = format!("/{}/", kind);
+    if let Some(idx) = s.find(&path) {
+        return s[idx + path.len()..].split(&['?', '&', '/'][..]).next().map(|x| x.to_string());
+    }
+
+    None
+}
+
+pub fn parse_link(s: &str) -> Option<SpotifyLink> {
+    if let Some(id) = parse_id(s, "track") {
+        return Some(SpotifyLink::Track(id));
+    }
+    if let Some(id) = parse_id(s, "episode") {
+        return Some(SpotifyLink::Episode(id));
+    }
+    parse_id(s, "show").map(SpotifyLink::Show)
+}
+
+/// The Spotify Web API. Requests go to Spotify unless `SPOTIFY_ACCOUNTS_URL`/`SPOTIFY_API_URL`
+/// point somewhere else, such as a proxy or, in tests, a mock server.
+#[derive(Debug, Clone)]
+pub struct SpotifyApi {
+    client: Client,
+    accounts_url: String,
+    api_url: String,
+    /// Episodes need a market with client-credentials tokens; `SPOTIFY_MARKET`, default `US`.
+    market: String,
+}
+
+impl SpotifyApi {
+    pub fn new(accounts_url: impl Into<String>, api_url: impl Into<String>) -> Self {
+        SpotifyApi {
+            client: Client::new(),
+            accounts_url: accounts_url.into().trim_end_matches('/').to_string(),
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+            market: "US".to_string(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let mut api = SpotifyApi::new(
+            env::var("SPOTIFY_ACCOUNTS_URL").unwrap_or_else(|_| ACCOUNTS_URL.to_string()),
+            env::var("SPOTIFY_API_URL").unwrap_or_else(|_| API_URL.to_string()),
+        );
+        if let Ok(market) = env::var("SPOTIFY_MARKET") {
+            api.market = market;
+        }
+        api
+    }
+
+    /// A client-credentials token.
+    pub async fn token(&self, client_id: &str, client_secret: &str) -> Result<SpotifyToken, Error> {
+        let auth = format!("{}:{}", client_id, client_secret);
+        let auth_b64 = B64_ENGINE.encode(auth);
+
+        let res = self
+            .client
+            .post(format!("{}/api/token", self.accounts_url))
+            .header("Authorization", format!("Basic {}", auth_b64))
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let token: SpotifyToken = res.json().await?;
+        Ok(token)
+    }
+
+    // Convenience wrapper to fetch a token using env vars (returns SpotifyToken or Err)
+    pub async fn token_from_env(&self) -> Result<SpotifyToken, Error> {
+        let client_id = env::var("SPOTIFY_CLIENT_ID").map_err(|_| "SPOTIFY_CLIENT_ID not set")?;
+        let client_secret = env::var("SPOTIFY_CLIENT_SECRET").map_err(|_| "SPOTIFY_CLIENT_SECRET not set")?;
+        self.token(&client_id, &client_secret).await
+    }
+
+    /// The best match for `query` as (title, artist).
+    pub async fn search_track(&self, token: &str, query: &str) -> Result<Option<(String, String)>, Error> {
+        let res = self
+            .client
+            .get(format!("{}/search", self.api_url))
+            .query(&[("q", query), ("type", "track"), ("limit", "1")])
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let data: SpotifySearch = res.json().await?;
+        let track = data.tracks.items.into_iter().next();
+        Ok(track.map(|t| {
+            let artist = t
+                .artists
+                .first()
+                .map(|a| a.name.clone())
+                .unwrap_or_else(|| "Unknown".to_string());
+            (t.name, artist)
+        }))
+    }
+
+    // Fetch a Spotify track by its id using the Web API
+    pub async fn track(&self, token: &str, id: &str) -> Result<Option<SpotifyTrackInfo>, Error> {
+        let url = format!("{}/tracks/{}", self.api_url, id);
+        let res = self.client.get(&url).bearer_auth(token).send().await?.error_for_status()?;
+        let v: serde_json::Value = res.json().await?;
+
+        let name = v.get("name").and_then(|s| s.as_str()).map(|s| s.to_string());
+        let artist = v.get("artists").and_then(|a| a.as_array()).and_then(|arr| arr.first()).and_then(|a0| a0.get("name")).and_then(|n| n.as_str()).map(|s| s.to_string());
+        let duration = v.get("duration_ms").and_then(|d| d.as_u64()).map(Duration::from_millis);
+        let thumbnail = v.get("album").and_then(|al| al.get("images")).and_then(|imgs| imgs.as_array()).and_then(|arr| arr.first()).and_then(|i0| i0.get("url")).and_then(|u| u.as_str()).map(|s| s.to_string());
+        let isrc = v.get("external_ids").and_then(|ids| ids.get("isrc")).and_then(|i| i.as_str()).map(|s| s.to_string());
+
+        if let (Some(title), Some(artist)) = (name, artist) {
+            Ok(Some(SpotifyTrackInfo { title, artist, duration, thumbnail, isrc }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Resolve an episode link, or a show link to its newest episode.
+    pub async fn episode(&self, token: &str, link: &SpotifyLink) -> Result<Option<SpotifyEpisode>, Error> {
+        match link {
+            SpotifyLink::Episode(id) => {
+                let url = format!("{}/episodes/{}", self.api_url, id);
+                let v: serde_json::Value =
+                    self.client.get(&url).query(&[("market", &self.market)]).bearer_auth(token).send().await?.error_for_status()?.json().await?;
+                let show = v.get("show").and_then(|s| s.get("name")).and_then(|n| n.as_str()).unwrap_or_default();
+                Ok(SpotifyEpisode::from_json(&v, show))
+            }
+            SpotifyLink::Show(id) => {
+                // The show object embeds its first page of episodes, newest first
+                let url = format!("{}/shows/{}", self.api_url, id);
+                let v: serde_json::Value =
+                    self.client.get(&url).query(&[("market", &self.market)]).bearer_auth(token).send().await?.error_for_status()?.json().await?;
+                let show = v.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+                let latest = v.get("episodes").and_then(|e| e.get("items")).and_then(|i| i.get(0));
+                Ok(latest.and_then(|e| SpotifyEpisode::from_json(e, show)))
+            }
+            SpotifyLink::Track(_) => Ok(None),
+        }
+    }
+}
+
+/// "title artist" of the best Spotify match for a plain search, to search YouTube with; `None`
+/// when the Web API isn't configured or nothing matches.
+pub async fn first_then_query(user_query: &str) -> Result<Option<String>, Error> {
+    let client_id = match env::var("SPOTIFY_CLIENT_ID") {
+        Ok(v) if !v.is_empty() => v,
+        _ => return Ok(None),
+    };
+    let client_secret = match env::var("SPOTIFY_CLIENT_SECRET") {
+        Ok(v) if !v.is_empty() => v,
+        _ => return Ok(None),
+    };
+
+    let api = SpotifyApi::from_env();
+    let token = api.token(&client_id, &client_secret).await?;
+    let track = api.search_track(&token.access_token, user_query).await?;
+
+    Ok(track.map(|(name, artist)| format!("{} {}", name, artist)))
+}
+
+/// ISRC of the track a Spotify track link points at, if the Web API is configured.
+pub async fn isrc(query: &str) -> Option<String> {
+    let id = parse_track_id(query)?;
+    let api = SpotifyApi::from_env();
+    let token = api.token_from_env().await.ok()?;
+    api.track(&token.access_token, &id).await.ok().flatten()?.isrc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn api() -> (MockServer, SpotifyApi) {
+        let server = MockServer::start().await;
+        let api = SpotifyApi::new(server.uri(), format!("{}/v1", server.uri()));
+        (server, api)
+    }
+
+    #[test]
+    fn parses_links() {
+        assert_eq!(parse_link("https://open.spotify.com/track/abc123?si=x"), Some(SpotifyLink::Track("abc123".into())));
+        assert_eq!(parse_link("spotify:episode:ep1"), Some(SpotifyLink::Episode("ep1".into())));
+        assert_eq!(parse_link("https://open.spotify.com/show/sh1/"), Some(SpotifyLink::Show("sh1".into())));
+        assert_eq!(parse_link("https://example.com/"), None);
+    }
+
+    #[tokio::test]
+    async fn token_uses_client_credentials() {
+        let (server, api) = api().await;
+        Mock::given(method("POST"))
+            .and(path("/api/token"))
+            .and(header("Authorization", "Basic aWQ6c2VjcmV0"))
+            .and(body_string("grant_type=client_credentials"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "access_token": "tok", "expires_in": 3600 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let token = api.token("id", "secret").await.unwrap();
+        assert_eq!(token.access_token, "tok");
+    }
+
+    #[tokio::test]
+    async fn token_fails_on_rejected_credentials() {
+        let (server, api) = api().await;
+        Mock::given(path("/api/token")).respond_with(ResponseTemplate::new(401)).mount(&server).await;
+
+        assert!(api.token("id", "wrong").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn looks_up_tracks() {
+        let (server, api) = api().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/tracks/abc"))
+            .and(header("Authorization", "Bearer tok"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "Song",
+                "artists": [{ "name": "Band" }, { "name": "Guest" }],
+                "duration_ms": 215000,
+                "album": { "images": [{ "url": "https://img/1" }] },
+                "external_ids": { "isrc": "USRC17607839" }
+            })))
+            .mount(&server)
+            .await;
+
+        let track = api.track("tok", "abc").await.unwrap().unwrap();
+        assert_eq!(
+            track,
+            SpotifyTrackInfo {
+                title: "Song".into(),
+                artist: "Band".into(),
+                duration: Some(Duration::from_millis(215000)),
+                thumbnail: Some("https://img/1".into()),
+                isrc: Some("USRC17607839".into()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn track_without_artist_is_none() {
+        let (server, api) = api().await;
+        Mock::given(path("/v1/tracks/abc"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "name": "Song", "artists": [] })))
+            .mount(&server)
+            .await;
+
+        assert_eq!(api.track("tok", "abc").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn searches_tracks() {
+        let (server, api) = api().await;
+        Mock::given(path("/v1/search"))
+            .and(query_param("q", "never gonna"))
+            .and(query_param("type", "track"))
+            .and(query_param("limit", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "tracks": { "items": [{ "name": "Never Gonna Give You Up", "artists": [{ "name": "Rick Astley" }] }] }
+            })))
+            .mount(&server)
+            .await;
+
+        let found = api.search_track("tok", "never gonna").await.unwrap();
+        assert_eq!(found, Some(("Never Gonna Give You Up".into(), "Rick Astley".into())));
+    }
+
+    #[tokio::test]
+    async fn show_resolves_to_newest_episode() {
+        let (server, api) = api().await;
+        Mock::given(path("/v1/shows/sh1"))
+            .and(query_param("market", "US"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "The Show",
+                "episodes": { "items": [
+                    { "id": "new", "name": "Latest", "duration_ms": 60000 },
+                    { "id": "old", "name": "Older" }
+                ] }
+            })))
+            .mount(&server)
+            .await;
+
+        let episode = api.episode("tok", &SpotifyLink::Show("sh1".into())).await.unwrap().unwrap();
+        assert_eq!(episode.id, "new");
+        assert_eq!(episode.show, "The Show");
+        assert_eq!(episode.duration, Some(Duration::from_secs(60)));
+    }
+}
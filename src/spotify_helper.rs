@@ -0,0 +1,93 @@
+use poise::serenity_prelude as serenity;
+use serde::Deserialize;
+use serenity::all::GuildId;
+use serenity::prelude::*;
+use songbird::tracks::{PlayMode, TrackHandle};
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::player;
+
+/// After the helper reports the end of the track, how long the buffered audio gets to drain
+/// before the track is stopped anyway.
+const END_GRACE: Duration = Duration::from_secs(10);
+
+/// One line of `librespot-wrapper --json` output.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Status {
+    Started {
+        title: Option<String>,
+        artist: Option<String>,
+        duration_ms: Option<u64>,
+    },
+    /// Songbird keeps its own play time, so these are only read to tell the helper is alive.
+    Position {},
+    Ended,
+    Error {
+        message: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Whether `handle` is still the guild's current track, i.e. it wasn't skipped meanwhile.
+async fn is_current(ctx: &Context, guild_id: GuildId, handle: &TrackHandle) -> bool {
+    player::handle(ctx, guild_id).await.is_some_and(|h| h.uuid() == handle.uuid())
+}
+
+async fn apply(ctx: &Context, guild_id: GuildId, handle: &TrackHandle, status: Status) {
+    match status {
+        Status::Started { title, artist, duration_ms } => {
+            if !is_current(ctx, guild_id, handle).await {
+                return;
+            }
+            let Some(store) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() else { return };
+            let mut metas = store.lock().await;
+            let meta = metas.entry(guild_id).or_default();
+            meta.title = title.or(meta.title.take());
+            meta.artist = artist.or(meta.artist.take());
+            meta.duration = duration_ms.map(Duration::from_millis).or(meta.duration);
+            meta.is_live = false;
+        }
+        Status::Ended => {
+            let (ctx, guild_id, handle) = (ctx.clone(), guild_id, handle.clone());
+            tokio::spawn(async move {
+                tokio::time::sleep(END_GRACE).await;
+                let playing = handle.get_info().await.is_ok_and(|i| i.playing == PlayMode::Play);
+                if playing && is_current(&ctx, guild_id, &handle).await {
+                    tracing::warn!("Spotify helper finished in guild {} but the stream didn't end; stopping it", guild_id);
+                    let _ = handle.stop();
+                }
+            });
+        }
+        Status::Error { message } => tracing::warn!("Spotify helper error in guild {}: {}", guild_id, message),
+        Status::Position {} | Status::Other => {}
+    }
+}
+
+/// Read the helper's stderr for the life of the stream: JSON status lines update the track's
+/// metadata and end it once the helper is done, anything else is logged. Reading it also keeps
+/// a chatty helper from blocking on a full pipe.
+pub fn watch(ctx: &Context, guild_id: GuildId, stderr: std::process::ChildStderr, handle: TrackHandle) {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in std::io::BufReader::new(stderr).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            match serde_json::from_str::<Status>(&line) {
+                Ok(status) => apply(&ctx, guild_id, &handle, status).await,
+                Err(_) if !line.trim().is_empty() => tracing::warn!("[spotify helper] {}", line),
+                Err(_) => {}
+            }
+        }
+    });
+}
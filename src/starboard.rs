@@ -0,0 +1,324 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::builder::{CreateAllowedMentions, CreateEmbed, CreateEmbedAuthor, CreateMessage, EditMessage};
+use serenity::model::channel::{Message, Reaction, ReactionType};
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::{Ctx, Error};
+
+pub const STARBOARD_PATH: &str = "starboard.json";
+
+const STAR: &str = "⭐";
+const DEFAULT_THRESHOLD: u64 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StarboardConfig {
+    pub channel: u64,
+    pub threshold: u64,
+}
+
+/// A message that made it onto the starboard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StarPost {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub board_message: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StarboardData {
+    #[serde(default)]
+    guilds: HashMap<u64, StarboardConfig>,
+    /// original message id -> starboard post
+    #[serde(default)]
+    posts: HashMap<u64, StarPost>,
+    /// original message id -> lock taken while its post is being refreshed
+    #[serde(skip)]
+    refreshing: HashMap<u64, Arc<Mutex<()>>>,
+}
+
+pub struct StarboardStore;
+impl TypeMapKey for StarboardStore {
+    type Value = Arc<Mutex<StarboardData>>;
+}
+
+async fn load_disk() -> Result<StarboardData, Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::load_json(&crate::storage::data_path(STARBOARD_PATH)).await
+}
+
+async fn save_disk(data: &StarboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(STARBOARD_PATH), data).await
+}
+
+pub async fn ensure_starboard_store() -> Result<Arc<Mutex<StarboardData>>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = load_disk().await?;
+    Ok(Arc::new(Mutex::new(data)))
+}
+
+async fn starboard_store(ctx: &Context) -> Result<Arc<Mutex<StarboardData>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<StarboardStore>()
+        .cloned()
+        .ok_or_else(|| "Starboard store not initialised".into())
+}
+
+/// Drop `gid`'s starboard channel and threshold and its record of posted messages.
+pub async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<(), Error> {
+    let store = starboard_store(ctx).await?;
+    let mut data = store.lock().await;
+    data.guilds.remove(&gid.get());
+    data.posts.retain(|_, post| post.guild_id != gid.get());
+    save_disk(&data).await?;
+    Ok(())
+}
+
+fn is_star(emoji: &ReactionType) -> bool {
+    matches!(emoji, ReactionType::Unicode(s) if s == STAR)
+}
+
+fn star_count(msg: &Message) -> u64 {
+    msg.reactions.iter().find(|r| is_star(&r.reaction_type)).map(|r| r.count).unwrap_or(0)
+}
+
+fn board_content(count: u64, channel: ChannelId) -> String {
+    format!("{} **{}** in <#{}>", STAR, count, channel)
+}
+
+fn board_embed(msg: &Message, gid: GuildId) -> CreateEmbed {
+    let mut description: String = msg.content.chars().take(3900).collect();
+    if description.len() < msg.content.len() {
+        description.push_str("...");
+    }
+    let mut embed = CreateEmbed::new()
+        .author(CreateEmbedAuthor::new(msg.author.tag()).icon_url(msg.author.face()))
+        .description(description)
+        .field("Source", format!("[Jump to message]({})", msg.id.link(msg.channel_id, Some(gid))), false)
+        .timestamp(msg.timestamp)
+        .color(crate::EMBED_COLOR);
+    let image = msg
+        .attachments
+        .iter()
+        .find(|a| a.content_type.as_deref().is_some_and(|t| t.starts_with("image/")));
+    if let Some(a) = image {
+        embed = embed.image(&a.url);
+    }
+    embed
+}
+
+/// Re-count stars on a message and create, update or remove its starboard post.
+async fn refresh(ctx: &Context, gid: GuildId, channel_id: ChannelId, message_id: MessageId) -> Result<(), Error> {
+    let store = starboard_store(ctx).await?;
+    let (cfg, turn) = {
+        let mut data = store.lock().await;
+        let Some(cfg) = data.guilds.get(&gid.get()).cloned() else { return Ok(()) };
+        let turn = data.refreshing.entry(message_id.get()).or_default().clone();
+        (cfg, turn)
+    };
+    // Refreshes of the same message take turns, so concurrent reactions can't post it twice;
+    // the store itself isn't held across the Discord calls below
+    let result = {
+        let _turn = turn.lock().await;
+        update_post(ctx, &store, gid, channel_id, message_id, &cfg).await
+    };
+    let mut data = store.lock().await;
+    // Ours and the map's are the only references left once nobody else is waiting
+    if Arc::strong_count(&turn) <= 2 {
+        data.refreshing.remove(&message_id.get());
+    }
+    result
+}
+
+async fn update_post(
+    ctx: &Context,
+    store: &Mutex<StarboardData>,
+    gid: GuildId,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    cfg: &StarboardConfig,
+) -> Result<(), Error> {
+    let board = ChannelId::new(cfg.channel);
+    if channel_id == board {
+        return Ok(());
+    }
+
+    let msg = channel_id.message(&ctx.http, message_id).await?;
+    let count = star_count(&msg);
+    let existing = store.lock().await.posts.get(&message_id.get()).cloned();
+
+    match existing {
+        Some(post) if count >= cfg.threshold => {
+            board
+                .edit_message(
+                    &ctx.http,
+                    MessageId::new(post.board_message),
+                    EditMessage::new().content(board_content(count, channel_id)),
+                )
+                .await?;
+        }
+        Some(post) => {
+            let _ = board.delete_message(&ctx.http, MessageId::new(post.board_message)).await;
+            let mut data = store.lock().await;
+            data.posts.remove(&message_id.get());
+            save_disk(&data).await?;
+        }
+        None if count >= cfg.threshold => {
+            let post = board
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new()
+                        .content(board_content(count, channel_id))
+                        .embed(board_embed(&msg, gid))
+                        .allowed_mentions(CreateAllowedMentions::new()),
+                )
+                .await?;
+            let mut data = store.lock().await;
+            data.posts.insert(
+                message_id.get(),
+                StarPost { guild_id: gid.get(), channel_id: channel_id.get(), board_message: post.id.get() },
+            );
+            save_disk(&data).await?;
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// Handle a star being added or removed.
+pub async fn on_reaction(ctx: &Context, reaction: &Reaction) {
+    let Some(gid) = reaction.guild_id else { return };
+    if !is_star(&reaction.emoji) {
+        return;
+    }
+    if let Err(e) = refresh(ctx, gid, reaction.channel_id, reaction.message_id).await {
+        tracing::warn!("Starboard update for message {} failed: {e:?}", reaction.message_id);
+    }
+}
+
+/// Handle all reactions (or all stars) being cleared from a message.
+pub async fn on_reactions_cleared(ctx: &Context, gid: GuildId, channel_id: ChannelId, message_id: MessageId) {
+    if let Err(e) = refresh(ctx, gid, channel_id, message_id).await {
+        tracing::warn!("Starboard update for message {} failed: {e:?}", message_id);
+    }
+}
+
+/// Drop starboard posts for deleted originals, and forget posts deleted from the board itself.
+pub async fn on_messages_deleted(ctx: &Context, ids: &[MessageId]) {
+    let Ok(store) = starboard_store(ctx).await else { return };
+    let mut orphaned = Vec::new();
+    {
+        let mut data = store.lock().await;
+        let mut changed = false;
+        for id in ids {
+            if let Some(post) = data.posts.remove(&id.get()) {
+                changed = true;
+                if let Some(cfg) = data.guilds.get(&post.guild_id) {
+                    orphaned.push((ChannelId::new(cfg.channel), MessageId::new(post.board_message)));
+                }
+            }
+            let before = data.posts.len();
+            data.posts.retain(|_, p| p.board_message != id.get());
+            changed |= data.posts.len() != before;
+        }
+        if changed && let Err(e) = save_disk(&data).await {
+            tracing::warn!("Failed to save starboard: {e:?}");
+        }
+    }
+    // Deleted after letting go of the store so other guilds' reactions aren't kept waiting
+    for (board, post) in orphaned {
+        let _ = board.delete_message(&ctx.http, post).await;
+    }
+}
+
+/// Repost popular messages to a starboard channel
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands("starboard_channel", "starboard_threshold"),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn starboard(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = starboard_store(ctx.serenity_context()).await?;
+    let cfg = store.lock().await.guilds.get(&guild_id.get()).cloned();
+    match cfg {
+        Some(cfg) => {
+            ctx.say(format!("Starboard: <#{}>, {} {} needed.", cfg.channel, cfg.threshold, STAR)).await?
+        }
+        None => ctx.say("Starboard is off. Set a channel with `starboard channel #channel`.").await?,
+    };
+    Ok(())
+}
+
+/// Set the starboard channel (omit to turn the starboard off)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "channel")]
+pub async fn starboard_channel(
+    ctx: Ctx<'_>,
+    #[description = "Starboard channel"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = starboard_store(ctx.serenity_context()).await?;
+    {
+        let mut data = store.lock().await;
+        match &channel {
+            Some(c) => {
+                let threshold = data.guilds.get(&guild_id.get()).map(|g| g.threshold).unwrap_or(DEFAULT_THRESHOLD);
+                data.guilds.insert(guild_id.get(), StarboardConfig { channel: c.id.get(), threshold });
+            }
+            None => {
+                data.guilds.remove(&guild_id.get());
+                data.posts.retain(|_, p| p.guild_id != guild_id.get());
+            }
+        }
+        save_disk(&data).await?;
+    }
+    match channel {
+        Some(c) => ctx.say(format!("Starred messages will be posted in <#{}>.", c.id)).await?,
+        None => ctx.say("Starboard turned off.").await?,
+    };
+    Ok(())
+}
+
+/// Set how many stars a message needs
+#[poise::command(prefix_command, slash_command, guild_only, rename = "threshold")]
+pub async fn starboard_threshold(
+    ctx: Ctx<'_>,
+    #[description = "Stars needed"]
+    #[min = 1]
+    #[max = 100]
+    stars: u64,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = starboard_store(ctx.serenity_context()).await?;
+    let updated = {
+        let mut data = store.lock().await;
+        let updated = match data.guilds.get_mut(&guild_id.get()) {
+            Some(cfg) => {
+                cfg.threshold = stars.max(1);
+                true
+            }
+            None => false,
+        };
+        if updated {
+            save_disk(&data).await?;
+        }
+        updated
+    };
+    if updated {
+        ctx.say(format!("Messages now need {} {} to reach the starboard.", stars, STAR)).await?;
+    } else {
+        ctx.say("Set a starboard channel first with `starboard channel #channel`.").await?;
+    }
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![starboard()]
+}
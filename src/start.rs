@@ -1,149 +1,976 @@
-use crate::config::load_config;
+use crate::config;
+use crate::embeds::{self, Theme};
+use poise::{CreateReply, ReplyHandle};
+use serenity::all::{ButtonStyle, ComponentInteractionCollector};
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-pub async fn handle_start(
-    ctx: &serenity::prelude::Context,
-    channel_id: serenity::all::ChannelId,
-    args: &str,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Action names recognised as the second word of `start <service> <action>` even before a
+/// service configures them, so a missing one gets a clear reply instead of being sent as args.
+const STANDARD_ACTIONS: &[&str] = &["start", "stop", "restart", "status"];
+
+const COLOR_OK: u32 = 0x57F287;
+const COLOR_FAIL: u32 = 0xED4245;
+const COLOR_PENDING: u32 = 0xFEE75C;
+
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Run `start <service> [action] [args]`, replying through `cmd` (the interaction for slash
+/// commands) and editing that reply as jobs and health checks progress.
+pub async fn handle_start(cmd: crate::Ctx<'_>, args: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let ctx = cmd.serenity_context();
+    let channel_id = cmd.channel_id();
+    let guild_id = cmd.guild_id();
+    let user_id = cmd.author().id;
+    let theme = embeds::theme(ctx, guild_id).await;
     let trimmed = args.trim();
     if trimmed.is_empty() {
-        channel_id
-            .say(&ctx.http, "Usage: !is start <service> [args]")
-            .await?;
+        cmd.say("Usage: !is start <service> [start|stop|restart|status] [args]").await?;
         return Ok(());
     }
 
-    let mut parts = trimmed.split_whitespace();
+    let mut parts = trimmed.split_whitespace().peekable();
     let service_key = parts.next().unwrap_or("").to_string();
-    let extra_args = parts.collect::<Vec<_>>().join(" ");
 
-    let cfg = match load_config().await {
-        Ok(c) => match c.start {
-            Some(s) => s,
-            None => {
-                channel_id
-                    .say(&ctx.http, "Config missing 'start' section in config.jsonc")
-                    .await?;
-                return Ok(());
-            }
-        },
-        Err(e) => {
-            channel_id
-                .say(&ctx.http, format!(
-                    "Config not found or invalid: {e}. Expected config.jsonc in working dir (auto-created)."
-                ))
-                .await?;
-            return Ok(());
-        }
+    // Shared snapshot from the ConfigStore; edits to config.jsonc are picked up by the watcher
+    let app_config = config::current(ctx).await;
+    let Some(cfg) = app_config.start.as_ref() else {
+        cmd.say("Config missing 'start' section in config.jsonc").await?;
+        return Ok(());
     };
 
+    let services = cfg.services_for(guild_id);
+
     // Handle listing services
     if service_key.eq_ignore_ascii_case("list") {
-        if cfg.services.is_empty() {
-            channel_id
-                .say(&ctx.http, "No services configured in config.jsonc")
-                .await?;
+        if services.is_empty() {
+            cmd.say("No services configured in config.jsonc").await?;
         } else {
-            let mut names = cfg.services.keys().cloned().collect::<Vec<_>>();
-            names.sort();
-            let msg = format!("Available start services ({}): {}", names.len(), names.join(", "));
-            channel_id.say(&ctx.http, msg).await?;
+            cmd.send(CreateReply::default().embed(list_embed(&services, &theme))).await?;
         }
         return Ok(());
     }
 
-    let svc = match cfg.services.get(&service_key) {
-        Some(s) => s,
+    let svc = match services.get(service_key.as_str()) {
+        Some(s) => *s,
         None => {
-            let available = if cfg.services.is_empty() {
+            let available = if services.is_empty() {
                 "<none>".to_string()
             } else {
-                cfg.services.keys().cloned().collect::<Vec<_>>().join(", ")
+                let mut names = services.keys().copied().collect::<Vec<_>>();
+                names.sort();
+                names.join(", ")
+            };
+            cmd.say(format!("Unknown service '{service_key}'. Available: {available}")).await?;
+            return Ok(());
+        }
+    };
+
+    // `start mc server-1` still means the start action with args
+    let action_name = match parts.peek() {
+        Some(word)
+            if svc.actions.contains_key(*word)
+                || STANDARD_ACTIONS.iter().any(|a| a.eq_ignore_ascii_case(word)) =>
+        {
+            parts.next().unwrap_or("start").to_ascii_lowercase()
+        }
+        _ => "start".to_string(),
+    };
+    let extra_args = parts.collect::<Vec<_>>().join(" ");
+
+    if !is_allowed(ctx, svc, channel_id, guild_id, user_id).await {
+        cmd.say(format!("You are not allowed to use '{service_key}' here.")).await?;
+        return Ok(());
+    }
+
+    let Some(action) = svc.action(&action_name) else {
+        cmd.say(format!(
+            "Service '{service_key}' has no '{action_name}' action. Available: {}",
+            svc.action_names().join(", ")
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    if action.confirm.unwrap_or(false) && !confirm(cmd, &theme, &service_key, &action_name).await? {
+        return Ok(());
+    }
+
+    if crate::dryrun::active(ctx, guild_id).await {
+        let described = describe_action(&action_name, &action, &extra_args, &env_var)
+            .unwrap_or_else(|e| format!("fail to build the request: {e}"));
+        let action_text = format!("run '{service_key}' ({action_name}): {described}");
+        cmd.say(crate::dryrun::skip(guild_id, &action_text)).await?;
+        return Ok(());
+    }
+
+    let (status, text) = match call_action(&cfg.allow, &action_name, &action, &extra_args).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            cmd.say(format!("Request error for '{service_key}' ({action_name}): {e}")).await?;
+            return Ok(());
+        }
+    };
+
+    let embed = reply_embed(&theme, &service_key, &action_name, &action, status, &text);
+    let message = cmd.send(CreateReply::default().embed(embed)).await?;
+
+    // Accepted jobs: follow them to completion before any health wait
+    if let Some(poll) = &action.poll
+        && status.is_success()
+        && !poll_job(cmd, &message, &service_key, &action_name, &action, poll, &text).await?
+    {
+        return Ok(());
+    }
+
+    let wants_wait = matches!(action_name.as_str(), "start" | "restart" | "status");
+    let status_action = svc.action("status");
+    if let (true, Some(wait), Some(status_action)) = (wants_wait, &svc.wait, status_action) {
+        if !status.is_success() {
+            return Ok(());
+        }
+        // A status reply that is already healthy needs no polling
+        if action_name == "status" && is_healthy(&status_action, status, &text) {
+            return Ok(());
+        }
+        let started = Instant::now();
+        let timeout = Duration::from_secs(wait.timeout_secs);
+        loop {
+            tokio::time::sleep(Duration::from_secs(wait.interval_secs.max(1))).await;
+            let elapsed = started.elapsed();
+            let (status, text) = match call_action(&cfg.allow, "status", &status_action, "").await {
+                Ok(reply) => reply,
+                Err(e) => (reqwest::StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+            };
+            let healthy = is_healthy(&status_action, status, &text);
+            let mut embed = reply_embed(&theme, &service_key, "status", &status_action, status, &text);
+            embed = if healthy {
+                embed.color(COLOR_OK).footer(CreateEmbedFooter::new(format!(
+                    "Healthy after {}s",
+                    elapsed.as_secs()
+                )))
+            } else if elapsed >= timeout {
+                embed.color(COLOR_FAIL).footer(CreateEmbedFooter::new(format!(
+                    "Not healthy after {}s, gave up",
+                    elapsed.as_secs()
+                )))
+            } else {
+                embed.color(COLOR_PENDING).footer(CreateEmbedFooter::new(format!(
+                    "Waiting for healthy... {}s",
+                    elapsed.as_secs()
+                )))
             };
-            channel_id
-                .say(
+            message.edit(cmd, CreateReply::default().embed(embed)).await?;
+            if healthy || elapsed >= timeout {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reply with Confirm/Cancel buttons that only the invoking user can press. Returns true once
+/// confirmed; cancelling or letting it time out edits the prompt and returns false.
+async fn confirm(
+    cmd: crate::Ctx<'_>,
+    theme: &Theme,
+    service_key: &str,
+    action_name: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let ctx = cmd.serenity_context();
+    let user_id = cmd.author().id;
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("start:confirm").label("Confirm").style(ButtonStyle::Danger),
+        CreateButton::new("start:cancel").label("Cancel").style(ButtonStyle::Secondary),
+    ])];
+    let prompt = theme.embed()
+        .title(format!("Service: {service_key}"))
+        .description(format!("Run **{action_name}** on `{service_key}`?"))
+        .color(COLOR_PENDING);
+    let reply = cmd.send(CreateReply::default().embed(prompt).components(buttons)).await?;
+    let message = reply.message().await?;
+
+    let deadline = Instant::now() + CONFIRM_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let press = ComponentInteractionCollector::new(ctx)
+            .message_id(message.id)
+            .timeout(remaining)
+            .await;
+        let Some(press) = press else {
+            let embed = theme.embed()
+                .title(format!("Service: {service_key}"))
+                .description(format!("No confirmation, **{action_name}** was not run."))
+                .color(COLOR_FAIL);
+            reply.edit(cmd, CreateReply::default().embed(embed).components(vec![])).await?;
+            return Ok(false);
+        };
+        if press.user.id != user_id {
+            press
+                .create_response(
                     &ctx.http,
-                    format!(
-                        "Unknown service '{service_key}'. Available: {available}"
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("Only the person who ran the command can confirm it.")
+                            .ephemeral(true),
                     ),
                 )
                 .await?;
-            return Ok(());
+            continue;
         }
-    };
-
-    let method = svc
-        .method
-        .as_deref()
-        .unwrap_or("POST")
-        .to_ascii_uppercase();
-    if method != "POST" {
-        channel_id
-            .say(
+        let confirmed = press.data.custom_id == "start:confirm";
+        let text = if confirmed {
+            format!("Running **{action_name}** on `{service_key}`...")
+        } else {
+            format!("Cancelled, **{action_name}** was not run.")
+        };
+        let embed = theme.embed()
+            .title(format!("Service: {service_key}"))
+            .description(text)
+            .color(if confirmed { COLOR_PENDING } else { COLOR_FAIL });
+        press
+            .create_response(
                 &ctx.http,
-                format!("Service '{service_key}' uses unsupported method '{method}'. Only POST is supported."),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().embed(embed).components(vec![]),
+                ),
             )
             .await?;
-        return Ok(());
+        return Ok(confirmed);
+    }
+}
+
+/// Follow an accepted job until its success or failure condition matches or the poll times
+/// out, editing `message` as it goes. Returns whether the job succeeded.
+async fn poll_job(
+    cmd: crate::Ctx<'_>,
+    message: &ReplyHandle<'_>,
+    service_key: &str,
+    action_name: &str,
+    action: &config::ActionConfig,
+    poll: &config::PollConfig,
+    first_reply: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let theme = &embeds::theme(cmd.serenity_context(), cmd.guild_id()).await;
+    let first = serde_json::from_str::<serde_json::Value>(first_reply).unwrap_or(serde_json::Value::Null);
+    // `${VAR}`s are filled in before the reply goes in, and the reply's values are escaped, so
+    // a backend can't make the bot expand variables of its choosing
+    let mut secrets = Vec::new();
+    let url = substitute_env(&poll.url, &env_var, &mut secrets)?;
+    let poll_action = config::ActionConfig {
+        url: Some(render_template_with(&url, &first, first_reply, 0, url_escape)),
+        method: Some(poll.method.clone().unwrap_or_else(|| "GET".to_string())),
+        headers: action.headers.clone(),
+        timeout_secs: action.timeout_secs,
+        response: action.response.clone(),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    let timeout = Duration::from_secs(poll.timeout_secs);
+    loop {
+        tokio::time::sleep(Duration::from_secs(poll.interval_secs.max(1))).await;
+        let elapsed = started.elapsed().as_secs();
+        // Job polls are always plain HTTP, so nothing needs allow-listing
+        let (status, text) = match call_action(&config::ExecAllowList::default(), "status", &poll_action, "").await {
+            Ok((status, text)) => (status, redact(&text, &secrets)),
+            Err(e) => (reqwest::StatusCode::SERVICE_UNAVAILABLE, redact(&e.to_string(), &secrets)),
+        };
+        // A non-2xx reply or a failed request is taken as a hiccup, not the job's answer: only
+        // `failure` (or running out of time) ends the poll unsuccessfully
+        let answered = status.is_success();
+        let json = serde_json::from_str::<serde_json::Value>(&text).unwrap_or(serde_json::Value::Null);
+        let failed = answered && poll.failure.as_ref().is_some_and(|c| condition_met(c, &json));
+        let succeeded = answered && !failed && condition_met(&poll.success, &json);
+        let timed_out = !failed && !succeeded && started.elapsed() >= timeout;
+
+        let (embed, done) = if succeeded || failed {
+            let embed = reply_embed(theme, service_key, action_name, &poll_action, status, &text);
+            let footer = if succeeded { "Job finished" } else { "Job failed" };
+            let color = if succeeded { COLOR_OK } else { COLOR_FAIL };
+            (embed.color(color).footer(CreateEmbedFooter::new(format!("{footer} after {elapsed}s"))), true)
+        } else {
+            let progress = match &poll.progress {
+                _ if !answered => format!("Job status unavailable ({}), retrying...", status),
+                Some(t) => render_template(t, &json, &text, status.as_u16()),
+                None => "Working...".to_string(),
+            };
+            let embed = theme.embed()
+                .title(format!("Service: {service_key}"))
+                .description(progress)
+                .field("Action", action_name, true);
+            if timed_out {
+                let footer = format!("Still not finished after {elapsed}s, stopped checking");
+                (embed.color(COLOR_FAIL).footer(CreateEmbedFooter::new(footer)), true)
+            } else {
+                (embed.color(COLOR_PENDING).footer(CreateEmbedFooter::new(format!("Running... {elapsed}s"))), false)
+            }
+        };
+        message.edit(cmd, CreateReply::default().embed(embed)).await?;
+        if done {
+            return Ok(succeeded);
+        }
+    }
+}
+
+/// Send one action's request. `extra_args` goes in the JSON body under `args_field`, or in the
+/// query string for bodiless GET/HEAD requests. `${VAR}` placeholders in the url, headers and body
+/// are filled from the environment, and their values are redacted from the reply and any error.
+///
+/// Local executors (`command`, `systemd`, `docker`) report success as 200 and failure as 500 so
+/// templates, health checks and colors work the same for every type.
+async fn call_action(
+    allow: &config::ExecAllowList,
+    action_name: &str,
+    action: &config::ActionConfig,
+    extra_args: &str,
+) -> Result<(reqwest::StatusCode, String), Box<dyn std::error::Error + Send + Sync>> {
+    match action.kind.unwrap_or_default() {
+        config::ServiceKind::Http => {}
+        config::ServiceKind::Wol => {
+            return send_magic_packet(action).await.map(|text| (reqwest::StatusCode::OK, text));
+        }
+        config::ServiceKind::Command => {
+            allow.check(action)?;
+            let argv = action.command.as_deref().unwrap_or_default();
+            return run_process(&argv[0], &argv[1..], action.timeout_secs).await;
+        }
+        config::ServiceKind::Systemd => {
+            allow.check(action)?;
+            let unit = action.unit.as_deref().unwrap_or_default();
+            let args = match action_name {
+                "status" => vec!["status".to_string(), "--no-pager".to_string(), unit.to_string()],
+                "start" | "stop" | "restart" => vec![action_name.to_string(), unit.to_string()],
+                other => return Err(format!("systemd services have no '{other}' action").into()),
+            };
+            return run_process("systemctl", &args, action.timeout_secs).await;
+        }
+        config::ServiceKind::Docker => {
+            allow.check(action)?;
+            let container = action.container.as_deref().unwrap_or_default();
+            return docker_action(allow, action_name, container).await;
+        }
     }
+    let mut secrets = Vec::new();
+    let result = send_action(action, extra_args, &env_var, &mut secrets).await;
+    match result {
+        Ok((status, text)) => Ok((status, redact(&text, &secrets))),
+        Err(e) => Err(redact(&e.to_string(), &secrets).into()),
+    }
+}
+
+async fn send_action(
+    action: &config::ActionConfig,
+    extra_args: &str,
+    vars: Vars<'_>,
+    secrets: &mut Vec<String>,
+) -> Result<(reqwest::StatusCode, String), Box<dyn std::error::Error + Send + Sync>> {
+    let resp = build_request(action, extra_args, vars, secrets)?.send().await?;
+    let status = resp.status();
+    let text = resp.text().await.unwrap_or_else(|_| "<no body>".to_string());
+    Ok((status, text))
+}
+
+/// The request `send_action` sends, with every `${VAR}` it filled in added to `secrets`.
+fn build_request(
+    action: &config::ActionConfig,
+    extra_args: &str,
+    vars: Vars<'_>,
+    secrets: &mut Vec<String>,
+) -> Result<reqwest::RequestBuilder, Box<dyn std::error::Error + Send + Sync>> {
+    let url = substitute_env(action.url.as_deref().ok_or("action has no url")?, vars, secrets)?;
+    let method_name = action.method.as_deref().unwrap_or("POST").to_ascii_uppercase();
+    let method = reqwest::Method::from_bytes(method_name.as_bytes())
+        .map_err(|_| format!("invalid method '{method_name}'"))?;
+    // GET/HEAD carry no body unless the service config sets one explicitly
+    let send_body = action.body.is_some() || !matches!(method, reqwest::Method::GET | reqwest::Method::HEAD);
+    let args_key = action.args_field.as_deref().unwrap_or("args");
 
     // Build JSON body
-    let mut body = match svc.body.clone().unwrap_or(serde_json::json!({})) {
+    let mut body = match action.body.clone().unwrap_or(serde_json::json!({})) {
         serde_json::Value::Object(map) => map,
         _ => serde_json::Map::new(),
     };
+    for value in body.values_mut() {
+        substitute_json(value, vars, secrets)?;
+    }
 
-    if !extra_args.is_empty() {
-        let key = svc.args_field.as_deref().unwrap_or("args");
-        body.insert(key.to_string(), serde_json::Value::String(extra_args));
+    if send_body && !extra_args.is_empty() {
+        body.insert(args_key.to_string(), serde_json::Value::String(extra_args.to_string()));
     }
 
     // Build client with optional timeout
     let mut client_builder = reqwest::Client::builder();
-    if let Some(t) = svc.timeout_secs {
-        client_builder = client_builder.timeout(std::time::Duration::from_secs(t));
+    if let Some(t) = action.timeout_secs {
+        client_builder = client_builder.timeout(Duration::from_secs(t));
     }
     let client = client_builder.build()?;
 
-    let mut req = client.post(&svc.url);
-    if let Some(hs) = &svc.headers {
+    let mut req = client.request(method, url);
+    if let Some(hs) = &action.headers {
         for (k, v) in hs {
-            req = req.header(k, v);
+            req = req.header(k, substitute_env(v, vars, secrets)?);
         }
     }
-    req = req.json(&body);
+    if send_body {
+        req = req.json(&body);
+    } else if !extra_args.is_empty() {
+        // Without a body the extra text goes in the query string instead
+        req = req.query(&[(args_key, extra_args)]);
+    }
+    Ok(req)
+}
 
-    let resp = match req.send().await {
-        Ok(r) => r,
-        Err(e) => {
-            channel_id
-                .say(&ctx.http, format!("Request error for '{service_key}': {e}"))
-                .await?;
-            return Ok(());
+/// What `call_action` would do, for dry runs. Filled-in `${VAR}` values are redacted.
+fn describe_action(
+    action_name: &str,
+    action: &config::ActionConfig,
+    extra_args: &str,
+    vars: Vars<'_>,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let described = match action.kind.unwrap_or_default() {
+        config::ServiceKind::Http => {
+            let mut secrets = Vec::new();
+            let request = build_request(action, extra_args, vars, &mut secrets)?.build()?;
+            let body = request.body().and_then(|b| b.as_bytes()).map(String::from_utf8_lossy);
+            let line = format!("send `{} {}`", request.method(), request.url());
+            let text = match body {
+                Some(body) if !body.is_empty() => format!("{line} with body `{body}`"),
+                _ => line,
+            };
+            redact(&text, &secrets)
         }
+        config::ServiceKind::Wol => format!("send a magic packet to {}", action.mac.as_deref().unwrap_or("?")),
+        config::ServiceKind::Command => format!("run `{}`", action.command.as_deref().unwrap_or_default().join(" ")),
+        config::ServiceKind::Systemd => format!("run `systemctl {} {}`", action_name, action.unit.as_deref().unwrap_or("?")),
+        config::ServiceKind::Docker => format!("{} container `{}`", action_name, action.container.as_deref().unwrap_or("?")),
     };
+    Ok(described)
+}
 
-    let status = resp.status();
-    let text = resp.text().await.unwrap_or_else(|_| "<no body>".to_string());
+/// Run an allow-listed program and capture its output. User-supplied args are never passed on.
+async fn run_process(
+    program: &str,
+    args: &[String],
+    timeout_secs: Option<u64>,
+) -> Result<(reqwest::StatusCode, String), Box<dyn std::error::Error + Send + Sync>> {
+    let child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(Duration::from_secs(timeout_secs.unwrap_or(60)), child)
+        .await
+        .map_err(|_| format!("`{program}` timed out"))??;
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        text.push_str(&stderr);
+    }
+    if output.status.success() {
+        Ok((reqwest::StatusCode::OK, text))
+    } else {
+        let code = output.status.code().map(|c| c.to_string()).unwrap_or_else(|| "signal".to_string());
+        Ok((reqwest::StatusCode::INTERNAL_SERVER_ERROR, format!("exit code {code}\n{text}")))
+    }
+}
+
+/// Drive a container through the Docker Engine API on its unix socket. `status` returns the
+/// container's inspect JSON, so templates like `{{.State.Status}}` work.
+#[cfg(unix)]
+async fn docker_action(
+    allow: &config::ExecAllowList,
+    action_name: &str,
+    container: &str,
+) -> Result<(reqwest::StatusCode, String), Box<dyn std::error::Error + Send + Sync>> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-    // Discord message length safety
-    let mut preview = text.trim().to_string();
-    if preview.is_empty() {
-        preview = "<empty>".to_string();
+    if container.is_empty() || !container.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c)) {
+        return Err(format!("invalid container name '{container}'").into());
     }
-    let max_len = 1800usize; // leave room for header lines
-    if preview.len() > max_len {
-        preview.truncate(max_len);
-        preview.push_str("... (truncated)");
+    let (method, path) = match action_name {
+        "status" => ("GET", format!("/containers/{container}/json")),
+        "start" | "stop" | "restart" => ("POST", format!("/containers/{container}/{action_name}")),
+        other => return Err(format!("docker services have no '{other}' action").into()),
+    };
+    let socket = allow.docker_socket.as_deref().unwrap_or("/var/run/docker.sock");
+    let mut stream = tokio::net::UnixStream::connect(socket).await?;
+    // HTTP/1.0 keeps the reply un-chunked and closes the connection when done
+    let request = format!("{method} {path} HTTP/1.0\r\nHost: docker\r\nContent-Length: 0\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).await?;
+    let raw = String::from_utf8_lossy(&raw);
+
+    let (head, body) = raw.split_once("\r\n\r\n").unwrap_or((&raw, ""));
+    let code = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|c| c.parse::<u16>().ok())
+        .ok_or("malformed reply from the Docker socket")?;
+    let status = match code {
+        // 304: already started/stopped
+        304 => reqwest::StatusCode::OK,
+        other => reqwest::StatusCode::from_u16(other)?,
+    };
+    let text = if body.trim().is_empty() {
+        format!("docker {action_name} {container}: {}", if status.is_success() { "ok" } else { "failed" })
+    } else {
+        body.to_string()
+    };
+    Ok((status, text))
+}
+
+#[cfg(not(unix))]
+async fn docker_action(
+    _allow: &config::ExecAllowList,
+    _action_name: &str,
+    _container: &str,
+) -> Result<(reqwest::StatusCode, String), Box<dyn std::error::Error + Send + Sync>> {
+    Err("docker services need a unix socket and are not supported on this platform".into())
+}
+
+/// Wake-on-LAN: six 0xFF bytes followed by the target MAC repeated 16 times, sent over UDP broadcast.
+async fn send_magic_packet(action: &config::ActionConfig) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mac_text = action.mac.as_deref().ok_or("action has no mac")?;
+    let mac = config::parse_mac(mac_text).ok_or_else(|| format!("invalid MAC address '{mac_text}'"))?;
+    let target = action.broadcast.as_deref().unwrap_or("255.255.255.255:9");
+
+    let mut packet = vec![0xFFu8; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac);
     }
+    let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&packet, target).await?;
+    Ok(format!("Magic packet sent to {mac_text} via {target}"))
+}
+
+/// Where `${VAR}` placeholders get their values: `env_var`, except in tests.
+type Vars<'a> = &'a (dyn Fn(&str) -> Option<String> + Sync);
 
-    let msg = format!(
-        "Service: {service_key}\nURL: {}\nStatus: {}\nBody:\n{}",
-        svc.url,
-        status,
-        preview
-    );
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
 
-    channel_id.say(&ctx.http, msg).await?;
+/// Replace `${VAR}` with its value from `vars`, remembering each value in `secrets`.
+fn substitute_env(input: &str, vars: Vars<'_>, secrets: &mut Vec<String>) -> Result<String, String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(open) = rest.find("${") {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 2..];
+        let Some(close) = after.find('}') else {
+            out.push_str(&rest[open..]);
+            return Ok(out);
+        };
+        let name = &after[..close];
+        let value = vars(name).ok_or_else(|| format!("environment variable `{name}` is not set"))?;
+        if !value.is_empty() && !secrets.contains(&value) {
+            secrets.push(value.clone());
+        }
+        out.push_str(&value);
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn substitute_json(value: &mut serde_json::Value, vars: Vars<'_>, secrets: &mut Vec<String>) -> Result<(), String> {
+    match value {
+        serde_json::Value::String(s) => *s = substitute_env(s, vars, secrets)?,
+        serde_json::Value::Array(items) => {
+            for item in items {
+                substitute_json(item, vars, secrets)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                substitute_json(item, vars, secrets)?;
+            }
+        }
+        _ => {}
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+fn redact(text: &str, secrets: &[String]) -> String {
+    secrets
+        .iter()
+        .fold(text.to_string(), |acc, secret| acc.replace(secret.as_str(), "[redacted]"))
+}
+
+fn is_healthy(action: &config::ActionConfig, status: reqwest::StatusCode, text: &str) -> bool {
+    if !status.is_success() {
+        return false;
+    }
+    match &action.healthy {
+        None => true,
+        Some(check) => {
+            let json = serde_json::from_str::<serde_json::Value>(text).unwrap_or(serde_json::Value::Null);
+            condition_met(check, &json)
+        }
+    }
+}
+
+fn condition_met(condition: &config::Condition, json: &serde_json::Value) -> bool {
+    lookup(json, condition.field.trim_start_matches('.')) == Some(&condition.equals)
+}
+
+fn reply_embed(
+    theme: &Theme,
+    service_key: &str,
+    action_name: &str,
+    action: &config::ActionConfig,
+    status: reqwest::StatusCode,
+    text: &str,
+) -> CreateEmbed {
+    let template = action.response.as_ref().and_then(|r| {
+        if status.is_success() {
+            r.template.as_deref()
+        } else {
+            r.error_template.as_deref().or(r.template.as_deref())
+        }
+    });
+    let mut description = match template {
+        Some(t) => {
+            let json = serde_json::from_str::<serde_json::Value>(text).unwrap_or(serde_json::Value::Null);
+            render_template(t, &json, text, status.as_u16())
+        }
+        None => {
+            let mut preview = text.trim().to_string();
+            if preview.is_empty() {
+                preview = "<empty>".to_string();
+            }
+            format!("```\n{}\n```", preview.replace("```", "`\u{200b}``"))
+        }
+    };
+
+    // Discord embed description limit is 4096; leave room for the suffix
+    let max_len = 3800usize;
+    if description.len() > max_len {
+        let mut cut = max_len;
+        while !description.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        description.truncate(cut);
+        description.push_str("... (truncated)");
+    }
+
+    let title = action
+        .response
+        .as_ref()
+        .and_then(|r| r.title.clone())
+        .unwrap_or_else(|| format!("Service: {service_key}"));
+    let color = if status.is_success() { COLOR_OK } else { COLOR_FAIL };
+    let embed = theme.embed()
+        .title(title)
+        .description(description)
+        .color(color)
+        .field("Action", action_name, true);
+    match action.kind.unwrap_or_default() {
+        config::ServiceKind::Http => embed
+            .field("Status", status.to_string(), true)
+            .field("URL", action.url.clone().unwrap_or_else(|| "-".to_string()), true),
+        config::ServiceKind::Wol => embed.field("MAC", action.mac.clone().unwrap_or_else(|| "-".to_string()), true),
+        config::ServiceKind::Command => {
+            let command = action.command.as_ref().map(|c| c.join(" ")).unwrap_or_else(|| "-".to_string());
+            embed.field("Command", format!("`{command}`"), true)
+        }
+        config::ServiceKind::Systemd => embed.field("Unit", action.unit.clone().unwrap_or_else(|| "-".to_string()), true),
+        config::ServiceKind::Docker => {
+            embed.field("Container", action.container.clone().unwrap_or_else(|| "-".to_string()), true)
+        }
+    }
+}
+
+/// One field per service: description, actions and who may use it.
+fn list_embed(services: &HashMap<&str, &config::ServiceConfig>, theme: &Theme) -> CreateEmbed {
+    let mut names = services.keys().copied().collect::<Vec<_>>();
+    names.sort();
+    let mut embed = theme.embed()
+        .title(format!("Start services ({})", names.len()))
+        .color(COLOR_OK);
+    // Discord caps embeds at 25 fields
+    for name in names.iter().take(25) {
+        let svc = services[name];
+        let mut lines = Vec::new();
+        if let Some(d) = &svc.description {
+            // Field values are capped at 1024 characters
+            lines.push(d.chars().take(300).collect());
+        }
+        lines.push(format!("Actions: {}", svc.action_names().join(", ")));
+        if !svc.allowed_roles.is_empty() {
+            let roles = svc.allowed_roles.iter().map(|r| format!("<@&{r}>")).collect::<Vec<_>>();
+            lines.push(format!("Roles: {}", roles.join(" ")));
+        }
+        if !svc.allowed_users.is_empty() {
+            let users = svc.allowed_users.iter().map(|u| format!("<@{u}>")).collect::<Vec<_>>();
+            lines.push(format!("Users: {}", users.join(" ")));
+        }
+        if !svc.allowed_channels.is_empty() {
+            let channels = svc.allowed_channels.iter().map(|c| format!("<#{c}>")).collect::<Vec<_>>();
+            lines.push(format!("Channels: {}", channels.join(" ")));
+        }
+        embed = embed.field(*name, lines.join("\n"), false);
+    }
+    if names.len() > 25 {
+        embed = embed.footer(CreateEmbedFooter::new(format!("...and {} more", names.len() - 25)));
+    }
+    embed
+}
+
+/// Slash autocomplete for `/start service`: the services visible in this guild, plus `list`.
+pub async fn autocomplete_service(ctx: crate::Ctx<'_>, partial: &str) -> Vec<String> {
+    let app_config = config::current(ctx.serenity_context()).await;
+    let Some(cfg) = app_config.start.as_ref() else { return vec!["list".to_string()] };
+    let partial = partial.to_ascii_lowercase();
+    let mut names: Vec<String> = cfg
+        .services_for(ctx.guild_id())
+        .keys()
+        .filter(|name| name.to_ascii_lowercase().contains(&partial))
+        .map(|name| name.to_string())
+        .collect();
+    names.sort();
+    names.truncate(24);
+    if "list".contains(&partial) {
+        names.push("list".to_string());
+    }
+    names
+}
+
+/// Slash autocomplete for `/start action`: the actions of the service already picked.
+pub async fn autocomplete_action(ctx: crate::Ctx<'_>, partial: &str) -> Vec<String> {
+    let poise::Context::Application(actx) = ctx else { return Vec::new() };
+    let service = actx.interaction.data.options.iter().find_map(|o| match (&*o.name, &o.value) {
+        ("service", serenity::all::CommandDataOptionValue::String(s)) => Some(s.clone()),
+        _ => None,
+    });
+    let app_config = config::current(ctx.serenity_context()).await;
+    let (Some(service), Some(cfg)) = (service, app_config.start.as_ref()) else {
+        return STANDARD_ACTIONS.iter().map(|a| a.to_string()).collect();
+    };
+    let partial = partial.to_ascii_lowercase();
+    cfg.services_for(ctx.guild_id())
+        .get(service.as_str())
+        .map(|svc| svc.action_names())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|a| a.contains(&partial))
+        .collect()
+}
+
+/// Check the service's `allowed_channels`, `allowed_users` and `allowed_roles`.
+async fn is_allowed(
+    ctx: &serenity::prelude::Context,
+    svc: &config::ServiceConfig,
+    channel_id: serenity::all::ChannelId,
+    guild_id: Option<serenity::all::GuildId>,
+    user_id: serenity::all::UserId,
+) -> bool {
+    if !svc.allowed_channels.is_empty() && !svc.allowed_channels.contains(&channel_id) {
+        // Threads inherit their parent channel's permission
+        let parent = guild_id.and_then(|g| crate::thread_parent(ctx, g, channel_id));
+        if !parent.is_some_and(|p| svc.allowed_channels.contains(&p)) {
+            return false;
+        }
+    }
+    if svc.allowed_users.is_empty() && svc.allowed_roles.is_empty() {
+        return true;
+    }
+    if svc.allowed_users.contains(&user_id) {
+        return true;
+    }
+    if svc.allowed_roles.is_empty() {
+        return false;
+    }
+    let Some(guild_id) = guild_id else { return false };
+    match guild_id.member(ctx, user_id).await {
+        Ok(member) => member.roles.iter().any(|r| svc.allowed_roles.contains(r)),
+        Err(e) => {
+            tracing::warn!("Failed to fetch member {user_id} for start permission check: {e:?}");
+            false
+        }
+    }
+}
+
+/// Fill `{{.path}}`, `{{.}}` and `{{status}}` placeholders from a service reply.
+/// Missing fields render as `?`; a non-JSON body only satisfies `{{.}}`.
+fn render_template(template: &str, json: &serde_json::Value, raw: &str, status: u16) -> String {
+    render_template_with(template, json, raw, status, |value| value)
+}
+
+/// `render_template` with every filled-in value passed through `escape` first.
+fn render_template_with(
+    template: &str,
+    json: &serde_json::Value,
+    raw: &str,
+    status: u16,
+    escape: impl Fn(String) -> String,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 2..];
+        let Some(close) = after.find("}}") else {
+            out.push_str(&rest[open..]);
+            return out;
+        };
+        let expr = after[..close].trim();
+        let value = match expr {
+            "status" => status.to_string(),
+            "." => raw.trim().to_string(),
+            _ => match expr.strip_prefix('.') {
+                Some(path) => lookup(json, path).map(display_value).unwrap_or_else(|| "?".to_string()),
+                // Not a placeholder we understand: leave it as written
+                None => {
+                    out.push_str(&rest[open..open + close + 4]);
+                    rest = &after[close + 2..];
+                    continue;
+                }
+            },
+        };
+        out.push_str(&escape(value));
+        rest = &after[close + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Percent-encode everything but RFC 3986's unreserved characters, so a value can't change
+/// the shape of the URL it's put in.
+fn url_escape(value: String) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+fn lookup<'a>(json: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.').try_fold(json, |node, key| match node {
+        serde_json::Value::Object(map) => map.get(key),
+        serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+fn display_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn action(server: &MockServer, extra: serde_json::Value) -> config::ActionConfig {
+        let mut v = serde_json::json!({ "url": format!("{}/deploy", server.uri()) });
+        v.as_object_mut().unwrap().extend(extra.as_object().cloned().unwrap_or_default());
+        serde_json::from_value(v).unwrap()
+    }
+
+    fn no_vars(_: &str) -> Option<String> {
+        None
+    }
+
+    #[tokio::test]
+    async fn posts_args_in_json_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/deploy"))
+            .and(header("X-Team", "ops"))
+            .and(body_json(serde_json::json!({ "env": "prod", "params": "--fast" })))
+            .respond_with(ResponseTemplate::new(202).set_body_string("queued"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let action = action(
+            &server,
+            serde_json::json!({ "headers": { "X-Team": "ops" }, "body": { "env": "prod" }, "args_field": "params" }),
+        );
+        let (status, text) = send_action(&action, "--fast", &no_vars, &mut Vec::new()).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::ACCEPTED);
+        assert_eq!(text, "queued");
+    }
+
+    #[tokio::test]
+    async fn get_sends_args_in_query_string() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/deploy"))
+            .and(query_param("args", "verbose"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let action = action(&server, serde_json::json!({ "method": "get" }));
+        let req = build_request(&action, "verbose", &no_vars, &mut Vec::new()).unwrap().build().unwrap();
+        assert!(req.body().is_none());
+        let (status, _) = send_action(&action, "verbose", &no_vars, &mut Vec::new()).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn fills_placeholders_and_records_secrets() {
+        let server = MockServer::start().await;
+        Mock::given(header("Authorization", "Bearer s3cret"))
+            .and(body_json(serde_json::json!({ "token": "s3cret" })))
+            .respond_with(ResponseTemplate::new(200).set_body_string("token s3cret accepted"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let action = action(
+            &server,
+            serde_json::json!({ "headers": { "Authorization": "Bearer ${START_TEST_TOKEN}" }, "body": { "token": "${START_TEST_TOKEN}" } }),
+        );
+        let mut secrets = Vec::new();
+        let vars = |name: &str| (name == "START_TEST_TOKEN").then(|| "s3cret".to_string());
+        let (_, text) = send_action(&action, "", &vars, &mut secrets).await.unwrap();
+        assert!(secrets.iter().any(|s| s == "s3cret"));
+        assert!(!redact(&text, &secrets).contains("s3cret"));
+    }
+
+    #[test]
+    fn dry_run_description_redacts_secrets() {
+        let action: config::ActionConfig = serde_json::from_value(serde_json::json!({
+            "url": "http://host:8080/start?key=${START_TEST_DRY_RUN_KEY}",
+            "body": { "world": "survival" }
+        }))
+        .unwrap();
+        let vars = |name: &str| (name == "START_TEST_DRY_RUN_KEY").then(|| "hunter2".to_string());
+        let described = describe_action("start", &action, "fast", &vars).unwrap();
+        assert!(described.starts_with("send `POST http://host:8080/start?key="));
+        assert!(described.contains(r#""args":"fast""#));
+        assert!(!described.contains("hunter2"));
+    }
+
+    #[test]
+    fn rejects_invalid_method() {
+        let action: config::ActionConfig = serde_json::from_value(serde_json::json!({ "url": "http://localhost/", "method": "NOT A METHOD" })).unwrap();
+        assert!(build_request(&action, "", &no_vars, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn poll_url_escapes_reply_values() {
+        let reply = serde_json::json!({ "id": "../${HOME}?x=1" });
+        let url = render_template_with("http://host/jobs/{{.id}}", &reply, "", 0, url_escape);
+        assert_eq!(url, "http://host/jobs/..%2F%24%7BHOME%7D%3Fx%3D1");
+    }
+}
@@ -0,0 +1,210 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serenity::prelude::*;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use crate::Error;
+
+/// The database, inside the data directory.
+pub const DB_PATH: &str = "bot.db";
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keep all state under `dir` (`--data-dir` / `BOT_DATA_DIR`) instead of the working directory,
+/// creating it if needed. Call once from `main`, before anything reads or writes state.
+pub fn set_data_dir(dir: PathBuf) -> std::io::Result<()> {
+    std::fs::create_dir_all(&dir)?;
+    let _ = DATA_DIR.set(dir);
+    Ok(())
+}
+
+/// Where the state file or directory `name` lives: in the data directory if one was set, else
+/// the working directory. An absolute `name` is used as is.
+pub fn data_path(name: impl AsRef<Path>) -> PathBuf {
+    match DATA_DIR.get() {
+        Some(dir) => dir.join(name),
+        None => name.as_ref().to_path_buf(),
+    }
+}
+
+/// `path` with `suffix` appended to its file name: `warnings.json` → `warnings.json.bak`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Schema migrations, applied in order. The index + 1 is stored in `PRAGMA user_version`,
+/// so only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_modalert.sql"),
+    include_str!("../migrations/0002_guild_settings.sql"),
+    include_str!("../migrations/0003_command_registrations.sql"),
+    include_str!("../migrations/0004_command_audit.sql"),
+    include_str!("../migrations/0005_command_permissions.sql"),
+    include_str!("../migrations/0006_access_lists.sql"),
+    include_str!("../migrations/0007_playback_positions.sql"),
+    include_str!("../migrations/0008_modalert_events.sql"),
+    include_str!("../migrations/0009_user_preferences.sql"),
+    include_str!("../migrations/0010_guild_onboarding.sql"),
+    include_str!("../migrations/0011_quiz_scores.sql"),
+    include_str!("../migrations/0012_scrobble_accounts.sql"),
+    include_str!("../migrations/0013_favorites.sql"),
+];
+
+/// Handle to the bot's SQLite database. Cheap to clone; queries run on the blocking pool.
+#[derive(Clone)]
+pub struct Db {
+    conn: Arc<std::sync::Mutex<Connection>>,
+}
+
+pub struct DbStore;
+impl TypeMapKey for DbStore {
+    type Value = Db;
+}
+
+impl Db {
+    /// Open (or create) the database at `path` and bring its schema up to date.
+    pub async fn open(path: &Path) -> Result<Db, Error> {
+        let path = path.to_path_buf();
+        let conn = tokio::task::spawn_blocking(move || -> Result<Connection, Error> {
+            let mut conn = Connection::open(path)?;
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            conn.pragma_update(None, "foreign_keys", "ON")?;
+            migrate(&mut conn)?;
+            Ok(conn)
+        })
+        .await??;
+        Ok(Db { conn: Arc::new(std::sync::Mutex::new(conn)) })
+    }
+
+    /// Run `f` against the connection without blocking the async runtime.
+    pub async fn call<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().map_err(|_| "database connection poisoned")?;
+            f(&mut conn).map_err(Error::from)
+        })
+        .await?
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Error> {
+    let current: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+pub async fn db(ctx: &Context) -> Result<Db, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<DbStore>()
+        .cloned()
+        .ok_or_else(|| "Database not initialised".into())
+}
+
+/// Replace `path` with `contents` so a crash can't leave it half-written: the new contents go
+/// to a temporary file of their own next to it and are synced before being renamed over the
+/// old file, so two saves at once can't mix their writes.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let path = path.to_path_buf();
+    let contents = contents.to_vec();
+    tokio::task::spawn_blocking(move || -> Result<(), Error> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        tmp.write_all(&contents)?;
+        tmp.as_file().sync_all()?;
+        tmp.persist(&path).map_err(|e| e.error)?;
+        Ok(())
+    })
+    .await?
+}
+
+/// Save a JSON file store with `write_atomic`. The file being replaced is kept as `<path>.bak`
+/// first, but only if it parses, so a torn write never overwrites the last good backup.
+pub async fn save_json<T: Serialize>(path: &Path, data: &T) -> Result<(), Error> {
+    if let Ok(previous) = tokio::fs::read(path).await
+        && serde_json::from_slice::<serde::de::IgnoredAny>(&previous).is_ok()
+    {
+        write_atomic(&with_suffix(path, ".bak"), &previous).await?;
+    }
+    write_atomic(path, serde_json::to_string_pretty(data)?.as_bytes()).await
+}
+
+/// Load a JSON file store; a missing file is an empty store. If the file can't be read or
+/// parsed, the `.bak` from the previous save is used instead, with a warning, so one bad write
+/// doesn't lose the whole store.
+pub async fn load_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, Error> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let error = match read_json(path).await {
+        Ok(data) => return Ok(data),
+        Err(e) => e,
+    };
+    let backup = with_suffix(path, ".bak");
+    if !backup.exists() {
+        return Err(format!("{} is unreadable and has no backup: {}", path.display(), error).into());
+    }
+    let data = read_json(&backup).await.map_err(|e| {
+        format!("{} is unreadable ({}) and so is {} ({})", path.display(), error, backup.display(), e)
+    })?;
+    tracing::warn!(path = %path.display(), %error, "store is unreadable, loaded the previous save from {} instead", backup.display());
+    Ok(data)
+}
+
+async fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let s = tokio::fs::read_to_string(path).await?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn json_store_falls_back_to_backup() {
+        let dir = std::env::temp_dir().join(format!("store-test-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("store.json");
+
+        let empty: HashMap<String, u32> = load_json(&path).await.unwrap();
+        assert!(empty.is_empty());
+
+        save_json(&path, &HashMap::from([("a".to_string(), 1u32)])).await.unwrap();
+        save_json(&path, &HashMap::from([("a".to_string(), 2u32)])).await.unwrap();
+        let loaded: HashMap<String, u32> = load_json(&path).await.unwrap();
+        assert_eq!(loaded["a"], 2);
+
+        // A torn write: the previous save is used
+        tokio::fs::write(&path, "{\"a\": ").await.unwrap();
+        let recovered: HashMap<String, u32> = load_json(&path).await.unwrap();
+        assert_eq!(recovered["a"], 1);
+
+        // Saving over the torn file keeps the good backup
+        save_json(&path, &HashMap::from([("a".to_string(), 3u32)])).await.unwrap();
+        tokio::fs::write(&path, "{\"a\": ").await.unwrap();
+        let recovered: HashMap<String, u32> = load_json(&path).await.unwrap();
+        assert_eq!(recovered["a"], 1);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
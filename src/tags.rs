@@ -0,0 +1,417 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ButtonStyle, ComponentInteractionCollector};
+use serenity::builder::{
+    CreateActionRow, CreateAllowedMentions, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage,
+};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::{Ctx, Error};
+
+pub const TAGS_PATH: &str = "tags.json";
+
+const MAX_NAME_LEN: usize = 32;
+const MAX_CONTENT_LEN: usize = 2000;
+/// Tags shown per page of `tag list`; names are capped at `MAX_NAME_LEN`, so a page always fits
+/// in an embed description.
+const PAGE_SIZE: usize = 50;
+/// How long the page buttons keep working.
+const VIEW_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tag {
+    pub content: String,
+    /// Send the content as an embed description instead of plain text
+    #[serde(default)]
+    pub embed: bool,
+    pub author: u64,
+    /// Unix timestamp (seconds)
+    pub created_at: i64,
+    #[serde(default)]
+    pub uses: u64,
+}
+
+/// guild id -> tag name -> tag
+pub type TagData = HashMap<u64, HashMap<String, Tag>>;
+
+pub struct TagStore;
+impl TypeMapKey for TagStore {
+    type Value = Arc<Mutex<TagData>>;
+}
+
+async fn load_disk() -> Result<TagData, Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::load_json(&crate::storage::data_path(TAGS_PATH)).await
+}
+
+async fn save_disk(data: &TagData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(TAGS_PATH), data).await
+}
+
+pub async fn ensure_tag_store() -> Result<Arc<Mutex<TagData>>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = load_disk().await?;
+    Ok(Arc::new(Mutex::new(data)))
+}
+
+async fn tag_store(ctx: &Context) -> Result<Arc<Mutex<TagData>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<TagStore>()
+        .cloned()
+        .ok_or_else(|| "Tag store not initialised".into())
+}
+
+/// Drop all of `gid`'s tags.
+pub async fn purge_guild(ctx: &Context, gid: serenity::GuildId) -> Result<(), Error> {
+    let store = tag_store(ctx).await?;
+    let mut data = store.lock().await;
+    if data.remove(&gid.get()).is_some() {
+        save_disk(&data).await?;
+    }
+    Ok(())
+}
+
+/// Tags `user` created, by guild, for `data export`.
+pub async fn export_user(ctx: &Context, user: serenity::UserId) -> Result<serde_json::Value, Error> {
+    let store = tag_store(ctx).await?;
+    let data = store.lock().await;
+    let mut out = serde_json::Map::new();
+    for (gid, tags) in data.iter() {
+        let mine: serde_json::Map<String, serde_json::Value> = tags
+            .iter()
+            .filter(|(_, tag)| tag.author == user.get())
+            .map(|(name, tag)| Ok((name.clone(), serde_json::to_value(tag)?)))
+            .collect::<Result<_, serde_json::Error>>()?;
+        if !mine.is_empty() {
+            out.insert(gid.to_string(), mine.into());
+        }
+    }
+    Ok(out.into())
+}
+
+/// Replace `{user}`, `{user.name}`, `{server}` and `{channel}` in a template.
+pub fn fill_placeholders(template: &str, user: &serenity::User, server: &str, channel: Option<serenity::ChannelId>) -> String {
+    let mut out = template
+        .replace("{user}", &format!("<@{}>", user.id))
+        .replace("{user.name}", user.global_name.as_deref().unwrap_or(&user.name))
+        .replace("{server}", server);
+    if let Some(channel) = channel {
+        out = out.replace("{channel}", &format!("<#{}>", channel));
+    }
+    out
+}
+
+/// Slash autocomplete: this server's tag names, most used first.
+async fn autocomplete_tag(ctx: Ctx<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else { return Vec::new() };
+    let Ok(store) = tag_store(ctx.serenity_context()).await else { return Vec::new() };
+    let partial = partial.trim().to_lowercase();
+    let mut matches: Vec<(String, u64)> = {
+        let data = store.lock().await;
+        data.get(&guild_id.get())
+            .map(|tags| {
+                tags.iter()
+                    .filter(|(n, _)| n.contains(&partial))
+                    .map(|(n, t)| (n.clone(), t.uses))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    matches.into_iter().take(25).map(|(n, _)| n).collect()
+}
+
+fn normalize_name(name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(format!("Tag names must be 1-{} characters.", MAX_NAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err("Tag names may only contain letters, numbers, `-` and `_`.".to_string());
+    }
+    if ["create", "edit", "delete", "list", "show"].contains(&name.as_str()) {
+        return Err(format!("`{}` is reserved.", name));
+    }
+    Ok(name)
+}
+
+/// Canned responses for this server
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands("tag_show", "tag_create", "tag_edit", "tag_delete", "tag_list")
+)]
+pub async fn tag(ctx: Ctx<'_>) -> Result<(), Error> {
+    list(ctx).await
+}
+
+/// Post a tag
+#[poise::command(prefix_command, slash_command, guild_only, rename = "show")]
+pub async fn tag_show(
+    ctx: Ctx<'_>,
+    #[description = "Tag name"]
+    #[autocomplete = "autocomplete_tag"]
+    name: String,
+) -> Result<(), Error> {
+    show(ctx, name).await
+}
+
+async fn show(ctx: Ctx<'_>, name: String) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let name = name.trim().to_lowercase();
+    let store = tag_store(ctx.serenity_context()).await?;
+    let tag = {
+        let mut data = store.lock().await;
+        let Some(tag) = data.get_mut(&guild_id.get()).and_then(|tags| tags.get_mut(&name)) else {
+            drop(data);
+            ctx.say(format!("No tag named `{}`.", name)).await?;
+            return Ok(());
+        };
+        tag.uses += 1;
+        let tag = tag.clone();
+        save_disk(&data).await?;
+        tag
+    };
+
+    let server = ctx.guild().map(|g| g.name.clone()).unwrap_or_default();
+    let text = fill_placeholders(&tag.content, ctx.author(), &server, Some(ctx.channel_id()));
+    // Tags are authored by moderators but shown by anyone, so never let them ping roles or everyone
+    let mentions = CreateAllowedMentions::new().users(vec![ctx.author().id]);
+    let reply = if tag.embed {
+        poise::CreateReply::default().embed(CreateEmbed::new().description(text).color(crate::EMBED_COLOR))
+    } else {
+        poise::CreateReply::default().content(text)
+    };
+    ctx.send(reply.allowed_mentions(mentions)).await?;
+    Ok(())
+}
+
+/// Create a tag (placeholders: {user}, {user.name}, {server}, {channel})
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "create",
+    required_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn tag_create(
+    ctx: Ctx<'_>,
+    #[description = "Tag name"] name: String,
+    #[description = "Send as an embed"] embed: Option<bool>,
+    #[description = "Tag content"]
+    #[rest]
+    content: String,
+) -> Result<(), Error> {
+    save_tag(ctx, name, embed, content, false).await
+}
+
+/// Change an existing tag
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "edit",
+    required_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn tag_edit(
+    ctx: Ctx<'_>,
+    #[description = "Tag name"]
+    #[autocomplete = "autocomplete_tag"]
+    name: String,
+    #[description = "Send as an embed (unchanged if omitted)"] embed: Option<bool>,
+    #[description = "New content"]
+    #[rest]
+    content: String,
+) -> Result<(), Error> {
+    save_tag(ctx, name, embed, content, true).await
+}
+
+async fn save_tag(ctx: Ctx<'_>, name: String, embed: Option<bool>, content: String, edit: bool) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let name = match normalize_name(&name) {
+        Ok(n) => n,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+    let content = content.trim().to_string();
+    if content.is_empty() || content.chars().count() > MAX_CONTENT_LEN {
+        ctx.say(format!("Tag content must be 1-{} characters.", MAX_CONTENT_LEN)).await?;
+        return Ok(());
+    }
+
+    let store = tag_store(ctx.serenity_context()).await?;
+    let reply = {
+        let mut data = store.lock().await;
+        let tags = data.entry(guild_id.get()).or_default();
+        match (tags.get_mut(&name), edit) {
+            (Some(_), false) => format!("Tag `{}` already exists, use `tag edit` to change it.", name),
+            (None, true) => format!("No tag named `{}`.", name),
+            (Some(tag), true) => {
+                tag.content = content;
+                if let Some(embed) = embed {
+                    tag.embed = embed;
+                }
+                save_disk(&data).await?;
+                format!("Updated tag `{}`.", name)
+            }
+            (None, false) => {
+                tags.insert(
+                    name.clone(),
+                    Tag {
+                        content,
+                        embed: embed.unwrap_or(false),
+                        author: ctx.author().id.get(),
+                        created_at: serenity::Timestamp::now().unix_timestamp(),
+                        uses: 0,
+                    },
+                );
+                save_disk(&data).await?;
+                format!("Created tag `{}`.", name)
+            }
+        }
+    };
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+/// Delete a tag
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "delete",
+    required_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn tag_delete(
+    ctx: Ctx<'_>,
+    #[description = "Tag name"]
+    #[autocomplete = "autocomplete_tag"]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let name = name.trim().to_lowercase();
+    let store = tag_store(ctx.serenity_context()).await?;
+    let removed = {
+        let mut data = store.lock().await;
+        let removed = data.get_mut(&guild_id.get()).and_then(|tags| tags.remove(&name)).is_some();
+        if removed {
+            save_disk(&data).await?;
+        }
+        removed
+    };
+    if removed {
+        ctx.say(format!("Deleted tag `{}`.", name)).await?;
+    } else {
+        ctx.say(format!("No tag named `{}`.", name)).await?;
+    }
+    Ok(())
+}
+
+/// List this server's tags
+#[poise::command(prefix_command, slash_command, guild_only, rename = "list")]
+pub async fn tag_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    list(ctx).await
+}
+
+/// This server's tags with their use counts, sorted by name.
+async fn names(ctx: &Context, guild_id: serenity::GuildId) -> Result<Vec<(String, u64)>, Error> {
+    let store = tag_store(ctx).await?;
+    let mut names: Vec<(String, u64)> = {
+        let data = store.lock().await;
+        data.get(&guild_id.get())
+            .map(|tags| tags.iter().map(|(n, t)| (n.clone(), t.uses)).collect())
+            .unwrap_or_default()
+    };
+    names.sort();
+    Ok(names)
+}
+
+fn page_count(names: &[(String, u64)]) -> usize {
+    names.len().div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Page `page` (0-based, clamped) of the tag list.
+fn page_embed(names: &[(String, u64)], page: usize) -> CreateEmbed {
+    let pages = page_count(names);
+    let page = page.min(pages - 1);
+    let list = names
+        .iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(n, uses)| format!("`{}` ({})", n, uses))
+        .collect::<Vec<_>>()
+        .join(", ");
+    CreateEmbed::new()
+        .title(format!("Tags ({})", names.len()))
+        .description(list)
+        .footer(CreateEmbedFooter::new(format!("Page {}/{}", page + 1, pages)))
+        .color(crate::EMBED_COLOR)
+}
+
+fn page_buttons(page: usize, pages: usize) -> Vec<CreateActionRow> {
+    if pages <= 1 {
+        return vec![];
+    }
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("tag:prev").label("Previous").style(ButtonStyle::Secondary).disabled(page == 0),
+        CreateButton::new("tag:next").label("Next").style(ButtonStyle::Secondary).disabled(page + 1 >= pages),
+    ])]
+}
+
+/// Show the tags a page at a time, read fresh on every press.
+async fn list(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let sctx = ctx.serenity_context();
+    let tags = names(sctx, guild_id).await?;
+    if tags.is_empty() {
+        ctx.say("No tags yet. Moderators can add one with `tag create <name> <content>`.").await?;
+        return Ok(());
+    }
+    let mut page = 0;
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(page_embed(&tags, page))
+                .components(page_buttons(page, page_count(&tags))),
+        )
+        .await?;
+    if page_count(&tags) <= 1 {
+        return Ok(());
+    }
+    let message = reply.message().await?;
+
+    while let Some(press) = ComponentInteractionCollector::new(ctx).message_id(message.id).timeout(VIEW_TIMEOUT).await {
+        if press.user.id != ctx.author().id {
+            let notice = CreateInteractionResponseMessage::new()
+                .content("Run `tag list` to page through the tags yourself.")
+                .ephemeral(true);
+            press.create_response(ctx.http(), CreateInteractionResponse::Message(notice)).await?;
+            continue;
+        }
+        let tags = names(sctx, guild_id).await?;
+        let pages = page_count(&tags);
+        page = match press.data.custom_id.as_str() {
+            "tag:prev" => page.saturating_sub(1),
+            _ => page + 1,
+        }
+        .min(pages - 1);
+        let update = CreateInteractionResponseMessage::new()
+            .embed(page_embed(&tags, page))
+            .components(page_buttons(page, pages));
+        press.create_response(ctx.http(), CreateInteractionResponse::UpdateMessage(update)).await?;
+    }
+    reply.edit(ctx, poise::CreateReply::default().components(vec![])).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![tag()]
+}
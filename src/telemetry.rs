@@ -0,0 +1,209 @@
+//! Optional error reporting: command errors, event handler errors and panics are forwarded to a
+//! Discord webhook and/or a Sentry project (`telemetry` in the config), rate limited so an
+//! error loop can't flood either.
+
+use poise::serenity_prelude as serenity;
+use rand::Rng;
+use serde_json::json;
+use serenity::prelude::*;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config;
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// How long a panic waits for `errors::on_error` to claim it as a command panic before it's
+/// reported on its own.
+const CLAIM_WINDOW: Duration = Duration::from_secs(2);
+const ERROR_COLOR: u32 = 0xED4245;
+/// Discord's embed description limit, with room for the code fence.
+const MAX_DETAIL: usize = 3900;
+
+/// One failure to forward.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// What went wrong, e.g. `command failed`.
+    pub kind: &'static str,
+    /// The id shown to the user, if they were shown one.
+    pub error_id: Option<String>,
+    /// Qualified command name, or the event being handled.
+    pub source: Option<String>,
+    pub detail: String,
+}
+
+/// Reports sent in the current window, and how many were dropped over the limit.
+struct Limiter {
+    started: Instant,
+    sent: u32,
+    dropped: u32,
+}
+
+static LIMITER: Mutex<Option<Limiter>> = Mutex::new(None);
+/// The newest panic not yet reported, with its sequence number.
+static PENDING_PANIC: Mutex<Option<(u64, String)>> = Mutex::new(None);
+static PANIC_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Whether another report fits in the window. Returns the number dropped since the last one
+/// that got through, so it can say so.
+fn admit(limit: u32, now: Instant) -> Option<u32> {
+    let mut limiter = LIMITER.lock().unwrap_or_else(|e| e.into_inner());
+    let state = limiter.get_or_insert(Limiter { started: now, sent: 0, dropped: 0 });
+    if now.duration_since(state.started) >= WINDOW {
+        state.started = now;
+        state.sent = 0;
+    }
+    if state.sent >= limit {
+        state.dropped += 1;
+        return None;
+    }
+    state.sent += 1;
+    Some(std::mem::take(&mut state.dropped))
+}
+
+/// Forward `report` to whatever `telemetry` configures, in the background.
+pub async fn report(ctx: &Context, report: Report) {
+    let cfg = config::current(ctx).await.telemetry.clone();
+    if cfg.webhook_url.is_none() && cfg.sentry_dsn.is_none() {
+        return;
+    }
+    let Some(dropped) = admit(cfg.max_per_minute(), Instant::now()) else { return };
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        if let Some(url) = &cfg.webhook_url
+            && let Err(e) = send_webhook(&client, url, &report, dropped).await
+        {
+            tracing::warn!("Couldn't send an error report to the webhook: {e}");
+        }
+        if let Some(dsn) = &cfg.sentry_dsn
+            && let Err(e) = send_sentry(&client, dsn, &report).await
+        {
+            tracing::warn!("Couldn't send an error report to Sentry: {e}");
+        }
+    });
+}
+
+async fn send_webhook(client: &reqwest::Client, url: &str, report: &Report, dropped: u32) -> Result<(), reqwest::Error> {
+    let detail: String = report.detail.chars().take(MAX_DETAIL).collect();
+    let mut fields = Vec::new();
+    if let Some(id) = &report.error_id {
+        fields.push(json!({ "name": "Error id", "value": format!("`{}`", id), "inline": true }));
+    }
+    if let Some(source) = &report.source {
+        fields.push(json!({ "name": "Where", "value": format!("`{}`", source), "inline": true }));
+    }
+    let mut body = json!({
+        "embeds": [{
+            "title": report.kind,
+            "description": format!("```\n{}\n```", detail),
+            "color": ERROR_COLOR,
+            "fields": fields,
+            "timestamp": serenity::Timestamp::now().to_string(),
+        }],
+        "allowed_mentions": { "parse": [] },
+    });
+    if dropped > 0 {
+        body["content"] = json!(format!("{} earlier report(s) were dropped by the rate limit.", dropped));
+    }
+    client.post(url).json(&body).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Sentry's store endpoint and public key from a DSN like `https://<key>@<host>/<project>`.
+fn sentry_endpoint(dsn: &str) -> Option<(String, String)> {
+    let url = reqwest::Url::parse(dsn).ok()?;
+    let key = url.username();
+    let project = url.path().trim_matches('/');
+    if key.is_empty() || project.is_empty() {
+        return None;
+    }
+    let host = url.host_str()?;
+    let port = url.port().map(|p| format!(":{}", p)).unwrap_or_default();
+    Some((format!("{}://{}{}/api/{}/store/", url.scheme(), host, port, project), key.to_string()))
+}
+
+async fn send_sentry(client: &reqwest::Client, dsn: &str, report: &Report) -> Result<(), crate::Error> {
+    let (endpoint, key) = sentry_endpoint(dsn).ok_or("invalid `telemetry.sentry_dsn`")?;
+    let version = env!("CARGO_PKG_VERSION");
+    let event = json!({
+        "event_id": format!("{:032x}", rand::thread_rng().r#gen::<u128>()),
+        "timestamp": serenity::Timestamp::now().unix_timestamp(),
+        "level": "error",
+        "platform": "other",
+        "logger": report.kind,
+        "release": format!("{}+{}", version, env!("GIT_COMMIT")),
+        "message": { "formatted": report.detail },
+        "tags": { "error_id": report.error_id, "source": report.source },
+    });
+    client
+        .post(endpoint)
+        .header("X-Sentry-Auth", format!("Sentry sentry_version=7, sentry_client=discord-bot/{}, sentry_key={}", version, key))
+        .json(&event)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Report panics anywhere in the bot, after the default hook has printed them. A panic inside a
+/// command is left for `errors::on_error` to report with the error id the user saw.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let seq = PANIC_SEQ.fetch_add(1, Ordering::Relaxed);
+        *PENDING_PANIC.lock().unwrap_or_else(|e| e.into_inner()) = Some((seq, info.to_string()));
+        let (Some(ctx), Ok(runtime)) = (crate::web::bot(), tokio::runtime::Handle::try_current()) else { return };
+        runtime.spawn(async move {
+            tokio::time::sleep(CLAIM_WINDOW).await;
+            let unclaimed = {
+                let mut pending = PENDING_PANIC.lock().unwrap_or_else(|e| e.into_inner());
+                match pending.take() {
+                    Some((s, detail)) if s == seq => Some(detail),
+                    other => {
+                        *pending = other;
+                        None
+                    }
+                }
+            };
+            if let Some(detail) = unclaimed {
+                report(ctx, Report { kind: "panic", error_id: None, source: None, detail }).await;
+            }
+        });
+    }));
+}
+
+/// The message and location of the panic that just happened, so a command panic's report
+/// carries them and the panic isn't reported a second time.
+pub fn claim_panic() -> Option<String> {
+    PENDING_PANIC.lock().unwrap_or_else(|e| e.into_inner()).take().map(|(_, detail)| detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentry_endpoint_from_dsn() {
+        let (endpoint, key) = sentry_endpoint("https://abc123@o1.ingest.sentry.io/4505").unwrap();
+        assert_eq!(endpoint, "https://o1.ingest.sentry.io/api/4505/store/");
+        assert_eq!(key, "abc123");
+        let (endpoint, _) = sentry_endpoint("http://key@localhost:9000/2").unwrap();
+        assert_eq!(endpoint, "http://localhost:9000/api/2/store/");
+        assert!(sentry_endpoint("https://o1.ingest.sentry.io/4505").is_none());
+        assert!(sentry_endpoint("not a dsn").is_none());
+    }
+
+    #[test]
+    fn rate_limit_drops_and_counts() {
+        let start = Instant::now();
+        *LIMITER.lock().unwrap() = None;
+        assert_eq!(admit(2, start), Some(0));
+        assert_eq!(admit(2, start), Some(0));
+        assert_eq!(admit(2, start), None);
+        assert_eq!(admit(2, start), None);
+        // A new window lets reports through again and mentions what was dropped
+        assert_eq!(admit(2, start + WINDOW), Some(2));
+        assert_eq!(admit(2, start + WINDOW), Some(0));
+    }
+}
@@ -0,0 +1,119 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::builder::CreateMessage;
+use serenity::prelude::*;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::events::{self, PlayerEvent};
+use crate::settings::{self, SettingKey};
+use crate::{config, queue};
+
+/// How long before the current track ends the next one is announced.
+const LEAD: Duration = Duration::from_secs(10);
+
+/// The guild's `music.announce_next` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Off,
+    Text,
+    Voice,
+    Both,
+}
+
+async fn mode(ctx: &Context, guild_id: GuildId) -> Mode {
+    match settings::get(ctx, guild_id, SettingKey::MusicAnnounceNext).await.as_deref() {
+        Some("text") => Mode::Text,
+        Some("voice") => Mode::Voice,
+        Some("both") => Mode::Both,
+        _ => Mode::Off,
+    }
+}
+
+async fn is_live(ctx: &Context, guild_id: GuildId) -> bool {
+    let Some(ms) = ctx.data.read().await.get::<crate::TrackMetaStore>().cloned() else { return false };
+    ms.lock().await.get(&guild_id).is_some_and(|m| m.is_live)
+}
+
+/// Spawn the announcer: it follows every guild's player events and, with `music.announce_next`
+/// on, announces the queue's next request once a track's `position` event is within `LEAD` of
+/// its end. Position events stop while a track is paused, and tracks of unknown length
+/// (livestreams) are never announced over.
+pub fn start(ctx: Context) {
+    tokio::spawn(async move {
+        let Some(mut bus) = events::subscribe(&ctx).await else { return };
+        // Guilds whose current track hasn't been announced over yet
+        let mut waiting: HashSet<GuildId> = HashSet::new();
+        loop {
+            let (guild_id, event) = match bus.recv().await {
+                Ok(received) => received,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+            };
+            match event {
+                PlayerEvent::TrackStarted { .. } => {
+                    if mode(&ctx, guild_id).await == Mode::Off {
+                        waiting.remove(&guild_id);
+                    } else {
+                        waiting.insert(guild_id);
+                    }
+                }
+                // The length can arrive after the track starts, from yt-dlp's metadata
+                PlayerEvent::Position { position_secs, duration_secs: Some(length) }
+                    if waiting.contains(&guild_id) && length.saturating_sub(position_secs) <= LEAD.as_secs() =>
+                {
+                    waiting.remove(&guild_id);
+                    if !is_live(&ctx, guild_id).await {
+                        let ctx = ctx.clone();
+                        tokio::spawn(async move { announce(&ctx, guild_id).await });
+                    }
+                }
+                PlayerEvent::TrackEnded => {
+                    waiting.remove(&guild_id);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+async fn announce(ctx: &Context, guild_id: GuildId) {
+    let Some(next) = queue::list(ctx, guild_id).await.into_iter().next() else { return };
+    let mode = mode(ctx, guild_id).await;
+    if matches!(mode, Mode::Text | Mode::Both) {
+        let theme = crate::embeds::theme(ctx, Some(guild_id)).await;
+        let mut text = format!("Up next: {}", next.query);
+        if let Some(user) = next.requested_by {
+            text.push_str(&format!(" (requested by <@{}>)", user));
+        }
+        let embed = theme.embed().title("Music").description(text);
+        let message = CreateMessage::new().embed(embed).allowed_mentions(serenity::builder::CreateAllowedMentions::new());
+        if let Err(e) = next.channel.send_message(&ctx.http, message).await {
+            tracing::warn!("Failed to announce the next track in guild {}: {e:?}", guild_id);
+        }
+    }
+    if matches!(mode, Mode::Voice | Mode::Both) {
+        speak(ctx, guild_id, &format!("Up next: {}", next.query)).await;
+    }
+}
+
+/// Say `text` over the music through `music.tts_url`. Needs the built-in player.
+async fn speak(ctx: &Context, guild_id: GuildId, text: &str) {
+    let music = config::current(ctx).await.music.clone();
+    let Some(base) = music.tts_url.as_deref() else { return };
+    if music.player_backend == config::PlayerBackend::Lavalink {
+        return;
+    }
+    let mut url = match reqwest::Url::parse(base) {
+        Ok(url) => url,
+        Err(e) => {
+            tracing::warn!("Bad `music.tts_url` {}: {e}", base);
+            return;
+        }
+    };
+    url.query_pairs_mut().append_pair("text", text);
+    let input = songbird::input::HttpRequest::new(reqwest::Client::new(), url.to_string());
+    if let Err(e) = crate::duck::play_over(ctx, guild_id, input.into()).await {
+        tracing::warn!("Failed to speak the next track in guild {}: {e:?}", guild_id);
+    }
+}
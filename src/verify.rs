@@ -0,0 +1,101 @@
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::Error;
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `name`'s hash in a `sha256sum`-style list (`<hex>  <name>` or `<hex> *<name>` per line). A
+/// file holding just a hash, as `<url>.sha256` files often do, matches any name.
+pub fn find_checksum(list: &str, name: &str) -> Option<String> {
+    let is_hash = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+    let mut lines = list.lines().map(str::trim).filter(|l| !l.is_empty());
+    let found = lines.clone().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        (file.trim().trim_start_matches('*') == name && is_hash(hash)).then(|| hash.to_ascii_lowercase())
+    });
+    found.or_else(|| {
+        let only = lines.next()?.split_whitespace().next()?;
+        (is_hash(only) && lines.next().is_none()).then(|| only.to_ascii_lowercase())
+    })
+}
+
+/// Fail unless `bytes` hash to `expected`.
+pub fn check_sha256(bytes: &[u8], expected: &str, what: &str) -> Result<(), Error> {
+    let actual = sha256_hex(bytes);
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(format!("{} failed its SHA-256 check (expected {}, got {})", what, expected.trim(), actual).into());
+    }
+    Ok(())
+}
+
+/// Check a minisign `signature` (the `.minisig` file) over `bytes` with `public_key`, the
+/// base64 line of a minisign `.pub` file.
+pub fn check_minisign(bytes: &[u8], signature: &str, public_key: &str, what: &str) -> Result<(), Error> {
+    let key = minisign_verify::PublicKey::from_base64(public_key.trim())
+        .map_err(|e| format!("invalid minisign public key: {}", e))?;
+    let signature = minisign_verify::Signature::decode(signature).map_err(|e| format!("invalid minisign signature for {}: {}", what, e))?;
+    key.verify(bytes, &signature, false)
+        .map_err(|e| format!("{} failed its minisign check: {}", what, e))?;
+    Ok(())
+}
+
+/// Check a detached GPG `signature` over `data` with `gpgv` against the keys in `keyring`. Both
+/// are written to a fresh private temporary directory, removed when the check is done.
+pub async fn check_gpg(data: &[u8], signature: &[u8], keyring: &str, what: &str) -> Result<(), Error> {
+    let dir = tempfile::Builder::new().prefix("verify-").tempdir()?;
+    let data_path = dir.path().join("data");
+    let sig_path = dir.path().join("data.sig");
+    fs::write(&data_path, data).await?;
+    fs::write(&sig_path, signature).await?;
+    let out = tokio::process::Command::new("gpgv")
+        .kill_on_drop(true)
+        .arg("--keyring")
+        .arg(keyring)
+        .arg(&sig_path)
+        .arg(&data_path)
+        .output()
+        .await
+        .map_err(|e| format!("couldn't run gpgv to check {}: {}", what, e))?;
+    if !out.status.success() {
+        return Err(format!("{} failed its GPG check: {}", what, String::from_utf8_lossy(&out.stderr).trim()).into());
+    }
+    Ok(())
+}
+
+/// Where the hash of a verified install of `path` is recorded.
+fn record_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".sha256");
+    path.with_file_name(name)
+}
+
+/// Install already-verified `bytes` as the executable `path`: written beside it and renamed
+/// over it, so a failure part way leaves the previous version in place. Records the hash so
+/// `is_verified` can tell the file hasn't been swapped since.
+pub async fn install(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let partial = path.with_extension("partial");
+    fs::write(&partial, bytes).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&partial, std::fs::Permissions::from_mode(0o755)).await?;
+    }
+    fs::rename(&partial, path).await?;
+    fs::write(record_path(path), sha256_hex(bytes)).await?;
+    Ok(())
+}
+
+/// Whether `path` exists and still hashes to what `install` recorded for it.
+pub async fn is_verified(path: &Path) -> bool {
+    let (Ok(bytes), Ok(recorded)) = (fs::read(path).await, fs::read_to_string(record_path(path)).await) else {
+        return false;
+    };
+    sha256_hex(&bytes).eq_ignore_ascii_case(recorded.trim())
+}
@@ -0,0 +1,379 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::builder::CreateEmbed;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::modalert::AlertKind;
+use crate::moderation::{apply_timeout, format_duration, parse_duration, report};
+use crate::{Ctx, Error};
+
+pub const WARNINGS_PATH: &str = "warnings.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Warning {
+    pub moderator: u64,
+    pub reason: String,
+    /// Unix timestamp (seconds)
+    pub at: i64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum EscalationAction {
+    Timeout { duration_secs: u64 },
+    Kick,
+    Ban,
+}
+
+/// Applied automatically when a member reaches exactly `warnings` warnings.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EscalationRule {
+    pub warnings: usize,
+    #[serde(flatten)]
+    pub action: EscalationAction,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WarningData {
+    #[serde(default)]
+    warnings: HashMap<u64, HashMap<u64, Vec<Warning>>>,
+    #[serde(default)]
+    escalation: HashMap<u64, Vec<EscalationRule>>,
+}
+
+pub struct WarningStore;
+impl TypeMapKey for WarningStore {
+    type Value = Arc<Mutex<WarningData>>;
+}
+
+async fn load_disk() -> Result<WarningData, Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::load_json(&crate::storage::data_path(WARNINGS_PATH)).await
+}
+
+async fn save_disk(data: &WarningData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(WARNINGS_PATH), data).await
+}
+
+pub async fn ensure_warning_store() -> Result<Arc<Mutex<WarningData>>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = load_disk().await?;
+    Ok(Arc::new(Mutex::new(data)))
+}
+
+async fn warning_store(ctx: &Context) -> Result<Arc<Mutex<WarningData>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<WarningStore>()
+        .cloned()
+        .ok_or_else(|| "Warning store not initialised".into())
+}
+
+/// Drop every warning given in `gid` and its escalation rules.
+pub async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<(), Error> {
+    let store = warning_store(ctx).await?;
+    let mut data = store.lock().await;
+    data.warnings.remove(&gid.get());
+    data.escalation.remove(&gid.get());
+    save_disk(&data).await?;
+    Ok(())
+}
+
+/// Warnings `user` received, by guild, for `data export`.
+pub async fn export_user(ctx: &Context, user: UserId) -> Result<serde_json::Value, Error> {
+    let store = warning_store(ctx).await?;
+    let data = store.lock().await;
+    let mut out = serde_json::Map::new();
+    for (gid, users) in &data.warnings {
+        if let Some(list) = users.get(&user.get()).filter(|l| !l.is_empty()) {
+            out.insert(gid.to_string(), serde_json::to_value(list)?);
+        }
+    }
+    Ok(out.into())
+}
+
+/// Store a warning and return how many warnings the member now has in this guild.
+pub async fn record_warning(
+    ctx: &Context,
+    gid: GuildId,
+    uid: UserId,
+    moderator: UserId,
+    reason: &str,
+) -> Result<usize, Error> {
+    let store = warning_store(ctx).await?;
+    let mut data = store.lock().await;
+    let list = data
+        .warnings
+        .entry(gid.get())
+        .or_default()
+        .entry(uid.get())
+        .or_default();
+    list.push(Warning {
+        moderator: moderator.get(),
+        reason: reason.to_string(),
+        at: serenity::Timestamp::now().unix_timestamp(),
+    });
+    let count = list.len();
+    save_disk(&data).await?;
+    Ok(count)
+}
+
+/// Run the escalation rule matching `count` (if any), returning a description of what was done.
+pub async fn escalate(
+    ctx: &Context,
+    gid: GuildId,
+    user: &serenity::User,
+    count: usize,
+) -> Option<String> {
+    let store = warning_store(ctx).await.ok()?;
+    let rule = {
+        let data = store.lock().await;
+        data.escalation
+            .get(&gid.get())
+            .and_then(|rules| rules.iter().find(|r| r.warnings == count).copied())?
+    };
+
+    let reason = format!("Automatic escalation: {} warnings", count);
+    // Timeouts check dry-run mode themselves
+    let dry_run = crate::dryrun::active(ctx, Some(gid)).await;
+    let (kind, verb, result) = match rule.action {
+        EscalationAction::Timeout { duration_secs } => {
+            let dur = Duration::from_secs(duration_secs);
+            (
+                AlertKind::Timeout,
+                format!("timed out for {}", format_duration(dur)),
+                apply_timeout(ctx, gid, user.id, dur, &reason).await,
+            )
+        }
+        EscalationAction::Kick if dry_run => {
+            crate::dryrun::skip(Some(gid), &format!("kick {} ({})", user.tag(), reason));
+            (AlertKind::Kick, "kicked".to_string(), Ok(()))
+        }
+        EscalationAction::Kick => (
+            AlertKind::Kick,
+            "kicked".to_string(),
+            gid.kick_with_reason(&ctx.http, user.id, &reason).await.map_err(Into::into),
+        ),
+        EscalationAction::Ban if dry_run => {
+            crate::dryrun::skip(Some(gid), &format!("ban {} ({})", user.tag(), reason));
+            (AlertKind::Ban, "banned".to_string(), Ok(()))
+        }
+        EscalationAction::Ban => (
+            AlertKind::Ban,
+            "banned".to_string(),
+            gid.ban_with_reason(&ctx.http, user.id, 0, &reason).await.map_err(Into::into),
+        ),
+    };
+
+    match result {
+        Ok(()) => {
+            let simulated = if dry_run { " [dry run]" } else { "" };
+            let detail = format!("{} was {} ({}){}.", user.tag(), verb, reason, simulated);
+            report(ctx, gid, kind, detail.clone()).await;
+            Some(detail)
+        }
+        Err(e) => {
+            tracing::warn!("Escalation for {} in guild {} failed: {e:?}", user.id, gid);
+            Some(format!("Escalation ({}) failed: {}", verb, e))
+        }
+    }
+}
+
+/// List a member's warnings
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MODERATE_MEMBERS",
+    default_member_permissions = "MODERATE_MEMBERS"
+)]
+pub async fn warnings(
+    ctx: Ctx<'_>,
+    #[description = "Member to look up"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = warning_store(ctx.serenity_context()).await?;
+    let list = {
+        let data = store.lock().await;
+        data.warnings
+            .get(&guild_id.get())
+            .and_then(|users| users.get(&user.id.get()))
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    if list.is_empty() {
+        ctx.say(format!("{} has no warnings.", user.tag())).await?;
+        return Ok(());
+    }
+
+    let lines = list
+        .iter()
+        .enumerate()
+        .map(|(i, w)| format!("**{}.** <t:{}:f> by <@{}>: {}", i + 1, w.at, w.moderator, w.reason))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let embed = CreateEmbed::new()
+        .title(format!("Warnings for {} ({})", user.tag(), list.len()))
+        .description(lines)
+        .color(crate::EMBED_COLOR);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Remove all warnings for a member
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    required_permissions = "MODERATE_MEMBERS",
+    default_member_permissions = "MODERATE_MEMBERS"
+)]
+pub async fn clearwarnings(
+    ctx: Ctx<'_>,
+    #[description = "Member whose warnings to clear"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = warning_store(ctx.serenity_context()).await?;
+    let removed = {
+        let mut data = store.lock().await;
+        let removed = data
+            .warnings
+            .get_mut(&guild_id.get())
+            .and_then(|users| users.remove(&user.id.get()))
+            .map(|l| l.len())
+            .unwrap_or(0);
+        save_disk(&data).await?;
+        removed
+    };
+    ctx.say(format!("Cleared {} warnings for {}.", removed, user.tag())).await?;
+    Ok(())
+}
+
+/// Configure automatic actions when members reach a number of warnings
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands("escalation_set", "escalation_remove", "escalation_list"),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn escalation(ctx: Ctx<'_>) -> Result<(), Error> {
+    list_rules(ctx).await
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum EscalationChoice {
+    #[name = "timeout"]
+    Timeout,
+    #[name = "kick"]
+    Kick,
+    #[name = "ban"]
+    Ban,
+}
+
+/// Set the action taken when a member reaches a warning count
+#[poise::command(prefix_command, slash_command, guild_only, rename = "set")]
+pub async fn escalation_set(
+    ctx: Ctx<'_>,
+    #[description = "Warning count that triggers the action"]
+    #[min = 1]
+    warnings: usize,
+    #[description = "Action to take"] action: EscalationChoice,
+    #[description = "Timeout duration, e.g. 1h (timeout only)"] duration: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let action = match action {
+        EscalationChoice::Timeout => {
+            let Some(dur) = duration.as_deref().and_then(parse_duration) else {
+                ctx.say("A timeout escalation needs a duration, e.g. `1h`.").await?;
+                return Ok(());
+            };
+            EscalationAction::Timeout { duration_secs: dur.as_secs() }
+        }
+        EscalationChoice::Kick => EscalationAction::Kick,
+        EscalationChoice::Ban => EscalationAction::Ban,
+    };
+
+    let store = warning_store(ctx.serenity_context()).await?;
+    {
+        let mut data = store.lock().await;
+        let rules = data.escalation.entry(guild_id.get()).or_default();
+        rules.retain(|r| r.warnings != warnings);
+        rules.push(EscalationRule { warnings, action });
+        rules.sort_by_key(|r| r.warnings);
+        save_disk(&data).await?;
+    }
+    ctx.say(format!("At {} warnings: {}.", warnings, describe_action(action))).await?;
+    Ok(())
+}
+
+/// Remove the action for a warning count
+#[poise::command(prefix_command, slash_command, guild_only, rename = "remove")]
+pub async fn escalation_remove(
+    ctx: Ctx<'_>,
+    #[description = "Warning count"] warnings: usize,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = warning_store(ctx.serenity_context()).await?;
+    let removed = {
+        let mut data = store.lock().await;
+        let rules = data.escalation.entry(guild_id.get()).or_default();
+        let before = rules.len();
+        rules.retain(|r| r.warnings != warnings);
+        let removed = rules.len() != before;
+        save_disk(&data).await?;
+        removed
+    };
+    if removed {
+        ctx.say(format!("Removed the escalation at {} warnings.", warnings)).await?;
+    } else {
+        ctx.say(format!("No escalation configured at {} warnings.", warnings)).await?;
+    }
+    Ok(())
+}
+
+/// Show the configured escalation rules
+#[poise::command(prefix_command, slash_command, guild_only, rename = "list")]
+pub async fn escalation_list(ctx: Ctx<'_>) -> Result<(), Error> {
+    list_rules(ctx).await
+}
+
+async fn list_rules(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = warning_store(ctx.serenity_context()).await?;
+    let rules = {
+        let data = store.lock().await;
+        data.escalation.get(&guild_id.get()).cloned().unwrap_or_default()
+    };
+    if rules.is_empty() {
+        ctx.say("No escalation rules configured. Add one with `escalation set <warnings> <action> [duration]`.").await?;
+        return Ok(());
+    }
+    let lines = rules
+        .iter()
+        .map(|r| format!("{} warnings → {}", r.warnings, describe_action(r.action)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ctx.say(lines).await?;
+    Ok(())
+}
+
+fn describe_action(action: EscalationAction) -> String {
+    match action {
+        EscalationAction::Timeout { duration_secs } => {
+            format!("timeout for {}", format_duration(Duration::from_secs(duration_secs)))
+        }
+        EscalationAction::Kick => "kick".to_string(),
+        EscalationAction::Ban => "ban".to_string(),
+    }
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![warnings(), clearwarnings(), escalation()]
+}
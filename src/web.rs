@@ -0,0 +1,104 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use poise::serenity_prelude as serenity;
+use serenity::prelude::*;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::HttpConfig;
+
+/// Liveness/readiness flags flipped by the gateway events and framework setup.
+#[derive(Default)]
+pub struct Health {
+    gateway: AtomicBool,
+    voice: AtomicBool,
+    ready: AtomicBool,
+}
+
+impl Health {
+    pub fn set_gateway(&self, connected: bool) {
+        self.gateway.store(connected, Ordering::Relaxed);
+    }
+
+    /// Setup finished; `voice` says whether songbird is registered on the client.
+    pub fn set_ready(&self, voice: bool) {
+        self.voice.store(voice, Ordering::Relaxed);
+        self.ready.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct HealthStore;
+impl TypeMapKey for HealthStore {
+    type Value = Arc<Health>;
+}
+
+pub async fn health(ctx: &Context) -> Option<Arc<Health>> {
+    ctx.data.read().await.get::<HealthStore>().cloned()
+}
+
+/// The bot's context, available to HTTP handlers once framework setup has finished.
+static BOT: OnceLock<Context> = OnceLock::new();
+
+/// Make `ctx` (and through it the shared stores) reachable from HTTP handlers.
+pub fn attach(ctx: &Context) {
+    let _ = BOT.set(ctx.clone());
+}
+
+/// `None` until setup has finished.
+pub fn bot() -> Option<&'static Context> {
+    BOT.get()
+}
+
+#[derive(Clone)]
+struct AppState {
+    health: Arc<Health>,
+}
+
+/// Serve the HTTP endpoints in the background when `http.port` is set. Started before the
+/// gateway connects so probes get an answer (503) during startup.
+pub fn start(health: Arc<Health>, http: &HttpConfig) {
+    let Some(port) = http.port else { return };
+    let mut app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(AppState { health });
+    if let Some(cfg) = http.dashboard.clone() {
+        app = app.merge(crate::dashboard::router(cfg));
+    }
+    if let Some(cfg) = http.api.clone() {
+        app = app.merge(crate::api::router(cfg));
+    }
+    let addr = format!("{}:{}", http.bind, port);
+    tokio::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(&addr).await {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::warn!("Failed to bind HTTP server on {addr}: {e:?}");
+                return;
+            }
+        };
+        tracing::info!("HTTP server listening on {addr}");
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::warn!("HTTP server stopped: {e:?}");
+        }
+    });
+}
+
+/// Liveness: the process is up and serving requests. The gateway and voice client come and go
+/// without a restart helping, so they're only checked by `readyz`.
+async fn healthz() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "ok": true }))
+}
+
+/// Readiness: setup has finished (stores loaded, commands registered), the gateway is up and
+/// songbird is registered.
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
+    let ready = state.health.ready.load(Ordering::Relaxed);
+    let gateway = state.health.gateway.load(Ordering::Relaxed);
+    let voice = state.health.voice.load(Ordering::Relaxed);
+    let ok = ready && gateway && voice;
+    let code = if ok { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (code, Json(serde_json::json!({ "ok": ok, "ready": ready, "gateway": gateway, "songbird": voice })))
+}
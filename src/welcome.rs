@@ -0,0 +1,306 @@
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::builder::{CreateAllowedMentions, CreateEmbed, CreateMessage};
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::tags::fill_placeholders;
+use crate::{Ctx, Error};
+
+pub const WELCOME_PATH: &str = "welcome.json";
+
+const DEFAULT_WELCOME: &str = "Welcome to {server}, {user}! You are member #{membercount}.";
+const DEFAULT_GOODBYE: &str = "{user.name} left {server}.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Greeting {
+    pub template: String,
+    #[serde(default)]
+    pub embed: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildWelcome {
+    pub channel: Option<u64>,
+    #[serde(default)]
+    pub welcome: Option<Greeting>,
+    #[serde(default)]
+    pub goodbye: Option<Greeting>,
+    /// Image shown in embed greetings
+    #[serde(default)]
+    pub image: Option<String>,
+}
+
+pub type WelcomeData = HashMap<u64, GuildWelcome>;
+
+pub struct WelcomeStore;
+impl TypeMapKey for WelcomeStore {
+    type Value = Arc<Mutex<WelcomeData>>;
+}
+
+async fn load_disk() -> Result<WelcomeData, Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::load_json(&crate::storage::data_path(WELCOME_PATH)).await
+}
+
+async fn save_disk(data: &WelcomeData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::storage::save_json(&crate::storage::data_path(WELCOME_PATH), data).await
+}
+
+pub async fn ensure_welcome_store() -> Result<Arc<Mutex<WelcomeData>>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = load_disk().await?;
+    Ok(Arc::new(Mutex::new(data)))
+}
+
+async fn welcome_store(ctx: &Context) -> Result<Arc<Mutex<WelcomeData>>, Error> {
+    ctx.data
+        .read()
+        .await
+        .get::<WelcomeStore>()
+        .cloned()
+        .ok_or_else(|| "Welcome store not initialised".into())
+}
+
+/// Drop `gid`'s welcome and goodbye messages.
+pub async fn purge_guild(ctx: &Context, gid: GuildId) -> Result<(), Error> {
+    let store = welcome_store(ctx).await?;
+    let mut data = store.lock().await;
+    if data.remove(&gid.get()).is_some() {
+        save_disk(&data).await?;
+    }
+    Ok(())
+}
+
+fn render(greeting: &Greeting, image: Option<&str>, user: &serenity::User, server: &str, member_count: u64) -> CreateMessage {
+    let text = fill_placeholders(&greeting.template, user, server, None).replace("{membercount}", &member_count.to_string());
+    // Only ever ping the member being greeted
+    let msg = CreateMessage::new().allowed_mentions(CreateAllowedMentions::new().users(vec![user.id]));
+    if !greeting.embed {
+        return msg.content(text);
+    }
+    let mut embed = CreateEmbed::new()
+        .description(text)
+        .thumbnail(user.face())
+        .color(crate::EMBED_COLOR);
+    if let Some(url) = image {
+        embed = embed.image(url);
+    }
+    msg.embed(embed)
+}
+
+/// Send the configured greeting for `user` joining (or leaving) `gid`.
+async fn greet(ctx: &Context, gid: GuildId, user: &serenity::User, joined: bool) {
+    if user.bot {
+        return;
+    }
+    let Ok(store) = welcome_store(ctx).await else { return };
+    let (channel, greeting, image) = {
+        let data = store.lock().await;
+        let Some(cfg) = data.get(&gid.get()) else { return };
+        let greeting = if joined { cfg.welcome.clone() } else { cfg.goodbye.clone() };
+        match (cfg.channel, greeting) {
+            (Some(channel), Some(greeting)) => (ChannelId::new(channel), greeting, cfg.image.clone()),
+            _ => return,
+        }
+    };
+
+    let (server, member_count) = ctx
+        .cache
+        .guild(gid)
+        .map(|g| (g.name.clone(), g.member_count))
+        .unwrap_or_default();
+    let msg = render(&greeting, image.as_deref(), user, &server, member_count);
+    if let Err(e) = channel.send_message(&ctx.http, msg).await {
+        tracing::warn!("Failed to send greeting in guild {}: {e:?}", gid);
+    }
+}
+
+pub async fn on_member_join(ctx: &Context, member: &serenity::Member) {
+    greet(ctx, member.guild_id, &member.user, true).await;
+}
+
+pub async fn on_member_leave(ctx: &Context, gid: GuildId, user: &serenity::User) {
+    greet(ctx, gid, user, false).await;
+}
+
+/// Join and leave announcements
+#[poise::command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    subcommands(
+        "welcome_channel",
+        "welcome_message",
+        "welcome_goodbye",
+        "welcome_image",
+        "welcome_off",
+        "welcome_test"
+    ),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn welcome(ctx: Ctx<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let cfg = current(ctx, guild_id).await?;
+    let describe = |g: &Option<Greeting>| match g {
+        Some(g) => format!("`{}`{}", g.template, if g.embed { " (embed)" } else { "" }),
+        None => "off".to_string(),
+    };
+    ctx.say(format!(
+        "Channel: {}\nWelcome: {}\nGoodbye: {}\nImage: {}",
+        cfg.channel.map(|c| format!("<#{}>", c)).unwrap_or_else(|| "not set".to_string()),
+        describe(&cfg.welcome),
+        describe(&cfg.goodbye),
+        cfg.image.as_deref().unwrap_or("none")
+    ))
+    .await?;
+    Ok(())
+}
+
+async fn current(ctx: Ctx<'_>, guild_id: GuildId) -> Result<GuildWelcome, Error> {
+    let store = welcome_store(ctx.serenity_context()).await?;
+    let data = store.lock().await;
+    Ok(data.get(&guild_id.get()).cloned().unwrap_or_default())
+}
+
+async fn update(ctx: Ctx<'_>, f: impl FnOnce(&mut GuildWelcome)) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let store = welcome_store(ctx.serenity_context()).await?;
+    let mut data = store.lock().await;
+    f(data.entry(guild_id.get()).or_default());
+    save_disk(&data).await?;
+    Ok(())
+}
+
+/// Set the channel for join/leave announcements (omit to turn them off)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "channel")]
+pub async fn welcome_channel(
+    ctx: Ctx<'_>,
+    #[description = "Announcement channel"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let id = channel.as_ref().map(|c| c.id.get());
+    update(ctx, |cfg| cfg.channel = id).await?;
+    match channel {
+        Some(c) => ctx.say(format!("Join/leave announcements will be posted in <#{}>.", c.id)).await?,
+        None => ctx.say("Join/leave announcements turned off.").await?,
+    };
+    Ok(())
+}
+
+/// Set the welcome message ({user}, {user.name}, {server}, {membercount})
+#[poise::command(prefix_command, slash_command, guild_only, rename = "message")]
+pub async fn welcome_message(
+    ctx: Ctx<'_>,
+    #[description = "Send as an embed"] embed: Option<bool>,
+    #[description = "Message template"]
+    #[rest]
+    template: Option<String>,
+) -> Result<(), Error> {
+    let greeting = greeting(template, embed, DEFAULT_WELCOME);
+    let shown = greeting.template.clone();
+    update(ctx, |cfg| cfg.welcome = Some(greeting)).await?;
+    ctx.say(format!("Welcome message set: `{}`", shown)).await?;
+    Ok(())
+}
+
+/// Set the goodbye message ({user}, {user.name}, {server}, {membercount})
+#[poise::command(prefix_command, slash_command, guild_only, rename = "goodbye")]
+pub async fn welcome_goodbye(
+    ctx: Ctx<'_>,
+    #[description = "Send as an embed"] embed: Option<bool>,
+    #[description = "Message template"]
+    #[rest]
+    template: Option<String>,
+) -> Result<(), Error> {
+    let greeting = greeting(template, embed, DEFAULT_GOODBYE);
+    let shown = greeting.template.clone();
+    update(ctx, |cfg| cfg.goodbye = Some(greeting)).await?;
+    ctx.say(format!("Goodbye message set: `{}`", shown)).await?;
+    Ok(())
+}
+
+fn greeting(template: Option<String>, embed: Option<bool>, default: &str) -> Greeting {
+    let template = template
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| default.to_string());
+    Greeting { template, embed: embed.unwrap_or(false) }
+}
+
+/// Set an image shown in embed greetings (omit to remove)
+#[poise::command(prefix_command, slash_command, guild_only, rename = "image")]
+pub async fn welcome_image(
+    ctx: Ctx<'_>,
+    #[description = "Image URL"] url: Option<String>,
+) -> Result<(), Error> {
+    if url.as_deref().is_some_and(|u| !(u.starts_with("https://") || u.starts_with("http://"))) {
+        ctx.say("The image must be an http(s) URL.").await?;
+        return Ok(());
+    }
+    let set = url.is_some();
+    update(ctx, |cfg| cfg.image = url).await?;
+    ctx.say(if set { "Greeting image set (shown when the greeting is an embed)." } else { "Greeting image removed." })
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum GreetingChoice {
+    #[name = "welcome"]
+    Welcome,
+    #[name = "goodbye"]
+    Goodbye,
+}
+
+/// Turn off the welcome or goodbye message
+#[poise::command(prefix_command, slash_command, guild_only, rename = "off")]
+pub async fn welcome_off(
+    ctx: Ctx<'_>,
+    #[description = "Which message"] which: GreetingChoice,
+) -> Result<(), Error> {
+    update(ctx, |cfg| match which {
+        GreetingChoice::Welcome => cfg.welcome = None,
+        GreetingChoice::Goodbye => cfg.goodbye = None,
+    })
+    .await?;
+    ctx.say("Done.").await?;
+    Ok(())
+}
+
+/// Preview the welcome or goodbye message using yourself
+#[poise::command(prefix_command, slash_command, guild_only, rename = "test")]
+pub async fn welcome_test(
+    ctx: Ctx<'_>,
+    #[description = "Which message"] which: GreetingChoice,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("This command can only be used in a server.")?;
+    let cfg = current(ctx, guild_id).await?;
+    let greeting = match which {
+        GreetingChoice::Welcome => cfg.welcome,
+        GreetingChoice::Goodbye => cfg.goodbye,
+    };
+    let Some(channel) = cfg.channel else {
+        ctx.say("No welcome channel is set, so nothing would be posted; set one with `welcome channel`.").await?;
+        return Ok(());
+    };
+    let Some(greeting) = greeting else {
+        ctx.say("That message is turned off.").await?;
+        return Ok(());
+    };
+    let (server, member_count) = ctx.guild().map(|g| (g.name.clone(), g.member_count)).unwrap_or_default();
+    let msg = render(&greeting, cfg.image.as_deref(), ctx.author(), &server, member_count);
+    ctx.channel_id().send_message(ctx.http(), msg).await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!("Preview posted; the real message goes to <#{}>.", channel))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<crate::Data, Error>> {
+    vec![welcome()]
+}
@@ -1,195 +1,327 @@
 use anyhow::{Context, Result};
 use clap::Parser;
+use librespot_core::authentication::Credentials;
+use librespot_core::cache::Cache;
+use librespot_core::config::SessionConfig;
+use librespot_core::session::Session;
+use librespot_core::spotify_id::SpotifyId;
+use librespot_metadata::audio::UniqueFields;
+use librespot_playback::audio_backend::{Sink, SinkError, SinkResult};
+use librespot_playback::config::PlayerConfig;
+use librespot_playback::convert::Converter;
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::mixer::NoOpVolume;
+use librespot_playback::player::{Player, PlayerEvent};
+use librespot_playback::{NUM_CHANNELS, SAMPLE_RATE};
 use reqwest::Client;
 use serde::Deserialize;
 use std::env;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about = "librespot-wrapper: convenience helper to play a Spotify URI and stream audio to stdout (WIP)")]
+#[command(author, version, about = "librespot-wrapper: play a Spotify URI with librespot and stream the decoded audio to stdout")]
 struct Args {
     /// Spotify URI to play (e.g., spotify:track:... or open.spotify.com link)
     #[arg(long)]
     uri: Option<String>,
 
-    /// Write raw WAV to stdout (when implemented)
+    /// Write WAV (16-bit stereo, 44.1 kHz) to stdout
     #[arg(long)]
     stdout: bool,
 
-    /// Device name to register as (defaults to 'Librespot-Wrapper')
+    /// Device name the session registers as (defaults to 'Librespot-Wrapper')
     #[arg(long, default_value = "Librespot-Wrapper")]
     name: String,
+
+    /// Directory for librespot's credential and audio cache. After the first login the stored
+    /// credentials are enough, so no token is needed on later runs.
+    #[arg(long, default_value = ".cache/librespot")]
+    cache: PathBuf,
+
+    /// Write status events (started, position, ended, error) as JSON lines to stderr
+    #[arg(long)]
+    json: bool,
+
+    /// Write the JSON status events to this file descriptor instead of stderr (unix)
+    #[arg(long)]
+    status_fd: Option<i32>,
+}
+
+/// How often a `position` event is written while playing.
+const POSITION_EVERY: Duration = Duration::from_secs(5);
+/// Session drops in a row before the stream is given up on.
+const MAX_RECONNECTS: u32 = 5;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// Access tokens are refreshed this long before they expire.
+const TOKEN_MARGIN: Duration = Duration::from_secs(300);
+
+/// Where `--json`/`--status-fd` events go, one JSON object per line. Does nothing when neither
+/// flag is given.
+struct Status {
+    out: Option<Box<dyn Write + Send>>,
+}
+
+impl Status {
+    fn open(args: &Args) -> Result<Self> {
+        let out: Option<Box<dyn Write + Send>> = match args.status_fd {
+            #[cfg(unix)]
+            Some(fd) => {
+                use std::os::unix::io::FromRawFd;
+                // Safety: the caller passed us this descriptor to write to, and nothing else here owns it
+                Some(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+            }
+            #[cfg(not(unix))]
+            Some(_) => anyhow::bail!("--status-fd is only supported on unix"),
+            None if args.json => Some(Box::new(std::io::stderr())),
+            None => None,
+        };
+        Ok(Status { out })
+    }
+
+    fn emit(&mut self, event: serde_json::Value) {
+        if let Some(out) = self.out.as_mut() {
+            let _ = writeln!(out, "{}", event);
+            let _ = out.flush();
+        }
+    }
+
+    fn error(&mut self, message: &str) {
+        self.emit(serde_json::json!({ "event": "error", "message": message }));
+    }
 }
 
 #[derive(Deserialize)]
 struct TokenResponse {
     access_token: String,
-    token_type: String,
     expires_in: u64,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-
-    // Load config from env
-    let client_id = env::var("SPOTIFY_CLIENT_ID").ok();
-    let client_secret = env::var("SPOTIFY_CLIENT_SECRET").ok();
-    let refresh_token = env::var("SPOTIFY_REFRESH_TOKEN").ok();
-
-    if refresh_token.is_none() || client_id.is_none() || client_secret.is_none() {
-        eprintln!("Missing SPOTIFY_CLIENT_ID, SPOTIFY_CLIENT_SECRET, or SPOTIFY_REFRESH_TOKEN in env.");
-        eprintln!("This tool will attempt to control playback on a librespot device via the Web API.");
-        eprintln!("See tools/librespot-wrapper/README.md for instructions to obtain a refresh token.");
-        anyhow::bail!("missing Spotify credentials");
+    let mut status = Status::open(&args)?;
+    let result = run(&args, &mut status).await;
+    if let Err(e) = &result {
+        status.error(&format!("{:#}", e));
     }
+    result
+}
 
-    let client = Client::new();
-
-    // Ensure URI present
-    let uri_owned = args.uri.as_ref().ok_or_else(|| anyhow::anyhow!("You must pass --uri <spotify:track:... or open.spotify.com/track/..."))?;
-
-    // Exchange refresh token for access token using the client credentials
-    let token = refresh_access_token(&client, &client_id.unwrap(), &client_secret.unwrap(), &refresh_token.unwrap())
-        .await
-        .context("failed to refresh access token")?;
-
-    // If stdout mode requested, set up a FIFO and spawn librespot in pipe backend so we can capture audio
-    let mut librespot_child = None;
-    let mut fifo_path_opt = None;
+async fn run(args: &Args, status: &mut Status) -> Result<()> {
+    let uri = args.uri.as_deref().ok_or_else(|| anyhow::anyhow!("You must pass --uri <spotify:track:... or open.spotify.com/track/..."))?;
+    let track = parse_uri(uri)?;
 
-    if args.stdout {
-        // Prepare a FIFO in the temp dir
-        let tmpdir = std::env::temp_dir();
-        let uniq = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_nanos();
-        let fifo_path = tmpdir.join(format!("librespot-pipe-{}", uniq));
+    if !args.stdout {
+        anyhow::bail!("nothing to do: pass --stdout to stream the audio");
+    }
 
-        // Create FIFO using mkfifo
-        let mk = std::process::Command::new("mkfifo").arg(&fifo_path).status();
-        match mk {
-            Ok(s) if s.success() => {
-                eprintln!("Created FIFO at {}", fifo_path.display());
+    let cache = Cache::new(Some(&args.cache), None, Some(&args.cache.join("audio")), None).context("failed to open the librespot cache")?;
+    let mut auth = Auth::new(cache);
+
+    // A dropped session (network blip, expired login) reconnects and picks the track up where
+    // it left off; only `MAX_RECONNECTS` failures in a row end the stream
+    let mut position_ms = 0;
+    let mut failures = 0;
+    loop {
+        let session = match connect(args, &mut auth).await {
+            Ok(session) => session,
+            Err(e) if failures < MAX_RECONNECTS && position_ms > 0 => {
+                failures += 1;
+                eprintln!("Reconnect attempt {} failed: {:#}", failures, e);
+                tokio::time::sleep(RECONNECT_DELAY * failures).await;
+                continue;
             }
-            Ok(s) => {
-                eprintln!("mkfifo returned non-zero: {:?}", s);
-                anyhow::bail!("failed to create fifo");
+            Err(e) => return Err(e),
+        };
+        match play(&session, track, position_ms, &mut auth, status).await? {
+            Outcome::Finished => {
+                session.shutdown();
+                break;
             }
-            Err(e) => {
-                eprintln!("mkfifo error: {e:?}");
-                anyhow::bail!("mkfifo failed");
+            Outcome::Lost { at_ms } => {
+                session.shutdown();
+                // Playing for a while before the drop means the last reconnect worked
+                if at_ms > position_ms + RECONNECT_DELAY.as_millis() as u32 * 6 {
+                    failures = 0;
+                }
+                failures += 1;
+                if failures > MAX_RECONNECTS {
+                    anyhow::bail!("lost the Spotify session {} times in a row", MAX_RECONNECTS);
+                }
+                eprintln!("Lost the Spotify session at {} ms; reconnecting", at_ms);
+                position_ms = at_ms;
+                tokio::time::sleep(RECONNECT_DELAY).await;
             }
         }
+    }
 
-        // Find librespot binary (prefer our built pipe-enabled binary, then the wrapper, then 'librespot')
-        let librespot_bin = if std::path::Path::new(".bin/librespot-pipe").is_file() {
-            ".bin/librespot-pipe".to_string()
-        } else if std::path::Path::new(".bin/librespot-wrapper").is_file() {
-            ".bin/librespot-wrapper".to_string()
-        } else {
-            "librespot".to_string()
-        };
-
-        // Build librespot args: use '--device' to point to FIFO and pass the access token if available
-        let mut ls_args: Vec<String> = vec!["--name".into(), args.name.clone(), "--backend".into(), "pipe".into(), "--device".into(), fifo_path.to_string_lossy().to_string(), "--format".into(), "S16".into()];
+    eprintln!("Streaming finished");
+    Ok(())
+}
 
-        // Prefer passing an OAuth access token rather than username/password
-        ls_args.push("--access-token".into());
-        ls_args.push(token.access_token.clone());
+/// How a `play` call ended.
+enum Outcome {
+    Finished,
+    /// The session dropped mid-track, `at_ms` into it.
+    Lost { at_ms: u32 },
+}
 
-        eprintln!("Spawning librespot: {} {:?}", librespot_bin, ls_args);
-        let mut cmd = tokio::process::Command::new(&librespot_bin);
-        for a in ls_args.iter() { cmd.arg(a); }
-        cmd.stdout(std::process::Stdio::null()).stderr(std::process::Stdio::piped());
+async fn connect(args: &Args, auth: &mut Auth) -> Result<Session> {
+    let credentials = auth.credentials().await?;
+    let session_config = SessionConfig { device_id: device_id(&args.name), ..SessionConfig::default() };
+    let session = Session::new(session_config, Some(auth.cache.clone()));
+    // Store the reusable credentials librespot gets back, so later runs (and reconnects) can
+    // skip the token
+    session.connect(credentials, true).await.context("failed to connect to Spotify")?;
+    Ok(session)
+}
 
-        match cmd.spawn() {
-            Ok(child) => {
-                eprintln!("librespot started (pid {:?}). Waiting for device to appear...", child.id());
-                librespot_child = Some(child);
-                fifo_path_opt = Some(fifo_path.clone());
-            }
-            Err(e) => {
-                eprintln!("Failed to start librespot: {e:?}");
-                anyhow::bail!("failed to start librespot");
+/// Play `track` from `start_ms` until it ends or the session drops, writing status events as
+/// it goes and keeping the access token fresh in the background.
+async fn play(session: &Session, track: SpotifyId, start_ms: u32, auth: &mut Auth, status: &mut Status) -> Result<Outcome> {
+    let player = Player::new(PlayerConfig::default(), session.clone(), Box::new(NoOpVolume), || Box::new(WavSink));
+    let mut events = player.get_player_event_channel();
+    player.load(track, true, start_ms);
+
+    // Where playback was at the last Playing/Seeked event, and when that was
+    let mut anchor: Option<(Instant, u32)> = None;
+    let mut last_ms = start_ms;
+    let mut ticker = tokio::time::interval(POSITION_EVERY);
+    let outcome = loop {
+        let position_ms = anchor.map_or(last_ms, |(at, ms)| ms + at.elapsed().as_millis() as u32);
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(PlayerEvent::TrackChanged { audio_item }) => {
+                    let artist = match &audio_item.unique_fields {
+                        UniqueFields::Track { artists, .. } => {
+                            Some(artists.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", "))
+                        }
+                        UniqueFields::Episode { show_name, .. } => Some(show_name.clone()),
+                    };
+                    status.emit(serde_json::json!({
+                        "event": "started",
+                        "uri": audio_item.uri,
+                        "title": audio_item.name,
+                        "artist": artist,
+                        "duration_ms": audio_item.duration_ms,
+                    }));
+                }
+                Some(PlayerEvent::Playing { position_ms, .. })
+                | Some(PlayerEvent::Seeked { position_ms, .. })
+                | Some(PlayerEvent::PositionCorrection { position_ms, .. }) => {
+                    anchor = Some((Instant::now(), position_ms));
+                }
+                Some(PlayerEvent::Paused { position_ms, .. }) => {
+                    anchor = None;
+                    last_ms = position_ms;
+                    status.emit(serde_json::json!({ "event": "position", "position_ms": position_ms }));
+                }
+                // The player stops on its own when the session underneath it goes away
+                Some(PlayerEvent::EndOfTrack { .. }) | Some(PlayerEvent::Stopped { .. }) | Some(PlayerEvent::Unavailable { .. }) | None
+                    if session.is_invalid() =>
+                {
+                    break Outcome::Lost { at_ms: position_ms };
+                }
+                Some(PlayerEvent::EndOfTrack { .. }) | Some(PlayerEvent::Stopped { .. }) | None => {
+                    status.emit(serde_json::json!({ "event": "ended" }));
+                    break Outcome::Finished;
+                }
+                Some(PlayerEvent::Unavailable { .. }) => anyhow::bail!("{} is not available to this account", track.to_uri().unwrap_or_default()),
+                Some(_) => {}
+            },
+            _ = ticker.tick() => {
+                if session.is_invalid() {
+                    break Outcome::Lost { at_ms: position_ms };
+                }
+                if anchor.is_some() {
+                    status.emit(serde_json::json!({ "event": "position", "position_ms": position_ms }));
+                }
             }
-        }
-
-        // Wait for device to appear (poll)
-        let mut dev_id = None;
-        for _ in 0..20 {
-            if let Ok(Some(did)) = find_device_by_name(&client, &token.access_token, &args.name).await {
-                dev_id = Some(did); break;
+            _ = tokio::time::sleep_until(auth.refresh_at()) => {
+                // Refresh ahead of expiry so a reconnect never has to wait on a dead token
+                if let Err(e) = auth.refresh().await {
+                    eprintln!("Access token refresh failed: {:#}", e);
+                }
             }
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         }
+    };
+    player.stop();
+    Ok(outcome)
+}
 
-        if dev_id.is_none() {
-            eprintln!("Device didn't appear in time");
-            anyhow::bail!("device not ready");
-        }
+/// Accept `spotify:track:ID`, `spotify:episode:ID`, or the open.spotify.com link for either.
+fn parse_uri(uri: &str) -> Result<SpotifyId> {
+    let uri = uri.trim();
+    let uri = if uri.starts_with("http") {
+        let path = uri.split('?').next().unwrap_or(uri);
+        let mut parts = path.trim_end_matches('/').rsplit('/');
+        let id = parts.next().unwrap_or_default();
+        let kind = parts.next().unwrap_or_default();
+        format!("spotify:{}:{}", kind, id)
+    } else {
+        uri.to_string()
+    };
+    SpotifyId::from_uri(&uri).map_err(|e| anyhow::anyhow!("not a Spotify track or episode URI ({}): {:?}", uri, e))
+}
 
-        let dev = dev_id.unwrap();
-
-        // Request playback on that device
-        let test_uri = args.uri.as_deref().unwrap_or("");
-        // Sanity check dev type
-        let _: &String = &dev;
-        let url = format!("https://api.spotify.com/v1/me/player/play?device_id={}", dev);
-        let body = serde_json::json!({ "uris": [ test_uri ] });
-        let _ = client
-            .put(&url)
-            .bearer_auth(&token.access_token)
-            .json(&body)
-            .send()
-            .await?
-            .error_for_status()?;
-
-        // Spawn ffmpeg to read from FIFO and write WAV to stdout
-        let ff_cmd = format!("ffmpeg -hide_banner -loglevel error -f s16le -ar 48000 -ac 2 -i {} -f wav -", fifo_path.to_string_lossy());
-        eprintln!("Spawning ffmpeg: {}", ff_cmd);
-        let mut ff = tokio::process::Command::new("sh");
-        ff.arg("-c").arg(&ff_cmd);
-        ff.stdout(std::process::Stdio::inherit()); // write to our stdout
-        ff.stderr(std::process::Stdio::piped());
-
-        let mut ff_child = ff.spawn().context("failed to spawn ffmpeg")?;
-
-        // Wait for ffmpeg to exit (or return immediately if ffmpeg runs until killed)
-        let status = ff_child.wait().await.context("ffmpeg wait failed")?;
-        eprintln!("ffmpeg exited with: {:?}", status);
-
-        // Clean up fifo
-        if let Some(fp) = fifo_path_opt {
-            let _ = std::fs::remove_file(&fp);
-        }
+/// Where session credentials come from: ones librespot cached on an earlier run, else an access
+/// token from `SPOTIFY_ACCESS_TOKEN`, else one exchanged for `SPOTIFY_REFRESH_TOKEN` and kept
+/// fresh for the life of the stream.
+struct Auth {
+    cache: Cache,
+    client: Client,
+    /// The last token from the refresh-token exchange and when it expires.
+    token: Option<(String, tokio::time::Instant)>,
+}
 
-        // If we reach here, streaming ended
-        println!("Streaming finished");
-        return Ok(());
+impl Auth {
+    fn new(cache: Cache) -> Self {
+        Auth { cache, client: Client::new(), token: None }
     }
 
-    // Otherwise: non-stdout mode -> find a device and start playback normally
-    let device_id = find_device_by_name(&client, &token.access_token, &args.name).await?;
-
-    if device_id.is_none() {
-        eprintln!("No device named '{}' found for the Spotify account. Start a librespot device with that name and try again.", args.name);
-        anyhow::bail!("device not found");
+    async fn credentials(&mut self) -> Result<Credentials> {
+        if let Some(cached) = self.cache.credentials() {
+            return Ok(cached);
+        }
+        if let Ok(token) = env::var("SPOTIFY_ACCESS_TOKEN") {
+            return Ok(Credentials::with_access_token(token));
+        }
+        if self.token.as_ref().is_none_or(|(_, expires)| tokio::time::Instant::now() + TOKEN_MARGIN >= *expires) {
+            self.refresh().await?;
+        }
+        let (token, _) = self.token.clone().ok_or_else(|| anyhow::anyhow!("missing Spotify credentials"))?;
+        Ok(Credentials::with_access_token(token))
     }
 
-    let dev = device_id.unwrap();
-
-    // Request playback on that device
-    let url = format!("https://api.spotify.com/v1/me/player/play?device_id={}", dev);
-    let body = serde_json::json!({ "uris": [ args.uri.as_deref().unwrap_or("") ] });
-    let _ = client
-        .put(&url)
-        .bearer_auth(&token.access_token)
-        .json(&body)
-        .send()
-        .await?
-        .error_for_status()?;
+    /// When the current token should be refreshed: `TOKEN_MARGIN` before it expires, or never
+    /// (in practice) when there's no refresh token in use.
+    fn refresh_at(&self) -> tokio::time::Instant {
+        match &self.token {
+            Some((_, expires)) => *expires - TOKEN_MARGIN,
+            None => tokio::time::Instant::now() + Duration::from_secs(86400 * 365),
+        }
+    }
 
-    println!("Requested playback of {} on device {}", args.uri.as_deref().unwrap_or(""), dev);
+    async fn refresh(&mut self) -> Result<()> {
+        let client_id = env::var("SPOTIFY_CLIENT_ID").ok();
+        let client_secret = env::var("SPOTIFY_CLIENT_SECRET").ok();
+        let refresh_token = env::var("SPOTIFY_REFRESH_TOKEN").ok();
+        let (Some(client_id), Some(client_secret), Some(refresh_token)) = (client_id, client_secret, refresh_token) else {
+            eprintln!("No cached credentials and no SPOTIFY_ACCESS_TOKEN, or SPOTIFY_CLIENT_ID, SPOTIFY_CLIENT_SECRET and SPOTIFY_REFRESH_TOKEN, in env.");
+            eprintln!("See tools/librespot-wrapper/README.md for instructions to obtain a token.");
+            anyhow::bail!("missing Spotify credentials");
+        };
 
-    Ok(())
+        let token = refresh_access_token(&self.client, &client_id, &client_secret, &refresh_token)
+            .await
+            .context("failed to refresh access token")?;
+        let expires = tokio::time::Instant::now() + Duration::from_secs(token.expires_in);
+        self.token = Some((token.access_token, expires));
+        Ok(())
+    }
 }
 
 async fn refresh_access_token(client: &Client, client_id: &str, client_secret: &str, refresh_token: &str) -> Result<TokenResponse> {
@@ -210,41 +342,62 @@ async fn refresh_access_token(client: &Client, client_id: &str, client_secret: &
     Ok(tr)
 }
 
-async fn find_device_by_name(client: &Client, access_token: &str, name: &str) -> Result<Option<String>> {
-    // GET https://api.spotify.com/v1/me/player/devices
-    #[derive(Deserialize)]
-    struct Devices { devices: Vec<Device> }
-    #[derive(Deserialize)]
-    struct Device { id: String, name: String }
+/// A stable device id derived from the device name, so Spotify sees the same device each run.
+fn device_id(name: &str) -> String {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    name.hash(&mut hasher);
+    format!("{:016x}{:016x}", hasher.finish(), hasher.finish().rotate_left(32))
+}
 
-    let res = client
-        .get("https://api.spotify.com/v1/me/player/devices")
-        .bearer_auth(access_token)
-        .send()
-        .await?
-        .error_for_status()?;
+/// Writes the player's decoded audio to stdout as a WAV stream. The header claims the maximum
+/// length since the real one isn't known up front; ffmpeg and symphonia read until EOF anyway.
+/// A reconnect builds a new player and sink, which carry on the same stream without a header.
+struct WavSink;
+
+static HEADER_WRITTEN: AtomicBool = AtomicBool::new(false);
+
+impl WavSink {
+    fn header() -> Vec<u8> {
+        const BITS: u16 = 16;
+        let channels = NUM_CHANNELS as u16;
+        let block_align = channels * BITS / 8;
+        let byte_rate = SAMPLE_RATE * block_align as u32;
+
+        let mut h = Vec::with_capacity(44);
+        h.extend_from_slice(b"RIFF");
+        h.extend_from_slice(&u32::MAX.to_le_bytes());
+        h.extend_from_slice(b"WAVEfmt ");
+        h.extend_from_slice(&16u32.to_le_bytes());
+        h.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        h.extend_from_slice(&channels.to_le_bytes());
+        h.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        h.extend_from_slice(&byte_rate.to_le_bytes());
+        h.extend_from_slice(&block_align.to_le_bytes());
+        h.extend_from_slice(&BITS.to_le_bytes());
+        h.extend_from_slice(b"data");
+        h.extend_from_slice(&(u32::MAX - 36).to_le_bytes());
+        h
+    }
 
-    let devs: Devices = res.json().await?;
-    for d in devs.devices.into_iter() {
-        if d.name == name {
-            return Ok(Some(d.id));
+    fn emit(&mut self, bytes: &[u8]) -> SinkResult<()> {
+        let mut out = std::io::stdout().lock();
+        if !HEADER_WRITTEN.swap(true, Ordering::SeqCst) {
+            out.write_all(&Self::header()).map_err(|e| SinkError::OnWrite(e.to_string()))?;
         }
+        out.write_all(bytes).map_err(|e| SinkError::OnWrite(e.to_string()))
     }
-    Ok(None)
 }
 
-async fn start_playback(client: &Client, access_token: &str, device_id: &str, uri: &str) -> Result<()> {
-    // PUT https://api.spotify.com/v1/me/player/play?device_id={device_id}
-    let url = format!("https://api.spotify.com/v1/me/player/play?device_id={}", device_id);
-    let body = serde_json::json!({ "uris": [ uri ] });
-
-    let _ = client
-        .put(&url)
-        .bearer_auth(access_token)
-        .json(&body)
-        .send()
-        .await?
-        .error_for_status()?;
+impl Sink for WavSink {
+    fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()> {
+        let samples = packet.samples().map_err(|e| SinkError::OnWrite(e.to_string()))?;
+        let pcm = converter.f64_to_s16(samples);
+        let bytes: Vec<u8> = pcm.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.emit(&bytes)
+    }
 
-    Ok(())
+    fn stop(&mut self) -> SinkResult<()> {
+        std::io::stdout().flush().map_err(|e| SinkError::OnWrite(e.to_string()))
+    }
 }